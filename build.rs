@@ -0,0 +1,27 @@
+/*
+ * 打开 ffi feature 时用 cbindgen 从 src/ffi.rs 生成 include/simple_tcp_ip.h, 选项见 cbindgen.toml
+ */
+fn main() {
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", dir)).expect("cbindgen.toml");
+        let bindings = cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(format!("{}/src/ffi.rs", dir))
+            .generate()
+            .expect("generate include/simple_tcp_ip.h");
+        let mut header = vec![];
+        bindings.write(&mut header);
+        // 仓库的 /** */ 文档注释每行自带 " * ", cbindgen 会再加一个
+        let header = String::from_utf8(header).unwrap().replace(" * * ", " * ").replace(" * *\n", " *\n");
+        let path = format!("{}/include/simple_tcp_ip.h", dir);
+        if std::fs::read_to_string(&path).ok().as_deref() != Some(header.as_str()) {
+            std::fs::write(&path, header).expect("write include/simple_tcp_ip.h");
+        }
+    }
+    #[cfg(not(feature = "ffi"))]
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,113 @@
+/*
+ * TAP 上的回显服务器(需要 root): 宿主机连过来的数据原样发回
+ * 协议栈是一个只有一个接口的 Stack, ARP、重传和关闭都由它处理; 等待帧的超时取 time_until_next_event
+ * 每 10 秒把 Prometheus 格式的指标打印到标准错误
+ *
+ *   cargo run --features os-interop --example echo_server -- 10.211.0.1/24 7
+ *   # 另一个终端: nc 10.211.0.2 7
+ */
+use std::collections::HashMap;
+use std::env;
+use std::process;
+use std::time::Duration;
+
+use simple_tcp_ip::config::StackConfig;
+use simple_tcp_ip::link::interface::EthernetInterface;
+use simple_tcp_ip::stack::Stack;
+use simple_tcp_ip::testing::osnet::HostNet;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::utils::addr;
+use simple_tcp_ip::utils::clock::{Clock, MonotonicClock};
+
+const MY_MAC: [u8; 6] = [0x02, 0, 0, 0, 0xec, 0x01];
+const METRICS_INTERVAL_MS: u64 = 10_000;
+const MAX_WAIT_MS: u64 = 1_000;
+
+struct Server {
+    net: HostNet,
+    stack: Stack,
+    clock: MonotonicClock,
+    last_metrics_ms: u64,
+    listener: ConnectionId,
+    conns: HashMap<ConnectionId, Vec<u8>>, // 连接和还没有写进发送缓冲区的回显数据
+}
+
+impl Server {
+    fn dump_metrics(&mut self, now_ms: u64) {
+        if now_ms < self.last_metrics_ms + METRICS_INTERVAL_MS {
+            return;
+        }
+        self.last_metrics_ms = now_ms;
+        eprint!("{}", self.stack.render_metrics());
+    }
+
+    /**
+     * 接受新连接, 读到的数据写回去; 对端关闭之后也关闭, 连接离开连接表时移除
+     */
+    fn echo(&mut self, now_ms: u64) {
+        let tcp = self.stack.tcp_mut();
+        while let Some(id) = tcp.accept(self.listener) {
+            println!("{}:{} connected", addr::format_ipv4(id.d_ip), id.d_port);
+            self.conns.insert(id, vec![]);
+        }
+        self.conns.retain(|id, pending| {
+            let Some(state) = tcp.state(*id) else {
+                println!("{}:{} closed", addr::format_ipv4(id.d_ip), id.d_port);
+                return false;
+            };
+            if let Ok(data) = tcp.read(*id, usize::MAX) {
+                pending.extend(data);
+            }
+            let written = tcp.write(*id, pending).unwrap_or(0);
+            pending.drain(..written);
+            if state == TcpState::CloseWait && pending.is_empty() {
+                let _ = tcp.close(*id, now_ms);
+            }
+            true
+        });
+    }
+
+    fn run(&mut self) -> std::io::Result<()> {
+        loop {
+            let now_ms = self.clock.now_ms();
+            self.stack.pump(0, &mut self.net.tap, usize::MAX);
+            self.stack.poll(now_ms);
+            self.echo(now_ms);
+            self.stack.poll(now_ms);
+            self.stack.pump(0, &mut self.net.tap, 0);
+            self.dump_metrics(now_ms);
+
+            let wait_ms = self.stack.time_until_next_event(now_ms).unwrap_or(MAX_WAIT_MS).min(MAX_WAIT_MS);
+            if let Some(frame) = self.net.tap.read_frame_timeout(Duration::from_millis(wait_ms))? {
+                self.stack.frame_received(0, frame);
+            }
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let parsed = args.first().and_then(|cidr| addr::parse_cidr(cidr)).zip(args.get(1).and_then(|p| p.parse::<u16>().ok()));
+    let Some(((host_ip, prefix_len), port)) = parsed else {
+        eprintln!("usage: echo_server <host-ip/prefix> <port>");
+        process::exit(2);
+    };
+    let net = match HostNet::setup("stip-echo", host_ip, prefix_len) {
+        Ok(net) => net,
+        Err(e) => {
+            eprintln!("echo_server: {}", e);
+            process::exit(1);
+        }
+    };
+    let my_ip = host_ip + 1;
+    println!("echo server on {}:{} via {}", addr::format_ipv4(my_ip), port, net.tap.name());
+    let mut stack = Stack::new(&StackConfig::default()).expect("default config is valid");
+    stack.add_interface(EthernetInterface::new(MY_MAC, my_ip, prefix_len));
+    let listener = stack.tcp_mut().listen(my_ip, port);
+    let clock = MonotonicClock::new();
+    let mut server = Server { net, stack, clock, last_metrics_ms: 0, listener, conns: HashMap::new() };
+    if let Err(e) = server.run() {
+        eprintln!("echo_server: {}", e);
+        process::exit(1);
+    }
+}
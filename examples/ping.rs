@@ -0,0 +1,97 @@
+/*
+ * 通过 TAP 向宿主机发 ICMP 回显请求并统计往返时间(需要 root)
+ * 本端地址取宿主机地址的下一个
+ *
+ *   cargo run --features os-interop --example ping -- 10.211.0.1/24 [count]
+ */
+use std::env;
+use std::process;
+use std::time::Duration;
+
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::net::icmp_v4::IcmpV4;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::testing::osnet::{self, HostNet};
+use simple_tcp_ip::testing::packet::PacketBuilder;
+use simple_tcp_ip::utils::addr;
+use simple_tcp_ip::utils::clock::{Clock, MonotonicClock};
+
+const MY_MAC: [u8; 6] = [0x02, 0, 0, 0, 0x50, 0x01];
+const ECHO_ID: u16 = 0x5354;
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/**
+ * 回显应答里的 (id, seq), 不是发给我们的回显应答时返回 None
+ */
+fn echo_reply(frame: &[u8], my_ip: u32) -> Option<(u32, u16, u16)> {
+    let frame = EthernetFrame::try_deserialize(frame).ok()?;
+    let datagram = Ipv4Datagram::try_deserialize(frame.payload()).ok()?;
+    if datagram.protocol() != 1 || datagram.d_addr() != my_ip {
+        return None;
+    }
+    let icmp = IcmpV4::try_deserialize(datagram.payload()).ok()?;
+    let data = icmp.data();
+    if icmp.icmp_type() != 0 || data.len() < 4 {
+        return None;
+    }
+    Some((datagram.s_addr(), u16::from_be_bytes([data[0], data[1]]), u16::from_be_bytes([data[2], data[3]])))
+}
+
+fn run(cidr: &str, count: u16) -> Result<(), Box<dyn std::error::Error>> {
+    let (host_ip, prefix_len) = addr::parse_cidr(cidr).ok_or("bad host address, expected a.b.c.d/len")?;
+    let my_ip = host_ip + 1;
+    let mut net = HostNet::setup("stip-ping", host_ip, prefix_len)?;
+    let host_mac = net.resolve_host(MY_MAC, my_ip, TIMEOUT)?;
+    println!("PING {} from {} ({})", addr::format_ipv4(host_ip), addr::format_ipv4(my_ip), addr::format_mac(&host_mac));
+
+    let clock = MonotonicClock::new();
+    let mut received = 0;
+    for seq in 1..=count {
+        let mut data = Vec::with_capacity(36);
+        data.extend_from_slice(&ECHO_ID.to_be_bytes());
+        data.extend_from_slice(&seq.to_be_bytes());
+        data.extend_from_slice(b"simple_tcp_ip ping payload 0123");
+        let request = PacketBuilder::ether(MY_MAC, host_mac).ipv4(my_ip, host_ip).ip_id(seq).icmp(8, 0).payload(&data).build();
+        let sent_at = clock.now_us();
+        net.tap.write_frame(&request)?;
+
+        loop {
+            let left = TIMEOUT.saturating_sub(Duration::from_micros(clock.now_us() - sent_at));
+            let Some(frame) = net.tap.read_frame_timeout(left)? else {
+                println!("request timeout for icmp_seq={}", seq);
+                break;
+            };
+            if let Some(reply) = osnet::arp_reply_to(&frame, MY_MAC, my_ip) {
+                net.tap.write_frame(&reply)?;
+                continue;
+            }
+            if let Some((from, ECHO_ID, reply_seq)) = echo_reply(&frame, my_ip) {
+                if reply_seq == seq {
+                    let rtt_us = clock.now_us() - sent_at;
+                    received += 1;
+                    println!("{} bytes from {}: icmp_seq={} time={:.3} ms",
+                        data.len() + 4, addr::format_ipv4(from), seq, rtt_us as f64 / 1000.0);
+                    break;
+                }
+            }
+        }
+        if seq < count {
+            std::thread::sleep(Duration::from_millis(200));
+        }
+    }
+    println!("{} packets transmitted, {} received", count, received);
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let count = args.get(1).map(|c| c.parse::<u16>());
+    let (Some(cidr), Ok(count)) = (args.first(), count.unwrap_or(Ok(4))) else {
+        eprintln!("usage: ping <host-ip/prefix> [count]");
+        process::exit(2);
+    };
+    if let Err(e) = run(cidr, count) {
+        eprintln!("ping: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,78 @@
+/*
+ * 精简版 tcpdump: 每个帧输出一行摘要, 可选过滤表达式(见 utils::filter)
+ *
+ *   cargo run --example sniff -- tests/fixtures/handshake.pcap "tcp and port 80"
+ *   cargo run --features os-interop --example sniff -- --tap stip0 10.211.0.1/24 icmp
+ */
+use std::env;
+use std::fs::File;
+use std::process;
+
+use simple_tcp_ip::utils::dissect;
+use simple_tcp_ip::utils::filter::Filter;
+use simple_tcp_ip::utils::pcap::PcapReader;
+
+const USAGE: &str = "usage: sniff <file.pcap> [filter]\n       sniff --tap <name> <host-ip/prefix> [filter]";
+
+fn print_frame(filter: &Filter, ts_us: u64, frame: &[u8]) {
+    if filter.matches(frame) {
+        println!("{}.{:06} {}", ts_us / 1_000_000, ts_us % 1_000_000, dissect::summary(frame));
+    }
+}
+
+fn sniff_pcap(path: &str, filter: &Filter) -> Result<(), Box<dyn std::error::Error>> {
+    let reader = PcapReader::new(File::open(path)?)?;
+    for record in reader {
+        let (ts_us, frame) = record?;
+        print_frame(filter, ts_us, &frame);
+    }
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", feature = "os-interop"))]
+fn sniff_tap(name: &str, cidr: &str, filter: &Filter) -> Result<(), Box<dyn std::error::Error>> {
+    use simple_tcp_ip::testing::osnet::HostNet;
+    use simple_tcp_ip::utils::addr;
+    use simple_tcp_ip::utils::clock::{Clock, WallClock};
+
+    let (host_ip, prefix_len) = addr::parse_cidr(cidr).ok_or("bad host address, expected a.b.c.d/len")?;
+    let mut net = HostNet::setup(name, host_ip, prefix_len)?;
+    eprintln!("listening on {}", net.tap.name());
+    loop {
+        let frame = net.tap.read_frame()?;
+        let ts_us = WallClock.now_us();
+        print_frame(filter, ts_us, &frame);
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "os-interop")))]
+fn sniff_tap(_: &str, _: &str, _: &Filter) -> Result<(), Box<dyn std::error::Error>> {
+    Err("TAP capture needs Linux and --features os-interop".into())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (pcap_path, filter_args) = match args.first().map(String::as_str) {
+        Some("--tap") if args.len() >= 3 => (None, &args[3..]),
+        Some(path) if !path.starts_with('-') => (Some(path.to_string()), &args[1..]),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    let filter = match Filter::parse(&filter_args.join(" ")) {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("bad filter: {}", e);
+            process::exit(2);
+        }
+    };
+    let outcome = match pcap_path {
+        Some(path) => sniff_pcap(&path, &filter),
+        None => sniff_tap(&args[1], &args[2], &filter),
+    };
+    if let Err(e) = outcome {
+        eprintln!("sniff: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,39 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::net::icmp_v4::IcmpV4;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+// Ethernet -> IPv4 -> TCP/ICMP, 任何输入都不能 panic
+fuzz_target!(|bytes: &[u8]| {
+    let _ = Ipv4Datagram::try_deserialize(bytes);
+    let _ = TcpSegment::try_deserialize(bytes);
+    let _ = IcmpV4::try_deserialize(bytes);
+
+    let frame = match EthernetFrame::try_deserialize(bytes) {
+        Ok(frame) => frame,
+        Err(_) => return,
+    };
+    let _ = frame.check_fcs();
+    let datagram = match Ipv4Datagram::try_deserialize(frame.payload()) {
+        Ok(datagram) => datagram,
+        Err(_) => return,
+    };
+    let _ = datagram.serialize();
+    match datagram.protocol() {
+        6 => {
+            if let Ok(segment) = TcpSegment::try_deserialize(datagram.payload()) {
+                let _ = segment.serialize();
+            }
+        }
+        1 => {
+            if let Ok(icmp) = IcmpV4::try_deserialize(datagram.payload()) {
+                let _ = IcmpV4::check(&icmp.serialize());
+            }
+        }
+        _ => {}
+    }
+});
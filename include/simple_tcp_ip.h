@@ -0,0 +1,228 @@
+/*
+ * simple_tcp_ip C 接口, 对应 src/ffi.rs (cargo feature "ffi")
+ * 所有缓冲区由调用者持有; 负的返回值为 STIP_ERR_* 错误码
+ */
+
+#ifndef SIMPLE_TCP_IP_H
+#define SIMPLE_TCP_IP_H
+
+/* 由 build.rs 经 cbindgen 从 src/ffi.rs 生成, 不要手工修改 */
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define STIP_OK 0
+
+#define STIP_ERR_NULL -1
+
+#define STIP_ERR_BUFFER_TOO_SMALL -2
+
+#define STIP_ERR_PANIC -3
+
+#define STIP_ERR_NOT_TCP -4
+
+#define STIP_ERR_CHECKSUM -5
+
+#define STIP_ERR_NOT_FOR_US -6
+
+#define STIP_ERR_AGAIN -7
+
+#define STIP_ERR_BAD_HANDLE -8
+
+#define STIP_ERR_ETHERNET -10
+
+#define STIP_ERR_IPV4 -11
+
+#define STIP_ERR_TCP -12
+
+#define STIP_ERR_ICMP -13
+
+#define STIP_ERR_ARP -14
+
+#define STIP_ERR_DEVICE -20
+
+#define STIP_ERR_CONNECTION -21
+
+#define STIP_ERR_SEND -22
+
+#define STIP_ERR_SERIALIZE -23
+
+#define STIP_ERR_CONFIG -24
+
+/**
+ * 不透明句柄, C 侧只持有指针
+ */
+typedef struct StipReceiver StipReceiver;
+
+/**
+ * 单接口的协议栈 (Stack) 和 C 侧的句柄表
+ * 要发出的帧排在 tx 中, 由 stip_stack_poll 逐个交给调用者
+ */
+typedef struct StipStack StipStack;
+
+/**
+ * stip_stack_new 的参数, 布局与头文件中的结构体一致
+ */
+typedef struct StipConfig {
+  uint8_t mac[6];
+  uint32_t ip;
+  uint8_t prefix_len;
+  uint32_t gateway;
+  uint16_t mss;
+  size_t recv_buffer;
+} StipConfig;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * 创建接收端, 只接受发往 ip:port 的报文段, capacity 为接收缓冲区字节数; 失败返回 NULL
+ */
+struct StipReceiver *stip_receiver_new(uint32_t ip,
+                                       uint16_t port,
+                                       size_t capacity);
+
+/**
+ * 释放句柄, 传入 NULL 无操作
+ *
+ * # Safety
+ * rx 必须是 stip_receiver_new 返回且尚未释放的指针
+ */
+void stip_receiver_free(struct StipReceiver *rx);
+
+/**
+ * 送入一个完整的以太网帧(含 FCS); 非 TCP 报文返回 STIP_ERR_NOT_TCP, 校验失败返回 STIP_ERR_CHECKSUM
+ * 不是发给接收端的返回 STIP_ERR_NOT_FOR_US
+ *
+ * # Safety
+ * rx 为有效句柄, frame 指向至少 len 个可读字节
+ */
+int32_t stip_receiver_feed_frame(struct StipReceiver *rx,
+                                 const uint8_t *frame,
+                                 size_t len);
+
+/**
+ * 读出至多 len 字节已按序到达的数据, 返回写入的字节数或负的错误码
+ *
+ * # Safety
+ * rx 为有效句柄, buf 指向至少 len 个可写字节
+ */
+int64_t stip_receiver_read(struct StipReceiver *rx, uint8_t *buf, size_t len);
+
+/**
+ * 当前应答号与通告窗口, 尚未收到 SYN 时 ack 为 0
+ *
+ * # Safety
+ * rx 为有效句柄, ack 与 window 为可写指针
+ */
+int32_t stip_receiver_state(const struct StipReceiver *rx, uint32_t *ack, uint32_t *window);
+
+/**
+ * 按配置创建协议栈; config 为 NULL 或参数矛盾时返回 NULL
+ *
+ * # Safety
+ * config 为 NULL 或指向有效的 StipConfig
+ */
+struct StipStack *stip_stack_new(const struct StipConfig *config);
+
+/**
+ * 释放协议栈和它的所有连接句柄, 传入 NULL 无操作
+ *
+ * # Safety
+ * stack 必须是 stip_stack_new 返回且尚未释放的指针
+ */
+void stip_stack_free(struct StipStack *stack);
+
+/**
+ * 送入设备收到的一个完整以太网帧(含 FCS), 校验和交付在下一次 stip_stack_poll 中进行
+ *
+ * # Safety
+ * stack 为有效句柄, frame 指向至少 len 个可读字节
+ */
+int32_t stip_stack_feed_frame(struct StipStack *stack,
+                              const uint8_t *frame,
+                              size_t len);
+
+/**
+ * 驱动协议栈到 now_ms, 把一个要发出的帧写入 out, 返回帧长; 没有要发的帧时返回 0
+ * 调用者应反复调用直到返回 0; 缓冲区放不下时返回 STIP_ERR_BUFFER_TOO_SMALL, 这一帧留到下次
+ *
+ * # Safety
+ * stack 为有效句柄, out 指向至少 out_len 个可写字节
+ */
+int64_t stip_stack_poll(struct StipStack *stack,
+                        uint64_t now_ms,
+                        uint8_t *out,
+                        size_t out_len);
+
+/**
+ * 在本机地址的 port 上监听, 返回监听句柄
+ *
+ * # Safety
+ * stack 为有效句柄
+ */
+int32_t stip_tcp_listen(struct StipStack *stack, uint16_t port);
+
+/**
+ * 取出监听句柄上一个已完成握手的连接, 返回连接句柄; 没有时返回 STIP_ERR_AGAIN
+ *
+ * # Safety
+ * stack 为有效句柄
+ */
+int32_t stip_tcp_accept(struct StipStack *stack,
+                        int32_t listener);
+
+/**
+ * 从临时端口向 d_ip:d_port 发起连接, 返回连接句柄; SYN 在下一次 stip_stack_poll 时发出
+ *
+ * # Safety
+ * stack 为有效句柄
+ */
+int32_t stip_tcp_connect(struct StipStack *stack,
+                         uint32_t d_ip,
+                         uint16_t d_port,
+                         uint64_t now_ms);
+
+/**
+ * 写入至多 len 字节, 返回接收进发送缓冲区的字节数或负的错误码
+ *
+ * # Safety
+ * stack 为有效句柄, buf 指向至少 len 个可读字节
+ */
+int64_t stip_tcp_write(struct StipStack *stack, int32_t conn, const uint8_t *buf, size_t len);
+
+/**
+ * 读出至多 len 字节已按序到达的数据, 返回写入的字节数或负的错误码
+ *
+ * # Safety
+ * stack 为有效句柄, buf 指向至少 len 个可写字节
+ */
+int64_t stip_tcp_read(struct StipStack *stack, int32_t conn, uint8_t *buf, size_t len);
+
+/**
+ * 关闭发送方向, FIN 在下一次 stip_stack_poll 时发出; 连接离开连接表之后句柄失效
+ *
+ * # Safety
+ * stack 为有效句柄
+ */
+int32_t stip_tcp_close(struct StipStack *stack,
+                       int32_t conn,
+                       uint64_t now_ms);
+
+/**
+ * 把帧解析成文本写入 out(以 NUL 结尾), 返回不含 NUL 的长度; 缓冲区不够时返回 STIP_ERR_BUFFER_TOO_SMALL
+ *
+ * # Safety
+ * frame 指向至少 len 个可读字节, out 指向至少 out_len 个可写字节
+ */
+int64_t stip_dissect(const uint8_t *frame,
+                     size_t len,
+                     uint8_t *out,
+                     size_t out_len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SIMPLE_TCP_IP_H */
@@ -0,0 +1,349 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+/**
+ * 长度前缀的字节数, 大端
+ */
+pub const LENGTH_PREFIX: usize = 4;
+
+/**
+ * 默认的消息长度上限
+ */
+pub const DEFAULT_MAX_MESSAGE: usize = 16 * 1024 * 1024;
+
+/**
+ * 一次 fill_from 最多读取的字节数
+ */
+const READ_CHUNK: usize = 16 * 1024;
+
+/**
+ * 分帧错误
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FramingError {
+    TooLarge { len: usize, max: usize },              // 长度超过上限: 发送时不写出, 接收时跳过该消息的内容
+    UnexpectedEof { expected: usize, received: usize }, // 对端在一条消息中间关闭, 含长度前缀
+    Io(io::ErrorKind),                                 // WouldBlock 时已经读写的部分保留, 之后可以重试
+}
+
+impl fmt::Display for FramingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FramingError::TooLarge { len, max } => write!(f, "message of {} bytes exceeds the limit of {}", len, max),
+            FramingError::UnexpectedEof { expected, received } => {
+                write!(f, "stream ended after {} of {} bytes of a message", received, expected)
+            }
+            FramingError::Io(kind) => write!(f, "I/O error: {}", kind),
+        }
+    }
+}
+
+impl Error for FramingError {}
+
+impl From<io::Error> for FramingError {
+    fn from(e: io::Error) -> Self {
+        FramingError::Io(e.kind())
+    }
+}
+
+/**
+ * 4 字节大端长度前缀的分帧状态, 不持有流, 可以配合就绪事件在非阻塞流上使用:
+ * READABLE 时 fill_from 读入已到达的字节, 再反复 decode 取出完整的消息;
+ * encode 把消息排进发送积压, flush_to 在 WRITABLE 时写出
+ * 超过上限的消息只报告一次 TooLarge, 它的内容随到随丢, 之后的消息照常解出
+ */
+#[derive(Debug, Clone)]
+pub struct FrameCodec {
+    max: usize,
+    recv: Vec<u8>,
+    skip: usize,   // 被拒绝的消息还没有到达的内容字节数
+    eof: bool,
+    send: Vec<u8>, // 已编码、还没有写出的字节
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        FrameCodec::new(DEFAULT_MAX_MESSAGE)
+    }
+}
+
+impl FrameCodec {
+    pub fn new(max: usize) -> Self {
+        FrameCodec { max, recv: vec![], skip: 0, eof: false, send: vec![] }
+    }
+
+    pub fn max_message(&self) -> usize {
+        self.max
+    }
+
+    /**
+     * 把一条消息排进发送积压
+     */
+    pub fn encode(&mut self, msg: &[u8]) -> Result<(), FramingError> {
+        if msg.len() > self.max || msg.len() > u32::MAX as usize {
+            return Err(FramingError::TooLarge { len: msg.len(), max: self.max });
+        }
+        self.send.extend_from_slice(&(msg.len() as u32).to_be_bytes());
+        self.send.extend_from_slice(msg);
+        Ok(())
+    }
+
+    /**
+     * 尽量写出发送积压, 遇到 WouldBlock 停下, 返回还没有写出的字节数
+     */
+    pub fn flush_to<W: Write>(&mut self, writer: &mut W) -> Result<usize, FramingError> {
+        let mut written = 0;
+        let result = loop {
+            if written == self.send.len() {
+                break Ok(());
+            }
+            match writer.write(&self.send[written..]) {
+                Ok(0) => break Err(FramingError::Io(io::ErrorKind::WriteZero)),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => break Err(e.into()),
+            }
+        };
+        self.send.drain(..written);
+        result.map(|()| self.send.len())
+    }
+
+    pub fn pending_send(&self) -> usize {
+        self.send.len()
+    }
+
+    /**
+     * 从 reader 读一次, 返回读到的字节数; 暂时没有数据 (WouldBlock) 或读到 EOF 时为 0, 用 at_eof 区分
+     */
+    pub fn fill_from<R: Read>(&mut self, reader: &mut R) -> Result<usize, FramingError> {
+        if self.eof {
+            return Ok(0);
+        }
+        let mut buf = [0; READ_CHUNK];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => {
+                    self.eof = true;
+                    return Ok(0);
+                }
+                Ok(n) => {
+                    self.push(&buf[..n]);
+                    return Ok(n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(0),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /**
+     * 直接交给分帧器的字节, 被拒绝的消息的内容在这里丢弃, 不进入缓冲区
+     */
+    pub fn push(&mut self, bytes: &[u8]) {
+        let skipped = self.skip.min(bytes.len());
+        self.skip -= skipped;
+        self.recv.extend_from_slice(&bytes[skipped..]);
+    }
+
+    /**
+     * 对端已经关闭
+     */
+    pub fn at_eof(&self) -> bool {
+        self.eof
+    }
+
+    /**
+     * 缓冲区中的字节数, 不含被跳过的内容
+     */
+    pub fn buffered(&self) -> usize {
+        self.recv.len()
+    }
+
+    /**
+     * 取出一条完整的消息; 还不完整时为 None
+     * 读到 EOF 且停在消息中间时报告 UnexpectedEof 并清空缓冲区, 之后为 None
+     */
+    pub fn decode(&mut self) -> Result<Option<Vec<u8>>, FramingError> {
+        if self.recv.len() >= LENGTH_PREFIX {
+            let len = u32::from_be_bytes(self.recv[..LENGTH_PREFIX].try_into().unwrap()) as usize;
+            if len > self.max {
+                self.recv.drain(..LENGTH_PREFIX);
+                let skipped = len.min(self.recv.len());
+                self.recv.drain(..skipped);
+                self.skip = len - skipped;
+                return Err(FramingError::TooLarge { len, max: self.max });
+            }
+            if self.recv.len() >= LENGTH_PREFIX + len {
+                let msg = self.recv[LENGTH_PREFIX..LENGTH_PREFIX + len].to_vec();
+                self.recv.drain(..LENGTH_PREFIX + len);
+                return Ok(Some(msg));
+            }
+        }
+        if self.eof && (!self.recv.is_empty() || self.skip > 0) {
+            return Err(self.truncated());
+        }
+        Ok(None)
+    }
+
+    fn truncated(&mut self) -> FramingError {
+        let received = self.recv.len();
+        let expected = if received >= LENGTH_PREFIX {
+            LENGTH_PREFIX + u32::from_be_bytes(self.recv[..LENGTH_PREFIX].try_into().unwrap()) as usize
+        } else if self.skip > 0 {
+            self.skip // 被跳过的消息: 只报告还差多少
+        } else {
+            LENGTH_PREFIX
+        };
+        self.recv.clear();
+        self.skip = 0;
+        FramingError::UnexpectedEof { expected, received }
+    }
+}
+
+/**
+ * 在实现了 std::io::Read / Write 的流(如 transport::stream::Stream)上收发长度前缀的消息
+ * 非阻塞流上 WouldBlock 报告为 FramingError::Io, 读到一半的消息和没写完的消息都留在分帧器里;
+ * 流只是临时借用时, 用 into_parts / from_parts 在两次借用之间保留分帧器
+ */
+pub struct FramedStream<S> {
+    inner: S,
+    codec: FrameCodec,
+}
+
+impl<S: Read + Write> FramedStream<S> {
+    pub fn new(inner: S) -> Self {
+        FramedStream::from_parts(inner, FrameCodec::default())
+    }
+
+    pub fn with_max_message(inner: S, max: usize) -> Self {
+        FramedStream::from_parts(inner, FrameCodec::new(max))
+    }
+
+    pub fn from_parts(inner: S, codec: FrameCodec) -> Self {
+        FramedStream { inner, codec }
+    }
+
+    pub fn into_parts(self) -> (S, FrameCodec) {
+        (self.inner, self.codec)
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    pub fn codec(&self) -> &FrameCodec {
+        &self.codec
+    }
+
+    /**
+     * 发出一条消息, 连同之前没写完的部分; 流暂时写不进时余下的留在分帧器里, 下次发送或 flush 时继续
+     */
+    pub fn send_msg(&mut self, msg: &[u8]) -> Result<(), FramingError> {
+        self.codec.encode(msg)?;
+        self.flush()
+    }
+
+    /**
+     * 写出分帧器里积压的字节, 仍有积压时报告 WouldBlock
+     */
+    pub fn flush(&mut self) -> Result<(), FramingError> {
+        match self.codec.flush_to(&mut self.inner)? {
+            0 => Ok(()),
+            _ => Err(FramingError::Io(io::ErrorKind::WouldBlock)),
+        }
+    }
+
+    /**
+     * 收一条消息; 对端在消息边界上关闭时为 None
+     */
+    pub fn recv_msg(&mut self) -> Result<Option<Vec<u8>>, FramingError> {
+        loop {
+            if let Some(msg) = self.codec.decode()? {
+                return Ok(Some(msg));
+            }
+            if self.codec.at_eof() {
+                return Ok(None);
+            }
+            if self.codec.fill_from(&mut self.inner)? == 0 && !self.codec.at_eof() {
+                return Err(FramingError::Io(io::ErrorKind::WouldBlock));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /**
+     * 每次 read 只给出 step 个字节
+     */
+    struct Trickle {
+        data: Vec<u8>,
+        pos: usize,
+        step: usize,
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.step.min(buf.len()).min(self.data.len() - self.pos);
+            buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.data.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_byte_at_a_time() {
+        let mut codec = FrameCodec::default();
+        codec.encode(b"hello").unwrap();
+        codec.encode(b"").unwrap();
+        codec.encode(&[7; 300]).unwrap();
+        let mut wire = Trickle { data: vec![], pos: 0, step: 1 };
+        assert_eq!(codec.flush_to(&mut wire), Ok(0));
+
+        let mut framed = FramedStream::new(wire);
+        assert_eq!(framed.recv_msg(), Ok(Some(b"hello".to_vec())));
+        assert_eq!(framed.recv_msg(), Ok(Some(vec![])));
+        assert_eq!(framed.recv_msg(), Ok(Some(vec![7; 300])));
+        assert_eq!(framed.recv_msg(), Ok(None));
+    }
+
+    #[test]
+    fn test_oversized_message_is_skipped() {
+        let mut codec = FrameCodec::new(8);
+        assert_eq!(codec.encode(&[0; 9]), Err(FramingError::TooLarge { len: 9, max: 8 }));
+        assert_eq!(codec.pending_send(), 0);
+
+        codec.push(&[0, 0, 0, 10, 1, 2, 3]);
+        assert_eq!(codec.decode(), Err(FramingError::TooLarge { len: 10, max: 8 }));
+        codec.push(&[4, 5, 6, 7, 8, 9, 10, 0, 0, 0, 2, b'o']);
+        assert_eq!(codec.decode(), Ok(None));
+        codec.push(b"k");
+        assert_eq!(codec.decode(), Ok(Some(b"ok".to_vec())));
+    }
+
+    #[test]
+    fn test_eof_inside_message() {
+        let mut framed = FramedStream::new(Trickle { data: vec![0, 0, 0, 5, b'a', b'b'], pos: 0, step: 64 });
+        assert_eq!(framed.recv_msg(), Err(FramingError::UnexpectedEof { expected: 9, received: 6 }));
+        assert_eq!(framed.recv_msg(), Ok(None));
+
+        let mut framed = FramedStream::new(Trickle { data: vec![0, 0], pos: 0, step: 64 });
+        assert_eq!(framed.recv_msg(), Err(FramingError::UnexpectedEof { expected: 4, received: 2 }));
+    }
+}
@@ -0,0 +1,2 @@
+pub mod traceroute;
+pub mod framing;
@@ -0,0 +1,290 @@
+use crate::net::icmp_v4::IcmpV4;
+use crate::net::ipv4::{Ipv4Datagram, Ipv4Header};
+use crate::net::pinger::{ECHO_REPLY, ECHO_REQUEST};
+use crate::net::raw_socket::{RawSocketHandle, RawSockets};
+use crate::transport::udp::{UdpDatagram, PROTOCOL_UDP};
+use crate::utils::wire::WireSerialize;
+
+pub const DEFAULT_BASE_PORT: u16 = 33434;
+pub const DEFAULT_MAX_TTL: u8 = 30;
+pub const PROBES_PER_HOP: usize = 3;
+
+const PROTOCOL_ICMP: u8 = 1;
+const TIME_EXCEEDED: u8 = 11;
+const DEST_UNREACHABLE: u8 = 3;
+
+/**
+ * 探测方式: UDP 发往高端口(目的端口 base_port + 探测序号), 或 ICMP 回显
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeMode {
+    Udp { base_port: u16 },
+    IcmpEcho { id: u16 },
+}
+
+/**
+ * 一跳的结果, 每个探测记录应答方地址和往返时间, 超时为 None
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hop {
+    pub ttl: u8,
+    pub probes: Vec<Option<(u32, u64)>>,
+}
+
+impl Hop {
+    /**
+     * 第一个应答的地址
+     */
+    pub fn addr(&self) -> Option<u32> {
+        self.probes.iter().flatten().map(|&(addr, _)| addr).next()
+    }
+}
+
+/**
+ * 逐跳路径探测, 相当于 traceroute -q probes -w timeout
+ * TTL 从 1 开始, 每跳发 probes 个探测, 一次只有一个探测在途;
+ * 中间路由器回 Time Exceeded, 终点回端口不可达(UDP)或回显应答(ICMP), 按差错报文带回的原首部对应到探测
+ */
+pub struct Traceroute {
+    s_addr: u32,
+    d_addr: u32,
+    mode: ProbeMode,
+    probes_per_hop: usize,
+    max_ttl: u8,
+    timeout_ms: u64,
+    next_seq: u16,
+    outstanding: Option<(u16, u64)>, // (seq, 发送时刻)
+    reached: bool,
+    done: bool,
+    hops: Vec<Hop>,
+}
+
+impl Traceroute {
+    pub fn new(s_addr: u32, d_addr: u32) -> Self {
+        Traceroute {
+            s_addr,
+            d_addr,
+            mode: ProbeMode::Udp { base_port: DEFAULT_BASE_PORT },
+            probes_per_hop: PROBES_PER_HOP,
+            max_ttl: DEFAULT_MAX_TTL,
+            timeout_ms: 5000,
+            next_seq: 0,
+            outstanding: None,
+            reached: false,
+            done: false,
+            hops: vec![Hop { ttl: 1, probes: Vec::new() }],
+        }
+    }
+
+    pub fn with_mode(mut self, mode: ProbeMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_probes_per_hop(mut self, probes: usize) -> Self {
+        self.probes_per_hop = probes.max(1);
+        self
+    }
+
+    pub fn with_max_ttl(mut self, max_ttl: u8) -> Self {
+        self.max_ttl = max_ttl.max(1);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout_ms: u64) -> Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+
+    /**
+     * 在途探测超时则记为 None; 没有在途探测时返回下一个探测, 已经带好本跳的 TTL
+     */
+    pub fn poll(&mut self, now_ms: u64) -> Option<Ipv4Datagram> {
+        if let Some((_, sent)) = self.outstanding {
+            if now_ms < sent + self.timeout_ms {
+                return None;
+            }
+            self.record(None);
+        }
+        if self.done {
+            return None;
+        }
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        self.outstanding = Some((seq, now_ms));
+        Some(self.probe(seq, self.current_ttl()))
+    }
+
+    /**
+     * 处理发给本机的 ICMP 数据报, 不属于在途探测的报文忽略, 返回是否对应上了
+     */
+    pub fn on_icmp(&mut self, datagram: &Ipv4Datagram, now_ms: u64) -> bool {
+        self.on_raw_icmp(&datagram.header(), datagram.payload(), now_ms)
+    }
+
+    /**
+     * 读完原始 ICMP 套接字中缓存的报文, 返回其中对应上在途探测的个数
+     */
+    pub fn receive(&mut self, sockets: &mut RawSockets, handle: RawSocketHandle, now_ms: u64) -> usize {
+        let mut matched = 0;
+        while let Some((header, payload)) = sockets.recv(handle) {
+            matched += self.on_raw_icmp(&header, &payload, now_ms) as usize;
+        }
+        matched
+    }
+
+    fn on_raw_icmp(&mut self, header: &Ipv4Header, payload: &[u8], now_ms: u64) -> bool {
+        let Some((seq, sent)) = self.outstanding else { return false };
+        if header.protocol != PROTOCOL_ICMP || header.d_addr != self.s_addr {
+            return false;
+        }
+        let Ok(icmp) = IcmpV4::try_deserialize(payload) else { return false };
+        let from = header.s_addr;
+        let matched = match (icmp.icmp_type(), self.mode) {
+            (ECHO_REPLY, ProbeMode::IcmpEcho { id }) => {
+                let data = icmp.data();
+                from == self.d_addr && data.len() >= 4 && data[0..4] == [(id >> 8) as u8, id as u8, (seq >> 8) as u8, seq as u8]
+            }
+            (TIME_EXCEEDED | DEST_UNREACHABLE, _) => icmp.embedded_datagram().is_some_and(|original| self.is_our_probe(original, seq)),
+            _ => false,
+        };
+        if !matched {
+            return false;
+        }
+        if icmp.icmp_type() != TIME_EXCEEDED {
+            self.reached = true; // 终点的端口不可达/回显应答, 或中途的其他不可达
+        }
+        self.record(Some((from, now_ms - sent)));
+        true
+    }
+
+    pub fn hops(&self) -> &[Hop] {
+        &self.hops
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /**
+     * 是否到达了终点
+     */
+    pub fn reached(&self) -> bool {
+        self.reached
+    }
+
+    fn current_ttl(&self) -> u8 {
+        self.hops.last().map_or(1, |hop| hop.ttl)
+    }
+
+    fn probe(&self, seq: u16, ttl: u8) -> Ipv4Datagram {
+        let (protocol, payload) = match self.mode {
+            ProbeMode::Udp { base_port } => {
+                let udp = UdpDatagram::new(base_port, base_port.wrapping_add(seq), vec![0; 12], self.s_addr, self.d_addr);
+                (PROTOCOL_UDP, udp.serialize())
+            }
+            ProbeMode::IcmpEcho { id } => {
+                let mut data = Vec::with_capacity(16);
+                data.extend_from_slice(&id.to_be_bytes());
+                data.extend_from_slice(&seq.to_be_bytes());
+                data.extend_from_slice(&[0; 12]);
+                (PROTOCOL_ICMP, IcmpV4::new(ECHO_REQUEST, 0, data).serialize())
+            }
+        };
+        Ipv4Datagram::new(4, 5, 0, (20 + payload.len()) as u16, seq, 0, 0, ttl, protocol, self.s_addr, self.d_addr, payload)
+    }
+
+    /**
+     * 差错报文带回的原首部和前 8 字节载荷: 地址、协议以及目的端口或回显 id/seq 都要对得上
+     * 带回的数据报是截断的, 不能按完整数据报解析
+     */
+    fn is_our_probe(&self, original: &[u8], seq: u16) -> bool {
+        let ihl = (original[0] & 0x0f) as usize * 4;
+        if original.len() < ihl + 8 || ihl < 20 {
+            return false;
+        }
+        let s_addr = u32::from_be_bytes([original[12], original[13], original[14], original[15]]);
+        let d_addr = u32::from_be_bytes([original[16], original[17], original[18], original[19]]);
+        if s_addr != self.s_addr || d_addr != self.d_addr {
+            return false;
+        }
+        let transport = &original[ihl..ihl + 8];
+        match self.mode {
+            ProbeMode::Udp { base_port } => {
+                original[9] == PROTOCOL_UDP && u16::from_be_bytes([transport[2], transport[3]]) == base_port.wrapping_add(seq)
+            }
+            ProbeMode::IcmpEcho { id } => {
+                original[9] == PROTOCOL_ICMP
+                    && transport[0] == ECHO_REQUEST
+                    && u16::from_be_bytes([transport[4], transport[5]]) == id
+                    && u16::from_be_bytes([transport[6], transport[7]]) == seq
+            }
+        }
+    }
+
+    /**
+     * 记下在途探测的结果, 本跳探测发满后进入下一跳或结束
+     */
+    fn record(&mut self, result: Option<(u32, u64)>) {
+        self.outstanding = None;
+        let ttl = self.current_ttl();
+        let Some(hop) = self.hops.last_mut() else { return };
+        hop.probes.push(result);
+        if hop.probes.len() < self.probes_per_hop {
+            return;
+        }
+        if self.reached || ttl >= self.max_ttl {
+            self.done = true;
+        } else {
+            self.hops.push(Hop { ttl: ttl + 1, probes: Vec::new() });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOST: u32 = 0x0a000001;
+    const DEST: u32 = 0x0a000302;
+    const ROUTER: u32 = 0x0a000101;
+
+    fn icmp_from(s_addr: u32, icmp: IcmpV4) -> Ipv4Datagram {
+        let payload = icmp.serialize();
+        Ipv4Datagram::new(4, 5, 0, (20 + payload.len()) as u16, 0, 0, 0, 64, PROTOCOL_ICMP, s_addr, HOST, payload)
+    }
+
+    #[test]
+    fn test_timeouts_and_unrelated_errors() {
+        let mut trace = Traceroute::new(HOST, DEST).with_probes_per_hop(2).with_max_ttl(2).with_timeout(100);
+        let first = trace.poll(0).unwrap();
+        assert_eq!(first.ttl(), 1);
+        assert!(trace.poll(50).is_none());
+
+        // 别的探测(端口不同)引起的差错不算数
+        let stray = Traceroute::new(HOST, DEST).probe(7, 1).serialize();
+        assert!(!trace.on_icmp(&icmp_from(ROUTER, IcmpV4::time_exceeded(&stray)), 60));
+        assert!(trace.on_icmp(&icmp_from(ROUTER, IcmpV4::time_exceeded(&first.serialize())), 60));
+
+        assert_eq!(trace.poll(60).unwrap().ttl(), 1);
+        let third = trace.poll(160).unwrap(); // 第二个探测超时
+        assert_eq!(third.ttl(), 2);
+        assert!(trace.poll(260).is_some());
+        assert!(trace.poll(360).is_none());
+        assert!(trace.is_done() && !trace.reached());
+        assert_eq!(trace.hops(), &[
+            Hop { ttl: 1, probes: vec![Some((ROUTER, 60)), None] },
+            Hop { ttl: 2, probes: vec![None, None] },
+        ]);
+    }
+
+    #[test]
+    fn test_icmp_echo_mode() {
+        let mut trace = Traceroute::new(HOST, DEST).with_mode(ProbeMode::IcmpEcho { id: 9 }).with_probes_per_hop(1);
+        let probe = trace.poll(0).unwrap();
+        let request = IcmpV4::try_deserialize(probe.payload()).unwrap();
+        assert!(trace.on_icmp(&icmp_from(DEST, IcmpV4::new(ECHO_REPLY, 0, request.data().to_vec())), 4));
+        assert!(trace.is_done() && trace.reached());
+        assert_eq!(trace.hops()[0].addr(), Some(DEST));
+    }
+}
@@ -0,0 +1,430 @@
+use std::error::Error;
+use std::fmt;
+
+/**
+ * 发送端判断丢包的方式
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LossDetection {
+    #[default]
+    DupAck, // 3 个重复 ACK 即快速重传 (RFC 5681)
+    Rack,   // 按发送时间判断 (RFC 8985), 容忍乱序
+}
+
+/**
+ * TCP 相关参数
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct TcpConfig {
+    pub mss: u16,
+    pub send_buffer: usize,
+    pub recv_buffer: usize, // 接收缓冲区容量, 即最大通告窗口
+    pub window_scale: u8,   // 0 ~ 14
+    pub rto_initial_ms: u64,
+    pub rto_min_ms: u64,
+    pub rto_max_ms: u64,
+    pub max_retransmissions: u32,
+    pub msl_ms: u64, // TIME_WAIT 持续 2 * MSL
+    pub syn_backlog: usize, // 监听端口上保存状态的半连接数上限
+    pub syn_cookies: bool,  // 半连接队列满时改用 SYN cookie 而不是丢弃
+    pub window_update_interval_ms: u64, // 零窗口重新打开后对端静默多久补发窗口更新
+    pub window_update_retries: u32,     // 补发窗口更新的次数上限, 0 表示不补发
+    pub fast_open: bool,                // 被动端接受 TCP Fast Open, 每个监听端口在创建时读取
+    pub initial_cwnd: u32,              // 初始拥塞窗口, 单位为段 (RFC 6928)
+    pub pacing: bool,                   // 按 cwnd / SRTT 的速率均匀发送, 而不是一次发完整个窗口
+    pub pacing_gain_percent: u32,       // 实际速率为 cwnd / SRTT 的百分之多少
+    pub pacing_burst: u32,              // 允许连续发出的段数
+    pub max_syn_per_source_per_sec: Option<u32>, // 监听端口上每个源地址每秒接受的 SYN 数, None 不限
+    pub rcvbuf_autotune: bool,          // 接收缓冲区从 rcvbuf_initial 开始按应用读取速率调整, 上限为 recv_buffer
+    pub rcvbuf_initial: usize,
+    pub offer_window_scale: bool,       // SYN 中提出 Window Scale, 移位数为 window_scale
+    pub offer_sack: bool,               // SYN 中提出 SACK permitted
+    pub offer_timestamps: bool,         // SYN 中提出 Timestamps (RFC 7323)
+    pub loss_detection: LossDetection,
+    pub allow_time_wait_reuse: bool,    // 协商了 Timestamps 的 TIME_WAIT 连接在 TSval 前进的新 SYN 到达时提前结束 (RFC 6191)
+    pub stall_timeout_ms: Option<u64>,  // 有待处理数据却没有报文段进出多久后生成卡死诊断, None 不检测
+    pub destination_cache_size: usize,  // 按对端地址缓存 RTT/ssthresh 的条目数, 0 表示不缓存
+    pub destination_cache_ttl_ms: u64,
+}
+
+impl Default for TcpConfig {
+    fn default() -> Self {
+        TcpConfig {
+            mss: 1460,
+            send_buffer: 64 * 1024,
+            recv_buffer: u16::MAX as usize, // 不提出 Window Scale 时能通告的最大窗口
+            window_scale: 2,
+            rto_initial_ms: 1000,
+            rto_min_ms: 200,
+            rto_max_ms: 60_000,
+            max_retransmissions: 8,
+            msl_ms: 30_000,
+            syn_backlog: 128,
+            syn_cookies: true,
+            window_update_interval_ms: 1000,
+            window_update_retries: 3,
+            fast_open: false,
+            initial_cwnd: 10,
+            pacing: false,
+            pacing_gain_percent: 120,
+            pacing_burst: 2,
+            max_syn_per_source_per_sec: None,
+            rcvbuf_autotune: false,
+            rcvbuf_initial: 16 * 1024,
+            offer_window_scale: false,
+            offer_sack: false,
+            offer_timestamps: false,
+            loss_detection: LossDetection::DupAck,
+            allow_time_wait_reuse: false,
+            stall_timeout_ms: None,
+            destination_cache_size: 256,
+            destination_cache_ttl_ms: 10 * 60_000,
+        }
+    }
+}
+
+impl TcpConfig {
+    pub fn time_wait_ms(&self) -> u64 {
+        2 * self.msl_ms
+    }
+
+    /**
+     * 能通告的最大窗口: 提出 Window Scale 时按 window_scale 移位, 否则窗口字段只有 16 位
+     * 对端不支持时协商结果仍是不移位, 超出的部分只是通告不出去
+     */
+    pub fn max_advertisable_window(&self) -> usize {
+        let shift = if self.offer_window_scale { self.window_scale.min(14) } else { 0 };
+        (u16::MAX as usize) << shift
+    }
+}
+
+/**
+ * ARP 缓存相关参数
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct ArpConfig {
+    pub entry_ttl_ms: u64,        // 确认之后保持 Reachable 的时间, 之后变为 Stale
+    pub stale_ttl_ms: u64,        // 没有流量的 Stale 表项保留多久
+    pub request_retries: u32,
+    pub retry_interval_ms: u64,
+    pub pending_queue_len: usize, // 等待解析时每个地址最多缓存的报文数
+    pub negative_ttl_ms: u64,     // 解析失败后多久之内直接报告不可达, 连续失败时加倍
+    pub negative_ttl_max_ms: u64,
+}
+
+impl Default for ArpConfig {
+    fn default() -> Self {
+        ArpConfig {
+            entry_ttl_ms: 60_000,
+            stale_ttl_ms: 300_000,
+            request_retries: 3,
+            retry_interval_ms: 1000,
+            pending_queue_len: 16,
+            negative_ttl_ms: 5_000,
+            negative_ttl_max_ms: 60_000,
+        }
+    }
+}
+
+/**
+ * ICMP 相关参数
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct IcmpConfig {
+    pub reply_to_echo: bool,
+    pub error_rate_per_sec: u32, // ICMP 差错报文的令牌桶速率
+    pub error_burst: u32,
+}
+
+impl Default for IcmpConfig {
+    fn default() -> Self {
+        IcmpConfig {
+            reply_to_echo: true,
+            error_rate_per_sec: 100,
+            error_burst: 10,
+        }
+    }
+}
+
+/**
+ * 收到的数据报怎样算发给本机 (RFC 1122 3.3.4.2)
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HostModel {
+    #[default]
+    Weak,   // 目的地址是任一接口的地址即可
+    Strong, // 目的地址必须属于数据报到达的接口
+}
+
+/**
+ * IPv4 相关参数
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct Ipv4Config {
+    pub mtu: u16,
+    pub default_ttl: u8,
+    pub reassembly_timeout_ms: u64,
+    pub martian_filter: bool, // 丢弃源地址不可能出现在线路上的数据报
+    pub strict_rpf: bool,     // 源地址必须经由到达的接口可达 (RFC 3704 严格模式)
+    pub loopback_local: bool, // 发往本机任一地址的数据报走回环接口, 而不只是 127.0.0.0/8
+    pub icmp_error_rate: Option<u32>, // 每秒最多发出的 ICMP 差错报文, 也是突发的上限; None 不限速
+    pub host_model: HostModel,
+    pub forwarding: bool,     // 在接口之间转发不是发给本机的数据报
+    pub link_down_abort_ms: Option<u64>, // 链路断开这么久之后放弃经过它的连接; None 时连接一直重传, 等待链路恢复
+}
+
+impl Default for Ipv4Config {
+    fn default() -> Self {
+        Ipv4Config {
+            mtu: 1500,
+            default_ttl: 64,
+            reassembly_timeout_ms: 30_000,
+            martian_filter: true,
+            strict_rpf: false,
+            loopback_local: true,
+            icmp_error_rate: Some(100),
+            host_model: HostModel::Weak,
+            forwarding: false,
+            link_down_abort_ms: None,
+        }
+    }
+}
+
+/**
+ * NAT(masquerade)参数, 空闲超时取 RFC 5382 / 4787 / 5508 的建议值
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct NatConfig {
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::addr::serde_ipv4"))]
+    pub public_ip: u32,
+    pub port_min: u16, // 分配给映射的公网端口范围, 闭区间
+    pub port_max: u16,
+    pub tcp_idle_ms: u64,
+    pub udp_idle_ms: u64,
+    pub icmp_idle_ms: u64,
+}
+
+impl Default for NatConfig {
+    fn default() -> Self {
+        NatConfig {
+            public_ip: 0,
+            port_min: 49152,
+            port_max: 65535,
+            tcp_idle_ms: 7_440_000,
+            udp_idle_ms: 120_000,
+            icmp_idle_ms: 60_000,
+        }
+    }
+}
+
+impl NatConfig {
+    /**
+     * 端口范围不能为空, 也不能包含 0
+     */
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.port_min == 0 {
+            return Err(ConfigError::ZeroValue { field: "nat.port_min" });
+        }
+        if self.port_min > self.port_max {
+            return Err(ConfigError::NatPortRangeInverted { port_min: self.port_min, port_max: self.port_max });
+        }
+        Ok(())
+    }
+}
+
+/**
+ * 整个协议栈的配置, 各组件从这里读取自己的参数
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct StackConfig {
+    pub tcp: TcpConfig,
+    pub arp: ArpConfig,
+    pub icmp: IcmpConfig,
+    pub ipv4: Ipv4Config,
+}
+
+/**
+ * 配置校验错误
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    MtuTooSmall { mtu: u16 },
+    MssTooLarge { mss: u16, mtu: u16 },
+    WindowScaleTooLarge { window_scale: u8 },
+    RecvBufferTooLarge { recv_buffer: usize, max: usize },
+    RtoBoundsInverted { min_ms: u64, max_ms: u64 },
+    RtoInitialOutOfBounds { initial_ms: u64 },
+    NatPortRangeInverted { port_min: u16, port_max: u16 },
+    ZeroValue { field: &'static str },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::MtuTooSmall { mtu } => write!(f, "ipv4.mtu {} is below the minimum of 68", mtu),
+            ConfigError::MssTooLarge { mss, mtu } => write!(f, "tcp.mss {} does not fit in mtu {}", mss, mtu),
+            ConfigError::WindowScaleTooLarge { window_scale } => {
+                write!(f, "tcp.window_scale {} exceeds 14", window_scale)
+            }
+            ConfigError::RecvBufferTooLarge { recv_buffer, max } => {
+                write!(f, "tcp.recv_buffer {} exceeds the {} bytes expressible with window_scale", recv_buffer, max)
+            }
+            ConfigError::RtoBoundsInverted { min_ms, max_ms } => {
+                write!(f, "tcp.rto_min_ms {} is greater than tcp.rto_max_ms {}", min_ms, max_ms)
+            }
+            ConfigError::RtoInitialOutOfBounds { initial_ms } => {
+                write!(f, "tcp.rto_initial_ms {} is outside [rto_min_ms, rto_max_ms]", initial_ms)
+            }
+            ConfigError::NatPortRangeInverted { port_min, port_max } => {
+                write!(f, "nat.port_min {} is greater than nat.port_max {}", port_min, port_max)
+            }
+            ConfigError::ZeroValue { field } => write!(f, "{} must not be zero", field),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl StackConfig {
+    /**
+     * 检查参数之间是否矛盾
+     */
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let tcp = &self.tcp;
+        let ipv4 = &self.ipv4;
+
+        if ipv4.mtu < 68 {
+            return Err(ConfigError::MtuTooSmall { mtu: ipv4.mtu });
+        }
+        if ipv4.default_ttl == 0 {
+            return Err(ConfigError::ZeroValue { field: "ipv4.default_ttl" });
+        }
+        if ipv4.icmp_error_rate == Some(0) {
+            return Err(ConfigError::ZeroValue { field: "ipv4.icmp_error_rate" });
+        }
+        if tcp.mss == 0 {
+            return Err(ConfigError::ZeroValue { field: "tcp.mss" });
+        }
+        if tcp.mss as usize + 40 > ipv4.mtu as usize {
+            return Err(ConfigError::MssTooLarge { mss: tcp.mss, mtu: ipv4.mtu });
+        }
+        if tcp.window_scale > 14 {
+            return Err(ConfigError::WindowScaleTooLarge { window_scale: tcp.window_scale });
+        }
+        if tcp.recv_buffer == 0 {
+            return Err(ConfigError::ZeroValue { field: "tcp.recv_buffer" });
+        }
+        if tcp.recv_buffer > tcp.max_advertisable_window() {
+            return Err(ConfigError::RecvBufferTooLarge { recv_buffer: tcp.recv_buffer, max: tcp.max_advertisable_window() });
+        }
+        if tcp.send_buffer == 0 {
+            return Err(ConfigError::ZeroValue { field: "tcp.send_buffer" });
+        }
+        if tcp.rto_min_ms > tcp.rto_max_ms {
+            return Err(ConfigError::RtoBoundsInverted { min_ms: tcp.rto_min_ms, max_ms: tcp.rto_max_ms });
+        }
+        if tcp.rto_initial_ms < tcp.rto_min_ms || tcp.rto_initial_ms > tcp.rto_max_ms {
+            return Err(ConfigError::RtoInitialOutOfBounds { initial_ms: tcp.rto_initial_ms });
+        }
+        if tcp.syn_backlog == 0 {
+            return Err(ConfigError::ZeroValue { field: "tcp.syn_backlog" });
+        }
+        if tcp.initial_cwnd == 0 {
+            return Err(ConfigError::ZeroValue { field: "tcp.initial_cwnd" });
+        }
+        if tcp.pacing && tcp.pacing_gain_percent == 0 {
+            return Err(ConfigError::ZeroValue { field: "tcp.pacing_gain_percent" });
+        }
+        if tcp.max_syn_per_source_per_sec == Some(0) {
+            return Err(ConfigError::ZeroValue { field: "tcp.max_syn_per_source_per_sec" });
+        }
+        if tcp.window_update_interval_ms == 0 {
+            return Err(ConfigError::ZeroValue { field: "tcp.window_update_interval_ms" });
+        }
+        if tcp.rcvbuf_autotune && tcp.rcvbuf_initial == 0 {
+            return Err(ConfigError::ZeroValue { field: "tcp.rcvbuf_initial" });
+        }
+        if self.arp.pending_queue_len == 0 {
+            return Err(ConfigError::ZeroValue { field: "arp.pending_queue_len" });
+        }
+        if self.arp.negative_ttl_ms == 0 {
+            return Err(ConfigError::ZeroValue { field: "arp.negative_ttl_ms" });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_are_valid() {
+        let config = StackConfig::default();
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(config.tcp.mss, 1460);
+        assert_eq!(config.ipv4.mtu, 1500);
+        assert_eq!(config.tcp.time_wait_ms(), 60_000);
+    }
+
+    #[test]
+    fn test_validation_failures() {
+        let mut config = StackConfig::default();
+        config.ipv4.mtu = 67;
+        assert_eq!(config.validate(), Err(ConfigError::MtuTooSmall { mtu: 67 }));
+
+        let mut config = StackConfig::default();
+        config.tcp.rto_min_ms = 5000;
+        config.tcp.rto_max_ms = 1000;
+        assert_eq!(config.validate(), Err(ConfigError::RtoBoundsInverted { min_ms: 5000, max_ms: 1000 }));
+
+        let mut config = StackConfig::default();
+        config.tcp.window_scale = 0;
+        config.tcp.recv_buffer = 65536;
+        assert_eq!(config.validate(), Err(ConfigError::RecvBufferTooLarge { recv_buffer: 65536, max: 65535 }));
+
+        // window_scale 只在提出 Window Scale 时放大上限
+        let mut config = StackConfig::default();
+        config.tcp.recv_buffer = 200_000;
+        assert_eq!(config.validate(), Err(ConfigError::RecvBufferTooLarge { recv_buffer: 200_000, max: 65535 }));
+        config.tcp.offer_window_scale = true;
+        assert_eq!(config.validate(), Ok(()));
+
+        let mut config = StackConfig::default();
+        config.tcp.window_scale = 15;
+        assert_eq!(config.validate(), Err(ConfigError::WindowScaleTooLarge { window_scale: 15 }));
+
+        let mut config = StackConfig::default();
+        config.ipv4.mtu = 576;
+        assert_eq!(config.validate(), Err(ConfigError::MssTooLarge { mss: 1460, mtu: 576 }));
+        config.tcp.mss = 536;
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_nat_port_range() {
+        assert_eq!(NatConfig::default().validate(), Ok(()));
+        let single = NatConfig { port_min: 50000, port_max: 50000, ..NatConfig::default() };
+        assert_eq!(single.validate(), Ok(()));
+        let inverted = NatConfig { port_min: 50001, port_max: 50000, ..NatConfig::default() };
+        assert_eq!(inverted.validate(), Err(ConfigError::NatPortRangeInverted { port_min: 50001, port_max: 50000 }));
+        let zero = NatConfig { port_min: 0, ..NatConfig::default() };
+        assert_eq!(zero.validate(), Err(ConfigError::ZeroValue { field: "nat.port_min" }));
+    }
+
+    #[test]
+    fn test_tiny_msl_shortens_time_wait() {
+        let mut config = StackConfig::default();
+        config.tcp.msl_ms = 10;
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(config.tcp.time_wait_ms(), 20);
+    }
+}
@@ -0,0 +1,249 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::config::ConfigError;
+use crate::link::arp::ArpParseError;
+use crate::link::ethernet::EthernetParseError;
+use crate::net::icmp_v4::IcmpParseError;
+use crate::net::ipv4::Ipv4ParseError;
+use crate::transport::tcp_connection::ConnectionError;
+use crate::transport::tcp_segment::TcpParseError;
+
+/**
+ * 网络设备错误
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceError {
+    Down,
+    QueueFull,
+    Io(String),
+    Unsupported { what: &'static str },
+    ShortPayload { len: usize, min: usize }, // 严格模式下载荷不足最小长度, 上层必须自己补齐
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceError::Down => write!(f, "device is down"),
+            DeviceError::QueueFull => write!(f, "device transmit queue is full"),
+            DeviceError::Io(msg) => write!(f, "device I/O error: {}", msg),
+            DeviceError::Unsupported { what } => write!(f, "device does not support {}", what),
+            DeviceError::ShortPayload { len, min } => {
+                write!(f, "payload of {} bytes is below the {} byte minimum and padding is strict", len, min)
+            }
+        }
+    }
+}
+
+impl Error for DeviceError {}
+
+/**
+ * 发送路径上的错误
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendError {
+    PayloadTooLarge { len: usize, max: usize },
+    NoRoute { d_addr: u32 },
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::PayloadTooLarge { len, max } => {
+                write!(f, "payload of {} bytes exceeds the limit of {}", len, max)
+            }
+            SendError::NoRoute { d_addr } => {
+                let [a, b, c, d] = d_addr.to_be_bytes();
+                write!(f, "no route to {}.{}.{}.{}", a, b, c, d)
+            }
+        }
+    }
+}
+
+impl Error for SendError {}
+
+/**
+ * 序列化错误
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SerializeError {
+    BufferTooSmall { needed: usize, available: usize },
+}
+
+impl fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerializeError::BufferTooSmall { needed, available } => {
+                write!(f, "buffer too small: need {} bytes, have {}", needed, available)
+            }
+        }
+    }
+}
+
+impl Error for SerializeError {}
+
+/**
+ * WireDeserialize 统一使用的解析错误, 包装各层的解析错误
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Ethernet(EthernetParseError),
+    Arp(ArpParseError),
+    Ipv4(Ipv4ParseError),
+    Tcp(TcpParseError),
+    Icmp(IcmpParseError),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Ethernet(e) => write!(f, "ethernet: {}", e),
+            ParseError::Arp(e) => write!(f, "arp: {}", e),
+            ParseError::Ipv4(e) => write!(f, "ipv4: {}", e),
+            ParseError::Tcp(e) => write!(f, "tcp: {}", e),
+            ParseError::Icmp(e) => write!(f, "icmp: {}", e),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Ethernet(e) => Some(e),
+            ParseError::Arp(e) => Some(e),
+            ParseError::Ipv4(e) => Some(e),
+            ParseError::Tcp(e) => Some(e),
+            ParseError::Icmp(e) => Some(e),
+        }
+    }
+}
+
+/**
+ * 协议栈顶层错误, 包装各层的错误
+ * 各层错误都实现了 From, 可以直接用 `?` 向上传递
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StackError {
+    Ethernet(EthernetParseError),
+    Arp(ArpParseError),
+    Ipv4(Ipv4ParseError),
+    Tcp(TcpParseError),
+    Icmp(IcmpParseError),
+    Device(DeviceError),
+    Connection(ConnectionError),
+    Send(SendError),
+    Serialize(SerializeError),
+    Config(ConfigError),
+}
+
+impl fmt::Display for StackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StackError::Ethernet(e) => write!(f, "ethernet: {}", e),
+            StackError::Arp(e) => write!(f, "arp: {}", e),
+            StackError::Ipv4(e) => write!(f, "ipv4: {}", e),
+            StackError::Tcp(e) => write!(f, "tcp: {}", e),
+            StackError::Icmp(e) => write!(f, "icmp: {}", e),
+            StackError::Device(e) => write!(f, "device: {}", e),
+            StackError::Connection(e) => write!(f, "connection: {}", e),
+            StackError::Send(e) => write!(f, "send: {}", e),
+            StackError::Serialize(e) => write!(f, "serialize: {}", e),
+            StackError::Config(e) => write!(f, "config: {}", e),
+        }
+    }
+}
+
+impl Error for StackError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StackError::Ethernet(e) => Some(e),
+            StackError::Arp(e) => Some(e),
+            StackError::Ipv4(e) => Some(e),
+            StackError::Tcp(e) => Some(e),
+            StackError::Icmp(e) => Some(e),
+            StackError::Device(e) => Some(e),
+            StackError::Connection(e) => Some(e),
+            StackError::Send(e) => Some(e),
+            StackError::Serialize(e) => Some(e),
+            StackError::Config(e) => Some(e),
+        }
+    }
+}
+
+macro_rules! impl_from_error {
+    ($error: ty, $variant: ident) => {
+        impl From<$error> for StackError {
+            fn from(e: $error) -> Self {
+                StackError::$variant(e)
+            }
+        }
+    };
+    ($target: ident, $error: ty, $variant: ident) => {
+        impl From<$error> for $target {
+            fn from(e: $error) -> Self {
+                $target::$variant(e)
+            }
+        }
+    };
+}
+
+impl_from_error!(EthernetParseError, Ethernet);
+impl_from_error!(ArpParseError, Arp);
+impl_from_error!(Ipv4ParseError, Ipv4);
+impl_from_error!(TcpParseError, Tcp);
+impl_from_error!(IcmpParseError, Icmp);
+impl_from_error!(DeviceError, Device);
+impl_from_error!(ConnectionError, Connection);
+impl_from_error!(SendError, Send);
+impl_from_error!(SerializeError, Serialize);
+impl_from_error!(ConfigError, Config);
+impl_from_error!(ParseError, EthernetParseError, Ethernet);
+impl_from_error!(ParseError, ArpParseError, Arp);
+impl_from_error!(ParseError, Ipv4ParseError, Ipv4);
+impl_from_error!(ParseError, TcpParseError, Tcp);
+impl_from_error!(ParseError, IcmpParseError, Icmp);
+
+impl From<ParseError> for StackError {
+    fn from(e: ParseError) -> Self {
+        match e {
+            ParseError::Ethernet(e) => StackError::Ethernet(e),
+            ParseError::Arp(e) => StackError::Arp(e),
+            ParseError::Ipv4(e) => StackError::Ipv4(e),
+            ParseError::Tcp(e) => StackError::Tcp(e),
+            ParseError::Icmp(e) => StackError::Icmp(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::ethernet::EthernetFrame;
+    use crate::net::ipv4::Ipv4Datagram;
+
+    fn parse_chain(bytes: &[u8]) -> Result<Ipv4Datagram, StackError> {
+        let frame = EthernetFrame::try_deserialize(bytes)?;
+        let datagram = Ipv4Datagram::try_deserialize(frame.payload())?;
+        Ok(datagram)
+    }
+
+    #[test]
+    fn test_question_mark_flows_through_layers() {
+        let err = parse_chain(&[0u8; 10]).unwrap_err();
+        assert_eq!(err, StackError::Ethernet(EthernetParseError::TooShort { len: 10, min: 64 }));
+        assert_eq!(err.to_string(), "ethernet: frame too short: 10 bytes, need at least 64");
+        assert!(err.source().is_some());
+
+        // 以太网帧合法, 但载荷不是合法的 IPv4 头部
+        let err = parse_chain(&[0u8; 64]).unwrap_err();
+        assert_eq!(err, StackError::Ipv4(Ipv4ParseError::BadHeaderLength { ihl: 0, available: 46 }));
+    }
+
+    #[test]
+    fn test_display_context() {
+        let err: StackError = SendError::NoRoute { d_addr: 0x0a000002 }.into();
+        assert_eq!(err.to_string(), "send: no route to 10.0.0.2");
+        let err: StackError = ConnectionError::Reset.into();
+        assert_eq!(err.to_string(), "connection: connection reset by peer");
+    }
+}
@@ -0,0 +1,703 @@
+/**
+ * 供 C 固件嵌入的 extern "C" 接口, 由 `ffi` feature 打开, 头文件见 include/simple_tcp_ip.h
+ * 所有缓冲区都由调用者持有; 每个入口都用 catch_unwind 包住, panic 不会越过边界
+ */
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+
+use crate::config::StackConfig;
+use crate::error::StackError;
+use crate::link::ethernet::EthernetFrame;
+use crate::link::interface::EthernetInterface;
+use crate::net::ipv4::Ipv4Datagram;
+use crate::stack::Stack;
+use crate::transport::tcp_connection::ConnectionId;
+use crate::transport::tcp_receiver::TcpReceiver;
+use crate::transport::tcp_segment::TcpSegment;
+use crate::utils::dissect;
+
+const PROTOCOL_TCP: u8 = 6;
+const EPHEMERAL_PORT_MIN: u16 = 49152;
+
+pub const STIP_OK: i32 = 0;
+pub const STIP_ERR_NULL: i32 = -1;
+pub const STIP_ERR_BUFFER_TOO_SMALL: i32 = -2;
+pub const STIP_ERR_PANIC: i32 = -3;
+pub const STIP_ERR_NOT_TCP: i32 = -4;
+pub const STIP_ERR_CHECKSUM: i32 = -5;
+pub const STIP_ERR_NOT_FOR_US: i32 = -6;
+pub const STIP_ERR_AGAIN: i32 = -7;
+pub const STIP_ERR_BAD_HANDLE: i32 = -8;
+pub const STIP_ERR_ETHERNET: i32 = -10;
+pub const STIP_ERR_IPV4: i32 = -11;
+pub const STIP_ERR_TCP: i32 = -12;
+pub const STIP_ERR_ICMP: i32 = -13;
+pub const STIP_ERR_ARP: i32 = -14;
+pub const STIP_ERR_DEVICE: i32 = -20;
+pub const STIP_ERR_CONNECTION: i32 = -21;
+pub const STIP_ERR_SEND: i32 = -22;
+pub const STIP_ERR_SERIALIZE: i32 = -23;
+pub const STIP_ERR_CONFIG: i32 = -24;
+
+/**
+ * StackError 到 C 错误码的映射
+ */
+pub fn error_code(e: &StackError) -> i32 {
+    match e {
+        StackError::Ethernet(_) => STIP_ERR_ETHERNET,
+        StackError::Ipv4(_) => STIP_ERR_IPV4,
+        StackError::Tcp(_) => STIP_ERR_TCP,
+        StackError::Icmp(_) => STIP_ERR_ICMP,
+        StackError::Arp(_) => STIP_ERR_ARP,
+        StackError::Device(_) => STIP_ERR_DEVICE,
+        StackError::Connection(_) => STIP_ERR_CONNECTION,
+        StackError::Send(_) => STIP_ERR_SEND,
+        StackError::Serialize(_) => STIP_ERR_SERIALIZE,
+        StackError::Config(_) => STIP_ERR_CONFIG,
+    }
+}
+
+fn code<E: Into<StackError>>(e: E) -> i32 {
+    error_code(&e.into())
+}
+
+/**
+ * 不透明句柄, C 侧只持有指针
+ */
+pub struct StipReceiver {
+    receiver: TcpReceiver,
+    ip: u32,
+    port: u16,
+}
+
+fn guard<F: FnOnce() -> i64>(f: F) -> i64 {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(STIP_ERR_PANIC as i64)
+}
+
+/**
+ * 长度为 0 时允许空指针
+ */
+unsafe fn input<'a>(ptr: *const u8, len: usize) -> Option<&'a [u8]> {
+    if len == 0 {
+        return Some(&[]);
+    }
+    if ptr.is_null() {
+        return None;
+    }
+    Some(slice::from_raw_parts(ptr, len))
+}
+
+unsafe fn output<'a>(ptr: *mut u8, len: usize) -> Option<&'a mut [u8]> {
+    if len == 0 {
+        return Some(&mut []);
+    }
+    if ptr.is_null() {
+        return None;
+    }
+    Some(slice::from_raw_parts_mut(ptr, len))
+}
+
+/**
+ * 逐层解析、校验并交给接收端, 与 EthernetInterface::verify_datagram 一样检查 FCS、IP 首部校验和与 TCP 校验和
+ * 目的地址或端口不是接收端自己的报文段不交付
+ */
+fn feed(rx: &mut StipReceiver, frame: &[u8]) -> Result<(), i32> {
+    let frame = EthernetFrame::try_deserialize(frame).map_err(code)?;
+    if !frame.check_fcs() {
+        return Err(STIP_ERR_CHECKSUM);
+    }
+    let datagram = Ipv4Datagram::try_deserialize(frame.payload()).map_err(code)?;
+    if !datagram.check_hdr_checksum() {
+        return Err(STIP_ERR_CHECKSUM);
+    }
+    if datagram.protocol() != PROTOCOL_TCP {
+        return Err(STIP_ERR_NOT_TCP);
+    }
+    if datagram.d_addr() != rx.ip {
+        return Err(STIP_ERR_NOT_FOR_US);
+    }
+    let segment = TcpSegment::try_deserialize(datagram.payload()).map_err(code)?;
+    if !segment.check_checksum(datagram.s_addr(), datagram.d_addr()) {
+        return Err(STIP_ERR_CHECKSUM);
+    }
+    if segment.d_port != rx.port {
+        return Err(STIP_ERR_NOT_FOR_US);
+    }
+    rx.receiver.segment_received(&segment);
+    Ok(())
+}
+
+/**
+ * 创建接收端, 只接受发往 ip:port 的报文段, capacity 为接收缓冲区字节数; 失败返回 NULL
+ */
+#[no_mangle]
+pub extern "C" fn stip_receiver_new(ip: u32, port: u16, capacity: usize) -> *mut StipReceiver {
+    panic::catch_unwind(|| Box::into_raw(Box::new(StipReceiver { receiver: TcpReceiver::new(0, capacity), ip, port })))
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/**
+ * 释放句柄, 传入 NULL 无操作
+ *
+ * # Safety
+ * rx 必须是 stip_receiver_new 返回且尚未释放的指针
+ */
+#[no_mangle]
+pub unsafe extern "C" fn stip_receiver_free(rx: *mut StipReceiver) {
+    if !rx.is_null() {
+        drop(Box::from_raw(rx));
+    }
+}
+
+/**
+ * 送入一个完整的以太网帧(含 FCS); 非 TCP 报文返回 STIP_ERR_NOT_TCP, 校验失败返回 STIP_ERR_CHECKSUM
+ * 不是发给接收端的返回 STIP_ERR_NOT_FOR_US
+ *
+ * # Safety
+ * rx 为有效句柄, frame 指向至少 len 个可读字节
+ */
+#[no_mangle]
+pub unsafe extern "C" fn stip_receiver_feed_frame(rx: *mut StipReceiver, frame: *const u8, len: usize) -> i32 {
+    guard(|| {
+        let (Some(rx), Some(frame)) = (rx.as_mut(), input(frame, len)) else {
+            return STIP_ERR_NULL as i64;
+        };
+        match feed(rx, frame) {
+            Ok(()) => STIP_OK as i64,
+            Err(code) => code as i64,
+        }
+    }) as i32
+}
+
+/**
+ * 读出至多 len 字节已按序到达的数据, 返回写入的字节数或负的错误码
+ *
+ * # Safety
+ * rx 为有效句柄, buf 指向至少 len 个可写字节
+ */
+#[no_mangle]
+pub unsafe extern "C" fn stip_receiver_read(rx: *mut StipReceiver, buf: *mut u8, len: usize) -> i64 {
+    guard(|| {
+        let (Some(rx), Some(buf)) = (rx.as_mut(), output(buf, len)) else {
+            return STIP_ERR_NULL as i64;
+        };
+        let data = rx.receiver.read(len);
+        buf[..data.len()].copy_from_slice(&data);
+        data.len() as i64
+    })
+}
+
+/**
+ * 当前应答号与通告窗口, 尚未收到 SYN 时 ack 为 0
+ *
+ * # Safety
+ * rx 为有效句柄, ack 与 window 为可写指针
+ */
+#[no_mangle]
+pub unsafe extern "C" fn stip_receiver_state(rx: *const StipReceiver, ack: *mut u32, window: *mut u32) -> i32 {
+    guard(|| {
+        let Some(rx) = rx.as_ref() else {
+            return STIP_ERR_NULL as i64;
+        };
+        if ack.is_null() || window.is_null() {
+            return STIP_ERR_NULL as i64;
+        }
+        let snapshot = rx.receiver.snapshot();
+        *ack = snapshot.ack.unwrap_or(0);
+        *window = snapshot.window;
+        STIP_OK as i64
+    }) as i32
+}
+
+/**
+ * stip_stack_new 的参数, 布局与头文件中的结构体一致
+ */
+#[repr(C)]
+pub struct StipConfig {
+    pub mac: [u8; 6],
+    pub ip: u32,
+    pub prefix_len: u8,
+    pub gateway: u32,       // 0 表示没有默认网关
+    pub mss: u16,           // 0 取默认值
+    pub recv_buffer: usize, // 0 取默认值
+}
+
+/**
+ * C 侧的连接句柄是 StipStack::handles 中的下标, 指向一个监听端口或一个连接
+ */
+#[derive(Debug, Clone, Copy)]
+enum Handle {
+    Listener(ConnectionId),
+    Conn { id: ConnectionId, closed: bool },
+}
+
+/**
+ * 单接口的协议栈 (Stack) 和 C 侧的句柄表
+ * 要发出的帧排在 tx 中, 由 stip_stack_poll 逐个交给调用者
+ */
+pub struct StipStack {
+    inner: Stack,
+    ip: u32,
+    tx: VecDeque<Vec<u8>>,
+    handles: Vec<Option<Handle>>,
+    next_port: u16,
+}
+
+impl StipStack {
+    fn new(config: &StipConfig) -> Result<Self, StackError> {
+        let mut stack = StackConfig::default();
+        if config.mss != 0 {
+            stack.tcp.mss = config.mss;
+        }
+        if config.recv_buffer != 0 {
+            stack.tcp.recv_buffer = config.recv_buffer;
+        }
+        let mut inner = Stack::new(&stack)?;
+        inner.add_interface(EthernetInterface::new(config.mac, config.ip, config.prefix_len));
+        if config.gateway != 0 {
+            inner.interfaces_mut().routes_mut().add(0, 0, Some(config.gateway));
+        }
+        Ok(StipStack {
+            inner,
+            ip: config.ip,
+            tx: VecDeque::new(),
+            handles: vec![],
+            next_port: EPHEMERAL_PORT_MIN,
+        })
+    }
+
+    /**
+     * 占用第一个空闲的下标
+     */
+    fn insert(&mut self, handle: Handle) -> i32 {
+        let slot = match self.handles.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None => {
+                self.handles.push(None);
+                self.handles.len() - 1
+            }
+        };
+        self.handles[slot] = Some(handle);
+        slot as i32
+    }
+
+    fn handle(&self, handle: i32) -> Option<Handle> {
+        usize::try_from(handle).ok().and_then(|slot| self.handles.get(slot).copied().flatten())
+    }
+
+    fn conn(&self, handle: i32) -> Result<ConnectionId, i32> {
+        match self.handle(handle) {
+            Some(Handle::Conn { id, .. }) => Ok(id),
+            _ => Err(STIP_ERR_BAD_HANDLE),
+        }
+    }
+
+    /**
+     * 下一个没有被占用的临时端口
+     */
+    fn ephemeral(&mut self, d_ip: u32, d_port: u16) -> ConnectionId {
+        loop {
+            let id = ConnectionId { s_ip: self.ip, s_port: self.next_port, d_ip, d_port };
+            self.next_port = self.next_port.checked_add(1).unwrap_or(EPHEMERAL_PORT_MIN);
+            if self.inner.tcp().state(id).is_none() {
+                return id;
+            }
+        }
+    }
+
+    /**
+     * 处理收到的帧、推进定时器, 要发出的帧放进 tx; 同一时刻重复调用是幂等的
+     */
+    fn drive(&mut self, now_ms: u64) {
+        self.inner.poll(now_ms);
+        self.inner.take_delivered(); // 没有 TCP 之外的套接字
+        // 关闭过的连接离开连接表之后句柄可以复用
+        for slot in self.handles.iter_mut() {
+            if let Some(Handle::Conn { id, closed: true }) = *slot {
+                if self.inner.tcp().state(id).is_none() {
+                    *slot = None;
+                }
+            }
+        }
+        self.tx.extend(self.inner.take_tx(0));
+    }
+}
+
+/**
+ * 按配置创建协议栈; config 为 NULL 或参数矛盾时返回 NULL
+ *
+ * # Safety
+ * config 为 NULL 或指向有效的 StipConfig
+ */
+#[no_mangle]
+pub unsafe extern "C" fn stip_stack_new(config: *const StipConfig) -> *mut StipStack {
+    panic::catch_unwind(|| match config.as_ref().map(StipStack::new) {
+        Some(Ok(stack)) => Box::into_raw(Box::new(stack)),
+        _ => std::ptr::null_mut(),
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/**
+ * 释放协议栈和它的所有连接句柄, 传入 NULL 无操作
+ *
+ * # Safety
+ * stack 必须是 stip_stack_new 返回且尚未释放的指针
+ */
+#[no_mangle]
+pub unsafe extern "C" fn stip_stack_free(stack: *mut StipStack) {
+    if !stack.is_null() {
+        drop(Box::from_raw(stack));
+    }
+}
+
+/**
+ * 送入设备收到的一个完整以太网帧(含 FCS), 校验和交付在下一次 stip_stack_poll 中进行
+ *
+ * # Safety
+ * stack 为有效句柄, frame 指向至少 len 个可读字节
+ */
+#[no_mangle]
+pub unsafe extern "C" fn stip_stack_feed_frame(stack: *mut StipStack, frame: *const u8, len: usize) -> i32 {
+    guard(|| {
+        let (Some(stack), Some(frame)) = (stack.as_mut(), input(frame, len)) else {
+            return STIP_ERR_NULL as i64;
+        };
+        stack.inner.frame_received(0, frame.to_vec());
+        STIP_OK as i64
+    }) as i32
+}
+
+/**
+ * 驱动协议栈到 now_ms, 把一个要发出的帧写入 out, 返回帧长; 没有要发的帧时返回 0
+ * 调用者应反复调用直到返回 0; 缓冲区放不下时返回 STIP_ERR_BUFFER_TOO_SMALL, 这一帧留到下次
+ *
+ * # Safety
+ * stack 为有效句柄, out 指向至少 out_len 个可写字节
+ */
+#[no_mangle]
+pub unsafe extern "C" fn stip_stack_poll(stack: *mut StipStack, now_ms: u64, out: *mut u8, out_len: usize) -> i64 {
+    guard(|| {
+        let (Some(stack), Some(out)) = (stack.as_mut(), output(out, out_len)) else {
+            return STIP_ERR_NULL as i64;
+        };
+        stack.drive(now_ms);
+        let Some(frame) = stack.tx.front() else {
+            return 0;
+        };
+        if frame.len() > out.len() {
+            return STIP_ERR_BUFFER_TOO_SMALL as i64;
+        }
+        out[..frame.len()].copy_from_slice(frame);
+        stack.tx.pop_front().unwrap().len() as i64
+    })
+}
+
+/**
+ * 在本机地址的 port 上监听, 返回监听句柄
+ *
+ * # Safety
+ * stack 为有效句柄
+ */
+#[no_mangle]
+pub unsafe extern "C" fn stip_tcp_listen(stack: *mut StipStack, port: u16) -> i32 {
+    guard(|| {
+        let Some(stack) = stack.as_mut() else {
+            return STIP_ERR_NULL as i64;
+        };
+        let listener = stack.inner.tcp_mut().listen(stack.ip, port);
+        stack.insert(Handle::Listener(listener)) as i64
+    }) as i32
+}
+
+/**
+ * 取出监听句柄上一个已完成握手的连接, 返回连接句柄; 没有时返回 STIP_ERR_AGAIN
+ *
+ * # Safety
+ * stack 为有效句柄
+ */
+#[no_mangle]
+pub unsafe extern "C" fn stip_tcp_accept(stack: *mut StipStack, listener: i32) -> i32 {
+    guard(|| {
+        let Some(stack) = stack.as_mut() else {
+            return STIP_ERR_NULL as i64;
+        };
+        let Some(Handle::Listener(listener)) = stack.handle(listener) else {
+            return STIP_ERR_BAD_HANDLE as i64;
+        };
+        match stack.inner.tcp_mut().accept(listener) {
+            Some(id) => stack.insert(Handle::Conn { id, closed: false }) as i64,
+            None => STIP_ERR_AGAIN as i64,
+        }
+    }) as i32
+}
+
+/**
+ * 从临时端口向 d_ip:d_port 发起连接, 返回连接句柄; SYN 在下一次 stip_stack_poll 时发出
+ *
+ * # Safety
+ * stack 为有效句柄
+ */
+#[no_mangle]
+pub unsafe extern "C" fn stip_tcp_connect(stack: *mut StipStack, d_ip: u32, d_port: u16, now_ms: u64) -> i32 {
+    guard(|| {
+        let Some(stack) = stack.as_mut() else {
+            return STIP_ERR_NULL as i64;
+        };
+        let id = stack.ephemeral(d_ip, d_port);
+        let _ = stack.inner.connect(id, now_ms); // 没有路由时留给重传
+        stack.insert(Handle::Conn { id, closed: false }) as i64
+    }) as i32
+}
+
+/**
+ * 写入至多 len 字节, 返回接收进发送缓冲区的字节数或负的错误码
+ *
+ * # Safety
+ * stack 为有效句柄, buf 指向至少 len 个可读字节
+ */
+#[no_mangle]
+pub unsafe extern "C" fn stip_tcp_write(stack: *mut StipStack, conn: i32, buf: *const u8, len: usize) -> i64 {
+    guard(|| {
+        let (Some(stack), Some(buf)) = (stack.as_mut(), input(buf, len)) else {
+            return STIP_ERR_NULL as i64;
+        };
+        let result = stack.conn(conn).and_then(|id| stack.inner.tcp_mut().write(id, buf).map_err(code));
+        result.map_or_else(|code| code as i64, |written| written as i64)
+    })
+}
+
+/**
+ * 读出至多 len 字节已按序到达的数据, 返回写入的字节数或负的错误码
+ *
+ * # Safety
+ * stack 为有效句柄, buf 指向至少 len 个可写字节
+ */
+#[no_mangle]
+pub unsafe extern "C" fn stip_tcp_read(stack: *mut StipStack, conn: i32, buf: *mut u8, len: usize) -> i64 {
+    guard(|| {
+        let (Some(stack), Some(buf)) = (stack.as_mut(), output(buf, len)) else {
+            return STIP_ERR_NULL as i64;
+        };
+        match stack.conn(conn).and_then(|id| stack.inner.tcp_mut().read(id, len).map_err(code)) {
+            Ok(data) => {
+                buf[..data.len()].copy_from_slice(&data);
+                data.len() as i64
+            }
+            Err(code) => code as i64,
+        }
+    })
+}
+
+/**
+ * 关闭发送方向, FIN 在下一次 stip_stack_poll 时发出; 连接离开连接表之后句柄失效
+ *
+ * # Safety
+ * stack 为有效句柄
+ */
+#[no_mangle]
+pub unsafe extern "C" fn stip_tcp_close(stack: *mut StipStack, conn: i32, now_ms: u64) -> i32 {
+    guard(|| {
+        let Some(stack) = stack.as_mut() else {
+            return STIP_ERR_NULL as i64;
+        };
+        let id = match stack.conn(conn) {
+            Ok(id) => id,
+            Err(code) => return code as i64,
+        };
+        if let Err(e) = stack.inner.tcp_mut().close(id, now_ms) {
+            return code(e) as i64;
+        }
+        stack.handles[conn as usize] = Some(Handle::Conn { id, closed: true });
+        STIP_OK as i64
+    }) as i32
+}
+
+/**
+ * 把帧解析成文本写入 out(以 NUL 结尾), 返回不含 NUL 的长度; 缓冲区不够时返回 STIP_ERR_BUFFER_TOO_SMALL
+ *
+ * # Safety
+ * frame 指向至少 len 个可读字节, out 指向至少 out_len 个可写字节
+ */
+#[no_mangle]
+pub unsafe extern "C" fn stip_dissect(frame: *const u8, len: usize, out: *mut u8, out_len: usize) -> i64 {
+    guard(|| {
+        let (Some(frame), Some(out)) = (input(frame, len), output(out, out_len)) else {
+            return STIP_ERR_NULL as i64;
+        };
+        let text = dissect::dissect(frame);
+        if text.len() + 1 > out.len() {
+            return STIP_ERR_BUFFER_TOO_SMALL as i64;
+        }
+        out[..text.len()].copy_from_slice(text.as_bytes());
+        out[text.len()] = 0;
+        text.len() as i64
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::packet::PacketBuilder;
+    use crate::transport::tcp_segment::TcpCtrlFlag;
+
+    const A: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+    const B: [u8; 6] = [0x02, 0, 0, 0, 0, 2];
+    const A_IP: u32 = 0x0a000001;
+    const B_IP: u32 = 0x0a000002;
+
+    fn packet(seq: u32, flags: u16, payload: &[u8]) -> PacketBuilder {
+        PacketBuilder::ether(A, B).ipv4(A_IP, B_IP).tcp(40000, 80).flags(flags).seq(seq).payload(payload)
+    }
+
+    fn segment(seq: u32, flags: u16, payload: &[u8]) -> Vec<u8> {
+        packet(seq, flags, payload).build()
+    }
+
+    fn config(mac: [u8; 6], ip: u32) -> StipConfig {
+        StipConfig { mac, ip, prefix_len: 24, gateway: 0, mss: 0, recv_buffer: 0 }
+    }
+
+    /**
+     * 像 C 侧的主循环一样在两个协议栈之间搬运帧, 直到双方都没有要发的
+     */
+    unsafe fn shuttle(a: *mut StipStack, b: *mut StipStack, now_ms: u64) {
+        let mut buf = vec![0u8; 2048];
+        loop {
+            let mut moved = false;
+            for (from, to) in [(a, b), (b, a)] {
+                loop {
+                    let n = stip_stack_poll(from, now_ms, buf.as_mut_ptr(), buf.len());
+                    assert!(n >= 0, "poll failed: {}", n);
+                    if n == 0 {
+                        break;
+                    }
+                    assert_eq!(stip_stack_feed_frame(to, buf.as_ptr(), n as usize), STIP_OK);
+                    moved = true;
+                }
+            }
+            if !moved {
+                return;
+            }
+        }
+    }
+
+    unsafe fn read_all(stack: *mut StipStack, conn: i32) -> Vec<u8> {
+        let mut buf = [0u8; 256];
+        let n = stip_tcp_read(stack, conn, buf.as_mut_ptr(), buf.len());
+        assert!(n >= 0, "read failed: {}", n);
+        buf[..n as usize].to_vec()
+    }
+
+    #[test]
+    fn test_receive_through_ffi() {
+        unsafe {
+            let rx = stip_receiver_new(B_IP, 80, 1024);
+            assert!(!rx.is_null());
+            let syn = segment(100, TcpCtrlFlag::SYN as u16, b"hello");
+            assert_eq!(stip_receiver_feed_frame(rx, syn.as_ptr(), syn.len()), STIP_OK);
+
+            let mut buf = [0u8; 3];
+            assert_eq!(stip_receiver_read(rx, buf.as_mut_ptr(), buf.len()), 3);
+            assert_eq!(&buf, b"hel");
+            assert_eq!(stip_receiver_read(rx, buf.as_mut_ptr(), buf.len()), 2);
+            assert_eq!(&buf[..2], b"lo");
+
+            let (mut ack, mut window) = (0u32, 0u32);
+            assert_eq!(stip_receiver_state(rx, &mut ack, &mut window), STIP_OK);
+            assert_eq!(window, 1024);
+            stip_receiver_free(rx);
+        }
+    }
+
+    #[test]
+    fn test_errors_do_not_cross_boundary() {
+        unsafe {
+            let rx = stip_receiver_new(B_IP, 80, 64);
+            assert_eq!(stip_receiver_feed_frame(rx, std::ptr::null(), 10), STIP_ERR_NULL);
+            assert_eq!(stip_receiver_feed_frame(rx, [0u8; 10].as_ptr(), 10), STIP_ERR_ETHERNET);
+            let icmp = PacketBuilder::ether(A, B).ipv4(1, 2).icmp(8, 0).build();
+            assert_eq!(stip_receiver_feed_frame(rx, icmp.as_ptr(), icmp.len()), STIP_ERR_NOT_TCP);
+            assert_eq!(stip_receiver_read(std::ptr::null_mut(), std::ptr::null_mut(), 0), STIP_ERR_NULL as i64);
+            stip_receiver_free(rx);
+
+            let syn = segment(1, TcpCtrlFlag::SYN as u16, b"");
+            let mut small = [0u8; 8];
+            assert_eq!(stip_dissect(syn.as_ptr(), syn.len(), small.as_mut_ptr(), small.len()), STIP_ERR_BUFFER_TOO_SMALL as i64);
+            let mut out = vec![0u8; 4096];
+            let n = stip_dissect(syn.as_ptr(), syn.len(), out.as_mut_ptr(), out.len());
+            assert!(n > 0);
+            assert!(String::from_utf8_lossy(&out[..n as usize]).starts_with("Ethernet II"));
+        }
+    }
+
+    #[test]
+    fn test_receiver_rejects_corrupt_and_misaddressed() {
+        unsafe {
+            let rx = stip_receiver_new(B_IP, 80, 64);
+            let rejected = [
+                (packet(1, TcpCtrlFlag::SYN as u16, b"x").corrupt_fcs().build(), STIP_ERR_CHECKSUM),
+                (packet(1, TcpCtrlFlag::SYN as u16, b"x").corrupt_ip_checksum().build(), STIP_ERR_CHECKSUM),
+                (packet(1, TcpCtrlFlag::SYN as u16, b"x").corrupt_l4_checksum().build(), STIP_ERR_CHECKSUM),
+                (PacketBuilder::ether(A, B).ipv4(A_IP, 0x0a000003).tcp(40000, 80).flags(TcpCtrlFlag::SYN as u16).build(), STIP_ERR_NOT_FOR_US),
+                (PacketBuilder::ether(A, B).ipv4(A_IP, B_IP).tcp(40000, 81).flags(TcpCtrlFlag::SYN as u16).build(), STIP_ERR_NOT_FOR_US),
+            ];
+            for (frame, expected) in rejected {
+                assert_eq!(stip_receiver_feed_frame(rx, frame.as_ptr(), frame.len()), expected);
+            }
+            // 都没有交付: 接收端仍未收到 SYN
+            let (mut ack, mut window) = (1u32, 0u32);
+            assert_eq!(stip_receiver_state(rx, &mut ack, &mut window), STIP_OK);
+            assert_eq!(ack, 0);
+            stip_receiver_free(rx);
+        }
+    }
+
+    #[test]
+    fn test_stack_handshake_and_exchange() {
+        unsafe {
+            assert!(stip_stack_new(std::ptr::null()).is_null());
+            let invalid = StipConfig { mss: 9000, ..config(A, A_IP) };
+            assert!(stip_stack_new(&invalid).is_null());
+
+            let client = stip_stack_new(&config(A, A_IP));
+            let server = stip_stack_new(&config(B, B_IP));
+            let listener = stip_tcp_listen(server, 80);
+            assert!(listener >= 0);
+            assert_eq!(stip_tcp_accept(server, listener), STIP_ERR_AGAIN);
+
+            // ARP 解析与三次握手都经 stip_stack_poll 发出的帧完成
+            let conn = stip_tcp_connect(client, B_IP, 80, 0);
+            assert!(conn >= 0);
+            shuttle(client, server, 0);
+            let peer = stip_tcp_accept(server, listener);
+            assert!(peer >= 0 && peer != listener);
+
+            let request = b"GET / HTTP/1.0\r\n\r\n";
+            assert_eq!(stip_tcp_write(client, conn, request.as_ptr(), request.len()), request.len() as i64);
+            shuttle(client, server, 10);
+            assert_eq!(read_all(server, peer), request);
+            let response = b"HTTP/1.0 200 OK\r\n\r\n";
+            assert_eq!(stip_tcp_write(server, peer, response.as_ptr(), response.len()), response.len() as i64);
+            shuttle(client, server, 20);
+            assert_eq!(read_all(client, conn), response);
+
+            // 帧放不进调用者的缓冲区时留到下一次
+            assert_eq!(stip_tcp_write(client, conn, request.as_ptr(), request.len()), request.len() as i64);
+            let mut small = [0u8; 16];
+            assert_eq!(stip_stack_poll(client, 30, small.as_mut_ptr(), small.len()), STIP_ERR_BUFFER_TOO_SMALL as i64);
+            shuttle(client, server, 30);
+            assert_eq!(read_all(server, peer), request);
+
+            assert_eq!(stip_tcp_close(client, conn, 40), STIP_OK);
+            assert_eq!(stip_tcp_close(server, peer, 40), STIP_OK);
+            shuttle(client, server, 40);
+            assert_eq!(stip_tcp_write(client, conn, request.as_ptr(), request.len()), STIP_ERR_CONNECTION as i64);
+            assert_eq!(stip_tcp_read(client, 99, small.as_mut_ptr(), small.len()), STIP_ERR_BAD_HANDLE as i64);
+            assert_eq!(stip_tcp_close(server, listener, 40), STIP_ERR_BAD_HANDLE);
+            stip_stack_free(client);
+            stip_stack_free(server);
+        }
+    }
+}
@@ -0,0 +1,16 @@
+// 仓库沿用 `return x;`、大写标志位方法等写法
+#![allow(non_snake_case)]
+#![allow(clippy::needless_return, clippy::upper_case_acronyms, clippy::too_many_arguments, clippy::needless_range_loop)]
+
+pub mod link;
+pub mod transport;
+pub mod net;
+pub mod utils;
+pub mod error;
+pub mod config;
+pub mod app;
+pub mod stack;
+pub mod testing;
+pub mod prelude;
+#[cfg(feature = "ffi")]
+pub mod ffi;
@@ -0,0 +1,198 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::error::{ParseError, SerializeError};
+use crate::utils::wire::{self, WireDeserialize, WireSerialize};
+
+pub const ETHER_TYPE_ARP: u16 = 0x0806;
+pub const ARP_LEN: usize = 28;
+pub const OP_REQUEST: u16 = 1;
+pub const OP_REPLY: u16 = 2;
+pub const HTYPE_ETHERNET: u16 = 1;
+pub const PTYPE_IPV4: u16 = 0x0800;
+
+/**
+ * ARP 报文解析错误
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArpParseError {
+    TooShort { len: usize },
+    UnsupportedHardware { htype: u16 },
+    UnsupportedProtocol { ptype: u16 },
+    BadAddressLength { hlen: u8, plen: u8 }, // 只支持以太网/IPv4 的 6 + 4
+}
+
+impl fmt::Display for ArpParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArpParseError::TooShort { len } => write!(f, "arp packet too short: {} bytes, need {}", len, ARP_LEN),
+            ArpParseError::UnsupportedHardware { htype } => write!(f, "unsupported hardware type {} (offset 0)", htype),
+            ArpParseError::UnsupportedProtocol { ptype } => write!(f, "unsupported protocol type 0x{:04x} (offset 2)", ptype),
+            ArpParseError::BadAddressLength { hlen, plen } => {
+                write!(f, "address lengths hlen={} plen={} (offset 4), expected 6 and 4", hlen, plen)
+            }
+        }
+    }
+}
+
+impl Error for ArpParseError {}
+
+/**
+ * 以太网上 IPv4 的 ARP 报文 (RFC 826)
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArpPacket {
+    pub op: u16,
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::addr::serde_mac"))]
+    pub s_mac: [u8; 6],
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::addr::serde_ipv4"))]
+    pub s_ip: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::addr::serde_mac"))]
+    pub t_mac: [u8; 6],
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::addr::serde_ipv4"))]
+    pub t_ip: u32,
+}
+
+impl ArpPacket {
+    pub fn request(s_mac: [u8; 6], s_ip: u32, t_ip: u32) -> Self {
+        ArpPacket { op: OP_REQUEST, s_mac, s_ip, t_mac: [0; 6], t_ip }
+    }
+
+    /**
+     * 对 request 的应答, 发送方为 (mac, 被询问的地址)
+     */
+    pub fn reply_to(request: &ArpPacket, mac: [u8; 6]) -> Self {
+        ArpPacket { op: OP_REPLY, s_mac: mac, s_ip: request.t_ip, t_mac: request.s_mac, t_ip: request.s_ip }
+    }
+
+    /**
+     * 只接受以太网/IPv4, 先检查类型和地址长度再按定长读取地址字段
+     * 多余的字节(以太网最小帧的填充)忽略
+     */
+    pub fn try_deserialize(bytes: &[u8]) -> Result<Self, ArpParseError> {
+        if bytes.len() < 8 {
+            return Err(ArpParseError::TooShort { len: bytes.len() });
+        }
+        let htype = u16::from_be_bytes([bytes[0], bytes[1]]);
+        if htype != HTYPE_ETHERNET {
+            return Err(ArpParseError::UnsupportedHardware { htype });
+        }
+        let ptype = u16::from_be_bytes([bytes[2], bytes[3]]);
+        if ptype != PTYPE_IPV4 {
+            return Err(ArpParseError::UnsupportedProtocol { ptype });
+        }
+        if bytes[4] != 6 || bytes[5] != 4 {
+            return Err(ArpParseError::BadAddressLength { hlen: bytes[4], plen: bytes[5] });
+        }
+        if bytes.len() < ARP_LEN {
+            return Err(ArpParseError::TooShort { len: bytes.len() });
+        }
+        let mac = |at: usize| -> [u8; 6] { bytes[at..at + 6].try_into().unwrap() };
+        let ip = |at: usize| u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+        Ok(ArpPacket { op: u16::from_be_bytes([bytes[6], bytes[7]]), s_mac: mac(8), s_ip: ip(14), t_mac: mac(18), t_ip: ip(24) })
+    }
+
+    /**
+     * 地址冲突探测 (RFC 5227): 发送方还没有地址, 不能据此更新缓存
+     */
+    pub fn is_probe(&self) -> bool {
+        self.op == OP_REQUEST && self.s_ip == 0
+    }
+
+    /**
+     * 免费 ARP: 发送方宣告自己的地址, 只用于刷新已有表项
+     */
+    pub fn is_gratuitous(&self) -> bool {
+        self.s_ip != 0 && self.s_ip == self.t_ip
+    }
+}
+
+impl WireDeserialize for ArpPacket {
+    fn deserialize(bytes: &[u8]) -> Result<Self, ParseError> {
+        Ok(Self::try_deserialize(bytes)?)
+    }
+}
+
+impl WireSerialize for ArpPacket {
+    fn wire_size(&self) -> usize {
+        ARP_LEN
+    }
+
+    fn serialize_into(&self, buf: &mut [u8]) -> Result<usize, SerializeError> {
+        wire::check_buffer(buf, ARP_LEN)?;
+        buf[0..6].copy_from_slice(&[0x00, 0x01, 0x08, 0x00, 6, 4]); // 以太网, IPv4
+        buf[6..8].copy_from_slice(&self.op.to_be_bytes());
+        buf[8..14].copy_from_slice(&self.s_mac);
+        buf[14..18].copy_from_slice(&self.s_ip.to_be_bytes());
+        buf[18..24].copy_from_slice(&self.t_mac);
+        buf[24..28].copy_from_slice(&self.t_ip.to_be_bytes());
+        Ok(ARP_LEN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let request = ArpPacket::request([2, 0, 0, 0, 0, 1], 0x0a000001, 0x0a000002);
+        let bytes = request.serialize();
+        assert_eq!(&bytes[..8], &[0x00, 0x01, 0x08, 0x00, 6, 4, 0, 1]);
+        assert_eq!(ArpPacket::try_deserialize(&bytes), Ok(request.clone()));
+        let reply = ArpPacket::reply_to(&request, [2, 0, 0, 0, 0, 2]);
+        assert_eq!((reply.op, reply.s_ip, reply.t_ip, reply.t_mac), (OP_REPLY, 0x0a000002, 0x0a000001, request.s_mac));
+        assert_eq!(ArpPacket::try_deserialize(&bytes[..27]), Err(ArpParseError::TooShort { len: 27 }));
+    }
+
+    // 抓包得到的 ARP 请求: who-has 24.166.173.159 tell 24.166.172.1
+    const CAPTURED_REQUEST: [u8; 28] = [
+        0x00, 0x01, 0x08, 0x00, 0x06, 0x04, 0x00, 0x01,
+        0x00, 0x07, 0x0d, 0xaf, 0xf4, 0x54, 0x18, 0xa6, 0xac, 0x01,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0xa6, 0xad, 0x9f,
+    ];
+
+    #[test]
+    fn test_captured_request() {
+        let packet = ArpPacket::try_deserialize(&CAPTURED_REQUEST).unwrap();
+        assert_eq!(packet.op, OP_REQUEST);
+        assert_eq!(packet.s_mac, [0x00, 0x07, 0x0d, 0xaf, 0xf4, 0x54]);
+        assert_eq!(packet.s_ip, 0x18a6ac01);
+        assert_eq!(packet.t_ip, 0x18a6ad9f);
+        assert!(!packet.is_probe() && !packet.is_gratuitous());
+        assert_eq!(packet.serialize(), CAPTURED_REQUEST);
+
+        // 交换机补齐到 46 字节的以太网最小载荷
+        let mut padded = CAPTURED_REQUEST.to_vec();
+        padded.resize(46, 0);
+        assert_eq!(ArpPacket::try_deserialize(&padded), Ok(packet));
+    }
+
+    #[test]
+    fn test_unsupported_formats() {
+        // Infiniband 风格的 8 字节硬件地址
+        let mut bytes = CAPTURED_REQUEST.to_vec();
+        bytes[4] = 8;
+        assert_eq!(ArpPacket::try_deserialize(&bytes), Err(ArpParseError::BadAddressLength { hlen: 8, plen: 4 }));
+        assert_eq!(
+            ArpPacket::deserialize(&bytes).unwrap_err().to_string(),
+            "arp: address lengths hlen=8 plen=4 (offset 4), expected 6 and 4"
+        );
+
+        let mut bytes = CAPTURED_REQUEST.to_vec();
+        bytes[1] = 32;
+        assert_eq!(ArpPacket::try_deserialize(&bytes), Err(ArpParseError::UnsupportedHardware { htype: 32 }));
+        let mut bytes = CAPTURED_REQUEST.to_vec();
+        bytes[2..4].copy_from_slice(&[0x86, 0xdd]);
+        assert_eq!(ArpPacket::try_deserialize(&bytes), Err(ArpParseError::UnsupportedProtocol { ptype: 0x86dd }));
+    }
+
+    #[test]
+    fn test_probe_and_gratuitous() {
+        let mac = [2, 0, 0, 0, 0, 1];
+        assert!(ArpPacket::request(mac, 0, 0x0a000001).is_probe());
+        assert!(ArpPacket::request(mac, 0x0a000001, 0x0a000001).is_gratuitous());
+        assert!(!ArpPacket::request(mac, 0, 0).is_gratuitous());
+    }
+}
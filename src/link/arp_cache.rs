@@ -0,0 +1,463 @@
+use std::collections::HashMap;
+
+use crate::config::ArpConfig;
+use crate::link::arp::ArpPacket;
+use crate::utils::timer::TimerQueue;
+
+pub const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+
+/**
+ * 表项状态, 仿照 IPv6 邻居不可达检测 (RFC 4861 7.3)
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArpState {
+    Incomplete,  // 正在广播请求, 还没有 MAC
+    Reachable,   // entry_ttl_ms 内得到过确认
+    Stale,       // 超过 entry_ttl_ms 未确认, 仍然可用, 下次发送时开始探测
+    Probing,     // 向缓存的 MAC 单播请求, 无应答则退回广播
+    Unreachable, // 解析失败, 退避期内直接报告不可达, 不再发请求
+}
+
+/**
+ * 一次查询的结果
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Resolved([u8; 6]),
+    Pending,     // 已经在解析, 报文应当排队等待
+    Unreachable, // 刚刚解析失败过, 上层应立即报告主机不可达
+}
+
+/**
+ * 需要发出的 ARP 请求, d_mac 为广播地址或缓存的 MAC
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArpRequest {
+    pub d_mac: [u8; 6],
+    pub target_ip: u32,
+}
+
+impl ArpRequest {
+    pub fn is_broadcast(&self) -> bool {
+        self.d_mac == BROADCAST_MAC
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    mac: Option<[u8; 6]>,
+    state: ArpState,
+    since_ms: u64,      // 最近一次确认或进入当前状态的时刻
+    probes: u32,        // 当前状态下已发出的请求数
+    next_probe_ms: u64, // Unreachable 时为退避结束的时刻
+    failures: u32,      // 连续解析失败的次数, 得到应答后清零
+}
+
+/**
+ * ARP 缓存
+ * 上层流量(收到来自该 IP/MAC 的报文)可以确认表项, 正在通信的主机不会因为过期而被重新广播解析
+ * 需要发出的请求放进发件箱, 由接口取走编码成帧
+ * 每个表项的下一个事件(重发请求、变为 Stale、删除)排在定时器队列里, tick 只处理到期的表项
+ */
+#[derive(Debug)]
+pub struct ArpCache {
+    entry_ttl_ms: u64,
+    stale_ttl_ms: u64,
+    retries: u32,
+    retry_interval_ms: u64,
+    negative_ttl_ms: u64,
+    negative_ttl_max_ms: u64,
+    entries: HashMap<u32, Entry>,
+    timers: TimerQueue<u32>,
+    outbox: Vec<ArpRequest>,
+    unreachable: Vec<u32>, // 本轮解析失败的地址
+}
+
+impl ArpCache {
+    pub fn new(config: &ArpConfig) -> Self {
+        ArpCache {
+            entry_ttl_ms: config.entry_ttl_ms,
+            stale_ttl_ms: config.stale_ttl_ms,
+            retries: config.request_retries.max(1),
+            retry_interval_ms: config.retry_interval_ms,
+            negative_ttl_ms: config.negative_ttl_ms,
+            negative_ttl_max_ms: config.negative_ttl_max_ms.max(config.negative_ttl_ms),
+            entries: HashMap::new(),
+            timers: TimerQueue::new(),
+            outbox: Vec::new(),
+            unreachable: Vec::new(),
+        }
+    }
+
+    /**
+     * 发送前查询下一跳的 MAC
+     * 没有表项时开始广播解析并返回 None; Stale 表项照常返回, 同时开始单播探测
+     */
+    pub fn resolve(&mut self, ip: u32, now_ms: u64) -> Option<[u8; 6]> {
+        match self.resolution(ip, now_ms) {
+            Resolution::Resolved(mac) => Some(mac),
+            Resolution::Pending | Resolution::Unreachable => None,
+        }
+    }
+
+    /**
+     * 与 resolve 相同, 但区分正在解析和退避期内的不可达
+     * 退避期过后重新广播解析, 之前的失败次数保留
+     */
+    pub fn resolution(&mut self, ip: u32, now_ms: u64) -> Resolution {
+        let resolution = self.lookup(ip, now_ms);
+        self.arm(ip);
+        resolution
+    }
+
+    fn lookup(&mut self, ip: u32, now_ms: u64) -> Resolution {
+        self.expire(ip, now_ms);
+        let Some(entry) = self.entries.get_mut(&ip) else {
+            self.start_resolving(ip, 0, now_ms);
+            return Resolution::Pending;
+        };
+        match (entry.state, entry.mac) {
+            (ArpState::Unreachable, _) if now_ms < entry.next_probe_ms => Resolution::Unreachable,
+            (ArpState::Unreachable, _) => {
+                let failures = entry.failures;
+                self.start_resolving(ip, failures, now_ms);
+                Resolution::Pending
+            }
+            (ArpState::Stale, Some(mac)) => {
+                *entry = Entry { mac: Some(mac), state: ArpState::Probing, since_ms: now_ms, probes: 1, next_probe_ms: now_ms + self.retry_interval_ms, failures: 0 };
+                self.outbox.push(ArpRequest { d_mac: mac, target_ip: ip });
+                Resolution::Resolved(mac)
+            }
+            (_, Some(mac)) => Resolution::Resolved(mac),
+            (_, None) => Resolution::Pending,
+        }
+    }
+
+    fn start_resolving(&mut self, ip: u32, failures: u32, now_ms: u64) {
+        self.entries.insert(ip, Entry { mac: None, state: ArpState::Incomplete, since_ms: now_ms, probes: 1, next_probe_ms: now_ms + self.retry_interval_ms, failures });
+        self.outbox.push(ArpRequest { d_mac: BROADCAST_MAC, target_ip: ip });
+    }
+
+    /**
+     * 解析失败: 记为 Unreachable, 退避时间从 negative_ttl_ms 开始随连续失败加倍, 不超过上限
+     */
+    fn mark_unreachable(&mut self, ip: u32, now_ms: u64) {
+        let Some(entry) = self.entries.get_mut(&ip) else { return };
+        let backoff = self.negative_ttl_ms.saturating_mul(1 << entry.failures.min(16)).min(self.negative_ttl_max_ms);
+        *entry = Entry { mac: None, state: ArpState::Unreachable, since_ms: now_ms, probes: 0, next_probe_ms: now_ms + backoff, failures: entry.failures + 1 };
+        self.unreachable.push(ip);
+    }
+
+    /**
+     * 收到 ip 发来的 ARP 应答(或询问本机的请求), 表项变为 Reachable
+     */
+    pub fn on_arp_reply(&mut self, ip: u32, mac: [u8; 6], now_ms: u64) {
+        self.entries.insert(ip, Entry { mac: Some(mac), state: ArpState::Reachable, since_ms: now_ms, probes: 0, next_probe_ms: 0, failures: 0 });
+        self.arm(ip);
+    }
+
+    /**
+     * 收到的 ARP 报文按发送方更新缓存
+     * 探测报文的发送方还没有地址, 忽略; 免费 ARP 只更新已有表项, 不为无关主机新建表项
+     */
+    pub fn on_arp_packet(&mut self, packet: &ArpPacket, now_ms: u64) {
+        if packet.is_probe() {
+            return;
+        }
+        if packet.is_gratuitous() && !self.entries.contains_key(&packet.s_ip) {
+            return;
+        }
+        self.on_arp_reply(packet.s_ip, packet.s_mac, now_ms);
+    }
+
+    /**
+     * 上层确认: 收到了源地址为 ip、源 MAC 为 mac 的报文
+     * 只刷新 MAC 一致的已有表项, 不会新建或改写表项
+     */
+    pub fn confirm(&mut self, ip: u32, mac: [u8; 6], now_ms: u64) {
+        if let Some(entry) = self.entries.get_mut(&ip) {
+            if entry.mac == Some(mac) {
+                *entry = Entry { mac: Some(mac), state: ArpState::Reachable, since_ms: now_ms, probes: 0, next_probe_ms: 0, failures: 0 };
+                self.arm(ip);
+            }
+        }
+    }
+
+    /**
+     * 重发到期的请求, 清理长期不用的表项
+     * Probing 先单播 retries 次, 再广播 retries 次; Incomplete 广播 retries 次; 都没有应答则记为 Unreachable
+     * 退避结束后又过了 stale_ttl_ms 仍没有被查询的 Unreachable 表项删除, 失败次数随之遗忘
+     */
+    pub fn tick(&mut self, now_ms: u64) {
+        for ip in self.timers.expired(now_ms) {
+            self.tick_entry(ip, now_ms);
+            self.arm(ip);
+        }
+    }
+
+    fn tick_entry(&mut self, ip: u32, now_ms: u64) {
+        self.expire(ip, now_ms);
+        let Some(entry) = self.entries.get_mut(&ip) else { return };
+        let limit = match entry.state {
+            ArpState::Incomplete => self.retries,
+            ArpState::Probing => 2 * self.retries,
+            ArpState::Stale if now_ms >= entry.since_ms + self.stale_ttl_ms => {
+                self.entries.remove(&ip);
+                return;
+            }
+            ArpState::Unreachable if now_ms >= entry.next_probe_ms + self.stale_ttl_ms => {
+                self.entries.remove(&ip);
+                return;
+            }
+            _ => return,
+        };
+        if now_ms < entry.next_probe_ms {
+            return;
+        }
+        if entry.probes >= limit {
+            self.mark_unreachable(ip, now_ms);
+            return;
+        }
+        let d_mac = match (entry.state, entry.mac) {
+            (ArpState::Probing, Some(mac)) if entry.probes < self.retries => mac,
+            _ => BROADCAST_MAC,
+        };
+        entry.probes += 1;
+        entry.next_probe_ms = now_ms + self.retry_interval_ms;
+        self.outbox.push(ArpRequest { d_mac, target_ip: ip });
+    }
+
+    /**
+     * 按表项的状态把它的下一个事件排进定时器队列, 表项已删除时取消
+     * Incomplete / Probing 重发请求, Reachable 变为 Stale, Stale 和退避结束的 Unreachable 删除
+     */
+    fn arm(&mut self, ip: u32) {
+        let Some(entry) = self.entries.get(&ip) else {
+            self.timers.cancel(&ip);
+            return;
+        };
+        let deadline = match entry.state {
+            ArpState::Incomplete | ArpState::Probing => entry.next_probe_ms,
+            ArpState::Reachable => entry.since_ms + self.entry_ttl_ms,
+            ArpState::Stale => entry.since_ms + self.stale_ttl_ms,
+            ArpState::Unreachable => entry.next_probe_ms + self.stale_ttl_ms,
+        };
+        if self.timers.deadline_of(&ip) != Some(deadline) {
+            self.timers.schedule(ip, deadline);
+        }
+    }
+
+    /**
+     * 最早需要 tick 的时刻, 没有表项时为 None
+     */
+    pub fn next_deadline_ms(&mut self) -> Option<u64> {
+        self.timers.next_deadline()
+    }
+
+    /**
+     * 取走待发的请求
+     */
+    pub fn take_requests(&mut self) -> Vec<ArpRequest> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    /**
+     * 取走自上次调用以来解析失败的地址, IP 层据此向上层报告主机不可达
+     */
+    pub fn take_unreachable(&mut self) -> Vec<u32> {
+        std::mem::take(&mut self.unreachable)
+    }
+
+    pub fn state(&self, ip: u32) -> Option<ArpState> {
+        self.entries.get(&ip).map(|entry| entry.state)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /**
+     * Reachable 超过 entry_ttl_ms 未确认则变为 Stale
+     */
+    fn expire(&mut self, ip: u32, now_ms: u64) {
+        if let Some(entry) = self.entries.get_mut(&ip) {
+            if entry.state == ArpState::Reachable && now_ms >= entry.since_ms + self.entry_ttl_ms {
+                entry.state = ArpState::Stale;
+                entry.since_ms = now_ms;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOST: u32 = 0x0a000002;
+    const HOST_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 2];
+
+    fn cache() -> ArpCache {
+        let config = ArpConfig { entry_ttl_ms: 1000, stale_ttl_ms: 10_000, request_retries: 2, retry_interval_ms: 100, ..ArpConfig::default() };
+        ArpCache::new(&config)
+    }
+
+    fn destinations(cache: &mut ArpCache) -> Vec<bool> {
+        cache.take_requests().iter().map(|request| request.is_broadcast()).collect()
+    }
+
+    #[test]
+    fn test_resolution_and_upper_layer_confirmation() {
+        let mut cache = cache();
+        assert_eq!(cache.resolve(HOST, 0), None);
+        assert_eq!(cache.state(HOST), Some(ArpState::Incomplete));
+        assert_eq!(destinations(&mut cache), vec![true]);
+        cache.on_arp_reply(HOST, HOST_MAC, 10);
+        assert_eq!(cache.resolve(HOST, 20), Some(HOST_MAC));
+
+        // TCP 流量持续到达, 表项一直保持 Reachable, 不会发出任何请求
+        for now in (500..5000).step_by(500) {
+            cache.confirm(HOST, HOST_MAC, now);
+            assert_eq!(cache.resolve(HOST, now + 1), Some(HOST_MAC));
+            cache.tick(now + 2);
+        }
+        assert_eq!(cache.state(HOST), Some(ArpState::Reachable));
+        assert!(cache.take_requests().is_empty());
+
+        // MAC 不一致的确认不起作用
+        cache.confirm(HOST, [0x02, 0, 0, 0, 0, 9], 5600);
+        cache.tick(6000);
+        assert_eq!(cache.state(HOST), Some(ArpState::Stale));
+    }
+
+    #[test]
+    fn test_stale_probe_is_unicast_then_broadcast() {
+        let mut cache = cache();
+        cache.on_arp_reply(HOST, HOST_MAC, 0);
+        cache.tick(1000);
+        assert_eq!(cache.state(HOST), Some(ArpState::Stale));
+        assert!(cache.take_requests().is_empty()); // 没有发送就不探测
+
+        assert_eq!(cache.resolve(HOST, 1500), Some(HOST_MAC)); // 探测期间照常使用
+        assert_eq!(cache.state(HOST), Some(ArpState::Probing));
+        let requests = cache.take_requests();
+        assert_eq!(requests, vec![ArpRequest { d_mac: HOST_MAC, target_ip: HOST }]);
+
+        for now in [1600, 1700, 1800] {
+            cache.tick(now);
+        }
+        // 单播 2 次之后退回广播 2 次, 最后一次也没有应答则放弃
+        assert_eq!(destinations(&mut cache), vec![false, true, true]);
+        assert_eq!(cache.state(HOST), Some(ArpState::Probing));
+        cache.tick(1899);
+        assert_eq!(cache.state(HOST), Some(ArpState::Probing));
+        cache.tick(1900);
+        assert_eq!(cache.state(HOST), Some(ArpState::Unreachable));
+        assert_eq!(cache.take_unreachable(), vec![HOST]);
+    }
+
+    #[test]
+    fn test_probe_answered_and_stale_gc() {
+        let mut cache = cache();
+        cache.on_arp_reply(HOST, HOST_MAC, 0);
+        cache.resolve(HOST, 1000);
+        cache.tick(1100);
+        assert_eq!(destinations(&mut cache), vec![false, false]);
+        cache.on_arp_reply(HOST, HOST_MAC, 1150);
+        assert_eq!(cache.state(HOST), Some(ArpState::Reachable));
+        cache.tick(1300);
+        assert!(cache.take_requests().is_empty());
+
+        // 不再使用的 Stale 表项 stale_ttl_ms 后删除
+        cache.tick(2150);
+        assert_eq!(cache.state(HOST), Some(ArpState::Stale));
+        cache.tick(12_149);
+        assert_eq!(cache.len(), 1);
+        cache.tick(12_150);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_incomplete_gives_up_after_retries() {
+        let mut cache = cache();
+        cache.resolve(HOST, 0);
+        cache.tick(100);
+        cache.tick(200);
+        assert_eq!(destinations(&mut cache), vec![true, true]);
+        assert_eq!(cache.state(HOST), Some(ArpState::Unreachable));
+        assert_eq!(cache.take_unreachable(), vec![HOST]);
+    }
+
+    #[test]
+    fn test_negative_entry_backoff_doubles() {
+        let mut cache = cache(); // negative_ttl_ms 5000, 上限 60000
+        let mut failed_at = 0;
+        for backoff in [5_000, 10_000, 20_000, 40_000, 60_000, 60_000] {
+            assert_eq!(cache.resolution(HOST, failed_at), Resolution::Pending);
+            cache.tick(failed_at + 100);
+            cache.tick(failed_at + 200);
+            failed_at += 200;
+            assert_eq!(cache.take_unreachable(), vec![HOST]);
+            destinations(&mut cache);
+            // 退避期内直接失败, 不发请求
+            assert_eq!(cache.resolution(HOST, failed_at + backoff - 1), Resolution::Unreachable);
+            assert!(cache.take_requests().is_empty());
+            failed_at += backoff;
+        }
+
+        // 免费 ARP 更新不可达表项, 失败次数清零
+        cache.on_arp_packet(&ArpPacket::request(HOST_MAC, HOST, HOST), failed_at);
+        assert_eq!(cache.resolution(HOST, failed_at + 1), Resolution::Resolved(HOST_MAC));
+        assert_eq!(cache.entries[&HOST].failures, 0);
+
+        // 长期不再查询的不可达表项被清理
+        let mut cache = self::cache();
+        cache.resolve(HOST, 0);
+        cache.tick(100);
+        cache.tick(200);
+        cache.tick(200 + 5_000 + 10_000);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_probes_and_gratuitous_packets() {
+        let mut cache = cache();
+        cache.on_arp_packet(&ArpPacket::request(HOST_MAC, 0, 0x0a0000aa), 0);
+        cache.on_arp_packet(&ArpPacket::request(HOST_MAC, HOST, HOST), 0);
+        assert!(cache.is_empty()); // 探测和无关主机的免费 ARP 都不建表项
+
+        cache.on_arp_packet(&ArpPacket::request(HOST_MAC, HOST, 0x0a0000aa), 0);
+        assert_eq!(cache.state(HOST), Some(ArpState::Reachable));
+        let new_mac = [0x02, 0, 0, 0, 0, 0x77];
+        cache.on_arp_packet(&ArpPacket::request(new_mac, HOST, HOST), 10);
+        assert_eq!(cache.resolve(HOST, 20), Some(new_mac));
+    }
+
+    #[test]
+    fn test_timer_queue_follows_entry_state() {
+        let mut cache = cache();
+        assert_eq!(cache.next_deadline_ms(), None);
+        cache.on_arp_reply(HOST, HOST_MAC, 0);
+        assert_eq!(cache.next_deadline_ms(), Some(1000)); // Reachable 到期变为 Stale
+        cache.resolve(0x0a000003, 50);
+        assert_eq!(cache.next_deadline_ms(), Some(150)); // Incomplete 重发请求
+
+        // 确认推迟到期时刻, 之前排的截止时间不再触发
+        cache.confirm(HOST, HOST_MAC, 900);
+        cache.take_requests();
+        cache.tick(1000);
+        assert_eq!(cache.state(HOST), Some(ArpState::Reachable));
+        assert_eq!(cache.timers.deadline_of(&HOST), Some(1900));
+        cache.tick(1900);
+        assert_eq!(cache.state(HOST), Some(ArpState::Stale));
+        assert_eq!(cache.timers.deadline_of(&HOST), Some(1900 + 10_000));
+
+        // 删除的表项不再有定时器
+        cache.tick(11_900);
+        assert_eq!(cache.state(HOST), None);
+        assert!(!cache.timers.contains(&HOST));
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::config::{ArpConfig, Ipv4Config};
+use crate::net::icmp_v4::{IcmpRateLimiter, IcmpV4};
+use crate::net::ipv4::Ipv4Datagram;
+use crate::net::source_guard::is_martian_source;
+use crate::transport::tcp_connection::ConnectionId;
+use crate::utils::wire::WireSerialize;
+
+const BROADCAST: u32 = 0xffff_ffff;
+const HOST_UNREACHABLE: u8 = 1;
+const PROTOCOL_ICMP: u8 = 1;
+
+/**
+ * 排队的数据报从哪里来, 决定解析失败时通知谁
+ */
+#[derive(Debug)]
+pub enum Origin {
+    Local { conn_hint: Option<ConnectionId> }, // 本机发出, 直接告诉上层
+    Forwarded { original: Ipv4Datagram },      // 转发别人的数据报, 保留收到时的样子供差错报文带回
+}
+
+/**
+ * 解析失败后的通知
+ */
+#[derive(Debug)]
+pub enum ArpFailure {
+    Local { d_addr: u32, conn_hint: Option<ConnectionId> }, // 交给 ConnectionTable::host_unreachable 等
+    Icmp(Ipv4Datagram),                                     // 发回原始源地址的主机不可达 (type 3 code 1)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PendingStats {
+    pub queued: u64,
+    pub overflow: u64,    // 队列满而丢弃
+    pub failed: u64,      // 解析失败而丢弃
+    pub icmp_sent: u64,
+    pub icmp_skipped: u64, // 广播、组播、ICMP 差错或非首片, 不回差错报文
+    pub icmp_limited: u64, // 被限速
+}
+
+/**
+ * 等待 ARP 解析的数据报, 按下一跳分队, 每个下一跳最多 pending_queue_len 个
+ * 解析成功时按序取出发送; 失败时本机发出的报告给上层, 转发的向原始源地址回 ICMP 主机不可达 (RFC 1812 4.3.2.7)
+ */
+#[derive(Debug)]
+pub struct ArpPendingQueue {
+    capacity: usize,
+    ttl: u8,
+    queues: HashMap<u32, VecDeque<(Ipv4Datagram, Origin)>>,
+    limiter: IcmpRateLimiter,
+    stats: PendingStats,
+}
+
+impl ArpPendingQueue {
+    pub fn new(arp: &ArpConfig, ipv4: &Ipv4Config) -> Self {
+        ArpPendingQueue {
+            capacity: arp.pending_queue_len,
+            ttl: ipv4.default_ttl,
+            queues: HashMap::new(),
+            limiter: IcmpRateLimiter::new(ipv4.icmp_error_rate),
+            stats: PendingStats::default(),
+        }
+    }
+
+    /**
+     * 队列满时丢弃新来的数据报并返回 false, 不产生通知
+     */
+    pub fn push(&mut self, next_hop: u32, datagram: Ipv4Datagram, origin: Origin) -> bool {
+        let queue = self.queues.entry(next_hop).or_default();
+        if queue.len() >= self.capacity {
+            self.stats.overflow += 1;
+            return false;
+        }
+        queue.push_back((datagram, origin));
+        self.stats.queued += 1;
+        true
+    }
+
+    /**
+     * 下一跳解析成功, 按排队顺序取出
+     */
+    pub fn resolved(&mut self, next_hop: u32) -> Vec<Ipv4Datagram> {
+        self.queues.remove(&next_hop).into_iter().flatten().map(|(datagram, _)| datagram).collect()
+    }
+
+    /**
+     * 下一跳解析失败, 丢弃它的队列并返回通知; 同一连接只报告一次
+     * icmp_source 是差错报文的源地址, 一般为收到原数据报的接口的地址
+     */
+    pub fn failed(&mut self, next_hop: u32, icmp_source: u32, now_ms: u64) -> Vec<ArpFailure> {
+        let mut notices = vec![];
+        for (datagram, origin) in self.queues.remove(&next_hop).into_iter().flatten() {
+            self.stats.failed += 1;
+            match origin {
+                Origin::Local { conn_hint } => {
+                    let seen = notices.iter().any(|notice| matches!(notice,
+                        ArpFailure::Local { d_addr, conn_hint: hint } if *d_addr == datagram.d_addr() && *hint == conn_hint));
+                    if !seen {
+                        notices.push(ArpFailure::Local { d_addr: datagram.d_addr(), conn_hint });
+                    }
+                }
+                Origin::Forwarded { original } => {
+                    if !wants_error(&original) {
+                        self.stats.icmp_skipped += 1;
+                    } else if !self.limiter.allow(now_ms) {
+                        self.stats.icmp_limited += 1;
+                    } else {
+                        self.stats.icmp_sent += 1;
+                        notices.push(ArpFailure::Icmp(self.host_unreachable(&original, icmp_source)));
+                    }
+                }
+            }
+        }
+        notices
+    }
+
+    fn host_unreachable(&self, original: &Ipv4Datagram, icmp_source: u32) -> Ipv4Datagram {
+        let icmp = IcmpV4::dest_unreachable(HOST_UNREACHABLE, &original.serialize()).serialize();
+        Ipv4Datagram::new(4, 5, 0, (20 + icmp.len()) as u16, 0, 0, 0, self.ttl, PROTOCOL_ICMP, icmp_source, original.s_addr(), icmp)
+    }
+
+    pub fn pending(&self, next_hop: u32) -> usize {
+        self.queues.get(&next_hop).map_or(0, VecDeque::len)
+    }
+
+    pub fn stats(&self) -> PendingStats {
+        self.stats
+    }
+}
+
+/**
+ * 不为差错报文、非首片、广播/组播目的地址以及不可能回得去的源地址产生差错报文 (RFC 1122 3.2.2)
+ */
+fn wants_error(original: &Ipv4Datagram) -> bool {
+    if original.d_addr() == BROADCAST || original.d_addr() >> 28 == 0xe || is_martian_source(original.s_addr(), original.d_addr()) {
+        return false;
+    }
+    if original.fragment_offset() != 0 {
+        return false;
+    }
+    if original.protocol() == PROTOCOL_ICMP {
+        return IcmpV4::try_deserialize(original.payload()).is_ok_and(|icmp| !icmp.is_error());
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTER: u32 = 0x0a000001;
+    const SENDER: u32 = 0x0a010005;
+    const DEAD: u32 = 0x0a000063;
+
+    fn datagram(s_addr: u32, d_addr: u32, protocol: u8, payload: Vec<u8>) -> Ipv4Datagram {
+        Ipv4Datagram::new(4, 5, 0, (20 + payload.len()) as u16, 7, 0, 0, 64, protocol, s_addr, d_addr, payload)
+    }
+
+    fn forwarded(queue: &mut ArpPendingQueue, original: Ipv4Datagram) {
+        let copy = Ipv4Datagram::try_deserialize(&original.serialize()).unwrap();
+        assert!(queue.push(DEAD, copy, Origin::Forwarded { original }));
+    }
+
+    #[test]
+    fn test_no_errors_about_errors_or_broadcasts() {
+        let mut queue = ArpPendingQueue::new(&ArpConfig::default(), &Ipv4Config::default());
+        let unreachable = IcmpV4::dest_unreachable(3, &datagram(DEAD, SENDER, 17, vec![0; 8]).serialize()).serialize();
+        forwarded(&mut queue, datagram(SENDER, DEAD, PROTOCOL_ICMP, unreachable));
+        forwarded(&mut queue, datagram(SENDER, 0xe0000005, 17, vec![0; 8]));
+        forwarded(&mut queue, datagram(SENDER, BROADCAST, 17, vec![0; 8]));
+        forwarded(&mut queue, datagram(0, DEAD, 17, vec![0; 8]));
+        let echo = IcmpV4::new(8, 0, vec![0; 8]).serialize();
+        forwarded(&mut queue, datagram(SENDER, DEAD, PROTOCOL_ICMP, echo));
+
+        let notices = queue.failed(DEAD, ROUTER, 0);
+        assert_eq!(notices.len(), 1); // 只有回显请求得到差错报文
+        assert!(matches!(&notices[0], ArpFailure::Icmp(icmp) if icmp.d_addr() == SENDER));
+        assert_eq!(queue.stats().icmp_skipped, 4);
+    }
+
+    #[test]
+    fn test_overflow_and_rate_limit() {
+        let arp = ArpConfig { pending_queue_len: 3, ..ArpConfig::default() };
+        let ipv4 = Ipv4Config { icmp_error_rate: Some(2), ..Ipv4Config::default() };
+        let mut queue = ArpPendingQueue::new(&arp, &ipv4);
+        for _ in 0..3 {
+            forwarded(&mut queue, datagram(SENDER, DEAD, 17, vec![0; 8]));
+        }
+        assert!(!queue.push(DEAD, datagram(SENDER, DEAD, 17, vec![]), Origin::Local { conn_hint: None }));
+        assert_eq!(queue.pending(DEAD), 3);
+
+        assert_eq!(queue.failed(DEAD, ROUTER, 0).len(), 2);
+        let stats = queue.stats();
+        assert_eq!((stats.overflow, stats.failed, stats.icmp_sent, stats.icmp_limited), (1, 3, 2, 1));
+        assert_eq!(queue.pending(DEAD), 0);
+    }
+
+    #[test]
+    fn test_resolved_keeps_order() {
+        let mut queue = ArpPendingQueue::new(&ArpConfig::default(), &Ipv4Config::default());
+        for i in 0..3u8 {
+            queue.push(DEAD, datagram(ROUTER, DEAD, 17, vec![i]), Origin::Local { conn_hint: None });
+        }
+        let sent: Vec<u8> = queue.resolved(DEAD).iter().map(|datagram| datagram.payload()[0]).collect();
+        assert_eq!(sent, vec![0, 1, 2]);
+        assert!(queue.failed(DEAD, ROUTER, 0).is_empty());
+    }
+}
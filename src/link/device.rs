@@ -0,0 +1,132 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::error::DeviceError;
+
+/**
+ * 收发整帧的设备, 帧的格式与 EthernetInterface 收发的相同; 所有方法都不阻塞
+ * 批量方法默认逐帧调用单帧方法, 能一次收发多帧的设备 (一次系统调用、一次加锁) 应当覆盖它们
+ */
+pub trait NetworkDevice {
+    /**
+     * 取一帧, 暂时没有时返回 Ok(None)
+     */
+    fn receive(&mut self) -> Result<Option<Vec<u8>>, DeviceError>;
+
+    /**
+     * 发出一帧; 设备队列满时返回 QueueFull, 帧没有发出
+     */
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), DeviceError>;
+
+    /**
+     * 至多取 max 帧追加到 out, 返回取到的帧数; 出错时停下, 已经取到的帧照常返回
+     */
+    fn receive_batch(&mut self, out: &mut Vec<Vec<u8>>, max: usize) -> usize {
+        let mut n = 0;
+        while n < max {
+            match self.receive() {
+                Ok(Some(frame)) => out.push(frame),
+                _ => break,
+            }
+            n += 1;
+        }
+        n
+    }
+
+    /**
+     * 按顺序发出 frames, 返回发出的帧数; 第一帧发不出去时停下, 之后的帧由调用方留着下次再发
+     */
+    fn transmit_batch(&mut self, frames: &[&[u8]]) -> usize {
+        frames.iter().take_while(|frame| self.transmit(frame).is_ok()).count()
+    }
+}
+
+/**
+ * 设备方法的调用次数, 用来确认走的是单帧还是批量路径
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeviceCalls {
+    pub receive: u64,
+    pub transmit: u64,
+    pub receive_batch: u64,
+    pub transmit_batch: u64,
+}
+
+/**
+ * 内存中的一对互连设备, 一端发出的帧从另一端收到; 每个方向的队列至多容纳 capacity 帧
+ */
+pub struct ChannelPair {
+    pub a: ChannelEnd,
+    pub b: ChannelEnd,
+}
+
+impl ChannelPair {
+    pub fn new(capacity: usize) -> Self {
+        let queues = Rc::new(RefCell::new([VecDeque::new(), VecDeque::new()]));
+        let end = |side| ChannelEnd { queues: queues.clone(), side, capacity, calls: DeviceCalls::default() };
+        ChannelPair { a: end(0), b: end(1) }
+    }
+}
+
+/**
+ * ChannelPair 的一端: 从 queues[side] 收, 向另一个队列发
+ */
+pub struct ChannelEnd {
+    queues: Rc<RefCell<[VecDeque<Vec<u8>>; 2]>>,
+    side: usize,
+    capacity: usize,
+    calls: DeviceCalls,
+}
+
+impl ChannelEnd {
+    pub fn calls(&self) -> DeviceCalls {
+        self.calls
+    }
+
+    /**
+     * 等待本端取走的帧数
+     */
+    pub fn pending(&self) -> usize {
+        self.queues.borrow()[self.side].len()
+    }
+}
+
+impl NetworkDevice for ChannelEnd {
+    fn receive(&mut self) -> Result<Option<Vec<u8>>, DeviceError> {
+        self.calls.receive += 1;
+        Ok(self.queues.borrow_mut()[self.side].pop_front())
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), DeviceError> {
+        self.calls.transmit += 1;
+        let queue = &mut self.queues.borrow_mut()[1 - self.side];
+        if queue.len() >= self.capacity {
+            return Err(DeviceError::QueueFull);
+        }
+        queue.push_back(frame.to_vec());
+        Ok(())
+    }
+
+    /**
+     * 一次取空队列 (至多 max 帧)
+     */
+    fn receive_batch(&mut self, out: &mut Vec<Vec<u8>>, max: usize) -> usize {
+        self.calls.receive_batch += 1;
+        let queue = &mut self.queues.borrow_mut()[self.side];
+        let n = max.min(queue.len());
+        out.extend(queue.drain(..n));
+        n
+    }
+
+    /**
+     * 一次放进对端队列剩余的空间, 放不下的帧不发
+     */
+    fn transmit_batch(&mut self, frames: &[&[u8]]) -> usize {
+        self.calls.transmit_batch += 1;
+        let queue = &mut self.queues.borrow_mut()[1 - self.side];
+        let n = frames.len().min(self.capacity.saturating_sub(queue.len()));
+        queue.extend(frames[..n].iter().map(|frame| frame.to_vec()));
+        n
+    }
+}
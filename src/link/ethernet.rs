@@ -1,10 +1,57 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::error::{ParseError, SerializeError};
+use crate::link::arp::ETHER_TYPE_ARP;
+use crate::utils::trans_bytes;
+use crate::utils::wire::{self, WireDeserialize, WireSerialize};
+
+const MIN_FRAME_LEN: usize = 64; // 14 + 46 + 4
+pub const MIN_PAYLOAD_LEN: usize = 46;
+pub const HDR_LEN: usize = 14;
+const ETHER_TYPE_IPV4: u16 = 0x0800;
+const ARP_LEN: usize = 28;
+
+/**
+ * 上层主动把载荷补齐到最小长度, 补 0; 已经够长时不变
+ */
+pub fn pad_payload(payload: &mut Vec<u8>) {
+    if payload.len() < MIN_PAYLOAD_LEN {
+        payload.resize(MIN_PAYLOAD_LEN, 0);
+    }
+}
+
+/**
+ * 以太网帧解析错误
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EthernetParseError {
+    TooShort { len: usize, min: usize },
+}
+
+impl fmt::Display for EthernetParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EthernetParseError::TooShort { len, min } => {
+                write!(f, "frame too short: {} bytes, need at least {}", len, min)
+            }
+        }
+    }
+}
+
+impl Error for EthernetParseError {}
+
 /* 以太网帧, 没设置前导码(7bytes)和起始定界符(1byte) */
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EthernetFrame {
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::addr::serde_mac"))]
     d_mac: [u8; 6],
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::addr::serde_mac"))]
     s_mac: [u8; 6],
     ether_type: u16,
     payload: Vec<u8>, // 46 ~ 1500 Bytes
+    payload_len: usize, // 上层数据的实际长度, 收到的最小帧里不含链路层补齐的字节
     fcs: u32,
 }
 
@@ -14,6 +61,7 @@ impl EthernetFrame {
             d_mac,
             s_mac,
             ether_type,
+            payload_len: payload.len(),
             payload,
             fcs: 0,
         };
@@ -21,36 +69,134 @@ impl EthernetFrame {
         return new_ins;
     }
 
+    /**
+     * FCS 填 0, 用于由链路保证完整性、接收方不校验的场合
+     */
+    pub fn without_fcs(d_mac: [u8; 6], s_mac: [u8; 6], ether_type: u16, payload: Vec<u8>) -> Self {
+        EthernetFrame { d_mac, s_mac, ether_type, payload_len: payload.len(), payload, fcs: 0 }
+    }
+
     // 字节流变成EthernetFrame对象
-    pub fn deserialize(bytes: &[u8]) -> Self {
+    pub fn try_deserialize(bytes: &[u8]) -> Result<Self, EthernetParseError> {
+        let mut frame = Self::parse_header(bytes)?;
+        frame.payload = bytes[14..(bytes.len() - 4)].to_vec();
+        frame.payload_len = frame.unpadded_len();
+        return Ok(frame);
+    }
+
+    /**
+     * 接管设备交上来的整帧缓冲区, 原地去掉首部和FCS后作为 payload, 不再复制
+     */
+    pub fn from_frame_vec(mut bytes: Vec<u8>) -> Result<Self, EthernetParseError> {
+        let mut frame = Self::parse_header(&bytes)?;
         let size = bytes.len();
+        trans_bytes::keep_range(&mut bytes, 14, size - 4);
+        frame.payload = bytes;
+        frame.payload_len = frame.unpadded_len();
+        return Ok(frame);
+    }
 
-        if size < 64 {
+    /**
+     * 解析首部和FCS, payload 留空由调用者填充
+     */
+    fn parse_header(bytes: &[u8]) -> Result<Self, EthernetParseError> {
+        let size = bytes.len();
+
+        if size < MIN_FRAME_LEN {
             // 确保 size 至少大于 14 + 46 + 4 == 64，才能成功解析以太网帧
-            panic!("Invalid Ethernet frame: too small");
+            return Err(EthernetParseError::TooShort { len: size, min: MIN_FRAME_LEN });
         }
 
-        let d_mac = match bytes[0..6].try_into() {
-            Ok(val) => val,
-            Err(e) => panic!("{}", e),
-        };
-        let s_mac = match bytes[6..12].try_into() {
-            Ok(val) => val,
-            Err(e) => panic!("{}", e),
-        };
+        let mut d_mac = [0u8; 6];
+        d_mac.copy_from_slice(&bytes[0..6]);
+        let mut s_mac = [0u8; 6];
+        s_mac.copy_from_slice(&bytes[6..12]);
         let ether_type = ((bytes[12] as u16) << 8) + (bytes[13] as u16);
-        let payload = bytes[14..(size - 4)].to_vec();
         let fcs: u32 = bytes[(size - 4)..]
             .iter()
             .fold(0, |acc, &x| (acc << 8) + (x as u32));
 
-        return EthernetFrame {
+        return Ok(EthernetFrame {
             d_mac,
             s_mac,
             ether_type,
-            payload,
+            payload: Vec::new(),
+            payload_len: 0,
             fcs,
+        });
+    }
+
+    /**
+     * 只有最小帧可能带补齐: IPv4 按 total_len, ARP 固定 28 字节; 其他类型无从判断, 整个载荷都算数据
+     */
+    fn unpadded_len(&self) -> usize {
+        if self.payload.len() != MIN_PAYLOAD_LEN {
+            return self.payload.len();
+        }
+        let len = match self.ether_type {
+            ETHER_TYPE_IPV4 => u16::from_be_bytes([self.payload[2], self.payload[3]]) as usize,
+            ETHER_TYPE_ARP => ARP_LEN,
+            _ => MIN_PAYLOAD_LEN,
         };
+        len.min(MIN_PAYLOAD_LEN)
+    }
+
+    /**
+     * 解析失败时 panic, 仅用于确定输入合法的场景
+     */
+    #[deprecated(note = "use WireDeserialize::deserialize")]
+    pub fn deserialize_unchecked(bytes: &[u8]) -> Self {
+        match Self::try_deserialize(bytes) {
+            Ok(frame) => frame,
+            Err(e) => panic!("Invalid Ethernet frame: {}", e),
+        }
+    }
+
+    pub fn d_mac(&self) -> [u8; 6] {
+        self.d_mac
+    }
+
+    pub fn s_mac(&self) -> [u8; 6] {
+        self.s_mac
+    }
+
+    pub fn ether_type(&self) -> u16 {
+        self.ether_type
+    }
+
+    /**
+     * 接收到的帧的载荷可能带有链路层补齐的字节, 只要上层数据时用 unpadded_payload
+     */
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /**
+     * 去掉链路层补齐之后的载荷
+     */
+    pub fn unpadded_payload(&self) -> &[u8] {
+        &self.payload[..self.payload_len]
+    }
+
+    /**
+     * 上层数据的实际长度; 构造的帧就是载荷长度, 收到的最小帧不含补齐
+     */
+    pub fn payload_len(&self) -> usize {
+        self.payload_len
+    }
+
+    /**
+     * 线路上载荷部分的长度, 含补齐的字节, 至少 MIN_PAYLOAD_LEN
+     */
+    pub fn padded_len(&self) -> usize {
+        self.payload.len().max(MIN_PAYLOAD_LEN)
+    }
+
+    /**
+     * 交出载荷的所有权, 上层可以原地解析
+     */
+    pub fn into_payload(self) -> Vec<u8> {
+        self.payload
     }
 
     /**
@@ -65,7 +211,7 @@ impl EthernetFrame {
     pub fn generate_fcs(&self) -> u32 {
         const G: u32 = 0x04C11DB7; // 在以太网中，CRC-32使用的G
         let mut fcs: u32 = 0xffff_ffff;
-        let serialzed_frame = self.serialized();
+        let serialzed_frame = self.serialize();
         let d = &serialzed_frame[0..serialzed_frame.len() - 4];
 
         /* CRC */
@@ -86,21 +232,42 @@ impl EthernetFrame {
         return fcs;
     }
 
+    /**
+     * 首部(d_mac、s_mac、ether_type)写进定长数组, 不做堆分配
+     */
+    pub fn header_bytes(&self) -> [u8; HDR_LEN] {
+        let mut bytes = [0u8; HDR_LEN];
+        bytes[0..6].copy_from_slice(&self.d_mac);
+        bytes[6..12].copy_from_slice(&self.s_mac);
+        bytes[12..14].copy_from_slice(&self.ether_type.to_be_bytes());
+        bytes
+    }
+
     pub fn check_fcs(&self) -> bool {
         // TODO
         self.fcs == self.generate_fcs()
     }
 
     // 序列化成字节流
+    #[deprecated(note = "use WireSerialize::serialize")]
     pub fn serialized(&self) -> Vec<u8> {
-        let size: usize = 14 + self.payload.len() + 4;
-        let mut nums: Vec<u8> = vec![0; size]; //  存放字节流
-                                               // 将数据从 d_mac、s_mac、ether_type 和 payload 填充到 nums 中
-        nums[0..6].copy_from_slice(&self.d_mac[0..6]);
-        nums[6..12].copy_from_slice(&self.s_mac[0..6]);
-        nums[12..14]
-            .copy_from_slice(&[(self.ether_type >> 8) as u8, (self.ether_type & 0xFF) as u8]);
-        nums[14..(size - 4)].copy_from_slice(&self.payload[0..self.payload.len()]);
+        self.serialize()
+    }
+}
+
+impl WireSerialize for EthernetFrame {
+    // 载荷不足 46 字节时在线路上补 0, 补齐的字节不属于 payload
+    fn wire_size(&self) -> usize {
+        14 + self.payload.len().max(MIN_PAYLOAD_LEN) + 4
+    }
+
+    fn serialize_into(&self, nums: &mut [u8]) -> Result<usize, SerializeError> {
+        let size: usize = self.wire_size();
+        wire::check_buffer(nums, size)?;
+        // 将数据从 d_mac、s_mac、ether_type 和 payload 填充到 nums 中
+        nums[0..HDR_LEN].copy_from_slice(&self.header_bytes());
+        nums[14..(14 + self.payload.len())].copy_from_slice(&self.payload[0..self.payload.len()]);
+        nums[(14 + self.payload.len())..(size - 4)].fill(0);
         nums[(size - 4)..size].copy_from_slice(&[
             (self.fcs >> 24) as u8,
             (self.fcs >> 16) as u8,
@@ -108,7 +275,13 @@ impl EthernetFrame {
             self.fcs as u8,
         ]);
 
-        return nums;
+        return Ok(size);
+    }
+}
+
+impl WireDeserialize for EthernetFrame {
+    fn deserialize(bytes: &[u8]) -> Result<Self, ParseError> {
+        Ok(Self::try_deserialize(bytes)?)
     }
 }
 
@@ -117,7 +290,8 @@ impl EthernetFrame {
  */
 #[cfg(test)]
 mod tests {
-    use super::EthernetFrame;
+    use super::{EthernetFrame, EthernetParseError};
+    use crate::utils::wire::WireSerialize;
 
     #[test]
     fn test_new_ethernet() {
@@ -130,11 +304,39 @@ mod tests {
         let new_ins = EthernetFrame::new(d_mac, s_mac, ether_type, payload.to_vec());
         eprintln!("<New Instance>:\n {:?}", new_ins);
         eprintln!("<Result Of CRC>: {}", new_ins.generate_fcs());
-        eprintln!("<Serialized>: \n {:?}", new_ins.serialized());
+        eprintln!("<Serialized>: \n {:?}", new_ins.serialize());
 
-        let new_ins1 = EthernetFrame::deserialize(&new_ins.serialized());
+        let new_ins1 = EthernetFrame::try_deserialize(&new_ins.serialize()).unwrap();
         eprintln!("<Deserialized>: \n{:?}", new_ins1);
         eprintln!("<Result Of CRC>: {}", new_ins1.generate_fcs());
         eprintln!("<Check FCS>: \n {:?}", new_ins1.check_fcs());
     }
+
+    #[test]
+    fn test_deserialize_too_short() {
+        let bytes = [0u8; 63];
+        assert_eq!(
+            EthernetFrame::try_deserialize(&bytes).unwrap_err(),
+            EthernetParseError::TooShort { len: 63, min: 64 }
+        );
+    }
+
+    #[test]
+    fn test_short_payload_is_padded() {
+        let frame = EthernetFrame::new([0xff; 6], [0x12; 6], 0x0800, vec![1, 2, 3]);
+        assert_eq!(frame.payload(), &[1, 2, 3]);
+
+        let bytes = frame.serialize();
+        assert_eq!(bytes.len(), 64);
+        assert_eq!(&bytes[14..17], &[1, 2, 3]);
+        assert!(bytes[17..60].iter().all(|&b| b == 0));
+
+        let parsed = EthernetFrame::try_deserialize(&bytes).unwrap();
+        assert!(parsed.check_fcs());
+        assert_eq!(parsed.payload().len(), 46);
+        assert_eq!(parsed.serialize(), bytes);
+        // 不是合法的 IPv4 首部, 无从判断补齐了多少
+        assert_eq!((parsed.payload_len(), parsed.padded_len()), (46, 46));
+        assert_eq!((frame.payload_len(), frame.padded_len()), (3, 46));
+    }
 }
@@ -0,0 +1,397 @@
+use crate::error::{DeviceError, StackError};
+use crate::link::arp::{ArpPacket, OP_REQUEST};
+use crate::link::ethernet::{EthernetFrame, MIN_PAYLOAD_LEN};
+use crate::net::ipv4::Ipv4Datagram;
+use crate::net::route::{prefix_mask, RoutingTable};
+use crate::transport::tcp_segment::TcpSegment;
+use crate::transport::udp::{UdpDatagram, PROTOCOL_UDP};
+use crate::utils::checksum::ChecksumPolicy;
+use crate::utils::clock::Clock;
+use crate::utils::drops::{DropCounters, DropReason};
+use crate::utils::trace::{SharedTraceSink, Tracer};
+use crate::utils::wire::{WireDeserialize, WireSerialize};
+
+const ETHER_TYPE_IPV4: u16 = 0x0800;
+pub const DEFAULT_MTU: u16 = 1500;
+pub const JUMBO_MTU: u16 = 9000;
+
+/**
+ * 随一帧沿接收路径向上传递的元数据
+ * rx_timestamp_ms 为帧从设备取出的时刻, 取自注入的时钟
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketMeta {
+    pub rx_timestamp_ms: u64,
+    pub interface_id: usize,
+    pub frame_len: usize,
+}
+
+/**
+ * 接口收发的帧数和字节数; 收到的帧无论是否通过检查都计入
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InterfaceStats {
+    pub rx_frames: u64,
+    pub rx_bytes: u64,
+    pub tx_frames: u64,
+    pub tx_bytes: u64,
+}
+
+/**
+ * 载荷不足最小长度的帧怎样发出
+ * Zero 在线路上补 0, 复用的缓冲区里残留的内容不会出现在补齐部分; Strict 拒绝发送, 上层必须自己补齐
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Padding {
+    #[default]
+    Zero,
+    Strict,
+}
+
+/**
+ * 以太网接口: MAC 地址加上一个主地址和若干别名地址
+ * 地址按添加顺序保存, 第一个为主地址
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EthernetInterface {
+    mac: [u8; 6],
+    addrs: Vec<(u32, u8)>,
+    mtu: u16,
+    jumbo: bool,
+    drops: DropCounters,
+    checksum_policy: ChecksumPolicy,
+    tap: bool, // 连接着真实的 TAP 设备, 帧会离开本进程
+    id: usize,
+    stats: InterfaceStats,
+    padding: Padding,
+    tracer: Tracer,
+}
+
+impl EthernetInterface {
+    pub fn new(mac: [u8; 6], primary: u32, prefix_len: u8) -> Self {
+        EthernetInterface {
+            mac, addrs: vec![(primary, prefix_len)], mtu: DEFAULT_MTU, jumbo: false, drops: DropCounters::new(),
+            checksum_policy: ChecksumPolicy::default(), tap: false, id: 0, stats: InterfaceStats::default(),
+            padding: Padding::default(), tracer: Tracer::default(),
+        }
+    }
+
+    /**
+     * 收发的每一帧和丢弃交给 sink, None 取消
+     */
+    pub fn set_trace_sink(&mut self, sink: Option<SharedTraceSink>) {
+        self.tracer = Tracer::new(sink);
+    }
+
+    /**
+     * 标记接口连接着 TAP 设备, 校验和策略随之回到 GenerateAndVerify
+     */
+    pub fn set_tap(&mut self, tap: bool) {
+        self.tap = tap;
+        if tap {
+            self.checksum_policy = ChecksumPolicy::GenerateAndVerify;
+        }
+    }
+
+    /**
+     * TAP 设备上的帧会经过内核和真实网络, 不允许 TrustLink
+     */
+    pub fn set_checksum_policy(&mut self, policy: ChecksumPolicy) -> Result<(), DeviceError> {
+        if self.tap && policy == ChecksumPolicy::TrustLink {
+            return Err(DeviceError::Unsupported { what: "checksum offload on a TAP device" });
+        }
+        self.checksum_policy = policy;
+        Ok(())
+    }
+
+    pub fn checksum_policy(&self) -> ChecksumPolicy {
+        self.checksum_policy
+    }
+
+    /**
+     * 从本接口发出的帧; TrustLink 时 FCS 填 0
+     */
+    pub fn frame(&self, d_mac: [u8; 6], ether_type: u16, payload: Vec<u8>) -> EthernetFrame {
+        if self.checksum_policy.generate() {
+            EthernetFrame::new(d_mac, self.mac, ether_type, payload)
+        } else {
+            EthernetFrame::without_fcs(d_mac, self.mac, ether_type, payload)
+        }
+    }
+
+    pub fn set_padding(&mut self, padding: Padding) {
+        self.padding = padding;
+    }
+
+    pub fn padding(&self) -> Padding {
+        self.padding
+    }
+
+    /**
+     * 多个接口时区分帧来自哪一个, 默认为 0
+     */
+    pub fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn set_mtu(&mut self, mtu: u16) {
+        self.mtu = mtu;
+    }
+
+    pub fn mtu(&self) -> u16 {
+        self.mtu
+    }
+
+    /**
+     * 打开后接受载荷不超过 JUMBO_MTU 的帧
+     */
+    pub fn set_jumbo(&mut self, jumbo: bool) {
+        self.jumbo = jumbo;
+    }
+
+    pub fn drop_counters(&self) -> &DropCounters {
+        &self.drops
+    }
+
+    /**
+     * 设备交上来的一帧, 通过检查的帧交给上层, 否则按原因计数后丢弃
+     */
+    pub fn receive(&mut self, bytes: &[u8]) -> Option<EthernetFrame> {
+        self.tracer.emit(|sink| sink.on_frame_rx(bytes));
+        self.parse(bytes)
+    }
+
+    fn parse(&mut self, bytes: &[u8]) -> Option<EthernetFrame> {
+        self.stats.rx_frames += 1;
+        self.stats.rx_bytes += bytes.len() as u64;
+        let frame = match EthernetFrame::deserialize(bytes) {
+            Ok(frame) => frame,
+            Err(e) => {
+                self.drop_packet(DropReason::from(&e), bytes.len());
+                return None;
+            }
+        };
+        if self.checksum_policy.verify() && !frame.check_fcs() {
+            self.drop_packet(DropReason::BadFcs, bytes.len());
+            return None;
+        }
+        if let Err(reason) = self.check_length(&frame) {
+            self.drop_packet(reason, bytes.len());
+            return None;
+        }
+        Some(frame)
+    }
+
+    /**
+     * 同 receive, 额外返回取出时刻等元数据; 时间戳在解析之前读取, 计入链路层的处理时间
+     */
+    pub fn receive_stamped(&mut self, bytes: &[u8], clock: &dyn Clock) -> Option<(EthernetFrame, PacketMeta)> {
+        let meta = PacketMeta { rx_timestamp_ms: clock.now_ms(), interface_id: self.id, frame_len: bytes.len() };
+        self.tracer.emit(|sink| sink.on_frame_rx_meta(bytes, &meta));
+        self.parse(bytes).map(|frame| (frame, meta))
+    }
+
+    /**
+     * 序列化一帧交给设备; Strict 时载荷不足最小长度返回 ShortPayload
+     */
+    pub fn transmit(&mut self, frame: &EthernetFrame) -> Result<Vec<u8>, DeviceError> {
+        let bytes = self.encode(frame)?;
+        self.tracer.emit(|sink| sink.on_frame_tx(&bytes));
+        Ok(bytes)
+    }
+
+    fn encode(&mut self, frame: &EthernetFrame) -> Result<Vec<u8>, DeviceError> {
+        let mut bytes = vec![0; frame.wire_size()];
+        self.encode_into(frame, &mut bytes).map_err(|e| match e {
+            StackError::Device(e) => e,
+            e => unreachable!("buffer sized by wire_size(): {}", e),
+        })?;
+        Ok(bytes)
+    }
+
+    /**
+     * 序列化进调用者复用的缓冲区 (如缓冲池里的), 返回帧长
+     * 缓冲区里可能残留上一帧的内容, 补齐部分一定重新写 0, 不会把旧数据带到线路上
+     */
+    pub fn transmit_into(&mut self, frame: &EthernetFrame, buf: &mut [u8]) -> Result<usize, StackError> {
+        let size = self.encode_into(frame, buf)?;
+        self.tracer.emit(|sink| sink.on_frame_tx(&buf[..size]));
+        Ok(size)
+    }
+
+    fn encode_into(&mut self, frame: &EthernetFrame, buf: &mut [u8]) -> Result<usize, StackError> {
+        let len = frame.payload().len();
+        if self.padding == Padding::Strict && len < MIN_PAYLOAD_LEN {
+            return Err(DeviceError::ShortPayload { len, min: MIN_PAYLOAD_LEN }.into());
+        }
+        let size = frame.serialize_into(buf)?;
+        assert!(buf[14 + len..size - 4].iter().all(|&b| b == 0), "pad region must be zeroed");
+        self.stats.tx_frames += 1;
+        self.stats.tx_bytes += size as u64;
+        Ok(size)
+    }
+
+    /**
+     * 同 transmit, 同时返回交付时刻
+     */
+    pub fn transmit_stamped(&mut self, frame: &EthernetFrame, clock: &dyn Clock) -> Result<(Vec<u8>, u64), DeviceError> {
+        let bytes = self.encode(frame)?;
+        let tx_ms = clock.now_ms();
+        self.tracer.emit(|sink| sink.on_frame_tx_at(&bytes, tx_ms));
+        Ok((bytes, tx_ms))
+    }
+
+    pub fn stats(&self) -> InterfaceStats {
+        self.stats
+    }
+
+    /**
+     * 收到的数据报交给上层之前校验 IP 首部和 TCP/UDP 校验和, 失败时计数并返回 false
+     * TrustLink 时不校验; UDP 校验和为 0 表示发送方没有计算
+     */
+    pub fn verify_datagram(&mut self, datagram: &Ipv4Datagram) -> bool {
+        if !self.checksum_policy.verify() {
+            return true;
+        }
+        let (s_addr, d_addr) = (datagram.s_addr(), datagram.d_addr());
+        let verdict = if !datagram.check_hdr_checksum() {
+            Err(DropReason::BadIpChecksum)
+        } else if datagram.is_fragment() {
+            Ok(()) // 重组之后再校验
+        } else {
+            match datagram.protocol() {
+                6 => match TcpSegment::try_deserialize(datagram.payload()) {
+                    Ok(segment) if segment.check_checksum(s_addr, d_addr) => Ok(()),
+                    _ => Err(DropReason::BadTcpChecksum),
+                },
+                PROTOCOL_UDP => match UdpDatagram::try_deserialize(datagram.payload()) {
+                    Ok(udp) if udp.check_checksum(s_addr, d_addr) => Ok(()),
+                    _ => Err(DropReason::BadUdpChecksum),
+                },
+                _ => Ok(()),
+            }
+        };
+        match verdict {
+            Ok(()) => true,
+            Err(reason) => {
+                self.drop_packet(reason, datagram.wire_size());
+                false
+            }
+        }
+    }
+
+    fn drop_packet(&mut self, reason: DropReason, bytes: usize) {
+        let total = self.drops.record(reason);
+        self.tracer.emit(|sink| sink.on_drop(reason, bytes, total));
+    }
+
+    /**
+     * 解析之后的长度检查: 载荷超过 MTU 为 giant; IPv4 的 total_len 超过载荷为截断
+     * total_len 小于载荷是最小帧的补齐, 由 IP 层去掉
+     */
+    fn check_length(&self, frame: &EthernetFrame) -> Result<(), DropReason> {
+        let payload = frame.payload();
+        let limit = if self.jumbo { self.mtu.max(JUMBO_MTU) } else { self.mtu };
+        if payload.len() > limit as usize {
+            return Err(DropReason::RxGiant);
+        }
+        if frame.ether_type() == ETHER_TYPE_IPV4 && payload.len() >= 4 {
+            let total_len = u16::from_be_bytes([payload[2], payload[3]]) as usize;
+            if total_len > payload.len() {
+                return Err(DropReason::RxTruncated);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn mac(&self) -> [u8; 6] {
+        self.mac
+    }
+
+    /**
+     * 添加别名地址, 已存在时只更新前缀长度
+     */
+    pub fn add_address(&mut self, addr: u32, prefix_len: u8) {
+        match self.addrs.iter_mut().find(|(a, _)| *a == addr) {
+            Some(entry) => entry.1 = prefix_len,
+            None => self.addrs.push((addr, prefix_len)),
+        }
+    }
+
+    /**
+     * 删除别名地址, 主地址不能删除
+     */
+    pub fn remove_address(&mut self, addr: u32) -> bool {
+        match self.addrs.iter().skip(1).position(|(a, _)| *a == addr) {
+            Some(index) => {
+                self.addrs.remove(index + 1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn primary(&self) -> u32 {
+        self.addrs[0].0
+    }
+
+    pub fn addresses(&self) -> &[(u32, u8)] {
+        &self.addrs
+    }
+
+    pub fn is_local(&self, addr: u32) -> bool {
+        self.addrs.iter().any(|(a, _)| *a == addr)
+    }
+
+    /**
+     * 与 dst 在同一子网的本地地址, 前缀最长的优先
+     */
+    pub fn on_link_source(&self, dst: u32) -> Option<u32> {
+        self.addrs.iter()
+            .filter(|(addr, prefix_len)| (addr ^ dst) & prefix_mask(*prefix_len) == 0)
+            .max_by_key(|(_, prefix_len)| *prefix_len)
+            .map(|(addr, _)| *addr)
+    }
+
+    /**
+     * 发往 dst 的源地址: 路由指定的首选源(必须是本地地址), 其次是同一子网的地址, 否则用主地址
+     */
+    pub fn select_source(&self, dst: u32, routes: &RoutingTable) -> u32 {
+        routes.lookup(dst)
+            .and_then(|route| route.preferred_source)
+            .filter(|source| self.is_local(*source))
+            .or_else(|| self.on_link_source(dst))
+            .unwrap_or(self.primary())
+    }
+
+    /**
+     * 询问任一本地地址的 ARP 请求返回应答
+     */
+    pub fn answer_arp(&self, request: &ArpPacket) -> Option<ArpPacket> {
+        if request.op != OP_REQUEST || !self.is_local(request.t_ip) {
+            return None;
+        }
+        Some(ArpPacket::reply_to(request, self.mac))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aliases() {
+        let mut iface = EthernetInterface::new([2, 0, 0, 0, 0, 1], 0x0a000001, 24);
+        iface.add_address(0xc0a80101, 24);
+        iface.add_address(0xc0a80101, 16);
+        assert_eq!(iface.addresses(), &[(0x0a000001, 24), (0xc0a80101, 16)]);
+        assert!(!iface.remove_address(0x0a000001));
+        assert!(iface.remove_address(0xc0a80101));
+        assert!(!iface.is_local(0xc0a80101));
+    }
+}
@@ -1 +1,7 @@
 pub mod ethernet;
+pub mod arp_cache;
+pub mod arp;
+pub mod arp_queue;
+pub mod interface;
+pub mod vlan;
+pub mod device;
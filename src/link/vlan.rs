@@ -0,0 +1,66 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::error::SerializeError;
+use crate::utils::wire::{self, WireSerialize};
+
+pub const ETHER_TYPE_VLAN: u16 = 0x8100;
+pub const TAG_LEN: usize = 4;
+
+/**
+ * 802.1Q 标签解析错误
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VlanParseError {
+    TooShort { len: usize },
+}
+
+impl fmt::Display for VlanParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VlanParseError::TooShort { len } => write!(f, "vlan tag too short: {} bytes, need 4", len),
+        }
+    }
+}
+
+impl Error for VlanParseError {}
+
+/**
+ * 802.1Q 标签: ether_type 为 0x8100 的帧载荷以 TCI(PCP 3 位, DEI 1 位, VID 12 位) 和内层 ether_type 开头
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VlanTag {
+    pub pcp: u8,
+    pub dei: bool,
+    pub vid: u16,
+    pub ether_type: u16,
+}
+
+impl VlanTag {
+    pub fn try_deserialize(bytes: &[u8]) -> Result<Self, VlanParseError> {
+        if bytes.len() < TAG_LEN {
+            return Err(VlanParseError::TooShort { len: bytes.len() });
+        }
+        let tci = u16::from_be_bytes([bytes[0], bytes[1]]);
+        Ok(VlanTag {
+            pcp: (tci >> 13) as u8,
+            dei: tci & 0x1000 != 0,
+            vid: tci & 0x0fff,
+            ether_type: u16::from_be_bytes([bytes[2], bytes[3]]),
+        })
+    }
+}
+
+impl WireSerialize for VlanTag {
+    fn wire_size(&self) -> usize {
+        TAG_LEN
+    }
+
+    fn serialize_into(&self, buf: &mut [u8]) -> Result<usize, SerializeError> {
+        wire::check_buffer(buf, TAG_LEN)?;
+        let tci = ((self.pcp as u16 & 0b111) << 13) | ((self.dei as u16) << 12) | (self.vid & 0x0fff);
+        buf[0..2].copy_from_slice(&tci.to_be_bytes());
+        buf[2..4].copy_from_slice(&self.ether_type.to_be_bytes());
+        Ok(TAG_LEN)
+    }
+}
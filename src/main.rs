@@ -1,8 +1,3 @@
-mod link;
-mod transport;
-mod net;
-mod utils;
-
 fn main() {
     println!("This is a simple implenment of TCP/IP protocal stack!")
 }
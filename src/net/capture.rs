@@ -0,0 +1,75 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::rc::Rc;
+
+use crate::net::ipv4::Ipv4Datagram;
+use crate::utils::clock::Clock;
+use crate::utils::pcap::PcapWriter;
+use crate::utils::wire::WireSerialize;
+
+/**
+ * 被抓取的数据报的方向
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CaptureDirection {
+    Rx, // 解析成功之后
+    Tx, // 序列化之前
+}
+
+/**
+ * 抓取回调只拿到共享引用, 不能修改数据报, 也不影响协议栈的处理结果
+ */
+pub type CaptureFn = Box<dyn FnMut(CaptureDirection, &Ipv4Datagram)>;
+
+/**
+ * IP 层的旁路抓包点, 不需要原始套接字就能看到经过的每一个数据报
+ * 没有安装回调时什么都不做
+ */
+#[derive(Default)]
+pub struct CaptureTap {
+    capture: Option<CaptureFn>,
+}
+
+impl CaptureTap {
+    /**
+     * 安装或用 None 卸下回调, 返回原来的回调
+     */
+    pub fn set(&mut self, capture: Option<CaptureFn>) -> Option<CaptureFn> {
+        std::mem::replace(&mut self.capture, capture)
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    pub fn rx(&mut self, datagram: &Ipv4Datagram) {
+        self.emit(CaptureDirection::Rx, datagram);
+    }
+
+    pub fn tx(&mut self, datagram: &Ipv4Datagram) {
+        self.emit(CaptureDirection::Tx, datagram);
+    }
+
+    fn emit(&mut self, direction: CaptureDirection, datagram: &Ipv4Datagram) {
+        if let Some(capture) = self.capture.as_mut() {
+            capture(direction, datagram);
+        }
+    }
+}
+
+impl fmt::Debug for CaptureTap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CaptureTap").field("installed", &self.is_set()).finish()
+    }
+}
+
+/**
+ * 把抓到的数据报原样写入 pcap, writer 应以 LINKTYPE_RAW 创建, 这样不用伪造以太网首部
+ * writer 由调用方共享持有, 抓取结束后从中取出; 写入失败直接忽略
+ */
+pub fn pcap_capture<W: io::Write + 'static, C: Clock + 'static>(pcap: Rc<RefCell<PcapWriter<W>>>, clock: C) -> CaptureFn {
+    Box::new(move |_, datagram| {
+        let _ = pcap.borrow_mut().write_packet(clock.now_ms(), &datagram.serialize());
+    })
+}
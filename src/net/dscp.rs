@@ -0,0 +1,59 @@
+/**
+ * 区分服务码点 (RFC 2474), 占 TOS 字节的高 6 位, 低 2 位留给 ECN
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Dscp(u8);
+
+impl Dscp {
+    pub const DEFAULT: Dscp = Dscp(0);
+    pub const EF: Dscp = Dscp(46); // 加速转发 (RFC 3246)
+
+    /**
+     * 超过 6 位的部分截掉
+     */
+    pub const fn new(value: u8) -> Self {
+        Dscp(value & 0x3f)
+    }
+
+    /**
+     * 类选择码点 CS0 ~ CS7 (RFC 2474)
+     */
+    pub const fn cs(class: u8) -> Self {
+        Dscp::new(class << 3)
+    }
+
+    /**
+     * 确保转发 AFxy (RFC 2597), class 1 ~ 4, drop 1 ~ 3
+     */
+    pub const fn af(class: u8, drop: u8) -> Self {
+        Dscp::new((class << 3) | (drop << 1))
+    }
+
+    pub const fn value(self) -> u8 {
+        self.0
+    }
+
+    pub const fn from_tos(tos: u8) -> Self {
+        Dscp(tos >> 2)
+    }
+
+    /**
+     * 替换 tos 中的码点, 保留 ECN 位
+     */
+    pub const fn apply_to(self, tos: u8) -> u8 {
+        (self.0 << 2) | (tos & 0x03)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_points() {
+        assert_eq!(Dscp::af(4, 1).value(), 34);
+        assert_eq!(Dscp::cs(6).value(), 48);
+        assert_eq!(Dscp::EF.apply_to(0x01), 0xb9);
+        assert_eq!(Dscp::from_tos(0xb9), Dscp::EF);
+    }
+}
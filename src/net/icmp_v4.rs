@@ -1,5 +1,32 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::error::{ParseError, SerializeError};
+use crate::utils::checksum;
+use crate::utils::wire::{self, WireDeserialize, WireSerialize};
+
+/**
+ * ICMP 报文解析错误
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IcmpParseError {
+    TooShort { len: usize },
+}
+
+impl fmt::Display for IcmpParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IcmpParseError::TooShort { len } => {
+                write!(f, "message too short: {} bytes, header needs at least 4", len)
+            }
+        }
+    }
+}
+
+impl Error for IcmpParseError {}
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IcmpV4 {
     icmp_type: u8,
     code: u8,
@@ -11,45 +38,195 @@ impl IcmpV4 {
 
     pub fn new(icmp_type: u8, code: u8, data: Vec<u8>) -> Self {
         let mut new_ins = IcmpV4 {icmp_type, code, check_sum: 0, data};
-        new_ins.check_sum = Self::generate_checksum(&new_ins.serialized());
+        new_ins.check_sum = checksum::generate_checksum(&new_ins.serialize());
         return  new_ins;
     }
 
-    pub fn deserialize(bytes: &Vec<u8>) -> Self {
-        IcmpV4 {
+    pub fn try_deserialize(bytes: &[u8]) -> Result<Self, IcmpParseError> {
+        if bytes.len() < 4 {
+            return Err(IcmpParseError::TooShort { len: bytes.len() });
+        }
+        Ok(IcmpV4 {
             icmp_type: bytes[0],
             code: bytes[1],
             check_sum: ((bytes[2] as u16) << 8) + (bytes[3] as u16),
             data: bytes[4..].to_vec()
+        })
+    }
+
+    /**
+     * 解析失败时 panic, 仅用于确定输入合法的场景
+     */
+    #[deprecated(note = "use WireDeserialize::deserialize")]
+    pub fn deserialize_unchecked(bytes: &[u8]) -> Self {
+        match Self::try_deserialize(bytes) {
+            Ok(icmp) => icmp,
+            Err(e) => panic!("Invalid ICMP message: {}", e),
         }
     }
 
+    pub fn icmp_type(&self) -> u8 {
+        self.icmp_type
+    }
+
+    pub fn code(&self) -> u8 {
+        self.code
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    #[deprecated(note = "use WireSerialize::serialize")]
     pub fn serialized(&self) -> Vec<u8>{
-        let mut result: Vec<u8> = vec![self.icmp_type, self.code, (self.check_sum >> 8) as u8, self.check_sum as u8];
-        result.append(&mut self.data.clone());
-        return result;
+        self.serialize()
     }
 
-    fn generate_checksum(bytes: &Vec<u8>) -> u16{
-        let mut checksum = 0;
+    /**
+     * 差错报文 (RFC 792): 4 字节的 rest 之后带回原数据报的首部和至少 8 字节载荷
+     */
+    pub fn error(icmp_type: u8, code: u8, rest: [u8; 4], original: &[u8]) -> Self {
+        let hdr_len = original.first().map_or(20, |b| ((b & 0x0f) as usize * 4).max(20));
+        let mut data = rest.to_vec();
+        data.extend_from_slice(&original[..original.len().min(hdr_len + 8)]);
+        Self::new(icmp_type, code, data)
+    }
+
+    /**
+     * TTL 耗尽 (type 11 code 0)
+     */
+    pub fn time_exceeded(original: &[u8]) -> Self {
+        Self::error(11, 0, [0; 4], original)
+    }
 
-        if bytes.len() & 1 == 1 {
-            panic!("odd length!");
+    /**
+     * 目的不可达 (type 3), code 3 为端口不可达
+     */
+    pub fn dest_unreachable(code: u8, original: &[u8]) -> Self {
+        Self::error(3, code, [0; 4], original)
+    }
+
+    /**
+     * 差错报文的类型: 不可达、源抑制、重定向、超时、参数问题
+     */
+    pub fn is_error(&self) -> bool {
+        matches!(self.icmp_type, 3 | 4 | 5 | 11 | 12)
+    }
+
+    /**
+     * 差错报文中带回的原数据报(首部 + 部分载荷), 其他类型返回 None
+     */
+    pub fn embedded_datagram(&self) -> Option<&[u8]> {
+        match self.icmp_type {
+            _ if self.is_error() && self.data.len() >= 4 + 20 => Some(&self.data[4..]),
+            _ => None,
         }
+    }
 
-        for i in (0..bytes.len()).step_by(2) {
-            checksum += ((bytes[i] as u32) << 8) + (bytes[i + 1] as u32);
-            
-            if checksum & 0xffff0000 != 0 { // 处理溢出
-                checksum = (checksum & 0x0000ffff) + (checksum >> 16);
-            }
+    pub fn check(bytes: &[u8]) -> bool {
+        checksum::check(bytes)
+    }
+
+}
+
+impl WireSerialize for IcmpV4 {
+    fn wire_size(&self) -> usize {
+        4 + self.data.len()
+    }
+
+    fn serialize_into(&self, buf: &mut [u8]) -> Result<usize, SerializeError> {
+        let size = self.wire_size();
+        wire::check_buffer(buf, size)?;
+        buf[..4].copy_from_slice(&[self.icmp_type, self.code, (self.check_sum >> 8) as u8, self.check_sum as u8]);
+        buf[4..size].copy_from_slice(&self.data);
+        Ok(size)
+    }
+}
+
+impl WireDeserialize for IcmpV4 {
+    fn deserialize(bytes: &[u8]) -> Result<Self, ParseError> {
+        Ok(Self::try_deserialize(bytes)?)
+    }
+}
+
+/**
+ * ICMP 差错报文的全局令牌桶 (RFC 1812 4.3.2.8), 每秒补充 per_sec 个, 最多攒 per_sec 个
+ * 令牌以千分之一为单位, 补充不会因为取整丢失
+ */
+#[derive(Debug, Clone)]
+pub struct IcmpRateLimiter {
+    per_sec: Option<u64>,
+    tokens: u64,
+    last_ms: u64,
+    suppressed: u64,
+}
+
+impl IcmpRateLimiter {
+    /**
+     * None 不限速
+     */
+    pub fn new(per_sec: Option<u32>) -> Self {
+        let per_sec = per_sec.map(u64::from);
+        IcmpRateLimiter { per_sec, tokens: per_sec.unwrap_or(0) * 1000, last_ms: 0, suppressed: 0 }
+    }
+
+    /**
+     * 取一个令牌, 取不到时这条差错报文不发, 计入 suppressed
+     */
+    pub fn allow(&mut self, now_ms: u64) -> bool {
+        let Some(per_sec) = self.per_sec else {
+            return true;
+        };
+        self.tokens = (self.tokens + now_ms.saturating_sub(self.last_ms) * per_sec).min(per_sec * 1000);
+        self.last_ms = now_ms.max(self.last_ms);
+        if self.tokens >= 1000 {
+            self.tokens -= 1000;
+            true
+        } else {
+            self.suppressed += 1;
+            false
         }
-        
-        checksum as u16
     }
 
-    pub fn check(bytes: &Vec<u8>) -> bool {
-        Self::generate_checksum(bytes) == 0
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed
     }
+}
 
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_refills() {
+        let mut limiter = IcmpRateLimiter::new(Some(2));
+        assert!(limiter.allow(0) && limiter.allow(0));
+        assert!(!limiter.allow(0));
+        assert!(!limiter.allow(499));
+        assert!(limiter.allow(500));
+        // 空闲很久之后也只攒满两个
+        assert!(limiter.allow(10_000) && limiter.allow(10_000));
+        assert!(!limiter.allow(10_000));
+        assert_eq!(limiter.suppressed(), 3);
+
+        let mut unlimited = IcmpRateLimiter::new(None);
+        assert!((0..1000).all(|_| unlimited.allow(0)));
+    }
+
+    #[test]
+    fn test_icmp_checksum() {
+        // 奇数长度的载荷也能正确计算校验和
+        let echo = IcmpV4::new(8, 0, vec![0x00, 0x01, 0x00, 0x01, 0x61]);
+        let bytes = echo.serialize();
+        assert!(IcmpV4::check(&bytes));
+
+        let parsed = IcmpV4::try_deserialize(&bytes).unwrap();
+        assert_eq!(parsed.icmp_type, 8);
+        assert_eq!(parsed.data, vec![0x00, 0x01, 0x00, 0x01, 0x61]);
+    }
+
+    #[test]
+    fn test_deserialize_too_short() {
+        assert_eq!(IcmpV4::try_deserialize(&[8, 0, 0]).unwrap_err(), IcmpParseError::TooShort { len: 3 });
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, DefaultHasher, Hash, Hasher};
+
+use crate::error::SerializeError;
+use crate::utils::checksum;
+use crate::utils::wire::{self, WireSerialize};
+
+pub const PROTOCOL_IGMP: u8 = 2;
+pub const IGMP_LEN: usize = 8;
+pub const ALL_HOSTS: u32 = 0xe000_0001;   // 224.0.0.1
+pub const ALL_ROUTERS: u32 = 0xe000_0002; // 224.0.0.2, Leave 的目的地址
+pub const UNSOLICITED_REPORT_INTERVAL_MS: u64 = 10_000;
+
+/**
+ * IGMPv2 报文类型 (RFC 2236)
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IgmpType {
+    MembershipQuery, // 0x11, group 为 0 时是通用查询
+    V1Report,        // 0x12
+    V2Report,        // 0x16
+    Leave,           // 0x17
+}
+
+impl IgmpType {
+    pub fn code(self) -> u8 {
+        match self {
+            IgmpType::MembershipQuery => 0x11,
+            IgmpType::V1Report => 0x12,
+            IgmpType::V2Report => 0x16,
+            IgmpType::Leave => 0x17,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0x11 => Some(IgmpType::MembershipQuery),
+            0x12 => Some(IgmpType::V1Report),
+            0x16 => Some(IgmpType::V2Report),
+            0x17 => Some(IgmpType::Leave),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * IGMP 报文解析错误
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IgmpParseError {
+    TooShort { len: usize },
+    UnknownType { igmp_type: u8 },
+    BadChecksum,
+}
+
+impl fmt::Display for IgmpParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IgmpParseError::TooShort { len } => write!(f, "igmp message too short: {} bytes, need 8", len),
+            IgmpParseError::UnknownType { igmp_type } => write!(f, "unknown igmp type 0x{:02x}", igmp_type),
+            IgmpParseError::BadChecksum => write!(f, "igmp checksum mismatch"),
+        }
+    }
+}
+
+impl Error for IgmpParseError {}
+
+/**
+ * max_resp_time 单位为 0.1 秒, 只在查询报文中有意义
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IgmpMessage {
+    pub igmp_type: IgmpType,
+    pub max_resp_time: u8,
+    pub group: u32,
+}
+
+impl IgmpMessage {
+    pub fn new(igmp_type: IgmpType, max_resp_time: u8, group: u32) -> Self {
+        IgmpMessage { igmp_type, max_resp_time, group }
+    }
+
+    /**
+     * 校验和与 ICMP 相同, 覆盖整个 IGMP 报文
+     */
+    pub fn try_deserialize(bytes: &[u8]) -> Result<Self, IgmpParseError> {
+        if bytes.len() < IGMP_LEN {
+            return Err(IgmpParseError::TooShort { len: bytes.len() });
+        }
+        if !checksum::check(&bytes[..IGMP_LEN]) {
+            return Err(IgmpParseError::BadChecksum);
+        }
+        let igmp_type = IgmpType::from_code(bytes[0]).ok_or(IgmpParseError::UnknownType { igmp_type: bytes[0] })?;
+        Ok(IgmpMessage { igmp_type, max_resp_time: bytes[1], group: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) })
+    }
+
+    /**
+     * 报文应发往的地址: 报告发往组本身, Leave 发往所有路由器
+     */
+    pub fn destination(&self) -> u32 {
+        match self.igmp_type {
+            IgmpType::Leave => ALL_ROUTERS,
+            IgmpType::MembershipQuery if self.group == 0 => ALL_HOSTS,
+            _ => self.group,
+        }
+    }
+}
+
+impl WireSerialize for IgmpMessage {
+    fn wire_size(&self) -> usize {
+        IGMP_LEN
+    }
+
+    fn serialize_into(&self, buf: &mut [u8]) -> Result<usize, SerializeError> {
+        wire::check_buffer(buf, IGMP_LEN)?;
+        buf[0] = self.igmp_type.code();
+        buf[1] = self.max_resp_time;
+        buf[2..4].copy_from_slice(&[0, 0]);
+        buf[4..8].copy_from_slice(&self.group.to_be_bytes());
+        let sum = checksum::generate_checksum(&buf[..IGMP_LEN]);
+        buf[2..4].copy_from_slice(&sum.to_be_bytes());
+        Ok(IGMP_LEN)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupState {
+    Idle,            // 没有待发的报告
+    Delaying(u64),   // 在给定时刻发报告
+}
+
+#[derive(Debug, Clone)]
+struct Membership {
+    members: usize, // 加入该组的套接字数
+    state: GroupState,
+}
+
+/**
+ * 主机侧的 IGMPv2 状态机, 时间取自调用方的时钟
+ * 加入时立即发一次报告, UNSOLICITED_REPORT_INTERVAL_MS 之后重复一次;
+ * 收到查询在 [0, max_resp_time) 内随机延迟回应, 期间听到别人的报告则不再发送;
+ * 最后一个成员退出时发 Leave
+ */
+pub struct IgmpHost {
+    groups: HashMap<u32, Membership>,
+    outgoing: Vec<IgmpMessage>,
+    secret: u64,
+    draws: u64,
+}
+
+impl IgmpHost {
+    pub fn new() -> Self {
+        Self::with_seed(RandomState::new().build_hasher().finish())
+    }
+
+    /**
+     * 固定随机延迟的种子, 用于测试
+     */
+    pub fn with_seed(seed: u64) -> Self {
+        IgmpHost { groups: HashMap::new(), outgoing: vec![], secret: seed, draws: 0 }
+    }
+
+    /**
+     * 一个套接字加入组, 返回该组当前的成员数
+     * 224.0.0.1 所有主机默认加入, 不发报告
+     */
+    pub fn join(&mut self, group: u32, now_ms: u64) -> usize {
+        let membership = self.groups.entry(group).or_insert(Membership { members: 0, state: GroupState::Idle });
+        membership.members += 1;
+        if membership.members == 1 && group != ALL_HOSTS {
+            self.outgoing.push(IgmpMessage::new(IgmpType::V2Report, 0, group));
+            membership.state = GroupState::Delaying(now_ms + UNSOLICITED_REPORT_INTERVAL_MS);
+        }
+        membership.members
+    }
+
+    /**
+     * 一个套接字退出组, 最后一个退出时发 Leave 并忘掉该组
+     */
+    pub fn leave(&mut self, group: u32) -> usize {
+        let Some(membership) = self.groups.get_mut(&group) else {
+            return 0;
+        };
+        membership.members -= 1;
+        if membership.members > 0 {
+            return membership.members;
+        }
+        self.groups.remove(&group);
+        if group != ALL_HOSTS {
+            self.outgoing.push(IgmpMessage::new(IgmpType::Leave, 0, group));
+        }
+        0
+    }
+
+    pub fn is_member(&self, group: u32) -> bool {
+        self.groups.contains_key(&group)
+    }
+
+    /**
+     * IP 层按协议号 2 分派过来的报文
+     */
+    pub fn on_message(&mut self, message: &IgmpMessage, now_ms: u64) {
+        match message.igmp_type {
+            IgmpType::MembershipQuery => {
+                // v1 查询的 max_resp_time 为 0, 按 10 秒处理
+                let max_ms = if message.max_resp_time == 0 { 10_000 } else { message.max_resp_time as u64 * 100 };
+                let targets: Vec<u32> = self.groups.keys().copied()
+                    .filter(|group| *group != ALL_HOSTS && (message.group == 0 || message.group == *group))
+                    .collect();
+                for group in targets {
+                    let delay = self.random_below(group, max_ms);
+                    let membership = self.groups.get_mut(&group).unwrap();
+                    // 已经在等待且更早到期的保持不变
+                    match membership.state {
+                        GroupState::Delaying(at) if at <= now_ms + delay => {}
+                        _ => membership.state = GroupState::Delaying(now_ms + delay),
+                    }
+                }
+            }
+            IgmpType::V1Report | IgmpType::V2Report => {
+                if let Some(membership) = self.groups.get_mut(&message.group) {
+                    membership.state = GroupState::Idle;
+                }
+            }
+            IgmpType::Leave => {}
+        }
+    }
+
+    /**
+     * 到期的组发出报告
+     */
+    pub fn tick(&mut self, now_ms: u64) {
+        let mut due: Vec<u32> = self.groups.iter()
+            .filter(|(_, m)| matches!(m.state, GroupState::Delaying(at) if at <= now_ms))
+            .map(|(group, _)| *group)
+            .collect();
+        due.sort_unstable();
+        for group in due {
+            let membership = self.groups.get_mut(&group).unwrap();
+            membership.state = GroupState::Idle;
+            self.outgoing.push(IgmpMessage::new(IgmpType::V2Report, 0, group));
+        }
+    }
+
+    pub fn state(&self, group: u32) -> Option<GroupState> {
+        self.groups.get(&group).map(|m| m.state)
+    }
+
+    /**
+     * 取走待发的报文, 由调用方用 destination() 作为目的地址、TTL 1 发出
+     */
+    pub fn take_outgoing(&mut self) -> Vec<IgmpMessage> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    fn random_below(&mut self, group: u32, max_ms: u64) -> u64 {
+        self.draws += 1;
+        let mut hasher = DefaultHasher::new();
+        (self.secret, group, self.draws).hash(&mut hasher);
+        hasher.finish() % max_ms.max(1)
+    }
+}
+
+impl Default for IgmpHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GROUP_A: u32 = 0xef01_0101;
+    const GROUP_B: u32 = 0xef01_0102;
+
+    fn reports(messages: &[IgmpMessage]) -> Vec<u32> {
+        messages.iter().filter(|m| m.igmp_type == IgmpType::V2Report).map(|m| m.group).collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let message = IgmpMessage::new(IgmpType::MembershipQuery, 100, 0);
+        let mut bytes = message.serialize();
+        assert_eq!(&bytes[..2], &[0x11, 100]);
+        assert!(checksum::check(&bytes));
+        assert_eq!(IgmpMessage::try_deserialize(&bytes), Ok(message));
+        assert_eq!(message.destination(), ALL_HOSTS);
+        bytes[7] ^= 1;
+        assert_eq!(IgmpMessage::try_deserialize(&bytes), Err(IgmpParseError::BadChecksum));
+    }
+
+    #[test]
+    fn test_join_sends_two_reports() {
+        let mut host = IgmpHost::with_seed(1);
+        host.join(GROUP_A, 0);
+        assert_eq!(host.join(GROUP_A, 0), 2); // 第二个套接字不再发报告
+        assert_eq!(reports(&host.take_outgoing()), vec![GROUP_A]);
+        host.tick(UNSOLICITED_REPORT_INTERVAL_MS - 1);
+        assert!(host.take_outgoing().is_empty());
+        host.tick(UNSOLICITED_REPORT_INTERVAL_MS);
+        assert_eq!(reports(&host.take_outgoing()), vec![GROUP_A]);
+        host.tick(60_000);
+        assert!(host.take_outgoing().is_empty());
+    }
+
+    #[test]
+    fn test_general_query_is_answered_per_group() {
+        let mut host = IgmpHost::with_seed(7);
+        host.join(GROUP_A, 0);
+        host.join(GROUP_B, 0);
+        host.join(ALL_HOSTS, 0);
+        host.tick(UNSOLICITED_REPORT_INTERVAL_MS);
+        host.take_outgoing();
+
+        let now = 20_000;
+        host.on_message(&IgmpMessage::new(IgmpType::MembershipQuery, 50, 0), now); // 5 秒
+        assert!(host.take_outgoing().is_empty()); // 不立即回应
+        let mut sent = vec![];
+        for t in (now..=now + 5000).step_by(100) {
+            host.tick(t);
+            sent.extend(host.take_outgoing());
+        }
+        let mut groups = reports(&sent);
+        groups.sort_unstable();
+        assert_eq!(groups, vec![GROUP_A, GROUP_B]);
+    }
+
+    #[test]
+    fn test_report_from_other_host_suppresses() {
+        let mut host = IgmpHost::with_seed(3);
+        host.join(GROUP_A, 0);
+        host.tick(UNSOLICITED_REPORT_INTERVAL_MS);
+        host.take_outgoing();
+        host.on_message(&IgmpMessage::new(IgmpType::MembershipQuery, 100, GROUP_A), 20_000);
+        assert!(matches!(host.state(GROUP_A), Some(GroupState::Delaying(_))));
+        host.on_message(&IgmpMessage::new(IgmpType::V2Report, 0, GROUP_A), 20_001);
+        host.tick(40_000);
+        assert!(host.take_outgoing().is_empty());
+    }
+
+    #[test]
+    fn test_leave_on_last_drop() {
+        let mut host = IgmpHost::with_seed(1);
+        host.join(GROUP_A, 0);
+        host.join(GROUP_A, 0);
+        host.take_outgoing();
+        assert_eq!(host.leave(GROUP_A), 1);
+        assert!(host.take_outgoing().is_empty());
+        assert_eq!(host.leave(GROUP_A), 0);
+        let sent = host.take_outgoing();
+        assert_eq!(sent, vec![IgmpMessage::new(IgmpType::Leave, 0, GROUP_A)]);
+        assert_eq!(sent[0].destination(), ALL_ROUTERS);
+        assert!(!host.is_member(GROUP_A));
+        host.tick(UNSOLICITED_REPORT_INTERVAL_MS); // 退出后不再重复报告
+        assert!(host.take_outgoing().is_empty());
+    }
+}
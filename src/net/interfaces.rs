@@ -0,0 +1,564 @@
+use std::collections::VecDeque;
+
+use crate::config::{ArpConfig, ConfigError, HostModel, Ipv4Config, NatConfig};
+use crate::link::arp::{ArpPacket, ETHER_TYPE_ARP};
+use crate::link::arp_cache::{ArpCache, Resolution};
+use crate::link::arp_queue::{ArpFailure, ArpPendingQueue, Origin};
+use crate::link::device::NetworkDevice;
+use crate::link::ethernet::pad_payload;
+use crate::link::interface::EthernetInterface;
+use crate::net::ipv4::Ipv4Datagram;
+use crate::net::nat::Nat44;
+use crate::net::route::RoutingTable;
+use crate::net::source_guard::Arrival;
+use crate::transport::tcp_connection::ConnectionId;
+use crate::utils::drops::{DropCounters, DropReason};
+use crate::utils::trace::{SharedTraceSink, Tracer};
+use crate::utils::wire::{WireDeserialize, WireSerialize};
+
+const ETHER_TYPE_IPV4: u16 = 0x0800;
+const BROADCAST: u32 = 0xffff_ffff;
+
+/**
+ * 接口编号, 即 Route::interface 和 PacketMeta::interface_id
+ */
+pub type InterfaceId = usize;
+
+/**
+ * 发往某个目的地址时的出口: 出接口、ARP 解析的下一跳和源地址
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hop {
+    pub interface: InterfaceId,
+    pub next_hop: u32,
+    pub source: u32,
+}
+
+/**
+ * 链路状态的变化, 由 take_link_events 取走
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkEvent {
+    Up(InterfaceId),
+    Down(InterfaceId),
+    DownTimeout(InterfaceId), // 断开超过 link_down_abort_ms, 上层应放弃经过它的连接
+}
+
+/**
+ * 一个接口和只属于它的 ARP 状态, 以及设备交上来还没有处理的帧和要交给设备的帧
+ */
+#[derive(Debug)]
+struct Port {
+    iface: EthernetInterface,
+    arp: ArpCache,
+    pending: ArpPendingQueue,
+    rx: VecDeque<Vec<u8>>,
+    tx: Vec<Vec<u8>>,
+    down_since: Option<u64>, // 链路断开的时刻, 链路正常时为 None
+    timed_out: bool,         // 这次断开已经报告过 DownTimeout
+    io: DeviceIoStats,
+}
+
+/**
+ * pump 与设备之间的批量收发: 调用批量方法的次数和经过的帧数
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceIoStats {
+    pub rx_batches: u64,
+    pub rx_frames: u64,
+    pub tx_batches: u64,
+    pub tx_frames: u64,
+}
+
+impl Port {
+    /**
+     * 载荷先补齐到最小长度再交给设备, 接口设置为 Padding::Strict 时也发得出去
+     */
+    fn send_frame(&mut self, d_mac: [u8; 6], ether_type: u16, mut payload: Vec<u8>) {
+        pad_payload(&mut payload);
+        let frame = self.iface.frame(d_mac, ether_type, payload);
+        let bytes = self.iface.transmit(&frame).expect("payload padded to the minimum");
+        self.tx.push(bytes);
+    }
+}
+
+/**
+ * 多个以太网接口和它们之间的 IP 层
+ * 出接口只由路由表决定, 添加接口时为它的每个地址添加直连路由; ARP 缓存和等待解析的队列按接口分开
+ * 发给本机的数据报按 host_model 判断, 交给上层前放在 take_delivered 里; 打开 forwarding 时转发其余的数据报
+ * 设置了 NAT 时, 从其它接口转发到出接口的数据报改写成公网地址, 出接口上收到的应答按映射改写回内网地址再转发
+ */
+#[derive(Debug)]
+pub struct InterfaceSet {
+    ports: Vec<Port>,
+    routes: RoutingTable,
+    ipv4: Ipv4Config,
+    arp: ArpConfig,
+    next_poll: usize, // 下一轮 poll 最先服务的接口
+    delivered: Vec<(Ipv4Datagram, Arrival)>,
+    link_events: Vec<LinkEvent>,
+    nat: Option<(InterfaceId, Nat44)>, // 做地址转换的出接口和它的映射表
+    drops: DropCounters,
+    tracer: Tracer,
+}
+
+impl InterfaceSet {
+    pub fn new(ipv4: &Ipv4Config, arp: &ArpConfig) -> Self {
+        InterfaceSet {
+            ports: vec![],
+            routes: RoutingTable::new(),
+            ipv4: ipv4.clone(),
+            arp: arp.clone(),
+            next_poll: 0,
+            delivered: vec![],
+            link_events: vec![],
+            nat: None,
+            drops: DropCounters::new(),
+            tracer: Tracer::default(),
+        }
+    }
+
+    /**
+     * 各接口收发的帧和 IP 层的丢弃交给 sink, 之后添加的接口也一样; None 取消
+     */
+    pub fn set_trace_sink(&mut self, sink: Option<SharedTraceSink>) {
+        for port in &mut self.ports {
+            port.iface.set_trace_sink(sink.clone());
+        }
+        self.tracer = Tracer::new(sink);
+    }
+
+    /**
+     * 添加接口, 编号按添加顺序分配并写回接口
+     */
+    pub fn add_interface(&mut self, mut iface: EthernetInterface) -> InterfaceId {
+        let id = self.ports.len();
+        iface.set_id(id);
+        if self.tracer.is_set() {
+            iface.set_trace_sink(self.tracer.sink());
+        }
+        for (addr, prefix_len) in iface.addresses().to_vec() {
+            self.routes.add_on(addr, prefix_len, None, id);
+        }
+        self.ports.push(Port {
+            iface,
+            arp: ArpCache::new(&self.arp),
+            pending: ArpPendingQueue::new(&self.arp, &self.ipv4),
+            rx: VecDeque::new(),
+            tx: vec![],
+            down_since: None,
+            timed_out: false,
+            io: DeviceIoStats::default(),
+        });
+        id
+    }
+
+    /**
+     * 设备报告的链路状态; 新添加的接口认为链路正常
+     */
+    pub fn is_up(&self, id: InterfaceId) -> bool {
+        self.ports.get(id).is_some_and(|port| port.down_since.is_none())
+    }
+
+    /**
+     * 设备报告链路状态变化, 状态没有变化时什么也不做
+     * 断开时丢弃还没有处理和还没有交给设备的帧, 经过它的路由不再使用;
+     * 恢复时为接口的每个地址发一个免费 ARP, 让邻居更新缓存
+     */
+    pub fn set_link(&mut self, id: InterfaceId, up: bool, now_ms: u64) {
+        let Some(port) = self.ports.get_mut(id) else { return };
+        if up == port.down_since.is_none() {
+            return;
+        }
+        if up {
+            port.down_since = None;
+            port.timed_out = false;
+            for (addr, _) in port.iface.addresses().to_vec() {
+                let packet = ArpPacket::request(port.iface.mac(), addr, addr);
+                port.send_frame([0xff; 6], ETHER_TYPE_ARP, packet.serialize());
+            }
+            self.link_events.push(LinkEvent::Up(id));
+        } else {
+            port.down_since = Some(now_ms);
+            port.rx.clear();
+            port.tx.clear();
+            self.link_events.push(LinkEvent::Down(id));
+        }
+    }
+
+    /**
+     * 取走链路状态变化
+     */
+    pub fn take_link_events(&mut self) -> Vec<LinkEvent> {
+        std::mem::take(&mut self.link_events)
+    }
+
+    /**
+     * 不考虑链路状态时, 发往 d_addr 的数据报是否从接口 id 出去; 用于找出受链路断开影响的连接
+     */
+    pub fn reaches_via(&self, d_addr: u32, id: InterfaceId) -> bool {
+        self.routes.lookup(d_addr).is_some_and(|route| route.interface == id)
+    }
+
+    pub fn interface(&self, id: InterfaceId) -> Option<&EthernetInterface> {
+        self.ports.get(id).map(|port| &port.iface)
+    }
+
+    pub fn arp_cache(&self, id: InterfaceId) -> Option<&ArpCache> {
+        self.ports.get(id).map(|port| &port.arp)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ports.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ports.is_empty()
+    }
+
+    pub fn routes(&self) -> &RoutingTable {
+        &self.routes
+    }
+
+    pub fn routes_mut(&mut self) -> &mut RoutingTable {
+        &mut self.routes
+    }
+
+    /**
+     * 在出接口 outside 上做地址转换 (masquerade), 只对转发的数据报生效, 需要打开 forwarding
+     * config.public_ip 通常就是 outside 的地址: 没有映射的数据报照常交给本机
+     */
+    pub fn set_nat(&mut self, outside: InterfaceId, config: NatConfig) -> Result<(), ConfigError> {
+        self.nat = Some((outside, Nat44::new(config)?));
+        Ok(())
+    }
+
+    pub fn nat(&self) -> Option<&Nat44> {
+        self.nat.as_ref().map(|(_, nat)| nat)
+    }
+
+    pub fn drop_counters(&self) -> &DropCounters {
+        &self.drops
+    }
+
+    /**
+     * 目的地址 d_addr 从 arrival 进来时是否发给本机
+     * 回环上的数据报总是; 受限广播在任何接口上都是
+     */
+    pub fn is_local(&self, d_addr: u32, arrival: Arrival) -> bool {
+        if arrival.loopback || d_addr == BROADCAST {
+            return true;
+        }
+        match self.ipv4.host_model {
+            HostModel::Weak => self.ports.iter().any(|port| port.iface.is_local(d_addr)),
+            HostModel::Strong => self.ports.get(arrival.interface).is_some_and(|port| port.iface.is_local(d_addr)),
+        }
+    }
+
+    /**
+     * 按路由表选择出口, 跳过链路断开的接口上的路由; 没有可用路由时为 None
+     */
+    pub fn egress(&self, d_addr: u32) -> Option<Hop> {
+        let route = self.routes.lookup_with(d_addr, |route| self.is_up(route.interface))?;
+        let port = &self.ports[route.interface];
+        Some(Hop {
+            interface: route.interface,
+            next_hop: route.gateway.unwrap_or(d_addr),
+            source: port.iface.select_source(d_addr, &self.routes),
+        })
+    }
+
+    /**
+     * 设备交上来的一帧, 在下一次 poll 时处理; 链路断开时丢弃
+     */
+    pub fn frame_received(&mut self, id: InterfaceId, bytes: Vec<u8>) {
+        if let Some(port) = self.ports.get_mut(id).filter(|port| port.down_since.is_none()) {
+            port.rx.push_back(bytes);
+        }
+    }
+
+    /**
+     * 处理至多 budget 个收到的帧, 各接口轮流每次一个, 起点每轮后移一个接口
+     * 一个接口的突发流量不会让其他接口的帧一直等待; 返回处理的帧数
+     */
+    pub fn poll(&mut self, budget: usize, now_ms: u64) -> usize {
+        let count = self.ports.len();
+        let mut done = 0;
+        let mut idle = 0;
+        let mut id = self.next_poll;
+        while done < budget && idle < count {
+            match self.ports[id].rx.pop_front() {
+                Some(bytes) => {
+                    self.process(id, &bytes, now_ms);
+                    done += 1;
+                    idle = 0;
+                }
+                None => idle += 1,
+            }
+            id = (id + 1) % count;
+        }
+        if count > 0 {
+            self.next_poll = (self.next_poll + 1) % count;
+        }
+        done
+    }
+
+    /**
+     * 取走要交给接口 id 的设备的帧; 链路断开期间产生的帧 (如 ARP 重试) 直接丢弃
+     */
+    pub fn take_tx(&mut self, id: InterfaceId) -> Vec<Vec<u8>> {
+        let Some(port) = self.ports.get_mut(id) else { return vec![] };
+        let tx = std::mem::take(&mut port.tx);
+        if port.down_since.is_some() { vec![] } else { tx }
+    }
+
+    /**
+     * 与接口 id 的设备交换帧: 一次批量收下至多 max_rx 帧放进接收队列, 再一次批量发出待发的帧
+     * 设备没有收下的帧按原来的顺序留在队列里, 下次再发; 返回 (收到的帧数, 发出的帧数)
+     */
+    pub fn pump(&mut self, id: InterfaceId, device: &mut dyn NetworkDevice, max_rx: usize) -> (usize, usize) {
+        let Some(port) = self.ports.get_mut(id) else { return (0, 0) };
+        let mut frames = vec![];
+        let received = device.receive_batch(&mut frames, max_rx);
+        port.io.rx_batches += 1;
+        port.io.rx_frames += received as u64;
+        match port.down_since {
+            None => port.rx.extend(frames),
+            Some(_) => port.tx.clear(), // 链路断开: 收到的帧和待发的帧都丢弃, 同 frame_received 和 take_tx
+        }
+        if port.tx.is_empty() {
+            return (received, 0);
+        }
+        let pending: Vec<&[u8]> = port.tx.iter().map(Vec::as_slice).collect();
+        let sent = device.transmit_batch(&pending);
+        port.tx.drain(..sent);
+        port.io.tx_batches += 1;
+        port.io.tx_frames += sent as u64;
+        (received, sent)
+    }
+
+    pub fn io_stats(&self, id: InterfaceId) -> Option<DeviceIoStats> {
+        self.ports.get(id).map(|port| port.io)
+    }
+
+    /**
+     * 取走发给本机的数据报和它们到达的接口
+     */
+    pub fn take_delivered(&mut self) -> Vec<(Ipv4Datagram, Arrival)> {
+        std::mem::take(&mut self.delivered)
+    }
+
+    /**
+     * 发出本机的数据报, 返回出接口; 下一跳还没有解析时排队等待
+     */
+    pub fn send(&mut self, datagram: Ipv4Datagram, conn_hint: Option<ConnectionId>, now_ms: u64) -> Result<InterfaceId, DropReason> {
+        self.route_out(datagram, Origin::Local { conn_hint }, now_ms)
+    }
+
+    /**
+     * 推进各接口的 ARP 定时器; 解析失败时转发的数据报由这里回 ICMP 主机不可达, 本机的通知返回给上层
+     * 配置了 link_down_abort_ms 时, 链路断开超过这个时间的接口报告一次 DownTimeout
+     */
+    pub fn tick(&mut self, now_ms: u64) -> Vec<ArpFailure> {
+        if let Some((_, nat)) = self.nat.as_mut() {
+            nat.tick(now_ms);
+        }
+        let mut notices = vec![];
+        for (id, port) in self.ports.iter_mut().enumerate() {
+            if let (Some(since), Some(limit)) = (port.down_since, self.ipv4.link_down_abort_ms) {
+                if !port.timed_out && now_ms.saturating_sub(since) >= limit {
+                    port.timed_out = true;
+                    self.link_events.push(LinkEvent::DownTimeout(id));
+                }
+            }
+            port.arp.tick(now_ms);
+            for ip in port.arp.take_unreachable() {
+                notices.extend(port.pending.failed(ip, port.iface.primary(), now_ms));
+            }
+        }
+        for id in 0..self.ports.len() {
+            self.flush_arp_requests(id);
+        }
+        let mut local = vec![];
+        for notice in notices {
+            match notice {
+                ArpFailure::Icmp(error) => {
+                    let _ = self.send(error, None, now_ms);
+                }
+                notice => local.push(notice),
+            }
+        }
+        local
+    }
+
+    /**
+     * 最早需要 tick 的时刻: 各接口的 ARP 定时器和链路断开的超时, 都没有时为 None
+     */
+    pub fn next_deadline_ms(&mut self) -> Option<u64> {
+        let limit = self.ipv4.link_down_abort_ms;
+        self.ports.iter_mut()
+            .flat_map(|port| {
+                let down = port.down_since.filter(|_| !port.timed_out).zip(limit).map(|(since, limit)| since.saturating_add(limit));
+                [port.arp.next_deadline_ms(), down]
+            })
+            .flatten()
+            .min()
+    }
+
+    fn process(&mut self, id: InterfaceId, bytes: &[u8], now_ms: u64) {
+        let port = &mut self.ports[id];
+        let Some(frame) = port.iface.receive(bytes) else { return };
+        let d_mac = frame.d_mac();
+        if d_mac != port.iface.mac() && d_mac[0] & 1 == 0 {
+            self.drop_packet(DropReason::NotForUs, bytes.len());
+            return;
+        }
+        match frame.ether_type() {
+            ETHER_TYPE_ARP => {
+                let Ok(packet) = ArpPacket::try_deserialize(frame.unpadded_payload()) else {
+                    self.drop_packet(DropReason::ParseError, bytes.len());
+                    return;
+                };
+                port.arp.on_arp_packet(&packet, now_ms);
+                if let Some(reply) = port.iface.answer_arp(&packet) {
+                    port.send_frame(packet.s_mac, ETHER_TYPE_ARP, reply.serialize());
+                }
+                for datagram in port.pending.resolved(packet.s_ip) {
+                    self.transmit(id, packet.s_mac, &datagram);
+                }
+            }
+            ETHER_TYPE_IPV4 => {
+                let Ok(datagram) = Ipv4Datagram::try_deserialize(frame.unpadded_payload()) else {
+                    self.drop_packet(DropReason::ParseError, bytes.len());
+                    return;
+                };
+                if !port.iface.verify_datagram(&datagram) {
+                    return;
+                }
+                port.arp.confirm(datagram.s_addr(), frame.s_mac(), now_ms);
+                self.ip_received(datagram, Arrival { interface: id, loopback: false }, now_ms);
+            }
+            _ => {
+                self.drop_packet(DropReason::NotForUs, bytes.len());
+            }
+        }
+    }
+
+    fn ip_received(&mut self, mut datagram: Ipv4Datagram, arrival: Arrival, now_ms: u64) {
+        self.nat_inbound(&mut datagram, arrival, now_ms);
+        if self.is_local(datagram.d_addr(), arrival) {
+            self.delivered.push((datagram, arrival));
+            return;
+        }
+        if !self.ipv4.forwarding {
+            self.drop_packet(DropReason::NotForUs, datagram.wire_size());
+            return;
+        }
+        // 收到时的样子, 供差错报文带回
+        let Ok(original) = Ipv4Datagram::deserialize(&datagram.serialize()) else {
+            self.drop_packet(DropReason::ParseError, datagram.wire_size());
+            return;
+        };
+        if !datagram.decrement_ttl() {
+            self.drop_packet(DropReason::TtlExpired, datagram.wire_size());
+            return;
+        }
+        if !self.nat_outbound(&mut datagram, arrival, now_ms) {
+            self.drop_packet(DropReason::Untranslatable, datagram.wire_size());
+            return;
+        }
+        let _ = self.route_out(datagram, Origin::Forwarded { original }, now_ms);
+    }
+
+    /**
+     * 出接口上收到的、发往公网地址且有映射的数据报, 改写回内网的地址和端口; 其余的不动
+     */
+    fn nat_inbound(&mut self, datagram: &mut Ipv4Datagram, arrival: Arrival, now_ms: u64) {
+        let Some((outside, nat)) = self.nat.as_mut() else { return };
+        if !self.ipv4.forwarding || arrival.loopback || arrival.interface != *outside || datagram.d_addr() != nat.public_ip() {
+            return;
+        }
+        let mut packet = datagram.serialize();
+        if nat.inbound(&mut packet, now_ms).is_ok() {
+            if let Ok(translated) = Ipv4Datagram::try_deserialize(&packet) {
+                *datagram = translated;
+            }
+        }
+    }
+
+    /**
+     * 从其它接口进来、要从出接口转发出去的数据报改写源地址和端口; 不能转换时返回 false
+     */
+    fn nat_outbound(&mut self, datagram: &mut Ipv4Datagram, arrival: Arrival, now_ms: u64) -> bool {
+        let egress = self.egress(datagram.d_addr()).map(|hop| hop.interface);
+        let Some((outside, nat)) = self.nat.as_mut() else { return true };
+        if arrival.interface == *outside || egress != Some(*outside) {
+            return true;
+        }
+        let mut packet = datagram.serialize();
+        if nat.outbound(&mut packet, now_ms).is_err() {
+            return false;
+        }
+        match Ipv4Datagram::try_deserialize(&packet) {
+            Ok(translated) => {
+                *datagram = translated;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn route_out(&mut self, datagram: Ipv4Datagram, origin: Origin, now_ms: u64) -> Result<InterfaceId, DropReason> {
+        let Some(hop) = self.egress(datagram.d_addr()) else {
+            self.drop_packet(DropReason::NoRoute, datagram.wire_size());
+            return Err(DropReason::NoRoute);
+        };
+        let port = &mut self.ports[hop.interface];
+        match port.arp.resolution(hop.next_hop, now_ms) {
+            Resolution::Resolved(mac) => self.transmit(hop.interface, mac, &datagram),
+            Resolution::Pending => {
+                let bytes = datagram.wire_size();
+                if !port.pending.push(hop.next_hop, datagram, origin) {
+                    self.drop_packet(DropReason::QueueFull, bytes);
+                }
+            }
+            Resolution::Unreachable => {
+                port.pending.push(hop.next_hop, datagram, origin);
+                let notices = port.pending.failed(hop.next_hop, port.iface.primary(), now_ms);
+                for notice in notices {
+                    if let ArpFailure::Icmp(error) = notice {
+                        let _ = self.send(error, None, now_ms);
+                    }
+                }
+            }
+        }
+        self.flush_arp_requests(hop.interface);
+        Ok(hop.interface)
+    }
+
+    /**
+     * 下一跳已经解析为 mac, 封装成帧交给设备
+     */
+    fn transmit(&mut self, id: InterfaceId, mac: [u8; 6], datagram: &Ipv4Datagram) {
+        let port = &mut self.ports[id];
+        port.send_frame(mac, ETHER_TYPE_IPV4, datagram.serialize());
+    }
+
+    fn drop_packet(&mut self, reason: DropReason, bytes: usize) {
+        let total = self.drops.record(reason);
+        self.tracer.emit(|sink| sink.on_drop(reason, bytes, total));
+    }
+
+    /**
+     * ARP 缓存要发的请求, 发送方地址取与目标同一子网的本地地址
+     */
+    fn flush_arp_requests(&mut self, id: InterfaceId) {
+        let port = &mut self.ports[id];
+        for request in port.arp.take_requests() {
+            let s_ip = port.iface.on_link_source(request.target_ip).unwrap_or(port.iface.primary());
+            let packet = ArpPacket::request(port.iface.mac(), s_ip, request.target_ip);
+            port.send_frame(request.d_mac, ETHER_TYPE_ARP, packet.serialize());
+        }
+    }
+}
@@ -0,0 +1,232 @@
+/**
+ * IPv4 首部选项: Record Route (RFC 791 3.1) 和 Internet Timestamp
+ * 发送方按需生成空槽位, 途经的转发者在槽位未用完时填入自己的地址/时间戳
+ */
+pub const OPT_END: u8 = 0;
+pub const OPT_NOP: u8 = 1;
+pub const OPT_RECORD_ROUTE: u8 = 7;
+pub const OPT_TIMESTAMP: u8 = 68;
+
+pub const MAX_OPTIONS_LEN: usize = 40;
+pub const RECORD_ROUTE_SLOTS: usize = 9;
+
+/**
+ * Timestamp 选项的 flag 字段, 预先指定地址的方式(3)不支持生成
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampFlag {
+    TimestampOnly,   // 0: 只记录时间戳
+    WithAddress,     // 1: 地址 + 时间戳
+}
+
+impl TimestampFlag {
+    pub fn code(&self) -> u8 {
+        match self {
+            TimestampFlag::TimestampOnly => 0,
+            TimestampFlag::WithAddress => 1,
+        }
+    }
+
+    fn slot_len(&self) -> usize {
+        match self {
+            TimestampFlag::TimestampOnly => 4,
+            TimestampFlag::WithAddress => 8,
+        }
+    }
+}
+
+/**
+ * 发送时请求的 IP 选项
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SendOptions {
+    pub record_route: bool,
+    pub ip_timestamp: Option<TimestampFlag>,
+}
+
+impl SendOptions {
+    pub fn is_empty(&self) -> bool {
+        !self.record_route && self.ip_timestamp.is_none()
+    }
+
+    /**
+     * 生成选项字节, 指针指向第一个空槽, 末尾补 0 到 4 字节对齐
+     * 单独的 Record Route 有 9 个槽位; 同时请求两种选项时平分 40 字节
+     */
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(MAX_OPTIONS_LEN);
+        let rr_slots = match (self.record_route, self.ip_timestamp) {
+            (false, _) => 0,
+            (true, None) => RECORD_ROUTE_SLOTS,
+            (true, Some(_)) => 4,
+        };
+        if rr_slots > 0 {
+            bytes.extend_from_slice(&[OPT_RECORD_ROUTE, (3 + 4 * rr_slots) as u8, 4]);
+            bytes.resize(3 + 4 * rr_slots, 0);
+        }
+        if let Some(flag) = self.ip_timestamp {
+            let room = MAX_OPTIONS_LEN - bytes.len().next_multiple_of(4);
+            bytes.resize(bytes.len().next_multiple_of(4), OPT_NOP);
+            let slots = (room - 4) / flag.slot_len();
+            let len = 4 + slots * flag.slot_len();
+            bytes.extend_from_slice(&[OPT_TIMESTAMP, len as u8, 5, flag.code()]);
+            bytes.resize(bytes.len() + slots * flag.slot_len(), 0);
+        }
+        bytes.resize(bytes.len().next_multiple_of(4), OPT_END);
+        bytes
+    }
+}
+
+/**
+ * 从选项中读出的记录, 只包含已经填入的槽位
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordedOptions {
+    pub route: Vec<u32>,
+    pub timestamps: Vec<(Option<u32>, u32)>, // (地址, 午夜起的毫秒数)
+    pub timestamp_overflow: u8,               // 没有空槽而未能记录的转发者个数
+}
+
+impl RecordedOptions {
+    pub fn is_empty(&self) -> bool {
+        self.route.is_empty() && self.timestamps.is_empty() && self.timestamp_overflow == 0
+    }
+}
+
+/**
+ * 遍历选项, 对每个选项调用 f(起始偏移, 长度); 长度非法时停止
+ */
+fn walk(options: &[u8], mut f: impl FnMut(usize, usize)) {
+    let mut i = 0;
+    while i < options.len() {
+        match options[i] {
+            OPT_END => return,
+            OPT_NOP => i += 1,
+            _ => {
+                let Some(&len) = options.get(i + 1) else { return };
+                let len = len as usize;
+                if len < 2 || i + len > options.len() {
+                    return;
+                }
+                f(i, len);
+                i += len;
+            }
+        }
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/**
+ * 解析 Record Route 和 Timestamp 中已填入的内容, 其他选项跳过
+ */
+pub fn parse(options: &[u8]) -> RecordedOptions {
+    let mut recorded = RecordedOptions::default();
+    walk(options, |start, len| {
+        let opt = &options[start..start + len];
+        match opt[0] {
+            OPT_RECORD_ROUTE if len >= 3 => {
+                let end = (opt[2] as usize).saturating_sub(1).min(len);
+                recorded.route.extend(opt.get(3..end).unwrap_or(&[]).chunks_exact(4).map(read_u32));
+            }
+            OPT_TIMESTAMP if len >= 4 => {
+                let end = (opt[2] as usize).saturating_sub(1).min(len);
+                let filled = opt.get(4..end).unwrap_or(&[]);
+                recorded.timestamp_overflow = opt[3] >> 4;
+                match opt[3] & 0x0f {
+                    0 => recorded.timestamps.extend(filled.chunks_exact(4).map(|ts| (None, read_u32(ts)))),
+                    _ => recorded.timestamps.extend(filled.chunks_exact(8).map(|slot| (Some(read_u32(slot)), read_u32(&slot[4..])))),
+                }
+            }
+            _ => {}
+        }
+    });
+    recorded
+}
+
+/**
+ * 转发者在选项中记录自己, 原地修改, 返回是否改动了选项
+ * 指针超过长度(槽位用完)时 Record Route 原样保留, Timestamp 只增加溢出计数(到 15 为止)
+ */
+pub fn record(options: &mut [u8], addr: u32, now_ms: u64) -> bool {
+    let timestamp = (now_ms % 86_400_000) as u32;
+    let mut spans = vec![];
+    walk(options, |start, len| spans.push((start, len)));
+    let mut changed = false;
+    for (start, len) in spans {
+        let opt = &mut options[start..start + len];
+        match opt[0] {
+            OPT_RECORD_ROUTE if len >= 3 => {
+                let ptr = opt[2] as usize;
+                if ptr >= 4 && ptr + 3 <= len {
+                    opt[ptr - 1..ptr + 3].copy_from_slice(&addr.to_be_bytes());
+                    opt[2] += 4;
+                    changed = true;
+                }
+            }
+            OPT_TIMESTAMP if len >= 4 => {
+                let ptr = opt[2] as usize;
+                let slot = if opt[3] & 0x0f == 0 { 4 } else { 8 };
+                if ptr >= 5 && ptr + slot - 1 <= len {
+                    if slot == 8 {
+                        opt[ptr - 1..ptr + 3].copy_from_slice(&addr.to_be_bytes());
+                    }
+                    opt[ptr + slot - 5..ptr + slot - 1].copy_from_slice(&timestamp.to_be_bytes());
+                    opt[2] += slot as u8;
+                    changed = true;
+                } else if opt[3] >> 4 < 15 {
+                    opt[3] += 0x10;
+                    changed = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_sizes() {
+        let rr = SendOptions { record_route: true, ip_timestamp: None }.encode();
+        assert_eq!(rr.len(), 40);
+        assert_eq!(&rr[..3], &[OPT_RECORD_ROUTE, 39, 4]);
+
+        let ts = SendOptions { record_route: false, ip_timestamp: Some(TimestampFlag::WithAddress) }.encode();
+        assert_eq!(&ts[..4], &[OPT_TIMESTAMP, 36, 5, 1]);
+        assert_eq!(ts.len(), 36);
+
+        let both = SendOptions { record_route: true, ip_timestamp: Some(TimestampFlag::TimestampOnly) }.encode();
+        assert_eq!(both.len(), 40);
+        assert_eq!(&both[..3], &[OPT_RECORD_ROUTE, 19, 4]);
+        assert_eq!(&both[19..24], &[OPT_NOP, OPT_TIMESTAMP, 20, 5, 0]);
+        assert!(SendOptions::default().encode().is_empty());
+        assert!(parse(&both).is_empty());
+    }
+
+    #[test]
+    fn test_record_until_full() {
+        let mut options = SendOptions { record_route: true, ip_timestamp: Some(TimestampFlag::WithAddress) }.encode();
+        for hop in 1..=4u32 {
+            assert!(record(&mut options, 0x0a000000 + hop, 1000 * hop as u64));
+        }
+        let recorded = parse(&options);
+        assert_eq!(recorded.route, vec![0x0a000001, 0x0a000002, 0x0a000003, 0x0a000004]);
+        assert_eq!(recorded.timestamps, vec![(Some(0x0a000001), 1000), (Some(0x0a000002), 2000)]);
+        assert_eq!(recorded.timestamp_overflow, 2);
+
+        // 槽位用完后 Record Route 不再改动, 溢出计数到 15 为止
+        let full_rr = options[..19].to_vec();
+        for _ in 0..20 {
+            record(&mut options, 0x0a0000ff, 0);
+        }
+        assert_eq!(&options[..19], &full_rr[..]);
+        assert_eq!(parse(&options).timestamp_overflow, 15);
+        assert!(!record(&mut options, 0x0a0000ff, 0));
+    }
+}
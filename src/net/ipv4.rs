@@ -1,6 +1,90 @@
-use crate::utils::checksum;
+use std::error::Error;
+use std::fmt;
+
+use crate::error::{ParseError, SendError, SerializeError};
+use crate::net::dscp::Dscp;
+use crate::net::ip_options;
+use crate::net::raw_socket::IpProtocol;
+use crate::utils::{checksum, trans_bytes};
+use crate::utils::wire::{self, ParseStrictness, WireDeserialize, WireSerialize};
+
+pub const MAX_HDR_LEN: usize = 60; // ihl 最大为 15
+pub const MAX_PAYLOAD_LEN: usize = u16::MAX as usize - 20; // 不带选项时 total_len 能表示的最大载荷
+pub const DEFAULT_TTL: u8 = 64;
+
+/**
+ * IPv4 数据报解析错误
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipv4ParseError {
+    TooShort { len: usize },
+    BadHeaderLength { ihl: u8, available: usize },
+    BadTotalLength { total_len: u16, hdr_len: usize, available: usize },
+    BadVersion { version: u8 },
+    ReservedBits { bits: u8 },
+}
+
+impl fmt::Display for Ipv4ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ipv4ParseError::TooShort { len } => {
+                write!(f, "datagram too short: {} bytes, header needs at least 20", len)
+            }
+            Ipv4ParseError::BadHeaderLength { ihl, available } => {
+                write!(f, "invalid ihl {} (offset 0) for {} available bytes", ihl, available)
+            }
+            Ipv4ParseError::BadTotalLength { total_len, hdr_len, available } => {
+                write!(f, "invalid total_len {} (offset 2): header is {} bytes, {} bytes available", total_len, hdr_len, available)
+            }
+            Ipv4ParseError::BadVersion { version } => write!(f, "invalid version {} (offset 0)", version),
+            Ipv4ParseError::ReservedBits { bits } => write!(f, "reserved flag bits {:#03b} set (offset 6)", bits),
+        }
+    }
+}
+
+impl Error for Ipv4ParseError {}
+
+/**
+ * Ipv4DatagramBuilder::build 拒绝的输入
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ipv4BuildError {
+    MissingProtocol,
+    PayloadTooLarge { len: usize, max: usize },
+    ZeroTtl,
+}
+
+impl fmt::Display for Ipv4BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ipv4BuildError::MissingProtocol => write!(f, "protocol is not set"),
+            Ipv4BuildError::PayloadTooLarge { len, max } => write!(f, "payload of {} bytes exceeds the maximum of {}", len, max),
+            Ipv4BuildError::ZeroTtl => write!(f, "ttl must not be 0"),
+        }
+    }
+}
+
+impl Error for Ipv4BuildError {}
+
+/**
+ * 数据报首部中上层关心的字段, 交给原始套接字的接收者
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ipv4Header {
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::addr::serde_ipv4"))]
+    pub s_addr: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::addr::serde_ipv4"))]
+    pub d_addr: u32,
+    pub protocol: u8,
+    pub ttl: u8,
+    pub tos: u8,
+    pub id: u16,
+    pub options: Vec<u8>,
+}
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ipv4Datagram {
     version: u8, // 4bits
     ihl: u8,     // 4bits, 单位32bits
@@ -12,10 +96,11 @@ pub struct Ipv4Datagram {
     ttl: u8,
     protocol: u8,
     hdr_checksum: u16,
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::addr::serde_ipv4"))]
     s_addr: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::addr::serde_ipv4"))]
     d_addr: u32,
-    // 省略options字段
-    // 省略padding, 字节流中给头部字段补齐到 32bits 的倍数
+    options: Vec<u8>, // 原样保留的options(含padding), 长度为 ihl * 4 - 20
     payload: Vec<u8>, // 载荷
 }
 
@@ -25,36 +110,279 @@ impl Ipv4Datagram {
      * 传入除了校验和以外的所有字段
      */
     pub fn new(version: u8, ihl: u8, tos: u8, toltal_len: u16, id: u16, flag: u8, frag_offset: u16, ttl: u8, protocol: u8,  s_addr: u32, d_addr: u32, payload: Vec<u8>) -> Self{
-       let mut new_ins =  Ipv4Datagram {version, ihl, tos, toltal_len, id, flag, frag_offset, ttl, protocol, hdr_checksum: 0, s_addr, d_addr, payload };
+       let mut new_ins =  Ipv4Datagram {version, ihl, tos, toltal_len, id, flag, frag_offset, ttl, protocol, hdr_checksum: 0, s_addr, d_addr, options: vec![], payload };
        new_ins.generate_hdr_checksum();
        return new_ins;
     }
 
 
-    pub fn deserialize(bytes:Vec<u8>) -> Ipv4Datagram{
+    pub fn try_deserialize(bytes: &[u8]) -> Result<Ipv4Datagram, Ipv4ParseError> {
+        Self::try_deserialize_with(bytes, ParseStrictness::Loose)
+    }
+
+    pub fn try_deserialize_with(bytes: &[u8], strictness: ParseStrictness) -> Result<Ipv4Datagram, Ipv4ParseError> {
+        let mut datagram = Self::parse_header(bytes, strictness)?;
+        let hdr_len = (datagram.ihl as usize) * 4;
+        datagram.payload = bytes[hdr_len..(datagram.toltal_len as usize)].to_vec(); // total_len 之后是链路层补齐的字节
+        Ok(datagram)
+    }
+
+    /**
+     * 接管以太网帧的载荷, 原地去掉首部和链路层补齐, 剩下的部分直接作为 payload
+     */
+    pub fn from_payload_vec(bytes: Vec<u8>) -> Result<Ipv4Datagram, Ipv4ParseError> {
+        Self::from_payload_vec_with(bytes, ParseStrictness::Loose)
+    }
+
+    pub fn from_payload_vec_with(mut bytes: Vec<u8>, strictness: ParseStrictness) -> Result<Ipv4Datagram, Ipv4ParseError> {
+        let mut datagram = Self::parse_header(&bytes, strictness)?;
+        let hdr_len = (datagram.ihl as usize) * 4;
+        trans_bytes::keep_range(&mut bytes, hdr_len, datagram.toltal_len as usize);
+        datagram.payload = bytes;
+        Ok(datagram)
+    }
+
+    /**
+     * 解析并校验首部, payload 留空由调用者填充
+     * Strict 时版本号不是 4 或保留标志位(RFC 3514 的 evil bit)非零都是错误
+     */
+    fn parse_header(bytes: &[u8], strictness: ParseStrictness) -> Result<Ipv4Datagram, Ipv4ParseError> {
         if bytes.len() < 20 { // IPv4头部的最小长度为20字节
-            panic!("Invalid IPv4 datagram: too short (should be longer than 20Bytes)");
+            return Err(Ipv4ParseError::TooShort { len: bytes.len() });
         }
 
         let version: u8 = bytes[0] >> 4;
         let ihl: u8 = bytes[0] & 0x0f;
+        let hdr_len: usize = (ihl as usize) * 4;
+        if ihl < 5 || hdr_len > bytes.len() {
+            return Err(Ipv4ParseError::BadHeaderLength { ihl, available: bytes.len() });
+        }
         let tos: u8 = bytes[1];
         let toltal_len: u16 = ((bytes[2] as u16) << 8) + (bytes[3] as u16);
+        if (toltal_len as usize) < hdr_len || (toltal_len as usize) > bytes.len() {
+            return Err(Ipv4ParseError::BadTotalLength { total_len: toltal_len, hdr_len, available: bytes.len() });
+        }
         let id: u16 =  ((bytes[4] as u16) << 8) + (bytes[5] as u16);
         let flag: u8 = bytes[6] >> 5;
+        if strictness.is_strict() && version != 4 {
+            return Err(Ipv4ParseError::BadVersion { version });
+        }
+        if strictness.is_strict() && flag & 0b100 != 0 {
+            return Err(Ipv4ParseError::ReservedBits { bits: flag >> 2 });
+        }
         let frag_offset: u16 = (((bytes[6] as u16) & 0b00011111) << 8) + (bytes[7] as u16);
         let ttl: u8 = bytes[8];
         let protocol: u8 = bytes[9];
         let hdr_checksum: u16 = ((bytes[10] as u16) << 8) + (bytes[11] as u16);
         let s_addr: u32 = ((bytes[12] as u32) << 24) + ((bytes[13] as u32) << 16) + ((bytes[14] as u32) << 8) + (bytes[15] as u32);
         let d_addr: u32 = ((bytes[16] as u32) << 24) + ((bytes[17] as u32) << 16) + ((bytes[18] as u32) << 8) + (bytes[19] as u32);
-        let payload :Vec<u8>= bytes[20..].to_vec();
+        let options: Vec<u8> = bytes[20..hdr_len].to_vec();
 
-        Ipv4Datagram {version, ihl, tos, toltal_len, id, flag, frag_offset, ttl, protocol, hdr_checksum, s_addr, d_addr, payload }
+        Ok(Ipv4Datagram {version, ihl, tos, toltal_len, id, flag, frag_offset, ttl, protocol, hdr_checksum, s_addr, d_addr, options, payload: Vec::new() })
+    }
+
+    /**
+     * 解析失败时 panic, 仅用于确定输入合法的场景
+     */
+    #[deprecated(note = "use WireDeserialize::deserialize")]
+    pub fn deserialize_unchecked(bytes: &[u8]) -> Ipv4Datagram {
+        match Self::try_deserialize(bytes) {
+            Ok(datagram) => datagram,
+            Err(e) => panic!("Invalid IPv4 datagram: {}", e),
+        }
     }
 
     // 成员方法
 
+    pub fn s_addr(&self) -> u32 {
+        self.s_addr
+    }
+
+    pub fn d_addr(&self) -> u32 {
+        self.d_addr
+    }
+
+    pub fn protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /**
+     * 交出载荷的所有权, 上层可以原地解析
+     */
+    pub fn into_payload(self) -> Vec<u8> {
+        self.payload
+    }
+
+    pub fn ttl(&self) -> u8 {
+        self.ttl
+    }
+
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    pub fn tos(&self) -> u8 {
+        self.tos
+    }
+
+    pub fn dscp(&self) -> Dscp {
+        Dscp::from_tos(self.tos)
+    }
+
+    /**
+     * 标志字段中的保留位, Loose 解析时原样保留
+     */
+    pub fn reserved_bits(&self) -> u8 {
+        self.flag >> 2
+    }
+
+    pub fn header(&self) -> Ipv4Header {
+        Ipv4Header {
+            s_addr: self.s_addr,
+            d_addr: self.d_addr,
+            protocol: self.protocol,
+            ttl: self.ttl,
+            tos: self.tos,
+            id: self.id,
+            options: self.options.clone(),
+        }
+    }
+
+    /**
+     * 替换载荷, 更新 total_len 和首部校验和; 载荷中的上层校验和由调用方负责
+     */
+    pub fn set_payload(&mut self, payload: Vec<u8>) {
+        self.toltal_len = (self.ihl as usize * 4 + payload.len()) as u16;
+        self.payload = payload;
+        self.generate_hdr_checksum();
+    }
+
+    /**
+     * 原样保留的选项字节(含 padding)
+     */
+    pub fn options(&self) -> &[u8] {
+        &self.options
+    }
+
+    /**
+     * 替换选项, 补齐到 4 字节并更新 ihl、total_len 和首部校验和
+     * 超过 40 字节的部分 ihl 无法表示, 直接截断
+     */
+    pub fn set_options(&mut self, mut options: Vec<u8>) {
+        options.truncate(MAX_HDR_LEN - 20);
+        options.resize(options.len().next_multiple_of(4), ip_options::OPT_END);
+        self.ihl = (5 + options.len() / 4) as u8;
+        self.toltal_len = (20 + options.len() + self.payload.len()) as u16;
+        self.options = options;
+        self.generate_hdr_checksum();
+    }
+
+    /**
+     * 转发者在 Record Route / Timestamp 选项中记录自己, 有改动时重新计算校验和
+     */
+    pub fn record_options(&mut self, addr: u32, now_ms: u64) -> bool {
+        let len = self.options_on_wire().len();
+        if !ip_options::record(&mut self.options[..len], addr, now_ms) {
+            return false;
+        }
+        self.generate_hdr_checksum();
+        true
+    }
+
+    /**
+     * 转发前 TTL 减一并重新计算首部校验和, TTL 已经耗尽时返回 false
+     */
+    pub fn decrement_ttl(&mut self) -> bool {
+        if self.ttl <= 1 {
+            return false;
+        }
+        self.ttl -= 1;
+        self.generate_hdr_checksum();
+        true
+    }
+
+    pub fn dont_fragment(&self) -> bool {
+        self.flag & 0b010 != 0
+    }
+
+    /**
+     * 设置或清除 DF 并重新计算首部校验和
+     */
+    pub fn set_dont_fragment(&mut self, df: bool) {
+        self.flag = if df { self.flag | 0b010 } else { self.flag & !0b010 };
+        self.generate_hdr_checksum();
+    }
+
+    pub fn more_fragments(&self) -> bool {
+        self.flag & 0b001 != 0
+    }
+
+    /**
+     * 分片在原数据报载荷中的字节偏移
+     */
+    pub fn fragment_offset(&self) -> usize {
+        self.frag_offset as usize * 8
+    }
+
+    pub fn is_fragment(&self) -> bool {
+        self.more_fragments() || self.frag_offset != 0
+    }
+
+    /**
+     * 按 mtu 切分成分片, 不超过 mtu 时原样返回一个
+     * 除最后一片外每片的载荷都是 8 的倍数; options 只保留在第一片
+     * 设置了 DF 的数据报不能分片
+     */
+    pub fn fragment(&self, mtu: u16) -> Result<Vec<Ipv4Datagram>, SendError> {
+        let options = self.options_on_wire();
+        let chunk = (mtu as usize).saturating_sub(20) & !7;
+        if self.wire_size() > mtu as usize && (self.dont_fragment() || chunk == 0) {
+            return Err(SendError::PayloadTooLarge { len: self.wire_size(), max: mtu as usize });
+        }
+        let mut fragments = vec![];
+        let mut offset = 0;
+        loop {
+            let hdr_len = if offset == 0 { 20 + options.len() } else { 20 };
+            let room = if offset == 0 { ((mtu as usize).saturating_sub(hdr_len) & !7).max(8) } else { chunk };
+            let end = (offset + room).min(self.payload.len());
+            let last = end == self.payload.len();
+            let flag = (self.flag & 0b110) | if last { self.flag & 0b001 } else { 0b001 };
+            let mut fragment = Ipv4Datagram {
+                version: self.version,
+                ihl: (hdr_len / 4) as u8,
+                tos: self.tos,
+                toltal_len: (hdr_len + end - offset) as u16,
+                id: self.id,
+                flag,
+                frag_offset: self.frag_offset + (offset / 8) as u16,
+                ttl: self.ttl,
+                protocol: self.protocol,
+                hdr_checksum: 0,
+                s_addr: self.s_addr,
+                d_addr: self.d_addr,
+                options: if offset == 0 { options.to_vec() } else { vec![] },
+                payload: self.payload[offset..end].to_vec(),
+            };
+            fragment.generate_hdr_checksum();
+            fragments.push(fragment);
+            if last {
+                return Ok(fragments);
+            }
+            offset = end;
+        }
+    }
+
+    /**
+     * 校验首部校验和
+     */
+    pub fn check_hdr_checksum(&self) -> bool {
+        checksum::check(&self.serialized_hdr())
+    }
+
     fn generate_hdr_checksum(&mut self) -> u16 {
         self.hdr_checksum = 0;
         let serialized_hdr = self.serialized_hdr();
@@ -65,17 +393,133 @@ impl Ipv4Datagram {
     }
 
     pub fn serialized_hdr(&self) -> Vec<u8> {
-        vec![(self.version << 4) + (self.ihl), 
+        let (bytes, len) = self.header_bytes();
+        bytes[..len].to_vec()
+    }
+
+    /**
+     * 首部写进定长数组, 返回数组和实际使用的长度, 不做堆分配
+     * 超过 40 字节的 options 无法用 ihl 表示, 序列化时丢弃
+     */
+    pub fn header_bytes(&self) -> ([u8; MAX_HDR_LEN], usize) {
+        // 各字段先按位宽截断再拼接, 构造时传入越界值也不会溢出
+        let mut bytes = [0u8; MAX_HDR_LEN];
+        bytes[..20].copy_from_slice(&[((self.version & 0x0f) << 4) | (self.ihl & 0x0f), 
              self.tos, 
              (self.toltal_len >> 8) as u8, self.toltal_len as u8, 
              (self.id >> 8) as u8, self.id as u8, 
-             (self.flag << 5) + ((self.frag_offset >> 10) as u8), self.frag_offset as u8,
+             ((self.flag & 0b111) << 5) | (((self.frag_offset >> 8) as u8) & 0b0001_1111), self.frag_offset as u8,
              self.ttl,
              self.protocol,
              (self.hdr_checksum >> 8) as u8, self.hdr_checksum as u8,
-             (self.s_addr >> 24) as u8, (self.s_addr >> 16) as u8, (self.s_addr >> 8) as u8, self.s_addr as u8]
+             (self.s_addr >> 24) as u8, (self.s_addr >> 16) as u8, (self.s_addr >> 8) as u8, self.s_addr as u8,
+             (self.d_addr >> 24) as u8, (self.d_addr >> 16) as u8, (self.d_addr >> 8) as u8, self.d_addr as u8]);
+        let options = self.options_on_wire();
+        bytes[20..20 + options.len()].copy_from_slice(options);
+        (bytes, 20 + options.len())
+    }
+
+    fn options_on_wire(&self) -> &[u8] {
+        &self.options[..self.options.len().min(MAX_HDR_LEN - 20)]
+    }
+
+}
+
+impl WireSerialize for Ipv4Datagram {
+    fn wire_size(&self) -> usize {
+        20 + self.options_on_wire().len() + self.payload.len()
     }
 
+    fn serialize_into(&self, buf: &mut [u8]) -> Result<usize, SerializeError> {
+        let size = self.wire_size();
+        wire::check_buffer(buf, size)?;
+        let (hdr, hdr_len) = self.header_bytes();
+        buf[..hdr_len].copy_from_slice(&hdr[..hdr_len]);
+        buf[hdr_len..size].copy_from_slice(&self.payload);
+        Ok(size)
+    }
+}
+
+impl WireDeserialize for Ipv4Datagram {
+    fn deserialize(bytes: &[u8]) -> Result<Self, ParseError> {
+        Ok(Self::try_deserialize(bytes)?)
+    }
+}
+
+/**
+ * 按字段名构造数据报, version、ihl、total_len 和校验和由 build 推出
+ * 没有指定时 TTL 为 64、DSCP 为 0、不设 DF; 标识默认为 0, 需要递增标识的调用者(如 ConnectionTable)自己传入
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Ipv4DatagramBuilder {
+    s_addr: u32,
+    d_addr: u32,
+    protocol: Option<IpProtocol>,
+    ttl: Option<u8>,
+    dscp: Dscp,
+    dont_fragment: bool,
+    id: u16,
+    payload: Vec<u8>,
+}
+
+impl Ipv4DatagramBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn source(mut self, addr: u32) -> Self {
+        self.s_addr = addr;
+        self
+    }
+
+    pub fn destination(mut self, addr: u32) -> Self {
+        self.d_addr = addr;
+        self
+    }
+
+    pub fn protocol(mut self, protocol: IpProtocol) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    pub fn ttl(mut self, ttl: u8) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn dscp(mut self, dscp: Dscp) -> Self {
+        self.dscp = dscp;
+        self
+    }
+
+    pub fn dont_fragment(mut self, df: bool) -> Self {
+        self.dont_fragment = df;
+        self
+    }
+
+    pub fn identification(mut self, id: u16) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn payload(mut self, payload: Vec<u8>) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    pub fn build(self) -> Result<Ipv4Datagram, Ipv4BuildError> {
+        let protocol = self.protocol.ok_or(Ipv4BuildError::MissingProtocol)?;
+        if self.payload.len() > MAX_PAYLOAD_LEN {
+            return Err(Ipv4BuildError::PayloadTooLarge { len: self.payload.len(), max: MAX_PAYLOAD_LEN });
+        }
+        let ttl = self.ttl.unwrap_or(DEFAULT_TTL);
+        if ttl == 0 {
+            return Err(Ipv4BuildError::ZeroTtl);
+        }
+        let flag = if self.dont_fragment { 0b010 } else { 0 };
+        Ok(Ipv4Datagram::new(4, 5, self.dscp.apply_to(0), (20 + self.payload.len()) as u16, self.id, flag, 0, ttl, protocol.number(),
+            self.s_addr, self.d_addr, self.payload))
+    }
 }
 
 
@@ -97,10 +541,13 @@ mod tests {
             0x7a, 0x7a, // checksum
             0x0a, 0x00, 0x00, 0x01, // s_addr
             0x0a, 0x00, 0x00, 0x02, // d_addr
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
         ];
 
-        let datagram = Ipv4Datagram::deserialize(bytes);
+        let datagram = Ipv4Datagram::try_deserialize(&bytes).unwrap();
         // 测试字段的正确性
         assert_eq!(datagram.version, 4);
         assert_eq!(datagram.ihl, 5); 
@@ -113,9 +560,121 @@ mod tests {
         assert_eq!(datagram.protocol, 6); // TCP
         assert_eq!(datagram.s_addr, 0x0a000001); // 10.0.0.1
         assert_eq!(datagram.d_addr, 0x0a000002); // 10.0.0.2
+        assert_eq!(datagram.payload.len(), 40);
+    }
+
+    #[test]
+    fn test_trailing_padding_trimmed() {
+        let datagram = Ipv4Datagram::new(4, 5, 0, 23, 1, 0, 0, 64, 6, 0x0a000001, 0x0a000002, vec![7, 8, 9]);
+        let mut bytes = datagram.serialize();
+        bytes.extend_from_slice(&[0xaa; 23]); // 链路层补齐
+
+        let parsed = Ipv4Datagram::try_deserialize(&bytes).unwrap();
+        assert_eq!(parsed.payload(), &[7, 8, 9]);
+
+        // total_len 超出可用字节
+        assert_eq!(
+            Ipv4Datagram::try_deserialize(&bytes[..22]).unwrap_err(),
+            Ipv4ParseError::BadTotalLength { total_len: 23, hdr_len: 20, available: 22 }
+        );
     }
 
 
+    #[test]
+    fn test_fragment() {
+        let payload: Vec<u8> = (0..3000u32).map(|i| i as u8).collect();
+        let datagram = Ipv4Datagram::new(4, 5, 0, 3020, 9, 0, 0, 64, 1, 0x0a000001, 0x0a000002, payload.clone());
+        let fragments = datagram.fragment(1500).unwrap();
+        let sizes: Vec<(usize, usize, bool)> = fragments.iter().map(|f| (f.wire_size(), f.fragment_offset(), f.more_fragments())).collect();
+        assert_eq!(sizes, vec![(1500, 0, true), (1500, 1480, true), (60, 2960, false)]);
+        assert!(fragments.iter().all(|f| f.check_hdr_checksum() && f.id() == 9));
+        assert_eq!(fragments.iter().flat_map(|f| f.payload().to_vec()).collect::<Vec<u8>>(), payload);
+
+        assert_eq!(datagram.fragment(3020).unwrap().len(), 1);
+        let df = Ipv4Datagram::new(4, 5, 0, 3020, 9, 0b010, 0, 64, 1, 0x0a000001, 0x0a000002, payload);
+        assert_eq!(df.fragment(1500).unwrap_err(), SendError::PayloadTooLarge { len: 3020, max: 1500 });
+    }
+
+    #[test]
+    fn test_options_grow_header() {
+        use crate::net::ip_options::{self, SendOptions};
+
+        let mut datagram = Ipv4Datagram::new(4, 5, 0, 23, 1, 0, 0, 64, 1, 0x0a000001, 0x0a000002, vec![1, 2, 3]);
+        datagram.set_options(SendOptions { record_route: true, ip_timestamp: None }.encode());
+        assert!(datagram.record_options(0x0a000101, 0));
+        let parsed = Ipv4Datagram::try_deserialize(&datagram.serialize()).unwrap();
+        assert_eq!((parsed.ihl, parsed.toltal_len), (15, 63));
+        assert!(parsed.check_hdr_checksum());
+        assert_eq!(parsed.payload(), &[1, 2, 3]);
+        assert_eq!(ip_options::parse(parsed.options()).route, vec![0x0a000101]);
+
+        datagram.set_options(vec![ip_options::OPT_NOP]); // 补齐到 4 字节
+        assert_eq!((datagram.ihl, datagram.toltal_len, datagram.options().len()), (6, 27, 4));
+        assert!(!datagram.record_options(0x0a000101, 0));
+    }
+
+    #[test]
+    fn test_deserialize_invalid_ipv4() {
+        assert_eq!(
+            Ipv4Datagram::try_deserialize(&[0x45; 19]).unwrap_err(),
+            Ipv4ParseError::TooShort { len: 19 }
+        );
+
+        let mut bytes: Vec<u8> = vec![0; 20];
+        bytes[0] = 0x46; // ihl = 6, 但只有20字节
+        assert_eq!(
+            Ipv4Datagram::try_deserialize(&bytes).unwrap_err(),
+            Ipv4ParseError::BadHeaderLength { ihl: 6, available: 20 }
+        );
+        bytes[0] = 0x44; // ihl < 5
+        assert_eq!(
+            Ipv4Datagram::try_deserialize(&bytes).unwrap_err(),
+            Ipv4ParseError::BadHeaderLength { ihl: 4, available: 20 }
+        );
+    }
+
+    #[test]
+    fn test_builder_defaults() {
+        let datagram = Ipv4DatagramBuilder::new()
+            .source(0x0a000001)
+            .destination(0x0a000002)
+            .protocol(IpProtocol::Udp)
+            .payload(vec![1, 2, 3])
+            .build()
+            .unwrap();
+        assert_eq!((datagram.version, datagram.ihl, datagram.toltal_len), (4, 5, 23));
+        assert_eq!((datagram.ttl(), datagram.tos(), datagram.id(), datagram.protocol()), (64, 0, 0, 17));
+        assert!(!datagram.dont_fragment() && !datagram.is_fragment());
+        assert!(datagram.check_hdr_checksum());
+    }
+
+    #[test]
+    fn test_builder_validation() {
+        let base = || Ipv4DatagramBuilder::new().protocol(IpProtocol::Tcp);
+        assert_eq!(Ipv4DatagramBuilder::new().build().unwrap_err(), Ipv4BuildError::MissingProtocol);
+        assert_eq!(base().ttl(0).build().unwrap_err(), Ipv4BuildError::ZeroTtl);
+        assert_eq!(base().payload(vec![0; 65516]).build().unwrap_err(), Ipv4BuildError::PayloadTooLarge { len: 65516, max: 65515 });
+        let largest = base().payload(vec![0; 65515]).build().unwrap();
+        assert_eq!(largest.toltal_len, u16::MAX);
+    }
+
+    #[test]
+    fn test_builder_matches_positional_constructor() {
+        let built = Ipv4DatagramBuilder::new()
+            .source(0xc0a80001)
+            .destination(0xc0a800c7)
+            .protocol(IpProtocol::Tcp)
+            .ttl(32)
+            .dscp(Dscp::EF)
+            .dont_fragment(true)
+            .identification(0x1c46)
+            .payload(b"hello".to_vec())
+            .build()
+            .unwrap();
+        let old = Ipv4Datagram::new(4, 5, 0xb8, 25, 0x1c46, 0b010, 0, 32, 6, 0xc0a80001, 0xc0a800c7, b"hello".to_vec());
+        assert_eq!(built.serialize(), old.serialize());
+    }
+
     // 测试校验和计算逻辑
     #[test]
     fn test_generate_checksum_valid() {
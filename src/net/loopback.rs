@@ -0,0 +1,159 @@
+use std::collections::VecDeque;
+
+use crate::config::Ipv4Config;
+use crate::net::capture::{CaptureFn, CaptureTap};
+use crate::net::ipv4::{Ipv4Datagram, Ipv4ParseError};
+use crate::net::source_guard::Arrival;
+use crate::utils::memory::{MemoryBudget, MemoryComponent};
+use crate::utils::wire::WireSerialize;
+
+pub const LOOPBACK_MTU: u16 = 65535;
+
+/**
+ * 回环接口的编号, 不与真实设备的接口编号冲突
+ */
+pub const LOOPBACK_INTERFACE: usize = usize::MAX;
+
+pub fn is_loopback(addr: u32) -> bool {
+    addr >> 24 == 127
+}
+
+/**
+ * 数据报的出口
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Egress {
+    Loopback, // 送回本机的接收路径, 不查路由、不做 ARP
+    Device,
+}
+
+/**
+ * 127.0.0.0/8 总是走回环; loopback_local 打开时发往本机地址的数据报也走回环
+ */
+pub fn egress(d_addr: u32, local_addrs: &[(u32, u8)], config: &Ipv4Config) -> Egress {
+    if is_loopback(d_addr) || (config.loopback_local && local_addrs.iter().any(|(addr, _)| *addr == d_addr)) {
+        Egress::Loopback
+    } else {
+        Egress::Device
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LoopbackStats {
+    pub looped: u64,
+    pub too_big: u64,
+    pub no_memory: u64, // 内存预算不够而丢弃
+}
+
+/**
+ * 虚拟的回环接口: 发出的数据报按序排队, 由协议栈在下一轮 receive 取回
+ * 没有链路层, 队列里保存的是序列化后的 IP 数据报
+ */
+#[derive(Debug, Default)]
+pub struct LoopbackInterface {
+    queue: VecDeque<Vec<u8>>,
+    stats: LoopbackStats,
+    memory: MemoryBudget,
+    capture: CaptureTap,
+}
+
+impl LoopbackInterface {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * 排队的数据报向 budget 记账
+     */
+    pub fn set_memory_budget(&mut self, budget: MemoryBudget) {
+        self.memory = budget;
+    }
+
+    /**
+     * 旁路抓包: 发出的数据报在序列化之前、取回的数据报在解析成功之后交给回调, 因 MTU 或内存丢弃的不抓
+     */
+    pub fn set_capture(&mut self, capture: Option<CaptureFn>) -> Option<CaptureFn> {
+        self.capture.set(capture)
+    }
+
+    pub fn mtu(&self) -> u16 {
+        LOOPBACK_MTU
+    }
+
+    /**
+     * 超过 MTU 或内存预算不够时丢弃并返回 false
+     */
+    pub fn transmit(&mut self, datagram: &Ipv4Datagram) -> bool {
+        if datagram.wire_size() > LOOPBACK_MTU as usize {
+            self.stats.too_big += 1;
+            return false;
+        }
+        if !self.memory.try_charge(MemoryComponent::InterfaceQueue, datagram.wire_size()) {
+            self.stats.no_memory += 1;
+            return false;
+        }
+        self.stats.looped += 1;
+        self.capture.tx(datagram);
+        self.queue.push_back(datagram.serialize());
+        true
+    }
+
+    /**
+     * 取回下一个数据报, 同时给出交给入方向检查的到达信息
+     */
+    pub fn receive(&mut self) -> Option<Result<(Ipv4Datagram, Arrival), Ipv4ParseError>> {
+        let bytes = self.queue.pop_front()?;
+        self.memory.release(MemoryComponent::InterfaceQueue, bytes.len());
+        let datagram = Ipv4Datagram::from_payload_vec(bytes);
+        if let Ok(datagram) = &datagram {
+            self.capture.rx(datagram);
+        }
+        Some(datagram.map(|datagram| (datagram, Arrival { interface: LOOPBACK_INTERFACE, loopback: true })))
+    }
+
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn stats(&self) -> LoopbackStats {
+        self.stats
+    }
+}
+
+impl Drop for LoopbackInterface {
+    fn drop(&mut self) {
+        let queued = self.queue.iter().map(|bytes| bytes.len()).sum();
+        self.memory.release(MemoryComponent::InterfaceQueue, queued);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_egress() {
+        let config = Ipv4Config::default();
+        let local = [(0x0a000001, 24)];
+        assert_eq!(egress(0x7f000001, &local, &config), Egress::Loopback);
+        assert_eq!(egress(0x7f123456, &[], &config), Egress::Loopback);
+        assert_eq!(egress(0x0a000001, &local, &config), Egress::Loopback);
+        assert_eq!(egress(0x0a000002, &local, &config), Egress::Device);
+        let config = Ipv4Config { loopback_local: false, ..Ipv4Config::default() };
+        assert_eq!(egress(0x0a000001, &local, &config), Egress::Device);
+    }
+
+    #[test]
+    fn test_mtu() {
+        let mut lo = LoopbackInterface::new();
+        let datagram = |len: usize| Ipv4Datagram::new(4, 5, 0, (20 + len) as u16, 0, 0, 0, 64, 6, 0x7f000001, 0x7f000001, vec![0; len]);
+        assert!(lo.transmit(&datagram(60 * 1024)));
+        assert!(!lo.transmit(&datagram(65535 - 19)));
+        let (received, arrival) = lo.receive().unwrap().unwrap();
+        assert_eq!(received.payload().len(), 60 * 1024);
+        assert!(arrival.loopback);
+        assert!(lo.receive().is_none());
+        assert_eq!(lo.stats(), LoopbackStats { looped: 1, too_big: 1, no_memory: 0 });
+    }
+}
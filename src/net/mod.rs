@@ -1,2 +1,15 @@
 pub mod ipv4;
+pub mod loopback;
+pub mod capture;
 pub mod icmp_v4;
+pub mod interfaces;
+pub mod dscp;
+pub mod igmp;
+pub mod ip_options;
+pub mod nat;
+pub mod pinger;
+pub mod pmtu;
+pub mod raw_socket;
+pub mod reassembly;
+pub mod route;
+pub mod source_guard;
@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::config::{ConfigError, NatConfig};
+use crate::utils::checksum;
+use crate::utils::timer::TimerQueue;
+
+/**
+ * 参与地址转换的协议, ICMP 只转换回显请求/应答, 以标识符充当端口
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NatProto {
+    Tcp,
+    Udp,
+    Icmp,
+}
+
+/**
+ * 不能转换的报文, 由调用者决定丢弃还是原样转发
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NatError {
+    Malformed,
+    Fragment,
+    UnsupportedProtocol(u8),
+    NotPublicAddress(u32),
+    NoMapping { proto: NatProto, port: u16 },
+    PortsExhausted,
+}
+
+impl fmt::Display for NatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NatError::Malformed => write!(f, "malformed datagram"),
+            NatError::Fragment => write!(f, "non-first fragments are not translated"),
+            NatError::UnsupportedProtocol(protocol) => write!(f, "protocol {} is not translated", protocol),
+            NatError::NotPublicAddress(addr) => {
+                let [a, b, c, d] = addr.to_be_bytes();
+                write!(f, "{}.{}.{}.{} is not the public address", a, b, c, d)
+            }
+            NatError::NoMapping { proto, port } => write!(f, "no {:?} mapping for public port {}", proto, port),
+            NatError::PortsExhausted => write!(f, "no free public port"),
+        }
+    }
+}
+
+impl Error for NatError {}
+
+/**
+ * 一条映射: 内网 (地址, 端口) <-> 公网端口
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NatMapping {
+    pub proto: NatProto,
+    pub inside_ip: u32,
+    pub inside_port: u16,
+    pub public_port: u16,
+}
+
+/**
+ * 报文中需要改写的位置
+ */
+struct L4Layout {
+    proto: NatProto,
+    port_offset: usize,     // 相对于数据报开头; 出方向取源端口, 入方向取目的端口时再加 2
+    checksum_offset: usize,
+    pseudo_header: bool,    // ICMP 校验和不含伪首部
+}
+
+fn layout(packet: &[u8], outbound: bool) -> Result<L4Layout, NatError> {
+    if packet.len() < 20 || packet[0] >> 4 != 4 {
+        return Err(NatError::Malformed);
+    }
+    let ihl = ((packet[0] & 0x0f) as usize) * 4;
+    let frag_offset = u16::from_be_bytes([packet[6] & 0x1f, packet[7]]);
+    if frag_offset != 0 {
+        return Err(NatError::Fragment);
+    }
+    let (proto, port_offset, checksum_offset, pseudo_header, min_len) = match packet[9] {
+        6 => (NatProto::Tcp, if outbound { 0 } else { 2 }, 16, true, 20),
+        17 => (NatProto::Udp, if outbound { 0 } else { 2 }, 6, true, 8),
+        1 => {
+            let expected = if outbound { 8 } else { 0 }; // 出方向只转换请求, 入方向只转换应答
+            if packet.len() < ihl + 8 || packet[ihl] != expected {
+                return Err(NatError::UnsupportedProtocol(1));
+            }
+            (NatProto::Icmp, 4, 2, false, 8)
+        }
+        other => return Err(NatError::UnsupportedProtocol(other)),
+    };
+    if ihl < 20 || packet.len() < ihl + min_len {
+        return Err(NatError::Malformed);
+    }
+    Ok(L4Layout { proto, port_offset: ihl + port_offset, checksum_offset: ihl + checksum_offset, pseudo_header })
+}
+
+fn read_u16(packet: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([packet[offset], packet[offset + 1]])
+}
+
+fn read_u32(packet: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([packet[offset], packet[offset + 1], packet[offset + 2], packet[offset + 3]])
+}
+
+/**
+ * 改写地址(IP 首部 addr_offset 处)和端口, 增量修正 IP 首部校验和与上层校验和
+ */
+fn rewrite(packet: &mut [u8], l4: &L4Layout, addr_offset: usize, new_addr: u32, new_port: u16) {
+    let old_addr = read_u32(packet, addr_offset);
+    let old_port = read_u16(packet, l4.port_offset);
+    packet[addr_offset..addr_offset + 4].copy_from_slice(&new_addr.to_be_bytes());
+    packet[l4.port_offset..l4.port_offset + 2].copy_from_slice(&new_port.to_be_bytes());
+
+    let ip_checksum = checksum::update_u32(read_u16(packet, 10), old_addr, new_addr);
+    packet[10..12].copy_from_slice(&ip_checksum.to_be_bytes());
+
+    let old_checksum = read_u16(packet, l4.checksum_offset);
+    if l4.proto == NatProto::Udp && old_checksum == 0 {
+        return; // UDP 校验和为 0 表示未使用
+    }
+    let mut l4_checksum = checksum::update(old_checksum, old_port, new_port);
+    if l4.pseudo_header {
+        l4_checksum = checksum::update_u32(l4_checksum, old_addr, new_addr);
+    }
+    if l4.proto == NatProto::Udp && l4_checksum == 0 {
+        l4_checksum = 0xffff;
+    }
+    packet[l4.checksum_offset..l4.checksum_offset + 2].copy_from_slice(&l4_checksum.to_be_bytes());
+}
+
+/**
+ * 把内网发出的 TCP/UDP/ICMP 回显的源地址换成唯一的公网地址(masquerade)
+ * 映射与目的地址无关(RFC 4787 endpoint-independent), 空闲超时后由 tick 回收, 端口按游标轮转分配
+ * 输入输出都是整个 IPv4 数据报, 原地改写
+ */
+pub struct Nat44 {
+    config: NatConfig,
+    outbound: HashMap<(NatProto, u32, u16), u16>,
+    inbound: HashMap<(NatProto, u16), NatMapping>,
+    idle: TimerQueue<(NatProto, u16)>,
+    next_port: u16,
+}
+
+impl Nat44 {
+    /**
+     * 端口范围先经 NatConfig::validate 检查, 分配时不会遇到空的范围
+     */
+    pub fn new(config: NatConfig) -> Result<Self, ConfigError> {
+        config.validate()?;
+        let next_port = config.port_min;
+        Ok(Nat44 { config, outbound: HashMap::new(), inbound: HashMap::new(), idle: TimerQueue::new(), next_port })
+    }
+
+    pub fn public_ip(&self) -> u32 {
+        self.config.public_ip
+    }
+
+    /**
+     * 内网 -> 外网: 改写源地址和源端口(或 ICMP 标识符), 必要时新建映射
+     */
+    pub fn outbound(&mut self, packet: &mut [u8], now_ms: u64) -> Result<NatMapping, NatError> {
+        let l4 = layout(packet, true)?;
+        let inside_ip = read_u32(packet, 12);
+        let inside_port = read_u16(packet, l4.port_offset);
+        let public_port = match self.outbound.get(&(l4.proto, inside_ip, inside_port)) {
+            Some(&port) => port,
+            None => {
+                let port = self.allocate(l4.proto)?;
+                self.outbound.insert((l4.proto, inside_ip, inside_port), port);
+                self.inbound.insert((l4.proto, port), NatMapping { proto: l4.proto, inside_ip, inside_port, public_port: port });
+                port
+            }
+        };
+        self.touch(l4.proto, public_port, now_ms);
+        rewrite(packet, &l4, 12, self.config.public_ip, public_port);
+        Ok(self.inbound[&(l4.proto, public_port)])
+    }
+
+    /**
+     * 外网 -> 内网: 按目的端口查映射, 改写回内网的地址和端口
+     */
+    pub fn inbound(&mut self, packet: &mut [u8], now_ms: u64) -> Result<NatMapping, NatError> {
+        let l4 = layout(packet, false)?;
+        let d_addr = read_u32(packet, 16);
+        if d_addr != self.config.public_ip {
+            return Err(NatError::NotPublicAddress(d_addr));
+        }
+        let public_port = read_u16(packet, l4.port_offset);
+        let mapping = *self.inbound.get(&(l4.proto, public_port))
+            .ok_or(NatError::NoMapping { proto: l4.proto, port: public_port })?;
+        self.touch(l4.proto, public_port, now_ms);
+        rewrite(packet, &l4, 16, mapping.inside_ip, mapping.inside_port);
+        Ok(mapping)
+    }
+
+    /**
+     * 回收空闲超时的映射, 返回回收的个数
+     */
+    pub fn tick(&mut self, now_ms: u64) -> usize {
+        let expired = self.idle.expired(now_ms);
+        for key in &expired {
+            if let Some(mapping) = self.inbound.remove(key) {
+                self.outbound.remove(&(mapping.proto, mapping.inside_ip, mapping.inside_port));
+            }
+        }
+        expired.len()
+    }
+
+    pub fn mappings(&self) -> impl Iterator<Item = &NatMapping> {
+        self.inbound.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inbound.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inbound.is_empty()
+    }
+
+    fn touch(&mut self, proto: NatProto, public_port: u16, now_ms: u64) {
+        let idle_ms = match proto {
+            NatProto::Tcp => self.config.tcp_idle_ms,
+            NatProto::Udp => self.config.udp_idle_ms,
+            NatProto::Icmp => self.config.icmp_idle_ms,
+        };
+        self.idle.schedule((proto, public_port), now_ms + idle_ms);
+    }
+
+    /**
+     * 从游标开始找一个该协议下空闲的端口, 游标随之前进, 刚回收的端口不会马上被复用
+     */
+    fn allocate(&mut self, proto: NatProto) -> Result<u16, NatError> {
+        let (min, max) = (self.config.port_min, self.config.port_max);
+        let span = (max - min) as u32 + 1;
+        for _ in 0..span {
+            let port = self.next_port;
+            self.next_port = if port >= max { min } else { port + 1 };
+            if !self.inbound.contains_key(&(proto, port)) {
+                return Ok(port);
+            }
+        }
+        Err(NatError::PortsExhausted)
+    }
+}
+
+impl fmt::Debug for Nat44 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Nat44").field("config", &self.config).field("mappings", &self.inbound.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::ipv4::Ipv4Datagram;
+    use crate::testing::packet::PacketBuilder;
+    use crate::transport::tcp_segment::{TcpCtrlFlag, TcpSegment};
+    use crate::utils::wire::WireSerialize;
+
+    const INSIDE_A: u32 = 0xc0a8_0002; // 192.168.0.2
+    const INSIDE_B: u32 = 0xc0a8_0003;
+    const PUBLIC: u32 = 0xcb00_7101; // 203.0.113.1
+    const SERVER: u32 = 0xc633_6414; // 198.51.100.20
+
+    fn nat() -> Nat44 {
+        Nat44::new(NatConfig { public_ip: PUBLIC, port_min: 50000, port_max: 50003, ..NatConfig::default() }).unwrap()
+    }
+
+    fn tcp(s_addr: u32, d_addr: u32, s_port: u16, d_port: u16) -> Vec<u8> {
+        PacketBuilder::new().ipv4(s_addr, d_addr).tcp(s_port, d_port)
+            .flags(TcpCtrlFlag::SYN as u16).seq(7).mss(1460).payload(b"data").build()
+    }
+
+    /**
+     * IP 首部和 TCP 校验和都正确, 返回 (源, 目的, 源端口, 目的端口)
+     */
+    fn check_tcp(packet: &[u8]) -> (u32, u32, u16, u16) {
+        let datagram = Ipv4Datagram::try_deserialize(packet).unwrap();
+        assert!(datagram.check_hdr_checksum());
+        let segment = TcpSegment::try_deserialize(datagram.payload()).unwrap();
+        assert!(segment.check_checksum(datagram.s_addr(), datagram.d_addr()));
+        (datagram.s_addr(), datagram.d_addr(), segment.s_port, segment.d_port)
+    }
+
+    #[test]
+    fn test_tcp_round_trip() {
+        let mut nat = nat();
+        let mut syn = tcp(INSIDE_A, SERVER, 40000, 80);
+        let mapping = nat.outbound(&mut syn, 0).unwrap();
+        assert_eq!(check_tcp(&syn), (PUBLIC, SERVER, 50000, 80));
+        assert_eq!(mapping, NatMapping { proto: NatProto::Tcp, inside_ip: INSIDE_A, inside_port: 40000, public_port: 50000 });
+
+        // 另一台内网主机用同一个源端口, 分到不同的公网端口
+        let mut other = tcp(INSIDE_B, SERVER, 40000, 80);
+        nat.outbound(&mut other, 0).unwrap();
+        assert_eq!(check_tcp(&other).2, 50001);
+
+        let mut reply = tcp(SERVER, PUBLIC, 80, 50000);
+        nat.inbound(&mut reply, 5).unwrap();
+        assert_eq!(check_tcp(&reply), (SERVER, INSIDE_A, 80, 40000));
+
+        let mut stray = tcp(SERVER, PUBLIC, 80, 50003);
+        assert_eq!(nat.inbound(&mut stray, 5), Err(NatError::NoMapping { proto: NatProto::Tcp, port: 50003 }));
+        let mut misaddressed = tcp(SERVER, INSIDE_A, 80, 50000);
+        assert_eq!(nat.inbound(&mut misaddressed, 5), Err(NatError::NotPublicAddress(INSIDE_A)));
+    }
+
+    #[test]
+    fn test_icmp_echo_uses_identifier() {
+        let mut nat = nat();
+        let mut request = PacketBuilder::new().ipv4(INSIDE_A, SERVER).icmp(8, 0).payload(&[0x12, 0x34, 0, 1, 9, 9]).build();
+        nat.outbound(&mut request, 0).unwrap();
+        let datagram = Ipv4Datagram::try_deserialize(&request).unwrap();
+        assert!(datagram.check_hdr_checksum());
+        assert!(checksum::check(datagram.payload()));
+        assert_eq!(&datagram.payload()[4..6], &50000u16.to_be_bytes());
+
+        let mut reply = PacketBuilder::new().ipv4(SERVER, PUBLIC).icmp(0, 0).payload(&[0xc3, 0x50, 0, 1, 9, 9]).build();
+        nat.inbound(&mut reply, 1).unwrap();
+        let datagram = Ipv4Datagram::try_deserialize(&reply).unwrap();
+        assert_eq!(datagram.d_addr(), INSIDE_A);
+        assert!(checksum::check(datagram.payload()));
+        assert_eq!(&datagram.payload()[4..6], &[0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_udp_checksum_and_unsupported() {
+        let mut nat = nat();
+        // 手工构造 UDP: 53000 -> 53, 载荷 "q"
+        let mut udp = vec![0xcf, 0x08, 0x00, 0x35, 0x00, 0x09, 0x00, 0x00, b'q'];
+        let mut sum_input = checksum::pseudo_header(INSIDE_A, SERVER, 17, 9);
+        sum_input.extend_from_slice(&udp);
+        udp[6..8].copy_from_slice(&checksum::generate_checksum(&sum_input).to_be_bytes());
+        let mut packet = Ipv4Datagram::new(4, 5, 0, 29, 1, 0, 0, 64, 17, INSIDE_A, SERVER, udp).serialize();
+        nat.outbound(&mut packet, 0).unwrap();
+        let datagram = Ipv4Datagram::try_deserialize(&packet).unwrap();
+        let mut verify = checksum::pseudo_header(PUBLIC, SERVER, 17, 9);
+        verify.extend_from_slice(datagram.payload());
+        assert!(checksum::check(&verify));
+
+        let mut gre = Ipv4Datagram::new(4, 5, 0, 24, 1, 0, 0, 64, 47, INSIDE_A, SERVER, vec![0; 4]).serialize();
+        assert_eq!(nat.outbound(&mut gre, 0), Err(NatError::UnsupportedProtocol(47)));
+        assert_eq!(nat.outbound(&mut [0u8; 10], 0), Err(NatError::Malformed));
+    }
+
+    #[test]
+    fn test_idle_mapping_expires_and_gets_fresh_port() {
+        let mut nat = nat();
+        let mut first = tcp(INSIDE_A, SERVER, 40000, 80);
+        nat.outbound(&mut first, 0).unwrap();
+        let idle_ms = NatConfig::default().tcp_idle_ms;
+
+        // 有流量就续期
+        let mut again = tcp(INSIDE_A, SERVER, 40000, 80);
+        nat.outbound(&mut again, idle_ms - 1).unwrap();
+        assert_eq!(nat.tick(idle_ms + 1), 0);
+        assert_eq!(nat.tick(2 * idle_ms), 1);
+        assert!(nat.is_empty());
+
+        let mut late_reply = tcp(SERVER, PUBLIC, 80, 50000);
+        assert!(nat.inbound(&mut late_reply, 2 * idle_ms).is_err());
+        let mut reuse = tcp(INSIDE_A, SERVER, 40000, 80);
+        assert_eq!(nat.outbound(&mut reuse, 2 * idle_ms).unwrap().public_port, 50001);
+    }
+
+    #[test]
+    fn test_ports_exhausted() {
+        let mut nat = nat();
+        for port in 0..4 {
+            let mut packet = tcp(INSIDE_A, SERVER, 1000 + port, 80);
+            nat.outbound(&mut packet, 0).unwrap();
+        }
+        let mut packet = tcp(INSIDE_A, SERVER, 2000, 80);
+        assert_eq!(nat.outbound(&mut packet, 0), Err(NatError::PortsExhausted));
+    }
+
+    #[test]
+    fn test_invalid_port_range_rejected() {
+        let inverted = NatConfig { public_ip: PUBLIC, port_min: 50003, port_max: 50000, ..NatConfig::default() };
+        assert_eq!(Nat44::new(inverted).err(), Some(ConfigError::NatPortRangeInverted { port_min: 50003, port_max: 50000 }));
+
+        // 只有一个端口的范围可以用, 用完即耗尽
+        let mut nat = Nat44::new(NatConfig { public_ip: PUBLIC, port_min: 65535, port_max: 65535, ..NatConfig::default() }).unwrap();
+        let mut packet = tcp(INSIDE_A, SERVER, 40000, 80);
+        assert_eq!(nat.outbound(&mut packet, 0).unwrap().public_port, 65535);
+        let mut packet = tcp(INSIDE_A, SERVER, 40001, 80);
+        assert_eq!(nat.outbound(&mut packet, 0), Err(NatError::PortsExhausted));
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::BTreeMap;
+
+use crate::net::icmp_v4::IcmpV4;
+use crate::net::ip_options::{self, RecordedOptions};
+use crate::net::ipv4::Ipv4Datagram;
+use crate::net::raw_socket::{RawSendOptions, RawSocketHandle, RawSockets};
+use crate::utils::wire::WireSerialize;
+
+pub const ECHO_REQUEST: u8 = 8;
+pub const ECHO_REPLY: u8 = 0;
+
+/**
+ * 回显载荷的填充方式
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayloadPattern {
+    Incrementing,   // 0x00, 0x01, ... 0xff, 0x00, ...
+    Fixed(u8),
+    Custom(Vec<u8>), // 循环重复, 空时按 0 填充
+}
+
+impl PayloadPattern {
+    pub fn fill(&self, len: usize) -> Vec<u8> {
+        match self {
+            PayloadPattern::Incrementing => (0..len).map(|i| i as u8).collect(),
+            PayloadPattern::Fixed(byte) => vec![*byte; len],
+            PayloadPattern::Custom(bytes) if bytes.is_empty() => vec![0; len],
+            PayloadPattern::Custom(bytes) => bytes.iter().copied().cycle().take(len).collect(),
+        }
+    }
+}
+
+/**
+ * 一个探测的结果; 偏移从回显数据中 id 与 seq 之后算起
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PingOutcome {
+    Reply { seq: u16, size: usize, rtt_ms: u64 },
+    PayloadMismatch { seq: u16, first_diff_offset: usize },
+    Timeout { seq: u16 },
+}
+
+/**
+ * 按 size 扫描: 每个探测之后 size 增加 step, 超过 end 后回到 start
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Sweep {
+    start: usize,
+    step: usize,
+    end: usize,
+}
+
+/**
+ * ICMP 回显探测, 相当于 ping -s size -p pattern
+ * 只构造和核对 ICMP 报文; 经原始 ICMP 套接字收发时由 RawSockets 封装 IP 首部, 大报文的分片由调用方完成
+ */
+pub struct Pinger {
+    id: u16,
+    next_seq: u16,
+    size: usize,
+    pattern: PayloadPattern,
+    sweep: Option<Sweep>,
+    timeout_ms: u64,
+    outstanding: BTreeMap<u16, (u64, Vec<u8>)>, // seq -> (发送时刻, 载荷)
+}
+
+impl Pinger {
+    pub fn new(id: u16, size: usize) -> Self {
+        Pinger { id, next_seq: 0, size, pattern: PayloadPattern::Incrementing, sweep: None, timeout_ms: 1000, outstanding: BTreeMap::new() }
+    }
+
+    pub fn with_pattern(mut self, pattern: PayloadPattern) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout_ms: u64) -> Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+
+    /**
+     * 从 start 开始, 每个探测的载荷增加 step 字节, 到 end 为止后重新开始
+     */
+    pub fn with_sweep(mut self, start: usize, step: usize, end: usize) -> Self {
+        self.size = start;
+        self.sweep = Some(Sweep { start, step: step.max(1), end: end.max(start) });
+        self
+    }
+
+    /**
+     * 下一个探测的载荷大小
+     */
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /**
+     * 构造下一个回显请求
+     */
+    pub fn next_probe(&mut self, now_ms: u64) -> IcmpV4 {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        let payload = self.pattern.fill(self.size);
+        let mut data = Vec::with_capacity(4 + payload.len());
+        data.extend_from_slice(&self.id.to_be_bytes());
+        data.extend_from_slice(&seq.to_be_bytes());
+        data.extend_from_slice(&payload);
+        self.outstanding.insert(seq, (now_ms, payload));
+        if let Some(sweep) = self.sweep {
+            self.size = if self.size + sweep.step > sweep.end { sweep.start } else { self.size + sweep.step };
+        }
+        IcmpV4::new(ECHO_REQUEST, 0, data)
+    }
+
+    /**
+     * 处理收到的 ICMP 报文, 不是本 Pinger 未完成探测的回显应答时返回 None
+     */
+    pub fn on_reply(&mut self, reply: &IcmpV4, now_ms: u64) -> Option<PingOutcome> {
+        let data = reply.data();
+        if reply.icmp_type() != ECHO_REPLY || data.len() < 4 || u16::from_be_bytes([data[0], data[1]]) != self.id {
+            return None;
+        }
+        let seq = u16::from_be_bytes([data[2], data[3]]);
+        let (sent_ms, expected) = self.outstanding.remove(&seq)?;
+        let received = &data[4..];
+        let mismatch = expected.iter().zip(received).position(|(a, b)| a != b)
+            .or_else(|| (expected.len() != received.len()).then(|| expected.len().min(received.len())));
+        Some(match mismatch {
+            Some(first_diff_offset) => PingOutcome::PayloadMismatch { seq, first_diff_offset },
+            None => PingOutcome::Reply { seq, size: received.len(), rtt_ms: now_ms - sent_ms },
+        })
+    }
+
+    /**
+     * 处理收到的整个数据报, 同时读出应答带回的 Record Route / Timestamp 记录
+     */
+    pub fn on_reply_datagram(&mut self, datagram: &Ipv4Datagram, now_ms: u64) -> Option<(PingOutcome, RecordedOptions)> {
+        let reply = IcmpV4::try_deserialize(datagram.payload()).ok()?;
+        let outcome = self.on_reply(&reply, now_ms)?;
+        Some((outcome, ip_options::parse(datagram.options())))
+    }
+
+    /**
+     * 经原始 ICMP 套接字发出下一个回显请求, 返回交给出口的数据报; 套接字已经关闭时返回 None
+     */
+    pub fn send_probe(&mut self, sockets: &mut RawSockets, handle: RawSocketHandle, d_addr: u32, now_ms: u64) -> Option<Ipv4Datagram> {
+        let probe = self.next_probe(now_ms);
+        sockets.send(handle, d_addr, probe.serialize(), &RawSendOptions::default())
+    }
+
+    /**
+     * 读完原始 ICMP 套接字中缓存的报文, 返回其中属于本 Pinger 的结果
+     */
+    pub fn receive(&mut self, sockets: &mut RawSockets, handle: RawSocketHandle, now_ms: u64) -> Vec<PingOutcome> {
+        let mut outcomes = vec![];
+        while let Some((_, payload)) = sockets.recv(handle) {
+            let Ok(reply) = IcmpV4::try_deserialize(&payload) else { continue };
+            outcomes.extend(self.on_reply(&reply, now_ms));
+        }
+        outcomes
+    }
+
+    /**
+     * 超时未收到应答的探测
+     */
+    pub fn expire(&mut self, now_ms: u64) -> Vec<PingOutcome> {
+        let timeout_ms = self.timeout_ms;
+        let expired: Vec<u16> = self.outstanding.iter()
+            .filter(|(_, (sent_ms, _))| now_ms >= sent_ms + timeout_ms)
+            .map(|(seq, _)| *seq)
+            .collect();
+        expired.into_iter().map(|seq| {
+            self.outstanding.remove(&seq);
+            PingOutcome::Timeout { seq }
+        }).collect()
+    }
+}
+
+/**
+ * 回显请求对应的应答, 数据原样带回
+ */
+pub fn echo_reply(request: &IcmpV4) -> Option<IcmpV4> {
+    (request.icmp_type() == ECHO_REQUEST).then(|| IcmpV4::new(ECHO_REPLY, 0, request.data().to_vec()))
+}
+
+/**
+ * 回显请求数据报对应的应答数据报 (RFC 1122 3.2.2.6)
+ * 请求中的 Record Route / Timestamp 选项记录本机后带回应答, 后续转发者继续填写
+ */
+pub fn echo_reply_datagram(request: &Ipv4Datagram, addr: u32, now_ms: u64) -> Option<Ipv4Datagram> {
+    let reply = echo_reply(&IcmpV4::try_deserialize(request.payload()).ok()?)?.serialize();
+    let mut datagram = Ipv4Datagram::new(4, 5, 0, (20 + reply.len()) as u16, request.id(), 0, 0, 64, request.protocol(), addr, request.s_addr(), reply);
+    if !request.options().is_empty() {
+        datagram.set_options(request.options().to_vec());
+        datagram.record_options(addr, now_ms);
+    }
+    Some(datagram)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patterns() {
+        assert_eq!(PayloadPattern::Incrementing.fill(3), vec![0, 1, 2]);
+        assert_eq!(PayloadPattern::Incrementing.fill(258)[256..], [0, 1]);
+        assert_eq!(PayloadPattern::Fixed(0xa5).fill(2), vec![0xa5, 0xa5]);
+        assert_eq!(PayloadPattern::Custom(vec![1, 2, 3]).fill(7), vec![1, 2, 3, 1, 2, 3, 1]);
+        assert!(PayloadPattern::Custom(vec![]).fill(0).is_empty());
+    }
+
+    #[test]
+    fn test_sweep_and_timeout() {
+        let mut pinger = Pinger::new(7, 0).with_sweep(0, 500, 1200).with_timeout(100);
+        let sizes: Vec<usize> = (0..4).map(|_| pinger.next_probe(0).data().len() - 4).collect();
+        assert_eq!(sizes, vec![0, 500, 1000, 0]);
+        let reply = echo_reply(&pinger.next_probe(50)).unwrap();
+        assert_eq!(pinger.expire(100), (0..4).map(|seq| PingOutcome::Timeout { seq }).collect::<Vec<_>>());
+        assert_eq!(pinger.on_reply(&reply, 60), Some(PingOutcome::Reply { seq: 4, size: 500, rtt_ms: 10 }));
+        assert_eq!(pinger.on_reply(&reply, 61), None); // 重复的应答
+    }
+
+    #[test]
+    fn test_truncated_reply_is_mismatch() {
+        let mut pinger = Pinger::new(7, 10);
+        let request = pinger.next_probe(0);
+        let reply = IcmpV4::new(ECHO_REPLY, 0, request.data()[..10].to_vec());
+        assert_eq!(pinger.on_reply(&reply, 1), Some(PingOutcome::PayloadMismatch { seq: 0, first_diff_offset: 6 }));
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use crate::net::icmp_v4::IcmpV4;
+
+/**
+ * RFC 1191 第 7 节的 MTU 平台值, 从大到小
+ */
+const PLATEAUS: [u16; 11] = [65535, 32000, 17914, 8166, 4352, 2002, 1492, 1006, 508, 296, 68];
+const MIN_MTU: u16 = 68;
+pub const PMTU_EXPIRY_MS: u64 = 10 * 60_000;
+const IP_TCP_HDR_LEN: u16 = 40;
+
+/**
+ * ICMP 报文里没有给出下一跳 MTU 时, 取严格小于原数据报长度的最大平台值
+ */
+pub fn plateau_below(total_len: u16) -> u16 {
+    PLATEAUS.iter().copied().find(|&mtu| mtu < total_len).unwrap_or(MIN_MTU)
+}
+
+/**
+ * 从 ICMP 目的不可达/需要分片(type 3 code 4)中取出 (原数据报的目的地址, 下一跳 MTU)
+ * 报文里带回的是原数据报的首部 + 至少 8 字节载荷
+ */
+pub fn parse_frag_needed(icmp_bytes: &[u8]) -> Option<(u32, u16)> {
+    let icmp = IcmpV4::try_deserialize(icmp_bytes).ok()?;
+    let data = icmp.data();
+    if icmp.icmp_type() != 3 || icmp.code() != 4 || data.len() < 4 + 20 {
+        return None;
+    }
+    let original = &data[4..];
+    let d_addr = u32::from_be_bytes([original[16], original[17], original[18], original[19]]);
+    let mtu = match u16::from_be_bytes([data[2], data[3]]) {
+        0 => plateau_below(u16::from_be_bytes([original[2], original[3]])),
+        mtu => mtu,
+    };
+    Some((d_addr, mtu))
+}
+
+/**
+ * 每个目的地址的路径 MTU, 发现的值 PMTU_EXPIRY_MS 后过期, 重新使用接口 MTU 试探
+ */
+#[derive(Debug, Clone)]
+pub struct PmtuCache {
+    link_mtu: u16,
+    entries: HashMap<u32, (u16, u64)>, // d_addr -> (mtu, 过期时间)
+}
+
+impl PmtuCache {
+    pub fn new(link_mtu: u16) -> Self {
+        PmtuCache { link_mtu, entries: HashMap::new() }
+    }
+
+    pub fn mtu_for(&self, d_addr: u32, now_ms: u64) -> u16 {
+        self.discovered(d_addr, now_ms).unwrap_or(self.link_mtu)
+    }
+
+    /**
+     * 发现的、还没有过期的路径 MTU; 没有发现过时为 None
+     */
+    pub fn discovered(&self, d_addr: u32, now_ms: u64) -> Option<u16> {
+        match self.entries.get(&d_addr) {
+            Some(&(mtu, expires_ms)) if now_ms < expires_ms => Some(mtu),
+            _ => None,
+        }
+    }
+
+    pub fn link_mtu(&self) -> u16 {
+        self.link_mtu
+    }
+
+    /**
+     * 去掉不带选项的 IP 与 TCP 首部后的 MSS
+     */
+    pub fn mss_for(&self, d_addr: u32, now_ms: u64) -> u16 {
+        self.mtu_for(d_addr, now_ms) - IP_TCP_HDR_LEN
+    }
+
+    /**
+     * 记录一次“需要分片”; 只会调小, 低于 68 的值视为伪造而忽略, 返回缓存是否改变
+     */
+    pub fn update(&mut self, d_addr: u32, mtu: u16, now_ms: u64) -> bool {
+        if mtu < MIN_MTU || mtu >= self.mtu_for(d_addr, now_ms) {
+            return false;
+        }
+        self.entries.insert(d_addr, (mtu, now_ms + PMTU_EXPIRY_MS));
+        true
+    }
+
+    /**
+     * 处理 ICMP 差错报文, 调小了 PMTU 时返回 (目的地址, 新 MTU)
+     */
+    pub fn on_icmp_error(&mut self, icmp_bytes: &[u8], now_ms: u64) -> Option<(u32, u16)> {
+        let (d_addr, mtu) = parse_frag_needed(icmp_bytes)?;
+        self.update(d_addr, mtu, now_ms).then_some((d_addr, mtu))
+    }
+
+    /**
+     * 删除过期的条目, 返回 MTU 被重新调大的目的地址
+     */
+    pub fn tick(&mut self, now_ms: u64) -> Vec<u32> {
+        let expired: Vec<u32> = self.entries.iter()
+            .filter(|(_, &(_, expires_ms))| now_ms >= expires_ms)
+            .map(|(&d_addr, _)| d_addr)
+            .collect();
+        for d_addr in &expired {
+            self.entries.remove(d_addr);
+        }
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::packet::PacketBuilder;
+
+    fn frag_needed(mtu: u16, original: &[u8]) -> Vec<u8> {
+        let mut data = vec![0, 0];
+        data.extend_from_slice(&mtu.to_be_bytes());
+        data.extend_from_slice(&original[..28]);
+        PacketBuilder::new().icmp(3, 4).payload(&data).build()
+    }
+
+    #[test]
+    fn test_parse_and_plateau() {
+        let original = PacketBuilder::new().ipv4(0x0a000001, 0x0a000009).tcp(1, 2).payload(&[0; 1460]).build();
+        assert_eq!(parse_frag_needed(&frag_needed(1000, &original)), Some((0x0a000009, 1000)));
+        assert_eq!(parse_frag_needed(&frag_needed(0, &original)), Some((0x0a000009, 1492)));
+        assert_eq!(plateau_below(1006), 508);
+        assert_eq!(plateau_below(60), 68);
+        let unreachable = PacketBuilder::new().icmp(3, 1).payload(&[0; 32]).build();
+        assert_eq!(parse_frag_needed(&unreachable), None);
+    }
+
+    #[test]
+    fn test_cache_only_lowers_and_expires() {
+        let mut cache = PmtuCache::new(1500);
+        assert_eq!(cache.mss_for(9, 0), 1460);
+        assert!(cache.update(9, 1000, 0));
+        assert!(!cache.update(9, 1200, 1));
+        assert!(!cache.update(9, 40, 1));
+        assert_eq!(cache.mss_for(9, 1), 960);
+        assert_eq!(cache.mss_for(8, 1), 1460);
+        assert_eq!((cache.discovered(9, 1), cache.discovered(8, 1)), (Some(1000), None));
+
+        assert!(cache.tick(PMTU_EXPIRY_MS - 1).is_empty());
+        assert_eq!(cache.tick(PMTU_EXPIRY_MS), vec![9]);
+        assert_eq!(cache.mtu_for(9, PMTU_EXPIRY_MS), 1500);
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::config::Ipv4Config;
+use crate::net::ipv4::{Ipv4Datagram, Ipv4Header};
+
+/**
+ * 每个原始套接字默认最多缓存的数据报个数, 满了之后新到的丢弃并计数
+ */
+pub const RAW_QUEUE_CAPACITY: usize = 64;
+
+/**
+ * IP 首部中的上层协议号
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IpProtocol {
+    Icmp,
+    Igmp,
+    Tcp,
+    Udp,
+    Other(u8),
+}
+
+impl IpProtocol {
+    pub fn number(&self) -> u8 {
+        match self {
+            IpProtocol::Icmp => 1,
+            IpProtocol::Igmp => 2,
+            IpProtocol::Tcp => 6,
+            IpProtocol::Udp => 17,
+            IpProtocol::Other(number) => *number,
+        }
+    }
+
+    /**
+     * 协议栈自己处理的协议; 其他协议的数据报只交给原始套接字
+     */
+    pub fn handled(&self) -> bool {
+        !matches!(self, IpProtocol::Other(_))
+    }
+}
+
+impl From<u8> for IpProtocol {
+    fn from(number: u8) -> Self {
+        match number {
+            1 => IpProtocol::Icmp,
+            2 => IpProtocol::Igmp,
+            6 => IpProtocol::Tcp,
+            17 => IpProtocol::Udp,
+            other => IpProtocol::Other(other),
+        }
+    }
+}
+
+/**
+ * 发送时由调用方指定的首部字段, 其余字段由协议栈填写
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RawSendOptions {
+    pub ttl: Option<u8>, // None 时取 Ipv4Config::default_ttl
+    pub tos: u8,
+    pub dont_fragment: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RawSocketHandle(usize);
+
+struct RawSocket {
+    protocol: IpProtocol,
+    queue: VecDeque<(Ipv4Header, Vec<u8>)>,
+    capacity: usize,
+    dropped: u64,
+}
+
+/**
+ * 原始套接字, 相当于 SOCK_RAW: 发送时只给出载荷, IP 首部由这里构造;
+ * 收到的数据报按协议号复制给所有绑定该协议的套接字
+ * 协议栈处理的协议(IpProtocol::handled)照常处理, 原始套接字只是旁观者; 其他协议由原始套接字独占
+ */
+pub struct RawSockets {
+    s_addr: u32,
+    config: Ipv4Config,
+    sockets: BTreeMap<RawSocketHandle, RawSocket>,
+    next_handle: usize,
+    ip_id: u16,
+}
+
+impl RawSockets {
+    pub fn new(s_addr: u32, config: &Ipv4Config) -> Self {
+        RawSockets { s_addr, config: config.clone(), sockets: BTreeMap::new(), next_handle: 0, ip_id: 0 }
+    }
+
+    pub fn raw_socket(&mut self, protocol: IpProtocol) -> RawSocketHandle {
+        let handle = RawSocketHandle(self.next_handle);
+        self.next_handle += 1;
+        self.sockets.insert(handle, RawSocket { protocol, queue: VecDeque::new(), capacity: RAW_QUEUE_CAPACITY, dropped: 0 });
+        handle
+    }
+
+    /**
+     * 关闭套接字, 缓存中未读的数据报一起丢弃
+     */
+    pub fn close(&mut self, handle: RawSocketHandle) -> bool {
+        self.sockets.remove(&handle).is_some()
+    }
+
+    /**
+     * 缩小时已经缓存的数据报保留, 读出之前新到的都丢弃
+     */
+    pub fn set_queue_capacity(&mut self, handle: RawSocketHandle, capacity: usize) {
+        if let Some(socket) = self.sockets.get_mut(&handle) {
+            socket.capacity = capacity;
+        }
+    }
+
+    /**
+     * 以套接字的协议号把 payload 封装成发往 d_addr 的数据报, 由调用方交给出口
+     * 套接字已经关闭时返回 None
+     */
+    pub fn send(&mut self, handle: RawSocketHandle, d_addr: u32, payload: Vec<u8>, options: &RawSendOptions) -> Option<Ipv4Datagram> {
+        let protocol = self.sockets.get(&handle)?.protocol.number();
+        self.ip_id = self.ip_id.wrapping_add(1);
+        let flag = if options.dont_fragment { 0b010 } else { 0 };
+        Some(Ipv4Datagram::new(4, 5, options.tos, (20 + payload.len()) as u16, self.ip_id, flag, 0,
+            options.ttl.unwrap_or(self.config.default_ttl), protocol, self.s_addr, d_addr, payload))
+    }
+
+    /**
+     * 收到的数据报复制给绑定了它的协议的套接字, 返回收下副本的套接字个数(队列满而丢弃的不算)
+     * 协议栈不处理的协议返回 0 时没有任何消费者, 调用方可以回协议不可达
+     */
+    pub fn deliver(&mut self, datagram: &Ipv4Datagram) -> usize {
+        let protocol = IpProtocol::from(datagram.protocol());
+        let mut delivered = 0;
+        for socket in self.sockets.values_mut().filter(|socket| socket.protocol == protocol) {
+            if socket.queue.len() >= socket.capacity {
+                socket.dropped += 1;
+                continue;
+            }
+            socket.queue.push_back((datagram.header(), datagram.payload().to_vec()));
+            delivered += 1;
+        }
+        delivered
+    }
+
+    pub fn recv(&mut self, handle: RawSocketHandle) -> Option<(Ipv4Header, Vec<u8>)> {
+        self.sockets.get_mut(&handle)?.queue.pop_front()
+    }
+
+    /**
+     * 因队列满而丢弃的数据报个数
+     */
+    pub fn dropped(&self, handle: RawSocketHandle) -> u64 {
+        self.sockets.get(&handle).map_or(0, |socket| socket.dropped)
+    }
+
+    pub fn pending(&self, handle: RawSocketHandle) -> usize {
+        self.sockets.get(&handle).map_or(0, |socket| socket.queue.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded_queue_counts_drops() {
+        let mut sockets = RawSockets::new(0x0a000001, &Ipv4Config::default());
+        let handle = sockets.raw_socket(IpProtocol::Other(253));
+        sockets.set_queue_capacity(handle, 2);
+        let datagram = Ipv4Datagram::new(4, 5, 0, 21, 7, 0, 0, 64, 253, 0x0a000002, 0x0a000001, vec![1]);
+        let delivered: Vec<usize> = (0..3).map(|_| sockets.deliver(&datagram)).collect();
+        assert_eq!(delivered, vec![1, 1, 0]);
+        assert_eq!((sockets.pending(handle), sockets.dropped(handle)), (2, 1));
+        let (header, payload) = sockets.recv(handle).unwrap();
+        assert_eq!((header.s_addr, header.protocol, header.id, payload), (0x0a000002, 253, 7, vec![1]));
+        assert!(sockets.close(handle));
+        assert!(sockets.send(handle, 0x0a000002, vec![], &RawSendOptions::default()).is_none());
+    }
+}
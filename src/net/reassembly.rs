@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::config::Ipv4Config;
+use crate::net::ipv4::Ipv4Datagram;
+use crate::utils::drops::{DropCounters, DropReason};
+use crate::utils::memory::{MemoryBudget, MemoryComponent};
+use crate::utils::timer::TimerQueue;
+
+/**
+ * 同一个原数据报的分片: (源地址, 目的地址, 协议, id)
+ */
+type FragmentKey = (u32, u32, u8, u16);
+
+struct Pending {
+    fragments: BTreeMap<usize, Vec<u8>>, // 偏移 -> 载荷
+    total_len: Option<usize>,            // 收到最后一片后才知道
+    first: Option<Ipv4Datagram>,         // 偏移为 0 的分片, 提供首部字段
+    charged: usize,                      // 记在预算上的字节数
+}
+
+/**
+ * IPv4 分片重组 (RFC 791), 第一片到达后 reassembly_timeout_ms 内没有收齐则整体丢弃
+ * 重叠的分片以先到的为准, 每个未收齐的数据报的超时排在定时器队列里
+ * 缓存的分片向内存预算记账; 预算不够时拒绝开始新的重组, 已开始的重组丢弃放不下的分片
+ */
+pub struct Ipv4Reassembler {
+    timeout_ms: u64,
+    pending: HashMap<FragmentKey, Pending>,
+    timers: TimerQueue<FragmentKey>,
+    drops: DropCounters,
+    memory: MemoryBudget,
+}
+
+impl Ipv4Reassembler {
+    pub fn new(config: &Ipv4Config) -> Self {
+        Ipv4Reassembler { timeout_ms: config.reassembly_timeout_ms, pending: HashMap::new(), timers: TimerQueue::new(), drops: DropCounters::new(), memory: MemoryBudget::unlimited() }
+    }
+
+    /**
+     * 之后缓存的分片向 budget 记账, 一般在收到任何分片之前设置
+     */
+    pub fn set_memory_budget(&mut self, budget: MemoryBudget) {
+        self.memory = budget;
+    }
+
+    /**
+     * 不是分片的数据报原样返回; 分片收齐时返回重组后的数据报
+     */
+    pub fn push(&mut self, datagram: Ipv4Datagram, now_ms: u64) -> Option<Ipv4Datagram> {
+        if !datagram.is_fragment() {
+            return Some(datagram);
+        }
+        let key = (datagram.s_addr(), datagram.d_addr(), datagram.protocol(), datagram.id());
+        let offset = datagram.fragment_offset();
+        let duplicate = self.pending.get(&key).is_some_and(|pending| pending.fragments.contains_key(&offset));
+        if !duplicate && !self.memory.try_charge(MemoryComponent::Fragments, datagram.payload().len()) {
+            self.drops.record(DropReason::NoMemory);
+            return None;
+        }
+        if !self.pending.contains_key(&key) {
+            self.timers.schedule(key, now_ms + self.timeout_ms);
+        }
+        let pending = self.pending.entry(key).or_insert_with(|| Pending {
+            fragments: BTreeMap::new(),
+            total_len: None,
+            first: None,
+            charged: 0,
+        });
+        if !datagram.more_fragments() {
+            pending.total_len = Some(offset + datagram.payload().len());
+        }
+        if !duplicate {
+            pending.charged += datagram.payload().len();
+            pending.fragments.insert(offset, datagram.payload().to_vec());
+        }
+        if offset == 0 {
+            pending.first = Some(datagram);
+        }
+
+        let total_len = pending.total_len?;
+        let mut covered = 0;
+        for (offset, data) in &pending.fragments {
+            if *offset > covered {
+                return None;
+            }
+            covered = covered.max(offset + data.len());
+        }
+        if covered < total_len {
+            return None;
+        }
+
+        let pending = self.pending.remove(&key).unwrap();
+        self.timers.cancel(&key);
+        self.memory.release(MemoryComponent::Fragments, pending.charged);
+        let mut payload = vec![0u8; total_len];
+        for (offset, data) in pending.fragments.iter().rev() {
+            let end = (offset + data.len()).min(total_len);
+            payload[*offset..end].copy_from_slice(&data[..end - offset]);
+        }
+        let first = pending.first?;
+        Some(Ipv4Datagram::new(4, 5, 0, (20 + total_len) as u16, first.id(), 0, 0, first.ttl(), first.protocol(),
+            first.s_addr(), first.d_addr(), payload))
+    }
+
+    /**
+     * 丢弃超时未收齐的数据报, 返回丢弃的个数
+     */
+    pub fn expire(&mut self, now_ms: u64) -> usize {
+        let expired = self.timers.expired(now_ms);
+        for key in &expired {
+            if let Some(pending) = self.pending.remove(key) {
+                self.memory.release(MemoryComponent::Fragments, pending.charged);
+                self.drops.record(DropReason::ReassemblyTimeout);
+            }
+        }
+        expired.len()
+    }
+
+    /**
+     * 最早的重组超时时刻, 没有未收齐的数据报时为 None
+     */
+    pub fn next_deadline_ms(&mut self) -> Option<u64> {
+        self.timers.next_deadline()
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn drop_counters(&self) -> &DropCounters {
+        &self.drops
+    }
+}
+
+impl Drop for Ipv4Reassembler {
+    fn drop(&mut self) {
+        let charged = self.pending.values().map(|pending| pending.charged).sum();
+        self.memory.release(MemoryComponent::Fragments, charged);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datagram(id: u16) -> Ipv4Datagram {
+        let payload: Vec<u8> = (0..4000u32).map(|i| (i * 7) as u8).collect();
+        Ipv4Datagram::new(4, 5, 0, 4020, id, 0, 0, 64, 17, 0x0a000001, 0x0a000002, payload)
+    }
+
+    #[test]
+    fn test_out_of_order_reassembly() {
+        let mut reassembler = Ipv4Reassembler::new(&Ipv4Config::default());
+        let mut fragments = datagram(1).fragment(1000).unwrap();
+        fragments.reverse();
+        let last = fragments.pop().unwrap();
+        for fragment in fragments {
+            assert!(reassembler.push(fragment, 0).is_none());
+        }
+        let whole = reassembler.push(last, 0).unwrap();
+        assert_eq!(whole.payload(), datagram(1).payload());
+        assert!(whole.check_hdr_checksum());
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn test_incomplete_datagram_expires() {
+        let config = Ipv4Config::default();
+        let mut reassembler = Ipv4Reassembler::new(&config);
+        let fragments = datagram(2).fragment(1000).unwrap();
+        assert!(reassembler.push(fragments.into_iter().next().unwrap(), 0).is_none());
+        assert_eq!(reassembler.next_deadline_ms(), Some(config.reassembly_timeout_ms));
+        assert_eq!(reassembler.expire(config.reassembly_timeout_ms - 1), 0);
+        assert_eq!(reassembler.expire(config.reassembly_timeout_ms), 1);
+        assert_eq!(reassembler.drop_counters().get(DropReason::ReassemblyTimeout), 1);
+        assert_eq!(reassembler.next_deadline_ms(), None);
+    }
+
+    #[test]
+    fn test_completed_datagram_cancels_timeout() {
+        let config = Ipv4Config::default();
+        let mut reassembler = Ipv4Reassembler::new(&config);
+        let mut fragments = datagram(5).fragment(1000).unwrap().into_iter();
+        reassembler.push(fragments.next().unwrap(), 0);
+        let late = datagram(6).fragment(1000).unwrap().into_iter().next().unwrap();
+        reassembler.push(late, 100);
+        assert!(fragments.fold(None, |_, fragment| reassembler.push(fragment, 50)).is_some());
+        assert_eq!(reassembler.next_deadline_ms(), Some(100 + config.reassembly_timeout_ms));
+        assert_eq!(reassembler.expire(config.reassembly_timeout_ms), 0);
+        assert_eq!(reassembler.pending(), 1);
+    }
+
+    #[test]
+    fn test_memory_budget_refuses_new_reassembly() {
+        let budget = MemoryBudget::new(2500);
+        let mut reassembler = Ipv4Reassembler::new(&Ipv4Config::default());
+        reassembler.set_memory_budget(budget.clone());
+        let mut first = datagram(3).fragment(1000).unwrap().into_iter();
+        let mut second = datagram(4).fragment(1000).unwrap().into_iter();
+        reassembler.push(first.next().unwrap(), 0);
+        reassembler.push(first.next().unwrap(), 0);
+        assert!(reassembler.push(second.next().unwrap(), 0).is_none()); // 976 字节放不下
+        assert_eq!((reassembler.pending(), budget.used()), (1, 1952));
+        assert_eq!(reassembler.drop_counters().get(DropReason::NoMemory), 1);
+
+        reassembler.expire(Ipv4Config::default().reassembly_timeout_ms);
+        assert_eq!(budget.used(), 0);
+        let whole = second.fold(None, |_, fragment| reassembler.push(fragment, 0));
+        assert!(whole.is_none()); // 缺第一片
+        drop(reassembler);
+        assert_eq!(budget.used(), 0);
+    }
+}
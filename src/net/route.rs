@@ -0,0 +1,174 @@
+use crate::config::TcpConfig;
+
+const IP_TCP_HDR_LEN: u16 = 40;
+
+/**
+ * 路由上的 TCP 参数覆盖, None 表示沿用全局配置
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RouteMetrics {
+    pub mtu: Option<u16>,
+    pub initial_cwnd: Option<u32>,
+    pub recv_window: Option<usize>,
+}
+
+/**
+ * 一条路由: 目的网段、下一跳(None 表示直连)、出接口、首选源地址和路由参数
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub dest: u32,
+    pub prefix_len: u8,
+    pub gateway: Option<u32>,
+    pub interface: usize,
+    pub preferred_source: Option<u32>,
+    pub metrics: RouteMetrics,
+}
+
+impl Route {
+    pub fn contains(&self, addr: u32) -> bool {
+        addr & prefix_mask(self.prefix_len) == self.dest
+    }
+}
+
+pub fn prefix_mask(prefix_len: u8) -> u32 {
+    u32::MAX.checked_shl(32 - prefix_len.min(32) as u32).unwrap_or(0)
+}
+
+/**
+ * 路由表, 按最长前缀匹配
+ */
+#[derive(Debug, Clone, Default)]
+pub struct RoutingTable {
+    routes: Vec<Route>,
+}
+
+impl RoutingTable {
+    pub fn new() -> Self {
+        RoutingTable { routes: Vec::new() }
+    }
+
+    /**
+     * 添加或替换同一网段的路由, 出接口为 0, dest 中主机位清零
+     */
+    pub fn add(&mut self, dest: u32, prefix_len: u8, gateway: Option<u32>) {
+        self.add_on(dest, prefix_len, gateway, 0);
+    }
+
+    pub fn add_on(&mut self, dest: u32, prefix_len: u8, gateway: Option<u32>, interface: usize) {
+        let dest = dest & prefix_mask(prefix_len);
+        self.remove(dest, prefix_len);
+        self.routes.push(Route { dest, prefix_len, gateway, interface, preferred_source: None, metrics: RouteMetrics::default() });
+    }
+
+    /**
+     * 设置路由的首选源地址, 路由不存在时返回 false
+     */
+    pub fn set_preferred_source(&mut self, route: (u32, u8), source: Option<u32>) -> bool {
+        let (dest, prefix_len) = (route.0 & prefix_mask(route.1), route.1);
+        match self.routes.iter_mut().find(|r| r.dest == dest && r.prefix_len == prefix_len) {
+            Some(route) => {
+                route.preferred_source = source;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove(&mut self, dest: u32, prefix_len: u8) -> Option<Route> {
+        let dest = dest & prefix_mask(prefix_len);
+        let index = self.routes.iter().position(|route| route.dest == dest && route.prefix_len == prefix_len)?;
+        Some(self.routes.remove(index))
+    }
+
+    /**
+     * 设置 (dest, prefix_len) 这条路由的参数, 路由不存在时返回 false
+     */
+    pub fn set_metrics(&mut self, route: (u32, u8), metrics: RouteMetrics) -> bool {
+        let (dest, prefix_len) = (route.0 & prefix_mask(route.1), route.1);
+        match self.routes.iter_mut().find(|r| r.dest == dest && r.prefix_len == prefix_len) {
+            Some(route) => {
+                route.metrics = metrics;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn lookup(&self, addr: u32) -> Option<&Route> {
+        self.lookup_with(addr, |_| true)
+    }
+
+    /**
+     * 只在 usable 的路由中做最长前缀匹配, 如跳过出接口链路断开的路由
+     */
+    pub fn lookup_with(&self, addr: u32, usable: impl Fn(&Route) -> bool) -> Option<&Route> {
+        self.routes.iter().filter(|route| route.contains(addr) && usable(route)).max_by_key(|route| route.prefix_len)
+    }
+
+    /**
+     * 新连接使用的 TCP 配置: 以 base 为基础, 用目的地址所走路由的参数覆盖
+     * mtu 决定 MSS; 接收窗口不超过 window_scale 能通告的上限
+     */
+    pub fn tcp_config_for(&self, addr: u32, base: &TcpConfig) -> TcpConfig {
+        let mut config = base.clone();
+        let Some(metrics) = self.lookup(addr).map(|route| route.metrics) else {
+            return config;
+        };
+        if let Some(mtu) = metrics.mtu {
+            config.mss = mtu.saturating_sub(IP_TCP_HDR_LEN).max(1);
+        }
+        if let Some(cwnd) = metrics.initial_cwnd {
+            config.initial_cwnd = cwnd.max(1);
+        }
+        if let Some(window) = metrics.recv_window {
+            config.recv_buffer = window.clamp(1, config.max_advertisable_window());
+        }
+        config
+    }
+
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_longest_prefix_match() {
+        let mut table = RoutingTable::new();
+        table.add(0, 0, Some(0x0a000001));
+        table.add(0x0a000005, 24, None); // 主机位被清零
+        table.add(0x0a000080, 25, Some(0x0a000002));
+        assert_eq!(table.lookup(0x0a000010).unwrap().dest, 0x0a000000);
+        assert_eq!(table.lookup(0x0a0000f0).unwrap().prefix_len, 25);
+        assert_eq!(table.lookup(0x08080808).unwrap().prefix_len, 0);
+
+        assert!(table.remove(0x0a000080, 25).is_some());
+        assert_eq!(table.lookup(0x0a0000f0).unwrap().prefix_len, 24);
+        assert!(!table.set_metrics((0x0a000080, 25), RouteMetrics::default()));
+    }
+
+    #[test]
+    fn test_metrics_override_tcp_config() {
+        let mut table = RoutingTable::new();
+        table.add(0x7f000000, 8, None);
+        table.set_metrics((0x7f000000, 8), RouteMetrics { mtu: Some(65535), initial_cwnd: Some(64), recv_window: Some(usize::MAX) });
+        let base = TcpConfig::default();
+        let config = table.tcp_config_for(0x7f000001, &base);
+        assert_eq!(config.mss, 65495);
+        assert_eq!(config.initial_cwnd, 64);
+        assert_eq!(config.recv_buffer, base.max_advertisable_window());
+        assert_eq!(table.tcp_config_for(0x0a000001, &base), base); // 没有路由
+    }
+}
@@ -0,0 +1,104 @@
+use crate::config::Ipv4Config;
+use crate::net::route::RoutingTable;
+use crate::utils::drops::{DropCounters, DropReason};
+
+const BROADCAST: u32 = 0xffff_ffff;
+
+/**
+ * 数据报从哪个接口进来
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arrival {
+    pub interface: usize,
+    pub loopback: bool,
+}
+
+/**
+ * 线路上不可能合法出现的源地址 (RFC 1812 5.3.7, RFC 6890)
+ * 0.0.0.0 -> 255.255.255.255 是 DHCP 客户端还没有地址时的报文, 放行
+ */
+pub fn is_martian_source(s_addr: u32, d_addr: u32) -> bool {
+    match s_addr >> 24 {
+        0 => !(s_addr == 0 && d_addr == BROADCAST),
+        127 => true,
+        224..=255 => true, // 组播、E 类以及受限广播
+        _ => false,
+    }
+}
+
+/**
+ * 入方向的源地址检查, 每个被拒绝的数据报按原因计数
+ * 本机地址作为源只允许从回环接口进来; 回环接口上的数据报不做其他检查
+ */
+#[derive(Debug, Clone)]
+pub struct SourceGuard {
+    martian_filter: bool,
+    strict_rpf: bool,
+    local_addrs: Vec<u32>,
+    drops: DropCounters,
+}
+
+impl SourceGuard {
+    pub fn new(config: &Ipv4Config, local_addrs: &[u32]) -> Self {
+        SourceGuard {
+            martian_filter: config.martian_filter,
+            strict_rpf: config.strict_rpf,
+            local_addrs: local_addrs.to_vec(),
+            drops: DropCounters::new(),
+        }
+    }
+
+    /**
+     * 数据报可以继续向上层交付时返回 true
+     */
+    pub fn accept(&mut self, s_addr: u32, d_addr: u32, arrival: Arrival, routes: &RoutingTable) -> bool {
+        match self.check(s_addr, d_addr, arrival, routes) {
+            Ok(()) => true,
+            Err(reason) => {
+                self.drops.record(reason);
+                false
+            }
+        }
+    }
+
+    fn check(&self, s_addr: u32, d_addr: u32, arrival: Arrival, routes: &RoutingTable) -> Result<(), DropReason> {
+        if arrival.loopback {
+            return Ok(());
+        }
+        if self.local_addrs.contains(&s_addr) {
+            return Err(DropReason::LocalSource);
+        }
+        if self.martian_filter && is_martian_source(s_addr, d_addr) {
+            return Err(DropReason::MartianSource);
+        }
+        if self.strict_rpf && s_addr != 0 {
+            // 回程路由必须存在且走到达的接口
+            match routes.lookup(s_addr) {
+                Some(route) if route.interface == arrival.interface => {}
+                _ => return Err(DropReason::ReversePath),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn drop_counters(&self) -> &DropCounters {
+        &self.drops
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_martian_categories() {
+        assert!(!is_martian_source(0, BROADCAST)); // DHCP DISCOVER
+        assert!(is_martian_source(0, 0x0a000001));
+        assert!(is_martian_source(0x00000005, BROADCAST));
+        assert!(is_martian_source(0x7f000001, 0x0a000001));
+        assert!(is_martian_source(0xe0000001, 0x0a000001));
+        assert!(is_martian_source(0xf0000001, 0x0a000001));
+        assert!(is_martian_source(BROADCAST, 0x0a000001));
+        assert!(!is_martian_source(0x08080808, 0x0a000001));
+    }
+}
@@ -0,0 +1,28 @@
+/**
+ * 常用类型的统一入口, `use simple_tcp_ip::prelude::*` 即可搭起一个协议栈
+ * 地址在整个 crate 中用 u32 (IPv4) 与 [u8; 6] (MAC) 表示, 文本形式经 parse_ipv4 / format_mac 等转换
+ */
+pub use crate::config::{ArpConfig, ConfigError, IcmpConfig, Ipv4Config, NatConfig, StackConfig, TcpConfig};
+pub use crate::error::{DeviceError, ParseError, SendError, SerializeError, StackError};
+
+pub use crate::link::device::{ChannelPair, NetworkDevice};
+pub use crate::link::interface::EthernetInterface;
+
+pub use crate::net::interfaces::InterfaceSet;
+pub use crate::net::ipv4::{Ipv4BuildError, Ipv4Datagram, Ipv4DatagramBuilder, Ipv4ParseError};
+pub use crate::net::loopback::LoopbackInterface;
+pub use crate::net::raw_socket::IpProtocol;
+
+pub use crate::stack::Stack;
+
+#[cfg(feature = "async")]
+pub use crate::transport::async_stream::{AsyncStack, AsyncTcpListener, AsyncTcpStream};
+pub use crate::transport::connection_table::{ConnectionTable, Readiness};
+pub use crate::transport::shared_table::{PollError, SharedTable};
+pub use crate::transport::stream::Stream;
+pub use crate::transport::tcp_connection::{ConnectionError, ConnectionId, TcpState};
+pub use crate::transport::tcp_segment::{TcpParseError, TcpSegment};
+pub use crate::transport::udp::{UdpDatagram, UdpParseError};
+
+pub use crate::utils::addr::{format_ipv4, format_mac, parse_cidr, parse_ipv4, parse_mac};
+pub use crate::utils::wire::{WireDeserialize, WireSerialize};
@@ -0,0 +1,233 @@
+use crate::config::{ConfigError, StackConfig};
+use crate::link::arp_queue::ArpFailure;
+use crate::link::device::NetworkDevice;
+use crate::link::interface::EthernetInterface;
+use crate::net::interfaces::{InterfaceId, InterfaceSet, LinkEvent};
+use crate::net::ipv4::Ipv4Datagram;
+use crate::net::reassembly::Ipv4Reassembler;
+use crate::net::source_guard::Arrival;
+use crate::transport::connection_table::{ConnectionTable, ShutdownReport};
+use crate::transport::tcp_connection::ConnectionId;
+use crate::transport::tcp_segment::TcpSegment;
+use crate::utils::drops::DropReason;
+use crate::utils::memory::MemoryBudget;
+use crate::utils::metrics::StackMetrics;
+
+const PROTOCOL_ICMP: u8 = 1;
+const PROTOCOL_TCP: u8 = 6;
+
+/**
+ * 协议栈: 多个接口 (InterfaceSet) 和它们上面的连接表
+ * 设备收到的帧经 frame_received / pump 交给接口, poll 处理帧、推进定时器并发出连接表要发的段, 帧由 take_tx / pump 交给设备
+ * 交给本机的分片先重组; TCP 进连接表, ICMP 差错交给连接表, 其余的数据报留给 take_delivered
+ * ARP 解析失败让到该地址的连接以主机不可达失败, 链路断开超过 link_down_abort_ms 让经过它的连接失败
+ */
+pub struct Stack {
+    net: InterfaceSet,
+    tcp: ConnectionTable,
+    reassembler: Ipv4Reassembler,
+    config: StackConfig,
+    memory: Option<MemoryBudget>,
+    delivered: Vec<(Ipv4Datagram, Arrival)>,
+    link_events: Vec<LinkEvent>,
+}
+
+impl Stack {
+    pub fn new(config: &StackConfig) -> Result<Self, ConfigError> {
+        config.validate()?;
+        Ok(Stack {
+            net: InterfaceSet::new(&config.ipv4, &config.arp),
+            tcp: ConnectionTable::new(&config.tcp),
+            reassembler: Ipv4Reassembler::new(&config.ipv4),
+            config: config.clone(),
+            memory: None,
+            delivered: vec![],
+            link_events: vec![],
+        })
+    }
+
+    /**
+     * 添加接口, 编号按添加顺序分配; 接口的每个地址得到一条直连路由
+     */
+    pub fn add_interface(&mut self, iface: EthernetInterface) -> InterfaceId {
+        self.net.add_interface(iface)
+    }
+
+    /**
+     * 路由表、链路状态、NAT 等 IP 层的设置
+     */
+    pub fn interfaces(&self) -> &InterfaceSet {
+        &self.net
+    }
+
+    pub fn interfaces_mut(&mut self) -> &mut InterfaceSet {
+        &mut self.net
+    }
+
+    /**
+     * 监听、读写、关闭等 TCP 操作; 连接表要发的段在下一次 poll 时发出
+     */
+    pub fn tcp(&self) -> &ConnectionTable {
+        &self.tcp
+    }
+
+    pub fn tcp_mut(&mut self) -> &mut ConnectionTable {
+        &mut self.tcp
+    }
+
+    /**
+     * 连接表缓存的数据和重组中的分片向 budget 记账, 指标中一并输出
+     */
+    pub fn set_memory_budget(&mut self, budget: MemoryBudget) {
+        self.tcp.set_memory_budget(budget.clone());
+        self.reassembler.set_memory_budget(budget.clone());
+        self.memory = Some(budget);
+    }
+
+    /**
+     * 设备交上来的一帧, 在下一次 poll 时处理
+     */
+    pub fn frame_received(&mut self, id: InterfaceId, bytes: Vec<u8>) {
+        self.net.frame_received(id, bytes);
+    }
+
+    /**
+     * 取走要交给接口 id 的设备的帧
+     */
+    pub fn take_tx(&mut self, id: InterfaceId) -> Vec<Vec<u8>> {
+        self.net.take_tx(id)
+    }
+
+    /**
+     * 与接口 id 的设备交换帧, 见 InterfaceSet::pump
+     */
+    pub fn pump(&mut self, id: InterfaceId, device: &mut dyn NetworkDevice, max_rx: usize) -> (usize, usize) {
+        self.net.pump(id, device, max_rx)
+    }
+
+    /**
+     * 发起连接并立即发出 SYN; 没有路由时返回丢弃原因, 连接照常按 RTO 重传
+     */
+    pub fn connect(&mut self, id: ConnectionId, now_ms: u64) -> Result<InterfaceId, DropReason> {
+        let syn = self.tcp.connect(id, now_ms);
+        self.send(id, &syn, now_ms)
+    }
+
+    /**
+     * 把连接的报文段封装成数据报交给 IP 层, 返回出接口; 连接和监听端口都不存在时为 NoListener
+     */
+    pub fn send(&mut self, id: ConnectionId, segment: &TcpSegment, now_ms: u64) -> Result<InterfaceId, DropReason> {
+        let datagram = self.tcp.datagram(id, segment, &self.config.ipv4).ok_or(DropReason::NoListener)?;
+        self.net.send(datagram, Some(id), now_ms)
+    }
+
+    /**
+     * 一次驱动: 处理收到的帧, 推进 ARP、重组和 TCP 的定时器, 发出连接表要发的段
+     * 同一时刻重复调用是幂等的
+     */
+    pub fn poll(&mut self, now_ms: u64) {
+        self.net.poll(usize::MAX, now_ms);
+        for failure in self.net.tick(now_ms) {
+            if let ArpFailure::Local { d_addr, .. } = failure {
+                self.tcp.host_unreachable(d_addr, now_ms);
+            }
+        }
+        for event in self.net.take_link_events() {
+            if let LinkEvent::DownTimeout(id) = event {
+                self.link_down(id, now_ms);
+            }
+            self.link_events.push(event);
+        }
+        self.reassembler.expire(now_ms);
+        for (datagram, arrival) in self.net.take_delivered() {
+            let Some(datagram) = self.reassembler.push(datagram, now_ms) else { continue };
+            match datagram.protocol() {
+                PROTOCOL_TCP => {
+                    for reply in self.tcp.datagram_received(&datagram, now_ms) {
+                        let id = ConnectionId { s_ip: datagram.d_addr(), s_port: reply.s_port, d_ip: datagram.s_addr(), d_port: reply.d_port };
+                        let _ = self.send(id, &reply, now_ms);
+                    }
+                }
+                PROTOCOL_ICMP if self.tcp.icmp_received(&datagram, now_ms).is_some() => {}
+                _ => self.delivered.push((datagram, arrival)),
+            }
+        }
+        self.transmit(now_ms);
+    }
+
+    /**
+     * 连接表要发的段交给 IP 层; 没有路由的段丢弃, 留给重传
+     */
+    fn transmit(&mut self, now_ms: u64) {
+        for (id, segment) in self.tcp.poll(now_ms) {
+            let _ = self.send(id, &segment, now_ms);
+        }
+    }
+
+    /**
+     * 接口 id 的链路断开太久: 经过它的连接以 NetworkDown 失败, 返回这些连接; poll 在 DownTimeout 时调用
+     */
+    pub fn link_down(&mut self, id: InterfaceId, now_ms: u64) -> Vec<ConnectionId> {
+        let net = &self.net;
+        self.tcp.link_down(|d_ip| net.reaches_via(d_ip, id), now_ms)
+    }
+
+    /**
+     * 取走链路状态变化, DownTimeout 已经由 poll 处理
+     */
+    pub fn take_link_events(&mut self) -> Vec<LinkEvent> {
+        std::mem::take(&mut self.link_events)
+    }
+
+    /**
+     * 取走交给本机的非 TCP 数据报 (UDP、ICMP 回显、其他协议) 和它们到达的接口
+     */
+    pub fn take_delivered(&mut self) -> Vec<(Ipv4Datagram, Arrival)> {
+        std::mem::take(&mut self.delivered)
+    }
+
+    /**
+     * 有序关闭: 不再接受新连接, 所有连接 close; 之后照常 poll, 并调用 poll_shutdown 直到得到报告
+     */
+    pub fn shutdown(&mut self, now_ms: u64, deadline_ms: u64) {
+        self.tcp.shutdown(now_ms, deadline_ms);
+    }
+
+    /**
+     * 所有连接都关闭、或者到了截止时间时返回报告, 放弃的连接的 RST 同时交给 IP 层
+     */
+    pub fn poll_shutdown(&mut self, now_ms: u64) -> Option<ShutdownReport> {
+        let report = self.tcp.poll_shutdown(now_ms)?;
+        self.transmit(now_ms);
+        Some(report)
+    }
+
+    /**
+     * 最早需要 poll 的时刻: 连接表、ARP、链路断开和分片重组的定时器, 都没有时为 None
+     */
+    pub fn next_deadline_ms(&mut self) -> Option<u64> {
+        [self.tcp.next_timer_ms(), self.net.next_deadline_ms(), self.reassembler.next_deadline_ms()].into_iter().flatten().min()
+    }
+
+    /**
+     * 距离下一次需要 poll 还有多久, 事件循环据此设置等待的超时; 已经到期时为 0
+     */
+    pub fn time_until_next_event(&mut self, now_ms: u64) -> Option<u64> {
+        self.next_deadline_ms().map(|deadline| deadline.saturating_sub(now_ms))
+    }
+
+    /**
+     * Prometheus 文本格式的指标, 接口按编号命名为 eth0、eth1 …, ARP 表项数为各接口之和
+     */
+    pub fn render_metrics(&self) -> String {
+        let names: Vec<String> = (0..self.net.len()).map(|id| format!("eth{}", id)).collect();
+        let mut metrics = StackMetrics::new().connections(&self.tcp).drops(self.net.drop_counters()).drops(self.reassembler.drop_counters());
+        for (id, name) in names.iter().enumerate() {
+            metrics = metrics.interface(name, self.net.interface(id).unwrap()).arp_cache(self.net.arp_cache(id).unwrap());
+        }
+        if let Some(budget) = &self.memory {
+            metrics = metrics.memory(budget);
+        }
+        metrics.render()
+    }
+}
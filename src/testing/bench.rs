@@ -0,0 +1,354 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::config::TcpConfig;
+use crate::testing::netem::{NetemConfig, NetemLink};
+use crate::transport::connection_table::{listener_id, ConnectionTable};
+use crate::transport::tcp_connection::ConnectionId;
+use crate::transport::tcp_segment::TcpSegment;
+use crate::utils::clock::Clock;
+use crate::utils::stream_reassemble::StreamReassembler;
+use crate::utils::wire::WireSerialize;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+/**
+ * 统计分配次数的全局分配器, 由测试二进制用 #[global_allocator] 安装; 没有安装时计数保持为 0
+ */
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+impl CountingAllocator {
+    pub fn allocations() -> u64 {
+        ALLOCATIONS.load(Ordering::Relaxed)
+    }
+}
+
+/**
+ * 批量传输基准的参数
+ * 协议栈还没有发送端的重传定时器, 这里在 rto_ms 内接收端没有新数据时重传最早的未确认段
+ */
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    pub bytes: usize,
+    pub tcp: TcpConfig,
+    pub link: NetemConfig,
+    pub seed: u64,
+    pub rto_ms: u64,
+}
+
+impl Default for BenchConfig {
+    /**
+     * 4 MB, 100 Mbit/s、单向 5 ms 的无损链路
+     */
+    fn default() -> Self {
+        BenchConfig {
+            bytes: 4 << 20,
+            tcp: TcpConfig { recv_buffer: 256 * 1024, send_buffer: 256 * 1024, initial_cwnd: 64, ..TcpConfig::default() },
+            link: NetemConfig { delay_ms: 5, bandwidth_bps: Some(100_000_000), ..NetemConfig::default() },
+            seed: 1,
+            rto_ms: 200,
+        }
+    }
+}
+
+impl BenchConfig {
+    pub fn megabytes(mut self, mb: usize) -> Self {
+        self.bytes = mb << 20;
+        self
+    }
+}
+
+/**
+ * 一次传输的结果; elapsed_ms 是虚拟时间, 由链路速率决定, 同样的参数和种子结果不变
+ * cpu_ms 是实际耗费的时间, allocations 只有安装了 CountingAllocator 才有意义
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    pub bytes: usize,
+    pub elapsed_ms: u64,
+    pub segments: u64,
+    pub retransmissions: u64,
+    pub allocations: u64,
+    pub cpu_ms: f64,
+}
+
+impl BenchReport {
+    /**
+     * 应用层看到的速率, bit/s
+     */
+    pub fn goodput_bps(&self) -> f64 {
+        self.bytes as f64 * 8.0 * 1000.0 / self.elapsed_ms.max(1) as f64
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"bytes\": {}, \"elapsed_ms\": {}, \"goodput_bps\": {:.0}, \"segments\": {}, \"retransmissions\": {}, \"allocations\": {}, \"cpu_ms\": {:.1}}}",
+            self.bytes, self.elapsed_ms, self.goodput_bps(), self.segments, self.retransmissions, self.allocations, self.cpu_ms
+        )
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "bulk transfer: {} bytes in {} ms (virtual)", self.bytes, self.elapsed_ms)?;
+        writeln!(f, "  goodput         {:.2} Mbit/s", self.goodput_bps() / 1e6)?;
+        writeln!(f, "  segments        {}", self.segments)?;
+        writeln!(f, "  retransmissions {}", self.retransmissions)?;
+        writeln!(f, "  allocations     {}", self.allocations)?;
+        write!(f, "  cpu time        {:.1} ms", self.cpu_ms)
+    }
+}
+
+/**
+ * 两张连接表之间经 NetemLink 传输 config.bytes 字节, 时钟直接推进到下一个事件
+ * clock 只用来统计 cpu_ms, 基准用 MonotonicClock, 单元测试用 ManualClock 保证报告完全确定
+ */
+pub fn bulk_transfer(config: &BenchConfig, clock: &dyn Clock) -> BenchReport {
+    let started = clock.now_ms();
+    let allocations = CountingAllocator::allocations();
+    let mut client = ConnectionTable::new(&config.tcp);
+    let mut server = ConnectionTable::new(&config.tcp);
+    let id = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+    server.listen(B_IP, 80);
+    let mut link = NetemLink::new(config.link.clone(), config.seed);
+    let mut segments = 0;
+    let mut retransmissions = 0;
+
+    let data: Vec<u8> = (0..config.bytes).map(|i| (i % 251) as u8).collect();
+    let mut written = 0;
+    let mut received = 0;
+    let mut peer = None;
+    let mut now = 0;
+    let mut progress_at = 0;
+    link.a_to_b.send(client.connect(id, now).serialize(), now);
+    while received < config.bytes {
+        client.tick(now);
+        server.tick(now);
+        for bytes in link.b_to_a.poll(now) {
+            let Ok(segment) = TcpSegment::try_deserialize(&bytes) else { continue };
+            for reply in client.segment_received(B_IP, A_IP, &segment, now) {
+                link.a_to_b.send(reply.serialize(), now);
+                segments += 1;
+            }
+        }
+        written += client.write(id, &data[written..]).unwrap_or(0);
+        for (_, segment) in client.poll_transmit(usize::MAX) {
+            link.a_to_b.send(segment.serialize(), now);
+            segments += 1;
+        }
+        if client.fast_retransmit_due(id) || now >= progress_at + config.rto_ms {
+            if let Some(segment) = client.retransmission(id) {
+                link.a_to_b.send(segment.serialize(), now);
+                segments += 1;
+                retransmissions += 1;
+            }
+            progress_at = now;
+        }
+
+        for bytes in link.a_to_b.poll(now) {
+            let Ok(segment) = TcpSegment::try_deserialize(&bytes) else { continue };
+            for reply in server.segment_received(A_IP, B_IP, &segment, now) {
+                link.b_to_a.send(reply.serialize(), now);
+                segments += 1;
+            }
+        }
+        for (_, segment) in server.poll_transmit(usize::MAX) {
+            link.b_to_a.send(segment.serialize(), now);
+            segments += 1;
+        }
+        peer = peer.or_else(|| server.accept(listener_id(B_IP, 80)));
+        if let Some(peer) = peer {
+            let n = server.read(peer, usize::MAX).map_or(0, |data| data.len());
+            if n > 0 {
+                received += n;
+                progress_at = now;
+            }
+        }
+        let next = [link.next_delivery_ms(), client.loss_timer_ms(id), Some(progress_at + config.rto_ms)].into_iter().flatten().min();
+        now = next.unwrap_or(now + 1).max(now + 1);
+    }
+
+    BenchReport {
+        bytes: config.bytes,
+        elapsed_ms: now,
+        segments,
+        retransmissions,
+        allocations: CountingAllocator::allocations() - allocations,
+        cpu_ms: (clock.now_ms() - started) as f64,
+    }
+}
+
+/**
+ * 按序重组微基准的结果; allocations 只统计 recv 调用内的分配, 取走数据时的分配不算
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReassemblyReport {
+    pub bytes: usize,
+    pub calls: u64,
+    pub fast_path: u64,
+    pub slow_path: u64,
+    pub allocations: u64,
+    pub cpu_ms: f64,
+}
+
+impl fmt::Display for ReassemblyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "in-order reassembly: {} bytes in {} recv calls", self.bytes, self.calls)?;
+        writeln!(f, "  fast path       {}", self.fast_path)?;
+        writeln!(f, "  slow path       {}", self.slow_path)?;
+        writeln!(f, "  allocations     {}", self.allocations)?;
+        write!(f, "  cpu time        {:.1} ms", self.cpu_ms)
+    }
+}
+
+/**
+ * 按序把 segments 个 segment_len 字节的段交给重组器, 每段之后读空; 每四段有一段连同前一段的后半重传
+ * 第一段让环形缓冲区长到稳定的容量, 不计入结果; cpu_ms 按 clock 计时
+ */
+pub fn in_order_reassembly(segment_len: usize, segments: usize, clock: &dyn Clock) -> ReassemblyReport {
+    let data: Vec<u8> = (0..2 * segment_len).map(|i| (i % 251) as u8).collect();
+    let mut reassembler = StreamReassembler::new(4 * segment_len);
+    reassembler.recv(&data[..segment_len], 0, false);
+    black_box(reassembler.pop_assembled(usize::MAX));
+    let (fast_before, slow_before) = reassembler.path_counts();
+
+    let started = clock.now_ms();
+    let mut allocations = 0;
+    let mut offset = segment_len;
+    for i in 0..segments {
+        let overlap = if i % 4 == 3 { segment_len / 2 } else { 0 };
+        let before = CountingAllocator::allocations();
+        reassembler.recv(black_box(&data[..segment_len + overlap]), offset - overlap, false);
+        allocations += CountingAllocator::allocations() - before;
+        offset += segment_len;
+        black_box(reassembler.pop_assembled(usize::MAX));
+    }
+
+    let (fast, slow) = reassembler.path_counts();
+    ReassemblyReport {
+        bytes: segments * segment_len,
+        calls: segments as u64,
+        fast_path: fast - fast_before,
+        slow_path: slow - slow_before,
+        allocations,
+        cpu_ms: (clock.now_ms() - started) as f64,
+    }
+}
+
+/**
+ * 基线文件中记录的 goodput, 文件内容与 BenchReport::to_json 的格式相同
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Baseline {
+    pub goodput_bps: f64,
+}
+
+impl Baseline {
+    /**
+     * 只认扁平对象中的数字字段, 没有 goodput_bps 时返回 None
+     */
+    pub fn from_json(json: &str) -> Option<Baseline> {
+        Some(Baseline { goodput_bps: json_number(json, "goodput_bps")? })
+    }
+}
+
+fn json_number(json: &str, key: &str) -> Option<f64> {
+    let start = json.find(&format!("\"{}\"", key))? + key.len() + 2;
+    let rest = json[start..].trim_start().strip_prefix(':')?.trim_start();
+    let end = rest.find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E'))).unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+/**
+ * goodput 相对基线下降超过阈值
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Regression {
+    pub baseline_bps: f64,
+    pub measured_bps: f64,
+    pub drop_percent: f64,
+}
+
+impl fmt::Display for Regression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "goodput dropped {:.1}%: {:.0} bit/s, baseline {:.0} bit/s", self.drop_percent, self.measured_bps, self.baseline_bps)
+    }
+}
+
+impl std::error::Error for Regression {}
+
+/**
+ * goodput 比基线低超过 max_drop_percent 时返回 Err; 比基线高不算回退
+ */
+pub fn compare(baseline: &Baseline, report: &BenchReport, max_drop_percent: f64) -> Result<(), Regression> {
+    let measured_bps = report.goodput_bps();
+    let drop_percent = (baseline.goodput_bps - measured_bps) / baseline.goodput_bps * 100.0;
+    if drop_percent > max_drop_percent {
+        return Err(Regression { baseline_bps: baseline.goodput_bps, measured_bps, drop_percent });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock::ManualClock;
+
+    fn report(elapsed_ms: u64) -> BenchReport {
+        BenchReport { bytes: 1_000_000, elapsed_ms, segments: 0, retransmissions: 0, allocations: 0, cpu_ms: 0.0 }
+    }
+
+    #[test]
+    fn test_compare_threshold() {
+        let baseline = Baseline { goodput_bps: 8_000_000.0 };
+        assert_eq!(report(1000).goodput_bps(), 8_000_000.0);
+        assert!(compare(&baseline, &report(1000), 5.0).is_ok());
+        assert!(compare(&baseline, &report(500), 5.0).is_ok()); // 变快了
+        assert!(compare(&baseline, &report(1050), 5.0).is_ok()); // 下降约 4.8%
+
+        let regression = compare(&baseline, &report(1250), 5.0).unwrap_err();
+        assert_eq!(regression.measured_bps, 6_400_000.0);
+        assert!((regression.drop_percent - 20.0).abs() < 1e-9);
+        assert!(compare(&baseline, &report(1250), 25.0).is_ok());
+    }
+
+    #[test]
+    fn test_baseline_round_trips_through_json() {
+        let json = report(1000).to_json();
+        assert_eq!(Baseline::from_json(&json), Some(Baseline { goodput_bps: 8_000_000.0 }));
+        assert_eq!(Baseline::from_json("{\n  \"goodput_bps\" : 1.5e6\n}"), Some(Baseline { goodput_bps: 1.5e6 }));
+        assert_eq!(Baseline::from_json("{\"bytes\": 10}"), None);
+    }
+
+    #[test]
+    fn test_small_transfer_is_deterministic() {
+        let config = BenchConfig::default().megabytes(1);
+        let clock = ManualClock::new(0);
+        let first = bulk_transfer(&config, &clock);
+        let second = bulk_transfer(&config, &clock);
+        assert_eq!((first.elapsed_ms, first.segments, first.retransmissions), (second.elapsed_ms, second.segments, second.retransmissions));
+        assert_eq!((first.cpu_ms, second.cpu_ms), (0.0, 0.0));
+        assert_eq!(first.retransmissions, 0);
+    }
+}
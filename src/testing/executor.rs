@@ -0,0 +1,96 @@
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
+use std::thread::{self, Thread};
+
+/**
+ * 唤醒时 unpark 执行 block_on 的线程
+ */
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/**
+ * 测试用的最小执行器: 在当前线程上 poll 直到完成, Pending 时 park 等待唤醒
+ */
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/**
+ * 唤醒时只置位, 由 LocalTasks 决定何时再 poll
+ */
+struct FlagWaker(AtomicBool);
+
+impl Wake for FlagWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+type Task = (Pin<Box<dyn Future<Output = ()>>>, Arc<FlagWaker>);
+
+/**
+ * 测试用的单线程任务集, 与驱动协议栈的循环交替运行
+ * 只 poll 被唤醒过的任务: 漏掉的唤醒表现为任务永远不结束, 而不是被忙等掩盖
+ */
+pub struct LocalTasks {
+    tasks: Vec<Task>,
+}
+
+impl LocalTasks {
+    pub fn new() -> Self {
+        LocalTasks { tasks: Vec::new() }
+    }
+
+    /**
+     * 新任务在下一次 run_ready 时第一次被 poll
+     */
+    pub fn spawn(&mut self, future: impl Future<Output = ()> + 'static) {
+        self.tasks.push((Box::pin(future), Arc::new(FlagWaker(AtomicBool::new(true)))));
+    }
+
+    /**
+     * 把被唤醒过的任务各 poll 一次, 去掉已完成的; 返回 poll 的次数
+     */
+    pub fn run_ready(&mut self) -> usize {
+        let mut polled = 0;
+        self.tasks.retain_mut(|(future, flag)| {
+            if !flag.0.swap(false, Ordering::SeqCst) {
+                return true;
+            }
+            polled += 1;
+            let waker = Arc::clone(flag).into();
+            future.as_mut().poll(&mut Context::from_waker(&waker)).is_pending()
+        });
+        polled
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+}
+
+impl Default for LocalTasks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,109 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::link::ethernet::{EthernetFrame, HDR_LEN, MIN_PAYLOAD_LEN};
+use crate::utils::wire::WireSerialize;
+
+/**
+ * 文本格式的报文样本, 放在 tests/fixtures/<name>.hex
+ * # 开头的行是注释; 每帧写成空白分隔的十六进制字节, 可以跨多行; 帧与帧之间用空行分隔
+ */
+#[derive(Debug)]
+pub enum FixtureError {
+    Io(io::Error),
+    BadHex { line: usize, token: String },
+}
+
+impl fmt::Display for FixtureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FixtureError::Io(e) => write!(f, "fixture io error: {}", e),
+            FixtureError::BadHex { line, token } => write!(f, "line {}: invalid hex byte {:?}", line, token),
+        }
+    }
+}
+
+impl Error for FixtureError {}
+
+impl From<io::Error> for FixtureError {
+    fn from(e: io::Error) -> Self {
+        FixtureError::Io(e)
+    }
+}
+
+/**
+ * 解析 hex 文本, 返回其中的各帧
+ */
+pub fn parse_hex(text: &str) -> Result<Vec<Vec<u8>>, FixtureError> {
+    let mut frames = vec![];
+    let mut current: Vec<u8> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.is_empty() {
+            if !current.is_empty() {
+                frames.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        for token in line.split_whitespace() {
+            let byte = (token.len() == 2).then(|| u8::from_str_radix(token, 16).ok()).flatten()
+                .ok_or_else(|| FixtureError::BadHex { line: i + 1, token: token.to_string() })?;
+            current.push(byte);
+        }
+    }
+    if !current.is_empty() {
+        frames.push(current);
+    }
+    Ok(frames)
+}
+
+/**
+ * 读取 tests/fixtures/<name>.hex
+ */
+pub fn load(name: &str) -> Result<Vec<Vec<u8>>, FixtureError> {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", &format!("{}.hex", name)].iter().collect();
+    parse_hex(&fs::read_to_string(path)?)
+}
+
+/**
+ * 样本是在网卡之前抓到的帧: 没有 FCS, 最小帧也没有补齐
+ * 像网卡发送时那样补 0 到 60 字节并附上 FCS, 得到 EthernetFrame::try_deserialize 能解析的线路字节
+ */
+pub fn wire_frame(captured: &[u8]) -> Vec<u8> {
+    let mut padded = captured.to_vec();
+    padded.resize(padded.len().max(HDR_LEN + MIN_PAYLOAD_LEN), 0);
+    let mac = |range: std::ops::Range<usize>| <[u8; 6]>::try_from(&padded[range]).unwrap();
+    let ether_type = u16::from_be_bytes([padded[12], padded[13]]);
+    EthernetFrame::new(mac(0..6), mac(6..12), ether_type, padded[HDR_LEN..].to_vec()).serialize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hex() {
+        let text = "# comment\n00 01\n02\n\n\nff\n";
+        assert_eq!(parse_hex(text).unwrap(), vec![vec![0, 1, 2], vec![0xff]]);
+        assert!(matches!(parse_hex("00 0g\n"), Err(FixtureError::BadHex { line: 1, .. })));
+        assert!(matches!(parse_hex("# x\n000\n"), Err(FixtureError::BadHex { line: 2, .. })));
+    }
+
+    #[test]
+    fn test_wire_frame_pads_and_appends_fcs() {
+        let captured: Vec<u8> = [[0xff; 6].as_slice(), &[2, 0, 0, 0, 0, 1], &[0x08, 0x06], &[7; 28]].concat();
+        let wire = wire_frame(&captured);
+        assert_eq!(wire.len(), 64);
+        assert_eq!(wire[..42], captured[..]);
+        assert!(wire[42..60].iter().all(|&byte| byte == 0));
+        let frame = EthernetFrame::try_deserialize(&wire).unwrap();
+        assert!(frame.check_fcs());
+        assert_eq!(frame.unpadded_payload(), &[7; 28]);
+    }
+}
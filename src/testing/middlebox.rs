@@ -0,0 +1,127 @@
+use crate::net::ipv4::Ipv4Datagram;
+use crate::net::raw_socket::IpProtocol;
+use crate::transport::tcp_option::{self, TcpOption};
+use crate::transport::tcp_segment::TcpSegment;
+use crate::utils::wire::WireSerialize;
+
+/**
+ * 链路中间的改包设备, 帧为 IPv4 数据报的字节
+ * 返回 None 表示丢弃; 通过 Netem::set_mangler 挂到单个方向上
+ */
+pub trait Middlebox {
+    fn process(&mut self, frame: Vec<u8>) -> Option<Vec<u8>>;
+}
+
+impl<F: FnMut(Vec<u8>) -> Option<Vec<u8>>> Middlebox for F {
+    fn process(&mut self, frame: Vec<u8>) -> Option<Vec<u8>> {
+        self(frame)
+    }
+}
+
+/**
+ * 解析出 TCP 段交给 f 修改, 再按需重算 TCP 校验和、更新 IP 总长度和首部校验和
+ * 不是 TCP 或是分片时原样返回
+ */
+pub fn mangle_tcp(frame: Vec<u8>, f: impl FnOnce(&mut TcpSegment)) -> Vec<u8> {
+    let Ok(mut datagram) = Ipv4Datagram::try_deserialize(&frame) else {
+        return frame;
+    };
+    if datagram.protocol() != IpProtocol::Tcp.number() || datagram.is_fragment() {
+        return frame;
+    }
+    let Ok(mut segment) = TcpSegment::try_deserialize(datagram.payload()) else {
+        return frame;
+    };
+    f(&mut segment);
+    if segment.checksum_stale() {
+        segment.generate_checksum(datagram.s_addr(), datagram.d_addr());
+    }
+    datagram.set_payload(segment.serialize());
+    datagram.serialize()
+}
+
+/**
+ * 把 SYN 中大于上限的 MSS 改小, 相当于路由器上的 MSS clamping
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MssClamp(pub u16);
+
+impl Middlebox for MssClamp {
+    fn process(&mut self, frame: Vec<u8>) -> Option<Vec<u8>> {
+        let limit = self.0;
+        Some(mangle_tcp(frame, |segment| {
+            if !segment.SYN() {
+                return;
+            }
+            let too_large = tcp_option::parse_options(&segment.options_bytes())
+                .is_ok_and(|options| options.iter().any(|option| matches!(option, TcpOption::Mss(mss) if *mss > limit)));
+            if too_large {
+                segment.rewrite_mss(limit);
+            }
+        }))
+    }
+}
+
+/**
+ * 删掉每个 TCP 段中指定 kind 的选项, 模拟不认识新选项的防火墙
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StripOption(pub u8);
+
+impl Middlebox for StripOption {
+    fn process(&mut self, frame: Vec<u8>) -> Option<Vec<u8>> {
+        let kind = self.0;
+        Some(mangle_tcp(frame, |segment| {
+            segment.remove_option(kind);
+        }))
+    }
+}
+
+/**
+ * 清掉 DF 位, 模拟会改写 DF 的路径
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClearDf;
+
+impl Middlebox for ClearDf {
+    fn process(&mut self, frame: Vec<u8>) -> Option<Vec<u8>> {
+        let Ok(mut datagram) = Ipv4Datagram::try_deserialize(&frame) else {
+            return Some(frame);
+        };
+        datagram.set_dont_fragment(false);
+        Some(datagram.serialize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::packet::PacketBuilder;
+    use crate::transport::tcp_segment::TcpCtrlFlag;
+
+    #[test]
+    fn test_mss_clamp_keeps_checksums_valid() {
+        let frame = PacketBuilder::new().ipv4(1, 2).tcp(1000, 80).flags(TcpCtrlFlag::SYN).mss(1460).build();
+        let clamped = MssClamp(536).process(frame).unwrap();
+
+        let datagram = Ipv4Datagram::try_deserialize(&clamped).unwrap();
+        assert!(datagram.check_hdr_checksum());
+        let segment = TcpSegment::try_deserialize(datagram.payload()).unwrap();
+        assert!(segment.check_checksum(1, 2));
+        assert_eq!(tcp_option::parse_options(&segment.options_bytes()).unwrap()[0], TcpOption::Mss(536));
+
+        // 已经足够小的 MSS 不动
+        let frame = PacketBuilder::new().ipv4(1, 2).tcp(1000, 80).flags(TcpCtrlFlag::SYN).mss(500).build();
+        assert_eq!(MssClamp(536).process(frame.clone()).unwrap(), frame);
+    }
+
+    #[test]
+    fn test_clear_df() {
+        let frame = PacketBuilder::new().ipv4(1, 2).tcp(1000, 80).build();
+        assert!(Ipv4Datagram::try_deserialize(&frame).unwrap().dont_fragment());
+        let cleared = ClearDf.process(frame).unwrap();
+        let datagram = Ipv4Datagram::try_deserialize(&cleared).unwrap();
+        assert!(!datagram.dont_fragment());
+        assert!(datagram.check_hdr_checksum());
+    }
+}
@@ -0,0 +1,15 @@
+pub mod replay;
+pub mod fixtures;
+pub mod packet;
+pub mod rng;
+pub mod netem;
+pub mod middlebox;
+pub mod sim;
+pub mod table_host;
+pub mod pair;
+pub mod topology;
+pub mod bench;
+#[cfg(all(target_os = "linux", feature = "os-interop"))]
+pub mod osnet;
+#[cfg(feature = "async")]
+pub mod executor;
@@ -0,0 +1,428 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::error::DeviceError;
+use crate::link::device::{ChannelEnd, ChannelPair, NetworkDevice};
+use crate::testing::middlebox::Middlebox;
+use crate::testing::rng::SimRng;
+use crate::utils::clock::Clock;
+use crate::utils::timer::TimerQueue;
+
+/**
+ * 链路损伤参数, 仿照 Linux netem
+ * 概率取值 [0, 1], 带宽为 None 时不限速
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetemConfig {
+    pub loss: f64,
+    pub delay_ms: u64,
+    pub jitter_ms: u64,       // 每帧在 delay 基础上额外增加 [0, jitter] 的随机延迟
+    pub reorder: f64,         // 被选中的帧不经过延迟直接送达, 从而越过前面的帧
+    pub duplicate: f64,
+    pub bandwidth_bps: Option<u64>,
+    pub queue_limit: Option<usize>, // 限速时等待上链路的帧数上限, 超出则尾部丢弃
+}
+
+impl Default for NetemConfig {
+    /**
+     * 理想链路: 不丢包、无延迟、不限速
+     */
+    fn default() -> Self {
+        NetemConfig { loss: 0.0, delay_ms: 0, jitter_ms: 0, reorder: 0.0, duplicate: 0.0, bandwidth_bps: None, queue_limit: None }
+    }
+}
+
+impl NetemConfig {
+    pub fn lossy_wifi() -> Self {
+        NetemConfig {
+            loss: 0.02,
+            delay_ms: 5,
+            jitter_ms: 10,
+            reorder: 0.01,
+            duplicate: 0.005,
+            bandwidth_bps: Some(20_000_000),
+            queue_limit: None,
+        }
+    }
+
+    pub fn long_fat_pipe() -> Self {
+        NetemConfig {
+            loss: 0.0001,
+            delay_ms: 50,
+            jitter_ms: 0,
+            reorder: 0.0,
+            duplicate: 0.0,
+            bandwidth_bps: Some(1_000_000_000),
+            queue_limit: None,
+        }
+    }
+
+    pub fn with_loss(mut self, loss: f64) -> Self {
+        self.loss = loss;
+        self
+    }
+}
+
+/**
+ * 各类事件的计数
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NetemStats {
+    pub sent: u64,
+    pub dropped: u64,
+    pub duplicated: u64,
+    pub reordered: u64,
+    pub delivered: u64,
+    pub overflowed: u64, // 因 queue_limit 丢弃的帧, 同时计入 dropped
+}
+
+/**
+ * 单方向的损伤链路: send 放入帧, poll 取出到期的帧
+ * 时间由调用方传入(一般取自 ManualClock), 随机性只来自种子
+ * 用 wrap 包住一个 NetworkDevice 时自身也是 NetworkDevice, 见 Netem::wrap
+ */
+pub struct Netem<D = ()> {
+    device: D,
+    clock: Option<Box<dyn Clock>>,
+    due: VecDeque<Vec<u8>>, // 已经到期、设备队列满暂时没能交出的帧
+    config: NetemConfig,
+    rng: SimRng,
+    in_flight: TimerQueue<u64>,
+    frames: HashMap<u64, Vec<u8>>,
+    next_id: u64,
+    link_free_at_us: u64, // 限速时链路空闲的时刻
+    queued_until_us: VecDeque<u64>, // 排队中各帧发送完成的时刻
+    stats: NetemStats,
+    mangler: Option<Box<dyn Middlebox>>, // 在损伤之前改写每一帧
+}
+
+impl Netem {
+    pub fn new(config: NetemConfig, seed: u64) -> Self {
+        Netem::build((), None, config, seed)
+    }
+}
+
+impl<D: NetworkDevice> Netem<D> {
+    /**
+     * 包住 device: 发出的帧先经过损伤, 到期之后才交给 device, 收到的帧原样交出
+     * 与挂在出口队列上的 Linux netem 一样只损伤发送方向, 双向损伤时两端各包一个, 见 Netem::pair
+     * 时间取自 clock; 驱动循环推进时钟之后调用 flush (或 receive / transmit) 让到期的帧离开
+     */
+    pub fn wrap(device: D, config: NetemConfig, seed: u64, clock: impl Clock + 'static) -> Self {
+        Netem::build(device, Some(Box::new(clock)), config, seed)
+    }
+
+    /**
+     * 把到期的帧按到达顺序交给设备, 设备队列满时留到下次; 返回交出的帧数
+     */
+    pub fn flush(&mut self) -> usize {
+        let now_ms = self.clock.as_ref().map_or(0, |clock| clock.now_ms());
+        let frames = self.poll(now_ms);
+        self.due.extend(frames);
+        let mut sent = 0;
+        while let Some(frame) = self.due.front() {
+            if self.device.transmit(frame).is_err() {
+                break;
+            }
+            self.due.pop_front();
+            sent += 1;
+        }
+        sent
+    }
+
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+}
+
+impl Netem<ChannelEnd> {
+    /**
+     * 一对互连的设备, 两个方向各自损伤; 种子的取法与 NetemLink 相同, 同一种子得到同样的丢包与延迟
+     */
+    pub fn pair(a_to_b: NetemConfig, b_to_a: NetemConfig, seed: u64, clock: impl Clock + Clone + 'static) -> (Self, Self) {
+        let ChannelPair { a, b } = ChannelPair::new(usize::MAX);
+        (Netem::wrap(a, a_to_b, seed, clock.clone()), Netem::wrap(b, b_to_a, reverse_seed(seed), clock))
+    }
+}
+
+impl<D: NetworkDevice> NetworkDevice for Netem<D> {
+    fn receive(&mut self) -> Result<Option<Vec<u8>>, DeviceError> {
+        self.flush();
+        self.device.receive()
+    }
+
+    /**
+     * 帧总是被收下: 丢弃与排队都是损伤的一部分, 计入 stats
+     */
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), DeviceError> {
+        let now_ms = self.clock.as_ref().map_or(0, |clock| clock.now_ms());
+        self.send(frame.to_vec(), now_ms);
+        self.flush();
+        Ok(())
+    }
+}
+
+impl<D> Netem<D> {
+    fn build(device: D, clock: Option<Box<dyn Clock>>, config: NetemConfig, seed: u64) -> Self {
+        Netem {
+            device,
+            clock,
+            due: VecDeque::new(),
+            config,
+            rng: SimRng::new(seed),
+            in_flight: TimerQueue::new(),
+            frames: HashMap::new(),
+            next_id: 0,
+            link_free_at_us: 0,
+            queued_until_us: VecDeque::new(),
+            stats: NetemStats::default(),
+            mangler: None,
+        }
+    }
+
+    /**
+     * 设置改包设备, 之后 send 的每一帧先经过它; 被它丢弃的帧计入 dropped
+     */
+    pub fn set_mangler(&mut self, mangler: Option<Box<dyn Middlebox>>) {
+        self.mangler = mangler;
+    }
+
+    pub fn send(&mut self, frame: Vec<u8>, now_ms: u64) {
+        self.stats.sent += 1;
+        let frame = match self.mangler.as_mut() {
+            Some(mangler) => match mangler.process(frame) {
+                Some(frame) => frame,
+                None => {
+                    self.stats.dropped += 1;
+                    return;
+                }
+            },
+            None => frame,
+        };
+        if self.rng.chance(self.config.loss) {
+            self.stats.dropped += 1;
+            return;
+        }
+        if let Some(limit) = self.config.queue_limit {
+            while self.queued_until_us.front().is_some_and(|&done| done <= now_ms * 1000) {
+                self.queued_until_us.pop_front();
+            }
+            if self.queued_until_us.len() >= limit {
+                self.stats.dropped += 1;
+                self.stats.overflowed += 1;
+                return;
+            }
+        }
+        if self.rng.chance(self.config.duplicate) {
+            self.stats.duplicated += 1;
+            self.enqueue(frame.clone(), now_ms);
+        }
+        self.enqueue(frame, now_ms);
+    }
+
+    /**
+     * 取出所有在 now_ms 之前到达对端的帧, 按到达顺序
+     */
+    pub fn poll(&mut self, now_ms: u64) -> Vec<Vec<u8>> {
+        let frames: Vec<Vec<u8>> = self.in_flight.expired(now_ms).into_iter()
+            .filter_map(|id| self.frames.remove(&id))
+            .collect();
+        self.stats.delivered += frames.len() as u64;
+        frames
+    }
+
+    /**
+     * 下一帧到达的时刻, 仿真驱动用它决定时钟推进到哪里
+     */
+    pub fn next_delivery_ms(&mut self) -> Option<u64> {
+        self.in_flight.next_deadline()
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn stats(&self) -> NetemStats {
+        self.stats
+    }
+
+    fn enqueue(&mut self, frame: Vec<u8>, now_ms: u64) {
+        // 限速: 帧在链路空闲后才开始发送, 发送耗时按长度计算
+        let mut sent_at_ms = now_ms;
+        if let Some(bps) = self.config.bandwidth_bps {
+            let start_us = self.link_free_at_us.max(now_ms * 1000);
+            self.link_free_at_us = start_us + (frame.len() as u64 * 8 * 1_000_000).div_ceil(bps.max(1));
+            sent_at_ms = self.link_free_at_us.div_ceil(1000);
+            if self.config.queue_limit.is_some() {
+                self.queued_until_us.push_back(self.link_free_at_us);
+            }
+        }
+
+        let deliver_at = if self.rng.chance(self.config.reorder) {
+            self.stats.reordered += 1;
+            sent_at_ms
+        } else {
+            sent_at_ms + self.config.delay_ms + self.rng.below(self.config.jitter_ms + 1)
+        };
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.frames.insert(id, frame);
+        self.in_flight.schedule(id, deliver_at);
+    }
+}
+
+/**
+ * 反方向的种子, 两个方向的随机序列互不相关
+ */
+fn reverse_seed(seed: u64) -> u64 {
+    seed.rotate_left(32) ^ 0x5bd1_e995
+}
+
+/**
+ * 双向链路, 两个方向各自独立的参数和随机序列
+ */
+pub struct NetemLink {
+    pub a_to_b: Netem,
+    pub b_to_a: Netem,
+}
+
+impl NetemLink {
+    pub fn new(config: NetemConfig, seed: u64) -> Self {
+        Self::with_configs(config.clone(), config, seed)
+    }
+
+    pub fn with_configs(a_to_b: NetemConfig, b_to_a: NetemConfig, seed: u64) -> Self {
+        NetemLink {
+            a_to_b: Netem::new(a_to_b, seed),
+            b_to_a: Netem::new(b_to_a, reverse_seed(seed)),
+        }
+    }
+
+    /**
+     * 两个方向中最早的到达时刻
+     */
+    pub fn next_delivery_ms(&mut self) -> Option<u64> {
+        match (self.a_to_b.next_delivery_ms(), self.b_to_a.next_delivery_ms()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock::ManualClock;
+
+    #[test]
+    fn test_perfect_link_delivers_in_order() {
+        let mut netem = Netem::new(NetemConfig::default(), 1);
+        for i in 0..10u8 {
+            netem.send(vec![i], 0);
+        }
+        assert_eq!(netem.poll(0), (0..10u8).map(|i| vec![i]).collect::<Vec<_>>());
+        assert_eq!(netem.stats().delivered, 10);
+    }
+
+    #[test]
+    fn test_delay_and_jitter() {
+        let config = NetemConfig { delay_ms: 20, jitter_ms: 5, ..NetemConfig::default() };
+        let mut netem = Netem::new(config, 7);
+        netem.send(vec![1], 100);
+        assert!(netem.poll(119).is_empty());
+        let at = netem.next_delivery_ms().unwrap();
+        assert!((120..=125).contains(&at));
+        assert_eq!(netem.poll(125), vec![vec![1]]);
+    }
+
+    #[test]
+    fn test_bandwidth_cap_serializes_frames() {
+        // 8 kbit/s: 1000 字节的帧需要 1 秒
+        let config = NetemConfig { bandwidth_bps: Some(8_000), ..NetemConfig::default() };
+        let mut netem = Netem::new(config, 1);
+        netem.send(vec![0; 1000], 0);
+        netem.send(vec![0; 1000], 0);
+        assert_eq!(netem.next_delivery_ms(), Some(1000));
+        assert_eq!(netem.poll(1000).len(), 1);
+        assert_eq!(netem.poll(1999).len(), 0);
+        assert_eq!(netem.poll(2000).len(), 1);
+    }
+
+    #[test]
+    fn test_queue_limit_tail_drops() {
+        let config = NetemConfig { bandwidth_bps: Some(8_000), queue_limit: Some(2), ..NetemConfig::default() };
+        let mut netem = Netem::new(config, 1);
+        for _ in 0..4 {
+            netem.send(vec![0; 1000], 0);
+        }
+        assert_eq!(netem.stats().overflowed, 2);
+        netem.send(vec![0; 1000], 1000); // 第一帧已经发完, 空出一个位置
+        assert_eq!(netem.stats().dropped, 2);
+        assert_eq!(netem.poll(u64::MAX).len(), 3);
+    }
+
+    #[test]
+    fn test_mangler_rewrites_and_drops() {
+        let mut netem = Netem::new(NetemConfig::default(), 1);
+        netem.set_mangler(Some(Box::new(|frame: Vec<u8>| (frame[0] != 0).then(|| vec![frame[0] * 2]))));
+        for i in 0..3u8 {
+            netem.send(vec![i], 0);
+        }
+        assert_eq!(netem.poll(0), vec![vec![2], vec![4]]);
+        assert_eq!(netem.stats().dropped, 1);
+    }
+
+    #[test]
+    fn test_loss_dup_reorder_are_seeded() {
+        let config = NetemConfig { loss: 0.1, duplicate: 0.1, reorder: 0.1, delay_ms: 10, ..NetemConfig::default() };
+        let run = |seed: u64| {
+            let mut netem = Netem::new(config.clone(), seed);
+            for i in 0..1000u32 {
+                netem.send(i.to_be_bytes().to_vec(), i as u64);
+            }
+            (netem.poll(u64::MAX), netem.stats())
+        };
+        let (frames, stats) = run(99);
+        assert_eq!(run(99), (frames.clone(), stats));
+        assert_ne!(run(100).0, frames);
+
+        assert!(stats.dropped > 50 && stats.dropped < 150);
+        assert!(stats.duplicated > 50 && stats.reordered > 50);
+        assert_eq!(stats.delivered, stats.sent - stats.dropped + stats.duplicated);
+        let in_order = frames.windows(2).all(|w| w[0] <= w[1]);
+        assert!(!in_order);
+    }
+
+    #[test]
+    fn test_wrapped_channel_pair() {
+        let clock = ManualClock::new(0);
+        let config = NetemConfig { delay_ms: 10, ..NetemConfig::default() };
+        let (mut a, mut b) = Netem::pair(config.clone(), NetemConfig::default(), 1, clock.clone());
+        a.transmit(b"to b").unwrap();
+        b.transmit(b"to a").unwrap();
+        assert_eq!(a.receive().unwrap(), Some(b"to a".to_vec())); // 反方向没有延迟
+        assert_eq!(b.receive().unwrap(), None);
+        assert_eq!(a.in_flight(), 1);
+
+        // 时钟到了之后由发送端的 flush 交给设备
+        clock.set(10);
+        assert_eq!(b.receive().unwrap(), None);
+        assert_eq!(a.flush(), 1);
+        assert_eq!(b.receive().unwrap(), Some(b"to b".to_vec()));
+        assert_eq!(a.stats().delivered, 1);
+
+        // 与 NetemLink 同一种子的丢包相同
+        let lossy = NetemConfig::default().with_loss(0.3);
+        let (mut a, mut b) = Netem::pair(lossy.clone(), NetemConfig::default(), 9, clock.clone());
+        let mut link = NetemLink::new(lossy, 9);
+        for i in 0..50u8 {
+            a.transmit(&[i]).unwrap();
+            link.a_to_b.send(vec![i], 10);
+        }
+        let mut received = vec![];
+        b.receive_batch(&mut received, usize::MAX);
+        assert_eq!(received, link.a_to_b.poll(10));
+        assert!(a.stats().dropped > 0);
+    }
+}
@@ -0,0 +1,284 @@
+/**
+ * 与宿主机内核协议栈互通测试用的 TAP 设备工具, 仅 Linux
+ * 需要 root 或 CAP_NET_ADMIN; 地址通过外部 `ip` 命令配置
+ */
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::raw::{c_int, c_short, c_ulong};
+use std::os::unix::io::AsRawFd;
+use std::process::Command;
+use std::time::Duration;
+
+use crate::error::DeviceError;
+use crate::link::device::NetworkDevice;
+use crate::link::ethernet::{self, EthernetFrame};
+use crate::utils::wire::WireSerialize;
+
+const TUNSETIFF: c_ulong = 0x400454ca;
+const IFF_TAP: i16 = 0x0002;
+const IFF_NO_PI: i16 = 0x1000;
+const IFNAMSIZ: usize = 16;
+const POLLIN: c_short = 0x1;
+const ETHER_TYPE_ARP: u16 = 0x0806;
+
+extern "C" {
+    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    fn poll(fds: *mut PollFd, nfds: c_ulong, timeout: c_int) -> c_int;
+}
+
+#[repr(C)]
+struct PollFd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+/**
+ * struct ifreq 中用到的部分, 其余用 0 填充到 40 字节
+ */
+#[repr(C)]
+struct IfReq {
+    name: [u8; IFNAMSIZ],
+    flags: i16,
+    _pad: [u8; 22],
+}
+
+/**
+ * TAP 设备, 读写的都是不带 FCS 的以太网帧
+ * 设备不是持久的, 文件关闭时内核自动删除
+ */
+pub struct TapDevice {
+    file: File,
+    name: String,
+}
+
+impl TapDevice {
+    pub fn open(name: &str) -> io::Result<Self> {
+        if name.is_empty() || name.len() >= IFNAMSIZ {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("bad interface name {:?}", name)));
+        }
+        let file = OpenOptions::new().read(true).write(true).open("/dev/net/tun")?;
+        let mut req = IfReq { name: [0; IFNAMSIZ], flags: IFF_TAP | IFF_NO_PI, _pad: [0; 22] };
+        req.name[..name.len()].copy_from_slice(name.as_bytes());
+        // 安全性: req 在调用期间有效, 布局与内核的 ifreq 前缀一致
+        let ret = unsafe { ioctl(file.as_raw_fd(), TUNSETIFF, &mut req as *mut IfReq) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let len = req.name.iter().position(|&b| b == 0).unwrap_or(IFNAMSIZ);
+        let name = String::from_utf8_lossy(&req.name[..len]).into_owned();
+        Ok(TapDevice { file, name })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /**
+     * 读一帧(阻塞), 返回不带 FCS 的原始字节
+     */
+    pub fn read_raw(&mut self) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; 65536];
+        let n = self.file.read(&mut buf)?;
+        buf.truncate(n);
+        Ok(buf)
+    }
+
+    /**
+     * 读一帧并补齐到协议栈期望的格式(最短帧 + FCS)
+     */
+    pub fn read_frame(&mut self) -> io::Result<Vec<u8>> {
+        loop {
+            let raw = self.read_raw()?;
+            if let Some(frame) = from_tap(&raw) {
+                return Ok(frame);
+            }
+        }
+    }
+
+    /**
+     * 同 read_frame, 超时仍没有帧时返回 None
+     */
+    pub fn read_frame_timeout(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        let mut fd = PollFd { fd: self.file.as_raw_fd(), events: POLLIN, revents: 0 };
+        let timeout_ms = timeout.as_millis().min(c_int::MAX as u128) as c_int;
+        // 安全性: fd 在调用期间有效, nfds 为 1
+        let ready = unsafe { poll(&mut fd, 1, timeout_ms) };
+        if ready < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if ready == 0 {
+            return Ok(None);
+        }
+        Ok(from_tap(&self.read_raw()?))
+    }
+
+    /**
+     * 写入协议栈序列化出的帧, 去掉 FCS 后交给内核
+     */
+    pub fn write_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.file.write_all(to_tap(frame))
+    }
+
+    /**
+     * 不等待地检查是否有帧可读
+     */
+    fn readable(&self) -> io::Result<bool> {
+        let mut fd = PollFd { fd: self.file.as_raw_fd(), events: POLLIN, revents: 0 };
+        // 安全性: fd 在调用期间有效, nfds 为 1
+        let ready = unsafe { poll(&mut fd, 1, 0) };
+        if ready < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ready > 0)
+    }
+}
+
+fn device_error(e: io::Error) -> DeviceError {
+    DeviceError::Io(e.to_string())
+}
+
+/**
+ * TAP 上一次 read/write 正好是一帧, readv/writev 不能一次收发几帧
+ * 批量接收连续读到没有帧可读为止, 太短的帧跳过而不是当作没有帧; 批量发送用默认的逐帧写
+ */
+impl NetworkDevice for TapDevice {
+    fn receive(&mut self) -> Result<Option<Vec<u8>>, DeviceError> {
+        self.read_frame_timeout(Duration::ZERO).map_err(device_error)
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), DeviceError> {
+        self.write_frame(frame).map_err(device_error)
+    }
+
+    fn receive_batch(&mut self, out: &mut Vec<Vec<u8>>, max: usize) -> usize {
+        let mut n = 0;
+        while n < max && self.readable().unwrap_or(false) {
+            let Ok(raw) = self.read_raw() else { break };
+            if let Some(frame) = from_tap(&raw) {
+                out.push(frame);
+                n += 1;
+            }
+        }
+        n
+    }
+}
+
+/**
+ * TAP 读出的帧没有 FCS, 可能短于 60 字节; 重新组帧并算出 FCS, 太短(没有完整首部)返回 None
+ */
+pub fn from_tap(raw: &[u8]) -> Option<Vec<u8>> {
+    if raw.len() < ethernet::HDR_LEN {
+        return None;
+    }
+    let mut d_mac = [0u8; 6];
+    d_mac.copy_from_slice(&raw[0..6]);
+    let mut s_mac = [0u8; 6];
+    s_mac.copy_from_slice(&raw[6..12]);
+    let ether_type = u16::from_be_bytes([raw[12], raw[13]]);
+    let frame = EthernetFrame::new(d_mac, s_mac, ether_type, raw[ethernet::HDR_LEN..].to_vec());
+    Some(frame.serialize())
+}
+
+/**
+ * 去掉帧尾 4 字节 FCS
+ */
+pub fn to_tap(frame: &[u8]) -> &[u8] {
+    &frame[..frame.len().saturating_sub(4)]
+}
+
+/**
+ * 以太网上 IPv4 的 ARP 帧(带 FCS), op 为 1 请求 / 2 应答
+ */
+pub fn arp_frame(op: u16, s_mac: [u8; 6], s_ip: u32, t_mac: [u8; 6], t_ip: u32) -> Vec<u8> {
+    let d_mac = if op == 1 { [0xff; 6] } else { t_mac };
+    let mut arp = Vec::with_capacity(28);
+    arp.extend_from_slice(&[0x00, 0x01, 0x08, 0x00, 6, 4]); // 以太网, IPv4
+    arp.extend_from_slice(&op.to_be_bytes());
+    arp.extend_from_slice(&s_mac);
+    arp.extend_from_slice(&s_ip.to_be_bytes());
+    arp.extend_from_slice(&t_mac);
+    arp.extend_from_slice(&t_ip.to_be_bytes());
+    EthernetFrame::new(d_mac, s_mac, ETHER_TYPE_ARP, arp).serialize()
+}
+
+/**
+ * 解析 ARP 帧, 返回 (op, 发送方 MAC, 发送方 IP, 目标 IP); 不是 ARP 时返回 None
+ */
+pub fn parse_arp(frame: &[u8]) -> Option<(u16, [u8; 6], u32, u32)> {
+    let frame = EthernetFrame::try_deserialize(frame).ok()?;
+    let arp = frame.payload();
+    if frame.ether_type() != ETHER_TYPE_ARP || arp.len() < 28 {
+        return None;
+    }
+    let mut s_mac = [0u8; 6];
+    s_mac.copy_from_slice(&arp[8..14]);
+    let s_ip = u32::from_be_bytes([arp[14], arp[15], arp[16], arp[17]]);
+    let t_ip = u32::from_be_bytes([arp[24], arp[25], arp[26], arp[27]]);
+    Some((u16::from_be_bytes([arp[6], arp[7]]), s_mac, s_ip, t_ip))
+}
+
+/**
+ * 针对询问 my_ip 的 ARP 请求构造应答, 其他帧返回 None
+ */
+pub fn arp_reply_to(frame: &[u8], my_mac: [u8; 6], my_ip: u32) -> Option<Vec<u8>> {
+    match parse_arp(frame)? {
+        (1, s_mac, s_ip, t_ip) if t_ip == my_ip => Some(arp_frame(2, my_mac, my_ip, s_mac, s_ip)),
+        _ => None,
+    }
+}
+
+/**
+ * 执行 `ip <args>`, 非 0 退出码转成错误
+ */
+pub fn run_ip(args: &[&str]) -> io::Result<()> {
+    let output = Command::new("ip").args(args).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "ip {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/**
+ * 一块 TAP 网卡加上宿主机一侧的地址; drop 时关闭设备, 内核随之删除接口和地址
+ */
+pub struct HostNet {
+    pub tap: TapDevice,
+    pub host_ip: u32,
+    pub prefix_len: u8,
+}
+
+impl HostNet {
+    /**
+     * 创建 TAP, 给宿主机一侧配上 host_ip/prefix_len 并拉起
+     */
+    pub fn setup(name: &str, host_ip: u32, prefix_len: u8) -> io::Result<Self> {
+        let tap = TapDevice::open(name)?;
+        let cidr = format!("{}/{}", crate::utils::addr::format_ipv4(host_ip), prefix_len);
+        run_ip(&["addr", "add", &cidr, "dev", tap.name()])?;
+        run_ip(&["link", "set", "dev", tap.name(), "up"])?;
+        Ok(HostNet { tap, host_ip, prefix_len })
+    }
+
+    /**
+     * 以 my_mac/my_ip 的身份用 ARP 查询宿主机一侧的 MAC, 期间顺带应答宿主机对 my_ip 的询问
+     */
+    pub fn resolve_host(&mut self, my_mac: [u8; 6], my_ip: u32, timeout: Duration) -> io::Result<[u8; 6]> {
+        self.tap.write_frame(&arp_frame(1, my_mac, my_ip, [0; 6], self.host_ip))?;
+        while let Some(frame) = self.tap.read_frame_timeout(timeout)? {
+            if let Some(reply) = arp_reply_to(&frame, my_mac, my_ip) {
+                self.tap.write_frame(&reply)?;
+            }
+            if let Some((2, s_mac, s_ip, _)) = parse_arp(&frame) {
+                if s_ip == self.host_ip {
+                    return Ok(s_mac);
+                }
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut, "no ARP reply from host"))
+    }
+}
@@ -0,0 +1,306 @@
+use crate::link::ethernet::EthernetFrame;
+use crate::net::icmp_v4::IcmpV4;
+use crate::net::ipv4::Ipv4Datagram;
+use crate::transport::tcp_segment::{TcpFlags, TcpSegment};
+use crate::utils::wire::WireSerialize;
+
+const ETHER_TYPE_IPV4: u16 = 0x0800;
+const PROTOCOL_ICMP: u8 = 1;
+const PROTOCOL_TCP: u8 = 6;
+
+/**
+ * 测试用的报文构造器, 逐层声明字段, 长度、hl、total_len、校验和与 FCS 在 build 时统一计算
+ * PacketBuilder::ether(src, dst).ipv4(s, d).tcp(40000, 80).flags(SYN).seq(100).payload(b"hi").build()
+ * 不调用 ether 时 build 返回 IP 数据报, 字段方法作用于最近声明的那一层
+ */
+#[derive(Debug, Clone, Default)]
+pub struct PacketBuilder {
+    ether: Option<EtherFields>,
+    ipv4: Option<Ipv4Fields>,
+    l4: Option<L4>,
+    payload: Vec<u8>,
+    corrupt_fcs: bool,
+    corrupt_ip_checksum: bool,
+    corrupt_l4_checksum: bool,
+    truncate: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct EtherFields {
+    s_mac: [u8; 6],
+    d_mac: [u8; 6],
+}
+
+#[derive(Debug, Clone)]
+struct Ipv4Fields {
+    s_addr: u32,
+    d_addr: u32,
+    tos: u8,
+    id: u16,
+    flag: u8,
+    ttl: u8,
+}
+
+#[derive(Debug, Clone)]
+enum L4 {
+    Tcp(TcpFields),
+    Icmp { icmp_type: u8, code: u8 },
+}
+
+#[derive(Debug, Clone)]
+struct TcpFields {
+    s_port: u16,
+    d_port: u16,
+    seq: u32,
+    ack: u32,
+    ctrl: TcpFlags,
+    win_size: u16,
+    ur_ptr: u16,
+    options: Vec<u32>,
+}
+
+impl PacketBuilder {
+    /**
+     * 没有链路层, build 返回 IP 数据报
+     */
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ether(s_mac: [u8; 6], d_mac: [u8; 6]) -> Self {
+        PacketBuilder { ether: Some(EtherFields { s_mac, d_mac }), ..Self::default() }
+    }
+
+    /**
+     * 默认 TTL 64、设置 DF
+     */
+    pub fn ipv4(mut self, s_addr: u32, d_addr: u32) -> Self {
+        self.ipv4 = Some(Ipv4Fields { s_addr, d_addr, tos: 0, id: 0, flag: 0b010, ttl: 64 });
+        self
+    }
+
+    pub fn ttl(mut self, ttl: u8) -> Self {
+        self.ipv4_fields().ttl = ttl;
+        self
+    }
+
+    pub fn ip_id(mut self, id: u16) -> Self {
+        self.ipv4_fields().id = id;
+        self
+    }
+
+    pub fn tos(mut self, tos: u8) -> Self {
+        self.ipv4_fields().tos = tos;
+        self
+    }
+
+    /**
+     * 默认窗口 65535, 不带控制位
+     */
+    pub fn tcp(mut self, s_port: u16, d_port: u16) -> Self {
+        self.l4 = Some(L4::Tcp(TcpFields { s_port, d_port, seq: 0, ack: 0, ctrl: TcpFlags::EMPTY, win_size: 65535, ur_ptr: 0, options: vec![] }));
+        self
+    }
+
+    pub fn flags(mut self, ctrl: impl Into<TcpFlags>) -> Self {
+        self.tcp_fields().ctrl = ctrl.into();
+        self
+    }
+
+    pub fn seq(mut self, seq: u32) -> Self {
+        self.tcp_fields().seq = seq;
+        self
+    }
+
+    pub fn ack(mut self, ack: u32) -> Self {
+        self.tcp_fields().ack = ack;
+        self
+    }
+
+    pub fn window(mut self, win_size: u16) -> Self {
+        self.tcp_fields().win_size = win_size;
+        self
+    }
+
+    pub fn urgent(mut self, ur_ptr: u16) -> Self {
+        self.tcp_fields().ur_ptr = ur_ptr;
+        self
+    }
+
+    /**
+     * 追加 MSS 选项
+     */
+    pub fn mss(mut self, mss: u16) -> Self {
+        self.tcp_fields().options.push(0x0204_0000 | mss as u32);
+        self
+    }
+
+    /**
+     * 追加 SACK 选项(前面补两个 NOP 对齐), 每块为 [左边界, 右边界)
+     */
+    pub fn sack(mut self, blocks: &[(u32, u32)]) -> Self {
+        let options = &mut self.tcp_fields().options;
+        options.push(0x0101_0500 | (2 + 8 * blocks.len() as u32));
+        for &(left, right) in blocks {
+            options.push(left);
+            options.push(right);
+        }
+        self
+    }
+
+    /**
+     * 追加原始的 options 字(含填充), hl 按字数计算
+     */
+    pub fn tcp_options(mut self, words: &[u32]) -> Self {
+        self.tcp_fields().options.extend_from_slice(words);
+        self
+    }
+
+    pub fn icmp(mut self, icmp_type: u8, code: u8) -> Self {
+        self.l4 = Some(L4::Icmp { icmp_type, code });
+        self
+    }
+
+    /**
+     * 最上层的载荷
+     */
+    pub fn payload(mut self, payload: &[u8]) -> Self {
+        self.payload = payload.to_vec();
+        self
+    }
+
+    pub fn corrupt_fcs(mut self) -> Self {
+        self.corrupt_fcs = true;
+        self
+    }
+
+    pub fn corrupt_ip_checksum(mut self) -> Self {
+        self.corrupt_ip_checksum = true;
+        self
+    }
+
+    pub fn corrupt_l4_checksum(mut self) -> Self {
+        self.corrupt_l4_checksum = true;
+        self
+    }
+
+    /**
+     * 输出只保留前 n 字节, 在所有长度和校验和计算之后生效
+     */
+    pub fn truncate(mut self, n: usize) -> Self {
+        self.truncate = Some(n);
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let (s_addr, d_addr) = self.ipv4.as_ref().map_or((0, 0), |ip| (ip.s_addr, ip.d_addr));
+
+        let (protocol, mut bytes) = match &self.l4 {
+            Some(L4::Tcp(tcp)) => {
+                let hl = 5 + tcp.options.len() as u8;
+                let mut segment = TcpSegment::new(tcp.s_port, tcp.d_port, tcp.seq, tcp.ack, hl, 0, tcp.ctrl,
+                    tcp.win_size, tcp.ur_ptr, tcp.options.clone(), self.payload.clone());
+                segment.generate_checksum(s_addr, d_addr);
+                let mut bytes = segment.serialize();
+                if self.corrupt_l4_checksum {
+                    bytes[16] ^= 0xff;
+                }
+                (PROTOCOL_TCP, bytes)
+            }
+            Some(L4::Icmp { icmp_type, code }) => {
+                let mut bytes = IcmpV4::new(*icmp_type, *code, self.payload.clone()).serialize();
+                if self.corrupt_l4_checksum {
+                    bytes[2] ^= 0xff;
+                }
+                (PROTOCOL_ICMP, bytes)
+            }
+            None => (0, self.payload.clone()),
+        };
+
+        if let Some(ip) = &self.ipv4 {
+            let datagram = Ipv4Datagram::new(4, 5, ip.tos, (20 + bytes.len()) as u16, ip.id, ip.flag, 0, ip.ttl,
+                protocol, ip.s_addr, ip.d_addr, bytes);
+            bytes = datagram.serialize();
+            if self.corrupt_ip_checksum {
+                bytes[10] ^= 0xff;
+            }
+        }
+
+        if let Some(ether) = &self.ether {
+            bytes = EthernetFrame::new(ether.d_mac, ether.s_mac, ETHER_TYPE_IPV4, bytes).serialize();
+            if self.corrupt_fcs {
+                let last = bytes.len() - 1;
+                bytes[last] ^= 0xff;
+            }
+        }
+
+        if let Some(n) = self.truncate {
+            bytes.truncate(n);
+        }
+        bytes
+    }
+
+    fn ipv4_fields(&mut self) -> &mut Ipv4Fields {
+        self.ipv4.as_mut().expect("call ipv4() before setting IPv4 fields")
+    }
+
+    fn tcp_fields(&mut self) -> &mut TcpFields {
+        match self.l4.as_mut() {
+            Some(L4::Tcp(tcp)) => tcp,
+            _ => panic!("call tcp() before setting TCP fields"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tcp_segment::TcpCtrlFlag;
+    use crate::utils::checksum;
+
+    const A_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+    const B_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 2];
+
+    #[test]
+    fn test_tcp_frame_is_consistent() {
+        let bytes = PacketBuilder::ether(A_MAC, B_MAC)
+            .ipv4(0x0a000001, 0x0a000002).ttl(9).ip_id(77)
+            .tcp(40000, 80).flags(TcpCtrlFlag::SYN as u16).seq(100).mss(1460)
+            .payload(b"hi")
+            .build();
+
+        let frame = EthernetFrame::try_deserialize(&bytes).unwrap();
+        assert!(frame.check_fcs());
+        assert_eq!(frame.d_mac(), B_MAC);
+        assert_eq!(frame.s_mac(), A_MAC);
+        let datagram = Ipv4Datagram::try_deserialize(frame.payload()).unwrap();
+        assert!(datagram.check_hdr_checksum());
+        assert_eq!(datagram.ttl(), 9);
+        assert_eq!(datagram.payload().len(), 26);
+        let segment = TcpSegment::try_deserialize(datagram.payload()).unwrap();
+        assert!(segment.check_checksum(0x0a000001, 0x0a000002));
+        assert!(segment.SYN());
+        assert_eq!(segment.hl, 6);
+        assert_eq!(segment.seq, 100);
+        assert_eq!(segment.data, b"hi");
+    }
+
+    #[test]
+    fn test_negative_helpers() {
+        let base = PacketBuilder::new().ipv4(1, 2).tcp(1, 2).payload(b"x");
+        let good = base.build();
+        let bad_l4 = base.clone().corrupt_l4_checksum().build();
+        let segment = TcpSegment::try_deserialize(&good[20..]).unwrap();
+        assert!(segment.check_checksum(1, 2));
+        let segment = TcpSegment::try_deserialize(&bad_l4[20..]).unwrap();
+        assert!(!segment.check_checksum(1, 2));
+
+        let bad_ip = base.clone().corrupt_ip_checksum().build();
+        assert!(!Ipv4Datagram::try_deserialize(&bad_ip).unwrap().check_hdr_checksum());
+        assert_eq!(base.clone().truncate(10).build(), good[..10]);
+
+        let icmp = PacketBuilder::new().ipv4(1, 2).icmp(8, 0).payload(&[1, 2, 3]).build();
+        assert_eq!(icmp[9], 1);
+        assert!(checksum::check(&icmp[20..]));
+    }
+}
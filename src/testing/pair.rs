@@ -0,0 +1,151 @@
+use crate::transport::connection_table::ConnectionTable;
+use crate::transport::tcp_segment::TcpSegment;
+
+/**
+ * 直接相连的两张连接表: 报文段直接交给对端的 segment_received, 不经过 IP 层
+ * TablePair::new(CLIENT, SERVER).polling().at(100).exchange(&mut client, &mut server, vec![syn])
+ * 默认时刻为 0、没有时延, 只投递应答; polling 之后每轮还取走两边 poll_transmit 的段, ticking 之后改为取 poll(now) 的段
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct TablePair {
+    client_ip: u32,
+    server_ip: u32,
+    now_ms: u64,
+    delay_ms: u64,
+    polling: Polling,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Polling {
+    Off,
+    Transmit,
+    Timers,
+}
+
+/**
+ * 一次 exchange 的结果: 结束时刻 (有时延时为最后一批段到达的时刻)、最后交给客户端的段和按投递顺序经过线路的全部段
+ */
+#[derive(Debug, Clone)]
+pub struct Exchanged {
+    pub now_ms: u64,
+    pub last_to_client: Option<TcpSegment>,
+    pub wire: Vec<TcpSegment>,
+}
+
+impl TablePair {
+    pub const fn new(client_ip: u32, server_ip: u32) -> Self {
+        TablePair { client_ip, server_ip, now_ms: 0, delay_ms: 0, polling: Polling::Off }
+    }
+
+    /**
+     * 交换开始的时刻
+     */
+    pub const fn at(mut self, now_ms: u64) -> Self {
+        self.now_ms = now_ms;
+        self
+    }
+
+    /**
+     * 单向时延: 一批段在 now 发出, now + delay 到达, 应答再经过 delay 回来
+     */
+    pub const fn delay(mut self, delay_ms: u64) -> Self {
+        self.delay_ms = delay_ms;
+        self
+    }
+
+    /**
+     * 开始前和每次投递之后取走该端 poll_transmit 的段, 新写入的数据与关闭产生的 FIN 也一起交换
+     */
+    pub const fn polling(mut self) -> Self {
+        self.polling = Polling::Transmit;
+        self
+    }
+
+    /**
+     * 每次投递之后调用该端的 poll(now), 定时器在交换过程中照常到期; 开始前不 poll 客户端, to_server 一般就是它 poll 的结果
+     */
+    pub const fn ticking(mut self) -> Self {
+        self.polling = Polling::Timers;
+        self
+    }
+
+    /**
+     * 把 to_server 交给服务端, 之后两边来回投递, 直到一方没有要发的段
+     */
+    pub fn exchange(&self, client: &mut ConnectionTable, server: &mut ConnectionTable, mut to_server: Vec<TcpSegment>) -> Exchanged {
+        let mut now = self.now_ms;
+        let mut last_to_client = None;
+        let mut wire = vec![];
+        if self.polling == Polling::Transmit {
+            to_server.extend(transmit(client));
+        }
+        loop {
+            if !to_server.is_empty() {
+                now += self.delay_ms;
+            }
+            wire.extend(to_server.iter().cloned());
+            let mut to_client: Vec<TcpSegment> = to_server.drain(..)
+                .flat_map(|segment| server.segment_received(self.client_ip, self.server_ip, &segment, now))
+                .collect();
+            to_client.extend(self.poll(server, now));
+            if to_client.is_empty() {
+                return Exchanged { now_ms: now, last_to_client, wire };
+            }
+            last_to_client = to_client.last().cloned();
+            wire.extend(to_client.iter().cloned());
+            now += self.delay_ms;
+            to_server = to_client.iter()
+                .flat_map(|segment| client.segment_received(self.server_ip, self.client_ip, segment, now))
+                .collect();
+            to_server.extend(self.poll(client, now));
+        }
+    }
+
+    fn poll(&self, table: &mut ConnectionTable, now: u64) -> Vec<TcpSegment> {
+        match self.polling {
+            Polling::Off => vec![],
+            Polling::Transmit => transmit(table),
+            Polling::Timers => table.poll(now).into_iter().map(|(_, segment)| segment).collect(),
+        }
+    }
+}
+
+fn transmit(table: &mut ConnectionTable) -> Vec<TcpSegment> {
+    table.poll_transmit(usize::MAX).into_iter().map(|(_, segment)| segment).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TcpConfig;
+    use crate::transport::connection_table::listener_id;
+    use crate::transport::tcp_connection::{ConnectionId, TcpState};
+
+    const CLIENT: u32 = 0x0a000001;
+    const SERVER: u32 = 0x0a000002;
+    const ID: ConnectionId = ConnectionId { s_ip: CLIENT, s_port: 40000, d_ip: SERVER, d_port: 80 };
+
+    #[test]
+    fn test_handshake_and_polled_data() {
+        let mut client = ConnectionTable::new(&TcpConfig::default());
+        let mut server = ConnectionTable::new(&TcpConfig::default());
+        server.listen(SERVER, 80);
+        let syn = client.connect(ID, 0);
+
+        // SYN 去、SYN|ACK 回、ACK 去: 三次单向时延
+        let done = TablePair::new(CLIENT, SERVER).at(100).delay(10).exchange(&mut client, &mut server, vec![syn]);
+        assert_eq!(done.now_ms, 130);
+        assert!(done.last_to_client.unwrap().SYN());
+        assert_eq!(done.wire.iter().map(|segment| (segment.SYN(), segment.ACK())).collect::<Vec<_>>(), vec![(true, false), (true, true), (false, true)]);
+        assert_eq!(client.state(ID), Some(TcpState::Established));
+        assert_eq!(server.accept(listener_id(SERVER, 80)), Some(ID.reversed()));
+
+        // 不 polling 时写入的数据留在发送队列里
+        client.write(ID, b"hello").unwrap();
+        TablePair::new(CLIENT, SERVER).exchange(&mut client, &mut server, vec![]);
+        assert_eq!(server.read(ID.reversed(), usize::MAX).unwrap(), b"");
+        TablePair::new(CLIENT, SERVER).polling().exchange(&mut client, &mut server, vec![]);
+        assert_eq!(server.read(ID.reversed(), usize::MAX).unwrap(), b"hello");
+        assert_eq!(client.send_queued(ID), Some(0));
+    }
+}
@@ -0,0 +1,76 @@
+use std::io;
+
+use crate::utils::clock::{Clock, ManualClock};
+use crate::utils::pcap::{PcapError, PcapReader};
+
+/**
+ * 回放时虚拟时钟如何推进
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaySpeed {
+    CaptureTiming,    // 每帧注入前把时钟推进到与抓包时间戳相同的相对时刻
+    AsFastAsPossible, // 不推进时钟, 所有帧在同一时刻注入
+}
+
+/**
+ * 把抓包文件中的帧按顺序交给 deliver, 当作从设备收到的帧
+ * 时间戳以第一帧为零点, 相对于回放开始时 clock 的读数, 精度为毫秒
+ * 返回注入的帧数
+ */
+pub fn replay_into<R, F>(reader: PcapReader<R>, clock: &ManualClock, speed: ReplaySpeed, mut deliver: F) -> Result<usize, PcapError>
+where
+    R: io::Read,
+    F: FnMut(&[u8]),
+{
+    let start_ms = clock.now_ms();
+    let mut first_ts_us: Option<u64> = None;
+    let mut count = 0;
+
+    for record in reader {
+        let (ts_us, frame) = record?;
+        if speed == ReplaySpeed::CaptureTiming {
+            let first = *first_ts_us.get_or_insert(ts_us);
+            clock.set(start_ms + ts_us.saturating_sub(first) / 1000);
+        }
+        deliver(&frame);
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::pcap::PcapWriter;
+
+    fn capture() -> Vec<u8> {
+        let mut pcap = PcapWriter::new(Vec::new()).unwrap();
+        pcap.write_packet(10_000, &[1]).unwrap();
+        pcap.write_packet(10_250, &[2]).unwrap();
+        pcap.write_packet(12_000, &[3]).unwrap();
+        pcap.into_inner()
+    }
+
+    #[test]
+    fn test_replay_capture_timing() {
+        let bytes = capture();
+        let clock = ManualClock::new(500);
+        let mut seen: Vec<(u64, u8)> = vec![];
+        let count = replay_into(PcapReader::new(&bytes[..]).unwrap(), &clock, ReplaySpeed::CaptureTiming, |frame| {
+            seen.push((clock.now_ms(), frame[0]));
+        }).unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(seen, vec![(500, 1), (750, 2), (2_500, 3)]);
+    }
+
+    #[test]
+    fn test_replay_as_fast_as_possible() {
+        let bytes = capture();
+        let clock = ManualClock::new(500);
+        let mut seen: Vec<u64> = vec![];
+        replay_into(PcapReader::new(&bytes[..]).unwrap(), &clock, ReplaySpeed::AsFastAsPossible, |_| {
+            seen.push(clock.now_ms());
+        }).unwrap();
+        assert_eq!(seen, vec![500, 500, 500]);
+    }
+}
@@ -0,0 +1,64 @@
+/**
+ * 仿真用的伪随机数发生器(xorshift64*), 相同种子产生相同序列
+ */
+#[derive(Debug, Clone)]
+pub struct SimRng(u64);
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        // 全 0 状态会一直输出 0
+        SimRng(if seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { seed })
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /**
+     * [0, n) 内的整数, n 为 0 时返回 0
+     */
+    pub fn below(&mut self, n: u64) -> u64 {
+        if n == 0 {
+            return 0;
+        }
+        self.next_u64() % n
+    }
+
+    /**
+     * [0, 1) 内的浮点数
+     */
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /**
+     * 以概率 p 返回 true
+     */
+    pub fn chance(&mut self, p: f64) -> bool {
+        p > 0.0 && self.next_f64() < p
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deterministic() {
+        let mut a = SimRng::new(42);
+        let mut b = SimRng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        let mut zero = SimRng::new(0);
+        assert_ne!(zero.next_u64(), 0);
+
+        let hits = (0..10_000).filter(|_| a.chance(0.25)).count();
+        assert!((2_000..3_000).contains(&hits));
+        assert!(!a.chance(0.0));
+        assert!((0..1000).all(|_| a.below(7) < 7));
+    }
+}
@@ -0,0 +1,302 @@
+use crate::testing::netem::{NetemConfig, NetemLink};
+use crate::utils::clock::{Clock, ManualClock};
+use crate::utils::timer::TimerQueue;
+
+/**
+ * 参与仿真的一端, 只通过帧和时间与外界交互
+ * 协议栈或测试内的简化端点实现这个 trait 后即可放进 Simulation
+ */
+pub trait SimNode {
+    /**
+     * 收到一帧, 返回需要立即发出的帧
+     */
+    fn receive(&mut self, frame: &[u8], now_ms: u64) -> Vec<Vec<u8>>;
+
+    /**
+     * 定时处理(重传等), 返回需要发出的帧
+     */
+    fn poll(&mut self, now_ms: u64) -> Vec<Vec<u8>>;
+
+    /**
+     * 下一次需要 poll 的时刻, 没有待处理的定时器时返回 None
+     */
+    fn next_wakeup_ms(&mut self) -> Option<u64>;
+}
+
+/**
+ * 两端中的一端
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    A,
+    B,
+}
+
+impl Side {
+    fn index(self) -> usize {
+        match self {
+            Side::A => 0,
+            Side::B => 1,
+        }
+    }
+
+    pub fn peer(self) -> Side {
+        match self {
+            Side::A => Side::B,
+            Side::B => Side::A,
+        }
+    }
+}
+
+type Action<N> = Box<dyn FnOnce(&mut N, u64) -> Vec<Vec<u8>>>;
+
+/**
+ * 脚本事件
+ */
+pub enum Event<N> {
+    InjectFrame { to: Side, frame: Vec<u8> },   // 绕过链路直接交给一端
+    LinkDown,                                   // 之后发出的帧全部丢失, 已在途的帧照常到达
+    LinkUp,
+    SetNetem { a_to_b: NetemConfig, b_to_a: NetemConfig, seed: u64 },
+    Act { on: Side, action: Action<N> },        // 应用层操作(写、关闭、中止等), 返回需要发出的帧
+}
+
+impl<N> Event<N> {
+    pub fn act(on: Side, action: impl FnOnce(&mut N, u64) -> Vec<Vec<u8>> + 'static) -> Self {
+        Event::Act { on, action: Box::new(action) }
+    }
+}
+
+/**
+ * 帧在仿真中的去向
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameOutcome {
+    Sent,        // 交给链路(可能被 netem 丢弃)
+    LinkDown,    // 链路断开, 直接丢失
+    Delivered,   // 到达对端
+    Injected,    // 脚本注入
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameRecord {
+    pub at_ms: u64,
+    pub from: Side,
+    pub outcome: FrameOutcome,
+    pub frame: Vec<u8>,
+}
+
+/**
+ * 仿真的结束原因
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimOutcome {
+    Idle,        // 没有任何待处理的事件
+    TimeLimit,
+}
+
+/**
+ * 确定性的两端仿真: 共享的 ManualClock、一条 NetemLink 和按时间排列的事件脚本
+ * 时钟总是直接跳到下一个事件(脚本、帧到达、节点定时器)
+ */
+pub struct Simulation<N: SimNode> {
+    nodes: [N; 2],
+    clock: ManualClock,
+    link: NetemLink,
+    link_up: bool,
+    script: TimerQueue<usize>,
+    events: Vec<Option<Event<N>>>,
+    records: Vec<FrameRecord>,
+}
+
+impl<N: SimNode> Simulation<N> {
+    pub fn new(a: N, b: N, link: NetemLink, clock: ManualClock) -> Self {
+        Simulation {
+            nodes: [a, b],
+            clock,
+            link,
+            link_up: true,
+            script: TimerQueue::new(),
+            events: Vec::new(),
+            records: Vec::new(),
+        }
+    }
+
+    /**
+     * 在 at_ms 时刻执行事件, 同一时刻的事件按添加顺序执行
+     */
+    pub fn at(&mut self, at_ms: u64, event: Event<N>) -> &mut Self {
+        self.script.schedule(self.events.len(), at_ms);
+        self.events.push(Some(event));
+        self
+    }
+
+    pub fn node(&self, side: Side) -> &N {
+        &self.nodes[side.index()]
+    }
+
+    pub fn node_mut(&mut self, side: Side) -> &mut N {
+        &mut self.nodes[side.index()]
+    }
+
+    pub fn now_ms(&self) -> u64 {
+        self.clock.now_ms()
+    }
+
+    pub fn records(&self) -> &[FrameRecord] {
+        &self.records
+    }
+
+    pub fn link(&mut self) -> &mut NetemLink {
+        &mut self.link
+    }
+
+    /**
+     * 一直运行到没有事件, 或下一个事件晚于 limit_ms
+     */
+    pub fn run_until(&mut self, limit_ms: u64) -> SimOutcome {
+        loop {
+            let now = self.clock.now_ms();
+            self.step(now);
+
+            let next = [
+                self.script.next_deadline(),
+                self.link.next_delivery_ms(),
+                self.nodes[0].next_wakeup_ms(),
+                self.nodes[1].next_wakeup_ms(),
+            ].into_iter().flatten().min();
+            match next {
+                None => return SimOutcome::Idle,
+                Some(at) if at > limit_ms => {
+                    self.clock.set(limit_ms);
+                    return SimOutcome::TimeLimit;
+                }
+                // 节点的定时器可能已经过期, 时钟不倒退, 至少前进 1ms 避免空转
+                Some(at) => self.clock.set(at.max(now + 1)),
+            }
+        }
+    }
+
+    fn step(&mut self, now: u64) {
+        for id in self.script.expired(now) {
+            if let Some(event) = self.events[id].take() {
+                self.apply(event, now);
+            }
+        }
+
+        let to_b = self.link.a_to_b.poll(now);
+        let to_a = self.link.b_to_a.poll(now);
+        self.deliver(Side::B, to_b, now);
+        self.deliver(Side::A, to_a, now);
+
+        for side in [Side::A, Side::B] {
+            let out = self.nodes[side.index()].poll(now);
+            self.transmit(side, out, now);
+        }
+    }
+
+    fn apply(&mut self, event: Event<N>, now: u64) {
+        match event {
+            Event::InjectFrame { to, frame } => {
+                self.records.push(FrameRecord { at_ms: now, from: to.peer(), outcome: FrameOutcome::Injected, frame: frame.clone() });
+                let out = self.nodes[to.index()].receive(&frame, now);
+                self.transmit(to, out, now);
+            }
+            Event::LinkDown => self.link_up = false,
+            Event::LinkUp => self.link_up = true,
+            Event::SetNetem { a_to_b, b_to_a, seed } => {
+                // 在途的帧随旧链路一起丢弃
+                self.link = NetemLink::with_configs(a_to_b, b_to_a, seed);
+            }
+            Event::Act { on, action } => {
+                let out = action(&mut self.nodes[on.index()], now);
+                self.transmit(on, out, now);
+            }
+        }
+    }
+
+    fn deliver(&mut self, to: Side, frames: Vec<Vec<u8>>, now: u64) {
+        for frame in frames {
+            let out = self.nodes[to.index()].receive(&frame, now);
+            self.records.push(FrameRecord { at_ms: now, from: to.peer(), outcome: FrameOutcome::Delivered, frame });
+            self.transmit(to, out, now);
+        }
+    }
+
+    fn transmit(&mut self, from: Side, frames: Vec<Vec<u8>>, now: u64) {
+        for frame in frames {
+            if !self.link_up {
+                self.records.push(FrameRecord { at_ms: now, from, outcome: FrameOutcome::LinkDown, frame });
+                continue;
+            }
+            self.records.push(FrameRecord { at_ms: now, from, outcome: FrameOutcome::Sent, frame: frame.clone() });
+            match from {
+                Side::A => self.link.a_to_b.send(frame, now),
+                Side::B => self.link.b_to_a.send(frame, now),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /**
+     * 收到帧原样回送一次(帧首字节为 0 的不再回送), 定时器固定在 wake 时刻发出一帧
+     */
+    struct Echo {
+        wake: Option<u64>,
+        got: Vec<(u64, Vec<u8>)>,
+    }
+
+    impl SimNode for Echo {
+        fn receive(&mut self, frame: &[u8], now_ms: u64) -> Vec<Vec<u8>> {
+            self.got.push((now_ms, frame.to_vec()));
+            if frame[0] == 0 { vec![] } else { vec![vec![0, frame[0]]] }
+        }
+
+        fn poll(&mut self, now_ms: u64) -> Vec<Vec<u8>> {
+            match self.wake {
+                Some(at) if at <= now_ms => {
+                    self.wake = None;
+                    vec![vec![7]]
+                }
+                _ => vec![],
+            }
+        }
+
+        fn next_wakeup_ms(&mut self) -> Option<u64> {
+            self.wake
+        }
+    }
+
+    #[test]
+    fn test_timers_links_and_script() {
+        let config = NetemConfig { delay_ms: 10, ..NetemConfig::default() };
+        let a = Echo { wake: Some(5), got: vec![] };
+        let b = Echo { wake: None, got: vec![] };
+        let mut sim = Simulation::new(a, b, NetemLink::new(config, 1), ManualClock::new(0));
+        sim.at(100, Event::LinkDown)
+            .at(100, Event::act(Side::A, |_, _| vec![vec![9]]))
+            .at(200, Event::InjectFrame { to: Side::B, frame: vec![3] });
+
+        assert_eq!(sim.run_until(1_000), SimOutcome::Idle);
+        assert_eq!(sim.node(Side::B).got, vec![(15, vec![7]), (200, vec![3])]);
+        assert_eq!(sim.node(Side::A).got, vec![(25, vec![0, 7])]);
+
+        let lost: Vec<&FrameRecord> = sim.records().iter().filter(|r| r.outcome == FrameOutcome::LinkDown).collect();
+        assert_eq!(lost.len(), 2); // A 在 100ms 发出的帧和 B 在 200ms 的回送
+        assert_eq!(sim.now_ms(), 200);
+    }
+
+    #[test]
+    fn test_time_limit() {
+        let a = Echo { wake: Some(5_000), got: vec![] };
+        let b = Echo { wake: None, got: vec![] };
+        let mut sim = Simulation::new(a, b, NetemLink::new(NetemConfig::default(), 1), ManualClock::new(0));
+        assert_eq!(sim.run_until(1_000), SimOutcome::TimeLimit);
+        assert_eq!(sim.now_ms(), 1_000);
+        assert!(sim.records().is_empty());
+    }
+}
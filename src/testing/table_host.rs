@@ -0,0 +1,96 @@
+use crate::config::{Ipv4Config, TcpConfig};
+use crate::net::ipv4::Ipv4Datagram;
+use crate::testing::sim::SimNode;
+use crate::transport::connection_table::{listener_id, ConnectionTable};
+use crate::transport::tcp_connection::ConnectionId;
+use crate::transport::tcp_segment::TcpSegment;
+use crate::utils::drops::{DropCounters, DropReason};
+use crate::utils::wire::WireSerialize;
+
+/**
+ * 一张连接表作为仿真的一端: 把 data 写完并读出对端发来的全部数据, 帧就是序列化后的 IP 数据报
+ * 连接表的 RTO 到期时 (retransmissions_due) 调用 retransmission
+ * TCP 校验和在交给连接表之前检查, 错误的段记入 drops
+ */
+pub struct TableHost {
+    pub table: ConnectionTable,
+    pub id: ConnectionId,
+    pub data: Vec<u8>,
+    pub received: Vec<u8>,
+    pub drops: DropCounters,
+    pub finished_at: Option<u64>, // data 全部被确认的时刻
+    written: usize,
+}
+
+impl TableHost {
+    /**
+     * id 为本端视角的连接; 发起连接的一端在脚本里调用 table.connect 并把 SYN 交给 frames
+     */
+    pub fn new(config: &TcpConfig, id: ConnectionId, data: Vec<u8>) -> Self {
+        TableHost {
+            table: ConnectionTable::new(config), id, data, received: vec![], drops: DropCounters::new(), finished_at: None,
+            written: 0,
+        }
+    }
+
+    /**
+     * 监听 id 的本端地址, 等对端发起连接
+     */
+    pub fn listening(config: &TcpConfig, id: ConnectionId, data: Vec<u8>) -> Self {
+        let mut host = TableHost::new(config, id, data);
+        host.table.listen(id.s_ip, id.s_port);
+        host
+    }
+
+    /**
+     * 每次收发之后调用: 取走握手完成的连接, 读出数据, 重传 RTO 到期的段, 继续写入, 交出连接表要发的段
+     */
+    pub fn frames(&mut self, segments: Vec<TcpSegment>, now_ms: u64) -> Vec<Vec<u8>> {
+        let config = Ipv4Config::default();
+        let mut frames: Vec<Vec<u8>> = segments.iter().filter_map(|seg| self.table.datagram(self.id, seg, &config)).map(|d| d.serialize()).collect();
+        self.table.accept(listener_id(self.id.s_ip, self.id.s_port));
+        if let Ok(data) = self.table.read(self.id, usize::MAX) {
+            self.received.extend(data);
+        }
+        for id in self.table.retransmissions_due(now_ms) {
+            if let Some(seg) = self.table.retransmission(id) {
+                frames.extend(self.table.datagram(id, &seg, &config).map(|d| d.serialize()));
+            }
+        }
+        if self.table.error(self.id).is_none() && self.table.send_queued(self.id).is_some() {
+            self.written += self.table.write(self.id, &self.data[self.written..]).unwrap_or(0);
+            let done = self.written == self.data.len() && !self.data.is_empty();
+            if done && self.table.send_queued(self.id) == Some(0) && self.finished_at.is_none() {
+                self.finished_at = Some(now_ms);
+            }
+        }
+        for (id, seg) in self.table.poll(now_ms) {
+            frames.extend(self.table.datagram(id, &seg, &config).map(|d| d.serialize()));
+        }
+        frames
+    }
+}
+
+impl SimNode for TableHost {
+    fn receive(&mut self, frame: &[u8], now_ms: u64) -> Vec<Vec<u8>> {
+        let datagram = Ipv4Datagram::try_deserialize(frame).unwrap();
+        let segment = TcpSegment::try_deserialize(datagram.payload()).unwrap();
+        if !segment.check_checksum(datagram.s_addr(), datagram.d_addr()) {
+            self.drops.record(DropReason::BadTcpChecksum);
+            return vec![];
+        }
+        let replies = self.table.datagram_received(&datagram, now_ms);
+        self.frames(replies, now_ms)
+    }
+
+    fn poll(&mut self, now_ms: u64) -> Vec<Vec<u8>> {
+        self.frames(vec![], now_ms)
+    }
+
+    /**
+     * 连接表最早的定时器: 重传、pacing 放出下一段等
+     */
+    fn next_wakeup_ms(&mut self) -> Option<u64> {
+        self.table.next_timer_ms()
+    }
+}
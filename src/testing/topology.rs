@@ -0,0 +1,114 @@
+use crate::config::{ArpConfig, Ipv4Config, StackConfig};
+use crate::link::interface::EthernetInterface;
+use crate::net::interfaces::{InterfaceId, InterfaceSet};
+use crate::stack::Stack;
+
+/**
+ * 一台主机: 一个 /24 接口; gateway 为默认路由的下一跳, None 时只有直连路由
+ */
+pub fn host(mac: [u8; 6], ip: u32, gateway: Option<u32>) -> InterfaceSet {
+    let mut host = InterfaceSet::new(&Ipv4Config::default(), &ArpConfig::default());
+    host.add_interface(EthernetInterface::new(mac, ip, 24));
+    if let Some(gateway) = gateway {
+        host.routes_mut().add(0, 0, Some(gateway));
+    }
+    host
+}
+
+/**
+ * 同样配置的协议栈: 一个 /24 接口, gateway 为默认路由的下一跳
+ */
+pub fn stack(mac: [u8; 6], ip: u32, gateway: Option<u32>) -> Stack {
+    let mut stack = Stack::new(&StackConfig::default()).unwrap();
+    stack.add_interface(EthernetInterface::new(mac, ip, 24));
+    if let Some(gateway) = gateway {
+        stack.interfaces_mut().routes_mut().add(0, 0, Some(gateway));
+    }
+    stack
+}
+
+/**
+ * 可以接进 Topology 的节点: 只有 IP 层的 InterfaceSet, 或者带着连接表的 Stack
+ */
+pub trait TopologyNode {
+    fn frame_received(&mut self, id: InterfaceId, bytes: Vec<u8>);
+    fn take_tx(&mut self, id: InterfaceId) -> Vec<Vec<u8>>;
+    fn poll(&mut self, now_ms: u64);
+}
+
+impl TopologyNode for InterfaceSet {
+    fn frame_received(&mut self, id: InterfaceId, bytes: Vec<u8>) {
+        InterfaceSet::frame_received(self, id, bytes);
+    }
+
+    fn take_tx(&mut self, id: InterfaceId) -> Vec<Vec<u8>> {
+        InterfaceSet::take_tx(self, id)
+    }
+
+    fn poll(&mut self, now_ms: u64) {
+        InterfaceSet::poll(self, usize::MAX, now_ms);
+    }
+}
+
+impl TopologyNode for Stack {
+    fn frame_received(&mut self, id: InterfaceId, bytes: Vec<u8>) {
+        Stack::frame_received(self, id, bytes);
+    }
+
+    fn take_tx(&mut self, id: InterfaceId) -> Vec<Vec<u8>> {
+        Stack::take_tx(self, id)
+    }
+
+    fn poll(&mut self, now_ms: u64) {
+        Stack::poll(self, now_ms);
+    }
+}
+
+/**
+ * 三个节点 A -- R -- B: A 接 R 的接口 0, B 接 R 的接口 1, 帧在内存中直接搬运
+ * A、B 是 InterfaceSet (host) 或 Stack (stack), R 是一个有两个接口的 InterfaceSet; 所有节点都在 now_ms 时刻 poll
+ * Topology::new(host(A_MAC, A_IP, Some(R_IP0)), router, host(B_MAC, B_IP, Some(R_IP1)))
+ */
+pub struct Topology<H: TopologyNode = InterfaceSet> {
+    pub a: H,
+    pub r: InterfaceSet,
+    pub b: H,
+    pub now_ms: u64,
+}
+
+impl<H: TopologyNode> Topology<H> {
+    pub fn new(a: H, r: InterfaceSet, b: H) -> Self {
+        Topology { a, r, b, now_ms: 0 }
+    }
+
+    /**
+     * 三方轮流 poll 并搬运帧, 直到没有要发的
+     */
+    pub fn run(&mut self) {
+        loop {
+            self.a.poll(self.now_ms);
+            self.r.poll(usize::MAX, self.now_ms);
+            self.b.poll(self.now_ms);
+            let mut moved = false;
+            for bytes in self.a.take_tx(0) {
+                self.r.frame_received(0, bytes);
+                moved = true;
+            }
+            for bytes in self.b.take_tx(0) {
+                self.r.frame_received(1, bytes);
+                moved = true;
+            }
+            for bytes in self.r.take_tx(0) {
+                self.a.frame_received(0, bytes);
+                moved = true;
+            }
+            for bytes in self.r.take_tx(1) {
+                self.b.frame_received(0, bytes);
+                moved = true;
+            }
+            if !moved {
+                return;
+            }
+        }
+    }
+}
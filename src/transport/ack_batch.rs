@@ -0,0 +1,117 @@
+use super::seq::seq_lt;
+use super::tcp_option::TcpOption;
+use super::tcp_segment::{TcpFlags, TcpSegment};
+
+/**
+ * 一批纯 ACK 合并后的结果
+ * ack 为最高的累计确认号, window 为最后一个有效 ACK 携带的窗口
+ * dup_acks 为最后一次前进之后的重复 ACK 个数(RFC 5681: 同一 ack、同一窗口、无数据)
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AckSummary {
+    pub ack: u32,
+    pub window: u16,
+    pub dup_acks: u32,
+    pub absorbed: u32,
+}
+
+/**
+ * 没有数据、控制位只有 ACK, 选项至多是时间戳和填充
+ * 带 SACK 块的 ACK 需要逐个更新记分板, 不走合并
+ */
+pub fn is_pure_ack(segment: &TcpSegment) -> bool {
+    segment.ctrl == TcpFlags::ACK
+        && segment.data.is_empty()
+        && segment.parsed_options().is_ok_and(|options| options.iter().all(|option| {
+            matches!(option, TcpOption::Nop | TcpOption::EndOfList | TcpOption::Timestamps { .. })
+        }))
+}
+
+/**
+ * 一次 poll 内收到的纯 ACK 先在这里合并, poll 结束时交给发送端处理一次
+ * 重复 ACK 逐个计数, 快速重传看到的个数与逐个处理时相同
+ */
+#[derive(Debug, Default)]
+pub struct AckBatch {
+    pending: Option<AckSummary>,
+}
+
+impl AckBatch {
+    pub fn new() -> Self {
+        AckBatch { pending: None }
+    }
+
+    /**
+     * 纯 ACK 被吸收返回 true, 其他报文返回 false 由调用方走完整路径
+     * snd_una / snd_wnd 是发送端当前的状态, 只在批次为空时用作起点
+     */
+    pub fn offer(&mut self, segment: &TcpSegment, snd_una: u32, snd_wnd: u16) -> bool {
+        if !is_pure_ack(segment) {
+            return false;
+        }
+        let pending = self.pending.get_or_insert(AckSummary { ack: snd_una, window: snd_wnd, dup_acks: 0, absorbed: 0 });
+        pending.absorbed += 1;
+        if seq_lt(pending.ack, segment.ack) {
+            pending.ack = segment.ack;
+            pending.window = segment.win_size;
+            pending.dup_acks = 0;
+        } else if segment.ack == pending.ack {
+            if segment.win_size == pending.window {
+                pending.dup_acks += 1;
+            } else {
+                pending.window = segment.win_size; // 窗口更新, 不算重复 ACK
+            }
+        }
+        // 比已有 ack 更旧的报文只计数, 窗口也不采用
+        true
+    }
+
+    pub fn take(&mut self) -> Option<AckSummary> {
+        self.pending.take()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ack(ack: u32, win: u16) -> TcpSegment {
+        TcpSegment::new(80, 40000, 1, ack, 5, 0, TcpFlags::ACK, win, 0, vec![], vec![])
+    }
+
+    #[test]
+    fn test_pure_ack_detection() {
+        assert!(is_pure_ack(&ack(1, 100)));
+        let mut with_data = ack(1, 100);
+        with_data.data = b"x".to_vec();
+        assert!(!is_pure_ack(&with_data));
+        let fin = TcpSegment::new(80, 40000, 1, 1, 5, 0, TcpFlags::ACK | TcpFlags::FIN, 100, 0, vec![], vec![]);
+        assert!(!is_pure_ack(&fin));
+        let timestamps = TcpSegment::new(80, 40000, 1, 1, 8, 0, TcpFlags::ACK, 100, 0, vec![0x0101_080a, 1, 2], vec![]);
+        assert!(is_pure_ack(&timestamps));
+        let sack = TcpSegment::new(80, 40000, 1, 1, 8, 0, TcpFlags::ACK, 100, 0, vec![0x0101_050a, 10, 20], vec![]);
+        assert!(!is_pure_ack(&sack));
+    }
+
+    #[test]
+    fn test_batch_keeps_dup_signal() {
+        let mut batch = AckBatch::new();
+        assert!(batch.offer(&ack(100, 500), 0, 500));
+        assert!(batch.offer(&ack(100, 500), 0, 500));
+        assert!(batch.offer(&ack(100, 600), 0, 500)); // 窗口更新
+        assert!(batch.offer(&ack(100, 600), 0, 500));
+        assert!(batch.offer(&ack(100, 600), 0, 500));
+        assert!(batch.offer(&ack(50, 900), 0, 500)); // 旧 ACK
+        assert_eq!(batch.take(), Some(AckSummary { ack: 100, window: 600, dup_acks: 3, absorbed: 6 }));
+        assert!(batch.is_empty());
+
+        // 批次起点来自发送端, 第一个 ACK 就可以是重复的
+        batch.offer(&ack(100, 600), 100, 600);
+        assert_eq!(batch.take().unwrap().dup_acks, 1);
+    }
+}
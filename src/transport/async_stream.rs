@@ -0,0 +1,218 @@
+use std::cell::RefCell;
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use crate::utils::waker::{Interest, WakerRegistry};
+
+use super::connection_table::{ConnectionTable, Readiness};
+use super::stream::{io_error, Stream};
+use super::tcp_connection::ConnectionId;
+use super::tcp_segment::TcpSegment;
+
+struct Inner {
+    table: ConnectionTable,
+    wakers: WakerRegistry,
+    outbox: Vec<(ConnectionId, TcpSegment)>, // connect 产生的 SYN, 随下一次 poll 交出
+    now_ms: u64,
+}
+
+impl Inner {
+    /**
+     * 按连接表的就绪变化唤醒等待的任务; 每次访问连接表之后都调用, 变化不会攒到下一次
+     * ERROR 同时唤醒读写两边, 让它们观察到错误
+     */
+    fn wake_ready(&mut self) {
+        for (id, readiness) in self.table.readiness_changes() {
+            if readiness.contains(Readiness::ERROR) {
+                self.wakers.wake_all(id);
+                continue;
+            }
+            if readiness.contains(Readiness::READABLE) {
+                self.wakers.wake(id, Interest::Readable);
+            }
+            if readiness.contains(Readiness::WRITABLE) {
+                self.wakers.wake(id, Interest::Writable);
+            }
+            if readiness.contains(Readiness::ACCEPT) {
+                self.wakers.wake(id, Interest::Acceptable);
+            }
+        }
+    }
+}
+
+/**
+ * 连接表的异步视图: 驱动循环照常收发报文段, 任务通过 AsyncTcpStream / AsyncTcpListener 读写
+ * 任务未就绪时在 WakerRegistry 登记, 连接表的就绪状态变化时唤醒; 不依赖任何运行时
+ * 与 SharedTable 一样只在一个线程上使用, 克隆得到同一个连接表
+ */
+#[derive(Clone)]
+pub struct AsyncStack {
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl AsyncStack {
+    pub fn new(table: ConnectionTable) -> Self {
+        AsyncStack {
+            inner: Rc::new(RefCell::new(Inner { table, wakers: WakerRegistry::new(), outbox: vec![], now_ms: 0 })),
+        }
+    }
+
+    /**
+     * 在连接表上执行 f, 之后唤醒状态发生变化的任务
+     */
+    pub fn with<R>(&self, f: impl FnOnce(&mut ConnectionTable) -> R) -> R {
+        let mut inner = self.inner.borrow_mut();
+        let out = f(&mut inner.table);
+        inner.wake_ready();
+        out
+    }
+
+    /**
+     * 见 ConnectionTable::segment_received, 返回需要立即发出的应答
+     */
+    pub fn segment_received(&self, s_addr: u32, d_addr: u32, segment: &TcpSegment, now_ms: u64) -> Vec<TcpSegment> {
+        self.inner.borrow_mut().now_ms = now_ms;
+        self.with(|table| table.segment_received(s_addr, d_addr, segment, now_ms))
+    }
+
+    /**
+     * 见 ConnectionTable::poll, connect 发起的 SYN 排在最前面
+     */
+    pub fn poll(&self, now_ms: u64) -> Vec<(ConnectionId, TcpSegment)> {
+        let mut out = {
+            let mut inner = self.inner.borrow_mut();
+            inner.now_ms = now_ms;
+            std::mem::take(&mut inner.outbox)
+        };
+        out.extend(self.with(|table| table.poll(now_ms)));
+        out
+    }
+
+    pub fn listen(&self, ip: u32, port: u16) -> AsyncTcpListener {
+        let id = self.with(|table| table.listen(ip, port));
+        AsyncTcpListener { stack: self.clone(), id }
+    }
+
+    /**
+     * 主动打开, SYN 由下一次 poll 交出; 握手完成之前写入的数据进发送缓冲区, 读在握手完成前保持 Pending
+     */
+    pub fn connect(&self, id: ConnectionId) -> AsyncTcpStream {
+        let mut inner = self.inner.borrow_mut();
+        let now_ms = inner.now_ms;
+        let syn = inner.table.connect(id, now_ms);
+        inner.outbox.push((id, syn));
+        inner.wake_ready();
+        drop(inner);
+        AsyncTcpStream { stack: self.clone(), id }
+    }
+
+    /**
+     * 登记在册、还没被唤醒的任务数
+     */
+    pub fn waiting(&self) -> usize {
+        self.inner.borrow().wakers.len()
+    }
+}
+
+/**
+ * 监听端口, accept 返回一个在有连接完成握手时就绪的 future
+ */
+pub struct AsyncTcpListener {
+    stack: AsyncStack,
+    id: ConnectionId,
+}
+
+impl AsyncTcpListener {
+    pub fn id(&self) -> ConnectionId {
+        self.id
+    }
+
+    pub fn accept(&self) -> Accept<'_> {
+        Accept { listener: self }
+    }
+}
+
+pub struct Accept<'a> {
+    listener: &'a AsyncTcpListener,
+}
+
+impl Future for Accept<'_> {
+    type Output = AsyncTcpStream;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<AsyncTcpStream> {
+        let listener = self.listener;
+        let mut inner = listener.stack.inner.borrow_mut();
+        let accepted = inner.table.accept(listener.id);
+        inner.wake_ready();
+        match accepted {
+            Some(id) => Poll::Ready(AsyncTcpStream { stack: listener.stack.clone(), id }),
+            None => {
+                inner.wakers.register(listener.id, Interest::Acceptable, cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/**
+ * 连接的 futures-io 视图, 语义同 Stream: 读到对端的 FIN 后 poll_read 返回 0
+ * 没有数据可读或发送缓冲区已满时登记 waker 并返回 Pending
+ */
+pub struct AsyncTcpStream {
+    stack: AsyncStack,
+    id: ConnectionId,
+}
+
+impl AsyncTcpStream {
+    pub fn id(&self) -> ConnectionId {
+        self.id
+    }
+
+    /**
+     * 在连接的 Stream 上执行 f; WouldBlock 时按 interest 登记 waker
+     */
+    fn poll_io<R>(&self, cx: &mut Context<'_>, interest: Interest, f: impl FnOnce(&mut Stream<'_>) -> io::Result<R>) -> Poll<io::Result<R>> {
+        let mut inner = self.stack.inner.borrow_mut();
+        let result = f(&mut inner.table.stream(self.id));
+        inner.wake_ready();
+        match result {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                inner.wakers.register(self.id, interest, cx.waker());
+                Poll::Pending
+            }
+            result => Poll::Ready(result),
+        }
+    }
+}
+
+impl AsyncRead for AsyncTcpStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        self.poll_io(cx, Interest::Readable, |stream| stream.read(buf))
+    }
+}
+
+impl AsyncWrite for AsyncTcpStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.poll_io(cx, Interest::Writable, |stream| stream.write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_io(cx, Interest::Writable, |stream| stream.flush())
+    }
+
+    /**
+     * 发出 FIN, 不等待对端确认
+     */
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut inner = self.stack.inner.borrow_mut();
+        let now_ms = inner.now_ms;
+        let result = inner.table.close(self.id, now_ms);
+        inner.wake_ready();
+        Poll::Ready(result.map_err(io_error))
+    }
+}
@@ -0,0 +1,1736 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io;
+use std::ops::{BitOr, BitOrAssign};
+use std::time::Duration;
+
+use crate::config::{Ipv4Config, TcpConfig};
+use crate::link::interface::PacketMeta;
+use crate::net::dscp::Dscp;
+use crate::net::icmp_v4::IcmpV4;
+use crate::net::ipv4::Ipv4Datagram;
+use crate::net::pmtu::{self, PmtuCache};
+use crate::utils::checksum::ChecksumPolicy;
+use crate::utils::memory::{MemoryBudget, MemoryUsage};
+use crate::utils::timer::TimerQueue;
+use crate::utils::trace::{SegmentVerdict, SharedTraceSink, TraceSink, Tracer};
+use crate::utils::wire::WireSerialize;
+
+use super::destination_cache::DestinationCache;
+use super::fast_open::{fast_open_cookie, TfoCookies, TfoDecision, TfoListener, TfoStats};
+use super::isn::IsnGenerator;
+use super::latency::ConnectionLatency;
+use super::md5_signature;
+use super::socket_options::{ListenOptions, SocketOptions};
+use super::stream::Stream;
+use super::syn_cookie::{AcceptFilter, Accepted, BacklogStats, SynAction, SynBacklog};
+use super::tcp_option::{NegotiatedOptions, TcpOption};
+use super::tcp_connection::{ConnectionError, ConnectionId, ConnectionSnapshot, IcmpAdvice, IdleAction, PeerSynInfo, StallDiagnosis, TcpConnection, TcpState};
+use super::tcp_segment::{TcpFlags, TcpSegment};
+
+const PROTOCOL_TCP: u8 = 6;
+const PROTOCOL_ICMP: u8 = 1;
+const DEFAULT_PEER_MSS: u16 = 536; // SYN 不带 MSS 选项时对端的 MSS (RFC 1122 4.2.2.6)
+
+/**
+ * 连接或监听端口上可以做的事, 可以按位组合
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Readiness(u8);
+
+impl Readiness {
+    pub const EMPTY: Readiness = Readiness(0);
+    pub const READABLE: Readiness = Readiness(1);  // 有数据可读, 或读到 EOF
+    pub const WRITABLE: Readiness = Readiness(2);  // 发送缓冲区有空间
+    pub const ERROR: Readiness = Readiness(4);     // 被重置、被拒绝或超时
+    pub const ACCEPT: Readiness = Readiness(8);    // 监听端口上有完成握手的连接等待 accept
+    pub const READ_HIGH: Readiness = Readiness(16); // 未读数据超过接收高水位
+
+    const NAMES: [(Readiness, &'static str); 5] = [
+        (Readiness::READABLE, "READABLE"),
+        (Readiness::WRITABLE, "WRITABLE"),
+        (Readiness::ERROR, "ERROR"),
+        (Readiness::ACCEPT, "ACCEPT"),
+        (Readiness::READ_HIGH, "READ_HIGH"),
+    ];
+
+    pub const fn contains(self, other: Readiness) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for Readiness {
+    type Output = Readiness;
+
+    fn bitor(self, rhs: Readiness) -> Readiness {
+        Readiness(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Readiness {
+    fn bitor_assign(&mut self, rhs: Readiness) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl fmt::Display for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = Self::NAMES.iter().filter(|(bit, _)| self.contains(*bit)).map(|(_, name)| *name).collect();
+        write!(f, "{}", names.join("|"))
+    }
+}
+
+/**
+ * 关闭过程中一个连接的结局
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownOutcome {
+    Graceful, // 双方的 FIN 都已确认, 或者已经进入 TimeWait
+    Aborted,  // 截止时间到了还没有关完, 发 RST 放弃; 也包括关闭前已经出错的连接
+}
+
+/**
+ * 一个连接的关闭结果, unsent 为放弃时还没有被对端确认的字节数
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownEntry {
+    pub id: ConnectionId,
+    pub outcome: ShutdownOutcome,
+    pub unsent: usize,
+}
+
+/**
+ * 整张表的关闭报告, 按 ConnectionId 排序
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    pub entries: Vec<ShutdownEntry>,
+}
+
+impl ShutdownReport {
+    pub fn graceful(&self) -> Vec<ConnectionId> {
+        self.with_outcome(ShutdownOutcome::Graceful)
+    }
+
+    pub fn aborted(&self) -> Vec<ConnectionId> {
+        self.with_outcome(ShutdownOutcome::Aborted)
+    }
+
+    fn with_outcome(&self, outcome: ShutdownOutcome) -> Vec<ConnectionId> {
+        self.entries.iter().filter(|entry| entry.outcome == outcome).map(|entry| entry.id).collect()
+    }
+}
+
+/**
+ * 半连接随 SynBacklog 保存的内容: 握手完成时用它建立连接
+ * 对端重传的 SYN 原样得到第一次的 SYN|ACK, 协商结果也不变
+ * early 为握手完成之前到达、不带 ACK 的数据段, 总量不超过接收缓冲区
+ */
+struct HalfOpenSyn {
+    syn: TcpSegment,
+    ip: Option<(u8, Dscp)>,
+    peer: PeerSynInfo,
+    syn_ack: Option<TcpSegment>,
+    negotiated: Option<NegotiatedOptions>,
+    early: Vec<TcpSegment>,
+    early_bytes: usize,
+}
+
+/**
+ * 监听端口的标识: 对端地址和端口为 0
+ */
+pub fn listener_id(ip: u32, port: u16) -> ConnectionId {
+    ConnectionId { s_ip: ip, s_port: port, d_ip: 0, d_port: 0 }
+}
+
+/**
+ * 本机所有连接和监听端口, 以本端视角的 ConnectionId 为键
+ * 每次通过表修改连接之后重新计算该连接的就绪状态并缓存, readiness 不扫描缓冲区;
+ * 就绪状态变化过的连接记入 changed, 供边沿触发的 readiness_changes 使用
+ * 同时按连接的 next_timer_ms 与 rto_deadline_ms 排好定时器, tick 只处理到期的连接
+ */
+pub struct ConnectionTable {
+    config: TcpConfig,
+    conns: HashMap<ConnectionId, TcpConnection>,
+    listeners: HashMap<ConnectionId, VecDeque<ConnectionId>>, // 等待 accept 的连接
+    backlogs: HashMap<ConnectionId, SynBacklog<HalfOpenSyn>>, // 监听端口的半连接, 握手完成之前不建立 TcpConnection
+    fast_open: HashMap<ConnectionId, TfoListener>,            // 开启了 TFO 的监听端口
+    tfo_cookies: HashMap<u32, Vec<u8>>,                       // 主动打开时从对端 SYN|ACK 得到的 TFO cookie, 按对端地址
+    ready: BTreeMap<ConnectionId, Readiness>,                 // 只保存非空的就绪状态
+    reported: BTreeMap<ConnectionId, Readiness>,              // 边沿触发模式上次报告的状态
+    changed: BTreeSet<ConnectionId>,
+    active: VecDeque<ConnectionId>,                           // 有待发数据的连接, 轮转顺序跨 poll 保留
+    scheduled: HashSet<ConnectionId>,                         // 已在 active 中的连接, 入队前查重用
+    sent_last_poll: HashMap<ConnectionId, u32>,
+    acked: BTreeSet<ConnectionId>,                            // 本轮收到过报文段, 可能有合并中的 ACK
+    memory: MemoryBudget,
+    accepting: bool,                                          // 关闭开始后监听端口不再接受 SYN
+    shutdown_deadline: Option<u64>,
+    resets: VecDeque<(ConnectionId, TcpSegment)>,             // 放弃连接时的 RST, 下次 poll_transmit 最先发出
+    ip_id: u16,                                               // 封装数据报时使用的 IP 标识
+    checksum_policy: ChecksumPolicy,
+    isn: IsnGenerator,
+    latency: HashMap<ConnectionId, ConnectionLatency>,        // 经 segment_received_with_meta / frame_transmitted 记录的延迟
+    retransmissions: u64,                                     // 经 retransmission 重传的段数, 连接删除后仍保留
+    stalls: VecDeque<StallDiagnosis>,                         // tick 中收集、还没有被取走的卡死诊断
+    stalls_detected: u64,
+    destinations: DestinationCache,                           // 新连接按对端地址取 RTT 等起始值, 连接结束时更新
+    pmtu: PmtuCache,                                          // 按目的地址发现的路径 MTU, 同一目的地址的连接共用
+    clock_ms: u64,                                            // tick 和收到报文时见过的最大时刻, 时钟回退时沿用它
+    clock_regressions: u64,                                   // 时钟回退的次数
+    md5_keys: HashMap<u32, Vec<u8>>,                          // 按对端地址的 RFC 2385 签名密钥
+    tracer: Tracer,                                           // 收发的报文段和连接的状态变化
+    timers: TimerQueue<ConnectionId>,                         // 各连接下一次需要 tick 的时刻
+    rto_timers: TimerQueue<ConnectionId>,                     // 各连接的重传时刻, 由 retransmissions_due 取出
+}
+
+impl ConnectionTable {
+    pub fn new(config: &TcpConfig) -> Self {
+        ConnectionTable {
+            config: config.clone(),
+            conns: HashMap::new(),
+            listeners: HashMap::new(),
+            backlogs: HashMap::new(),
+            fast_open: HashMap::new(),
+            tfo_cookies: HashMap::new(),
+            ready: BTreeMap::new(),
+            reported: BTreeMap::new(),
+            changed: BTreeSet::new(),
+            active: VecDeque::new(),
+            scheduled: HashSet::new(),
+            sent_last_poll: HashMap::new(),
+            acked: BTreeSet::new(),
+            memory: MemoryBudget::unlimited(),
+            accepting: true,
+            shutdown_deadline: None,
+            resets: VecDeque::new(),
+            ip_id: 0,
+            checksum_policy: ChecksumPolicy::default(),
+            isn: IsnGenerator::new(),
+            latency: HashMap::new(),
+            retransmissions: 0,
+            stalls: VecDeque::new(),
+            stalls_detected: 0,
+            destinations: DestinationCache::new(config.destination_cache_size, config.destination_cache_ttl_ms),
+            pmtu: PmtuCache::new(config.mss.saturating_add(40)), // 表不知道接口 MTU, 按本端 MSS 加首部推算
+            clock_ms: 0,
+            clock_regressions: 0,
+            md5_keys: HashMap::new(),
+            tracer: Tracer::default(),
+            timers: TimerQueue::new(),
+            rto_timers: TimerQueue::new(),
+        }
+    }
+
+    /**
+     * 收到和发出的报文段、连接的状态变化交给 sink, 作用于已有和之后建立的连接; None 取消
+     * 收到的报文段在处理完之后带着处理结果报告, 处理中发生的状态变化先于它报告
+     */
+    pub fn set_trace_sink(&mut self, sink: Option<SharedTraceSink>) {
+        for conn in self.conns.values_mut() {
+            conn.set_trace_sink(sink.clone());
+        }
+        self.tracer = Tracer::new(sink);
+    }
+
+    fn trace_tx<'a>(&self, segments: impl IntoIterator<Item = (ConnectionId, &'a TcpSegment)>) {
+        self.tracer.emit(|sink| {
+            for (id, segment) in segments {
+                sink.on_segment_tx(&id, segment);
+            }
+        });
+    }
+
+    /**
+     * 时钟只能前进: 比见过的时刻早的读数计数后按见过的时刻处理, 定时器不会因此重新计时或提前到期
+     */
+    fn observe_clock(&mut self, now_ms: u64) -> u64 {
+        if now_ms < self.clock_ms {
+            self.clock_regressions += 1;
+            return self.clock_ms;
+        }
+        self.clock_ms = now_ms;
+        now_ms
+    }
+
+    pub fn clock_regressions(&self) -> u64 {
+        self.clock_regressions
+    }
+
+    /**
+     * 之后建立的连接向 budget 记账, 一般在建立任何连接之前设置
+     */
+    pub fn set_memory_budget(&mut self, budget: MemoryBudget) {
+        self.memory = budget;
+    }
+
+    /**
+     * 共享预算的用量, 包括同一预算下其他组件的记账
+     */
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.memory.usage()
+    }
+
+    fn new_connection(&mut self, id: ConnectionId, now_ms: u64) -> TcpConnection {
+        let mut conn = TcpConnection::with_config(id.s_ip, id.s_port, id.d_ip, id.d_port, &self.config, now_ms);
+        if let Some(metrics) = self.destinations.get(id.d_ip, now_ms) {
+            conn.seed_metrics(&metrics);
+        }
+        if let Some(mtu) = self.pmtu.discovered(id.d_ip, now_ms) {
+            conn.set_path_mtu(mtu);
+        }
+        conn.set_memory_budget(self.memory.clone());
+        conn.set_memory_share(self.memory_share(self.conns.len() + 1));
+        conn.set_checksum_policy(self.checksum_policy);
+        conn
+    }
+
+    /**
+     * 与 peer 之间之后建立的连接用 key 签名并校验 TCP MD5 签名选项 (RFC 2385), None 取消
+     * 没有连接时到达的报文段(SYN 与握手 ACK)在表中校验, 签名不对的静默丢弃
+     */
+    pub fn set_md5_key(&mut self, peer: u32, key: Option<Vec<u8>>) {
+        match key {
+            Some(key) => self.md5_keys.insert(peer, key),
+            None => self.md5_keys.remove(&peer),
+        };
+    }
+
+    fn md5_authentic(&self, s_addr: u32, d_addr: u32, segment: &TcpSegment) -> bool {
+        self.md5_keys.get(&s_addr).is_none_or(|key| md5_signature::verify(segment, s_addr, d_addr, key))
+    }
+
+    /**
+     * 出口链路的校验和策略, 作用于已有和之后建立的所有连接
+     */
+    pub fn set_checksum_policy(&mut self, policy: ChecksumPolicy) {
+        self.checksum_policy = policy;
+        for conn in self.conns.values_mut() {
+            conn.set_checksum_policy(policy);
+        }
+    }
+
+    /**
+     * 有内存上限时按连接数平分, 每个连接的发送侧和接收侧各占一半
+     */
+    fn memory_share(&self, conns: usize) -> usize {
+        if self.memory.limit() == usize::MAX {
+            return usize::MAX;
+        }
+        self.memory.limit() / (2 * conns.max(1))
+    }
+
+    /**
+     * 连接数变化后重新分配各连接的份额
+     */
+    fn rebalance_memory(&mut self) {
+        let share = self.memory_share(self.conns.len());
+        for conn in self.conns.values_mut() {
+            conn.set_memory_share(share);
+        }
+    }
+
+    /**
+     * 在 ip:port 上监听, ip 为 0 表示所有本机地址
+     * 半连接数与 accept 队列长度都以 syn_backlog 为上限; 是否接受 TFO 取自 tcp.fast_open
+     */
+    pub fn listen(&mut self, ip: u32, port: u16) -> ConnectionId {
+        let options = ListenOptions { fast_open: self.config.fast_open };
+        self.listen_with_options(ip, port, options)
+    }
+
+    /**
+     * 带选项的监听; 开启 fast_open 时监听端口持有自己的 TFO 密钥
+     */
+    pub fn listen_with_options(&mut self, ip: u32, port: u16, options: ListenOptions) -> ConnectionId {
+        let id = listener_id(ip, port);
+        self.listeners.entry(id).or_default();
+        let (config, now_ms) = (&self.config, self.clock_ms);
+        self.backlogs.entry(id).or_insert_with(|| SynBacklog::new(config, now_ms));
+        if options.fast_open {
+            self.fast_open.entry(id).or_insert_with(|| TfoListener::with_cookies(TfoCookies::new()));
+        } else {
+            self.fast_open.remove(&id);
+        }
+        id
+    }
+
+    /**
+     * 监听端口的 TFO 统计, 没有开启 TFO 时为 None
+     */
+    pub fn tfo_stats(&self, listener: ConnectionId) -> Option<TfoStats> {
+        self.fast_open.get(&listener).map(|tfo| tfo.stats())
+    }
+
+    /**
+     * 监听端口上新 SYN 的接受过滤器, 见 SynBacklog::set_accept_filter
+     */
+    pub fn set_accept_filter(&mut self, listener: ConnectionId, filter: AcceptFilter) -> Result<(), ConnectionError> {
+        self.backlogs.get_mut(&listener).ok_or(ConnectionError::NotConnected)?.set_accept_filter(filter);
+        Ok(())
+    }
+
+    /**
+     * 监听端口的半连接队列与 SYN cookie 统计
+     */
+    pub fn backlog_stats(&self, listener: ConnectionId) -> Option<BacklogStats> {
+        self.backlogs.get(&listener).map(|backlog| backlog.stats())
+    }
+
+    /**
+     * 监听端口上还没有完成握手的半连接数
+     */
+    pub fn half_open(&self, listener: ConnectionId) -> usize {
+        self.backlogs.get(&listener).map_or(0, |backlog| backlog.len())
+    }
+
+    fn listener_for(&self, d_addr: u32, d_port: u16) -> Option<ConnectionId> {
+        [listener_id(d_addr, d_port), listener_id(0, d_port)].into_iter().find(|listener| self.listeners.contains_key(listener))
+    }
+
+    /**
+     * 主动打开, 返回需要发出的 SYN
+     */
+    pub fn connect(&mut self, id: ConnectionId, now_ms: u64) -> TcpSegment {
+        self.connect_with_options(id, SocketOptions::default(), now_ms)
+    }
+
+    /**
+     * 带套接字选项的主动打开, 选项对 SYN 已经生效
+     */
+    pub fn connect_with_options(&mut self, id: ConnectionId, options: SocketOptions, now_ms: u64) -> TcpSegment {
+        let mut conn = self.new_connection(id, now_ms);
+        conn.set_socket_options(options);
+        conn.set_md5_key(self.md5_keys.get(&id.d_ip).cloned());
+        conn.set_trace_sink(self.tracer.sink());
+        let syn = conn.connect(self.isn.generate(&id, now_ms * 1000), now_ms);
+        self.conns.insert(id, conn);
+        self.rebalance_memory();
+        self.refresh(id);
+        self.trace_tx([(id, &syn)]);
+        syn
+    }
+
+    /**
+     * TFO 主动打开 (RFC 7413): 先写入 data, 有对端的 cookie 时 SYN 捎带 data 的开头, 否则 SYN 请求 cookie,
+     * data 在握手完成后照常发出; SYN|ACK 中的 cookie 按对端地址缓存, 供下次连接使用
+     */
+    pub fn connect_fast_open(&mut self, id: ConnectionId, data: &[u8], now_ms: u64) -> Result<TcpSegment, ConnectionError> {
+        let mut conn = self.new_connection(id, now_ms);
+        conn.set_md5_key(self.md5_keys.get(&id.d_ip).cloned());
+        conn.write(data)?;
+        conn.set_trace_sink(self.tracer.sink());
+        let cookie = self.tfo_cookies.get(&id.d_ip).cloned().unwrap_or_default();
+        let syn = conn.connect_fast_open(self.isn.generate(&id, now_ms * 1000), Some(&cookie), now_ms);
+        self.conns.insert(id, conn);
+        self.rebalance_memory();
+        self.refresh(id);
+        self.trace_tx([(id, &syn)]);
+        Ok(syn)
+    }
+
+    /**
+     * IP 层交上来的报文段, 返回需要立即发出的应答
+     * 没有对应连接时, 监听端口上的 SYN 建立新连接, 其他报文回 RST(本机重启后对端的旧连接由此发现自己半开);
+     * 没有监听的端口上的 SYN 也回 RST
+     * 已经关闭的连接不妨碍同一四元组上的新 SYN; 打开 allow_time_wait_reuse 时, TimeWait 中的连接在
+     * 新 SYN 的时间戳前进时也让位给新连接
+     */
+    pub fn segment_received(&mut self, s_addr: u32, d_addr: u32, segment: &TcpSegment, now_ms: u64) -> Vec<TcpSegment> {
+        self.dispatch(s_addr, d_addr, segment, None, now_ms)
+    }
+
+    /**
+     * ip 为承载报文段的数据报的 TTL 与 DSCP, 建立新连接时记入 PeerSynInfo
+     */
+    fn dispatch(&mut self, s_addr: u32, d_addr: u32, segment: &TcpSegment, ip: Option<(u8, Dscp)>, now_ms: u64) -> Vec<TcpSegment> {
+        let id = ConnectionId::for_incoming(s_addr, d_addr, segment);
+        let (replies, verdict) = self.demux(s_addr, d_addr, id, segment, ip, now_ms);
+        self.tracer.emit(|sink| sink.on_segment_rx(&id, segment, verdict));
+        self.trace_tx(replies.iter().map(|reply| (id, reply)));
+        replies
+    }
+
+    /**
+     * 把报文段交给所属的连接、半连接或监听端口, 返回应答和处理结果
+     */
+    fn demux(&mut self, s_addr: u32, d_addr: u32, id: ConnectionId, segment: &TcpSegment, ip: Option<(u8, Dscp)>, now_ms: u64)
+        -> (Vec<TcpSegment>, SegmentVerdict) {
+        let now_ms = self.observe_clock(now_ms);
+        if segment.SYN() && !segment.ACK() && self.conns.get(&id).is_some_and(|conn| {
+            conn.state() == TcpState::Closed || (self.config.allow_time_wait_reuse && conn.accepts_reincarnation(segment))
+        }) {
+            self.remove(id);
+        }
+        if !self.conns.contains_key(&id) && !self.md5_authentic(s_addr, d_addr, segment) {
+            return (vec![], SegmentVerdict::Dropped);
+        }
+        if (!segment.SYN() || segment.ACK()) && !self.conns.contains_key(&id) {
+            if let Some(handled) = self.half_open_received(s_addr, d_addr, id, segment, now_ms) {
+                return handled;
+            }
+        }
+        if segment.SYN() && segment.ACK() && self.conns.get(&id).is_some_and(|conn| conn.state() == TcpState::SynSent) {
+            if let Some(cookie) = fast_open_cookie(segment).filter(|cookie| !cookie.is_empty()) {
+                self.tfo_cookies.insert(s_addr, cookie);
+            }
+        }
+        if let Some(conn) = self.conns.get_mut(&id) {
+            let verdict = if conn.segment_received(segment, now_ms) { conn.last_verdict() } else { SegmentVerdict::Dropped };
+            let reply = conn.take_reply();
+            self.acked.insert(id);
+            self.refresh(id);
+            return (reply.into_iter().collect(), verdict);
+        }
+        if !segment.SYN() || segment.ACK() {
+            return (self.reset_for(s_addr, d_addr, segment).into_iter().collect(), SegmentVerdict::Dropped);
+        }
+        if !self.accepting {
+            return (vec![], SegmentVerdict::Dropped);
+        }
+        let Some(listener) = self.listener_for(d_addr, segment.d_port) else {
+            // 端口上没有监听: 拒绝连接
+            return (self.reset_for(s_addr, d_addr, segment).into_iter().collect(), SegmentVerdict::Dropped);
+        };
+        let backlog = self.backlogs.get_mut(&listener).unwrap();
+        if self.listeners[&listener].len() >= self.config.syn_backlog {
+            backlog.accept_queue_full();
+            return (vec![], SegmentVerdict::Dropped);
+        }
+        let options = segment.parsed_options().unwrap_or_default();
+        let peer_mss = options.iter().find_map(|option| match option {
+            TcpOption::Mss(mss) => Some(*mss),
+            _ => None,
+        }).unwrap_or(DEFAULT_PEER_MSS);
+        let server_isn = self.isn.generate(&id, now_ms * 1000);
+        let half_open = || HalfOpenSyn {
+            syn: segment.clone(),
+            ip,
+            peer: PeerSynInfo {
+                options, isn: segment.seq, window: segment.win_size, ttl: ip.map(|(ttl, _)| ttl), dscp: ip.map(|(_, dscp)| dscp),
+            },
+            syn_ack: None,
+            negotiated: None,
+            early: vec![],
+            early_bytes: 0,
+        };
+        match backlog.on_syn(id, segment.seq, peer_mss, server_isn, now_ms, half_open) {
+            SynAction::SynAck { isn, mss, cookie: true } => {
+                // cookie 只编码了 MSS, 应答里也只承诺 MSS, 与握手完成时重建的连接一致
+                let mut conn = self.new_connection(id, now_ms);
+                conn.syn_received(&cookie_syn(id, segment.seq, mss, segment.win_size), &TfoDecision::Normal, now_ms);
+                conn.set_md5_key(self.md5_keys.get(&id.d_ip).cloned());
+                (vec![conn.syn_ack(isn)], SegmentVerdict::Accepted)
+            }
+            SynAction::SynAck { isn, .. } => {
+                if let Some(syn_ack) = backlog.half_open(&id).and_then(|half_open| half_open.syn_ack.clone()) {
+                    return (vec![syn_ack], SegmentVerdict::Duplicate);
+                }
+                let syn = backlog.half_open(&id).map_or_else(|| segment.clone(), |half_open| half_open.syn.clone());
+                let tfo = self.fast_open.get_mut(&listener).map_or(TfoDecision::Normal, |tfo| tfo.on_syn(s_addr, &syn));
+                let mut conn = self.new_connection(id, now_ms);
+                conn.syn_received(&syn, &tfo, now_ms);
+                conn.set_md5_key(self.md5_keys.get(&id.d_ip).cloned());
+                let syn_ack = conn.syn_ack(isn);
+                // 半连接没有 TcpConnection, 进入 SynReceived 由表报告
+                self.tracer.emit(|sink| sink.on_state_change(&id, TcpState::Listen, TcpState::SynReceived));
+                if tfo == TfoDecision::AcceptData {
+                    self.accept_fast_open(listener, conn, ip);
+                    return (vec![syn_ack], SegmentVerdict::Accepted);
+                }
+                if let Some(half_open) = self.backlogs.get_mut(&listener).unwrap().half_open_mut(&id) {
+                    half_open.syn_ack = Some(syn_ack.clone());
+                    half_open.negotiated = conn.negotiated_options();
+                }
+                (vec![syn_ack], SegmentVerdict::Accepted)
+            }
+            SynAction::Drop => (vec![], SegmentVerdict::Dropped),
+            SynAction::Reset => (self.reset_for(s_addr, d_addr, segment).into_iter().collect(), SegmentVerdict::Dropped),
+        }
+    }
+
+    /**
+     * 监听端口上不属于任何连接的非 SYN 报文段, 返回 None 时照常处理(连接刚建立或者不是半连接的段)
+     * 握手 ACK 经半连接队列或 cookie 校验通过后才分配 TcpConnection; 校验失败回 RST, 半连接保留
+     */
+    fn half_open_received(&mut self, s_addr: u32, d_addr: u32, id: ConnectionId, segment: &TcpSegment, now_ms: u64)
+        -> Option<(Vec<TcpSegment>, SegmentVerdict)> {
+        let listener = self.listener_for(d_addr, segment.d_port)?;
+        let queued = self.listeners[&listener].len();
+        let backlog = self.backlogs.get_mut(&listener)?;
+        if segment.RST() {
+            let verdict = if backlog.forget(&id) { SegmentVerdict::Accepted } else { SegmentVerdict::Dropped };
+            return Some((vec![], verdict));
+        }
+        if !segment.ACK() {
+            // 先于握手 ACK 到达的数据 (例如 ACK 丢失后的重传), 建立连接时按序交给它
+            let half_open = backlog.half_open_mut(&id)?;
+            let len = segment.data.len();
+            if half_open.early_bytes + len <= self.config.recv_buffer {
+                half_open.early.push(segment.clone());
+                half_open.early_bytes += len;
+            }
+            return Some((vec![], SegmentVerdict::Accepted));
+        }
+        if !self.accepting {
+            return Some((vec![], SegmentVerdict::Dropped));
+        }
+        if queued >= self.config.syn_backlog {
+            backlog.accept_queue_full();
+            return Some((vec![], SegmentVerdict::Dropped));
+        }
+        let Some(accepted) = backlog.on_ack(id, segment.seq, segment.ack, now_ms) else {
+            return Some((self.reset_for(s_addr, d_addr, segment).into_iter().collect(), SegmentVerdict::Dropped));
+        };
+        self.establish(listener, accepted, now_ms);
+        None
+    }
+
+    /**
+     * 握手完成: 按保存的 SYN 重建 SynReceived 的连接并放入 accept 队列, 之后由调用方把 ACK 交给它
+     */
+    fn establish(&mut self, listener: ConnectionId, accepted: Accepted<HalfOpenSyn>, now_ms: u64) {
+        let id = accepted.id;
+        let (syn, ip, early) = match accepted.state {
+            Some(half_open) => (half_open.syn, half_open.ip, half_open.early),
+            None => (cookie_syn(id, accepted.client_isn, accepted.mss, 0), None, vec![]),
+        };
+        let mut conn = self.new_connection(id, now_ms);
+        conn.syn_received(&syn, &TfoDecision::Normal, now_ms);
+        if let Some((ttl, dscp)) = ip {
+            conn.set_peer_syn_ip(ttl, dscp);
+        }
+        // SYN 在表中校验过; cookie 重建的 SYN 没有签名, 密钥在它之后设置
+        conn.set_md5_key(self.md5_keys.get(&id.d_ip).cloned());
+        conn.syn_ack(accepted.server_isn);
+        for segment in &early {
+            conn.segment_received(segment, now_ms);
+        }
+        conn.take_reply();
+        conn.set_trace_sink(self.tracer.sink());
+        self.conns.insert(id, conn);
+        self.rebalance_memory();
+        self.listeners.get_mut(&listener).unwrap().push_back(id);
+    }
+
+    /**
+     * cookie 有效的 TFO SYN: 不等握手 ACK, 连接以 SynReceived 直接进入 accept 队列,
+     * 应用可以立即读到 SYN 捎带的数据并回复; 握手 ACK 之后交给连接本身处理
+     */
+    fn accept_fast_open(&mut self, listener: ConnectionId, mut conn: TcpConnection, ip: Option<(u8, Dscp)>) {
+        let id = conn.id();
+        self.backlogs.get_mut(&listener).unwrap().forget(&id);
+        if let Some((ttl, dscp)) = ip {
+            conn.set_peer_syn_ip(ttl, dscp);
+        }
+        conn.take_reply();
+        conn.set_trace_sink(self.tracer.sink());
+        self.conns.insert(id, conn);
+        self.rebalance_memory();
+        self.listeners.get_mut(&listener).unwrap().push_back(id);
+        self.refresh(id);
+    }
+
+    /**
+     * 同 segment_received, 额外用帧的接收元数据记录该连接的栈内处理时间和链路层 RTT
+     * now_ms 应在处理前刚从同一时钟读出
+     */
+    pub fn segment_received_with_meta(&mut self, s_addr: u32, d_addr: u32, segment: &TcpSegment, meta: &PacketMeta,
+        now_ms: u64) -> Vec<TcpSegment> {
+        let replies = self.segment_received(s_addr, d_addr, segment, now_ms);
+        let id = ConnectionId::for_incoming(s_addr, d_addr, segment);
+        if self.tracked(id) {
+            self.latency.entry(id).or_default().on_receive(meta, segment, now_ms);
+        }
+        replies
+    }
+
+    /**
+     * 连接的报文段所在的帧在 tx_ms 交给了设备
+     */
+    pub fn frame_transmitted(&mut self, id: ConnectionId, segment: &TcpSegment, tx_ms: u64) {
+        if self.tracked(id) {
+            self.latency.entry(id).or_default().on_transmit(segment, tx_ms);
+        }
+    }
+
+    /**
+     * 已经建立的连接或监听端口上的半连接
+     */
+    fn tracked(&self, id: ConnectionId) -> bool {
+        self.conns.contains_key(&id) || self.half_open_syn(id).is_some()
+    }
+
+    /**
+     * 连接的延迟直方图, 从未经带时间戳的接口收发过时为 None
+     */
+    pub fn latency(&self, id: ConnectionId) -> Option<&ConnectionLatency> {
+        self.latency.get(&id)
+    }
+
+    /**
+     * 回应不属于任何连接的报文段的 RST (RFC 793 3.4), 报文段本身是 RST 时不回应
+     * 带 ACK 时以它的确认号为序号, 否则确认它占用的序号
+     */
+    fn reset_for(&self, s_addr: u32, d_addr: u32, segment: &TcpSegment) -> Option<TcpSegment> {
+        if segment.RST() {
+            return None;
+        }
+        let mut rst = if segment.ACK() {
+            TcpSegment::new(segment.d_port, segment.s_port, segment.ack, 0, 5, 0, TcpFlags::RST, 0, 0, vec![], vec![])
+        } else {
+            let len = segment.data.len() as u32 + segment.SYN() as u32 + segment.FIN() as u32;
+            TcpSegment::new(segment.d_port, segment.s_port, 0, segment.seq.wrapping_add(len), 5, 0, TcpFlags::RST | TcpFlags::ACK,
+                0, 0, vec![], vec![])
+        };
+        if self.checksum_policy.generate() {
+            rst.generate_checksum(d_addr, s_addr);
+        } else {
+            rst.clear_checksum();
+        }
+        Some(rst)
+    }
+
+    /**
+     * 替换 ISN 生成器, 用于需要复现序号的测试
+     */
+    pub fn set_isn_generator(&mut self, isn: IsnGenerator) {
+        self.isn = isn;
+    }
+
+    /**
+     * 模拟本机重启: 所有连接和监听端口一起消失, 不通知任何对端, 返回丢失的连接数
+     * ISN 密钥也重新生成
+     * 对端之后发来的报文得到 RST, 或在它们发起新连接时经 challenge ACK 发现旧连接已经不存在
+     */
+    pub fn reset_all(&mut self) -> usize {
+        let lost = self.conns.len();
+        self.conns.clear();
+        self.listeners.clear();
+        self.backlogs.clear();
+        self.ready.clear();
+        self.reported.clear();
+        self.changed.clear();
+        self.active.clear();
+        self.scheduled.clear();
+        self.sent_last_poll.clear();
+        self.acked.clear();
+        self.resets.clear();
+        self.latency.clear();
+        self.timers = TimerQueue::new();
+        self.rto_timers = TimerQueue::new();
+        self.accepting = true;
+        self.shutdown_deadline = None;
+        self.isn = IsnGenerator::new();
+        lost
+    }
+
+    /**
+     * 取出一个完成握手的连接
+     */
+    pub fn accept(&mut self, listener: ConnectionId) -> Option<ConnectionId> {
+        let queue = self.listeners.get_mut(&listener)?;
+        let position = queue.iter().position(|id| self.conns.get(id).is_some_and(handshake_done))?;
+        let id = queue.remove(position);
+        self.refresh(listener);
+        id
+    }
+
+    /**
+     * 同 accept, 同时返回对端 SYN 的内容
+     */
+    pub fn accept_with_syn(&mut self, listener: ConnectionId) -> Option<(ConnectionId, PeerSynInfo)> {
+        let id = self.accept(listener)?;
+        let info = self.peer_syn(id)?.clone();
+        Some((id, info))
+    }
+
+    /**
+     * 被动打开的连接收到的第一个 SYN; 握手完成之前也可以查询
+     */
+    pub fn peer_syn(&self, id: ConnectionId) -> Option<&PeerSynInfo> {
+        match self.conns.get(&id) {
+            Some(conn) => conn.peer_syn(),
+            None => self.half_open_syn(id).map(|half_open| &half_open.peer),
+        }
+    }
+
+    fn half_open_syn(&self, id: ConnectionId) -> Option<&HalfOpenSyn> {
+        [listener_id(id.s_ip, id.s_port), listener_id(0, id.s_port)]
+            .iter()
+            .find_map(|listener| self.backlogs.get(listener)?.half_open(&id))
+    }
+
+    /**
+     * 未读的数据读完之后, 因空闲超时关闭的连接返回 IdleTimeout
+     */
+    pub fn read(&mut self, id: ConnectionId, max: usize) -> Result<Vec<u8>, ConnectionError> {
+        let conn = self.conns.get_mut(&id).ok_or(ConnectionError::NotConnected)?;
+        let data = conn.read(max);
+        let idle = data.is_empty() && max > 0 && conn.error() == Some(&ConnectionError::IdleTimeout);
+        self.refresh(id);
+        if idle {
+            return Err(ConnectionError::IdleTimeout);
+        }
+        Ok(data)
+    }
+
+    pub fn write(&mut self, id: ConnectionId, data: &[u8]) -> Result<usize, ConnectionError> {
+        self.write_vectored(id, &[data])
+    }
+
+    /**
+     * 写入紧急数据, 见 TcpConnection::write_urgent
+     */
+    pub fn write_urgent(&mut self, id: ConnectionId, data: &[u8]) -> Result<usize, ConnectionError> {
+        let conn = self.conns.get_mut(&id).ok_or(ConnectionError::NotConnected)?;
+        let n = conn.write_urgent(data)?;
+        self.schedule(id);
+        self.refresh(id);
+        Ok(n)
+    }
+
+    /**
+     * 只读到对端紧急数据的结尾为止; urgent_pending 给出还差多少字节
+     */
+    pub fn read_urgent(&mut self, id: ConnectionId, max: usize) -> Result<Vec<u8>, ConnectionError> {
+        let data = self.conns.get_mut(&id).ok_or(ConnectionError::NotConnected)?.read_urgent(max);
+        self.refresh(id);
+        Ok(data)
+    }
+
+    pub fn urgent_pending(&self, id: ConnectionId) -> Option<usize> {
+        self.conns.get(&id)?.urgent_pending()
+    }
+
+    /**
+     * writev: 依次写入多个缓冲区, 返回合计接受的字节数
+     */
+    pub fn write_vectored(&mut self, id: ConnectionId, bufs: &[&[u8]]) -> Result<usize, ConnectionError> {
+        let conn = self.conns.get_mut(&id).ok_or(ConnectionError::NotConnected)?;
+        let n = conn.write_vectored(bufs)?;
+        self.schedule(id);
+        self.refresh(id);
+        Ok(n)
+    }
+
+    /**
+     * 见 TcpConnection::send_from, 发送缓冲区有空间时再次调用继续读
+     */
+    pub fn send_from(&mut self, id: ConnectionId, reader: &mut dyn io::Read, max_bytes: Option<u64>) -> Result<u64, ConnectionError> {
+        let conn = self.conns.get_mut(&id).ok_or(ConnectionError::NotConnected)?;
+        let n = conn.send_from(reader, max_bytes)?;
+        self.schedule(id);
+        self.refresh(id);
+        Ok(n)
+    }
+
+    /**
+     * 见 TcpConnection::send_from_fn
+     */
+    pub fn send_from_fn(&mut self, id: ConnectionId, fill: &mut dyn FnMut(&mut [u8]) -> usize, max_bytes: Option<u64>) -> Result<u64, ConnectionError> {
+        let conn = self.conns.get_mut(&id).ok_or(ConnectionError::NotConnected)?;
+        let n = conn.send_from_fn(fill, max_bytes)?;
+        self.schedule(id);
+        self.refresh(id);
+        Ok(n)
+    }
+
+    /**
+     * 见 TcpConnection::set_idle_timeout, 到期由 tick 处理
+     */
+    pub fn set_idle_timeout(&mut self, id: ConnectionId, timeout: Option<Duration>, action: IdleAction) -> Result<(), ConnectionError> {
+        let conn = self.conns.get_mut(&id).ok_or(ConnectionError::NotConnected)?;
+        conn.advance_clock(self.clock_ms);
+        conn.set_idle_timeout(timeout);
+        conn.set_idle_action(action);
+        self.rearm(id);
+        Ok(())
+    }
+
+    /**
+     * 修改连接的套接字选项, 之后发出的报文立即生效
+     */
+    pub fn set_socket_options(&mut self, id: ConnectionId, options: SocketOptions) -> Result<(), ConnectionError> {
+        let conn = self.conns.get_mut(&id).ok_or(ConnectionError::NotConnected)?;
+        conn.set_socket_options(options);
+        self.refresh(id); // 水位变了, 就绪状态可能随之变化
+        Ok(())
+    }
+
+    pub fn socket_options(&self, id: ConnectionId) -> Option<SocketOptions> {
+        self.conns.get(&id).map(|conn| *conn.socket_options())
+    }
+
+    /**
+     * 把连接发出的报文段封装成 IP 数据报交给 IP 层, TTL 与 TOS 按连接的选项设置
+     * 监听端口对 SYN 的应答还没有连接, 按默认选项封装
+     */
+    pub fn datagram(&mut self, id: ConnectionId, segment: &TcpSegment, config: &Ipv4Config) -> Option<Ipv4Datagram> {
+        if !self.conns.contains_key(&id) {
+            self.listener_for(id.s_ip, id.s_port)?;
+        }
+        self.ip_id = self.ip_id.wrapping_add(1);
+        Some(match self.conns.get(&id) {
+            Some(conn) => conn.datagram(segment, self.ip_id, config),
+            None => SocketOptions::default().datagram(id.s_ip, id.d_ip, self.ip_id, segment, config),
+        })
+    }
+
+    /**
+     * 封装并按出口 MTU 切分: 连接按 set_path_mtu 缩小段长之后一般只有一个数据报
+     * 只有段长已经到了 MIN_SEND_MSS 仍然放不下时才清除 DF、由 IP 层分片
+     */
+    pub fn datagrams(&mut self, id: ConnectionId, segment: &TcpSegment, config: &Ipv4Config, mtu: u16) -> Option<Vec<Ipv4Datagram>> {
+        let mut datagram = self.datagram(id, segment, config)?;
+        if datagram.wire_size() <= mtu as usize {
+            return Some(vec![datagram]);
+        }
+        datagram.set_dont_fragment(false);
+        datagram.fragment(mtu).ok()
+    }
+
+    /**
+     * 出口 MTU 变化后调用, 连接之后发出的段不超过它
+     * 比已知的路径 MTU 小时同时记入 PMTU 缓存, 之后到同一目的地址的新连接直接按它分段
+     */
+    pub fn set_path_mtu(&mut self, id: ConnectionId, mtu: u16) -> Result<(), ConnectionError> {
+        self.conns.get_mut(&id).ok_or(ConnectionError::NotConnected)?.set_path_mtu(mtu);
+        self.pmtu.update(id.d_ip, mtu, self.clock_ms);
+        self.rearm(id);
+        Ok(())
+    }
+
+    /**
+     * 到 d_addr 的路径 MTU: 发现过的值, 否则为按本端 MSS 推算的链路 MTU
+     */
+    pub fn path_mtu(&self, d_addr: u32) -> u16 {
+        self.pmtu.mtu_for(d_addr, self.clock_ms)
+    }
+
+    /**
+     * 路径 MTU 变化后让到 d_addr 的所有连接按它分段
+     */
+    fn apply_path_mtu(&mut self, d_addr: u32, mtu: u16) {
+        let ids: Vec<ConnectionId> = self.conns.keys().filter(|id| id.d_ip == d_addr).copied().collect();
+        for id in ids {
+            self.conns.get_mut(&id).unwrap().set_path_mtu(mtu);
+            self.refresh(id);
+        }
+    }
+
+    /**
+     * IP 层交上来的 TCP 数据报; 分片必须先经过 Ipv4Reassembler, 这里直接丢弃, TCP 不会看到不完整的段
+     */
+    pub fn datagram_received(&mut self, datagram: &Ipv4Datagram, now_ms: u64) -> Vec<TcpSegment> {
+        if datagram.is_fragment() || datagram.protocol() != PROTOCOL_TCP {
+            return vec![];
+        }
+        let Ok(segment) = TcpSegment::try_deserialize(datagram.payload()) else {
+            return vec![];
+        };
+        self.dispatch(datagram.s_addr(), datagram.d_addr(), &segment, Some((datagram.ttl(), datagram.dscp())), now_ms)
+    }
+
+    /**
+     * IP 层交上来的 ICMP 目的不可达: 按带回的原数据报首部和 TCP 端口找到连接, 交给 TcpConnection::icmp_unreachable
+     * 需要分片 (code 4) 经 parse_frag_needed 记入 PMTU 缓存, 调小了路径 MTU 时到同一目的地址的连接都按新值分段
+     * 带回的序号不是本端发出的视为伪造; 返回接受了通知的连接
+     */
+    pub fn icmp_received(&mut self, datagram: &Ipv4Datagram, now_ms: u64) -> Option<ConnectionId> {
+        if datagram.is_fragment() || datagram.protocol() != PROTOCOL_ICMP {
+            return None;
+        }
+        let icmp = IcmpV4::try_deserialize(datagram.payload()).ok()?;
+        if icmp.icmp_type() != 3 {
+            return None;
+        }
+        let original = icmp.embedded_datagram()?;
+        let hdr_len = (original[0] & 0x0f) as usize * 4;
+        if original[9] != PROTOCOL_TCP || hdr_len < 20 || original.len() < hdr_len + 8 {
+            return None;
+        }
+        let tcp = &original[hdr_len..];
+        let id = ConnectionId {
+            s_ip: u32::from_be_bytes([original[12], original[13], original[14], original[15]]),
+            s_port: u16::from_be_bytes([tcp[0], tcp[1]]),
+            d_ip: u32::from_be_bytes([original[16], original[17], original[18], original[19]]),
+            d_port: u16::from_be_bytes([tcp[2], tcp[3]]),
+        };
+        let seq = u32::from_be_bytes([tcp[4], tcp[5], tcp[6], tcp[7]]);
+        if icmp.code() == 4 {
+            if !self.conns.get(&id)?.icmp_seq_plausible(seq) {
+                return None;
+            }
+            let (d_addr, mtu) = pmtu::parse_frag_needed(datagram.payload())?;
+            if !self.pmtu.update(d_addr, mtu, now_ms) {
+                return None;
+            }
+            self.apply_path_mtu(d_addr, mtu);
+            return Some(id);
+        }
+        let advice = IcmpAdvice { code: icmp.code(), when_ms: now_ms };
+        if !self.conns.get_mut(&id)?.icmp_unreachable(advice, seq) {
+            return None;
+        }
+        self.refresh(id);
+        Some(id)
+    }
+
+    /**
+     * 最近一次被接受的 ICMP 目的不可达, 有新数据被确认后清除
+     */
+    pub fn icmp_advice(&self, id: ConnectionId) -> Option<IcmpAdvice> {
+        self.conns.get(&id)?.icmp_advice()
+    }
+
+    pub fn snapshot(&self, id: ConnectionId) -> Option<ConnectionSnapshot> {
+        self.conns.get(&id).map(|conn| conn.snapshot())
+    }
+
+    /**
+     * 连接按其丢包检测方式应当立即重传
+     */
+    pub fn fast_retransmit_due(&self, id: ConnectionId) -> bool {
+        self.conns.get(&id).is_some_and(|conn| conn.fast_retransmit_due())
+    }
+
+    /**
+     * 连接下一次可能判定丢包的时刻(只有 Rack 模式有)
+     */
+    pub fn loss_timer_ms(&self, id: ConnectionId) -> Option<u64> {
+        self.conns.get(&id)?.loss_timer_ms()
+    }
+
+    /**
+     * RTO 到期的连接, 按 ConnectionId 排序; 调用方对每个连接调用 retransmission, 之后连接的重传计时重新开始
+     */
+    pub fn retransmissions_due(&mut self, now_ms: u64) -> Vec<ConnectionId> {
+        let now_ms = self.observe_clock(now_ms);
+        let mut ids = self.rto_timers.expired(now_ms);
+        ids.sort();
+        ids
+    }
+
+    /**
+     * 连接的重传时刻, 没有在途序号时为 None
+     */
+    pub fn rto_deadline_ms(&self, id: ConnectionId) -> Option<u64> {
+        self.conns.get(&id)?.rto_deadline_ms()
+    }
+
+    /**
+     * 重传连接最早的未确认段, 半连接重传第一次发出的 SYN|ACK
+     */
+    pub fn retransmission(&mut self, id: ConnectionId) -> Option<TcpSegment> {
+        let segment = match self.conns.get_mut(&id) {
+            Some(conn) => {
+                conn.advance_clock(self.clock_ms);
+                let segment = conn.retransmission();
+                self.rearm(id);
+                segment?
+            }
+            None => self.half_open_syn(id)?.syn_ack.clone()?,
+        };
+        self.retransmissions += 1;
+        self.trace_tx([(id, &segment)]);
+        Some(segment)
+    }
+
+    /**
+     * 所有连接累计的重传段数
+     */
+    pub fn retransmissions(&self) -> u64 {
+        self.retransmissions
+    }
+
+    /**
+     * 各状态的连接数, 按 TcpState::ALL 的顺序, 不含监听端口; 半连接计入 SynReceived
+     */
+    pub fn state_counts(&self) -> [(TcpState, usize); 11] {
+        let half_open: usize = self.backlogs.values().map(|backlog| backlog.len()).sum();
+        TcpState::ALL.map(|state| {
+            let conns = self.conns.values().filter(|conn| conn.state() == state).count();
+            (state, if state == TcpState::SynReceived { conns + half_open } else { conns })
+        })
+    }
+
+    /**
+     * IP 层报告 d_ip 不可达, 返回因此失败的连接(还在握手中的主动打开)
+     */
+    pub fn host_unreachable(&mut self, d_ip: u32, now_ms: u64) -> Vec<ConnectionId> {
+        let mut ids: Vec<ConnectionId> = self.conns.keys().filter(|id| id.d_ip == d_ip).copied().collect();
+        ids.sort();
+        ids.retain(|id| self.conns.get_mut(id).unwrap().host_unreachable(now_ms));
+        for id in &ids {
+            self.refresh(*id);
+        }
+        ids
+    }
+
+    /**
+     * 链路断开超过配置的时间, via 判断哪些对端地址经过这条链路; 返回因此失败的连接
+     */
+    pub fn link_down(&mut self, via: impl Fn(u32) -> bool, now_ms: u64) -> Vec<ConnectionId> {
+        let mut ids: Vec<ConnectionId> = self.conns.keys().filter(|id| id.d_ip != 0 && via(id.d_ip)).copied().collect();
+        ids.sort();
+        ids.retain(|id| self.conns.get_mut(id).unwrap().link_down(now_ms));
+        for id in &ids {
+            self.refresh(*id);
+        }
+        ids
+    }
+
+    /**
+     * 连接上的错误, 没有出错时为 None
+     */
+    pub fn error(&self, id: ConnectionId) -> Option<ConnectionError> {
+        self.conns.get(&id)?.error().cloned()
+    }
+
+    /**
+     * 关闭连接的发送方向, FIN 在已写入的数据之后由 poll_transmit 发出
+     */
+    pub fn close(&mut self, id: ConnectionId, now_ms: u64) -> Result<(), ConnectionError> {
+        let conn = self.conns.get_mut(&id).ok_or(ConnectionError::NotConnected)?;
+        conn.close(now_ms);
+        self.schedule(id);
+        self.refresh(id);
+        Ok(())
+    }
+
+    /**
+     * 已写入的数据不再被 Nagle 扣留, 由下一次 poll_transmit 在窗口允许的范围内发出
+     */
+    pub fn flush(&mut self, id: ConnectionId) -> Result<(), ConnectionError> {
+        let conn = self.conns.get_mut(&id).ok_or(ConnectionError::NotConnected)?;
+        conn.push();
+        self.schedule(id);
+        Ok(())
+    }
+
+    /**
+     * 放弃连接, 返回放弃时还没有被确认的字节数; 需要通知对端时 RST 排进发送队列
+     */
+    pub fn abort(&mut self, id: ConnectionId, now_ms: u64) -> Result<usize, ConnectionError> {
+        let conn = self.conns.get_mut(&id).ok_or(ConnectionError::NotConnected)?;
+        let unsent = conn.unsent_bytes();
+        if let Some(rst) = conn.abort(now_ms) {
+            self.resets.push_back((id, rst));
+        }
+        if self.scheduled.remove(&id) {
+            self.active.retain(|active| *active != id);
+        }
+        self.refresh(id);
+        Ok(unsent)
+    }
+
+    /**
+     * 有序关闭整张表: 监听端口不再接受新连接, 所有连接 close
+     * 之后照常驱动 poll_transmit / segment_received / tick, 并调用 poll_shutdown 直到得到报告
+     */
+    pub fn shutdown(&mut self, now_ms: u64, deadline_ms: u64) {
+        self.accepting = false;
+        self.shutdown_deadline = Some(deadline_ms);
+        let ids: Vec<ConnectionId> = self.conns.keys().copied().collect();
+        for id in ids {
+            let _ = self.close(id, now_ms);
+        }
+    }
+
+    /**
+     * 所有连接都进入 Closed 或 TimeWait 时返回报告
+     * 截止时间到了还没关完的连接被放弃, 它们的 RST 由随后的 poll_transmit 发出
+     * 没有调用 shutdown、或者报告已经给出过时返回 None
+     */
+    pub fn poll_shutdown(&mut self, now_ms: u64) -> Option<ShutdownReport> {
+        let deadline = self.shutdown_deadline?;
+        let finished = |conn: &TcpConnection| matches!(conn.state(), TcpState::Closed | TcpState::TimeWait);
+        if now_ms < deadline && !self.conns.values().all(finished) {
+            return None;
+        }
+        self.shutdown_deadline = None;
+        let mut ids: Vec<ConnectionId> = self.conns.keys().copied().collect();
+        ids.sort();
+        let mut report = ShutdownReport::default();
+        for id in ids {
+            let conn = &self.conns[&id];
+            let entry = if finished(conn) && conn.error().is_none() {
+                ShutdownEntry { id, outcome: ShutdownOutcome::Graceful, unsent: 0 }
+            } else if finished(conn) {
+                ShutdownEntry { id, outcome: ShutdownOutcome::Aborted, unsent: conn.unsent_bytes() }
+            } else {
+                let unsent = self.abort(id, now_ms).unwrap_or(0);
+                ShutdownEntry { id, outcome: ShutdownOutcome::Aborted, unsent }
+            };
+            report.entries.push(entry);
+        }
+        Some(report)
+    }
+
+    /**
+     * 轮转地从有待发数据的连接中各取一个段, 直到取满 budget 或所有连接都被窗口挡住
+     * 每个连接每轮的配额为一个段; 没有发完的连接排到队尾, 下次 poll 从上次停下的位置继续
+     */
+    pub fn poll_transmit(&mut self, budget: usize) -> Vec<(ConnectionId, TcpSegment)> {
+        self.sent_last_poll.clear();
+        // 发送缓冲区已满、数据都在途的连接不在轮转队列里, 它的 ACK 也要在本轮交给发送端
+        for id in std::mem::take(&mut self.acked) {
+            let Some(conn) = self.conns.get_mut(&id) else { continue };
+            conn.advance_clock(self.clock_ms);
+            conn.flush_acks();
+            self.schedule(id);
+            self.refresh(id);
+        }
+        let mut out = vec![];
+        while out.len() < budget {
+            let Some(reset) = self.resets.pop_front() else { break };
+            out.push(reset);
+        }
+        let mut blocked = 0; // 连续没有发出段的连接数, 转满一圈即停止
+        while out.len() < budget && blocked < self.active.len() {
+            let id = self.active.pop_front().unwrap();
+            self.scheduled.remove(&id);
+            let Some(conn) = self.conns.get_mut(&id) else {
+                continue;
+            };
+            conn.advance_clock(self.clock_ms);
+            conn.flush_acks();
+            match conn.next_segment() {
+                Some(segment) => {
+                    out.push((id, segment));
+                    *self.sent_last_poll.entry(id).or_insert(0) += 1;
+                    blocked = 0;
+                }
+                None => blocked += 1,
+            }
+            self.schedule(id);
+            self.refresh(id);
+        }
+        for conn in self.conns.values_mut() {
+            conn.notify_writable();
+        }
+        self.trace_tx(out.iter().map(|(id, segment)| (*id, segment)));
+        out
+    }
+
+    /**
+     * 连接每次由不可写变为可写(包括离开发送高水位)时, 在之后的 poll_transmit 中调用 callback
+     */
+    pub fn on_writable(&mut self, id: ConnectionId, callback: Box<dyn FnMut()>) -> Result<(), ConnectionError> {
+        self.conns.get_mut(&id).ok_or(ConnectionError::NotConnected)?.on_writable(callback);
+        Ok(())
+    }
+
+    /**
+     * 上一次 poll_transmit 中该连接发出的段数
+     */
+    pub fn segments_sent_last_poll(&self, id: ConnectionId) -> u32 {
+        self.sent_last_poll.get(&id).copied().unwrap_or(0)
+    }
+
+    /**
+     * 先处理本轮合并的 ACK, 再发出窗口允许的数据
+     */
+    pub fn poll_send(&mut self, id: ConnectionId) -> Vec<TcpSegment> {
+        let Some(conn) = self.conns.get_mut(&id) else {
+            return vec![];
+        };
+        conn.advance_clock(self.clock_ms);
+        conn.flush_acks();
+        let segments = conn.poll_send();
+        self.refresh(id);
+        self.trace_tx(segments.iter().map(|segment| (id, segment)));
+        segments
+    }
+
+    /**
+     * 定时器到期的连接的定时处理
+     */
+    pub fn tick(&mut self, now_ms: u64) -> Vec<TcpSegment> {
+        self.tick_connections(now_ms).into_iter().map(|(_, segment)| segment).collect()
+    }
+
+    /**
+     * 一次驱动: 定时处理之后取出所有待发的段, 带上所属的连接
+     * 同一时刻重复调用是幂等的: 每个定时器每个截止时间只到期一次, 已经发出的段不会再出现
+     */
+    pub fn poll(&mut self, now_ms: u64) -> Vec<(ConnectionId, TcpSegment)> {
+        let mut out = self.tick_connections(now_ms);
+        out.extend(self.poll_transmit(usize::MAX));
+        out
+    }
+
+    fn tick_connections(&mut self, now_ms: u64) -> Vec<(ConnectionId, TcpSegment)> {
+        let now_ms = self.observe_clock(now_ms);
+        for d_addr in self.pmtu.tick(now_ms) {
+            let mtu = self.pmtu.link_mtu(); // 过期之后重新按链路 MTU 试探
+            self.apply_path_mtu(d_addr, mtu);
+        }
+        let half_open: usize = self.backlogs.values().map(|backlog| backlog.len()).sum();
+        for backlog in self.backlogs.values_mut() {
+            backlog.tick(now_ms);
+        }
+        if self.backlogs.values().map(|backlog| backlog.len()).sum::<usize>() < half_open {
+            // 超时的半连接不会再有 remove, 它们的延迟记录在这里清掉
+            let latency = std::mem::take(&mut self.latency);
+            self.latency = latency.into_iter().filter(|(id, _)| self.tracked(*id)).collect();
+        }
+        let mut ids = self.timers.expired(now_ms);
+        ids.sort();
+        let mut out = vec![];
+        for id in ids {
+            let Some(conn) = self.conns.get_mut(&id) else { continue };
+            out.extend(conn.tick(now_ms).map(|segment| (id, segment)));
+            if let Some(stall) = conn.take_stall() {
+                self.stalls.push_back(stall);
+                self.stalls_detected += 1;
+            }
+            self.schedule(id); // 空闲超时关闭后待发的 FIN
+            self.refresh(id);
+        }
+        self.trace_tx(out.iter().map(|(id, segment)| (*id, segment)));
+        out
+    }
+
+    /**
+     * 最早需要再次调用 poll 或 retransmissions_due 的时刻, 没有连接定时器时为 None
+     * 半连接超时和路径 MTU 过期不在其中, 它们在每次 tick 时顺带检查
+     */
+    pub fn next_timer_ms(&mut self) -> Option<u64> {
+        [self.timers.next_deadline(), self.rto_timers.next_deadline()].into_iter().flatten().min()
+    }
+
+    /**
+     * 取走 tick 中收集的卡死诊断
+     */
+    pub fn take_stalls(&mut self) -> Vec<StallDiagnosis> {
+        self.stalls.drain(..).collect()
+    }
+
+    /**
+     * 把收集的卡死诊断交给追踪钩子, 返回报告的个数
+     */
+    pub fn report_stalls(&mut self, sink: &mut dyn TraceSink) -> usize {
+        let stalls = self.take_stalls();
+        for stall in &stalls {
+            sink.on_stall(stall);
+        }
+        stalls.len()
+    }
+
+    /**
+     * 看门狗累计发现的卡死次数, 连接删除后仍保留
+     */
+    pub fn stalls_detected(&self) -> u64 {
+        self.stalls_detected
+    }
+
+    /**
+     * 握手协商的选项, 连接不存在或还没有处理过对端的 SYN 时为 None
+     */
+    pub fn negotiated_options(&self, id: ConnectionId) -> Option<NegotiatedOptions> {
+        match self.conns.get(&id) {
+            Some(conn) => conn.negotiated_options(),
+            None => self.half_open_syn(id)?.negotiated,
+        }
+    }
+
+    /**
+     * 连接当前的重传超时
+     */
+    pub fn rto_ms(&self, id: ConnectionId) -> Option<u64> {
+        self.conns.get(&id).map(|conn| conn.rtt().rto_ms())
+    }
+
+    pub fn srtt_ms(&self, id: ConnectionId) -> Option<u64> {
+        self.conns.get(&id)?.rtt().srtt_ms()
+    }
+
+    pub fn ssthresh(&self, id: ConnectionId) -> Option<u32> {
+        self.conns.get(&id).map(|conn| conn.ssthresh())
+    }
+
+    pub fn pacing_rate_bps(&self, id: ConnectionId) -> Option<u64> {
+        self.conns.get(&id)?.pacing_rate_bps()
+    }
+
+    /**
+     * pacing 挡住了该连接的待发数据时, 下一次 poll 能发出新数据的时刻
+     */
+    pub fn next_send_ms(&self, id: ConnectionId) -> Option<u64> {
+        self.conns.get(&id)?.next_send_ms()
+    }
+
+    pub fn cwnd(&self, id: ConnectionId) -> Option<u32> {
+        self.conns.get(&id).map(|conn| conn.cwnd())
+    }
+
+    /**
+     * 对端通告的发送窗口, 已按协商的 Window Scale 还原
+     */
+    pub fn snd_wnd(&self, id: ConnectionId) -> Option<u32> {
+        self.conns.get(&id).map(|conn| conn.snd_wnd())
+    }
+
+    /**
+     * 当前接收缓冲区容量, 打开 rcvbuf_autotune 时随读取速率变化
+     */
+    pub fn recv_capacity(&self, id: ConnectionId) -> Option<usize> {
+        self.conns.get(&id).map(|conn| conn.recv_capacity())
+    }
+
+    /**
+     * 发送缓冲区加在途的字节数, 即连接为发送占用的缓冲
+     */
+    pub fn send_queued(&self, id: ConnectionId) -> Option<usize> {
+        self.conns.get(&id).map(|conn| conn.send_queued())
+    }
+
+    pub fn destination_cache(&self) -> &DestinationCache {
+        &self.destinations
+    }
+
+    /**
+     * 半连接队列中的连接处于 SynReceived
+     */
+    pub fn state(&self, id: ConnectionId) -> Option<TcpState> {
+        match self.conns.get(&id) {
+            Some(conn) => Some(conn.state()),
+            None => self.half_open_syn(id).map(|_| TcpState::SynReceived),
+        }
+    }
+
+    pub fn remove(&mut self, id: ConnectionId) -> bool {
+        let mut removed = self.conns.remove(&id).is_some();
+        for backlog in self.backlogs.values_mut() {
+            removed |= backlog.forget(&id);
+        }
+        self.latency.remove(&id);
+        for queue in self.listeners.values_mut() {
+            queue.retain(|queued| *queued != id);
+        }
+        self.rebalance_memory();
+        self.refresh(id);
+        removed
+    }
+
+    pub fn len(&self) -> usize {
+        self.conns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conns.is_empty()
+    }
+
+    /**
+     * 一个连接或监听端口当前的就绪状态
+     */
+    pub fn readiness_of(&self, id: ConnectionId) -> Readiness {
+        self.ready.get(&id).copied().unwrap_or_default()
+    }
+
+    /**
+     * 按 std::io 的方式读写一个连接
+     */
+    pub fn stream(&mut self, id: ConnectionId) -> Stream<'_> {
+        Stream::new(self, id)
+    }
+
+    /**
+     * 水平触发: 当前所有非空的就绪状态, 按 ConnectionId 排序
+     */
+    pub fn readiness(&self) -> Vec<(ConnectionId, Readiness)> {
+        self.ready.iter().map(|(id, readiness)| (*id, *readiness)).collect()
+    }
+
+    /**
+     * 边沿触发: 自上次调用以来就绪状态变化了的连接, 变为空也会报告一次
+     */
+    pub fn readiness_changes(&mut self) -> Vec<(ConnectionId, Readiness)> {
+        let mut changes = vec![];
+        for id in std::mem::take(&mut self.changed) {
+            let now = self.ready.get(&id).copied().unwrap_or_default();
+            let before = self.reported.get(&id).copied().unwrap_or_default();
+            if now == before {
+                continue;
+            }
+            if now.is_empty() {
+                self.reported.remove(&id);
+            } else {
+                self.reported.insert(id, now);
+            }
+            changes.push((id, now));
+        }
+        changes
+    }
+
+    /**
+     * 有待发数据的连接排到轮转队列末尾, 已经在队列中的不重复入队
+     */
+    fn schedule(&mut self, id: ConnectionId) {
+        if self.conns.get(&id).is_some_and(|conn| conn.has_pending_send()) && self.scheduled.insert(id) {
+            self.active.push_back(id);
+        }
+    }
+
+    /**
+     * 重新计算一个连接或监听端口的就绪状态
+     */
+    fn refresh(&mut self, id: ConnectionId) {
+        self.rearm(id);
+        if let Some(conn) = self.conns.get_mut(&id) {
+            conn.update_watermarks();
+            if let Some(metrics) = conn.take_destination_metrics() {
+                self.destinations.update(id.d_ip, metrics, conn.clock_ms());
+            }
+        }
+        let readiness = match (self.conns.get(&id), self.listeners.get(&id)) {
+            (Some(conn), _) => connection_readiness(conn),
+            (None, Some(queue)) => {
+                let pending = queue.iter().any(|id| self.conns.get(id).is_some_and(handshake_done));
+                if pending { Readiness::ACCEPT } else { Readiness::EMPTY }
+            }
+            (None, None) => Readiness::EMPTY,
+        };
+        let before = self.ready.get(&id).copied().unwrap_or_default();
+        if readiness == before {
+            return;
+        }
+        if readiness.is_empty() {
+            self.ready.remove(&id);
+        } else {
+            self.ready.insert(id, readiness);
+        }
+        self.changed.insert(id);
+        // 握手完成会改变所属监听端口的 ACCEPT
+        if id.d_port != 0 {
+            for listener in [listener_id(id.s_ip, id.s_port), listener_id(0, id.s_port)] {
+                if self.listeners.get(&listener).is_some_and(|queue| queue.contains(&id)) {
+                    self.refresh(listener);
+                }
+            }
+        }
+    }
+
+    /**
+     * 按连接当前的截止时间重排它的定时器, 连接已经删除时取消
+     */
+    fn rearm(&mut self, id: ConnectionId) {
+        let (timer, rto) = self.conns.get(&id).map_or((None, None), |conn| (conn.next_timer_ms(), conn.rto_deadline_ms()));
+        for (queue, deadline) in [(&mut self.timers, timer), (&mut self.rto_timers, rto)] {
+            match deadline {
+                Some(deadline) if queue.deadline_of(&id) != Some(deadline) => queue.schedule(id, deadline),
+                Some(_) => {}
+                None => {
+                    queue.cancel(&id);
+                }
+            }
+        }
+    }
+}
+
+/**
+ * 只带 MSS 选项的 SYN: 签发 cookie 时的应答与经 cookie 完成握手的连接都按它协商, 二者一致
+ * 握手完成时原来的窗口已经不知道, 由随后的 ACK 更新
+ */
+fn cookie_syn(id: ConnectionId, client_isn: u32, mss: u16, window: u16) -> TcpSegment {
+    let mss_option = 0x0204_0000 | mss as u32; // kind 2, 长度 4
+    TcpSegment::new(id.d_port, id.s_port, client_isn, 0, 6, 0, TcpFlags::SYN, window, 0, vec![mss_option], vec![])
+}
+
+/**
+ * 被动打开的连接完成了握手, 可以 accept; 对端可能在 accept 之前就已经发来 FIN
+ * 接受了 TFO 数据的连接在握手完成之前就可以 accept
+ */
+fn handshake_done(conn: &TcpConnection) -> bool {
+    matches!(conn.state(), TcpState::Established | TcpState::CloseWait) || conn.fast_open_accepted()
+}
+
+fn connection_readiness(conn: &TcpConnection) -> Readiness {
+    let mut readiness = Readiness::EMPTY;
+    if conn.readable() {
+        readiness |= Readiness::READABLE;
+    }
+    if conn.writable() {
+        readiness |= Readiness::WRITABLE;
+    }
+    if conn.read_high() {
+        readiness |= Readiness::READ_HIGH;
+    }
+    if conn.error().is_some() {
+        readiness |= Readiness::ERROR;
+    }
+    readiness
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::pair::TablePair;
+
+    const SERVER: u32 = 0x0a000001;
+    const CLIENT: u32 = 0x0a000002;
+    const R: Readiness = Readiness::READABLE;
+    const W: Readiness = Readiness::WRITABLE;
+    const LINK: TablePair = TablePair::new(CLIENT, SERVER);
+
+    fn server_id(port: u16) -> ConnectionId {
+        ConnectionId { s_ip: SERVER, s_port: 80, d_ip: CLIENT, d_port: port }
+    }
+
+    #[test]
+    fn test_readiness_display() {
+        assert_eq!((R | Readiness::ERROR).to_string(), "READABLE|ERROR");
+        assert!(Readiness::EMPTY.is_empty());
+    }
+
+    #[test]
+    fn test_level_and_edge_triggered_readiness() {
+        let config = TcpConfig::default();
+        let mut client = ConnectionTable::new(&config);
+        let mut server = ConnectionTable::new(&config);
+        let listener = server.listen(0, 80);
+
+        let mut client_ids = vec![];
+        for port in [40001, 40002, 40003] {
+            let id = ConnectionId { s_ip: CLIENT, s_port: port, d_ip: SERVER, d_port: 80 };
+            client_ids.push(id);
+            let syn = client.connect(id, 0);
+            LINK.exchange(&mut client, &mut server, vec![syn]);
+        }
+        assert_eq!(server.readiness(), vec![
+            (listener, Readiness::ACCEPT), (server_id(40001), W), (server_id(40002), W), (server_id(40003), W),
+        ]);
+        for port in [40001, 40002, 40003] {
+            assert_eq!(server.accept(listener), Some(server_id(port)));
+        }
+        assert_eq!(server.accept(listener), None);
+
+        // 监听端口的 ACCEPT 出现又消失, 边沿触发不报告
+        assert_eq!(server.readiness_changes(), vec![(server_id(40001), W), (server_id(40002), W), (server_id(40003), W)]);
+        assert!(server.readiness_changes().is_empty());
+
+        // 第一个连接收到数据, 第二个空闲, 第三个被重置
+        assert_eq!(client.write(client_ids[0], b"hello"), Ok(5));
+        let data = client.poll_send(client_ids[0]);
+        LINK.exchange(&mut client, &mut server, data);
+        client.abort(client_ids[2], 0).unwrap();
+        let (_, rst) = client.poll_transmit(usize::MAX).pop().unwrap();
+        assert!(rst.RST());
+        server.segment_received(CLIENT, SERVER, &rst, 0);
+
+        let expected = vec![(server_id(40001), R | W), (server_id(40002), W), (server_id(40003), Readiness::ERROR)];
+        assert_eq!(server.readiness(), expected);
+        assert_eq!(server.readiness_changes(), vec![(server_id(40001), R | W), (server_id(40003), Readiness::ERROR)]);
+        assert_eq!(server.write(server_id(40003), b"x"), Err(ConnectionError::Reset));
+
+        assert_eq!(server.read(server_id(40001), 100), Ok(b"hello".to_vec()));
+        assert_eq!(server.readiness_changes(), vec![(server_id(40001), W)]);
+        assert!(server.readiness_changes().is_empty());
+        assert_eq!(server.readiness(), vec![(server_id(40001), W), (server_id(40002), W), (server_id(40003), Readiness::ERROR)]);
+
+        server.remove(server_id(40003));
+        assert_eq!(server.readiness_changes(), vec![(server_id(40003), Readiness::EMPTY)]);
+    }
+
+    #[test]
+    fn test_md5_keys_cover_the_handshake() {
+        let config = TcpConfig::default();
+        let mut client = ConnectionTable::new(&config);
+        let mut server = ConnectionTable::new(&config);
+        client.set_md5_key(SERVER, Some(b"bgp-peer".to_vec()));
+        server.set_md5_key(CLIENT, Some(b"bgp-peer".to_vec()));
+        let listener = server.listen(0, 80);
+        let id = ConnectionId { s_ip: CLIENT, s_port: 40001, d_ip: SERVER, d_port: 80 };
+        let syn = client.connect(id, 0);
+        LINK.exchange(&mut client, &mut server, vec![syn]);
+        assert_eq!(server.accept(listener), Some(server_id(40001)));
+        assert_eq!(client.write(id, b"open"), Ok(4));
+        let data = client.poll_send(id);
+        LINK.exchange(&mut client, &mut server, data);
+        assert_eq!(server.read(server_id(40001), 100), Ok(b"open".to_vec()));
+
+        // 没有密钥的客户端: SYN 被静默丢弃, 也不占用半连接
+        let mut plain = ConnectionTable::new(&config);
+        let syn = plain.connect(ConnectionId { s_port: 40002, ..id }, 0);
+        assert!(server.segment_received(CLIENT, SERVER, &syn, 0).is_empty());
+        assert_eq!(server.half_open(listener), 0);
+    }
+
+    /**
+     * 三个连接都有大量数据, 接口队列每轮只放得下 4 个段
+     */
+    #[test]
+    fn test_transmit_round_robin() {
+        let config = TcpConfig::default();
+        let mut client = ConnectionTable::new(&config);
+        let mut server = ConnectionTable::new(&config);
+        server.listen(0, 80);
+        let ids: Vec<ConnectionId> = [40001, 40002, 40003].iter()
+            .map(|port| ConnectionId { s_ip: CLIENT, s_port: *port, d_ip: SERVER, d_port: 80 })
+            .collect();
+        for id in &ids {
+            let syn = client.connect(*id, 0);
+            LINK.exchange(&mut client, &mut server, vec![syn]);
+            assert_eq!(client.write(*id, &[7; 200_000]), Ok(config.send_buffer));
+        }
+
+        let mut totals = [0u32; 3];
+        for _ in 0..30 {
+            let segments = client.poll_transmit(4);
+            assert_eq!(segments.len(), 4);
+            for (i, id) in ids.iter().enumerate() {
+                let sent = client.segments_sent_last_poll(*id);
+                assert!((1..=2).contains(&sent));
+                totals[i] += sent;
+            }
+            LINK.exchange(&mut client, &mut server, segments.into_iter().map(|(_, segment)| segment).collect());
+            for id in &ids {
+                server.read(id.reversed(), usize::MAX).unwrap();
+            }
+        }
+        assert_eq!(totals.iter().sum::<u32>(), 120);
+        assert!(totals.iter().all(|total| *total == 40));
+    }
+
+    #[test]
+    fn test_timers_follow_connections() {
+        let config = TcpConfig::default();
+        let mut client = ConnectionTable::new(&config);
+        let mut server = ConnectionTable::new(&config);
+        server.listen(0, 80);
+        let id = ConnectionId { s_ip: CLIENT, s_port: 40001, d_ip: SERVER, d_port: 80 };
+        let syn = client.connect(id, 0);
+        let rto = client.rto_ms(id).unwrap();
+        assert_eq!(client.next_timer_ms(), Some(rto)); // SYN 的重传计时
+        LINK.at(10).exchange(&mut client, &mut server, vec![syn]);
+        assert_eq!(client.next_timer_ms(), None);
+
+        // 发出的数据开始重传计时, 到期之前不会交出
+        client.write(id, b"lost").unwrap();
+        client.poll(20);
+        let deadline = client.rto_deadline_ms(id).unwrap();
+        assert_eq!(client.next_timer_ms(), Some(deadline));
+        assert!(client.retransmissions_due(deadline - 1).is_empty());
+        assert_eq!(client.retransmissions_due(deadline), vec![id]);
+        assert!(client.retransmission(id).is_some());
+        assert!(client.rto_deadline_ms(id).unwrap() > deadline);
+
+        // 空闲超时排进定时器, 只有到期时才 tick 这个连接
+        client.set_idle_timeout(id, Some(Duration::from_millis(5000)), IdleAction::Close).unwrap();
+        let idle = deadline + 5000; // 从设置时表的时钟开始计时
+        assert!(client.next_timer_ms().is_some_and(|next| next <= idle));
+        assert!(client.tick(idle - 1).is_empty());
+        assert_eq!(client.state(id), Some(TcpState::Established));
+        client.poll(idle);
+        assert_eq!(client.state(id), Some(TcpState::FinWait1));
+
+        client.remove(id);
+        assert_eq!(client.next_timer_ms(), None);
+    }
+}
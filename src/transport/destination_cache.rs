@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+/**
+ * 连接结束时留下的、关于某个目的地址的路径信息
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DestinationMetrics {
+    pub srtt_ms: u64,
+    pub rttvar_ms: u64,
+    pub ssthresh: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    metrics: DestinationMetrics,
+    expires_ms: u64,
+    last_used: u64, // 越大越近, 容量满时淘汰最小的
+}
+
+/**
+ * 按对端地址缓存的 RTT 与 ssthresh, 新连接用它代替默认值起步, 连接结束时更新
+ * 条目 ttl_ms 之后过期; 最多 capacity 个, 满了淘汰最久没有使用的, capacity 为 0 时不缓存
+ */
+#[derive(Debug, Clone)]
+pub struct DestinationCache {
+    capacity: usize,
+    ttl_ms: u64,
+    entries: HashMap<u32, Entry>,
+    uses: u64,
+}
+
+impl DestinationCache {
+    pub fn new(capacity: usize, ttl_ms: u64) -> Self {
+        DestinationCache { capacity, ttl_ms, entries: HashMap::new(), uses: 0 }
+    }
+
+    /**
+     * 查询 d_addr 的缓存并记为最近使用, 过期的条目顺带删除
+     */
+    pub fn get(&mut self, d_addr: u32, now_ms: u64) -> Option<DestinationMetrics> {
+        let entry = self.entries.get_mut(&d_addr)?;
+        if now_ms >= entry.expires_ms {
+            self.entries.remove(&d_addr);
+            return None;
+        }
+        self.uses += 1;
+        entry.last_used = self.uses;
+        Some(entry.metrics)
+    }
+
+    /**
+     * 记录一个结束的连接测得的值, 覆盖旧条目并重新计时
+     */
+    pub fn update(&mut self, d_addr: u32, metrics: DestinationMetrics, now_ms: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+        if !self.entries.contains_key(&d_addr) && self.entries.len() >= self.capacity {
+            self.expire(now_ms);
+            if self.entries.len() >= self.capacity {
+                let oldest = self.entries.iter().min_by_key(|(_, entry)| entry.last_used).map(|(&d_addr, _)| d_addr);
+                if let Some(oldest) = oldest {
+                    self.entries.remove(&oldest);
+                }
+            }
+        }
+        self.uses += 1;
+        self.entries.insert(d_addr, Entry { metrics, expires_ms: now_ms + self.ttl_ms, last_used: self.uses });
+    }
+
+    /**
+     * 删除过期的条目
+     */
+    pub fn expire(&mut self, now_ms: u64) {
+        self.entries.retain(|_, entry| now_ms < entry.expires_ms);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(srtt_ms: u64) -> DestinationMetrics {
+        DestinationMetrics { srtt_ms, rttvar_ms: srtt_ms / 2, ssthresh: u32::MAX }
+    }
+
+    #[test]
+    fn test_lru_eviction_and_expiry() {
+        let mut cache = DestinationCache::new(3, 1000);
+        for d_addr in 1..=3 {
+            cache.update(d_addr, metrics(d_addr as u64 * 10), 0);
+        }
+        // 1 刚被使用, 满了之后淘汰的是 2
+        assert_eq!(cache.get(1, 10).map(|m| m.srtt_ms), Some(10));
+        cache.update(4, metrics(40), 10);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get(2, 10), None);
+        assert!(cache.get(1, 10).is_some() && cache.get(3, 10).is_some() && cache.get(4, 10).is_some());
+
+        // 更新已有条目不淘汰别的条目
+        cache.update(3, metrics(35), 500);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get(1, 999).map(|m| m.srtt_ms), Some(10));
+        assert_eq!(cache.get(1, 1000), None);
+        cache.expire(1499);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(3, 1499).map(|m| m.srtt_ms), Some(35));
+
+        let mut disabled = DestinationCache::new(0, 1000);
+        disabled.update(1, metrics(10), 0);
+        assert!(disabled.is_empty());
+    }
+}
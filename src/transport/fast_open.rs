@@ -0,0 +1,165 @@
+use crate::config::TcpConfig;
+use crate::utils::siphash::{random_key, siphash24};
+
+use super::tcp_option::TcpOption;
+use super::tcp_segment::TcpSegment;
+
+pub const TFO_COOKIE_LEN: usize = 8;
+
+/**
+ * TFO cookie: 用本端 128 位密钥对对端 IP 做 SipHash-2-4, 输出的 8 字节就是 cookie
+ * 同一对端在密钥不变时总是得到同一个 cookie, 服务端不需要保存
+ */
+pub struct TfoCookies {
+    secret: [u64; 2],
+}
+
+impl TfoCookies {
+    pub fn new() -> Self {
+        TfoCookies { secret: random_key() }
+    }
+
+    pub fn with_secret(secret: [u64; 2]) -> Self {
+        TfoCookies { secret }
+    }
+
+    pub fn generate(&self, peer_ip: u32) -> [u8; TFO_COOKIE_LEN] {
+        siphash24(self.secret, &peer_ip.to_be_bytes()).to_be_bytes()
+    }
+
+    pub fn validate(&self, peer_ip: u32, cookie: &[u8]) -> bool {
+        cookie == self.generate(peer_ip)
+    }
+}
+
+impl Default for TfoCookies {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/**
+ * 对一个 SYN 的 TFO 处理结果
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TfoDecision {
+    Normal,                                 // 未开启或 SYN 不带 TFO 选项, 普通握手
+    IssueCookie([u8; TFO_COOKIE_LEN]),      // 请求 cookie 或 cookie 无效: SYN-ACK 带上新 cookie, SYN 中的数据丢弃
+    AcceptData,                             // cookie 有效: SYN 中的数据立即交给应用
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TfoStats {
+    pub cookies_issued: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+}
+
+/**
+ * 监听端口上的 TFO 状态, 是否开启取自创建时的 tcp.fast_open
+ */
+pub struct TfoListener {
+    cookies: Option<TfoCookies>,
+    stats: TfoStats,
+}
+
+impl TfoListener {
+    pub fn new(config: &TcpConfig) -> Self {
+        TfoListener { cookies: config.fast_open.then(TfoCookies::new), stats: TfoStats::default() }
+    }
+
+    pub fn with_cookies(cookies: TfoCookies) -> Self {
+        TfoListener { cookies: Some(cookies), stats: TfoStats::default() }
+    }
+
+    pub fn on_syn(&mut self, peer_ip: u32, syn: &TcpSegment) -> TfoDecision {
+        let Some(cookies) = &self.cookies else {
+            return TfoDecision::Normal;
+        };
+        let Some(cookie) = fast_open_cookie(syn) else {
+            return TfoDecision::Normal;
+        };
+        if !cookie.is_empty() && cookies.validate(peer_ip, &cookie) {
+            self.stats.accepted += 1;
+            return TfoDecision::AcceptData;
+        }
+        if !cookie.is_empty() {
+            self.stats.rejected += 1;
+        }
+        self.stats.cookies_issued += 1;
+        TfoDecision::IssueCookie(cookies.generate(peer_ip))
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.cookies.is_some()
+    }
+
+    pub fn stats(&self) -> TfoStats {
+        self.stats
+    }
+}
+
+/**
+ * SYN 中的 TFO 选项, 空 Vec 表示请求 cookie
+ */
+pub fn fast_open_cookie(segment: &TcpSegment) -> Option<Vec<u8>> {
+    segment.parsed_options().ok()?.into_iter().find_map(|option| match option {
+        TcpOption::FastOpen(cookie) => Some(cookie),
+        _ => None,
+    })
+}
+
+/**
+ * 编码成 options 字: 两个 NOP 对齐后接 kind 34
+ */
+pub fn fast_open_words(cookie: &[u8]) -> Vec<u32> {
+    let mut bytes = vec![1, 1, 34, 2 + cookie.len() as u8];
+    bytes.extend_from_slice(cookie);
+    while !bytes.len().is_multiple_of(4) {
+        bytes.push(1);
+    }
+    bytes.chunks(4).map(|word| u32::from_be_bytes([word[0], word[1], word[2], word[3]])).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tcp_segment::TcpFlags;
+
+    const PEER: u32 = 0xc0a80002;
+
+    fn syn(options: Vec<u32>, data: &[u8]) -> TcpSegment {
+        let hl = 5 + options.len() as u8;
+        TcpSegment::new(51000, 80, 1000, 0, hl, 0, TcpFlags::SYN, 65535, 0, options, data.to_vec())
+    }
+
+    #[test]
+    fn test_cookie_request_gets_cookie() {
+        let mut listener = TfoListener::with_cookies(TfoCookies::with_secret([42, 0]));
+        let TfoDecision::IssueCookie(cookie) = listener.on_syn(PEER, &syn(fast_open_words(&[]), b"")) else { panic!() };
+        assert_eq!(cookie, TfoCookies::with_secret([42, 0]).generate(PEER));
+        assert_ne!(cookie, TfoCookies::with_secret([42, 0]).generate(PEER + 1));
+        assert_ne!(cookie, TfoCookies::with_secret([43, 0]).generate(PEER));
+
+        // SYN-ACK 中的 cookie 能解析回来
+        let words = fast_open_words(&cookie);
+        let syn_ack = TcpSegment::new(80, 51000, 7, 1001, 5 + words.len() as u8, 0, TcpFlags::SYN | TcpFlags::ACK, 65535, 0, words, vec![]);
+        assert_eq!(fast_open_cookie(&syn_ack), Some(cookie.to_vec()));
+        assert_eq!(listener.stats().cookies_issued, 1);
+    }
+
+    #[test]
+    fn test_valid_invalid_and_disabled() {
+        let mut listener = TfoListener::with_cookies(TfoCookies::with_secret([42, 0]));
+        let cookie = TfoCookies::with_secret([42, 0]).generate(PEER);
+        assert_eq!(listener.on_syn(PEER, &syn(fast_open_words(&cookie), b"GET /")), TfoDecision::AcceptData);
+        assert!(matches!(listener.on_syn(PEER + 1, &syn(fast_open_words(&cookie), b"GET /")), TfoDecision::IssueCookie(_)));
+        assert_eq!(listener.on_syn(PEER, &syn(vec![], b"")), TfoDecision::Normal);
+        assert_eq!(listener.stats(), TfoStats { cookies_issued: 1, accepted: 1, rejected: 1 });
+
+        let mut disabled = TfoListener::new(&TcpConfig::default());
+        assert!(!disabled.enabled());
+        assert_eq!(disabled.on_syn(PEER, &syn(fast_open_words(&cookie), b"GET /")), TfoDecision::Normal);
+    }
+}
@@ -0,0 +1,46 @@
+use crate::utils::siphash::{random_key, siphash24};
+
+use super::tcp_connection::ConnectionId;
+
+/**
+ * RFC 6528 的初始序号: ISN = M + F(本端地址, 本端端口, 对端地址, 对端端口, 密钥)
+ * M 为每 4 微秒加一的计时器, F 为 SipHash-2-4; 同一四元组的 ISN 随时间单调增长, 不同四元组之间不相关
+ * 密钥在创建时随机生成, 对外看不到
+ */
+pub struct IsnGenerator {
+    secret: [u64; 2],
+    fixed: Option<u32>,
+}
+
+impl IsnGenerator {
+    pub fn new() -> Self {
+        IsnGenerator { secret: random_key(), fixed: None }
+    }
+
+    /**
+     * 指定密钥, 用于需要复现 ISN 的测试
+     */
+    pub fn with_secret(secret: [u64; 2]) -> Self {
+        IsnGenerator { secret, fixed: None }
+    }
+
+    /**
+     * 每个连接都从 isn 开始, 用于把序号放到回绕点附近的测试
+     */
+    pub fn fixed(isn: u32) -> Self {
+        IsnGenerator { secret: [0, 0], fixed: Some(isn) }
+    }
+
+    pub fn generate(&self, id: &ConnectionId, now_us: u64) -> u32 {
+        if let Some(isn) = self.fixed {
+            return isn;
+        }
+        ((now_us / 4) as u32).wrapping_add(siphash24(self.secret, &id.to_bytes()) as u32)
+    }
+}
+
+impl Default for IsnGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
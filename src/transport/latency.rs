@@ -0,0 +1,77 @@
+use std::collections::VecDeque;
+
+use crate::link::interface::PacketMeta;
+use crate::utils::latency::LatencyHistogram;
+
+use super::seq::{seq_le, seq_lt};
+use super::tcp_segment::TcpSegment;
+
+/**
+ * 一个连接在链路层测得的延迟
+ * processing: 帧从设备取出到报文段被连接处理完的栈内耗时
+ * link_rtt: 帧交给设备发出到确认它的帧从设备取出的时间, 按 Karn 算法不对重传过的段取样
+ */
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionLatency {
+    processing: LatencyHistogram,
+    link_rtt: LatencyHistogram,
+    in_flight: VecDeque<(u32, u64)>, // 首次发出的段的 (序号终点, 发送时刻), 按序号排列
+    highest_sent: Option<u32>,
+}
+
+impl ConnectionLatency {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn processing(&self) -> &LatencyHistogram {
+        &self.processing
+    }
+
+    pub fn link_rtt(&self) -> &LatencyHistogram {
+        &self.link_rtt
+    }
+
+    /**
+     * 报文段所在的帧在 tx_ms 交给了设备; 不占序号的段不计时
+     * 重传的段使它覆盖的在途记录失效
+     */
+    pub fn on_transmit(&mut self, segment: &TcpSegment, tx_ms: u64) {
+        let len = segment.data.len() as u32 + segment.SYN() as u32 + segment.FIN() as u32;
+        if len == 0 {
+            return;
+        }
+        let end = segment.seq.wrapping_add(len);
+        match self.highest_sent {
+            Some(highest) if seq_le(end, highest) => {
+                self.in_flight.retain(|(sent_end, _)| seq_le(*sent_end, segment.seq));
+            }
+            _ => {
+                self.in_flight.push_back((end, tx_ms));
+                self.highest_sent = Some(end);
+            }
+        }
+    }
+
+    /**
+     * 报文段处理完毕时调用, now_ms 与 meta 的时间戳来自同一时钟
+     * 带 ACK 时用最后一个被完全确认的在途段取一次 RTT 样本
+     */
+    pub fn on_receive(&mut self, meta: &PacketMeta, segment: &TcpSegment, now_ms: u64) {
+        self.processing.record(now_ms.saturating_sub(meta.rx_timestamp_ms));
+        if !segment.ACK() {
+            return;
+        }
+        let mut sample = None;
+        while let Some(&(end, tx_ms)) = self.in_flight.front() {
+            if seq_lt(segment.ack, end) {
+                break;
+            }
+            sample = Some(tx_ms);
+            self.in_flight.pop_front();
+        }
+        if let Some(tx_ms) = sample {
+            self.link_rtt.record(meta.rx_timestamp_ms.saturating_sub(tx_ms));
+        }
+    }
+}
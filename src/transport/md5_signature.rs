@@ -0,0 +1,91 @@
+use crate::utils::md5::Md5;
+
+use super::tcp_option::TcpOption;
+use super::tcp_segment::TcpSegment;
+
+const PROTOCOL_TCP: u8 = 6;
+const BASE_HDR_LEN: usize = 20;
+
+/**
+ * 签名选项在首部中占用的字节数
+ */
+pub const OPTION_LEN: usize = 20;
+
+/**
+ * RFC 2385 的摘要: 伪首部、不含选项且校验和为 0 的 TCP 首部、数据、密钥
+ * hl 与伪首部中的长度都按带选项的实际长度计算, 所以要在加入签名选项之后再计算
+ */
+pub fn segment_digest(segment: &TcpSegment, s_addr: u32, d_addr: u32, key: &[u8]) -> [u8; 16] {
+    let tcp_len = segment.hl as usize * 4 + segment.data.len();
+    let mut md5 = Md5::new();
+    md5.update(&s_addr.to_be_bytes());
+    md5.update(&d_addr.to_be_bytes());
+    md5.update(&[0, PROTOCOL_TCP]);
+    md5.update(&(tcp_len as u16).to_be_bytes());
+    let (header, _) = segment.header_bytes();
+    let mut header: [u8; BASE_HDR_LEN] = header[..BASE_HDR_LEN].try_into().unwrap();
+    header[16] = 0;
+    header[17] = 0;
+    md5.update(&header);
+    md5.update(&segment.data);
+    md5.update(key);
+    md5.finish()
+}
+
+/**
+ * 追加签名选项(两个 NOP 对齐, 共 5 个字)并重新计算校验和
+ */
+pub fn sign(segment: &mut TcpSegment, s_addr: u32, d_addr: u32, key: &[u8]) {
+    segment.hl += (OPTION_LEN / 4) as u8;
+    let digest = segment_digest(segment, s_addr, d_addr, key);
+    let mut bytes = vec![1, 1, 19, 18];
+    bytes.extend_from_slice(&digest);
+    segment.options.extend(bytes.chunks(4).map(|w| u32::from_be_bytes([w[0], w[1], w[2], w[3]])));
+    segment.generate_checksum(s_addr, d_addr);
+}
+
+/**
+ * 报文段带有签名选项且与按 key 计算的摘要一致
+ */
+pub fn verify(segment: &TcpSegment, s_addr: u32, d_addr: u32, key: &[u8]) -> bool {
+    let carried = segment.parsed_options().ok().and_then(|options| options.into_iter().find_map(|option| match option {
+        TcpOption::Md5Signature(digest) => Some(digest),
+        _ => None,
+    }));
+    carried == Some(segment_digest(segment, s_addr, d_addr, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tcp_segment::TcpFlags;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let mut segment = TcpSegment::new(179, 40000, 1000, 2000, 5, 0, TcpFlags::ACK | TcpFlags::PSH, 8192, 0, vec![], b"OPEN".to_vec());
+        sign(&mut segment, 0x0a000001, 0x0a000002, b"secret");
+        assert_eq!(segment.hl, 10);
+        assert!(segment.check_checksum(0x0a000001, 0x0a000002));
+        assert!(verify(&segment, 0x0a000001, 0x0a000002, b"secret"));
+        assert!(!verify(&segment, 0x0a000001, 0x0a000002, b"other"));
+        assert!(!verify(&segment, 0x0a000001, 0x0a000003, b"secret")); // 伪首部也在摘要里
+
+        segment.data[0] ^= 1;
+        assert!(!verify(&segment, 0x0a000001, 0x0a000002, b"secret"));
+        let unsigned = TcpSegment::new(179, 40000, 1000, 2000, 5, 0, TcpFlags::ACK, 8192, 0, vec![], vec![]);
+        assert!(!verify(&unsigned, 0x0a000001, 0x0a000002, b"secret"));
+    }
+
+    /**
+     * 期望值由 Python hashlib.md5 对同样的字节序列计算:
+     * 0a000001 0a000002 0006 002c | 00b3 9c40 000003e8 000007d0 a018 2000 0000 0000 | "OPEN" | "secret"
+     */
+    #[test]
+    fn test_digest_matches_reference() {
+        let mut segment = TcpSegment::new(179, 40000, 1000, 2000, 5, 0, TcpFlags::ACK | TcpFlags::PSH, 8192, 0, vec![], b"OPEN".to_vec());
+        sign(&mut segment, 0x0a000001, 0x0a000002, b"secret");
+        let Some(TcpOption::Md5Signature(digest)) = segment.parsed_options().unwrap().into_iter().nth(2) else { panic!() };
+        let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, "943abd242faa3e5f4d065ec6801fec29");
+    }
+}
@@ -1,3 +1,26 @@
 pub mod tcp_segment;
 pub mod tcp_connection;
-pub mod tcp_receiver;
\ No newline at end of file
+pub mod tcp_fsm_table;
+pub mod connection_table;
+pub mod tcp_receiver;
+pub mod tcp_option;
+pub mod syn_cookie;
+pub mod isn;
+pub mod retransmit_queue;
+pub mod seq;
+pub(crate) mod rack;
+pub(crate) mod ack_batch;
+pub(crate) mod window_update;
+pub mod fast_open;
+pub mod socket_options;
+pub mod stream;
+pub mod pacing;
+pub(crate) mod rcvbuf_tune;
+pub(crate) mod md5_signature;
+pub mod udp;
+pub mod latency;
+pub(crate) mod rtt;
+pub mod destination_cache;
+pub mod shared_table;
+#[cfg(feature = "async")]
+pub mod async_stream;
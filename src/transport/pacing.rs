@@ -0,0 +1,117 @@
+use crate::config::TcpConfig;
+
+/**
+ * 发送节奏控制: 令牌桶按 gain * cwnd / SRTT 的速率补充, 桶容量为 pacing_burst 个 MSS
+ * 未开启时不做任何限制; 还没有 RTT 样本时也不限制(握手阶段没有可用的速率)
+ * 令牌以千分之一字节为单位, 避免低速率下按毫秒取整成 0
+ */
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    enabled: bool,
+    gain_percent: u64,
+    burst_milli: u64,
+    rate_milli_per_ms: Option<u64>, // 每毫秒补充的令牌
+    credit_milli: u64,
+    last_ms: u64,
+}
+
+impl Pacer {
+    pub fn new(config: &TcpConfig, now_ms: u64) -> Self {
+        let burst_milli = config.pacing_burst.max(1) as u64 * config.mss as u64 * 1000;
+        Pacer {
+            enabled: config.pacing,
+            gain_percent: config.pacing_gain_percent as u64,
+            burst_milli,
+            rate_milli_per_ms: None,
+            credit_milli: burst_milli,
+            last_ms: now_ms,
+        }
+    }
+
+    /**
+     * cwnd 或 SRTT 变化后更新速率
+     */
+    pub fn set_rate(&mut self, cwnd: u32, srtt_ms: u64) {
+        self.rate_milli_per_ms = Some((cwnd as u64 * 10 * self.gain_percent / srtt_ms.max(1)).max(1));
+    }
+
+    /**
+     * 发送速率, 比特每秒
+     */
+    pub fn rate_bps(&self) -> Option<u64> {
+        self.rate_milli_per_ms.map(|rate| rate * 8)
+    }
+
+    /**
+     * 现在能否发出 len 字节, 能则扣除令牌
+     */
+    pub fn try_send(&mut self, len: usize, now_ms: u64) -> bool {
+        let Some(rate) = self.rate_milli_per_ms.filter(|_| self.enabled) else {
+            return true;
+        };
+        self.refill(rate, now_ms);
+        let cost = len as u64 * 1000;
+        // 令牌不足一个段时也允许从满桶发出, 防止大段永远发不出去
+        if self.credit_milli >= cost.min(self.burst_milli) {
+            self.credit_milli = self.credit_milli.saturating_sub(cost);
+            true
+        } else {
+            false
+        }
+    }
+
+    /**
+     * 下一个 len 字节的段最早可以发出的时刻, 给 tick 定时用; 不受限时为 None
+     */
+    pub fn next_send_ms(&self, len: usize) -> Option<u64> {
+        let rate = self.rate_milli_per_ms.filter(|_| self.enabled)?;
+        let need = (len as u64 * 1000).min(self.burst_milli).saturating_sub(self.credit_milli);
+        Some(self.last_ms + need.div_ceil(rate))
+    }
+
+    fn refill(&mut self, rate: u64, now_ms: u64) {
+        let elapsed = now_ms.saturating_sub(self.last_ms);
+        self.credit_milli = (self.credit_milli + elapsed * rate).min(self.burst_milli);
+        self.last_ms = self.last_ms.max(now_ms);
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pacer(pacing: bool) -> Pacer {
+        let config = TcpConfig { mss: 1000, pacing, pacing_gain_percent: 100, pacing_burst: 2, ..TcpConfig::default() };
+        Pacer::new(&config, 0)
+    }
+
+    #[test]
+    fn test_rate_limits_after_burst() {
+        let mut pacer = pacer(true);
+        assert!(pacer.try_send(1000, 0)); // 没有 RTT 样本时不限制
+        pacer.set_rate(10_000, 100); // 100 字节/毫秒
+        assert_eq!(pacer.rate_bps(), Some(800_000));
+        assert!(pacer.try_send(1000, 0));
+        assert!(pacer.try_send(1000, 0));
+        assert!(!pacer.try_send(1000, 0)); // 突发额度用完
+        assert_eq!(pacer.next_send_ms(1000), Some(10));
+        assert!(!pacer.try_send(1000, 9));
+        assert!(pacer.try_send(1000, 10));
+
+        // 空闲很久之后也只能突发 2 段
+        let sent = (0..5).filter(|_| pacer.try_send(1000, 10_000)).count();
+        assert_eq!(sent, 2);
+    }
+
+    #[test]
+    fn test_disabled_never_blocks() {
+        let mut pacer = pacer(false);
+        pacer.set_rate(1, 1000);
+        assert!((0..100).all(|_| pacer.try_send(1460, 0)));
+        assert_eq!(pacer.next_send_ms(1460), None);
+    }
+}
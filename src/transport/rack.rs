@@ -0,0 +1,92 @@
+use super::retransmit_queue::RetransmitQueue;
+use super::seq::seq_lt;
+
+/**
+ * RACK 的最小乱序窗口
+ */
+pub const MIN_REORDER_WINDOW_MS: u64 = 1;
+
+/**
+ * 按时间判断丢包 (RFC 8985 的简化版本)
+ * 记住最近送达的段是什么时候发出的; 比它更早发出、还没有送达的段,
+ * 在它的发送时刻加上 RTT 和乱序窗口之后仍未送达才算丢失
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Rack {
+    xmit_ms: Option<u64>, // 最近送达的段的发出时刻
+    end_seq: u32,         // 该段的序号终点, 同一时刻发出的段按序号区分先后
+    rtt_ms: u64,          // 该段测得的 RTT
+    min_rtt_ms: Option<u64>,
+}
+
+impl Rack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * 一个没有重传过的段在 now_ms 送达
+     */
+    pub fn on_delivered(&mut self, sent_ms: u64, end_seq: u32, now_ms: u64) {
+        let rtt = now_ms.saturating_sub(sent_ms);
+        self.min_rtt_ms = Some(self.min_rtt_ms.map_or(rtt, |min| min.min(rtt)));
+        let newer = match self.xmit_ms {
+            None => true,
+            Some(xmit) => sent_ms > xmit || (sent_ms == xmit && seq_lt(self.end_seq, end_seq)),
+        };
+        if newer {
+            self.xmit_ms = Some(sent_ms);
+            self.end_seq = end_seq;
+            self.rtt_ms = rtt;
+        }
+    }
+
+    /**
+     * 乱序窗口: max(最小 RTT / 4, 1ms)
+     */
+    pub fn reorder_window_ms(&self) -> u64 {
+        (self.min_rtt_ms.unwrap_or(0) / 4).max(MIN_REORDER_WINDOW_MS)
+    }
+
+    /**
+     * 已经判定丢失的段的起始序号, 以及下一个可能判定丢失的时刻
+     */
+    pub fn detect(&self, queue: &RetransmitQueue, now_ms: u64) -> (Vec<u32>, Option<u64>) {
+        let Some(xmit) = self.xmit_ms else {
+            return (vec![], None);
+        };
+        let mut lost = vec![];
+        let mut timeout: Option<u64> = None;
+        let earlier = |sent_ms: u64, end: u32| sent_ms < xmit || (sent_ms == xmit && seq_lt(end, self.end_seq));
+        for segment in queue.iter().filter(|segment| !segment.sacked && earlier(segment.sent_ms, segment.end())) {
+            let deadline = segment.sent_ms + self.rtt_ms + self.reorder_window_ms();
+            if deadline <= now_ms {
+                lost.push(segment.seq);
+            } else {
+                timeout = Some(timeout.map_or(deadline, |t| t.min(deadline)));
+            }
+        }
+        (lost, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reordered_segment_is_not_lost_within_window() {
+        let mut queue = RetransmitQueue::new();
+        for i in 0..4 {
+            queue.push_at(i * 100, vec![0; 100], 10 * i as u64);
+        }
+        let mut rack = Rack::new();
+        // 发出于 t=20 的第三段先于前两段送达(经 SACK), RTT 为 40ms
+        rack.on_delivered(20, 300, 60);
+        assert_eq!(rack.reorder_window_ms(), 10);
+        assert_eq!(rack.detect(&queue, 45), (vec![], Some(50)));
+        // t=50 时第一段(t=0 发出)超过 0 + 40 + 10, 第二段还在窗口内
+        assert_eq!(rack.detect(&queue, 55), (vec![0], Some(60)));
+        assert_eq!(rack.detect(&queue, 60), (vec![0, 100], None));
+    }
+}
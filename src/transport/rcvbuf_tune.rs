@@ -0,0 +1,155 @@
+use crate::config::TcpConfig;
+
+/**
+ * 没有 RTT 样本时的测量周期
+ */
+pub const DEFAULT_RTT_MS: u64 = 100;
+
+/**
+ * 连续多少个周期未读数据都很多才缩小缓冲区
+ */
+const LAG_INTERVALS: u32 = 3;
+
+/**
+ * 接收缓冲区自动调整, 仿照 Linux 的 tcp_rcv_space_adjust
+ * 每个 RTT 统计应用层读走的字节数: 读走了至少半个缓冲区说明应用跟得上, 窗口限制了速率, 容量翻倍直到上限;
+ * 周期内未读数据始终超过容量的 3/4 并持续 LAG_INTERVALS 个周期, 说明应用跟不上, 容量缩回两倍读取速率(不低于初始值)
+ */
+#[derive(Debug, Clone)]
+pub struct RcvBufTuner {
+    initial: usize,
+    max: usize,
+    capacity: usize,
+    rtt_ms: u64,
+    interval_start_ms: u64,
+    copied: usize,
+    min_unread: usize,
+    high_water: usize,
+    lagging: u32,
+}
+
+impl RcvBufTuner {
+    pub fn new(config: &TcpConfig, now_ms: u64) -> Self {
+        let initial = config.rcvbuf_initial.min(config.recv_buffer).max(1);
+        RcvBufTuner {
+            initial,
+            max: config.recv_buffer,
+            capacity: initial,
+            rtt_ms: DEFAULT_RTT_MS,
+            interval_start_ms: now_ms,
+            copied: 0,
+            min_unread: usize::MAX,
+            high_water: 0,
+            lagging: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /**
+     * 整个连接期间未读数据的最大值
+     */
+    #[cfg(test)]
+    pub fn high_water(&self) -> usize {
+        self.high_water
+    }
+
+    /**
+     * 用平滑 RTT(或时间戳选项得到的样本)作为测量周期
+     */
+    pub fn on_rtt_sample(&mut self, rtt_ms: u64) {
+        self.rtt_ms = rtt_ms.max(1);
+    }
+
+    /**
+     * 应用层读走了 n 字节
+     */
+    pub fn on_read(&mut self, n: usize) {
+        self.copied += n;
+    }
+
+    /**
+     * 记录此刻未读的字节数; 应用读走数据之后和 tick 时调用
+     */
+    pub fn observe(&mut self, unread: usize) {
+        self.min_unread = self.min_unread.min(unread);
+        self.high_water = self.high_water.max(unread);
+    }
+
+    /**
+     * 本周期结束、tick 会调整容量的时刻
+     */
+    pub fn next_tick_ms(&self) -> u64 {
+        self.interval_start_ms + self.rtt_ms
+    }
+
+    /**
+     * 周期结束时调用, unread 为此刻未读的字节数; 容量变化时返回新容量
+     */
+    pub fn tick(&mut self, unread: usize, now_ms: u64) -> Option<usize> {
+        self.observe(unread);
+        if now_ms < self.next_tick_ms() {
+            return None;
+        }
+
+        let old = self.capacity;
+        if self.min_unread * 4 >= self.capacity * 3 {
+            self.lagging += 1;
+        } else {
+            self.lagging = 0;
+        }
+        if self.lagging >= LAG_INTERVALS {
+            self.capacity = (2 * self.copied).clamp(self.initial, self.capacity);
+            self.lagging = 0;
+        } else if self.lagging == 0 && 2 * self.copied >= self.capacity {
+            self.capacity = (2 * self.capacity).min(self.max);
+        }
+
+        self.interval_start_ms = now_ms;
+        self.copied = 0;
+        self.min_unread = usize::MAX;
+        (self.capacity != old).then_some(self.capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> TcpConfig {
+        TcpConfig { rcvbuf_autotune: true, rcvbuf_initial: 16 * 1024, recv_buffer: 256 * 1024, ..TcpConfig::default() }
+    }
+
+    #[test]
+    fn test_grows_geometrically_when_reader_keeps_up() {
+        let mut tuner = RcvBufTuner::new(&config(), 0);
+        let mut sizes = vec![];
+        for round in 1..=6 {
+            tuner.on_read(tuner.capacity());
+            if let Some(capacity) = tuner.tick(0, round * DEFAULT_RTT_MS) {
+                sizes.push(capacity / 1024);
+            }
+        }
+        assert_eq!(sizes, vec![32, 64, 128, 256]);
+    }
+
+    #[test]
+    fn test_shrinks_when_reader_lags() {
+        let mut tuner = RcvBufTuner::new(&config(), 0);
+        for round in 1..=3 {
+            tuner.on_read(tuner.capacity());
+            tuner.tick(0, round * DEFAULT_RTT_MS);
+        }
+        assert_eq!(tuner.capacity(), 128 * 1024);
+        // 应用每个周期只读 20 KB, 缓冲区一直接近满
+        for round in 4..=5 {
+            tuner.on_read(20 * 1024);
+            assert_eq!(tuner.tick(120 * 1024, round * DEFAULT_RTT_MS), None);
+        }
+        tuner.on_read(20 * 1024);
+        assert_eq!(tuner.tick(120 * 1024, 6 * DEFAULT_RTT_MS), Some(40 * 1024));
+        assert_eq!(tuner.high_water(), 120 * 1024);
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::VecDeque;
+
+use super::seq::{seq_le, seq_lt};
+
+/**
+ * 已发出、尚未被累计确认的段
+ * sacked 的段不再重传, 但数据保留到累计确认为止(对端可能食言, RFC 2018 第 8 节)
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InFlight {
+    pub seq: u32,
+    pub data: Vec<u8>,
+    pub sacked: bool,
+    pub retransmits: u32,
+    pub sent_ms: u64, // 最近一次发出(包括重传)的时刻
+    pub psh: bool,    // 原来的段带 PSH, 重传时保留
+    pub urgent: Option<u16>, // 原来的段带 URG 时的紧急指针, 重传时保留
+}
+
+impl InFlight {
+    pub fn end(&self) -> u32 {
+        self.seq.wrapping_add(self.data.len() as u32)
+    }
+}
+
+/**
+ * 发送端的重传队列, 按序号排列
+ */
+#[derive(Debug, Default)]
+pub struct RetransmitQueue {
+    segments: VecDeque<InFlight>,
+}
+
+impl RetransmitQueue {
+    pub fn new() -> Self {
+        RetransmitQueue { segments: VecDeque::new() }
+    }
+
+    /**
+     * 记录新发出的段, seq 必须紧接在队尾之后
+     */
+    pub fn push(&mut self, seq: u32, data: Vec<u8>) {
+        self.push_at(seq, data, 0);
+    }
+
+    /**
+     * 同 push, 记下发出的时刻供 RACK 使用
+     */
+    pub fn push_at(&mut self, seq: u32, data: Vec<u8>, now_ms: u64) {
+        debug_assert!(self.segments.back().is_none_or(|last| last.end() == seq));
+        self.segments.push_back(InFlight { seq, data, sacked: false, retransmits: 0, sent_ms: now_ms, psh: false, urgent: None });
+    }
+
+    /**
+     * 刚记录的段带了 PSH
+     */
+    pub fn mark_push(&mut self) {
+        if let Some(last) = self.segments.back_mut() {
+            last.psh = true;
+        }
+    }
+
+    /**
+     * 刚记录的段带了 URG 和紧急指针 ptr
+     */
+    pub fn mark_urgent(&mut self, ptr: u16) {
+        if let Some(last) = self.segments.back_mut() {
+            last.urgent = Some(ptr);
+        }
+    }
+
+    /**
+     * 这个 ACK 新送达(累计确认或整段被 SACK)的段中最后发出的一个, 返回 (发出时刻, 序号终点)
+     * 重传过的段无法区分确认的是哪一次发送, 不参与 (RFC 8985 的 Karn 式处理)
+     */
+    pub fn newest_delivered(&self, ack: u32, sack_blocks: &[(u32, u32)]) -> Option<(u64, u32)> {
+        let covered = |segment: &InFlight| {
+            seq_le(segment.end(), ack)
+                || sack_blocks.iter().any(|&(left, right)| seq_le(left, segment.seq) && seq_le(segment.end(), right))
+        };
+        self.segments.iter()
+            .filter(|segment| !segment.sacked && segment.retransmits == 0 && covered(segment))
+            .map(|segment| (segment.sent_ms, segment.end()))
+            .max_by_key(|(sent_ms, _)| *sent_ms) // 同一时刻发出的取序号最大的(max_by_key 返回最后一个)
+    }
+
+    /**
+     * 累计确认到 ack 时是否确认了重传过的段; 这时后面的段在对端等空洞补上, 用它们取样会把等待时间算进 RTT
+     */
+    pub fn acks_retransmitted(&self, ack: u32) -> bool {
+        self.segments.iter().take_while(|segment| seq_lt(segment.seq, ack)).any(|segment| segment.retransmits > 0)
+    }
+
+    /**
+     * 处理 ACK: 释放累计确认的数据(部分确认的段截掉已确认的前缀), 再按 SACK 块标记
+     * 只有整段落在某个块内才标记; 低于 ack 的块(D-SACK)忽略。返回释放的字节数
+     */
+    pub fn ack_received(&mut self, ack: u32, sack_blocks: &[(u32, u32)]) -> usize {
+        let mut released = 0;
+        while let Some(front) = self.segments.front_mut() {
+            if seq_le(front.end(), ack) {
+                released += front.data.len();
+                self.segments.pop_front();
+            } else if seq_lt(front.seq, ack) {
+                let n = ack.wrapping_sub(front.seq) as usize;
+                front.data.drain(..n);
+                front.seq = ack;
+                released += n;
+                break;
+            } else {
+                break;
+            }
+        }
+        for &(left, right) in sack_blocks.iter().filter(|(_, right)| seq_lt(ack, *right)) {
+            for segment in self.segments.iter_mut() {
+                if seq_le(left, segment.seq) && seq_le(segment.end(), right) {
+                    segment.sacked = true;
+                }
+            }
+        }
+        released
+    }
+
+    /**
+     * 第一个未被 SACK 的段, 即 RTO 或快速重传时应该重发的空洞
+     */
+    pub fn first_hole(&self) -> Option<&InFlight> {
+        self.segments.iter().find(|segment| !segment.sacked)
+    }
+
+    /**
+     * 位于最高 SACK 块之前、且本轮恢复中还没重传过的空洞(retransmits 小于 round)
+     * 快速重传时逐个取出, 不会重发对端已有的数据
+     */
+    pub fn lost(&self, round: u32) -> Vec<(u32, u32)> {
+        let Some(highest) = self.segments.iter().rposition(|segment| segment.sacked) else {
+            return Vec::new();
+        };
+        self.segments.iter().take(highest)
+            .filter(|segment| !segment.sacked && segment.retransmits < round)
+            .map(|segment| (segment.seq, segment.end()))
+            .collect()
+    }
+
+    /**
+     * 取出 seq 开始的段准备重传, 计数加一
+     */
+    pub fn retransmit(&mut self, seq: u32) -> Option<&InFlight> {
+        let segment = self.segments.iter_mut().find(|segment| segment.seq == seq)?;
+        segment.retransmits += 1;
+        Some(segment)
+    }
+
+    /**
+     * 同 retransmit, 同时更新发出时刻
+     */
+    pub fn retransmit_at(&mut self, seq: u32, now_ms: u64) -> Option<&InFlight> {
+        let segment = self.segments.iter_mut().find(|segment| segment.seq == seq)?;
+        segment.retransmits += 1;
+        segment.sent_ms = now_ms;
+        Some(segment)
+    }
+
+    /**
+     * RTO 时清除所有 SACK 标记(防止对端食言后永远不重传), 返回应重传的第一个段
+     */
+    pub fn on_rto(&mut self) -> Option<&InFlight> {
+        for segment in self.segments.iter_mut() {
+            segment.sacked = false;
+        }
+        self.segments.front()
+    }
+
+    pub fn snd_una(&self) -> Option<u32> {
+        self.segments.front().map(|segment| segment.seq)
+    }
+
+    pub fn bytes_in_flight(&self) -> usize {
+        self.segments.iter().filter(|segment| !segment.sacked).map(|segment| segment.data.len()).sum()
+    }
+
+    pub fn bytes_queued(&self) -> usize {
+        self.segments.iter().map(|segment| segment.data.len()).sum()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &InFlight> {
+        self.segments.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(start: u32, n: u32) -> RetransmitQueue {
+        let mut queue = RetransmitQueue::new();
+        for i in 0..n {
+            queue.push(start.wrapping_add(i * 100), vec![i as u8; 100]);
+        }
+        queue
+    }
+
+    #[test]
+    fn test_cumulative_and_partial_ack() {
+        let mut queue = queue(u32::MAX - 150, 4); // 跨越序号回绕
+        assert_eq!(queue.ack_received((u32::MAX - 150).wrapping_add(130), &[]), 130);
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.snd_una(), Some((u32::MAX - 150).wrapping_add(130)));
+        assert_eq!(queue.iter().next().unwrap().data.len(), 70);
+        assert_eq!(queue.bytes_queued(), 270);
+    }
+
+    #[test]
+    fn test_sack_marks_and_holes() {
+        let mut queue = queue(1000, 6); // [1000, 1600)
+        queue.ack_received(1000, &[(1100, 1300), (1400, 1550)]);
+        let sacked: Vec<bool> = queue.iter().map(|segment| segment.sacked).collect();
+        assert_eq!(sacked, vec![false, true, true, false, true, false]); // 1500..1600 只被盖住一半
+        assert_eq!(queue.first_hole().unwrap().seq, 1000);
+        assert_eq!(queue.lost(1), vec![(1000, 1100), (1300, 1400)]);
+        assert_eq!(queue.bytes_in_flight(), 300);
+
+        queue.retransmit(1000);
+        assert_eq!(queue.lost(1), vec![(1300, 1400)]);
+        queue.retransmit(1300);
+        assert!(queue.lost(1).is_empty());
+        assert_eq!(queue.lost(2).len(), 2);
+
+        // RTO 清除标记, 从 snd_una 开始
+        assert_eq!(queue.on_rto().unwrap().seq, 1000);
+        assert!(queue.iter().all(|segment| !segment.sacked));
+        assert_eq!(queue.bytes_queued(), 600);
+    }
+
+    #[test]
+    fn test_dsack_below_ack_is_ignored() {
+        let mut queue = queue(0, 3);
+        queue.ack_received(100, &[(0, 100)]);
+        assert!(queue.iter().all(|segment| !segment.sacked));
+    }
+
+    #[test]
+    fn test_ack_covering_retransmitted_segment() {
+        let mut queue = queue(0, 3);
+        queue.retransmit(100);
+        assert!(!queue.acks_retransmitted(100));
+        assert!(queue.acks_retransmitted(150));
+        assert!(queue.acks_retransmitted(300));
+        assert_eq!(queue.newest_delivered(300, &[]).map(|(_, end)| end), Some(300));
+    }
+}
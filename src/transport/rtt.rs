@@ -0,0 +1,78 @@
+use crate::config::TcpConfig;
+
+/**
+ * RTT 估计与 RTO 计算 (RFC 6298), 单位毫秒
+ * 第一个样本之前 RTO 为 rto_initial_ms; 之后为 SRTT + max(1, 4 * RTTVAR), 限制在 [rto_min_ms, rto_max_ms]
+ */
+#[derive(Debug, Clone)]
+pub struct RttEstimator {
+    srtt_ms: Option<u64>,
+    rttvar_ms: u64,
+    initial_ms: u64,
+    min_ms: u64,
+    max_ms: u64,
+}
+
+impl RttEstimator {
+    pub fn new(config: &TcpConfig) -> Self {
+        RttEstimator { srtt_ms: None, rttvar_ms: 0, initial_ms: config.rto_initial_ms, min_ms: config.rto_min_ms, max_ms: config.rto_max_ms }
+    }
+
+    /**
+     * 用以前测得的值代替第一个样本, 之后的样本照常平滑
+     */
+    pub fn seed(&mut self, srtt_ms: u64, rttvar_ms: u64) {
+        self.srtt_ms = Some(srtt_ms);
+        self.rttvar_ms = rttvar_ms;
+    }
+
+    /**
+     * 一个 RTT 样本; 调用方按 Karn 算法不对重传过的段取样
+     */
+    pub fn on_sample(&mut self, rtt_ms: u64) {
+        match self.srtt_ms {
+            None => {
+                self.srtt_ms = Some(rtt_ms);
+                self.rttvar_ms = rtt_ms / 2;
+            }
+            Some(srtt) => {
+                self.rttvar_ms = (3 * self.rttvar_ms + srtt.abs_diff(rtt_ms)) / 4;
+                self.srtt_ms = Some((7 * srtt + rtt_ms) / 8);
+            }
+        }
+    }
+
+    pub fn srtt_ms(&self) -> Option<u64> {
+        self.srtt_ms
+    }
+
+    pub fn rttvar_ms(&self) -> u64 {
+        self.rttvar_ms
+    }
+
+    pub fn rto_ms(&self) -> u64 {
+        match self.srtt_ms {
+            None => self.initial_ms,
+            Some(srtt) => (srtt + (4 * self.rttvar_ms).max(1)).clamp(self.min_ms, self.max_ms),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rto_follows_rfc6298() {
+        let mut rtt = RttEstimator::new(&TcpConfig::default());
+        assert_eq!((rtt.srtt_ms(), rtt.rto_ms()), (None, 1000));
+        rtt.on_sample(100);
+        assert_eq!((rtt.srtt_ms(), rtt.rttvar_ms(), rtt.rto_ms()), (Some(100), 50, 300));
+        rtt.on_sample(180);
+        assert_eq!((rtt.srtt_ms(), rtt.rttvar_ms(), rtt.rto_ms()), (Some(110), 57, 338));
+
+        // 很小的 RTT 受 rto_min_ms 限制
+        rtt.seed(2, 0);
+        assert_eq!(rtt.rto_ms(), 200);
+    }
+}
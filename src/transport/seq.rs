@@ -0,0 +1,159 @@
+use std::fmt;
+use std::ops::{Add, Sub};
+
+/**
+ * 序号空间中的一点 (RFC 793 3.3), 加减与比较都模 2^32 (RFC 1982 序号算术)
+ * 只比较相距不到 2^31 的两点; 恰好相距 2^31 时先后没有定义, lt / le / gt / ge 都为 false
+ * 窗口至多 2^30 (窗口扩大因子 14), 连接中需要比较的序号不会相距这么远
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct WrappingSeq(pub u32);
+
+impl WrappingSeq {
+    /**
+     * self 相对 origin 的有符号距离, self 在 origin 之后为正
+     */
+    pub fn offset_from(self, origin: WrappingSeq) -> i32 {
+        self.0.wrapping_sub(origin.0) as i32
+    }
+
+    pub fn le(self, other: WrappingSeq) -> bool {
+        other.offset_from(self) >= 0
+    }
+
+    pub fn lt(self, other: WrappingSeq) -> bool {
+        self != other && self.le(other)
+    }
+
+    pub fn ge(self, other: WrappingSeq) -> bool {
+        other.le(self)
+    }
+
+    pub fn gt(self, other: WrappingSeq) -> bool {
+        other.lt(self)
+    }
+
+    /**
+     * 落在 [start, end) 内, 区间可以跨过 2^32
+     */
+    pub fn in_window(self, start: WrappingSeq, end: WrappingSeq) -> bool {
+        start.le(self) && self.lt(end)
+    }
+
+    /**
+     * 二者中靠后的一个
+     */
+    pub fn max(self, other: WrappingSeq) -> WrappingSeq {
+        if self.lt(other) { other } else { self }
+    }
+}
+
+impl Add<u32> for WrappingSeq {
+    type Output = WrappingSeq;
+
+    fn add(self, n: u32) -> WrappingSeq {
+        WrappingSeq(self.0.wrapping_add(n))
+    }
+}
+
+/**
+ * 从 rhs 向前数到 self 的字节数; 调用方保证 rhs 不在 self 之后
+ */
+impl Sub for WrappingSeq {
+    type Output = u32;
+
+    fn sub(self, rhs: WrappingSeq) -> u32 {
+        self.0.wrapping_sub(rhs.0)
+    }
+}
+
+impl fmt::Display for WrappingSeq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/**
+ * 报文段字段是裸的 u32, 比较时用这两个简写
+ */
+pub(crate) fn seq_le(a: u32, b: u32) -> bool {
+    WrappingSeq(a).le(WrappingSeq(b))
+}
+
+pub(crate) fn seq_lt(a: u32, b: u32) -> bool {
+    WrappingSeq(a).lt(WrappingSeq(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::rng::SimRng;
+
+    const HALF: u32 = 0x8000_0000;
+
+    fn seq(n: u32) -> WrappingSeq {
+        WrappingSeq(n)
+    }
+
+    #[test]
+    fn test_order_across_wrap() {
+        assert!(seq(u32::MAX).lt(seq(0)));
+        assert!(seq(u32::MAX).le(seq(0)));
+        assert!(seq(0).gt(seq(u32::MAX)));
+        assert!(seq(0xffff_ff00).lt(seq(0x0000_0100)));
+        assert!(!seq(0x0000_0100).lt(seq(0xffff_ff00)));
+        assert!(seq(7).le(seq(7)) && seq(7).ge(seq(7)) && !seq(7).lt(seq(7)) && !seq(7).gt(seq(7)));
+        assert_eq!(seq(u32::MAX) + 1, seq(0));
+        assert_eq!(seq(0x10) - seq(0xffff_fff0), 0x20);
+        assert_eq!(seq(0x10).offset_from(seq(0xffff_fff0)), 0x20);
+        assert_eq!(seq(0xffff_fff0).offset_from(seq(0x10)), -0x20);
+        assert_eq!(seq(u32::MAX).max(seq(3)), seq(3));
+    }
+
+    #[test]
+    fn test_half_space_boundary() {
+        // 相距 2^31 - 1: 仍然有先后
+        let a = seq(0xffff_ff00);
+        let b = a + (HALF - 1);
+        assert!(a.lt(b) && b.gt(a) && !b.lt(a) && !a.gt(b));
+        assert_eq!(b.offset_from(a), i32::MAX);
+
+        // 相距恰好 2^31: 双向都不成立, 也不相等
+        let c = a + HALF;
+        assert!(!a.lt(c) && !c.lt(a) && !a.le(c) && !c.le(a));
+        assert!(!a.gt(c) && !c.gt(a) && !a.ge(c) && !c.ge(a));
+        assert_eq!(c.offset_from(a), i32::MIN);
+
+        // 相距 2^31 + 1: 反过来, 后加的点在前
+        let d = a + (HALF + 1);
+        assert!(d.lt(a) && a.gt(d));
+    }
+
+    #[test]
+    fn test_window_straddling_wrap() {
+        let (start, end) = (seq(0xffff_fff0), seq(0x10));
+        assert!(seq(0xffff_fff0).in_window(start, end));
+        assert!(seq(u32::MAX).in_window(start, end));
+        assert!(seq(0).in_window(start, end));
+        assert!(seq(0xf).in_window(start, end));
+        assert!(!seq(0x10).in_window(start, end));
+        assert!(!seq(0xffff_ffef).in_window(start, end));
+        assert!(!seq(0).in_window(seq(0), seq(0)));
+    }
+
+    #[test]
+    fn test_random_pairs_are_consistent() {
+        let mut rng = SimRng::new(733);
+        for _ in 0..10_000 {
+            // 起点集中在回绕点附近, 距离覆盖整个半空间
+            let a = seq(0xffff_ff00u32.wrapping_add(rng.below(0x200) as u32));
+            let d = 1 + rng.below(HALF as u64 - 1) as u32;
+            let b = a + d;
+            assert!(a.lt(b) && b.gt(a) && !b.le(a) && !a.ge(b), "{} {}", a, b);
+            assert_eq!(b - a, d);
+            assert_eq!(seq_lt(a.0, b.0), a.lt(b));
+            assert_eq!(seq_le(b.0, a.0), b.le(a));
+            assert!(a.in_window(a, b) && !b.in_window(a, b));
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+use super::connection_table::ConnectionTable;
+use super::tcp_connection::ConnectionId;
+use super::tcp_segment::TcpSegment;
+
+/**
+ * 共享连接表上的操作错误
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollError {
+    Reentrant, // 在 poll 触发的回调里再次访问连接表
+}
+
+impl fmt::Display for PollError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PollError::Reentrant => write!(f, "connection table is already being polled"),
+        }
+    }
+}
+
+impl Error for PollError {}
+
+/**
+ * 可以克隆进就绪回调的连接表, 接入已有的事件循环时使用
+ * 回调在 poll 之中执行, 这时再 poll 或调用其他操作得到 PollError::Reentrant, 而不是 RefCell 二次借用的 panic;
+ * 回调应当记下要做的事, 等 poll 返回之后再做
+ */
+#[derive(Clone)]
+pub struct SharedTable {
+    inner: Rc<RefCell<ConnectionTable>>,
+}
+
+impl SharedTable {
+    pub fn new(table: ConnectionTable) -> Self {
+        SharedTable { inner: Rc::new(RefCell::new(table)) }
+    }
+
+    /**
+     * 见 ConnectionTable::poll
+     */
+    pub fn poll(&self, now_ms: u64) -> Result<Vec<(ConnectionId, TcpSegment)>, PollError> {
+        self.with(|table| table.poll(now_ms))
+    }
+
+    /**
+     * 在连接表上执行 f, 连接表正被使用 (f 或 poll 的回调里) 时返回 Reentrant
+     */
+    pub fn with<R>(&self, f: impl FnOnce(&mut ConnectionTable) -> R) -> Result<R, PollError> {
+        let mut table = self.inner.try_borrow_mut().map_err(|_| PollError::Reentrant)?;
+        Ok(f(&mut table))
+    }
+}
+
+impl fmt::Debug for SharedTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedTable").field("busy", &self.inner.try_borrow_mut().is_err()).finish()
+    }
+}
@@ -0,0 +1,89 @@
+use crate::config::Ipv4Config;
+use crate::net::dscp::Dscp;
+use crate::net::ipv4::Ipv4Datagram;
+use crate::utils::wire::WireSerialize;
+
+use super::tcp_segment::TcpSegment;
+
+const PROTOCOL_TCP: u8 = 6;
+
+/**
+ * 保活参数: 空闲 idle_ms 之后开始探测, 每 interval_ms 一次, probes 次无应答即放弃
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepaliveParams {
+    pub idle_ms: u64,
+    pub interval_ms: u64,
+    pub probes: u32,
+}
+
+/**
+ * 队列深度的水位: 达到 high 进入高水位, 回落到 low 及以下才离开, 两者之间保持原状态, 不会反复切换
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watermarks {
+    pub low: usize,
+    pub high: usize,
+}
+
+impl Watermarks {
+    pub fn new(low: usize, high: usize) -> Self {
+        Watermarks { low: low.min(high), high }
+    }
+
+    /**
+     * 按当前深度和之前的状态得到是否处于高水位
+     */
+    pub fn above(&self, depth: usize, was_above: bool) -> bool {
+        if depth >= self.high {
+            true
+        } else if depth <= self.low {
+            false
+        } else {
+            was_above
+        }
+    }
+}
+
+/**
+ * 监听端口的选项, listen_with_options 时生效, 之后到达的 SYN 按它处理
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ListenOptions {
+    pub fast_open: bool, // 接受 TCP Fast Open (RFC 7413): 对请求签发 cookie, cookie 有效的 SYN 捎带的数据在握手完成前交给应用
+}
+
+/**
+ * 连接级的套接字选项, connect 之前和之后都可以设置, 之后发出的报文(包括重传)立即生效
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SocketOptions {
+    pub ttl: Option<u8>,                     // None 时使用 Ipv4Config::default_ttl
+    pub tos: u8,
+    pub keepalive: Option<KeepaliveParams>, // None 时不覆盖全局配置
+    pub send_watermarks: Option<Watermarks>, // 未发出与未确认的字节, 高水位时不报告 WRITABLE
+    pub recv_watermarks: Option<Watermarks>, // 未读的字节, 高水位时报告 READ_HIGH
+    pub nagle: bool,                         // RFC 896: 有未确认的数据时扣留不满 MSS 的段, 直到确认或 flush
+}
+
+impl SocketOptions {
+    pub fn dscp(&self) -> Dscp {
+        Dscp::from_tos(self.tos)
+    }
+
+    /**
+     * 只改码点, 保留 tos 中的 ECN 位
+     */
+    pub fn set_dscp(&mut self, dscp: Dscp) {
+        self.tos = dscp.apply_to(self.tos);
+    }
+
+    /**
+     * 把一个报文段封装成 IP 数据报, 首部的 TTL 与 TOS 取自选项
+     */
+    pub fn datagram(&self, s_addr: u32, d_addr: u32, id: u16, segment: &TcpSegment, config: &Ipv4Config) -> Ipv4Datagram {
+        let payload = segment.serialize();
+        Ipv4Datagram::new(4, 5, self.tos, (20 + payload.len()) as u16, id, 0b010, 0, self.ttl.unwrap_or(config.default_ttl),
+            PROTOCOL_TCP, s_addr, d_addr, payload)
+    }
+}
@@ -0,0 +1,78 @@
+use std::io::{self, IoSlice};
+
+use super::connection_table::{ConnectionTable, Readiness};
+use super::tcp_connection::{ConnectionError, ConnectionId};
+
+/**
+ * 连接表中一个连接的 std::io 视图, 非阻塞
+ * 暂时没有数据可读或发送缓冲区已满时返回 WouldBlock; 读到对端的 FIN 后 read 返回 0
+ */
+pub struct Stream<'a> {
+    table: &'a mut ConnectionTable,
+    id: ConnectionId,
+}
+
+impl<'a> Stream<'a> {
+    pub fn new(table: &'a mut ConnectionTable, id: ConnectionId) -> Self {
+        Stream { table, id }
+    }
+
+    pub fn id(&self) -> ConnectionId {
+        self.id
+    }
+}
+
+impl io::Read for Stream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.table.read(self.id, buf.len()).map_err(io_error)?;
+        if data.is_empty() && !buf.is_empty() {
+            // 没有未读数据时 READABLE 只可能来自 EOF
+            return if self.table.readiness_of(self.id).contains(Readiness::READABLE) {
+                Ok(0)
+            } else {
+                Err(io::ErrorKind::WouldBlock.into())
+            };
+        }
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+}
+
+impl io::Write for Stream<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_vectored(&[IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let slices: Vec<&[u8]> = bufs.iter().map(|buf| &**buf).collect();
+        match self.table.write_vectored(self.id, &slices).map_err(io_error)? {
+            0 if slices.iter().any(|slice| !slice.is_empty()) => Err(io::ErrorKind::WouldBlock.into()),
+            n => Ok(n),
+        }
+    }
+
+    /**
+     * 数据由 poll_transmit 发出; flush 之后 Nagle 不再扣留已写入的数据
+     */
+    fn flush(&mut self) -> io::Result<()> {
+        self.table.flush(self.id).map_err(io_error)
+    }
+}
+
+pub(crate) fn io_error(e: ConnectionError) -> io::Error {
+    let kind = match e {
+        ConnectionError::NotConnected => io::ErrorKind::NotConnected,
+        ConnectionError::Refused => io::ErrorKind::ConnectionRefused,
+        ConnectionError::Reset => io::ErrorKind::ConnectionReset,
+        ConnectionError::TimedOut => io::ErrorKind::TimedOut,
+        ConnectionError::Closed => io::ErrorKind::BrokenPipe,
+        ConnectionError::HostUnreachable => io::ErrorKind::HostUnreachable,
+        ConnectionError::IdleTimeout => io::ErrorKind::TimedOut,
+        ConnectionError::NetworkDown => io::ErrorKind::NetworkDown,
+        ConnectionError::ReadFailed(kind) => kind,
+        ConnectionError::Unreachable(3) => io::ErrorKind::ConnectionRefused,
+        ConnectionError::Unreachable(0 | 6) => io::ErrorKind::NetworkUnreachable,
+        ConnectionError::Unreachable(_) => io::ErrorKind::HostUnreachable,
+    };
+    io::Error::new(kind, e)
+}
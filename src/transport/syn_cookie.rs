@@ -0,0 +1,473 @@
+use std::collections::HashMap;
+
+use crate::config::TcpConfig;
+use crate::utils::siphash::{random_key, siphash24};
+
+use super::tcp_connection::ConnectionId;
+
+/**
+ * cookie 中 3 位 MSS 索引对应的取值, 编码时向下取整
+ */
+const MSS_TABLE: [u16; 8] = [536, 1024, 1220, 1300, 1360, 1400, 1440, 1460];
+const COUNTER_PERIOD_MS: u64 = 64_000; // 计数器每 64 秒加一
+const MAX_COUNTER_AGE: u32 = 2;        // 最多接受 2 个周期之前发出的 cookie
+pub const SECRET_ROTATE_MS: u64 = 5 * 60_000;
+const HALF_OPEN_TIMEOUT_MS: u64 = 75_000;
+
+/**
+ * SYN cookie: 把连接参数编码进 SYN-ACK 的 ISN, 服务端不用为半连接保存状态
+ * ISN = 计数器低 5 位 | MSS 索引 3 位 | SipHash-2-4(密钥, 四元组, 客户端 ISN, 计数器) 的低 24 位
+ * 密钥按 SECRET_ROTATE_MS 轮换, 上一个密钥签发的 cookie 仍然接受
+ */
+pub struct SynCookies {
+    secrets: [[u64; 2]; 2], // [当前, 上一个]
+    rotated_at_ms: u64,
+}
+
+impl SynCookies {
+    pub fn new(now_ms: u64) -> Self {
+        SynCookies { secrets: [random_key(), random_key()], rotated_at_ms: now_ms }
+    }
+
+    /**
+     * 到期则轮换密钥, 返回是否轮换
+     */
+    pub fn rotate_if_due(&mut self, now_ms: u64) -> bool {
+        if now_ms < self.rotated_at_ms + SECRET_ROTATE_MS {
+            return false;
+        }
+        self.secrets = [random_key(), self.secrets[0]];
+        self.rotated_at_ms = now_ms;
+        true
+    }
+
+    /**
+     * 为 SYN 生成作为 ISN 的 cookie, 对端 MSS 向下取到表中的值
+     */
+    pub fn generate(&self, id: &ConnectionId, client_isn: u32, peer_mss: u16, now_ms: u64) -> u32 {
+        let counter = (now_ms / COUNTER_PERIOD_MS) as u32 & 0x1f;
+        let mss_index = MSS_TABLE.iter().rposition(|&mss| mss <= peer_mss).unwrap_or(0) as u32;
+        (counter << 27) | (mss_index << 24) | Self::hash(self.secrets[0], id, client_isn, counter)
+    }
+
+    /**
+     * 校验最后一个 ACK 带回的 cookie (ack - 1), 通过时返回编码的 MSS
+     */
+    pub fn validate(&self, id: &ConnectionId, client_isn: u32, cookie: u32, now_ms: u64) -> Option<u16> {
+        let counter = cookie >> 27;
+        let now_counter = (now_ms / COUNTER_PERIOD_MS) as u32 & 0x1f;
+        if now_counter.wrapping_sub(counter) & 0x1f > MAX_COUNTER_AGE {
+            return None;
+        }
+        let hash = cookie & 0x00ff_ffff;
+        if self.secrets.iter().any(|&secret| Self::hash(secret, id, client_isn, counter) == hash) {
+            Some(MSS_TABLE[((cookie >> 24) & 0x7) as usize])
+        } else {
+            None
+        }
+    }
+
+    fn hash(secret: [u64; 2], id: &ConnectionId, client_isn: u32, counter: u32) -> u32 {
+        let mut input = [0u8; 20];
+        input[..12].copy_from_slice(&id.to_bytes());
+        input[12..16].copy_from_slice(&client_isn.to_be_bytes());
+        input[16..].copy_from_slice(&counter.to_be_bytes());
+        siphash24(secret, &input) as u32 & 0x00ff_ffff
+    }
+}
+
+/**
+ * 收到 SYN 后的动作
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SynAction {
+    SynAck { isn: u32, mss: u16, cookie: bool },
+    Drop,
+    Reset, // 回复 RST
+}
+
+/**
+ * 接受过滤器的结果
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcceptDecision {
+    Accept,
+    Drop,   // 静默丢弃
+    Reject, // 回复 RST
+}
+
+pub type AcceptFilter = Box<dyn Fn(&ConnectionId) -> AcceptDecision>;
+
+/**
+ * 按源地址的 SYN 令牌桶, 容量为一秒的额度
+ * 令牌以千分之一个为单位
+ */
+struct SourceLimiter {
+    per_sec: u64,
+    buckets: HashMap<u32, (u64, u64)>, // 源地址 -> (令牌, 上次补充的时刻)
+}
+
+impl SourceLimiter {
+    fn allow(&mut self, source: u32, now_ms: u64) -> bool {
+        let full = self.per_sec * 1000;
+        let (tokens, last_ms) = self.buckets.entry(source).or_insert((full, now_ms));
+        *tokens = (*tokens + now_ms.saturating_sub(*last_ms) * self.per_sec).min(full);
+        *last_ms = now_ms.max(*last_ms);
+        if *tokens >= 1000 {
+            *tokens -= 1000;
+            true
+        } else {
+            false
+        }
+    }
+
+    /**
+     * 已经补满的桶和新建时一样, 可以删掉
+     */
+    fn prune(&mut self, now_ms: u64) {
+        let per_sec = self.per_sec;
+        self.buckets.retain(|_, (tokens, last_ms)| *tokens + now_ms.saturating_sub(*last_ms) * per_sec < per_sec * 1000);
+    }
+}
+
+/**
+ * 三次握手完成, 可以交给 accept
+ * state 为收到 SYN 时随半连接保存的内容, 经 cookie 完成的握手没有
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Accepted<T = ()> {
+    pub id: ConnectionId,
+    pub client_isn: u32,
+    pub server_isn: u32,
+    pub mss: u16,
+    pub via_cookie: bool,
+    pub state: Option<T>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BacklogStats {
+    pub cookies_sent: u64,
+    pub cookies_accepted: u64,
+    pub cookies_rejected: u64,
+    pub dropped: u64,
+    pub filtered: u64,     // 被接受过滤器丢弃或拒绝
+    pub rate_limited: u64, // 超过单个源地址的 SYN 速率
+    pub accept_overflows: u64, // accept 队列已满时丢弃的 SYN 和握手 ACK
+}
+
+struct HalfOpen<T> {
+    client_isn: u32,
+    server_isn: u32,
+    mss: u16,
+    at_ms: u64,
+    state: T,
+}
+
+/**
+ * 监听端口的半连接队列, 容量为 syn_backlog
+ * 队列满时: 开启 syn_cookies 则回复 cookie 且不保存状态, 否则丢弃 SYN
+ * 新的 SYN 先经过接受过滤器和按源地址的限速, 通过后才占用队列或签发 cookie
+ * 每个半连接可以附带调用方的状态 T, 握手完成时随 Accepted 交回
+ */
+pub struct SynBacklog<T = ()> {
+    capacity: usize,
+    pending: HashMap<ConnectionId, HalfOpen<T>>,
+    cookies: Option<SynCookies>,
+    local_mss: u16,
+    filter: Option<AcceptFilter>,
+    limiter: Option<SourceLimiter>,
+    stats: BacklogStats,
+}
+
+impl<T> SynBacklog<T> {
+    pub fn new(config: &TcpConfig, now_ms: u64) -> Self {
+        SynBacklog {
+            capacity: config.syn_backlog,
+            pending: HashMap::new(),
+            cookies: config.syn_cookies.then(|| SynCookies::new(now_ms)),
+            local_mss: config.mss,
+            filter: None,
+            limiter: config.max_syn_per_source_per_sec.map(|per_sec| SourceLimiter { per_sec: per_sec as u64, buckets: HashMap::new() }),
+            stats: BacklogStats::default(),
+        }
+    }
+
+    /**
+     * 收到新 SYN 时调用, 决定接受、静默丢弃还是回复 RST
+     */
+    pub fn set_accept_filter(&mut self, filter: AcceptFilter) {
+        self.filter = Some(filter);
+    }
+
+    /**
+     * 新的 SYN: server_isn 为放入半连接队列时使用的 ISN, state 只在放入队列时求值
+     */
+    pub fn on_syn(&mut self, id: ConnectionId, client_isn: u32, peer_mss: u16, server_isn: u32, now_ms: u64,
+        state: impl FnOnce() -> T) -> SynAction {
+        let mss = peer_mss.min(self.local_mss);
+        if let Some(half_open) = self.pending.get(&id) {
+            // 重传的 SYN, 回复同一个 ISN
+            return SynAction::SynAck { isn: half_open.server_isn, mss: half_open.mss, cookie: false };
+        }
+        match self.filter.as_ref().map_or(AcceptDecision::Accept, |filter| filter(&id)) {
+            AcceptDecision::Accept => {}
+            AcceptDecision::Drop => {
+                self.stats.filtered += 1;
+                return SynAction::Drop;
+            }
+            AcceptDecision::Reject => {
+                self.stats.filtered += 1;
+                return SynAction::Reset;
+            }
+        }
+        if let Some(limiter) = self.limiter.as_mut() {
+            if !limiter.allow(id.d_ip, now_ms) {
+                self.stats.rate_limited += 1;
+                return SynAction::Drop;
+            }
+        }
+        if self.pending.len() < self.capacity {
+            self.pending.insert(id, HalfOpen { client_isn, server_isn, mss, at_ms: now_ms, state: state() });
+            return SynAction::SynAck { isn: server_isn, mss, cookie: false };
+        }
+        match &self.cookies {
+            Some(cookies) => {
+                self.stats.cookies_sent += 1;
+                let isn = cookies.generate(&id, client_isn, mss, now_ms);
+                SynAction::SynAck { isn, mss: MSS_TABLE[((isn >> 24) & 0x7) as usize], cookie: true }
+            }
+            None => {
+                self.stats.dropped += 1;
+                SynAction::Drop
+            }
+        }
+    }
+
+    /**
+     * 握手的最后一个 ACK: 先查半连接队列, 没有则按 cookie 校验
+     * 队列中的半连接记着客户端 ISN, 只校验确认号, 先于 ACK 到达的后续数据段也能完成握手;
+     * cookie 只能从 ACK 的序号推出客户端 ISN, 要求它是 SYN 之后的第一个段
+     */
+    pub fn on_ack(&mut self, id: ConnectionId, seq: u32, ack: u32, now_ms: u64) -> Option<Accepted<T>> {
+        let server_isn = ack.wrapping_sub(1);
+        if let Some(half_open) = self.pending.get(&id) {
+            if half_open.server_isn != server_isn {
+                return None;
+            }
+            let half_open = self.pending.remove(&id)?;
+            return Some(Accepted {
+                id, client_isn: half_open.client_isn, server_isn, mss: half_open.mss, via_cookie: false, state: Some(half_open.state),
+            });
+        }
+        let client_isn = seq.wrapping_sub(1);
+        let cookies = self.cookies.as_ref()?;
+        match cookies.validate(&id, client_isn, server_isn, now_ms) {
+            Some(mss) => {
+                self.stats.cookies_accepted += 1;
+                Some(Accepted { id, client_isn, server_isn, mss, via_cookie: true, state: None })
+            }
+            None => {
+                self.stats.cookies_rejected += 1;
+                None
+            }
+        }
+    }
+
+    /**
+     * 清理超时的半连接并按时轮换 cookie 密钥
+     */
+    pub fn tick(&mut self, now_ms: u64) {
+        self.pending.retain(|_, half_open| now_ms < half_open.at_ms + HALF_OPEN_TIMEOUT_MS);
+        if let Some(cookies) = self.cookies.as_mut() {
+            cookies.rotate_if_due(now_ms);
+        }
+        if let Some(limiter) = self.limiter.as_mut() {
+            limiter.prune(now_ms);
+        }
+    }
+
+    /**
+     * 对端用 RST 放弃了半连接 (RFC 793 3.4: SynReceived 收到 RST 回到 Listen)
+     */
+    pub fn forget(&mut self, id: &ConnectionId) -> bool {
+        self.pending.remove(id).is_some()
+    }
+
+    /**
+     * 半连接附带的状态, 不在队列中(包括只签发了 cookie)时为 None
+     */
+    pub fn half_open(&self, id: &ConnectionId) -> Option<&T> {
+        self.pending.get(id).map(|half_open| &half_open.state)
+    }
+
+    pub fn half_open_mut(&mut self, id: &ConnectionId) -> Option<&mut T> {
+        self.pending.get_mut(id).map(|half_open| &mut half_open.state)
+    }
+
+    /**
+     * accept 队列满, 新的 SYN 或握手 ACK 被丢弃
+     */
+    pub fn accept_queue_full(&mut self) {
+        self.stats.accept_overflows += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn stats(&self) -> BacklogStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::rng::SimRng;
+
+    const SERVER: u32 = 0x0a000001;
+
+    fn id(client: u32, port: u16) -> ConnectionId {
+        ConnectionId { s_ip: SERVER, s_port: 80, d_ip: client, d_port: port }
+    }
+
+    fn backlog(cookies: bool) -> SynBacklog {
+        let config = TcpConfig { syn_backlog: 16, syn_cookies: cookies, ..TcpConfig::default() };
+        SynBacklog::new(&config, 0)
+    }
+
+    /**
+     * 放入队列时的 ISN 由连接表的 IsnGenerator 给出, 这里取一个与客户端 ISN 无关的值
+     */
+    fn syn(backlog: &mut SynBacklog, id: ConnectionId, client_isn: u32, peer_mss: u16, now_ms: u64) -> SynAction {
+        backlog.on_syn(id, client_isn, peer_mss, client_isn ^ 0x5a5a_0000, now_ms, || ())
+    }
+
+    #[test]
+    fn test_flood_stays_bounded_and_cookie_handshake_completes() {
+        let mut backlog = backlog(true);
+        let mut rng = SimRng::new(7);
+        for _ in 0..1000 {
+            let spoofed = id(rng.next_u64() as u32, rng.below(65536) as u16);
+            assert!(matches!(syn(&mut backlog, spoofed, rng.next_u64() as u32, 1460, 10), SynAction::SynAck { .. }));
+        }
+        assert_eq!(backlog.len(), 16);
+        assert_eq!(backlog.stats().cookies_sent, 984);
+
+        let client = id(0xc0a80002, 51000);
+        let SynAction::SynAck { isn, mss, cookie } = syn(&mut backlog, client, 1000, 1400, 20) else { panic!() };
+        assert!(cookie);
+        assert_eq!(mss, 1400);
+        assert_eq!(backlog.len(), 16);
+
+        let accepted = backlog.on_ack(client, 1001, isn.wrapping_add(1), 30).unwrap();
+        assert_eq!(accepted, Accepted { id: client, client_isn: 1000, server_isn: isn, mss: 1400, via_cookie: true, state: None });
+
+        // 伪造的 ACK 和换了四元组的 ACK 都不通过
+        assert_eq!(backlog.on_ack(client, 1001, isn.wrapping_add(2), 30), None);
+        assert_eq!(backlog.on_ack(id(0xc0a80003, 51000), 1001, isn.wrapping_add(1), 30), None);
+        assert_eq!(backlog.stats().cookies_rejected, 2);
+    }
+
+    #[test]
+    fn test_queued_handshake_and_drop_without_cookies() {
+        let mut backlog = backlog(false);
+        let client = id(0xc0a80002, 51000);
+        let SynAction::SynAck { isn, cookie, .. } = syn(&mut backlog, client, 5, 1460, 0) else { panic!() };
+        assert!(!cookie);
+        assert_eq!(syn(&mut backlog, client, 5, 1460, 1), SynAction::SynAck { isn, mss: 1460, cookie: false });
+        for port in 0..15 {
+            syn(&mut backlog, id(1, port), 0, 1460, 0);
+        }
+        assert_eq!(syn(&mut backlog, id(2, 2), 0, 1460, 0), SynAction::Drop);
+        assert_eq!(backlog.stats().dropped, 1);
+
+        assert_eq!(backlog.on_ack(client, 6, isn.wrapping_add(1), 2).map(|a| a.via_cookie), Some(false));
+        assert_eq!(backlog.len(), 15);
+        backlog.tick(HALF_OPEN_TIMEOUT_MS);
+        assert!(backlog.is_empty());
+    }
+
+    #[test]
+    fn test_accept_filter_runs_before_cookies() {
+        let mut backlog = backlog(true);
+        backlog.set_accept_filter(Box::new(|id: &ConnectionId| match id.d_ip {
+            0x0a0000ff => AcceptDecision::Reject,
+            0x0a0000fe => AcceptDecision::Drop,
+            _ => AcceptDecision::Accept,
+        }));
+        for port in 0..20 {
+            assert!(matches!(syn(&mut backlog, id(0x0a000001, port), 0, 1460, 0), SynAction::SynAck { .. }));
+            assert_eq!(syn(&mut backlog, id(0x0a0000ff, port), 0, 1460, 0), SynAction::Reset);
+            assert_eq!(syn(&mut backlog, id(0x0a0000fe, port), 0, 1460, 0), SynAction::Drop);
+        }
+        // 队列满之后签发的 cookie 只给通过过滤器的源
+        let stats = backlog.stats();
+        assert_eq!((stats.filtered, stats.cookies_sent, stats.dropped), (40, 4, 0));
+        assert_eq!(backlog.len(), 16);
+    }
+
+    #[test]
+    fn test_per_source_rate_limit() {
+        let config = TcpConfig { max_syn_per_source_per_sec: Some(5), ..TcpConfig::default() };
+        let mut backlog = SynBacklog::new(&config, 0);
+        let mut completed = HashMap::new();
+        for ms in (0..2000).step_by(10) {
+            // 攻击者每 10ms 发一个新连接, 正常客户端每 500ms 一个
+            let sources: &[u32] = if ms % 500 == 0 { &[0xc0a80066, 0xc0a80002] } else { &[0xc0a80066] };
+            for &source in sources {
+                let client = id(source, ms as u16);
+                if let SynAction::SynAck { isn, .. } = syn(&mut backlog, client, 9, 1460, ms) {
+                    if backlog.on_ack(client, 10, isn.wrapping_add(1), ms).is_some() {
+                        *completed.entry(source).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+        // 一秒的突发额度, 之后 1.99 秒内每秒补充 5 个
+        assert_eq!(completed[&0xc0a80066], 5 + 9);
+        assert_eq!(completed[&0xc0a80002], 4);
+        assert_eq!(backlog.stats().rate_limited, 200 - 14);
+
+        backlog.tick(1_000_000);
+        assert!(backlog.limiter.as_ref().unwrap().buckets.is_empty());
+    }
+
+    #[test]
+    fn test_secret_rotation_and_cookie_age() {
+        let mut cookies = SynCookies::new(0);
+        let client = id(0xc0a80002, 51000);
+        let cookie = cookies.generate(&client, 77, 1460, 0);
+        assert_eq!(cookies.validate(&client, 77, cookie, 1000), Some(1460));
+        assert_eq!(cookies.validate(&client, 78, cookie, 1000), None);
+
+        // 计数器超过 2 个周期即过期
+        assert_eq!(cookies.validate(&client, 77, cookie, 3 * COUNTER_PERIOD_MS), None);
+
+        assert!(cookies.rotate_if_due(SECRET_ROTATE_MS));
+        let fresh = cookies.generate(&client, 77, 536, SECRET_ROTATE_MS);
+        assert_eq!(cookies.validate(&client, 77, fresh, SECRET_ROTATE_MS), Some(536));
+        let old = SynCookies { secrets: [cookies.secrets[1], [0, 0]], rotated_at_ms: 0 };
+        let before = old.generate(&client, 77, 1460, SECRET_ROTATE_MS);
+        assert_eq!(cookies.validate(&client, 77, before, SECRET_ROTATE_MS), Some(1460)); // 上一个密钥仍然有效
+        assert!(!cookies.rotate_if_due(SECRET_ROTATE_MS + 1));
+        assert!(cookies.rotate_if_due(2 * SECRET_ROTATE_MS));
+        let stale = old.generate(&client, 77, 1460, 2 * SECRET_ROTATE_MS);
+        assert_eq!(cookies.validate(&client, 77, stale, 2 * SECRET_ROTATE_MS), None); // 两次轮换之前的密钥失效
+    }
+
+    #[test]
+    fn test_cookie_stable_for_fixed_secret() {
+        // 散列是 SipHash-2-4, 同一密钥和输入在任何版本的工具链上都得到同一个 cookie
+        let cookies = SynCookies { secrets: [[1, 2], [3, 4]], rotated_at_ms: 0 };
+        let cookie = cookies.generate(&id(0xc0a80002, 51000), 77, 1460, 70_000);
+        assert_eq!(cookie, 0x0f94_a0b9);
+        assert_eq!(cookie >> 27, 1);
+        assert_eq!((cookie >> 24) & 0x7, 7);
+    }
+}
@@ -1,32 +1,2734 @@
-use super::tcp_segment;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
 
-struct TcpConnection {
+use crate::config::{Ipv4Config, LossDetection, TcpConfig};
+use crate::net::dscp::Dscp;
+use crate::net::ipv4::Ipv4Datagram;
+use crate::utils::checksum::ChecksumPolicy;
+use crate::utils::drops::DropReason;
+use crate::utils::memory::{MemoryBudget, MemoryComponent};
+use crate::utils::trace::{SegmentVerdict, SharedTraceSink, Tracer};
+
+use super::ack_batch::{AckBatch, AckSummary};
+use super::destination_cache::DestinationMetrics;
+use super::fast_open::{fast_open_words, TfoDecision};
+use super::md5_signature;
+use super::pacing::Pacer;
+use super::rack::Rack;
+use super::rcvbuf_tune::RcvBufTuner;
+use super::rtt::RttEstimator;
+use super::socket_options::SocketOptions;
+use super::retransmit_queue::RetransmitQueue;
+use super::seq::{seq_le, seq_lt};
+use super::tcp_option::{NegotiatedOptions, SynOffer, TcpOption};
+use super::tcp_receiver::{ReceiveOutcome, ReceiverSnapshot, TcpReceiver};
+use super::tcp_segment::{TcpFlags, TcpSegment};
+use super::window_update::WindowUpdateTimer;
+
+/**
+ * 连接层面的错误
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionError {
+    NotConnected,
+    Refused,
+    Reset,
+    TimedOut,
+    Closed,
+    HostUnreachable,
+    IdleTimeout,
+    NetworkDown,
+    ReadFailed(io::ErrorKind), // send_from 的数据源读取失败, 连接本身不受影响
+    Unreachable(u8),            // 握手中收到 ICMP 目的不可达, 带不可达代码
+}
+
+impl fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionError::NotConnected => write!(f, "connection is not established"),
+            ConnectionError::Refused => write!(f, "connection refused by peer"),
+            ConnectionError::Reset => write!(f, "connection reset by peer"),
+            ConnectionError::TimedOut => write!(f, "connection timed out"),
+            ConnectionError::Closed => write!(f, "connection already closed"),
+            ConnectionError::HostUnreachable => write!(f, "no route to host"),
+            ConnectionError::IdleTimeout => write!(f, "connection idle timeout expired"),
+            ConnectionError::NetworkDown => write!(f, "network link is down"),
+            ConnectionError::ReadFailed(kind) => write!(f, "reading the send source failed: {}", kind),
+            ConnectionError::Unreachable(code) => write!(f, "destination unreachable (ICMP code {})", code),
+        }
+    }
+}
+
+impl Error for ConnectionError {}
+
+/**
+ * 空闲超时到期后的处理方式
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdleAction {
+    #[default]
+    Close, // 正常关闭, 已写入的数据照常发完
+    Abort, // 丢弃未发的数据并向对端发 RST
+}
+
+/**
+ * 连接标识: 本端与对端的四元组
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConnectionId {
+    pub s_ip: u32,
+    pub s_port: u16,
+    pub d_ip: u32,
+    pub d_port: u16,
+}
+
+impl ConnectionId {
+    /**
+     * 收到的报文段所属连接的标识, 以本端为 s
+     * 回环连接的两端都在同一张表里, 彼此的标识正好互为 reversed
+     */
+    pub fn for_incoming(s_addr: u32, d_addr: u32, segment: &TcpSegment) -> Self {
+        ConnectionId { s_ip: d_addr, s_port: segment.d_port, d_ip: s_addr, d_port: segment.s_port }
+    }
+
+    pub fn reversed(&self) -> Self {
+        ConnectionId { s_ip: self.d_ip, s_port: self.d_port, d_ip: self.s_ip, d_port: self.s_port }
+    }
+
+    /**
+     * 四元组按 s_ip, s_port, d_ip, d_port 的网络字节序拼成 12 字节, 作为带密钥散列的输入
+     */
+    pub fn to_bytes(&self) -> [u8; 12] {
+        let mut bytes = [0u8; 12];
+        bytes[0..4].copy_from_slice(&self.s_ip.to_be_bytes());
+        bytes[4..6].copy_from_slice(&self.s_port.to_be_bytes());
+        bytes[6..10].copy_from_slice(&self.d_ip.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.d_port.to_be_bytes());
+        bytes
+    }
+}
+
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d] = self.s_ip.to_be_bytes();
+        let [e, g, h, i] = self.d_ip.to_be_bytes();
+        write!(f, "{}.{}.{}.{}:{} -> {}.{}.{}.{}:{}", a, b, c, d, self.s_port, e, g, h, i, self.d_port)
+    }
+}
+
+/**
+ * RFC 793 中的连接状态
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TcpState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+impl TcpState {
+    pub const ALL: [TcpState; 11] = [
+        TcpState::Closed,
+        TcpState::Listen,
+        TcpState::SynSent,
+        TcpState::SynReceived,
+        TcpState::Established,
+        TcpState::FinWait1,
+        TcpState::FinWait2,
+        TcpState::CloseWait,
+        TcpState::Closing,
+        TcpState::LastAck,
+        TcpState::TimeWait,
+    ];
+
+    /**
+     * 稳定的名字, 用于指标标签
+     */
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TcpState::Closed => "closed",
+            TcpState::Listen => "listen",
+            TcpState::SynSent => "syn_sent",
+            TcpState::SynReceived => "syn_received",
+            TcpState::Established => "established",
+            TcpState::FinWait1 => "fin_wait_1",
+            TcpState::FinWait2 => "fin_wait_2",
+            TcpState::CloseWait => "close_wait",
+            TcpState::Closing => "closing",
+            TcpState::LastAck => "last_ack",
+            TcpState::TimeWait => "time_wait",
+        }
+    }
+}
+
+/**
+ * 最近状态迁移环形缓冲区的容量
+ */
+pub const TRANSITION_HISTORY: usize = 16;
+
+/**
+ * 按路径 MTU 缩小段长时的下限 (RFC 1122 的默认 MSS)
+ */
+pub const MIN_SEND_MSS: u16 = 536;
+
+/**
+ * 触发快速重传的重复 ACK 个数 (RFC 5681)
+ */
+pub const DUP_ACK_THRESHOLD: u32 = 3;
+
+/**
+ * 卡死诊断中保留的最近报文段事件个数
+ */
+pub const STALL_EVENTS: usize = 5;
+
+/**
+ * send_from 预读时在拥塞窗口之外多留的段数, 确认到达后马上有数据可发
+ */
+pub const SEND_FROM_MARGIN_SEGMENTS: usize = 2;
+
+/**
+ * 一次状态迁移, at_ms 为迁移发生时的时钟读数
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateTransition {
+    pub at_ms: u64,
+    pub from: TcpState,
+    pub to: TcpState,
+}
+
+pub(crate) struct TcpConnection {
     s_ip: u32,
     s_port: u16,
     d_ip: u32,
-    d_port: u16
+    d_port: u16,
+    state: TcpState,
+    transitions: VecDeque<StateTransition>, // 最多保留 TRANSITION_HISTORY 条
+    receiver: TcpReceiver,
+    snd_una: u32,
+    snd_wnd: u32,
+    dup_acks: u32,              // 最后一次 snd_una 前进之后的重复 ACK 个数
+    retransmit: RetransmitQueue,
+    acks: AckBatch,             // 本轮 poll 中尚未处理的纯 ACK
+    ack_work: u64,              // 发送端处理 ACK 的次数, 合并的一批只算一次
+    window_update: WindowUpdateTimer,
+    mss: u16,                   // 本端通告的 MSS
+    path_mtu: Option<u16>,      // 出口 MTU, 设置后发送的段不超过它
+    cwnd: u32,                  // 拥塞窗口, 字节
+    ssthresh: u32,              // 慢启动阈值, 字节; 没有目的地缓存时为 u32::MAX, 丢包时减为在途数据的一半
+    cwnd_acked: u32,            // 拥塞避免阶段累计确认的字节, 满一个 cwnd 增加一个 MSS
+    recover: Option<u32>,       // 丢包恢复中: 发现丢包时的 snd_nxt, 确认到它才结束, 期间不再减 ssthresh
+    fast_recovery: bool,        // 恢复由快速重传开始, 部分确认不增长 cwnd
+    pacer: Pacer,               // tcp.pacing 打开时按 cwnd / SRTT 的速率放出新数据
+    rtt: RttEstimator,
+    metrics_saved: bool,        // 结束时已经交出 destination_metrics
+    md5_key: Option<Vec<u8>>,   // RFC 2385 签名密钥
+    ack_owed: bool,             // 收到重复报文或保活探测, 需要回一个 ACK
+    reset_owed: Option<u32>,    // SynSent / SynReceived 收到不确认本端 SYN 的 ACK, 以它的确认号为序号回 RST
+    rcvbuf: Option<RcvBufTuner>, // 打开自动调整时才有
+    rcv_adv: u32,               // 通告过的窗口右沿, 缩小缓冲区时不能退到它之前
+    send_buf: VecDeque<u8>,     // 应用层写入、尚未发出的数据
+    send_capacity: usize,       // send_buf 与在途数据合计的上限
+    sent_bytes: u64,            // 已从 send_buf 切出的字节数; write_ends 与 push_until 都是这样的流偏移
+    write_ends: VecDeque<u64>,  // 每次 write 的数据结束处, 切到它的段带 PSH
+    push_until: u64,            // flush 之前写入的数据不再被 Nagle 扣留
+    urgent_end: Option<u64>,    // 紧急数据之后第一个字节的流偏移, 包含它之前字节的段都带 URG
+    error: Option<ConnectionError>,
+    memory: MemoryBudget,
+    send_charged: usize,        // 记在预算上的发送侧字节数
+    recv_charged: usize,        // 记在预算上的接收侧字节数: 未读数据 + 已通告未用完的窗口
+    memory_share: usize,        // 每个方向最多向预算记账的字节数
+    fin_queued: bool,           // 应用层已经 close, 发送缓冲区发完之后发 FIN
+    syn_seq: Option<u32>,       // 本端 SYN 占用的序号, 即 ISN
+    fin_seq: Option<u32>,       // 已发出的 FIN 占用的序号
+    time_wait_ms: u64,
+    time_wait_until: u64,       // TimeWait 到期的时刻
+    clock_ms: u64,              // 最近一次处理报文或定时的时刻, 记录 ACK 引起的状态迁移
+    options: SocketOptions,
+    checksum_policy: ChecksumPolicy,
+    send_above_high: bool,      // 发送队列处于高水位
+    recv_above_high: bool,      // 未读数据处于高水位
+    writable: bool,             // 上次 update_watermarks 时是否可写
+    writable_edge: bool,        // 变为可写之后还没有通知
+    on_writable: Option<Box<dyn FnMut()>>,
+    offer: SynOffer,            // 握手中提出的选项; 被动端收到 SYN 后改为实际应答的选项
+    negotiated: Option<NegotiatedOptions>, // 处理第一个 SYN 或 SYN|ACK 时确定, 之后不再改变
+    handshake: Option<TcpSegment>, // 第一次发出的 SYN 或 SYN|ACK, 重传时原样发出
+    handshake_owed: bool,       // SynReceived 收到重传的 SYN, 重发 SYN|ACK
+    early_data: Vec<TcpSegment>, // SynReceived 中先于握手 ACK 到达的数据段, 进入 Established 后交给接收端
+    tracer: Tracer,             // 状态变化交给追踪钩子
+    verdict: SegmentVerdict,    // 最近一次收到的报文段的处理结果
+    tfo: TfoDecision,           // 被动端对 SYN 的 TFO 处理结果: IssueCookie 时 SYN|ACK 带上 cookie, AcceptData 时握手完成前就能发送
+    ts_recent: u32,             // 对端最近的 TSval: SYN 中的填入 SYN|ACK 的 TSecr, 之后协商了 Timestamps 才更新
+    peer_isn: Option<u32>,      // 对端 SYN 的序号, TimeWait 中判断新 SYN 是否与旧连接的序号空间重叠
+    peer_syn: Option<PeerSynInfo>, // 被动打开时第一个 SYN 的内容
+    loss_detection: LossDetection,
+    rack: Rack,
+    idle_timeout_ms: Option<u64>, // 两个方向都没有数据多久之后关闭连接, 与保活无关
+    idle_action: IdleAction,
+    last_data_ms: u64,          // 最近一次收到或发出数据的时刻, 纯 ACK 和保活探测不算
+    stall_timeout_ms: Option<u64>, // 有待处理的数据却没有任何报文段进出多久算卡死
+    last_segment_ms: u64,       // 最近一次收到或发出报文段的时刻
+    recent: VecDeque<SegmentEvent>, // 最多保留 STALL_EVENTS 条
+    stalled: bool,              // 本次卡死已经诊断过, 有报文段进出后清除
+    stall: Option<StallDiagnosis>, // 还没有被取走的诊断
+    icmp_advice: Option<IcmpAdvice>, // 最近一次 ICMP 目的不可达, 之后有新数据被确认时清除
+    rto_deadline: Option<u64>,  // 有在途序号时的重传时刻 (RFC 6298 5.1), 确认推进或重传时重新开始
+}
+
+/**
+ * 被动打开时对端 SYN 的内容, 供服务器在 accept 前后按客户端的能力做决定
+ * ttl 与 dscp 来自承载 SYN 的 IP 数据报, 只经过 ConnectionTable::datagram_received 的 SYN 才有
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerSynInfo {
+    pub options: Vec<TcpOption>,
+    pub isn: u32,
+    pub window: u16,
+    pub ttl: Option<u8>,
+    pub dscp: Option<Dscp>,
+}
+
+impl PeerSynInfo {
+    /**
+     * SYN 中带有 TFO cookie(空 cookie 是请求, 不算)
+     */
+    pub fn has_fast_open_cookie(&self) -> bool {
+        self.options.iter().any(|option| matches!(option, TcpOption::FastOpen(cookie) if !cookie.is_empty()))
+    }
+}
+
+/**
+ * 连接收到的 ICMP 目的不可达 (type 3), when_ms 为收到时的时钟读数
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IcmpAdvice {
+    pub code: u8,
+    pub when_ms: u64,
+}
+
+impl IcmpAdvice {
+    /**
+     * 主机 (1)、协议 (2)、端口 (3) 不可达让握手中的主动打开立即失败; 网络不可达等其他代码可能是暂时的
+     */
+    pub fn is_hard(&self) -> bool {
+        matches!(self.code, 1..=3)
+    }
+}
+
+/**
+ * 报文段 Timestamps 选项中的 TSval
+ */
+fn ts_val(segment: &TcpSegment) -> Option<u32> {
+    segment.parsed_options().ok()?.into_iter().find_map(|option| match option {
+        TcpOption::Timestamps { val, .. } => Some(val),
+        _ => None,
+    })
+}
+
+impl Drop for TcpConnection {
+    fn drop(&mut self) {
+        self.memory.release(MemoryComponent::SendBuffer, self.send_charged);
+        self.memory.release(MemoryComponent::RecvBuffer, self.recv_charged);
+    }
 }
 
 impl PartialEq for TcpConnection {
     fn eq(&self, other: &Self) -> bool {
-        (self.s_ip, self.s_port, self.d_ip, self.d_port) == (other.s_ip, other.s_port, other.d_ip, other.d_port)
+        self.id() == other.id()
     }
 }
 
 impl TcpConnection {
+    #[cfg(test)]
     pub fn new(s_ip: u32, s_port: u16, d_ip: u32, d_port: u16) -> TcpConnection {
+        Self::with_config(s_ip, s_port, d_ip, d_port, &TcpConfig::default(), 0)
+    }
+
+    pub fn with_config(s_ip: u32, s_port: u16, d_ip: u32, d_port: u16, config: &TcpConfig, now_ms: u64) -> TcpConnection {
+        let rcvbuf = config.rcvbuf_autotune.then(|| RcvBufTuner::new(config, now_ms));
+        let receiver = match &rcvbuf {
+            Some(tuner) => TcpReceiver::new(0, tuner.capacity()),
+            None => TcpReceiver::from_config(config),
+        };
         TcpConnection {
-            s_ip, s_port, d_ip, d_port
+            s_ip, s_port, d_ip, d_port,
+            state: TcpState::Closed,
+            transitions: VecDeque::with_capacity(TRANSITION_HISTORY),
+            receiver,
+            snd_una: 0,
+            snd_wnd: 0,
+            dup_acks: 0,
+            retransmit: RetransmitQueue::new(),
+            acks: AckBatch::new(),
+            ack_work: 0,
+            window_update: WindowUpdateTimer::new(config, now_ms),
+            mss: config.mss,
+            path_mtu: None,
+            cwnd: config.initial_cwnd.saturating_mul(config.mss as u32),
+            ssthresh: u32::MAX,
+            cwnd_acked: 0,
+            recover: None,
+            fast_recovery: false,
+            pacer: Pacer::new(config, now_ms),
+            rtt: RttEstimator::new(config),
+            metrics_saved: false,
+            md5_key: None,
+            ack_owed: false,
+            reset_owed: None,
+            rcvbuf,
+            rcv_adv: 0,
+            send_buf: VecDeque::new(),
+            send_capacity: config.send_buffer,
+            sent_bytes: 0,
+            write_ends: VecDeque::new(),
+            push_until: 0,
+            urgent_end: None,
+            error: None,
+            memory: MemoryBudget::unlimited(),
+            send_charged: 0,
+            recv_charged: 0,
+            memory_share: usize::MAX,
+            fin_queued: false,
+            syn_seq: None,
+            fin_seq: None,
+            time_wait_ms: config.time_wait_ms(),
+            time_wait_until: 0,
+            clock_ms: now_ms,
+            options: SocketOptions::default(),
+            checksum_policy: ChecksumPolicy::default(),
+            send_above_high: false,
+            recv_above_high: false,
+            writable: false,
+            writable_edge: false,
+            on_writable: None,
+            offer: SynOffer::from_config(config),
+            negotiated: None,
+            handshake: None,
+            handshake_owed: false,
+            early_data: Vec::new(),
+            tracer: Tracer::default(),
+            verdict: SegmentVerdict::Accepted,
+            tfo: TfoDecision::Normal,
+            ts_recent: 0,
+            peer_isn: None,
+            peer_syn: None,
+            loss_detection: config.loss_detection,
+            rack: Rack::new(),
+            idle_timeout_ms: None,
+            idle_action: IdleAction::default(),
+            last_data_ms: now_ms,
+            stall_timeout_ms: config.stall_timeout_ms,
+            last_segment_ms: now_ms,
+            recent: VecDeque::with_capacity(STALL_EVENTS),
+            stalled: false,
+            stall: None,
+            icmp_advice: None,
+            rto_deadline: None,
         }
     }
 
-    pub fn connect() {
+    /**
+     * 改为向 budget 记账, 已记在原预算上的字节转过去
+     */
+    pub fn set_memory_budget(&mut self, budget: MemoryBudget) {
+        self.memory.release(MemoryComponent::SendBuffer, self.send_charged);
+        self.memory.release(MemoryComponent::RecvBuffer, self.recv_charged);
+        self.send_charged = budget.charge_up_to(MemoryComponent::SendBuffer, self.send_charged);
+        self.recv_charged = budget.charge_up_to(MemoryComponent::RecvBuffer, self.recv_charged);
+        self.memory = budget;
+    }
+
+    /**
+     * 发送侧和接收侧各自最多占用 share 字节预算, 空闲方向(只下载的连接的接收窗口)不会占满共享预算
+     * 已经记上的部分超过新的 share 时不退还, 只是不再增长
+     */
+    pub fn set_memory_share(&mut self, share: usize) {
+        self.memory_share = share;
+    }
 
+    /**
+     * 设置后每个发出的报文段都带 MD5 签名, 收到的报文段签名不匹配则丢弃
+     */
+    pub fn set_md5_key(&mut self, key: Option<Vec<u8>>) {
+        self.md5_key = key;
     }
 
-    pub fn disconnect() {
+    /**
+     * 每次收到报文段时被调用, 返回报文段是否被接受
+     * 纯 ACK 只进入本轮的批次; 其他报文先把批次交给发送端, 保证处理顺序与到达顺序一致
+     */
+    pub fn segment_received(&mut self, segment: &TcpSegment, now_ms: u64) -> bool {
+        if !self.authentic(segment) {
+            return false;
+        }
+        self.process(segment, now_ms);
+        true
+    }
 
+    /**
+     * 没有配置密钥, 或签名校验通过; 否则记一次丢弃
+     */
+    fn authentic(&mut self, segment: &TcpSegment) -> bool {
+        let Some(key) = &self.md5_key else {
+            return true;
+        };
+        let valid = md5_signature::verify(segment, self.d_ip, self.s_ip, key);
+        if !valid {
+            self.receiver.record_drop(DropReason::BadMd5Signature);
+        }
+        valid
     }
 
-}
+    fn process(&mut self, segment: &TcpSegment, now_ms: u64) {
+        let now_ms = now_ms.max(self.clock_ms); // 时钟回退时沿用已经见过的时刻
+        self.clock_ms = now_ms;
+        self.record_segment(SegmentDirection::Rx, segment);
+        self.window_update.on_peer_segment(!segment.data.is_empty(), now_ms);
+        self.verdict = SegmentVerdict::Accepted;
+        if segment.RST() {
+            if self.acceptable_reset(segment) {
+                self.reset_received(now_ms);
+            } else {
+                self.verdict = SegmentVerdict::Dropped;
+            }
+            return;
+        }
+        if self.state == TcpState::SynSent && segment.ACK() && !self.acceptable_handshake_ack(segment.ack) {
+            self.reset_owed = Some(segment.ack); // 旧连接的报文, 对端收到 RST 后放弃它 (RFC 793 3.4)
+            self.verdict = SegmentVerdict::Dropped;
+            return;
+        }
+        if self.state == TcpState::SynSent && segment.SYN() && !segment.ACK() {
+            self.simultaneous_open(segment, now_ms);
+            return;
+        }
+        if self.state == TcpState::SynReceived && segment.SYN() && !segment.ACK() {
+            self.handshake_owed = true; // 对端没有收到 SYN|ACK; 协商结果不受重传的 SYN 影响
+            return;
+        }
+        if self.state == TcpState::SynReceived && segment.ACK() && !self.acceptable_handshake_ack(segment.ack) {
+            self.reset_owed = Some(segment.ack); // RFC 793 3.9: SYN-RECEIVED 中不可接受的 ACK 回 RST, 状态不变
+            self.verdict = SegmentVerdict::Dropped;
+            return;
+        }
+        if self.state == TcpState::SynReceived && !segment.ACK() {
+            self.hold_early_data(segment);
+            return;
+        }
+        if segment.SYN() && self.synchronized_state() {
+            self.ack_owed = true; // challenge ACK (RFC 5961 4.2): 对端若已重启, 会用 RST 回应
+            self.verdict = SegmentVerdict::Dropped;
+            return;
+        }
+        if segment.ACK() && self.synchronized_state() && seq_lt(self.snd_nxt(), segment.ack) {
+            self.ack_owed = true; // 确认了还没有发出的序号: 回 ACK, 整段丢弃 (RFC 793 3.9)
+            self.verdict = SegmentVerdict::Dropped;
+            return;
+        }
+        if self.state == TcpState::TimeWait && segment.FIN() {
+            self.time_wait_until = now_ms + self.time_wait_ms; // 对端重传了 FIN, 重新开始 2*MSL
+        }
+        self.complete_handshake(segment, now_ms);
+        self.note_timestamp(segment);
+        if self.is_keepalive(segment) {
+            self.ack_owed = true;
+            return;
+        }
+        if self.acks.offer(segment, self.snd_una, (self.snd_wnd >> self.send_shift()).min(u16::MAX as u32) as u16) {
+            return;
+        }
+        self.flush_acks();
+        if self.beyond_reservation(segment) {
+            self.receiver.record_drop(DropReason::NoMemory);
+            self.ack_owed = true; // 把当前窗口告诉对端
+            self.verdict = SegmentVerdict::Dropped;
+            return;
+        }
+        let synchronized = self.receiver.is_synchronized();
+        let fin_received = self.receiver.fin_received();
+        match self.receiver.segment_received(segment) {
+            ReceiveOutcome::Duplicate => {
+                self.ack_owed = true;
+                self.verdict = SegmentVerdict::Duplicate;
+            }
+            // 窗口外的段 (包括零窗口探测) 整段丢弃, rcv_nxt 不动, 回 ACK 重申 rcv_nxt 和当前窗口 (RFC 793 3.9)
+            ReceiveOutcome::Dropped(DropReason::OutOfWindow) => {
+                self.ack_owed = true;
+                self.verdict = SegmentVerdict::OutOfWindow;
+            }
+            ReceiveOutcome::Dropped(_) => self.verdict = SegmentVerdict::Dropped,
+            ReceiveOutcome::Accepted if !segment.data.is_empty() => {
+                self.ack_owed = true; // 没有延迟确认, 每个数据段都确认
+                self.last_data_ms = now_ms;
+            }
+            _ => {}
+        }
+        if !synchronized && self.receiver.is_synchronized() {
+            self.rcv_adv = self.receiver.ack_num(); // 还没有相对于 rcv_nxt 通告过窗口
+        }
+        if !fin_received && self.receiver.fin_received() {
+            self.peer_closed(now_ms);
+        }
+        if segment.ACK() {
+            let summary = AckSummary { ack: segment.ack, window: segment.win_size, dup_acks: 0, absorbed: 1 };
+            self.apply_ack(summary, &segment.sack_blocks());
+            if segment.SYN() {
+                self.snd_wnd = segment.win_size as u32; // SYN|ACK 中的窗口不缩放 (RFC 7323 2.2)
+            }
+        }
+    }
+
+    /**
+     * 有内存上限时, 通告的窗口就是为这条连接预留的内存
+     * 超出通告窗口右沿的数据没有预留, 即使接收缓冲区放得下也丢弃
+     */
+    fn beyond_reservation(&self, segment: &TcpSegment) -> bool {
+        if self.memory.limit() == usize::MAX || segment.data.is_empty() || segment.SYN() || !self.receiver.is_synchronized() {
+            return false;
+        }
+        seq_lt(self.rcv_adv, segment.seq.wrapping_add(segment.data.len() as u32))
+    }
+
+    /**
+     * 握手已经完成的状态, 这时收到的 SYN 不会建立新连接
+     */
+    fn synchronized_state(&self) -> bool {
+        !matches!(self.state, TcpState::Closed | TcpState::Listen | TcpState::SynSent | TcpState::SynReceived)
+    }
+
+    /**
+     * 握手阶段只看控制位: SynSent 收到 SYN|ACK、SynReceived 收到 ACK(同时打开时是对端的 SYN|ACK) 即进入 Established
+     */
+    fn complete_handshake(&mut self, segment: &TcpSegment, now_ms: u64) {
+        match self.state {
+            TcpState::SynSent if segment.ctrl.contains(TcpFlags::SYN | TcpFlags::ACK) => {
+                if self.negotiated.is_none() {
+                    let peer = segment.parsed_options().unwrap_or_default();
+                    self.negotiated = Some(NegotiatedOptions::negotiate(&self.offer, &peer));
+                    self.peer_isn = Some(segment.seq);
+                }
+                if seq_lt(segment.ack, self.snd_nxt()) {
+                    self.requeue_syn_data();
+                }
+                self.set_state(TcpState::Established, now_ms);
+                self.ack_owed = true;
+            }
+            TcpState::SynReceived if segment.ACK() => {
+                self.set_state(TcpState::Established, now_ms);
+                self.deliver_early_data();
+            }
+            _ => {}
+        }
+    }
+
+    /**
+     * 同时打开 (RFC 793 图 8): SynSent 收到不带 ACK 的 SYN, 进入 SynReceived 并发出 SYN|ACK
+     * SYN|ACK 沿用本端 SYN 的序号, 之后重传握手时发出的也是它
+     */
+    fn simultaneous_open(&mut self, syn: &TcpSegment, now_ms: u64) {
+        if self.negotiated.is_none() {
+            let peer = syn.parsed_options().unwrap_or_default();
+            self.negotiated = Some(NegotiatedOptions::negotiate(&self.offer, &peer));
+            self.ts_recent = ts_val(syn).unwrap_or(0);
+            self.peer_isn = Some(syn.seq);
+        }
+        let mut stripped = syn.clone();
+        stripped.data.clear();
+        self.receiver.segment_received(&stripped);
+        self.set_state(TcpState::SynReceived, now_ms);
+        let isn = self.snd_una;
+        self.syn_ack(isn);
+        self.handshake_owed = true;
+    }
+
+    /**
+     * 握手中可接受的 ACK: SND.UNA < SEG.ACK <= SND.NXT, 即确认了本端的 SYN 或 SYN|ACK
+     * SYN 带了 TFO 数据时, 只确认 SYN 的 ACK 也可接受; 还没有经 syn_ack 发出 SYN|ACK 时没有可比较的序号, 不做检查
+     */
+    fn acceptable_handshake_ack(&self, ack: u32) -> bool {
+        self.syn_seq.is_none() || seq_lt(self.snd_una, ack) && seq_le(ack, self.snd_nxt())
+    }
+
+    /**
+     * 握手 ACK 被重排到数据段之后时, 先到的数据段不丢弃, 避免对端再等一次重传
+     * 合计不超过一个接收窗口, 超出的部分丢弃; 不回 ACK, 对端还没有确认 SYN|ACK
+     */
+    fn hold_early_data(&mut self, segment: &TcpSegment) {
+        if segment.data.is_empty() {
+            return;
+        }
+        let held: usize = self.early_data.iter().map(|early| early.data.len()).sum();
+        if held + segment.data.len() > self.window_offer() as usize {
+            self.receiver.record_drop(DropReason::NoMemory);
+            return;
+        }
+        self.early_data.push(segment.clone());
+    }
+
+    /**
+     * 握手完成, 把 SynReceived 中缓存的数据段按到达顺序交给接收端
+     */
+    fn deliver_early_data(&mut self) {
+        for segment in std::mem::take(&mut self.early_data) {
+            if self.receiver.segment_received(&segment) == ReceiveOutcome::Accepted {
+                self.ack_owed = true;
+                self.last_data_ms = self.clock_ms;
+            }
+        }
+    }
+
+    /**
+     * 对端的 FIN 按序到达: 确认它, 并按本端是否已经关闭迁移状态
+     */
+    fn peer_closed(&mut self, now_ms: u64) {
+        self.ack_owed = true;
+        match self.state {
+            TcpState::SynReceived | TcpState::Established => self.set_state(TcpState::CloseWait, now_ms),
+            TcpState::FinWait1 => self.set_state(TcpState::Closing, now_ms),
+            TcpState::FinWait2 => self.enter_time_wait(now_ms),
+            _ => {}
+        }
+    }
+
+    /**
+     * 本端的 FIN 被确认
+     */
+    fn fin_acked(&mut self) {
+        let now_ms = self.clock_ms;
+        match self.state {
+            TcpState::FinWait1 => self.set_state(TcpState::FinWait2, now_ms),
+            TcpState::Closing => self.enter_time_wait(now_ms),
+            TcpState::LastAck => self.set_state(TcpState::Closed, now_ms),
+            _ => {}
+        }
+    }
+
+    /**
+     * 协商了 Timestamps 时记下对端较新的 TSval, 供 TimeWait 判断新的 SYN 是否属于新连接
+     */
+    fn note_timestamp(&mut self, segment: &TcpSegment) {
+        if !self.negotiated.is_some_and(|negotiated| negotiated.timestamps) {
+            return;
+        }
+        if let Some(val) = ts_val(segment) {
+            if seq_lt(self.ts_recent, val) {
+                self.ts_recent = val;
+            }
+        }
+    }
+
+    /**
+     * TimeWait 中收到同一四元组的 SYN, 是否可以提前结束旧连接、接受新连接 (RFC 6191)
+     * 要求旧连接协商了 Timestamps, SYN 的 TSval 严格大于旧连接上见过的最后一个,
+     * 且 SYN 的序号不落在旧连接用过的序号空间 [对端 ISN, rcv_nxt] 内
+     */
+    pub fn accepts_reincarnation(&self, syn: &TcpSegment) -> bool {
+        if self.state != TcpState::TimeWait || !self.negotiated.is_some_and(|negotiated| negotiated.timestamps) {
+            return false;
+        }
+        let Some(val) = ts_val(syn) else {
+            return false;
+        };
+        let reused = self.peer_isn.is_some_and(|isn| seq_le(isn, syn.seq) && seq_le(syn.seq, self.receiver.ack_num()));
+        seq_lt(self.ts_recent, val) && !reused
+    }
+
+    fn enter_time_wait(&mut self, now_ms: u64) {
+        self.time_wait_until = now_ms + self.time_wait_ms;
+        self.set_state(TcpState::TimeWait, now_ms);
+    }
+
+    /**
+     * 应用层关闭发送方向: 已写入的数据照常发送, 之后发 FIN
+     * 握手还没完成的主动打开直接关闭; 已经关闭过时什么也不做
+     */
+    pub fn close(&mut self, now_ms: u64) {
+        match self.state {
+            TcpState::SynReceived | TcpState::Established => self.set_state(TcpState::FinWait1, now_ms),
+            TcpState::CloseWait => self.set_state(TcpState::LastAck, now_ms),
+            TcpState::Listen | TcpState::SynSent => {
+                self.set_state(TcpState::Closed, now_ms);
+                return;
+            }
+            _ => return,
+        }
+        self.fin_queued = true;
+    }
+
+    /**
+     * 放弃连接: 丢掉所有未确认的数据, 进入 Closed
+     * 对端可能还持有连接状态时返回要发给它的 RST
+     */
+    pub fn abort(&mut self, now_ms: u64) -> Option<TcpSegment> {
+        let rst = (self.receiver.is_synchronized() && !matches!(self.state, TcpState::Closed | TcpState::Listen | TcpState::TimeWait))
+            .then(|| self.outgoing(TcpSegment::new(self.s_port, self.d_port, self.snd_nxt(), self.receiver.ack_num(), 5, 0,
+                TcpFlags::ACK | TcpFlags::RST, 0, 0, vec![], vec![])));
+        self.send_buf.clear();
+        self.retransmit = RetransmitQueue::new();
+        self.release_send();
+        if self.error.is_none() {
+            self.error = Some(ConnectionError::Closed);
+        }
+        self.set_state(TcpState::Closed, now_ms);
+        rst
+    }
+
+    /**
+     * 还没有被对端确认的字节数: 发送缓冲区中的数据加上在途数据
+     */
+    pub fn unsent_bytes(&self) -> usize {
+        self.send_buf.len() + self.retransmit.bytes_queued()
+    }
+
+    /**
+     * IP 层报告对端不可达(如 ARP 解析失败): 正在建立的连接以 HostUnreachable 失败
+     * 已建立的连接把它当作暂时的错误, 不受影响 (RFC 1122 4.2.3.9)
+     */
+    pub fn host_unreachable(&mut self, now_ms: u64) -> bool {
+        if self.state != TcpState::SynSent {
+            return false;
+        }
+        self.error = Some(ConnectionError::HostUnreachable);
+        self.send_buf.clear();
+        self.release_send();
+        self.set_state(TcpState::Closed, now_ms);
+        true
+    }
+
+    /**
+     * ICMP 目的不可达, seq 为带回的原报文段的序号, 不在 [snd_una, snd_nxt) 内的视为伪造而忽略 (RFC 5927)
+     * 握手中的主动打开遇到主机/协议/端口不可达以 Unreachable 失败; 其他情况只记下来, 连接照常重传 (RFC 1122 4.2.3.9)
+     */
+    pub fn icmp_unreachable(&mut self, advice: IcmpAdvice, seq: u32) -> bool {
+        if !self.icmp_seq_plausible(seq) {
+            return false;
+        }
+        self.icmp_advice = Some(advice);
+        if self.state == TcpState::SynSent && advice.is_hard() {
+            self.error = Some(ConnectionError::Unreachable(advice.code));
+            self.send_buf.clear();
+            self.release_send();
+            self.set_state(TcpState::Closed, advice.when_ms);
+        }
+        true
+    }
+
+    /**
+     * ICMP 差错带回的序号是否可能是本端发出的: 在 [snd_una, snd_nxt) 内且连接还在收发
+     */
+    pub fn icmp_seq_plausible(&self, seq: u32) -> bool {
+        !matches!(self.state, TcpState::Closed | TcpState::Listen | TcpState::TimeWait)
+            && seq_le(self.snd_una, seq) && seq_lt(seq, self.snd_nxt())
+    }
+
+    pub fn icmp_advice(&self) -> Option<IcmpAdvice> {
+        self.icmp_advice
+    }
+
+    /**
+     * 通往对端的链路断开太久: 连接以 NetworkDown 失败, 不发 RST (链路上发不出去)
+     */
+    pub fn link_down(&mut self, now_ms: u64) -> bool {
+        if matches!(self.state, TcpState::Closed | TcpState::Listen) {
+            return false;
+        }
+        self.error = Some(ConnectionError::NetworkDown);
+        self.send_buf.clear();
+        self.retransmit = RetransmitQueue::new();
+        self.release_send();
+        self.set_state(TcpState::Closed, now_ms);
+        true
+    }
+
+    /**
+     * SynSent 中只有确认了本端 SYN 的 RST 有效, 其他的可能属于旧连接, 丢弃 (RFC 793 3.9)
+     * 之后只有序号恰好为 RCV.NXT 的 RST 有效; 落在窗口内的其他序号回 challenge ACK, 窗口外的丢弃 (RFC 5961 3.2)
+     */
+    fn acceptable_reset(&mut self, segment: &TcpSegment) -> bool {
+        match self.state {
+            TcpState::SynSent => return segment.ACK() && self.acceptable_handshake_ack(segment.ack),
+            TcpState::Closed | TcpState::Listen | TcpState::TimeWait => return true,
+            _ => {}
+        }
+        let rcv_nxt = self.receiver.ack_num();
+        if segment.seq == rcv_nxt {
+            return true;
+        }
+        if seq_lt(rcv_nxt, segment.seq) && seq_lt(segment.seq, self.rcv_adv) {
+            self.ack_owed = true;
+        }
+        false
+    }
+
+    /**
+     * 握手中收到 RST 为连接被拒绝, 之后为连接被重置
+     * Closing / LastAck 中双方都已经关闭, 直接进入 Closed, 不报告错误
+     */
+    fn reset_received(&mut self, now_ms: u64) {
+        if matches!(self.state, TcpState::Closed | TcpState::Listen | TcpState::TimeWait) {
+            return;
+        }
+        if matches!(self.state, TcpState::Closing | TcpState::LastAck) {
+            self.set_state(TcpState::Closed, now_ms);
+            return;
+        }
+        self.error = Some(if self.state == TcpState::SynSent { ConnectionError::Refused } else { ConnectionError::Reset });
+        self.send_buf.clear();
+        self.release_send();
+        self.set_state(TcpState::Closed, now_ms);
+    }
+
+    /**
+     * 保活探测: 不带数据, seq = rcv_nxt - 1, 确认号和窗口都没有变化
+     * 只需要回一个 ACK, 不能当作重复 ACK 或交给重组器
+     * 确认号或窗口变了的旧序号报文是窗口更新, 照常处理
+     */
+    fn is_keepalive(&mut self, segment: &TcpSegment) -> bool {
+        if !self.receiver.is_synchronized()
+            || !segment.data.is_empty()
+            || segment.ctrl.intersects(TcpFlags::SYN | TcpFlags::FIN | TcpFlags::RST)
+            || segment.seq != self.receiver.ack_num().wrapping_sub(1)
+        {
+            return false;
+        }
+        self.flush_acks(); // 和已经合并的 ACK 之后的状态比较
+        segment.ack == self.snd_una && (segment.win_size as u32) << self.send_shift() == self.snd_wnd
+    }
+
+    /**
+     * 需要立即回复的 ACK(重复报文、保活探测), 没有则为 None
+     */
+    pub fn take_reply(&mut self) -> Option<TcpSegment> {
+        if let Some(seq) = self.reset_owed.take() {
+            self.ack_owed = false;
+            return Some(self.outgoing(TcpSegment::new(self.s_port, self.d_port, seq, 0, 5, 0, TcpFlags::RST, 0, 0, vec![], vec![])));
+        }
+        if std::mem::take(&mut self.handshake_owed) {
+            self.ack_owed = false;
+            return self.handshake.clone();
+        }
+        if !std::mem::take(&mut self.ack_owed) {
+            return None;
+        }
+        Some(self.make_ack())
+    }
+
+    /**
+     * 监听端收到 SYN, 进入 SynReceived
+     * TFO cookie 有效时 SYN 中的数据立即可读, 应用不必等握手的最后一个 ACK; 否则数据丢弃, 由对端在握手后重传
+     */
+    pub fn syn_received(&mut self, syn: &TcpSegment, tfo: &TfoDecision, now_ms: u64) {
+        if !self.authentic(syn) {
+            return;
+        }
+        if self.negotiated.is_none() {
+            let peer = syn.parsed_options().unwrap_or_default();
+            self.offer = self.offer.answer(&peer);
+            self.negotiated = Some(NegotiatedOptions::negotiate(&self.offer, &peer));
+            self.ts_recent = ts_val(syn).unwrap_or(0);
+            self.peer_isn = Some(syn.seq);
+            self.peer_syn = Some(PeerSynInfo { options: peer, isn: syn.seq, window: syn.win_size, ttl: None, dscp: None });
+        }
+        self.tfo = tfo.clone();
+        if *tfo == TfoDecision::AcceptData {
+            self.snd_wnd = syn.win_size as u32; // 握手完成前就要发送, 先用 SYN 通告的窗口 (SYN 中的窗口不缩放)
+        }
+        if *tfo == TfoDecision::AcceptData || syn.data.is_empty() {
+            self.process(syn, now_ms);
+        } else {
+            let mut stripped = syn.clone();
+            stripped.data.clear();
+            self.process(&stripped, now_ms);
+        }
+        self.set_state(TcpState::SynReceived, now_ms);
+    }
+
+    /**
+     * 监听端对 SYN 的应答, 在 syn_received 之后调用
+     * TFO 签发了 cookie 时附带 kind 34; 有 MD5 签名时选项空间不够, 不附带
+     */
+    pub fn syn_ack(&mut self, isn: u32) -> TcpSegment {
+        self.snd_una = isn;
+        self.syn_seq = Some(isn);
+        let window = self.advertise_window();
+        self.window_update.on_advertised(window);
+        let mut options = self.offer.encode(self.clock_ms as u32, self.ts_recent);
+        if let (TfoDecision::IssueCookie(cookie), None) = (&self.tfo, &self.md5_key) {
+            options.extend(fast_open_words(cookie));
+        }
+        let syn_ack = self.outgoing(TcpSegment::new(self.s_port, self.d_port, isn, self.receiver.ack_num(), 5 + options.len() as u8, 0,
+            TcpFlags::SYN | TcpFlags::ACK, window as u16, 0, options, vec![]));
+        self.handshake = Some(syn_ack.clone());
+        self.restart_rto();
+        syn_ack
+    }
+
+    /**
+     * 接受了 SYN 捎带的 TFO 数据、还在等握手 ACK: 这时已经可以读写
+     */
+    pub fn fast_open_accepted(&self) -> bool {
+        self.state == TcpState::SynReceived && self.tfo == TfoDecision::AcceptData
+    }
+
+    /**
+     * 被动打开的连接收到的第一个 SYN, 主动打开的连接为 None
+     */
+    pub fn peer_syn(&self) -> Option<&PeerSynInfo> {
+        self.peer_syn.as_ref()
+    }
+
+    /**
+     * 记下承载 SYN 的数据报的 TTL 和 DSCP
+     */
+    pub fn set_peer_syn_ip(&mut self, ttl: u8, dscp: Dscp) {
+        if let Some(info) = &mut self.peer_syn {
+            info.ttl = Some(ttl);
+            info.dscp = Some(dscp);
+        }
+    }
+
+    /**
+     * 握手协商的结果, 还没有处理过对端的 SYN 或 SYN|ACK 时为 None
+     */
+    pub fn negotiated_options(&self) -> Option<NegotiatedOptions> {
+        self.negotiated
+    }
+
+    /**
+     * 应用层写入数据, 返回接受的字节数; 连接出错后不再接受
+     */
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, ConnectionError> {
+        self.write_vectored(&[data])
+    }
+
+    /**
+     * 依次写入多个缓冲区, 不必先拼接; 空间不够时在某个缓冲区中间截断
+     * 返回所有缓冲区合计接受的字节数
+     */
+    pub fn write_vectored(&mut self, bufs: &[&[u8]]) -> Result<usize, ConnectionError> {
+        if let Some(e) = &self.error {
+            return Err(e.clone());
+        }
+        if self.fin_queued {
+            return Err(ConnectionError::Closed);
+        }
+        let total: usize = bufs.iter().map(|buf| buf.len()).sum();
+        let n = self.memory.charge_up_to(MemoryComponent::SendBuffer, total.min(self.send_space()));
+        self.send_charged += n;
+        let mut left = n;
+        for buf in bufs {
+            if left == 0 {
+                break;
+            }
+            let take = left.min(buf.len());
+            self.send_buf.extend(&buf[..take]);
+            left -= take;
+        }
+        if n > 0 {
+            self.write_ends.push_back(self.sent_bytes + self.send_buf.len() as u64);
+        }
+        Ok(n)
+    }
+
+    /**
+     * 从 fill 直接读入发送缓冲区, 不经过中间缓冲: fill 填充给它的切片并返回写入的字节数, 返回 0 表示暂时没有数据
+     * 数据在确认之前要留着重传, 所以预读不超过拥塞窗口加 SEND_FROM_MARGIN_SEGMENTS 个段; 返回这次读入的字节数,
+     * 发出并被确认之后再次调用从数据源当前的位置继续, max_bytes 限制这一次最多读入多少
+     */
+    pub fn send_from_fn(&mut self, fill: &mut dyn FnMut(&mut [u8]) -> usize, max_bytes: Option<u64>) -> Result<u64, ConnectionError> {
+        if let Some(e) = &self.error {
+            return Err(e.clone());
+        }
+        if self.fin_queued {
+            return Err(ConnectionError::Closed);
+        }
+        let room = self.send_from_room().min(max_bytes.map_or(usize::MAX, |max| max.min(usize::MAX as u64) as usize));
+        let charged = self.memory.charge_up_to(MemoryComponent::SendBuffer, room);
+        if charged == 0 {
+            return Ok(0);
+        }
+        let start = self.send_buf.len();
+        self.send_buf.resize(start + charged, 0);
+        let (head, tail) = self.send_buf.as_mut_slices();
+        let parts: [&mut [u8]; 2] = match start.checked_sub(head.len()) {
+            Some(skip) => [&mut tail[skip..], &mut []],
+            None => [&mut head[start..], tail],
+        };
+        let mut filled = 0;
+        'fill: for part in parts {
+            let mut at = 0;
+            while at < part.len() {
+                let n = fill(&mut part[at..]).min(part.len() - at);
+                if n == 0 {
+                    break 'fill;
+                }
+                at += n;
+                filled += n;
+            }
+        }
+        self.send_buf.truncate(start + filled);
+        self.memory.release(MemoryComponent::SendBuffer, charged - filled);
+        self.send_charged += filled;
+        if filled > 0 {
+            self.write_ends.push_back(self.sent_bytes + self.send_buf.len() as u64);
+        }
+        Ok(filled as u64)
+    }
+
+    /**
+     * 见 send_from_fn, 数据源是 io::Read: WouldBlock 当作暂时没有数据, Interrupted 重试,
+     * 其他错误在没有读到数据时返回 ReadFailed, 已经读到的数据照常发送
+     */
+    pub fn send_from(&mut self, reader: &mut dyn io::Read, max_bytes: Option<u64>) -> Result<u64, ConnectionError> {
+        let mut failed = None;
+        let n = self.send_from_fn(&mut |buf| loop {
+            match reader.read(buf) {
+                Ok(n) => return n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return 0,
+                Err(e) => {
+                    failed = Some(e.kind());
+                    return 0;
+                }
+            }
+        }, max_bytes)?;
+        match failed {
+            Some(kind) if n == 0 => Err(ConnectionError::ReadFailed(kind)),
+            _ => Ok(n),
+        }
+    }
+
+    /**
+     * send_from 这一次最多读入的字节数: 发送缓冲区剩余空间, 且发送队列不超过拥塞窗口加几个段
+     */
+    pub fn send_from_room(&self) -> usize {
+        let cap = self.cwnd as usize + SEND_FROM_MARGIN_SEGMENTS * self.send_mss() as usize;
+        self.send_space().min(cap.saturating_sub(self.send_queued()))
+    }
+
+    /**
+     * 写入紧急数据: 之后切出的段只要包含紧急数据结尾之前的字节就带 URG, 紧急数据发完之后不再设置
+     * 上一次的紧急数据还没有发完时, 标记移到这次写入的结尾
+     */
+    pub fn write_urgent(&mut self, data: &[u8]) -> Result<usize, ConnectionError> {
+        let n = self.write(data)?;
+        if n > 0 {
+            self.urgent_end = Some(self.sent_bytes + self.send_buf.len() as u64);
+        }
+        Ok(n)
+    }
+
+    /**
+     * 段从流偏移 start 开始时的紧急指针: 到紧急数据之后第一个字节的距离 (RFC 6093)
+     * 紧急数据可以跨过本段; 距离超过 u16 时取 0xffff, 对端在后面的段中得到准确的指针
+     */
+    fn urgent_pointer(&self, start: u64) -> Option<u16> {
+        let end = self.urgent_end?;
+        (start < end).then(|| (end - start).min(u16::MAX as u64) as u16)
+    }
+
+    /**
+     * 发送缓冲区剩余空间, 在途数据在确认之前仍然占用空间; 同时受内存预算限制
+     */
+    pub fn send_space(&self) -> usize {
+        self.send_capacity.saturating_sub(self.send_buf.len() + self.retransmit.bytes_queued())
+            .min(self.memory_share.saturating_sub(self.send_charged))
+            .min(self.memory.available())
+    }
+
+    /**
+     * 发送缓冲区加上在途的字节数, 即发送队列深度
+     */
+    pub fn send_queued(&self) -> usize {
+        self.send_buf.len() + self.retransmit.bytes_queued()
+    }
+
+    /**
+     * 应用可以写入: 连接可以发送数据, 缓冲区有空间, 且发送队列不在高水位
+     */
+    pub fn writable(&self) -> bool {
+        matches!(self.state, TcpState::Established | TcpState::CloseWait) && self.send_space() > 0 && !self.send_above_high
+    }
+
+    /**
+     * 未读数据处于高水位, 服务端可以据此暂停接受新的工作
+     */
+    pub fn read_high(&self) -> bool {
+        self.recv_above_high
+    }
+
+    /**
+     * 按当前队列深度更新两侧的水位状态, 变为可写时记下一次待通知
+     */
+    pub fn update_watermarks(&mut self) {
+        self.send_above_high = self.options.send_watermarks.is_some_and(|marks| marks.above(self.send_queued(), self.send_above_high));
+        self.recv_above_high = self.options.recv_watermarks.is_some_and(|marks| marks.above(self.receiver.unread(), self.recv_above_high));
+        let writable = self.writable();
+        if writable && !self.writable {
+            self.writable_edge = true;
+        }
+        self.writable = writable;
+    }
+
+    /**
+     * 连接每次由不可写变为可写时调用 callback, 调用发生在 poll 中; 注册之前的边沿不补发
+     */
+    pub fn on_writable(&mut self, callback: Box<dyn FnMut()>) {
+        self.writable_edge = false;
+        self.on_writable = Some(callback);
+    }
+
+    /**
+     * 有待通知的可写边沿时调用回调
+     */
+    pub fn notify_writable(&mut self) {
+        if !std::mem::take(&mut self.writable_edge) {
+            return;
+        }
+        if let Some(callback) = &mut self.on_writable {
+            callback();
+        }
+    }
+
+    /**
+     * 把发送缓冲区中的数据在对端窗口和拥塞窗口允许的范围内按 MSS 切分发出
+     */
+    pub fn poll_send(&mut self) -> Vec<TcpSegment> {
+        std::iter::from_fn(|| self.next_segment()).collect()
+    }
+
+    /**
+     * 窗口允许时切出下一个数据段
+     */
+    pub fn next_segment(&mut self) -> Option<TcpSegment> {
+        if !self.fast_open_accepted() && !matches!(self.state, TcpState::Established | TcpState::CloseWait | TcpState::FinWait1 | TcpState::LastAck) {
+            return None;
+        }
+        if self.send_buf.is_empty() {
+            return self.next_fin();
+        }
+        let window = (self.snd_wnd as usize).min(self.cwnd as usize);
+        let room = window.saturating_sub(self.retransmit.bytes_queued());
+        if room == 0 {
+            return None;
+        }
+        let n = self.send_buf.len().min(self.send_mss() as usize).min(room);
+        if self.nagle_holds(n) || !self.pacer.try_send(n, self.clock_ms) {
+            return None;
+        }
+        let data: Vec<u8> = self.send_buf.drain(..n).collect();
+        let urgent = self.urgent_pointer(self.sent_bytes);
+        self.sent_bytes += n as u64;
+        if self.urgent_end.is_some_and(|end| end <= self.sent_bytes) {
+            self.urgent_end = None;
+        }
+        self.last_data_ms = self.clock_ms;
+        let seq = self.snd_nxt();
+        self.retransmit.push_at(seq, data.clone(), self.clock_ms);
+        self.start_rto();
+        let mut flags = TcpFlags::ACK;
+        if self.ends_write() {
+            flags |= TcpFlags::PSH;
+        }
+        if self.fin_queued && self.send_buf.is_empty() {
+            self.fin_seq = Some(seq.wrapping_add(n as u32)); // 最后一段数据捎带 FIN, 省掉一个单独的 FIN
+            flags |= TcpFlags::FIN | TcpFlags::PSH;
+        }
+        if flags.contains(TcpFlags::PSH) {
+            self.retransmit.mark_push();
+        }
+        if let Some(ptr) = urgent {
+            flags |= TcpFlags::URG;
+            self.retransmit.mark_urgent(ptr);
+        }
+        let window = self.advertise_window();
+        Some(self.outgoing(TcpSegment::new(self.s_port, self.d_port, seq, self.receiver.ack_num(), 5, 0,
+            flags, self.window_field(window), urgent.unwrap_or(0), vec![], data)))
+    }
+
+    /**
+     * Nagle (RFC 896): 打开时, 有未确认的数据就不发不满 MSS 的段
+     * flush 之前写入的数据和 close 之后的数据不扣留
+     */
+    fn nagle_holds(&self, n: usize) -> bool {
+        self.options.nagle
+            && n < self.send_mss() as usize
+            && !self.retransmit.is_empty()
+            && self.push_until <= self.sent_bytes
+            && !self.fin_queued
+    }
+
+    /**
+     * 刚切出的段包含某次 write 的最后一个字节
+     */
+    fn ends_write(&mut self) -> bool {
+        let mut ended = false;
+        while self.write_ends.front().is_some_and(|end| *end <= self.sent_bytes) {
+            self.write_ends.pop_front();
+            ended = true;
+        }
+        ended
+    }
+
+    /**
+     * 已写入的数据不再被 Nagle 扣留, 报文段留给之后的 poll_send 切出
+     */
+    pub fn push(&mut self) {
+        self.push_until = self.sent_bytes + self.send_buf.len() as u64;
+    }
+
+    /**
+     * 数据发完之后的 FIN, 不受窗口限制, 占用一个序号
+     */
+    fn next_fin(&mut self) -> Option<TcpSegment> {
+        if !self.fin_queued || self.fin_seq.is_some() {
+            return None;
+        }
+        let seq = self.snd_nxt();
+        self.fin_seq = Some(seq);
+        self.start_rto();
+        let window = self.advertise_window();
+        Some(self.outgoing(TcpSegment::new(self.s_port, self.d_port, seq, self.receiver.ack_num(), 5, 0,
+            TcpFlags::ACK | TcpFlags::FIN, self.window_field(window), 0, vec![], vec![])))
+    }
+
+    /**
+     * 发送缓冲区中还有没发出的数据, 或者还有 FIN 要发
+     */
+    pub fn has_pending_send(&self) -> bool {
+        !self.send_buf.is_empty() || (self.fin_queued && self.fin_seq.is_none())
+    }
+
+    /**
+     * 有数据可读, 或对端已经关闭(读到 EOF)
+     */
+    pub fn readable(&self) -> bool {
+        self.receiver.unread() > 0 || self.receiver.fin_received()
+    }
+
+    pub fn error(&self) -> Option<&ConnectionError> {
+        self.error.as_ref()
+    }
+
+    /**
+     * 一轮 poll 结束时调用, 把合并的纯 ACK 交给发送端
+     */
+    pub fn flush_acks(&mut self) {
+        if let Some(summary) = self.acks.take() {
+            self.apply_ack(summary, &[]);
+        }
+    }
+
+    fn apply_ack(&mut self, summary: AckSummary, sack_blocks: &[(u32, u32)]) {
+        self.ack_work += 1;
+        let fin_outstanding = self.fin_outstanding();
+        if seq_lt(summary.ack, self.snd_una) {
+            return; // 旧 ACK
+        }
+        let advanced = seq_lt(self.snd_una, summary.ack);
+        if advanced {
+            if !self.syn_outstanding() {
+                self.grow_cwnd(summary.ack.wrapping_sub(self.snd_una), summary.absorbed, summary.ack);
+            }
+            // Karn: 只对没有重传过的段取样, 补上重传空洞的累计确认不取样
+            let sample = self.retransmit.newest_delivered(summary.ack, &[]).filter(|_| !self.retransmit.acks_retransmitted(summary.ack));
+            if let Some((sent_ms, _)) = sample {
+                self.rtt.on_sample(self.clock_ms.saturating_sub(sent_ms));
+                if let (Some(tuner), Some(srtt_ms)) = (&mut self.rcvbuf, self.rtt.srtt_ms()) {
+                    tuner.on_rtt_sample(srtt_ms);
+                }
+                self.update_pacing_rate();
+            }
+            self.snd_una = summary.ack;
+            self.dup_acks = summary.dup_acks;
+            self.icmp_advice = None; // 路径恢复了
+        } else if !self.retransmit.is_empty() {
+            self.dup_acks += summary.dup_acks; // 没有在途数据时不算重复 ACK
+        }
+        self.snd_wnd = (summary.window as u32) << self.send_shift();
+        if self.loss_detection == LossDetection::Rack {
+            if let Some((sent_ms, end)) = self.retransmit.newest_delivered(summary.ack, sack_blocks) {
+                self.rack.on_delivered(sent_ms, end, self.clock_ms);
+            }
+        }
+        self.retransmit.ack_received(summary.ack, sack_blocks);
+        self.release_send();
+        if fin_outstanding && !self.fin_outstanding() {
+            self.fin_acked();
+        }
+        if advanced {
+            self.restart_rto(); // RFC 6298 5.3
+        }
+    }
+
+    /**
+     * 退还已确认数据占用的预算
+     */
+    fn release_send(&mut self) {
+        let held = self.send_buf.len() + self.retransmit.bytes_queued();
+        if self.send_charged > held {
+            self.memory.release(MemoryComponent::SendBuffer, self.send_charged - held);
+            self.send_charged = held;
+        }
+    }
+
+    /**
+     * 已通告还没用完的窗口
+     */
+    fn promised_window(&self) -> usize {
+        let ack = self.receiver.ack_num();
+        if seq_lt(ack, self.rcv_adv) { self.rcv_adv.wrapping_sub(ack) as usize } else { 0 }
+    }
 
+    /**
+     * 现在通告的话能给出的窗口: 接收缓冲区的空闲空间, 不超过已预留加上预算剩余的部分
+     */
+    fn window_offer(&self) -> u32 {
+        let want = self.receiver.window_size().min(self.max_window()) as usize;
+        let reservable = (self.recv_charged + self.memory.available()).min(self.memory_share).saturating_sub(self.receiver.unread());
+        want.min(reservable).max(self.promised_window()) as u32
+    }
+
+    /**
+     * 确定要通告的窗口并更新 rcv_adv; 窗口在通告之前先从预算中预留, 预留不到时窗口随之缩小
+     * 已经通告出去的部分不会收回
+     */
+    fn advertise_window(&mut self) -> u32 {
+        let unread = self.receiver.unread();
+        let want = self.receiver.window_size().min(self.max_window()) as usize;
+        let target = (unread + want).min(self.memory_share);
+        if target > self.recv_charged {
+            self.recv_charged += self.memory.charge_up_to(MemoryComponent::RecvBuffer, target - self.recv_charged);
+        }
+        let window = self.recv_charged.saturating_sub(unread).min(want).max(self.promised_window()) as u32;
+        self.rcv_adv = self.receiver.ack_num().wrapping_add(window);
+        self.release_recv();
+        window
+    }
+
+    /**
+     * 对端发来的窗口字段左移的位数; 协商了 Window Scale 才有, SYN 与 SYN|ACK 中的窗口不缩放
+     */
+    fn send_shift(&self) -> u8 {
+        self.negotiated.and_then(|negotiated| negotiated.window_scale).map_or(0, |(snd, _)| snd)
+    }
+
+    /**
+     * 本端通告的窗口右移的位数
+     */
+    fn recv_shift(&self) -> u8 {
+        self.negotiated.and_then(|negotiated| negotiated.window_scale).map_or(0, |(_, rcv)| rcv)
+    }
+
+    /**
+     * 窗口字段能表示的最大窗口; 握手完成之前 (包括 SYN|ACK) 不缩放
+     */
+    fn max_window(&self) -> u32 {
+        let shift = if self.syn_outstanding() { 0 } else { self.recv_shift() };
+        (u16::MAX as u32) << shift
+    }
+
+    /**
+     * 通告的窗口写进首部时右移; 低位舍去, 对端看到的窗口不超过 rcv_adv
+     */
+    fn window_field(&self, window: u32) -> u16 {
+        (window >> self.recv_shift()).min(u16::MAX as u32) as u16
+    }
+
+    /**
+     * 退还超出 未读数据 + 已通告窗口 的预留
+     */
+    fn release_recv(&mut self) {
+        let floor = self.receiver.unread() + self.promised_window();
+        if self.recv_charged > floor {
+            self.memory.release(MemoryComponent::RecvBuffer, self.recv_charged - floor);
+            self.recv_charged = floor;
+        }
+    }
+
+    /**
+     * 构造一个携带当前 ack 与窗口的 ACK 报文, 由调用方发出
+     */
+    pub fn make_ack(&mut self) -> TcpSegment {
+        let window = self.advertise_window();
+        self.window_update.on_advertised(window);
+        self.outgoing(TcpSegment::new(self.s_port, self.d_port, self.snd_nxt(), self.receiver.ack_num(), 5, 0, TcpFlags::ACK,
+            self.window_field(window), 0, vec![], vec![]))
+    }
+
+    /**
+     * 定时处理, 返回需要立即发出的报文; 由表在 next_timer_ms 到期时调用
+     * 目前只有零窗口重新打开后的窗口更新补发和空闲超时放弃连接的 RST; 打开自动调整时顺带调整接收缓冲区
+     */
+    pub fn tick(&mut self, now_ms: u64) -> Option<TcpSegment> {
+        let now_ms = now_ms.max(self.clock_ms);
+        self.clock_ms = now_ms;
+        if self.state == TcpState::TimeWait && now_ms >= self.time_wait_until {
+            self.set_state(TcpState::Closed, now_ms);
+        }
+        if let Some(rst) = self.check_idle(now_ms) {
+            return Some(rst);
+        }
+        self.check_stall(now_ms);
+        self.tune_rcvbuf(now_ms);
+        if self.window_update.poll(self.window_offer(), now_ms) {
+            return Some(self.make_ack());
+        }
+        None
+    }
+
+    /**
+     * 应用层读取数据, 窗口随之打开
+     */
+    pub fn read(&mut self, max: usize) -> Vec<u8> {
+        let data = self.receiver.read(max);
+        self.on_read(data.len());
+        data
+    }
+
+    /**
+     * 只读到对端紧急数据的结尾为止, 没有未读完的紧急数据时返回空
+     */
+    pub fn read_urgent(&mut self, max: usize) -> Vec<u8> {
+        let data = self.receiver.read_urgent(max);
+        self.on_read(data.len());
+        data
+    }
+
+    /**
+     * 从读位置到对端紧急数据结尾的字节数, 没有未读完的紧急数据时为 None
+     */
+    pub fn urgent_pending(&self) -> Option<usize> {
+        self.receiver.urgent_pending()
+    }
+
+    fn on_read(&mut self, n: usize) {
+        if let Some(tuner) = &mut self.rcvbuf {
+            tuner.on_read(n);
+            tuner.observe(self.receiver.unread());
+        }
+        self.release_recv();
+    }
+
+    /**
+     * 缩小时保留已经通告出去的窗口, 对端按旧窗口发来的数据仍然放得下
+     */
+    fn tune_rcvbuf(&mut self, now_ms: u64) {
+        let Some(tuner) = &mut self.rcvbuf else { return };
+        let Some(capacity) = tuner.tick(self.receiver.unread(), now_ms) else { return };
+        let ack = self.receiver.ack_num();
+        let promised = if seq_lt(ack, self.rcv_adv) { self.rcv_adv.wrapping_sub(ack) as usize } else { 0 };
+        self.receiver.resize_capacity(capacity.max(self.receiver.unread() + promised));
+    }
+
+    /**
+     * 当前接收缓冲区容量
+     */
+    pub fn recv_capacity(&self) -> usize {
+        self.receiver.capacity()
+    }
+
+    fn snd_nxt(&self) -> u32 {
+        let flags = self.syn_outstanding() as u32 + self.fin_outstanding() as u32;
+        self.snd_una.wrapping_add(self.retransmit.bytes_queued() as u32 + flags)
+    }
+
+    /**
+     * SYN 已经发出还没有被确认, 第一个数据字节在它之后
+     */
+    fn syn_outstanding(&self) -> bool {
+        self.syn_seq == Some(self.snd_una)
+    }
+
+    /**
+     * FIN 已经发出还没有被确认
+     */
+    fn fin_outstanding(&self) -> bool {
+        self.fin_seq.is_some_and(|fin| seq_le(self.snd_una, fin))
+    }
+
+    #[cfg(test)]
+    pub fn dup_acks(&self) -> u32 {
+        self.dup_acks
+    }
+
+    /**
+     * 应当立即重传第一个空洞
+     * DupAck: 重复 ACK 达到阈值且该段在本轮还没有重传过; Rack: 有段按发送时间判定丢失
+     */
+    pub fn fast_retransmit_due(&self) -> bool {
+        match self.loss_detection {
+            LossDetection::DupAck => {
+                self.dup_acks >= DUP_ACK_THRESHOLD && self.retransmit.first_hole().is_some_and(|segment| segment.retransmits == 0)
+            }
+            LossDetection::Rack => !self.rack.detect(&self.retransmit, self.clock_ms).0.is_empty(),
+        }
+    }
+
+    /**
+     * Rack 模式下一次可能判定丢包的时刻, 到时调用 tick 之后再检查 fast_retransmit_due
+     */
+    pub fn loss_timer_ms(&self) -> Option<u64> {
+        match self.loss_detection {
+            LossDetection::DupAck => None,
+            LossDetection::Rack => self.rack.detect(&self.retransmit, self.clock_ms).1,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn ack_work(&self) -> u64 {
+        self.ack_work
+    }
+
+    pub fn state(&self) -> TcpState {
+        self.state
+    }
+
+    /**
+     * 切换状态并记入环形缓冲区, 满了丢掉最旧的一条
+     */
+    pub fn set_state(&mut self, to: TcpState, now_ms: u64) {
+        if to == self.state {
+            return;
+        }
+        if self.transitions.len() == TRANSITION_HISTORY {
+            self.transitions.pop_front();
+        }
+        self.transitions.push_back(StateTransition { at_ms: now_ms, from: self.state, to });
+        let (id, from) = (self.id(), self.state);
+        self.tracer.emit(|sink| sink.on_state_change(&id, from, to));
+        self.state = to;
+    }
+
+    /**
+     * 之后的状态变化交给 sink, None 取消
+     */
+    pub fn set_trace_sink(&mut self, sink: Option<SharedTraceSink>) {
+        self.tracer = Tracer::new(sink);
+    }
+
+    /**
+     * 最近一次 segment_received 中报文段的处理结果; 签名校验失败的报文段没有进入连接, 由调用方记为丢弃
+     */
+    pub fn last_verdict(&self) -> SegmentVerdict {
+        self.verdict
+    }
+
+    /**
+     * 记入最近事件; 任何报文段进出都说明连接没有卡死
+     */
+    fn record_segment(&mut self, direction: SegmentDirection, segment: &TcpSegment) {
+        if self.recent.len() == STALL_EVENTS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(SegmentEvent {
+            at_ms: self.clock_ms,
+            direction,
+            seq: segment.seq,
+            ack: segment.ack,
+            len: segment.data.len(),
+            flags: segment.ctrl,
+            window: segment.win_size,
+        });
+        self.last_segment_ms = self.clock_ms;
+        self.stalled = false;
+    }
+
+    /**
+     * Established 且有未确认、未发出或未读的数据, 却超过 stall_timeout_ms 没有报文段进出时生成一次诊断
+     * 没有待处理数据的空闲连接不算卡死
+     */
+    fn check_stall(&mut self, now_ms: u64) {
+        if self.stall_deadline().is_none_or(|deadline| now_ms < deadline) {
+            return;
+        }
+        self.stalled = true;
+        self.stall = Some(StallDiagnosis {
+            id: self.id(),
+            at_ms: now_ms,
+            silent_ms: now_ms - self.last_segment_ms,
+            snd_una: self.snd_una,
+            snd_nxt: self.snd_nxt(),
+            rcv_nxt: self.receiver.ack_num(),
+            snd_wnd: self.snd_wnd,
+            rcv_wnd: self.window_offer(),
+            cwnd: self.cwnd,
+            unacked: self.retransmit.bytes_queued(),
+            unsent: self.send_buf.len(),
+            unread: self.receiver.unread(),
+            loss_timer_ms: self.loss_timer_ms(),
+            idle_deadline_ms: self.idle_deadline(),
+            window_updates_sent: self.window_update.sent(),
+            recent: self.recent.iter().copied().collect(),
+        });
+    }
+
+    /**
+     * 看门狗下一次生成诊断的时刻, 不满足卡死的前提时为 None
+     */
+    fn stall_deadline(&self) -> Option<u64> {
+        let timeout = self.stall_timeout_ms?;
+        let pending = self.retransmit.bytes_queued() > 0 || !self.send_buf.is_empty() || self.receiver.unread() > 0;
+        (!self.stalled && self.state == TcpState::Established && pending).then_some(self.last_segment_ms + timeout)
+    }
+
+    /**
+     * 取走看门狗生成的诊断
+     */
+    pub fn take_stall(&mut self) -> Option<StallDiagnosis> {
+        self.stall.take()
+    }
+
+    /**
+     * 导出连接的只读快照, 用于卡死后的事后排查
+     */
+    pub fn snapshot(&self) -> ConnectionSnapshot {
+        ConnectionSnapshot {
+            id: self.id(),
+            state: self.state,
+            transitions: self.transitions.iter().copied().collect(),
+            receiver: self.receiver.snapshot(),
+            options: self.options,
+            icmp_advice: self.icmp_advice,
+        }
+    }
+
+    pub fn id(&self) -> ConnectionId {
+        ConnectionId { s_ip: self.s_ip, s_port: self.s_port, d_ip: self.d_ip, d_port: self.d_port }
+    }
+
+    /**
+     * 主动打开: 进入 SynSent, 返回带 MSS 选项的 SYN
+     * 连接的 MSS 与窗口来自创建时的配置, 一般由 RoutingTable::tcp_config_for 按目的地址给出
+     */
+    pub fn connect(&mut self, isn: u32, now_ms: u64) -> TcpSegment {
+        self.connect_fast_open(isn, None, now_ms)
+    }
+
+    /**
+     * 带 TFO 选项的主动打开 (RFC 7413): cookie 为空时向对端请求 cookie, 已写入的数据留到握手之后;
+     * 带着以前得到的 cookie 时, SYN 捎带发送缓冲区开头至多一个 MSS 的数据
+     */
+    pub fn connect_fast_open(&mut self, isn: u32, cookie: Option<&[u8]>, now_ms: u64) -> TcpSegment {
+        self.snd_una = isn;
+        self.syn_seq = Some(isn);
+        self.set_state(TcpState::SynSent, now_ms);
+        let syn = self.syn(cookie);
+        self.handshake = Some(syn.clone());
+        self.restart_rto();
+        syn
+    }
+
+    fn syn(&mut self, cookie: Option<&[u8]>) -> TcpSegment {
+        let isn = self.snd_una;
+        let window = self.window_offer() as u16;
+        let mut options = self.offer.encode(self.clock_ms as u32, 0);
+        let mut data = vec![];
+        if let Some(cookie) = cookie {
+            options.extend(fast_open_words(cookie));
+            if !cookie.is_empty() {
+                let n = self.send_buf.len().min(self.send_mss() as usize);
+                data = self.send_buf.drain(..n).collect();
+                self.sent_bytes += n as u64;
+                self.retransmit.push_at(isn.wrapping_add(1), data.clone(), self.clock_ms);
+            }
+        }
+        self.outgoing(TcpSegment::new(self.s_port, self.d_port, isn, 0, 5 + options.len() as u8, 0, TcpFlags::SYN, window, 0,
+            options, data))
+    }
+
+    /**
+     * SYN|ACK 没有确认 SYN 捎带的数据 (对端不支持 TFO 或 cookie 无效): 放回发送缓冲区, 握手完成后作为普通数据发出
+     */
+    fn requeue_syn_data(&mut self) {
+        let data: Vec<u8> = self.retransmit.iter().flat_map(|in_flight| in_flight.data.iter().copied()).collect();
+        self.retransmit = RetransmitQueue::new();
+        self.sent_bytes -= data.len() as u64;
+        for byte in data.into_iter().rev() {
+            self.send_buf.push_front(byte);
+        }
+    }
+
+    /**
+     * 出口链路的校验和策略, TrustLink 时发出的报文段不计算校验和
+     */
+    pub fn set_checksum_policy(&mut self, policy: ChecksumPolicy) {
+        self.checksum_policy = policy;
+    }
+
+    /**
+     * 发出前的最后处理: 协商了 Timestamps 时加上 TSopt, 计算校验和, 配置了密钥时加上 MD5 签名
+     */
+    fn outgoing(&mut self, mut segment: TcpSegment) -> TcpSegment {
+        if self.negotiated.is_some_and(|negotiated| negotiated.timestamps) && !segment.SYN() && !segment.RST() {
+            // 协商了 Timestamps 之后每个报文段都带 TSopt (RFC 7323 3.2)
+            segment.options.extend([0x0101_080a, self.clock_ms as u32, self.ts_recent]);
+            segment.hl += 3;
+        }
+        self.record_segment(SegmentDirection::Tx, &segment);
+        match &self.md5_key {
+            Some(key) => md5_signature::sign(&mut segment, self.s_ip, self.d_ip, key),
+            None if self.checksum_policy.generate() => {
+                segment.generate_checksum(self.s_ip, self.d_ip);
+            }
+            None => segment.clear_checksum(),
+        }
+        segment
+    }
+
+    /**
+     * 两个方向都没有数据超过 timeout 时按 idle_action 关闭连接, 之后的读写返回 IdleTimeout
+     * 计时从设置时开始, 发出数据的时刻按最近一次 tick 或收到报文段的时刻计; None 关闭空闲超时
+     */
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle_timeout_ms = timeout.map(|timeout| timeout.as_millis() as u64);
+        self.last_data_ms = self.clock_ms;
+    }
+
+    pub fn set_idle_action(&mut self, action: IdleAction) {
+        self.idle_action = action;
+    }
+
+    /**
+     * 空闲超时到期的时刻, 没有设置或连接不在可能超时的状态时为 None
+     * 只有 Established 和 CloseWait 计时: 正在进行的关闭不受空闲超时打断
+     */
+    pub fn idle_deadline(&self) -> Option<u64> {
+        let timeout = self.idle_timeout_ms?;
+        matches!(self.state, TcpState::Established | TcpState::CloseWait).then_some(self.last_data_ms + timeout)
+    }
+
+    fn check_idle(&mut self, now_ms: u64) -> Option<TcpSegment> {
+        if self.idle_deadline().is_none_or(|deadline| now_ms < deadline) {
+            return None;
+        }
+        self.error = Some(ConnectionError::IdleTimeout);
+        match self.idle_action {
+            IdleAction::Close => {
+                self.close(now_ms);
+                None
+            }
+            IdleAction::Abort => self.abort(now_ms),
+        }
+    }
+
+    pub fn set_socket_options(&mut self, options: SocketOptions) {
+        self.options = options;
+    }
+
+    pub fn socket_options(&self) -> &SocketOptions {
+        &self.options
+    }
+
+    /**
+     * 把本连接发出的报文段封装成 IP 数据报, 首部字段取自套接字选项
+     */
+    pub fn datagram(&self, segment: &TcpSegment, id: u16, config: &Ipv4Config) -> Ipv4Datagram {
+        self.options.datagram(self.s_ip, self.d_ip, id, segment, config)
+    }
+
+    /**
+     * 重传第一个没有被 SACK 的在途段, 确认号和窗口取当前值
+     * 握手中原样重传第一次发出的 SYN 或 SYN|ACK, 选项与第一次逐字节相同
+     */
+    pub fn retransmission(&mut self) -> Option<TcpSegment> {
+        if matches!(self.state, TcpState::SynSent | TcpState::SynReceived) {
+            self.restart_rto();
+            return self.handshake.clone();
+        }
+        let seq = self.retransmit.first_hole()?.seq;
+        self.on_loss(self.fast_retransmit_due());
+        let in_flight = self.retransmit.retransmit_at(seq, self.clock_ms)?;
+        let (data, urgent, psh) = (in_flight.data.clone(), in_flight.urgent, in_flight.psh);
+        self.restart_rto();
+        let mut flags = TcpFlags::ACK;
+        flags.set(TcpFlags::PSH, psh);
+        flags.set(TcpFlags::URG, urgent.is_some());
+        if self.fin_seq == Some(seq.wrapping_add(data.len() as u32)) {
+            flags |= TcpFlags::FIN; // 原来捎带了 FIN, 重传时保留
+        }
+        let window = self.advertise_window();
+        Some(self.outgoing(TcpSegment::new(self.s_port, self.d_port, seq, self.receiver.ack_num(), 5, 0,
+            flags, self.window_field(window), urgent.unwrap_or(0), vec![], data)))
+    }
+
+    /**
+     * 有已经发出、还没有被确认的序号(数据、SYN 或 FIN)
+     */
+    fn outstanding(&self) -> bool {
+        !self.retransmit.is_empty() || self.syn_outstanding() || self.fin_outstanding()
+    }
+
+    /**
+     * 从当前时刻重新开始重传计时, 没有在途序号时停止
+     */
+    fn restart_rto(&mut self) {
+        self.rto_deadline = self.outstanding().then(|| self.clock_ms + self.rtt.rto_ms());
+    }
+
+    /**
+     * 发出新序号时, 计时没有在运行才开始 (RFC 6298 5.1)
+     */
+    fn start_rto(&mut self) {
+        if self.rto_deadline.is_none() {
+            self.restart_rto();
+        }
+    }
+
+    /**
+     * 应当调用 retransmission 的时刻; 连接已经结束或出错时为 None
+     */
+    pub fn rto_deadline_ms(&self) -> Option<u64> {
+        if self.error.is_some() || matches!(self.state, TcpState::Closed | TcpState::Listen | TcpState::TimeWait) {
+            return None;
+        }
+        self.rto_deadline
+    }
+
+    /**
+     * 下一次需要 tick 的时刻: TimeWait 到期、空闲超时、卡死检查、零窗口之后的窗口更新补发、
+     * 接收缓冲区调整周期、Rack 的丢包判定和 pacing 放行中最早的一个; 重传时刻见 rto_deadline_ms
+     */
+    pub fn next_timer_ms(&self) -> Option<u64> {
+        let time_wait = (self.state == TcpState::TimeWait).then_some(self.time_wait_until);
+        let window_update = self.window_update.next_deadline(self.window_offer());
+        let rcvbuf = self.rcvbuf.as_ref().filter(|_| !matches!(self.state, TcpState::Closed | TcpState::TimeWait))
+            .map(|tuner| tuner.next_tick_ms());
+        [time_wait, self.idle_deadline(), self.stall_deadline(), window_update, rcvbuf, self.loss_timer_ms(), self.next_send_ms()]
+            .into_iter().flatten().min()
+    }
+
+    /**
+     * 没有到期的定时器、不必 tick 的连接在发送之前由表把时钟推进到 now_ms, 发送时刻和 TSval 按它计
+     */
+    pub fn advance_clock(&mut self, now_ms: u64) {
+        self.clock_ms = self.clock_ms.max(now_ms);
+    }
+
+    /**
+     * 出口 MTU 变化(接口 MTU 或 PMTU 发现)后调用, 之后新发出的段缩小到放得进一个数据报
+     */
+    pub fn set_path_mtu(&mut self, mtu: u16) {
+        self.path_mtu = Some(mtu);
+    }
+
+    /**
+     * 发送时每段的数据上限: 不超过本端与对端 SYN 中的 MSS, 也不超过路径 MTU 减去首部(含 MD5 选项)
+     * 最小为 MIN_SEND_MSS, 路径 MTU 更小时只能由 IP 层分片
+     */
+    pub fn send_mss(&self) -> u16 {
+        let mss = match self.negotiated {
+            Some(negotiated) => self.mss.min(negotiated.peer_mss),
+            None => self.mss,
+        };
+        let Some(mtu) = self.path_mtu else {
+            return mss;
+        };
+        let overhead = 40 + if self.md5_key.is_some() { md5_signature::OPTION_LEN as u16 } else { 0 };
+        mss.min(mtu.saturating_sub(overhead)).max(MIN_SEND_MSS)
+    }
+
+    pub fn cwnd(&self) -> u32 {
+        self.cwnd
+    }
+
+    pub fn snd_wnd(&self) -> u32 {
+        self.snd_wnd
+    }
+
+    pub fn ssthresh(&self) -> u32 {
+        self.ssthresh
+    }
+
+    /**
+     * 打开 pacing 且有 RTT 样本之后的发送速率, 比特每秒
+     */
+    pub fn pacing_rate_bps(&self) -> Option<u64> {
+        self.pacer.rate_bps().filter(|_| self.pacer.enabled())
+    }
+
+    /**
+     * pacing 挡住了待发数据时, 下一段最早可以发出的时刻; 之前 poll 不会发出新数据
+     */
+    pub fn next_send_ms(&self) -> Option<u64> {
+        if self.send_buf.is_empty() {
+            return None;
+        }
+        self.pacer.next_send_ms(self.send_buf.len().min(self.send_mss() as usize))
+    }
+
+    /**
+     * 新数据被确认 (RFC 5681): 慢启动每个 ACK 至多增加一个 MSS, 拥塞避免每个 cwnd 的数据增加一个 MSS
+     * 快速恢复中的部分确认不增长, 确认到 recover 时结束恢复
+     * 在途数据没有用满 cwnd (受对端窗口或应用限制) 时也不增长 (RFC 7661), 否则 cwnd 会无限变大
+     */
+    fn grow_cwnd(&mut self, acked: u32, acks: u32, ack: u32) {
+        if let Some(recover) = self.recover {
+            if !seq_lt(ack, recover) {
+                self.recover = None;
+                self.fast_recovery = false;
+            } else if self.fast_recovery {
+                return;
+            }
+        }
+        let mss = self.send_mss() as u32;
+        if (self.retransmit.bytes_queued() as u32).saturating_add(mss) < self.cwnd {
+            return;
+        }
+        if self.cwnd < self.ssthresh {
+            self.cwnd = self.cwnd.saturating_add(acked.min(acks.max(1).saturating_mul(mss))).min(self.ssthresh.max(self.cwnd));
+        } else {
+            self.cwnd_acked += acked;
+            if self.cwnd_acked >= self.cwnd {
+                self.cwnd_acked -= self.cwnd;
+                self.cwnd = self.cwnd.saturating_add(mss);
+            }
+        }
+        self.update_pacing_rate();
+    }
+
+    /**
+     * 重传之前调用: 一次丢包恢复只在开始时把 ssthresh 减为在途数据的一半 (至少两个 MSS)
+     * 快速重传进入快速恢复, cwnd 降到 ssthresh; 超时重传 (包括快速恢复中的超时) cwnd 降到一个 MSS 重新慢启动
+     */
+    fn on_loss(&mut self, fast: bool) {
+        let recovering = self.recover.is_some_and(|recover| seq_lt(self.snd_una, recover));
+        if fast && recovering {
+            return;
+        }
+        let mss = self.send_mss() as u32;
+        if !recovering {
+            self.ssthresh = (self.retransmit.bytes_queued() as u32 / 2).max(2 * mss);
+            self.recover = Some(self.snd_nxt());
+        }
+        self.cwnd = if fast { self.ssthresh } else { mss };
+        self.fast_recovery = fast;
+        self.cwnd_acked = 0;
+        self.update_pacing_rate();
+    }
+
+    /**
+     * cwnd 或 SRTT 变化后重新计算 pacing 速率
+     */
+    fn update_pacing_rate(&mut self) {
+        if let Some(srtt_ms) = self.rtt.srtt_ms() {
+            self.pacer.set_rate(self.cwnd, srtt_ms);
+        }
+    }
+
+    pub fn rtt(&self) -> &RttEstimator {
+        &self.rtt
+    }
+
+    pub fn clock_ms(&self) -> u64 {
+        self.clock_ms
+    }
+
+    /**
+     * 用目的地缓存中同一对端以前的测量值起步: RTT 估计与 ssthresh
+     */
+    pub fn seed_metrics(&mut self, metrics: &DestinationMetrics) {
+        self.rtt.seed(metrics.srtt_ms, metrics.rttvar_ms);
+        self.ssthresh = metrics.ssthresh;
+        self.update_pacing_rate();
+    }
+
+    /**
+     * 连接结束 (TimeWait 或 Closed) 后交出一次测量值供目的地缓存更新; 没有 RTT 样本时没有可留的
+     */
+    pub fn take_destination_metrics(&mut self) -> Option<DestinationMetrics> {
+        if self.metrics_saved || !matches!(self.state, TcpState::TimeWait | TcpState::Closed) {
+            return None;
+        }
+        let srtt_ms = self.rtt.srtt_ms()?;
+        self.metrics_saved = true;
+        Some(DestinationMetrics { srtt_ms, rttvar_ms: self.rtt.rttvar_ms(), ssthresh: self.ssthresh })
+    }
+
+}
+
+/**
+ * 报文段是收到的还是发出的
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentDirection {
+    Rx,
+    Tx,
+}
+
+/**
+ * 卡死诊断中记录的一个报文段
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentEvent {
+    pub at_ms: u64,
+    pub direction: SegmentDirection,
+    pub seq: u32,
+    pub ack: u32,
+    pub len: usize,
+    pub flags: TcpFlags,
+    pub window: u16,
+}
+
+impl fmt::Display for SegmentEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = match self.direction {
+            SegmentDirection::Rx => "rx",
+            SegmentDirection::Tx => "tx",
+        };
+        write!(f, "{}ms {} [{}] seq={} ack={} len={} win={}", self.at_ms, direction, self.flags, self.seq, self.ack, self.len, self.window)
+    }
+}
+
+/**
+ * 传输卡死时的现场: 两个方向的窗口和序号、定时器状态、最近的报文段, recent 按时间先后排列
+ * snd_wnd 是对端通告的窗口, rcv_wnd 是本端此刻能通告的窗口
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StallDiagnosis {
+    pub id: ConnectionId,
+    pub at_ms: u64,
+    pub silent_ms: u64,
+    pub snd_una: u32,
+    pub snd_nxt: u32,
+    pub rcv_nxt: u32,
+    pub snd_wnd: u32,
+    pub rcv_wnd: u32,
+    pub cwnd: u32,
+    pub unacked: usize,
+    pub unsent: usize,
+    pub unread: usize,
+    pub loss_timer_ms: Option<u64>,
+    pub idle_deadline_ms: Option<u64>,
+    pub window_updates_sent: u32,
+    pub recent: Vec<SegmentEvent>,
+}
+
+impl fmt::Display for StallDiagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "stall {} at {}ms: no segments for {}ms", self.id, self.at_ms, self.silent_ms)?;
+        writeln!(f, "  snd_una {} snd_nxt {} snd_wnd {} cwnd {}", self.snd_una, self.snd_nxt, self.snd_wnd, self.cwnd)?;
+        writeln!(f, "  rcv_nxt {} rcv_wnd {}", self.rcv_nxt, self.rcv_wnd)?;
+        writeln!(f, "  unacked {} unsent {} unread {}", self.unacked, self.unsent, self.unread)?;
+        writeln!(f, "  loss timer {:?} idle deadline {:?} window updates sent {}", self.loss_timer_ms, self.idle_deadline_ms,
+            self.window_updates_sent)?;
+        write!(f, "  recent:")?;
+        for event in &self.recent {
+            write!(f, "\n    {}", event)?;
+        }
+        Ok(())
+    }
+}
+
+/**
+ * 连接快照, transitions 按时间先后排列
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionSnapshot {
+    pub id: ConnectionId,
+    pub state: TcpState,
+    pub transitions: Vec<StateTransition>,
+    pub receiver: ReceiverSnapshot,
+    pub options: SocketOptions,
+    pub icmp_advice: Option<IcmpAdvice>,
+}
+
+impl fmt::Display for ConnectionSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "connection {} [{:?}]", self.id, self.state)?;
+        let ttl = self.options.ttl.map_or("default".to_string(), |ttl| ttl.to_string());
+        writeln!(f, "  ttl {} tos {:#04x} keepalive {:?}", ttl, self.options.tos, self.options.keepalive)?;
+        if let Some(advice) = self.icmp_advice {
+            writeln!(f, "  icmp unreachable code {} at {}ms", advice.code, advice.when_ms)?;
+        }
+        writeln!(f, "  transitions:")?;
+        for t in &self.transitions {
+            writeln!(f, "    {:>10}ms {:?} -> {:?}", t.at_ms, t.from, t.to)?;
+        }
+        write!(f, "{}", self.receiver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::packet::PacketBuilder;
+    use crate::transport::tcp_segment::{TcpCtrlFlag, TcpSegment};
+    use crate::utils::wire::WireSerialize;
+
+    #[test]
+    fn test_snapshot_mid_handshake() {
+        let mut conn = TcpConnection::new(0x0a000001, 80, 0x0a000002, 51000);
+        conn.set_state(TcpState::Listen, 0);
+        let syn = TcpSegment::new(51000, 80, 1000, 0, 5, 0, TcpCtrlFlag::SYN as u16, 0, 0, vec![], vec![]);
+        conn.receiver.segment_received(&syn);
+        conn.set_state(TcpState::SynReceived, 12);
+        // 跳过 [1, 5), 先到的 [5, 9) 留在缓冲区
+        let early = TcpSegment::new(51000, 80, 1005, 0, 5, 0, TcpCtrlFlag::ACK as u16, 0, 0, vec![], vec![1, 2, 3, 4]);
+        conn.receiver.segment_received(&early);
+        let mut options = SocketOptions { ttl: Some(5), ..SocketOptions::default() };
+        options.set_dscp(Dscp::EF);
+        conn.set_socket_options(options);
+
+        let snap = conn.snapshot();
+        assert_eq!(snap.state, TcpState::SynReceived);
+        assert_eq!(snap.transitions, vec![
+            StateTransition { at_ms: 0, from: TcpState::Closed, to: TcpState::Listen },
+            StateTransition { at_ms: 12, from: TcpState::Listen, to: TcpState::SynReceived },
+        ]);
+        assert!(snap.receiver.syn_received);
+        assert_eq!(snap.receiver.initial_seq, 1000);
+        assert_eq!(snap.receiver.buffered, vec![(1005, 1009)]);
+        assert_eq!(snap.receiver.gaps.len(), 1);
+        assert_eq!(snap.receiver.gaps[0].1, 1005);
+        assert_eq!(snap.receiver.drops.total(), 0);
+
+        let text = snap.to_string();
+        assert!(text.starts_with("connection 10.0.0.1:80 -> 10.0.0.2:51000 [SynReceived]"));
+        assert!(text.contains("Listen -> SynReceived"));
+        assert!(text.contains("gaps: ["));
+        assert!(text.contains("ttl 5 tos 0xb8"));
+        assert_eq!(snap.options.dscp(), Dscp::EF);
+    }
+
+    #[test]
+    fn test_transition_ring_is_bounded() {
+        let mut conn = TcpConnection::new(1, 2, 3, 4);
+        for i in 0..40u64 {
+            let to = if i % 2 == 0 { TcpState::Listen } else { TcpState::Closed };
+            conn.set_state(to, i);
+        }
+        let snap = conn.snapshot();
+        assert_eq!(snap.transitions.len(), TRANSITION_HISTORY);
+        assert_eq!(snap.transitions[0].at_ms, 40 - TRANSITION_HISTORY as u64);
+    }
+
+    /**
+     * 10000 个纯 ACK, 每轮 poll 到达 100 个, 最后 3 个是重复 ACK
+     * 每个 ACK 单独 flush 时结果应完全相同, 只是处理次数多 100 倍
+     */
+    fn ack_flood(per_poll: usize) -> TcpConnection {
+        const ISN: u32 = 0xffff_0000;
+        let mut conn = TcpConnection::new(1, 80, 2, 51000);
+        conn.snd_una = ISN;
+        for i in 0..10_000u32 {
+            conn.retransmit.push(ISN.wrapping_add(i * 10), vec![0; 10]);
+        }
+        let acks = (1..=9_997u32).map(|i| ISN.wrapping_add(i * 10))
+            .chain([ISN.wrapping_add(99_970); 3]);
+        for (i, ack) in acks.enumerate() {
+            let segment = TcpSegment::new(51000, 80, 1, ack, 5, 0, TcpCtrlFlag::ACK as u16, 1000, 0, vec![], vec![]);
+            conn.segment_received(&segment, 0);
+            if (i + 1) % per_poll == 0 {
+                conn.flush_acks();
+            }
+        }
+        conn.flush_acks();
+        conn
+    }
+
+    #[test]
+    fn test_ack_flood_is_coalesced() {
+        let batched = ack_flood(100);
+        let single = ack_flood(1);
+        for conn in [&batched, &single] {
+            assert_eq!(conn.snd_una, 0xffff_0000u32.wrapping_add(99_970));
+            assert_eq!(conn.snd_wnd, 1000);
+            assert_eq!(conn.dup_acks(), 3);
+            assert_eq!(conn.retransmit.len(), 3);
+        }
+        assert_eq!(single.ack_work(), 10_000);
+        assert_eq!(batched.ack_work(), 100);
+    }
+
+    #[test]
+    fn test_data_segment_flushes_pending_acks() {
+        let mut conn = TcpConnection::new(1, 80, 2, 51000);
+        conn.retransmit.push(0, vec![0; 100]);
+        let ack = TcpSegment::new(51000, 80, 1, 50, 5, 0, TcpCtrlFlag::ACK as u16, 1000, 0, vec![], vec![]);
+        conn.segment_received(&ack, 0);
+        assert_eq!(conn.snd_una, 0);
+        let data = TcpSegment::new(51000, 80, 1, 50, 5, 0, TcpCtrlFlag::ACK as u16, 1000, 0, vec![], vec![7]);
+        conn.segment_received(&data, 0);
+        assert_eq!(conn.snd_una, 50);
+        assert_eq!(conn.dup_acks(), 0); // 带数据的报文不算重复 ACK
+        assert_eq!(conn.ack_work(), 2);
+    }
+
+    #[test]
+    fn test_connect_uses_route_metrics() {
+        use crate::net::route::{RouteMetrics, RoutingTable};
+        use crate::transport::tcp_option::TcpOption;
+
+        let mut routes = RoutingTable::new();
+        routes.add(0x7f000000, 8, None);
+        routes.add(0x0a000000, 24, None);
+        routes.set_metrics((0x7f000000, 8), RouteMetrics { mtu: Some(16384), initial_cwnd: Some(32), recv_window: None });
+        routes.set_metrics((0x0a000000, 24), RouteMetrics { mtu: Some(1500), ..RouteMetrics::default() });
+
+        let base = TcpConfig::default();
+        let mut advertised = vec![];
+        for dst in [0x7f000001, 0x0a000002] {
+            let config = routes.tcp_config_for(dst, &base);
+            let mut conn = TcpConnection::with_config(0x0a000001, 40000, dst, 80, &config, 0);
+            let syn = conn.connect(1000, 0);
+            assert_eq!(conn.state(), TcpState::SynSent);
+            assert!(syn.SYN());
+            let mss = syn.parsed_options().unwrap().into_iter().find_map(|option| match option {
+                TcpOption::Mss(mss) => Some(mss),
+                _ => None,
+            });
+            advertised.push((mss, conn.cwnd()));
+        }
+        assert_eq!(advertised, vec![(Some(16344), 32 * 16344), (Some(1460), 10 * 1460)]);
+    }
+
+    /**
+     * 已建立的连接: 对端 ISN 为 5000 并发来 100 字节, 本端从 9000 发出 300 字节
+     */
+    fn established() -> TcpConnection {
+        let mut conn = TcpConnection::new(0x0a000001, 80, 0x0a000002, 51000);
+        conn.segment_received(&TcpSegment::new(51000, 80, 5000, 0, 5, 0, TcpFlags::SYN, 4000, 0, vec![], vec![]), 0);
+        conn.segment_received(&TcpSegment::new(51000, 80, 5000, 9000, 5, 0, TcpFlags::ACK, 4000, 0, vec![], vec![1; 100]), 0);
+        conn.snd_una = 9000;
+        for i in 0..3 {
+            conn.retransmit.push(9000 + i * 100, vec![0; 100]);
+        }
+        conn.flush_acks();
+        assert_eq!(conn.take_reply().map(|ack| ack.ack), Some(5100)); // 数据段的 ACK
+        assert!(conn.take_reply().is_none());
+        conn
+    }
+
+    #[test]
+    fn test_keepalive_gets_pure_ack() {
+        let mut conn = established();
+        let rcv_nxt = conn.receiver.ack_num();
+        let probe = TcpSegment::new(51000, 80, rcv_nxt.wrapping_sub(1), 9000, 5, 0, TcpFlags::ACK, 4000, 0, vec![], vec![]);
+        for _ in 0..4 {
+            assert!(conn.segment_received(&probe, 10));
+        }
+        conn.flush_acks();
+        assert_eq!(conn.dup_acks(), 0);
+        assert_eq!(conn.snapshot().receiver.drops.total(), 0);
+
+        let reply = conn.take_reply().unwrap();
+        let expected = PacketBuilder::new().ipv4(0x0a000001, 0x0a000002).tcp(80, 51000)
+            .flags(TcpFlags::ACK).seq(9300).ack(rcv_nxt).window(conn.receiver.window_size() as u16)
+            .build();
+        assert_eq!(reply.serialize(), expected[20..]);
+        assert!(conn.take_reply().is_none()); // 四个探测合并成一个回复
+    }
+
+    #[test]
+    fn test_window_update_is_not_a_dup_ack() {
+        let mut conn = established();
+        let rcv_nxt = conn.receiver.ack_num();
+        let ack = |win: u16| TcpSegment::new(51000, 80, rcv_nxt, 9100, 5, 0, TcpFlags::ACK, win, 0, vec![], vec![]);
+        for per_ack in [true, false] {
+            let mut conn = established();
+            // 第一个推进 snd_una, 后两个是重复 ACK, 第四个只改变了窗口
+            for segment in [ack(4000), ack(4000), ack(4000), ack(8000)] {
+                conn.segment_received(&segment, 10);
+                if per_ack {
+                    conn.flush_acks();
+                }
+            }
+            conn.flush_acks();
+            assert_eq!(conn.snd_una, 9100);
+            assert_eq!(conn.snd_wnd, 8000);
+            assert_eq!(conn.dup_acks(), 2);
+            assert!(!conn.fast_retransmit_due());
+        }
+
+        // 序号过时的窗口更新同样生效
+        let stale = TcpSegment::new(51000, 80, rcv_nxt.wrapping_sub(1), 9000, 5, 0, TcpFlags::ACK, 100, 0, vec![], vec![]);
+        conn.segment_received(&stale, 10);
+        conn.flush_acks();
+        assert_eq!(conn.snd_wnd, 100);
+        assert_eq!(conn.dup_acks(), 0);
+    }
+
+    #[test]
+    fn test_md5_keys_must_match() {
+        let handshake = |client_key: &[u8], server_key: &[u8]| {
+            let mut client = TcpConnection::new(0x0a000002, 40000, 0x0a000001, 179);
+            let mut server = TcpConnection::new(0x0a000001, 179, 0x0a000002, 40000);
+            client.set_md5_key(Some(client_key.to_vec()));
+            server.set_md5_key(Some(server_key.to_vec()));
+            server.set_state(TcpState::Listen, 0);
+
+            let syn = client.connect(1000, 0);
+            assert!(md5_signature::verify(&syn, 0x0a000002, 0x0a000001, client_key));
+            server.syn_received(&syn, &TfoDecision::Normal, 1);
+            let reply = server.make_ack();
+            let client_accepted = client.segment_received(&reply, 2);
+            (server.state(), client_accepted, server.snapshot().receiver.drops.get(DropReason::BadMd5Signature))
+        };
+        assert_eq!(handshake(b"bgp-peer", b"bgp-peer"), (TcpState::SynReceived, true, 0));
+        assert_eq!(handshake(b"bgp-peer", b"bgp-peeR"), (TcpState::Listen, false, 1));
+
+        // 没有签名的报文段也被丢弃
+        let mut server = TcpConnection::new(0x0a000001, 179, 0x0a000002, 40000);
+        server.set_md5_key(Some(b"bgp-peer".to_vec()));
+        let mut plain = TcpConnection::new(0x0a000002, 40000, 0x0a000001, 179);
+        assert!(!server.segment_received(&plain.connect(1, 0), 0));
+    }
+
+    #[test]
+    fn test_fast_open_data_on_syn() {
+        use crate::transport::fast_open::{fast_open_words, TfoCookies, TfoListener};
+
+        let config = TcpConfig { fast_open: true, ..TcpConfig::default() };
+        let mut listener = TfoListener::new(&config);
+        let syn = |options: Vec<u32>| {
+            TcpSegment::new(51000, 80, 1000, 0, 5 + options.len() as u8, 0, TcpFlags::SYN, 65535, 0, options, b"GET /".to_vec())
+        };
+
+        // 第一次连接: 请求 cookie, 数据丢弃
+        let decision = listener.on_syn(2, &syn(fast_open_words(&[])));
+        let TfoDecision::IssueCookie(cookie) = decision else { panic!() };
+        let mut conn = TcpConnection::with_config(1, 80, 2, 51000, &config, 0);
+        conn.syn_received(&syn(fast_open_words(&[])), &decision, 0);
+        assert_eq!(conn.state(), TcpState::SynReceived);
+        assert!(conn.read(100).is_empty());
+
+        // 带上有效 cookie: 握手完成前就能读到数据
+        let decision = listener.on_syn(2, &syn(fast_open_words(&cookie)));
+        assert_eq!(decision, TfoDecision::AcceptData);
+        let mut conn = TcpConnection::with_config(1, 80, 2, 51000, &config, 0);
+        conn.syn_received(&syn(fast_open_words(&cookie)), &decision, 0);
+        assert_eq!(conn.state(), TcpState::SynReceived);
+        assert_eq!(conn.read(100), b"GET /");
+
+        // 伪造的 cookie 退回普通握手
+        let forged = TfoCookies::with_secret([1, 0]).generate(2);
+        let decision = listener.on_syn(2, &syn(fast_open_words(&forged)));
+        assert!(matches!(decision, TfoDecision::IssueCookie(_)));
+        let mut conn = TcpConnection::with_config(1, 80, 2, 51000, &config, 0);
+        conn.syn_received(&syn(fast_open_words(&forged)), &decision, 0);
+        assert!(conn.read(100).is_empty());
+    }
+
+    /**
+     * 发送端没有持续定时器: 收到零窗口后只能等窗口更新
+     * 接收端的第一个窗口更新丢失, 靠 tick 补发的窗口更新完成传输
+     */
+    #[test]
+    fn test_lost_window_update_is_reannounced() {
+        const ISN: u32 = 7000;
+        let config = TcpConfig { mss: 100, recv_buffer: 400, window_update_interval_ms: 200, ..TcpConfig::default() };
+        let mut conn = TcpConnection::with_config(1, 80, 2, 51000, &config, 0);
+        let payload: Vec<u8> = (0..2000u32).map(|i| i as u8).collect();
+        let segment = |seq: u32, ctrl: TcpFlags, data: &[u8]| {
+            TcpSegment::new(51000, 80, seq, 0, 5, 0, ctrl, 65535, 0, vec![], data.to_vec())
+        };
+
+        conn.segment_received(&segment(ISN, TcpFlags::SYN, &[]), 0);
+        let ack = conn.make_ack();
+        // 发送端从接收端确认的位置开始发数据
+        let (data_seq, mut peer_wnd) = (ack.ack, ack.win_size as u32);
+        let mut sent = 0usize;
+        let mut received = vec![];
+        let mut dropped_update = false;
+        let mut reannounced = 0;
+
+        let mut now = 0;
+        while received.len() < payload.len() && now < 60_000 {
+            now += 10;
+            // 发送端: 窗口内每轮最多发一个 MSS
+            let n = (payload.len() - sent).min(peer_wnd as usize).min(100);
+            if n > 0 {
+                let seq = data_seq.wrapping_add(sent as u32);
+                conn.segment_received(&segment(seq, TcpFlags::ACK, &payload[sent..sent + n]), now);
+                sent += n;
+                let ack = conn.make_ack();
+                assert_eq!(ack.ack, data_seq.wrapping_add(sent as u32));
+                peer_wnd = ack.win_size as u32;
+            }
+            // 应用层只在窗口为零之后才一次性读空缓冲区
+            if conn.receiver.window_size() == 0 {
+                received.extend(conn.read(usize::MAX));
+                let update = conn.make_ack();
+                if !dropped_update {
+                    dropped_update = true; // 第一个窗口更新丢失
+                } else {
+                    peer_wnd = update.win_size as u32;
+                }
+            }
+            if let Some(update) = conn.tick(now) {
+                assert!(update.win_size > 0);
+                reannounced += 1;
+                peer_wnd = update.win_size as u32;
+            }
+        }
+        assert_eq!(received, payload);
+        assert_eq!(reannounced, 1);
+        assert!(now > 200);
+    }
+
+    /**
+     * 127.0.0.1:40000 -> 127.0.0.1:80, 两端在同一张连接表里, 报文只经过回环接口
+     */
+    #[test]
+    fn test_loopback_connection() {
+        use std::collections::HashMap;
+        use crate::config::Ipv4Config;
+        use crate::net::ipv4::Ipv4Datagram;
+        use crate::net::loopback::{egress, Egress, LoopbackInterface};
+        use crate::utils::wire::WireSerialize;
+
+        const LO: u32 = 0x7f000001;
+        let config = Ipv4Config::default();
+        let mut lo = LoopbackInterface::new();
+        let mut device: Vec<Vec<u8>> = vec![]; // 真实设备上发出的帧
+        let mut send = |lo: &mut LoopbackInterface, s_addr: u32, d_addr: u32, segment: TcpSegment| {
+            let payload = segment.serialize();
+            let datagram = Ipv4Datagram::new(4, 5, 0, (20 + payload.len()) as u16, 0, 0b010, 0, 64, 6, s_addr, d_addr, payload);
+            match egress(d_addr, &[], &config) {
+                Egress::Loopback => assert!(lo.transmit(&datagram)),
+                Egress::Device => device.push(datagram.serialize()),
+            }
+        };
+
+        let mut table: HashMap<ConnectionId, TcpConnection> = HashMap::new();
+        let mut client = TcpConnection::new(LO, 40000, LO, 80);
+        let syn = client.connect(5000, 0);
+        let client_id = client.id();
+        table.insert(client_id, client);
+        send(&mut lo, LO, LO, syn);
+
+        let payload: Vec<u8> = (0..60 * 1024u32).map(|i| i as u8).collect();
+        let mut sent = false;
+        while let Some(received) = lo.receive() {
+            let (datagram, arrival) = received.unwrap();
+            assert!(arrival.loopback);
+            let segment = TcpSegment::try_deserialize(datagram.payload()).unwrap();
+            assert!(segment.check_checksum(datagram.s_addr(), datagram.d_addr()));
+            let id = ConnectionId::for_incoming(datagram.s_addr(), datagram.d_addr(), &segment);
+            if segment.SYN() {
+                // 监听端口上的新连接, 标识与客户端的互为镜像
+                assert_eq!(id, client_id.reversed());
+                let mut server = TcpConnection::new(id.s_ip, id.s_port, id.d_ip, id.d_port);
+                server.syn_received(&segment, &TfoDecision::Normal, 0);
+                let reply = server.make_ack();
+                table.insert(id, server);
+                send(&mut lo, LO, LO, reply);
+                continue;
+            }
+            let conn = table.get_mut(&id).unwrap();
+            conn.segment_received(&segment, 0);
+            if id == client_id && !sent {
+                // 整个 60 KB 作为一个报文段发出, 回环 MTU 放得下
+                sent = true;
+                let data = TcpSegment::new(40000, 80, segment.ack, 0, 5, 0, TcpFlags::ACK, 65535, 0, vec![], payload.clone());
+                send(&mut lo, LO, LO, conn.outgoing(data));
+            } else if id != client_id {
+                let ack = conn.make_ack();
+                send(&mut lo, LO, LO, ack);
+            }
+        }
+
+        assert_eq!(table.len(), 2);
+        let server = table.get_mut(&client_id.reversed()).unwrap();
+        assert_eq!(server.read(usize::MAX), payload);
+        assert!(device.is_empty());
+        assert_eq!(lo.stats().too_big, 0);
+    }
+
+    /**
+     * 主动打开的一端与被动打开的一端完成握手
+     */
+    fn handshake_pair() -> (TcpConnection, TcpConnection) {
+        let mut client = TcpConnection::new(1, 40000, 2, 80);
+        let syn = client.connect(1000, 0);
+        let mut server = TcpConnection::new(2, 80, 1, 40000);
+        server.syn_received(&syn, &TfoDecision::Normal, 0);
+        let syn_ack = server.syn_ack(9000);
+        client.segment_received(&syn_ack, 0);
+        server.segment_received(&client.make_ack(), 0);
+        server.flush_acks(); // 纯 ACK 先进了合并队列, 对端窗口在这里生效
+        assert_eq!((client.state(), server.state()), (TcpState::Established, TcpState::Established));
+        (client, server)
+    }
+
+    #[test]
+    fn test_fast_retransmit_halves_ssthresh() {
+        let (mut client, mut server) = handshake_pair();
+        let mss = client.send_mss() as u32;
+        client.write(&vec![5; 10 * mss as usize]).unwrap();
+        let segments = client.poll_send();
+        assert_eq!(segments.len(), 10); // 初始窗口 10 段
+
+        // 第一段丢失, 后面三段各引起一个重复 ACK
+        for segment in &segments[1..4] {
+            server.segment_received(segment, 10);
+            let dup_ack = server.take_reply().unwrap();
+            client.segment_received(&dup_ack, 10);
+        }
+        client.flush_acks();
+        assert!(client.fast_retransmit_due());
+        assert_eq!(client.retransmission().unwrap().seq, segments[0].seq);
+        assert_eq!((client.ssthresh(), client.cwnd()), (5 * mss, 5 * mss));
+
+        // 快速恢复中的部分确认不增长 cwnd; 确认全部数据后进入拥塞避免
+        server.segment_received(&segments[0], 20);
+        client.segment_received(&server.take_reply().unwrap(), 20);
+        client.flush_acks();
+        assert_eq!(client.cwnd(), 5 * mss);
+        for segment in &segments[4..] {
+            server.segment_received(segment, 30);
+        }
+        client.segment_received(&server.make_ack(), 30);
+        client.flush_acks();
+        assert_eq!(client.cwnd(), 6 * mss);
+    }
+
+    #[test]
+    fn test_timeout_restarts_slow_start() {
+        let (mut client, mut server) = handshake_pair();
+        let mss = client.send_mss() as u32;
+        client.write(&vec![5; 4 * mss as usize]).unwrap();
+        let segments = client.poll_send();
+
+        // 整窗丢失: 超时重传时 cwnd 降到一个 MSS, 再次超时不再减 ssthresh
+        client.retransmission().unwrap();
+        assert_eq!((client.ssthresh(), client.cwnd()), (2 * mss, mss));
+        client.retransmission().unwrap();
+        assert_eq!(client.ssthresh(), 2 * mss);
+
+        // 慢启动: 每个确认新数据的 ACK 增加一个 MSS
+        server.segment_received(&segments[0], 10);
+        client.segment_received(&server.take_reply().unwrap(), 10);
+        client.flush_acks();
+        assert_eq!(client.cwnd(), 2 * mss);
+    }
+
+    #[test]
+    fn test_pacing_holds_new_data_until_next_send() {
+        let (mut client, _server) = handshake_pair();
+        let config = TcpConfig { pacing: true, pacing_gain_percent: 100, ..TcpConfig::default() };
+        client.pacer = Pacer::new(&config, 0);
+        client.pacer.set_rate(14_600, 100); // 146 字节/毫秒
+        client.write(&[7; 5000]).unwrap();
+
+        // 桶里的两段突发额度先发出, 第三段要等令牌补足一个 MSS
+        assert_eq!(client.poll_send().len(), 2);
+        assert_eq!(client.next_send_ms(), Some(10));
+        client.tick(9);
+        assert!(client.poll_send().is_empty());
+        client.tick(10);
+        assert_eq!(client.poll_send().len(), 1);
+    }
+
+    #[test]
+    fn test_simultaneous_close_passes_through_closing() {
+        let (mut client, mut server) = handshake_pair();
+        server.write(b"bye").unwrap();
+        client.close(10);
+        server.close(10);
+        let client_fin = client.poll_send().pop().unwrap();
+        let server_fin = server.poll_send().pop().unwrap();
+        assert!(client_fin.FIN() && server_fin.FIN());
+        assert_eq!(server_fin.data, b"bye"); // 数据在 FIN 之前, 捎带在同一段里
+
+        // 对端的 FIN 没有确认本端的 FIN: FinWait1 -> Closing, 之前的数据照常交付
+        assert_eq!(server_fin.ack, client_fin.seq);
+        client.segment_received(&server_fin, 20);
+        assert_eq!(client.state(), TcpState::Closing);
+        assert_eq!(client.read(usize::MAX), b"bye");
+        let fin_ack = client.take_reply().unwrap();
+        assert_eq!(fin_ack.ack, server_fin.seq.wrapping_add(4));
+
+        // 重传的 FIN 再确认一次, 状态不变
+        client.segment_received(&server_fin, 30);
+        assert_eq!(client.state(), TcpState::Closing);
+        assert_eq!(client.take_reply().unwrap().ack, fin_ack.ack);
+
+        // 本端 FIN 的 ACK 到达: Closing -> TimeWait
+        server.segment_received(&client_fin, 20);
+        assert_eq!(server.state(), TcpState::Closing);
+        let server_ack = server.take_reply().unwrap();
+        client.segment_received(&server_ack, 40);
+        client.flush_acks();
+        assert_eq!(client.state(), TcpState::TimeWait);
+        server.segment_received(&fin_ack, 40);
+        server.flush_acks();
+        assert_eq!(server.state(), TcpState::TimeWait);
+    }
+
+    #[test]
+    fn test_fin_acking_our_fin_skips_closing() {
+        let (mut client, mut server) = handshake_pair();
+        client.close(10);
+        let client_fin = client.poll_send().pop().unwrap();
+        server.segment_received(&client_fin, 20);
+        server.close(20);
+        let server_fin = server.poll_send().pop().unwrap();
+        assert!(server_fin.FIN() && server_fin.ack == client_fin.seq.wrapping_add(1));
+
+        // FIN 同时确认了本端的 FIN, 直接进入 TimeWait
+        client.segment_received(&server_fin, 30);
+        assert_eq!(client.state(), TcpState::TimeWait);
+    }
+
+    /**
+     * 发送端每 10 ms 把对端窗口发满, 返回传输结束时的接收缓冲区容量和未读数据的最大值
+     * read_per_step 为应用层每步读取的字节数; 两端都提出 Window Scale, 窗口能超过 64 KB
+     */
+    fn autotuned_transfer(read_per_step: usize, steps: u64) -> (TcpConnection, usize) {
+        let config = TcpConfig {
+            rcvbuf_autotune: true, rcvbuf_initial: 8192, recv_buffer: 200_000, offer_window_scale: true, window_scale: 2,
+            ..TcpConfig::default()
+        };
+        let mut conn = TcpConnection::with_config(1, 80, 2, 51000, &config, 0);
+        let wscale = vec![0x0103_0302]; // NOP, Window Scale 2
+        conn.syn_received(&TcpSegment::new(51000, 80, 7000, 0, 6, 0, TcpFlags::SYN, 65535, 0, wscale, vec![]), &TfoDecision::Normal, 0);
+        conn.syn_ack(9000);
+        conn.segment_received(&TcpSegment::new(51000, 80, 7001, 9001, 5, 0, TcpFlags::ACK, 65535, 0, vec![], vec![]), 0);
+        let window = |conn: &mut TcpConnection| (conn.make_ack().win_size as usize) << conn.recv_shift();
+        let (mut seq, mut peer_wnd) = (conn.make_ack().ack, window(&mut conn));
+        let mut high_water = 0;
+        for step in 1..=steps {
+            let now = step * 10;
+            while peer_wnd > 0 {
+                let n = peer_wnd.min(1460);
+                conn.segment_received(&TcpSegment::new(51000, 80, seq, 9001, 5, 0, TcpFlags::ACK, 65535, 0, vec![], vec![0; n]), now);
+                seq = seq.wrapping_add(n as u32);
+                peer_wnd = window(&mut conn);
+            }
+            high_water = high_water.max(conn.receiver.unread());
+            conn.read(read_per_step);
+            conn.tick(now);
+            peer_wnd = window(&mut conn);
+        }
+        (conn, high_water)
+    }
+
+    #[test]
+    fn test_rcvbuf_grows_for_fast_reader() {
+        let (mut conn, _) = autotuned_transfer(usize::MAX, 100);
+        assert_eq!(conn.recv_capacity(), 200_000);
+        // 超过 64 KB 的窗口靠移位通告
+        let field = conn.make_ack().win_size;
+        assert_eq!((field as usize) << conn.recv_shift(), 200_000);
+    }
+
+    #[test]
+    fn test_rcvbuf_plateaus_for_slow_reader() {
+        let (conn, high_water) = autotuned_transfer(200, 100);
+        assert_eq!(conn.recv_capacity(), 8192);
+        assert_eq!(high_water, 8192);
+    }
+}
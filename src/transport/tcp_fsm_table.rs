@@ -0,0 +1,253 @@
+use std::fmt;
+use std::ops::BitOr;
+
+use super::tcp_connection::TcpState;
+use super::tcp_segment::{TcpFlags, TcpSegment};
+
+/**
+ * 驱动状态机的事件: 对端发来的报文段、应用层调用或定时器到期
+ * 报文段的序号和确认号由驱动按连接当前的序号空间填写, 除非另有说明都落在窗口内
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsmEvent {
+    Connect,   // 应用层主动打开
+    Close,     // 应用层关闭发送方向
+    Abort,     // 应用层放弃连接
+    Syn,       // 对端的 SYN; 握手完成之后是一个新 ISN 的 SYN
+    SynAck,    // 确认本端 SYN 的 SYN|ACK
+    Ack,       // 确认本端发出的全部序号
+    Data,      // 1 字节按序数据, 不确认本端的 FIN
+    Fin,       // 按序的 FIN, 不确认本端的 FIN; 已经收到过对端的 FIN 时是它的重传
+    FinAck,    // 按序的 FIN, 同时确认本端的 FIN
+    Rst,       // RST|ACK, 确认号可接受
+    BareRst,   // 不带 ACK 的 RST
+    AckUnsent, // 确认号超过 SND.NXT, 即确认了还没有发出的数据
+    Timeout,   // 过去 2*MSL
+}
+
+/**
+ * 一次迁移要求的动作, 可以按位组合
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FsmActions(u8);
+
+impl FsmActions {
+    pub const NONE: FsmActions = FsmActions(0);
+    pub const SEND_SYN: FsmActions = FsmActions(1);
+    pub const SEND_SYN_ACK: FsmActions = FsmActions(2);
+    pub const SEND_ACK: FsmActions = FsmActions(4);     // 纯 ACK 或带数据的段
+    pub const SEND_FIN: FsmActions = FsmActions(8);
+    pub const SEND_RST: FsmActions = FsmActions(16);
+    pub const SIGNAL_USER: FsmActions = FsmActions(32); // 连接(未 accept 时为监听端口)出现新的就绪事件
+    pub const START_TIMER: FsmActions = FsmActions(64); // (重新) 开始 2*MSL 定时器
+
+    /**
+     * 所有 SEND_* 动作, 与事件之后发出的报文段比较
+     */
+    pub const SENDS: FsmActions = FsmActions(31);
+
+    const NAMES: [(FsmActions, &'static str); 7] = [
+        (FsmActions::SEND_SYN, "SEND_SYN"),
+        (FsmActions::SEND_SYN_ACK, "SEND_SYN_ACK"),
+        (FsmActions::SEND_ACK, "SEND_ACK"),
+        (FsmActions::SEND_FIN, "SEND_FIN"),
+        (FsmActions::SEND_RST, "SEND_RST"),
+        (FsmActions::SIGNAL_USER, "SIGNAL_USER"),
+        (FsmActions::START_TIMER, "START_TIMER"),
+    ];
+
+    pub const fn contains(self, other: FsmActions) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /**
+     * 置位的动作个数; 每个 SEND_* 动作对应恰好一个报文段
+     */
+    pub const fn count(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub const fn intersection(self, other: FsmActions) -> FsmActions {
+        FsmActions(self.0 & other.0)
+    }
+
+    /**
+     * 发出的一个报文段对应的 SEND_* 动作, 按 SYN、RST、FIN 的优先级归类
+     */
+    pub fn for_segment(segment: &TcpSegment) -> FsmActions {
+        let ctrl = segment.ctrl;
+        if ctrl.contains(TcpFlags::SYN | TcpFlags::ACK) {
+            FsmActions::SEND_SYN_ACK
+        } else if ctrl.contains(TcpFlags::SYN) {
+            FsmActions::SEND_SYN
+        } else if ctrl.contains(TcpFlags::RST) {
+            FsmActions::SEND_RST
+        } else if ctrl.contains(TcpFlags::FIN) {
+            FsmActions::SEND_FIN
+        } else {
+            FsmActions::SEND_ACK
+        }
+    }
+}
+
+impl BitOr for FsmActions {
+    type Output = FsmActions;
+
+    fn bitor(self, rhs: FsmActions) -> FsmActions {
+        FsmActions(self.0 | rhs.0)
+    }
+}
+
+impl fmt::Display for FsmActions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == 0 {
+            return write!(f, "NONE");
+        }
+        let names: Vec<&str> = Self::NAMES.iter().filter(|(bit, _)| self.contains(*bit)).map(|(_, name)| *name).collect();
+        write!(f, "{}", names.join("|"))
+    }
+}
+
+/**
+ * 状态转移表的一行: state 中发生 event 之后应处于 next, 并且恰好做了 actions
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsmRow {
+    pub state: TcpState,
+    pub event: FsmEvent,
+    pub next: TcpState,
+    pub actions: FsmActions,
+}
+
+const fn row(state: TcpState, event: FsmEvent, next: TcpState, actions: FsmActions) -> FsmRow {
+    FsmRow { state, event, next, actions }
+}
+
+const NONE: FsmActions = FsmActions::NONE;
+const SYN: FsmActions = FsmActions::SEND_SYN;
+const SYN_ACK: FsmActions = FsmActions::SEND_SYN_ACK;
+const ACK: FsmActions = FsmActions::SEND_ACK;
+const FIN: FsmActions = FsmActions::SEND_FIN;
+const RST: FsmActions = FsmActions::SEND_RST;
+const SIGNAL: FsmActions = FsmActions::SIGNAL_USER;
+const TIMER: FsmActions = FsmActions::START_TIMER;
+const ACK_SIGNAL: FsmActions = FsmActions(ACK.0 | SIGNAL.0);
+const RST_SIGNAL: FsmActions = FsmActions(RST.0 | SIGNAL.0);
+const ACK_SIGNAL_TIMER: FsmActions = FsmActions(ACK.0 | SIGNAL.0 | TIMER.0);
+const ACK_TIMER: FsmActions = FsmActions(ACK.0 | TIMER.0);
+
+/**
+ * RFC 793 3.9 的事件处理整理成的 (状态, 事件) 表, 按 RFC 5961 与 RFC 1337 修正:
+ * 已同步状态中的 SYN 回 challenge ACK, TimeWait 中的 RST 被忽略
+ * Closed 指没有连接也没有监听; 被动打开的连接在握手完成之前只是监听端口的半连接,
+ * SynReceived 的各行针对同时打开的连接
+ */
+pub const TCP_FSM_TABLE: &[FsmRow] = {
+    use FsmEvent::*;
+    use TcpState::*;
+    &[
+        row(Closed, Connect, SynSent, SYN),
+        row(Closed, Syn, Closed, RST),
+        row(Closed, Ack, Closed, RST),
+        row(Closed, Data, Closed, RST),
+        row(Closed, Rst, Closed, NONE),
+
+        row(Listen, Syn, SynReceived, SYN_ACK),
+        row(Listen, SynAck, Listen, RST),
+        row(Listen, Ack, Listen, RST),
+        row(Listen, Fin, Listen, RST),
+        row(Listen, Rst, Listen, NONE),
+
+        row(SynSent, SynAck, Established, ACK_SIGNAL),
+        row(SynSent, Syn, SynReceived, SYN_ACK), // 同时打开
+        row(SynSent, Rst, Closed, SIGNAL),       // 连接被拒绝
+        row(SynSent, BareRst, SynSent, NONE),    // 没有确认本端的 SYN, 丢弃
+        row(SynSent, AckUnsent, SynSent, RST),
+        row(SynSent, Close, Closed, NONE),
+        row(SynSent, Abort, Closed, SIGNAL),
+        row(SynSent, Timeout, SynSent, NONE),    // 握手的重传由应用层驱动
+
+        row(SynReceived, Ack, Established, SIGNAL),
+        row(SynReceived, Data, Established, ACK_SIGNAL), // 数据段确认了 SYN|ACK, 同样完成握手
+        row(SynReceived, Fin, CloseWait, ACK_SIGNAL),
+        row(SynReceived, SynAck, Established, ACK_SIGNAL), // 同时打开中对端的 SYN|ACK
+        row(SynReceived, Syn, SynReceived, SYN_ACK),  // 对端重传的 SYN
+        row(SynReceived, AckUnsent, SynReceived, RST),
+        row(SynReceived, Rst, Closed, SIGNAL), // 同时打开的连接被拒绝; 被动打开的半连接不在表里, 收到 RST 回到 Listen
+        row(SynReceived, Close, FinWait1, FIN),
+
+        row(Established, Data, Established, ACK_SIGNAL),
+        row(Established, Ack, Established, NONE),
+        row(Established, Fin, CloseWait, ACK_SIGNAL),
+        row(Established, Syn, Established, ACK),
+        row(Established, AckUnsent, Established, ACK),
+        row(Established, Rst, Closed, SIGNAL),
+        row(Established, BareRst, Closed, SIGNAL),
+        row(Established, Close, FinWait1, FIN),
+        row(Established, Abort, Closed, RST_SIGNAL),
+        row(Established, Timeout, Established, NONE),
+
+        row(FinWait1, Ack, FinWait2, NONE),
+        row(FinWait1, Data, FinWait1, ACK_SIGNAL),
+        row(FinWait1, Fin, Closing, ACK_SIGNAL),
+        row(FinWait1, FinAck, TimeWait, ACK_SIGNAL_TIMER),
+        row(FinWait1, AckUnsent, FinWait1, ACK),
+        row(FinWait1, Rst, Closed, SIGNAL),
+        row(FinWait1, Close, FinWait1, NONE),
+
+        row(FinWait2, Data, FinWait2, ACK_SIGNAL),
+        row(FinWait2, Fin, TimeWait, ACK_SIGNAL_TIMER),
+        row(FinWait2, Syn, FinWait2, ACK),
+        row(FinWait2, Rst, Closed, SIGNAL),
+        row(FinWait2, Close, FinWait2, NONE),
+
+        row(CloseWait, Close, LastAck, FIN),
+        row(CloseWait, Ack, CloseWait, NONE),
+        row(CloseWait, Fin, CloseWait, ACK),
+        row(CloseWait, Syn, CloseWait, ACK),
+        row(CloseWait, AckUnsent, CloseWait, ACK),
+        row(CloseWait, Rst, Closed, SIGNAL),
+
+        row(Closing, Ack, TimeWait, TIMER),
+        row(Closing, Fin, Closing, ACK),
+        row(Closing, Rst, Closed, NONE),
+
+        row(LastAck, Ack, Closed, NONE),
+        row(LastAck, Fin, LastAck, ACK),
+        row(LastAck, Syn, LastAck, ACK),
+        row(LastAck, Rst, Closed, NONE),
+
+        row(TimeWait, Fin, TimeWait, ACK_TIMER),
+        row(TimeWait, Syn, TimeWait, ACK),
+        row(TimeWait, Ack, TimeWait, NONE),
+        row(TimeWait, Rst, TimeWait, NONE),
+        row(TimeWait, Close, TimeWait, NONE),
+        row(TimeWait, Timeout, Closed, NONE),
+    ]
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_has_no_duplicate_rows() {
+        for (i, a) in TCP_FSM_TABLE.iter().enumerate() {
+            for b in &TCP_FSM_TABLE[i + 1..] {
+                assert!((a.state, a.event) != (b.state, b.event), "{:?} {:?}", a.state, a.event);
+            }
+        }
+        assert!(TCP_FSM_TABLE.len() >= 40);
+        assert!(TcpState::ALL.iter().all(|state| TCP_FSM_TABLE.iter().any(|row| row.state == *state)));
+    }
+
+    #[test]
+    fn test_actions_display() {
+        assert_eq!(FsmActions::NONE.to_string(), "NONE");
+        assert_eq!((FsmActions::SEND_ACK | FsmActions::SIGNAL_USER).to_string(), "SEND_ACK|SIGNAL_USER");
+        let syn_ack = TcpSegment::new(80, 40000, 1, 1, 5, 0, TcpFlags::SYN | TcpFlags::ACK, 100, 0, vec![], vec![]);
+        assert_eq!(FsmActions::for_segment(&syn_ack), FsmActions::SEND_SYN_ACK);
+        let rst = TcpSegment::new(80, 40000, 1, 1, 5, 0, TcpFlags::RST | TcpFlags::ACK, 0, 0, vec![], vec![]);
+        assert_eq!(FsmActions::for_segment(&rst), FsmActions::SEND_RST);
+    }
+}
@@ -0,0 +1,350 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::config::TcpConfig;
+
+/**
+ * TCP 选项(RFC 793 / 7323 / 2018), 从首部 options 字段解析
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TcpOption {
+    EndOfList,                          // kind 0
+    Nop,                                // kind 1
+    Mss(u16),                           // kind 2
+    WindowScale(u8),                    // kind 3
+    SackPermitted,                      // kind 4
+    Sack(Vec<(u32, u32)>),              // kind 5, 每块为 [左边界, 右边界)
+    Timestamps { val: u32, ecr: u32 },  // kind 8
+    Md5Signature([u8; 16]),             // kind 19 (RFC 2385)
+    FastOpen(Vec<u8>),                  // kind 34 (RFC 7413), 为空表示请求 cookie
+    Unknown { kind: u8, data: Vec<u8> },
+}
+
+impl TcpOption {
+    pub fn kind(&self) -> u8 {
+        match self {
+            TcpOption::EndOfList => 0,
+            TcpOption::Nop => 1,
+            TcpOption::Mss(_) => 2,
+            TcpOption::WindowScale(_) => 3,
+            TcpOption::SackPermitted => 4,
+            TcpOption::Sack(_) => 5,
+            TcpOption::Timestamps { .. } => 8,
+            TcpOption::Md5Signature(_) => 19,
+            TcpOption::FastOpen(_) => 34,
+            TcpOption::Unknown { kind, .. } => *kind,
+        }
+    }
+}
+
+impl fmt::Display for TcpOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TcpOption::EndOfList => write!(f, "End of Option List"),
+            TcpOption::Nop => write!(f, "No-Operation"),
+            TcpOption::Mss(mss) => write!(f, "MSS: {}", mss),
+            TcpOption::WindowScale(shift) => write!(f, "Window scale: {} (multiply by {})", shift, 1u32 << (*shift).min(31)),
+            TcpOption::SackPermitted => write!(f, "SACK permitted"),
+            TcpOption::Sack(blocks) => {
+                let blocks: Vec<String> = blocks.iter().map(|(l, r)| format!("{}-{}", l, r)).collect();
+                write!(f, "SACK: {}", blocks.join(" "))
+            }
+            TcpOption::Timestamps { val, ecr } => write!(f, "Timestamps: TSval {}, TSecr {}", val, ecr),
+            TcpOption::Md5Signature(digest) => {
+                let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+                write!(f, "MD5 signature: {}", hex)
+            }
+            TcpOption::FastOpen(cookie) if cookie.is_empty() => write!(f, "TFO: cookie request"),
+            TcpOption::FastOpen(cookie) => {
+                let hex: String = cookie.iter().map(|b| format!("{:02x}", b)).collect();
+                write!(f, "TFO: cookie {}", hex)
+            }
+            TcpOption::Unknown { kind, data } => write!(f, "Unknown (kind {}, {} bytes)", kind, data.len()),
+        }
+    }
+}
+
+/**
+ * TCP 选项解析错误, offset 为该选项在 options 字段中的起始位置
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TcpOptionError {
+    Truncated { kind: u8, offset: usize },
+    BadLength { kind: u8, len: u8, offset: usize },
+}
+
+impl fmt::Display for TcpOptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TcpOptionError::Truncated { kind, offset } => {
+                write!(f, "option kind {} at offset {} runs past the end of the header", kind, offset)
+            }
+            TcpOptionError::BadLength { kind, len, offset } => {
+                write!(f, "option kind {} at offset {} has invalid length {}", kind, offset, len)
+            }
+        }
+    }
+}
+
+impl Error for TcpOptionError {}
+
+impl TcpOptionError {
+    /**
+     * 出错的选项在 options 字段中的起始位置
+     */
+    pub fn offset(&self) -> usize {
+        match self {
+            TcpOptionError::Truncated { offset, .. } | TcpOptionError::BadLength { offset, .. } => *offset,
+        }
+    }
+}
+
+/**
+ * 逐个解析 options 字段, 返回每个选项及其在字段中的字节范围
+ * End of Option List 之后的字节是填充, 不再解析
+ */
+pub fn parse_options_with_spans(bytes: &[u8]) -> Result<Vec<(TcpOption, std::ops::Range<usize>)>, TcpOptionError> {
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let kind = bytes[i];
+        match kind {
+            0 => {
+                result.push((TcpOption::EndOfList, i..i + 1));
+                break;
+            }
+            1 => {
+                result.push((TcpOption::Nop, i..i + 1));
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        if i + 1 >= bytes.len() {
+            return Err(TcpOptionError::Truncated { kind, offset: i });
+        }
+        let len = bytes[i + 1];
+        if len < 2 {
+            return Err(TcpOptionError::BadLength { kind, len, offset: i });
+        }
+        let end = i + len as usize;
+        if end > bytes.len() {
+            return Err(TcpOptionError::Truncated { kind, offset: i });
+        }
+        let data = &bytes[i + 2..end];
+        let bad_length = TcpOptionError::BadLength { kind, len, offset: i };
+        let option = match kind {
+            2 if data.len() == 2 => TcpOption::Mss(u16::from_be_bytes([data[0], data[1]])),
+            3 if data.len() == 1 => TcpOption::WindowScale(data[0]),
+            4 if data.is_empty() => TcpOption::SackPermitted,
+            5 if !data.is_empty() && data.len().is_multiple_of(8) => TcpOption::Sack(
+                data.chunks(8)
+                    .map(|b| (u32::from_be_bytes([b[0], b[1], b[2], b[3]]), u32::from_be_bytes([b[4], b[5], b[6], b[7]])))
+                    .collect(),
+            ),
+            8 if data.len() == 8 => TcpOption::Timestamps {
+                val: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+                ecr: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            },
+            19 if data.len() == 16 => TcpOption::Md5Signature(data.try_into().unwrap()),
+            34 if data.is_empty() || (4..=16).contains(&data.len()) && data.len().is_multiple_of(2) => {
+                TcpOption::FastOpen(data.to_vec())
+            }
+            2..=5 | 8 | 19 | 34 => return Err(bad_length),
+            _ => TcpOption::Unknown { kind, data: data.to_vec() },
+        };
+        result.push((option, i..end));
+        i = end;
+    }
+    Ok(result)
+}
+
+pub fn parse_options(bytes: &[u8]) -> Result<Vec<TcpOption>, TcpOptionError> {
+    Ok(parse_options_with_spans(bytes)?.into_iter().map(|(option, _)| option).collect())
+}
+
+/**
+ * 不会失败的解析: 出错位置之前的选项照常返回, 从出错位置到字段结尾的原始字节
+ * (包括出错选项的 kind 和长度)整体作为一个 Unknown, kind 为出错选项的 kind
+ */
+pub fn parse_options_loose(bytes: &[u8]) -> Vec<TcpOption> {
+    match parse_options_with_spans(bytes) {
+        Ok(spans) => spans.into_iter().map(|(option, _)| option).collect(),
+        Err(error) => {
+            let offset = error.offset();
+            let mut options = parse_options(&bytes[..offset]).unwrap_or_default();
+            options.push(TcpOption::Unknown { kind: bytes[offset], data: bytes[offset..].to_vec() });
+            options
+        }
+    }
+}
+
+/**
+ * 握手中提出的选项
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SynOffer {
+    pub mss: u16,
+    pub window_scale: Option<u8>,
+    pub sack_permitted: bool,
+    pub timestamps: bool,
+}
+
+impl SynOffer {
+    pub fn from_config(config: &TcpConfig) -> Self {
+        SynOffer {
+            mss: config.mss,
+            window_scale: config.offer_window_scale.then_some(config.window_scale.min(14)),
+            sack_permitted: config.offer_sack,
+            timestamps: config.offer_timestamps,
+        }
+    }
+
+    /**
+     * 被动端的应答只包含双方都提出的选项; MSS 总是带上
+     */
+    pub fn answer(&self, peer: &[TcpOption]) -> SynOffer {
+        let peer = SynOffer::parse(peer);
+        SynOffer {
+            mss: self.mss,
+            window_scale: self.window_scale.filter(|_| peer.window_scale.is_some()),
+            sack_permitted: self.sack_permitted && peer.sack_permitted,
+            timestamps: self.timestamps && peer.timestamps,
+        }
+    }
+
+    /**
+     * 对端 SYN 中的选项, 没有 MSS 时取 RFC 1122 的默认值 536
+     */
+    pub fn parse(options: &[TcpOption]) -> SynOffer {
+        let mut offer = SynOffer { mss: DEFAULT_PEER_MSS, window_scale: None, sack_permitted: false, timestamps: false };
+        for option in options {
+            match option {
+                TcpOption::Mss(mss) => offer.mss = *mss,
+                TcpOption::WindowScale(shift) => offer.window_scale = Some((*shift).min(14)),
+                TcpOption::SackPermitted => offer.sack_permitted = true,
+                TcpOption::Timestamps { .. } => offer.timestamps = true,
+                _ => {}
+            }
+        }
+        offer
+    }
+
+    /**
+     * 编码为 options 字段, 顺序与 Linux 相同: MSS, SACK permitted + Timestamps, Window scale
+     */
+    pub fn encode(&self, ts_val: u32, ts_ecr: u32) -> Vec<u32> {
+        let mut words = vec![0x0204_0000 | self.mss as u32];
+        match (self.sack_permitted, self.timestamps) {
+            (true, true) => words.extend([0x0402_080a, ts_val, ts_ecr]),
+            (false, true) => words.extend([0x0101_080a, ts_val, ts_ecr]),
+            (true, false) => words.push(0x0101_0402),
+            (false, false) => {}
+        }
+        if let Some(shift) = self.window_scale {
+            words.push(0x0103_0300 | shift as u32);
+        }
+        words
+    }
+}
+
+/**
+ * 对端没有通告 MSS 时假定的值 (RFC 1122 4.2.2.6)
+ */
+pub const DEFAULT_PEER_MSS: u16 = 536;
+
+/**
+ * 握手协商的结果, 只在处理第一个 SYN 或 SYN|ACK 时确定一次
+ * window_scale 为 (发送方向移位, 接收方向移位), 双方都提出时才有
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NegotiatedOptions {
+    pub peer_mss: u16,
+    pub window_scale: Option<(u8, u8)>,
+    pub sack_permitted: bool,
+    pub timestamps: bool,
+}
+
+impl NegotiatedOptions {
+    /**
+     * ours 为本端在握手中发出的选项, peer 为对端 SYN 或 SYN|ACK 中的选项
+     */
+    pub fn negotiate(ours: &SynOffer, peer: &[TcpOption]) -> Self {
+        let peer = SynOffer::parse(peer);
+        NegotiatedOptions {
+            peer_mss: peer.mss,
+            window_scale: ours.window_scale.zip(peer.window_scale).map(|(rcv, snd)| (snd, rcv)),
+            sack_permitted: ours.sack_permitted && peer.sack_permitted,
+            timestamps: ours.timestamps && peer.timestamps,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_syn_options() {
+        // Linux 的 SYN: MSS, SACK permitted, Timestamps, NOP, Window scale
+        let bytes = [
+            0x02, 0x04, 0x05, 0xb4,
+            0x04, 0x02,
+            0x08, 0x0a, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+            0x01,
+            0x03, 0x03, 0x07,
+        ];
+        let options = parse_options(&bytes).unwrap();
+        assert_eq!(options, vec![
+            TcpOption::Mss(1460),
+            TcpOption::SackPermitted,
+            TcpOption::Timestamps { val: 1, ecr: 0 },
+            TcpOption::Nop,
+            TcpOption::WindowScale(7),
+        ]);
+        let text: Vec<String> = options.iter().map(|o| o.to_string()).collect();
+        assert_eq!(text[0], "MSS: 1460");
+        assert_eq!(text[4], "Window scale: 7 (multiply by 128)");
+    }
+
+    #[test]
+    fn test_parse_sack_and_padding() {
+        let bytes = [
+            0x01, 0x01,
+            0x05, 0x0a, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x00, 0x14,
+            0x00, 0xff, 0xff, // End of Option List 后是填充
+        ];
+        let spans = parse_options_with_spans(&bytes).unwrap();
+        assert_eq!(spans[2], (TcpOption::Sack(vec![(10, 20)]), 2..12));
+        assert_eq!(spans[3], (TcpOption::EndOfList, 12..13));
+        assert_eq!(spans.len(), 4);
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        assert_eq!(parse_options(&[0x02]), Err(TcpOptionError::Truncated { kind: 2, offset: 0 }));
+        assert_eq!(parse_options(&[0x01, 0x02, 0x04, 0x05]), Err(TcpOptionError::Truncated { kind: 2, offset: 1 }));
+        assert_eq!(parse_options(&[0x02, 0x03, 0x05]), Err(TcpOptionError::BadLength { kind: 2, len: 3, offset: 0 }));
+        assert_eq!(parse_options(&[0x1e, 0x00]), Err(TcpOptionError::BadLength { kind: 30, len: 0, offset: 0 }));
+        assert_eq!(parse_options(&[0x1e, 0x03, 0xaa]), Ok(vec![TcpOption::Unknown { kind: 30, data: vec![0xaa] }]));
+        assert_eq!(parse_options(&[0x22, 0x05, 1, 2, 3]), Err(TcpOptionError::BadLength { kind: 34, len: 5, offset: 0 }));
+        assert_eq!(parse_options(&[0x22, 0x02]), Ok(vec![TcpOption::FastOpen(vec![])]));
+    }
+
+    #[test]
+    fn test_offer_round_trip_and_negotiate() {
+        let ours = SynOffer { mss: 1460, window_scale: Some(7), sack_permitted: true, timestamps: true };
+        let bytes: Vec<u8> = ours.encode(100, 0).iter().flat_map(|word| word.to_be_bytes()).collect();
+        let options = parse_options(&bytes).unwrap();
+        assert_eq!(SynOffer::parse(&options), ours);
+
+        // 对端只提出 MSS 和 Window scale
+        let peer = [TcpOption::Mss(1400), TcpOption::Nop, TcpOption::WindowScale(3)];
+        assert_eq!(NegotiatedOptions::negotiate(&ours, &peer),
+            NegotiatedOptions { peer_mss: 1400, window_scale: Some((3, 7)), sack_permitted: false, timestamps: false });
+        assert_eq!(ours.answer(&peer), SynOffer { mss: 1460, window_scale: Some(7), sack_permitted: false, timestamps: false });
+        assert_eq!(NegotiatedOptions::negotiate(&ours, &[]).peer_mss, DEFAULT_PEER_MSS);
+    }
+}
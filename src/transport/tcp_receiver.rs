@@ -1,15 +1,36 @@
+use std::fmt;
+
+use crate::config::TcpConfig;
+use crate::utils::drops::{DropCounters, DropReason};
 use crate::utils::stream_reassemble::{self, StreamReassembler};
 
+use super::seq::{seq_le, seq_lt, WrappingSeq};
 use super::tcp_segment::TcpSegment;
+
+/**
+ * 一个报文段的处理结果
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReceiveOutcome {
+    Accepted,
+    Duplicate,          // 整段都已交付过, 只需要重发 ACK
+    Dropped(DropReason),
+}
+
 /**
  * 用以接收传入的 TCP segment 并将其转换成用户可读的数据流
  * 告诉发送者ack number, window size, 
+ * SYN 和 FIN 不经过重组器, 各自占用序号空间中的一个位置: SYN 在 initial_seq, 数据从 initial_seq + 1 开始,
+ * FIN 紧跟最后一个数据字节, 提前到达时等前面的空洞填上才被确认
  */
-struct TcpReceiver{
+pub(crate) struct TcpReceiver{
     initial_seq: u32,
     syn_flag: bool,
     capacity: usize,
-    reassembler: stream_reassemble::StreamReassembler
+    reassembler: stream_reassemble::StreamReassembler,
+    drops: DropCounters,
+    duplicate_fastpath_hits: u64,
+    urgent_mark: Option<u64>, // 对端紧急数据之后第一个字节的流偏移, 应用读到它之后清除
 }
 
 impl TcpReceiver {
@@ -18,54 +39,405 @@ impl TcpReceiver {
             initial_seq,
             syn_flag: false,
             capacity,
-            reassembler: StreamReassembler::new(capacity)
+            reassembler: StreamReassembler::new(capacity),
+            drops: DropCounters::new(),
+            duplicate_fastpath_hits: 0,
+            urgent_mark: None,
         }
     }
 
+    /**
+     * 接收缓冲区容量取自配置
+     */
+    pub fn from_config(config: &TcpConfig) -> Self {
+        Self::new(0, config.recv_buffer)
+    }
+
     /**
      * 每次接收tcp报文段时被调用
+     * 整段(含 SYN)落在 rcv_nxt 之前的重复报文不进入重组器; 跨过 rcv_nxt 的报文先去掉已交付的前缀
      */
-    pub fn segment_received(&mut self, segment: &TcpSegment) {
-        if self.syn_flag == false { 
-            if segment.SYN() == false { // 丢弃非SYN包
-                return;
+    pub fn segment_received(&mut self, segment: &TcpSegment) -> ReceiveOutcome {
+        let first_syn = !self.syn_flag;
+        if !self.syn_flag { 
+            if !segment.SYN() { // 丢弃非SYN包
+                self.drops.record(DropReason::NotSynchronized);
+                return ReceiveOutcome::Dropped(DropReason::NotSynchronized);
             }
             self.syn_flag = true;
             self.initial_seq = segment.seq;
         }
 
-        let abs_offset: usize = Self::rel_offset_to_abs(self.initial_seq, segment.seq, self.reassembler.assembled_cnt()).try_into().unwrap();
-        self.reassembler.recv(&segment.data, abs_offset, segment.FIN());
+        let rcv_nxt = self.ack_num();
+        let (mut seq, mut data) = (segment.seq.wrapping_add(segment.SYN() as u32), &segment.data[..]);
+        let urgent = segment.URG().then(|| seq.wrapping_add(segment.ur_ptr as u32));
+        let end = seq.wrapping_add(data.len() as u32);
+        if !first_syn && seq_lt(segment.seq, rcv_nxt) {
+            // 带 FIN 且恰好结束在 rcv_nxt 的报文, FIN 本身还是新的
+            if seq_le(end, rcv_nxt) && !(segment.FIN() && end == rcv_nxt) {
+                self.duplicate_fastpath_hits += 1;
+                return ReceiveOutcome::Duplicate;
+            }
+            if seq_lt(seq, rcv_nxt) {
+                data = &data[rcv_nxt.wrapping_sub(seq) as usize..];
+                seq = rcv_nxt;
+            }
+        }
+
+        let abs_offset: usize = Self::rel_offset_to_abs(self.data_start(), seq, self.reassembler.assembled_cnt()).try_into().unwrap();
+        if !self.reassembler.recv(data, abs_offset, segment.FIN()) {
+            self.drops.record(DropReason::OutOfWindow);
+            return ReceiveOutcome::Dropped(DropReason::OutOfWindow);
+        }
+        if let Some(urgent) = urgent {
+            self.urgent_received(urgent);
+        }
+        ReceiveOutcome::Accepted
     }
 
-    fn ack_num(&self) -> u32 {
-        Self::abs_offset_to_rel(self.initial_seq, self.reassembler.assembled_cnt()) 
+    /**
+     * 紧急指针指向紧急数据之后的第一个字节 (RFC 6093); 新的紧急数据只会把标记向后推
+     */
+    fn urgent_received(&mut self, urgent: u32) {
+        let mark = Self::rel_offset_to_abs(self.data_start(), urgent, self.reassembler.assembled_cnt());
+        if mark > self.read_offset() && self.urgent_mark.is_none_or(|old| old < mark) {
+            self.urgent_mark = Some(mark);
+        }
     }
 
-    fn window_size(&self) -> u32 {
-        self.reassembler.unassembled_window_size()
+    /**
+     * 应用已经读走的字节数
+     */
+    fn read_offset(&self) -> u64 {
+        self.reassembler.assembled_cnt() - self.reassembler.unread() as u64
     }
 
     /**
-     * 相对偏移转为绝对偏移
-     * recent_point: 最近的已经接收了的offset
+     * 从当前读位置到紧急数据结尾还有多少字节, 没有未读完的紧急数据时为 None
      */
-    fn rel_offset_to_abs(initial_seq: u32, rel_offset: u32, recent_point: u64) -> u64 {
-        const U32_RANGE: u64 = 1 << 32;
-        
-        let offset_this_round: u64  = rel_offset.wrapping_sub(initial_seq) as u64;
-        let round_cnt: u64 = recent_point / U32_RANGE;
-        let rel_of_recent_point: u64 = recent_point % U32_RANGE;
-
-        if (offset_this_round as u64) >= rel_of_recent_point { // 二者在同一轮
-            return offset_this_round + round_cnt * U32_RANGE;
+    pub fn urgent_pending(&self) -> Option<usize> {
+        self.urgent_mark.map(|mark| (mark - self.read_offset()) as usize)
+    }
+
+    /**
+     * 读到紧急数据的结尾为止, 至多 max 字节; 紧急数据还没有全部到达时只读已经到达的部分
+     */
+    pub fn read_urgent(&mut self, max: usize) -> Vec<u8> {
+        let Some(pending) = self.urgent_pending() else {
+            return vec![];
+        };
+        self.read(max.min(pending))
+    }
+
+    /**
+     * 走了重复报文快速路径的次数
+     */
+    #[cfg(test)]
+    pub fn duplicate_fastpath_hits(&self) -> u64 {
+        self.duplicate_fastpath_hits
+    }
+
+    /**
+     * 取走至多 max 字节已拼接好的数据, 窗口随之打开
+     */
+    pub fn read(&mut self, max: usize) -> Vec<u8> {
+        let data = self.reassembler.pop_assembled(max);
+        if self.urgent_mark.is_some_and(|mark| mark <= self.read_offset()) {
+            self.urgent_mark = None;
         }
-        else { // offset_this_round 在新一轮
-            return offset_this_round  + (round_cnt + 1) * U32_RANGE;
+        data
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.reassembler.capacity()
+    }
+
+    /**
+     * 已经可读但应用层还没有取走的字节数
+     */
+    pub fn unread(&self) -> usize {
+        self.reassembler.unread()
+    }
+
+    /**
+     * 对端的 FIN 已经到达, 之前的数据都已按序接收
+     */
+    pub fn fin_received(&self) -> bool {
+        self.reassembler.input_ended()
+    }
+
+    /**
+     * 调整接收缓冲区容量, 已缓存的数据不会丢弃, 返回实际容量
+     */
+    pub fn resize_capacity(&mut self, capacity: usize) -> usize {
+        self.capacity = self.reassembler.resize_capacity(capacity);
+        self.capacity
+    }
+
+    /**
+     * 记录在连接层面丢弃的报文段, 与接收端自己的丢弃一起出现在快照里
+     */
+    pub fn record_drop(&mut self, reason: DropReason) {
+        self.drops.record(reason);
+    }
+
+    pub fn drop_counters(&self) -> &DropCounters {
+        &self.drops
+    }
+
+    /**
+     * 接收端状态的只读快照, 区间均换算成序号空间
+     */
+    pub fn snapshot(&self) -> ReceiverSnapshot {
+        let to_seq = |abs: usize| Self::abs_offset_to_rel(self.data_start(), abs as u64);
+        let buffered = self.reassembler.buffered_ranges();
+        let mut gaps: Vec<(u32, u32)> = Vec::new();
+        let mut covered = self.reassembler.assembled_cnt() as usize;
+        for &(start, end) in &buffered {
+            if start > covered {
+                gaps.push((to_seq(covered), to_seq(start)));
+            }
+            covered = covered.max(end);
+        }
+        ReceiverSnapshot {
+            syn_received: self.syn_flag,
+            initial_seq: self.initial_seq,
+            ack: if self.syn_flag { Some(self.ack_num()) } else { None },
+            window: self.window_size(),
+            assembled: self.reassembler.assembled_cnt(),
+            buffered: buffered.into_iter().map(|(start, end)| (to_seq(start), to_seq(end))).collect(),
+            gaps,
+            drops: self.drop_counters().clone(),
         }
     }
 
+    /**
+     * 已经收到 SYN, ack_num 有意义
+     */
+    pub fn is_synchronized(&self) -> bool {
+        self.syn_flag
+    }
+
+    /**
+     * SYN 与 FIN 各占一个序号, FIN 按序到达之后确认号才包含它
+     */
+    pub fn ack_num(&self) -> u32 {
+        Self::abs_offset_to_rel(self.data_start(), self.reassembler.assembled_cnt()).wrapping_add(self.fin_received() as u32)
+    }
+
+    /**
+     * 第一个数据字节的序号, 紧跟在 SYN 之后
+     */
+    fn data_start(&self) -> u32 {
+        self.initial_seq.wrapping_add(1)
+    }
+
+    pub fn window_size(&self) -> u32 {
+        self.reassembler.unassembled_window_size()
+    }
+
+    /**
+     * 相对偏移转为绝对偏移, 取离 recent_point 最近的一个 (前后各不到 2^31)
+     * recent_point: 最近的已经接收了的offset; 早于流开头的序号按 0 处理
+     */
+    fn rel_offset_to_abs(initial_seq: u32, rel_offset: u32, recent_point: u64) -> u64 {
+        let recent = WrappingSeq(Self::abs_offset_to_rel(initial_seq, recent_point));
+        let delta = WrappingSeq(rel_offset).offset_from(recent);
+        recent_point.saturating_add_signed(delta as i64)
+    }
+
     fn abs_offset_to_rel(initial_seq: u32, abs_offset: u64) -> u32{
         initial_seq.wrapping_add((abs_offset % (1 << 32)) as u32)
     }
 }
+
+/**
+ * TcpReceiver 的快照, 区间为 [start, end) 的序号
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceiverSnapshot {
+    pub syn_received: bool,
+    pub initial_seq: u32,
+    pub ack: Option<u32>,
+    pub window: u32,
+    pub assembled: u64,
+    pub buffered: Vec<(u32, u32)>,
+    pub gaps: Vec<(u32, u32)>,
+    pub drops: DropCounters,
+}
+
+impl fmt::Display for ReceiverSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ranges = |ranges: &[(u32, u32)]| {
+            ranges.iter().map(|(start, end)| format!("[{}, {})", start, end)).collect::<Vec<String>>().join(" ")
+        };
+        match self.ack {
+            Some(ack) => writeln!(f, "  receiver: isn={} ack={} window={} assembled={}", self.initial_seq, ack, self.window, self.assembled)?,
+            None => writeln!(f, "  receiver: waiting for SYN, window={}", self.window)?,
+        }
+        writeln!(f, "  buffered: {}", ranges(&self.buffered))?;
+        writeln!(f, "  gaps: {}", ranges(&self.gaps))?;
+        let drops: Vec<String> = self.drops.iter().map(|(reason, count)| format!("{}={}", reason, count)).collect();
+        writeln!(f, "  drops: {}", if drops.is_empty() { "none".to_string() } else { drops.join(" ") })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tcp_segment::TcpCtrlFlag;
+
+    #[test]
+    fn test_window_from_config() {
+        let config = TcpConfig { recv_buffer: 1000, ..TcpConfig::default() };
+        let receiver = TcpReceiver::from_config(&config);
+        assert_eq!(receiver.window_size(), 1000);
+        assert_eq!(TcpReceiver::from_config(&TcpConfig::default()).window_size(), u16::MAX as u32);
+    }
+
+    #[test]
+    fn test_drop_counters() {
+        let mut receiver = TcpReceiver::new(0, 10);
+        let ack = TcpSegment::new(1, 2, 100, 0, 5, 0, TcpCtrlFlag::ACK as u16, 0, 0, vec![], vec![1]);
+        receiver.segment_received(&ack); // 尚未收到 SYN
+
+        let syn = TcpSegment::new(1, 2, 100, 0, 5, 0, TcpCtrlFlag::SYN as u16, 0, 0, vec![], vec![1]);
+        receiver.segment_received(&syn);
+        let far = TcpSegment::new(1, 2, 200, 0, 5, 0, TcpCtrlFlag::ACK as u16, 0, 0, vec![], vec![1]);
+        receiver.segment_received(&far); // 超出 10 字节的窗口
+
+        assert_eq!(receiver.drop_counters().get(DropReason::NotSynchronized), 1);
+        assert_eq!(receiver.drop_counters().get(DropReason::OutOfWindow), 1);
+        assert_eq!(receiver.drop_counters().total(), 2);
+    }
+
+    fn data(seq: u32, bytes: &[u8]) -> TcpSegment {
+        TcpSegment::new(1, 2, seq, 0, 5, 0, TcpCtrlFlag::ACK as u16, 0, 0, vec![], bytes.to_vec())
+    }
+
+    #[test]
+    fn test_stale_urgent_pointer_across_wrap() {
+        let mut receiver = TcpReceiver::new(0, 64);
+        receiver.segment_received(&TcpSegment::new(1, 2, 0xffff_fff0, 0, 5, 0, TcpCtrlFlag::SYN as u16, 0, 0, vec![], vec![]));
+        assert_eq!(receiver.segment_received(&data(0xffff_fff1, b"abcdefghijklmnop")), ReceiveOutcome::Accepted);
+        assert_eq!(receiver.ack_num(), 1);
+        assert_eq!(receiver.read(usize::MAX), b"abcdefghijklmnop");
+
+        // 重传的段前 8 字节已经收到, 紧急指针指向 rcv_nxt 之前: 不能当作下一轮序号空间里的标记
+        let mut overlap = data(0xffff_fff9, b"ijklmnopqrst");
+        overlap.ctrl |= TcpCtrlFlag::URG.into();
+        overlap.ur_ptr = 4;
+        assert_eq!(receiver.segment_received(&overlap), ReceiveOutcome::Accepted);
+        assert_eq!(receiver.ack_num(), 5);
+        assert_eq!(receiver.urgent_pending(), None);
+        assert_eq!(receiver.read(usize::MAX), b"qrst");
+    }
+
+    #[test]
+    fn test_zero_window_probe_does_not_advance_ack() {
+        let mut receiver = TcpReceiver::new(0, 4);
+        receiver.segment_received(&TcpSegment::new(1, 2, 99, 0, 5, 0, TcpCtrlFlag::SYN as u16, 0, 0, vec![], vec![]));
+        receiver.segment_received(&data(100, b"abcd"));
+        assert_eq!((receiver.ack_num(), receiver.window_size()), (104, 0));
+
+        // 带 FIN 的探测也整段丢弃, FIN 不算收到
+        let probe = TcpSegment::new(1, 2, 104, 0, 5, 0, (TcpCtrlFlag::ACK as u16) | (TcpCtrlFlag::FIN as u16), 0, 0, vec![], vec![b'e']);
+        for _ in 0..3 {
+            assert_eq!(receiver.segment_received(&probe), ReceiveOutcome::Dropped(DropReason::OutOfWindow));
+            assert_eq!(receiver.ack_num(), 104);
+            assert!(!receiver.fin_received());
+        }
+        assert_eq!(receiver.read(1), b"a");
+        assert_eq!(receiver.segment_received(&probe), ReceiveOutcome::Accepted);
+        assert_eq!(receiver.ack_num(), 106);
+        assert_eq!(receiver.read(100), b"bcde");
+    }
+
+    #[test]
+    fn test_full_duplicates_take_fast_path() {
+        let mut receiver = TcpReceiver::new(0, 1000);
+        let syn = TcpSegment::new(1, 2, u32::MAX - 5, 0, 5, 0, TcpCtrlFlag::SYN as u16, 0, 0, vec![], vec![]);
+        receiver.segment_received(&syn);
+        // 序号在这几段之间回绕, 数据从 SYN 之后的序号开始
+        assert_eq!(receiver.segment_received(&data(u32::MAX - 4, b"hello")), ReceiveOutcome::Accepted);
+        assert_eq!(receiver.segment_received(&data(0, b"world")), ReceiveOutcome::Accepted);
+        for _ in 0..3 {
+            assert_eq!(receiver.segment_received(&data(u32::MAX - 4, b"hello")), ReceiveOutcome::Duplicate);
+            assert_eq!(receiver.segment_received(&data(0, b"world")), ReceiveOutcome::Duplicate);
+        }
+        assert_eq!(receiver.segment_received(&syn), ReceiveOutcome::Duplicate); // 重传的 SYN
+        assert_eq!(receiver.duplicate_fastpath_hits(), 7);
+        assert_eq!(receiver.drop_counters().total(), 0);
+        assert_eq!(receiver.read(100), b"helloworld");
+        assert_eq!(receiver.ack_num(), 5);
+    }
+
+    #[test]
+    fn test_straddling_segment_is_trimmed() {
+        let mut receiver = TcpReceiver::new(0, 1000);
+        let syn = TcpSegment::new(1, 2, 99, 0, 5, 0, TcpCtrlFlag::SYN as u16, 0, 0, vec![], vec![]);
+        receiver.segment_received(&syn);
+        receiver.segment_received(&data(100, b"abcd"));
+        assert_eq!(receiver.read(100), b"abcd"); // 应用层已经取走, 重组器里没有这部分数据了
+
+        // [102, 108) 中 [102, 104) 已交付
+        assert_eq!(receiver.segment_received(&data(102, b"cdefgh")), ReceiveOutcome::Accepted);
+        assert_eq!(receiver.segment_received(&data(103, b"defgh")), ReceiveOutcome::Duplicate);
+        assert_eq!(receiver.read(100), b"efgh");
+        assert_eq!(receiver.ack_num(), 108);
+        assert_eq!(receiver.duplicate_fastpath_hits(), 1);
+
+        // 重传的最后一段带 FIN, 数据重复但 FIN 是新的
+        let fin = TcpSegment::new(1, 2, 104, 0, 5, 0, (TcpCtrlFlag::ACK as u16) | (TcpCtrlFlag::FIN as u16), 0, 0, vec![], b"efgh".to_vec());
+        assert_eq!(receiver.segment_received(&fin), ReceiveOutcome::Accepted);
+        assert!(receiver.read(100).is_empty());
+    }
+
+    fn fin(seq: u32, bytes: &[u8]) -> TcpSegment {
+        TcpSegment::new(1, 2, seq, 0, 5, 0, (TcpCtrlFlag::ACK as u16) | (TcpCtrlFlag::FIN as u16), 0, 0, vec![], bytes.to_vec())
+    }
+
+    #[test]
+    fn test_syn_and_bare_fin_take_one_seq_each() {
+        let mut receiver = TcpReceiver::new(0, 1000);
+        let syn = TcpSegment::new(1, 2, 1000, 0, 5, 0, TcpCtrlFlag::SYN as u16, 0, 0, vec![], vec![]);
+        assert_eq!(receiver.segment_received(&syn), ReceiveOutcome::Accepted);
+        assert_eq!(receiver.ack_num(), 1001);
+        receiver.segment_received(&data(1001, b"abc"));
+        assert_eq!(receiver.ack_num(), 1004);
+
+        assert_eq!(receiver.segment_received(&fin(1004, b"")), ReceiveOutcome::Accepted);
+        assert!(receiver.fin_received());
+        assert_eq!(receiver.ack_num(), 1005);
+        assert_eq!(receiver.segment_received(&fin(1004, b"")), ReceiveOutcome::Duplicate); // 重传的 FIN
+        assert_eq!(receiver.read(100), b"abc");
+    }
+
+    #[test]
+    fn test_early_fin_waits_for_gap() {
+        let mut receiver = TcpReceiver::new(0, 1000);
+        let syn = TcpSegment::new(1, 2, 0, 0, 5, 0, TcpCtrlFlag::SYN as u16, 0, 0, vec![], vec![]);
+        receiver.segment_received(&syn);
+        assert_eq!(receiver.segment_received(&fin(4, b"def")), ReceiveOutcome::Accepted);
+        assert!(!receiver.fin_received());
+        assert_eq!(receiver.ack_num(), 1);
+        assert_eq!(receiver.snapshot().gaps, vec![(1, 4)]);
+
+        receiver.segment_received(&data(1, b"abc"));
+        assert!(receiver.fin_received());
+        assert_eq!(receiver.ack_num(), 8);
+        assert_eq!(receiver.read(100), b"abcdef");
+    }
+
+    #[test]
+    fn test_syn_with_fin() {
+        let mut receiver = TcpReceiver::new(0, 1000);
+        let flags = (TcpCtrlFlag::SYN as u16) | (TcpCtrlFlag::FIN as u16);
+        let syn_fin = TcpSegment::new(1, 2, u32::MAX, 0, 5, 0, flags, 0, 0, vec![], b"hi".to_vec());
+        assert_eq!(receiver.segment_received(&syn_fin), ReceiveOutcome::Accepted);
+        assert!(receiver.fin_received());
+        assert_eq!(receiver.ack_num(), 3); // SYN、两个字节、FIN, 中间回绕
+        assert_eq!(receiver.segment_received(&syn_fin), ReceiveOutcome::Duplicate);
+        assert_eq!(receiver.read(100), b"hi");
+    }
+}
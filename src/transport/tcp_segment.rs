@@ -1,89 +1,510 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::{BitAnd, BitOr, BitOrAssign, Not};
+
+use crate::error::{ParseError, SerializeError};
+use crate::transport::tcp_option::{self, TcpOption, TcpOptionError};
 use crate::utils::checksum;
 use crate::utils::trans_bytes;
+use crate::utils::wire::{self, ParseStrictness, WireDeserialize, WireSerialize};
+
+pub const MAX_HDR_LEN: usize = 60; // hl 最大为 15
+const MAX_OPTION_WORDS: usize = (MAX_HDR_LEN - 20) / 4;
 
 macro_rules! generate_check_ctrl {
     ($tag_name: ident) => {
         pub fn $tag_name(&self) -> bool {
-            self.ctrl & (TcpCtrlFlag::$tag_name as u16) != 0
+            self.ctrl.contains(TcpFlags::$tag_name)
         }
     };
 }
 
+/**
+ * 控制位, 取值与线路上首部第 12~13 字节低 9 位的布局一致
+ */
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TcpCtrlFlag {
-    URG = 0b000000001,  // 位 0
-    ACK = 0b000000010,  // 位 1
-    PSH = 0b000000100,  // 位 2
-    RST = 0b000001000,  // 位 3
-    SYN = 0b000010000,  // 位 4
-    FIN = 0b000100000,  // 位 5
+    FIN = 0b000000001,  // 位 0
+    SYN = 0b000000010,  // 位 1
+    RST = 0b000000100,  // 位 2
+    PSH = 0b000001000,  // 位 3
+    ACK = 0b000010000,  // 位 4
+    URG = 0b000100000,  // 位 5
     ECE = 0b001000000,  // 位 6
     CWR = 0b010000000,  // 位 7
     NS  = 0b100000000,  // 位 8
 }
 
+impl TcpCtrlFlag {
+    // 摘要中的显示顺序
+    pub const ALL: [TcpCtrlFlag; 9] = [
+        TcpCtrlFlag::SYN, TcpCtrlFlag::ACK, TcpCtrlFlag::FIN, TcpCtrlFlag::RST, TcpCtrlFlag::PSH,
+        TcpCtrlFlag::URG, TcpCtrlFlag::ECE, TcpCtrlFlag::CWR, TcpCtrlFlag::NS,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TcpCtrlFlag::URG => "URG",
+            TcpCtrlFlag::ACK => "ACK",
+            TcpCtrlFlag::PSH => "PSH",
+            TcpCtrlFlag::RST => "RST",
+            TcpCtrlFlag::SYN => "SYN",
+            TcpCtrlFlag::FIN => "FIN",
+            TcpCtrlFlag::ECE => "ECE",
+            TcpCtrlFlag::CWR => "CWR",
+            TcpCtrlFlag::NS => "NS",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<TcpCtrlFlag> {
+        TcpCtrlFlag::ALL.iter().copied().find(|flag| flag.name().eq_ignore_ascii_case(name))
+    }
+
+    /**
+     * ctrl 中置位的标志名, 按 ALL 的顺序
+     */
+    pub fn names(ctrl: u16) -> Vec<&'static str> {
+        TcpCtrlFlag::ALL.iter()
+            .filter(|flag| ctrl & (**flag as u16) != 0)
+            .map(|flag| flag.name())
+            .collect()
+    }
+
+    /**
+     * names 的逆操作, 遇到未知的标志名返回该名字
+     */
+    pub fn ctrl_from_names<'a>(names: &[&'a str]) -> Result<u16, &'a str> {
+        names.iter().try_fold(0u16, |ctrl, name| match TcpCtrlFlag::from_name(name) {
+            Some(flag) => Ok(ctrl | flag as u16),
+            None => Err(*name),
+        })
+    }
+}
+
+/**
+ * 控制位集合, 只保留低 9 位
+ * 例: `TcpFlags::SYN | TcpFlags::ACK`, 显示为 `SYN|ACK`
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TcpFlags(u16);
+
+impl TcpFlags {
+    pub const EMPTY: TcpFlags = TcpFlags(0);
+    pub const FIN: TcpFlags = TcpFlags(TcpCtrlFlag::FIN as u16);
+    pub const SYN: TcpFlags = TcpFlags(TcpCtrlFlag::SYN as u16);
+    pub const RST: TcpFlags = TcpFlags(TcpCtrlFlag::RST as u16);
+    pub const PSH: TcpFlags = TcpFlags(TcpCtrlFlag::PSH as u16);
+    pub const ACK: TcpFlags = TcpFlags(TcpCtrlFlag::ACK as u16);
+    pub const URG: TcpFlags = TcpFlags(TcpCtrlFlag::URG as u16);
+    pub const ECE: TcpFlags = TcpFlags(TcpCtrlFlag::ECE as u16);
+    pub const CWR: TcpFlags = TcpFlags(TcpCtrlFlag::CWR as u16);
+    pub const NS: TcpFlags = TcpFlags(TcpCtrlFlag::NS as u16);
+    const MASK: u16 = 0x1ff;
+
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /**
+     * other 中的每一位都已置位
+     */
+    pub const fn contains(self, other: TcpFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /**
+     * 与 other 至少有一位相同
+     */
+    pub const fn intersects(self, other: TcpFlags) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn insert(&mut self, other: TcpFlags) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: TcpFlags) {
+        self.0 &= !other.0;
+    }
+
+    pub fn set(&mut self, other: TcpFlags, value: bool) {
+        if value { self.insert(other) } else { self.remove(other) }
+    }
+
+    /**
+     * 置位的标志, 按 TcpCtrlFlag::ALL 的顺序
+     */
+    pub fn iter(self) -> impl Iterator<Item = TcpCtrlFlag> {
+        TcpCtrlFlag::ALL.into_iter().filter(move |flag| self.0 & (*flag as u16) != 0)
+    }
+}
+
+/**
+ * 序列化为标志名的列表, 如 `["SYN", "ACK"]`
+ */
+#[cfg(feature = "serde")]
+impl serde::Serialize for TcpFlags {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(TcpCtrlFlag::names(self.0))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for TcpFlags {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let names = Vec::<String>::deserialize(deserializer)?;
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        TcpCtrlFlag::ctrl_from_names(&names)
+            .map(TcpFlags)
+            .map_err(|name| serde::de::Error::custom(format!("unknown tcp flag {:?}", name)))
+    }
+}
+
+impl From<u16> for TcpFlags {
+    fn from(bits: u16) -> Self {
+        TcpFlags(bits & TcpFlags::MASK)
+    }
+}
+
+impl From<TcpFlags> for u16 {
+    fn from(flags: TcpFlags) -> Self {
+        flags.0
+    }
+}
+
+impl From<TcpCtrlFlag> for TcpFlags {
+    fn from(flag: TcpCtrlFlag) -> Self {
+        TcpFlags(flag as u16)
+    }
+}
+
+impl BitOr for TcpFlags {
+    type Output = TcpFlags;
+
+    fn bitor(self, rhs: TcpFlags) -> TcpFlags {
+        TcpFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for TcpFlags {
+    fn bitor_assign(&mut self, rhs: TcpFlags) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for TcpFlags {
+    type Output = TcpFlags;
+
+    fn bitand(self, rhs: TcpFlags) -> TcpFlags {
+        TcpFlags(self.0 & rhs.0)
+    }
+}
+
+impl Not for TcpFlags {
+    type Output = TcpFlags;
+
+    fn not(self) -> TcpFlags {
+        TcpFlags(!self.0 & TcpFlags::MASK)
+    }
+}
+
+impl fmt::Display for TcpFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self.iter().map(|flag| flag.name()).collect();
+        write!(f, "{}", names.join("|"))
+    }
+}
+
+/**
+ * TCP报文段解析错误
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TcpParseError {
+    TooShort { len: usize },
+    BadHeaderLength { hl: u8, available: usize },
+    ReservedBits { bits: u8 },
+    MalformedOptions { offset: usize },
+}
+
+impl fmt::Display for TcpParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TcpParseError::TooShort { len } => {
+                write!(f, "segment too short: {} bytes, header needs at least 20", len)
+            }
+            TcpParseError::BadHeaderLength { hl, available } => {
+                write!(f, "invalid data offset {} (offset 12) for {} available bytes", hl, available)
+            }
+            TcpParseError::ReservedBits { bits } => write!(f, "reserved bits {:#05b} set (offset 12)", bits),
+            TcpParseError::MalformedOptions { offset } => write!(f, "malformed option at offset {}", offset),
+        }
+    }
+}
+
+impl Error for TcpParseError {}
+
 /**
  * TCP报文段
  */
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TcpSegment {
     pub s_port: u16, pub d_port: u16,
     pub seq: u32,
     pub ack: u32,
-    pub hl: u8/* 长度4bits, 单位32bits*/, pub rcvd: u8/* 长度3bits*/, pub ctrl: u16, pub win_size: u16,
+    pub hl: u8/* 长度4bits, 单位32bits*/, pub rcvd: u8/* 长度3bits*/, pub ctrl: TcpFlags, pub win_size: u16,
     checksum: u16, pub ur_ptr: u16,
     pub options: Vec<u32>,
-    pub data: Vec<u8> 
+    pub data: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    checksum_stale: bool, // 修改过首部, 校验和需要用伪首部重新计算
 }
 
 impl TcpSegment {
-    pub fn new(s_port: u16, d_port: u16, seq: u32, ack: u32, hl: u8, rcvd: u8, ctrl: u16, win_size: u16, ur_ptr: u16, options: Vec<u32>, data: Vec<u8> ) -> Self {
-        let mut new_ins = TcpSegment {s_port, d_port, seq, ack, hl, rcvd, ctrl, win_size, ur_ptr, options, data, checksum: 0 };
+    pub fn new(s_port: u16, d_port: u16, seq: u32, ack: u32, hl: u8, rcvd: u8, ctrl: impl Into<TcpFlags>, win_size: u16, ur_ptr: u16, options: Vec<u32>, data: Vec<u8> ) -> Self {
+        let mut new_ins = TcpSegment {s_port, d_port, seq, ack, hl, rcvd, ctrl: ctrl.into(), win_size, ur_ptr, options, data, checksum: 0, checksum_stale: false };
         new_ins.checksum = checksum::generate_checksum(&new_ins.serialized_hdr());
         
         new_ins
     }
 
-    pub fn deserialize(bytes: &Vec<u8>) -> Self {
-        let h_bytes: usize = (((bytes[12] >> 4) as u32) * 4).try_into().unwrap();
-        TcpSegment {
+    pub fn try_deserialize(bytes: &[u8]) -> Result<Self, TcpParseError> {
+        Self::try_deserialize_with(bytes, ParseStrictness::Loose)
+    }
+
+    pub fn try_deserialize_with(bytes: &[u8], strictness: ParseStrictness) -> Result<Self, TcpParseError> {
+        let mut segment = Self::parse_header(bytes, strictness)?;
+        segment.data = bytes[(segment.hl as usize) * 4..].to_vec();
+        Ok(segment)
+    }
+
+    /**
+     * 接管 IP 数据报的载荷, 原地去掉首部后剩下的部分直接作为 data, 整条接收路径只有一次分配
+     */
+    pub fn from_payload_vec(bytes: Vec<u8>) -> Result<Self, TcpParseError> {
+        Self::from_payload_vec_with(bytes, ParseStrictness::Loose)
+    }
+
+    pub fn from_payload_vec_with(mut bytes: Vec<u8>, strictness: ParseStrictness) -> Result<Self, TcpParseError> {
+        let mut segment = Self::parse_header(&bytes, strictness)?;
+        let size = bytes.len();
+        trans_bytes::keep_range(&mut bytes, (segment.hl as usize) * 4, size);
+        segment.data = bytes;
+        Ok(segment)
+    }
+
+    /**
+     * 解析并校验首部, data 留空由调用者填充
+     * data offset 超出可用字节总是错误; Strict 时保留位非零、选项不能按 kind/length 解析也是错误,
+     * Loose 时选项原样保存, 由 parsed_options_with 把解析不了的部分作为 Unknown 返回
+     * EOL 之后的字节不再解析, 两种模式都接受并原样保存, 序列化时写回
+     */
+    fn parse_header(bytes: &[u8], strictness: ParseStrictness) -> Result<Self, TcpParseError> {
+        if bytes.len() < 20 {
+            return Err(TcpParseError::TooShort { len: bytes.len() });
+        }
+        let hl: u8 = bytes[12] >> 4;
+        let h_bytes: usize = (hl as usize) * 4;
+        if hl < 5 || h_bytes > bytes.len() {
+            return Err(TcpParseError::BadHeaderLength { hl, available: bytes.len() });
+        }
+        if strictness.is_strict() {
+            let bits = (bytes[12] >> 1) & 0b0000_0111;
+            if bits != 0 {
+                return Err(TcpParseError::ReservedBits { bits });
+            }
+            tcp_option::parse_options_with_spans(&bytes[20..h_bytes])
+                .map_err(|error| TcpParseError::MalformedOptions { offset: 20 + error.offset() })?;
+        }
+        Ok(TcpSegment {
             s_port: trans_bytes::bytes_vec_to_muilt_bytes(&bytes[0..=1]) as u16, d_port: trans_bytes::bytes_vec_to_muilt_bytes(&bytes[2..=3]) as u16,
             seq: trans_bytes::bytes_vec_to_muilt_bytes(&bytes[4..=7]) as u32,
             ack: trans_bytes::bytes_vec_to_muilt_bytes(&bytes[8..=11]) as u32,
-            hl: bytes[12] >> 4, rcvd: bytes[12] & 0b0000_1110, ctrl: (((bytes[12] & 1)  as u16) << 8) + (bytes[13] as u16), win_size: trans_bytes::bytes_vec_to_muilt_bytes(&bytes[14..=15]) as u16,
+            hl, rcvd: (bytes[12] >> 1) & 0b0000_0111, ctrl: TcpFlags::from((((bytes[12] & 1)  as u16) << 8) + (bytes[13] as u16)), win_size: trans_bytes::bytes_vec_to_muilt_bytes(&bytes[14..=15]) as u16,
             checksum: trans_bytes::bytes_vec_to_muilt_bytes(&bytes[16..=17]) as u16, ur_ptr: trans_bytes::bytes_vec_to_muilt_bytes(&bytes[18..=19]) as u16,
             options: trans_bytes::bytes_vec_to_muilt_bytes_vec_u32(&bytes[20..h_bytes]),
-            data: bytes[h_bytes..].to_vec()
+            data: Vec::new(),
+            checksum_stale: false,
+        })
+    }
+
+    /**
+     * 解析失败时 panic, 仅用于确定输入合法的场景
+     */
+    #[deprecated(note = "use WireDeserialize::deserialize")]
+    pub fn deserialize_unchecked(bytes: &[u8]) -> Self {
+        match Self::try_deserialize(bytes) {
+            Ok(segment) => segment,
+            Err(e) => panic!("Invalid TCP segment: {}", e),
         }
     }
 
+    /**
+     * data offset 与控制位之间的 3 个保留位, Loose 解析时原样保留
+     */
+    pub fn reserved_bits(&self) -> u8 {
+        self.rcvd & 0b0000_0111
+    }
+
     pub fn serialized_hdr(&self) -> Vec<u8> {
-        let mut bytes = vec![
+        let (bytes, len) = self.header_bytes();
+        return bytes[..len].to_vec();
+    }
+
+    /**
+     * 首部写进定长数组, 返回数组和实际使用的长度, 不做堆分配
+     * hl 只有4bits, 超过 MAX_OPTION_WORDS 的 options 无法表示, 序列化时丢弃
+     */
+    pub fn header_bytes(&self) -> ([u8; MAX_HDR_LEN], usize) {
+        let mut bytes = [0u8; MAX_HDR_LEN];
+        bytes[..20].copy_from_slice(&[
             (self.s_port >> 8) as u8, self.s_port as u8, (self.d_port >> 8) as u8, self.d_port as u8, 
             (self.seq >> 24) as u8, (self.seq >> 16) as u8, (self.seq >> 8) as u8, self.seq as u8, 
             (self.ack >> 24) as u8, (self.ack >> 16) as u8, (self.ack >> 8) as u8, self.ack as u8, 
-            ((self.hl << 4) & 0xf0) + ((self.rcvd & 0b0000_0111) << 1) + (((self.ctrl >> 8) & 1)as u8), self.ctrl as u8, (self.win_size >> 8) as u8, self.win_size as u8,
+            ((self.hl << 4) & 0xf0) + ((self.rcvd & 0b0000_0111) << 1) + (((self.ctrl.bits() >> 8) & 1)as u8), self.ctrl.bits() as u8, (self.win_size >> 8) as u8, self.win_size as u8,
             (self.checksum >> 8) as u8, self.checksum as u8, (self.ur_ptr >> 8) as u8, self.ur_ptr as u8
-        ];
-        bytes.append(&mut trans_bytes::multi_bytes_vec_to_bytes_vec(&self.options));
+        ]);
+        let mut len = 20;
+        for option in self.option_words() {
+            bytes[len..len + 4].copy_from_slice(&option.to_be_bytes());
+            len += 4;
+        }
 
-        return bytes;
+        return (bytes, len);
     }
 
-    pub fn serialized(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = self.serialized_hdr();
-        result.append(&mut self.data.clone());
-        
-        result
+    /**
+     * options 字段的原始字节(含填充)
+     */
+    pub fn options_bytes(&self) -> Vec<u8> {
+        trans_bytes::multi_bytes_vec_to_bytes_vec(self.option_words())
+    }
+
+    pub fn parsed_options(&self) -> Result<Vec<TcpOption>, TcpOptionError> {
+        tcp_option::parse_options(&self.options_bytes())
     }
 
-    pub fn update_ctrl(&mut self, flag: &TcpCtrlFlag, valid: bool) {
-        if valid {
-            self.ctrl = self.ctrl | (*flag as u16);
+    /**
+     * 按解析策略取选项: Strict 时解析失败为 MalformedOptions, offset 从 TCP 首部开头算起;
+     * Loose 时解析不了的部分作为一个 Unknown 返回, 原始字节仍保存在 options 中
+     */
+    pub fn parsed_options_with(&self, strictness: ParseStrictness) -> Result<Vec<TcpOption>, TcpParseError> {
+        let bytes = self.options_bytes();
+        if strictness.is_strict() {
+            tcp_option::parse_options(&bytes).map_err(|error| TcpParseError::MalformedOptions { offset: 20 + error.offset() })
+        } else {
+            Ok(tcp_option::parse_options_loose(&bytes))
         }
-        else {
-            self.ctrl = self.ctrl & (!(*flag as u16));
+    }
+
+    /**
+     * 删掉所有 kind 类型的选项, 其余选项按原顺序重新排列并补齐到 4 字节, hl 随之更新
+     * 选项无法解析或没有该选项时不做改动, 返回 false; 改动后校验和标记为需要重新计算
+     */
+    pub fn remove_option(&mut self, kind: u8) -> bool {
+        let bytes = self.options_bytes();
+        let Ok(spans) = tcp_option::parse_options_with_spans(&bytes) else {
+            return false;
+        };
+        if !spans.iter().any(|(option, _)| option.kind() == kind) {
+            return false;
         }
+        let kept: Vec<u8> = spans.into_iter()
+            .filter(|(option, _)| option.kind() != kind && *option != TcpOption::EndOfList)
+            .flat_map(|(_, span)| bytes[span].to_vec())
+            .collect();
+        self.set_options_bytes(kept);
+        true
+    }
+
+    /**
+     * 改写 MSS 选项的值, 长度不变; 没有 MSS 选项时返回 false
+     */
+    pub fn rewrite_mss(&mut self, mss: u16) -> bool {
+        let mut bytes = self.options_bytes();
+        let Ok(spans) = tcp_option::parse_options_with_spans(&bytes) else {
+            return false;
+        };
+        let Some((_, span)) = spans.into_iter().find(|(option, _)| matches!(option, TcpOption::Mss(_))) else {
+            return false;
+        };
+        bytes[span.start + 2..span.end].copy_from_slice(&mss.to_be_bytes());
+        self.options = trans_bytes::bytes_vec_to_muilt_bytes_vec_u32(&bytes);
+        self.checksum_stale = true;
+        true
+    }
+
+    fn set_options_bytes(&mut self, mut bytes: Vec<u8>) {
+        bytes.resize(bytes.len().next_multiple_of(4), 0); // EOL 填充
+        self.options = trans_bytes::bytes_vec_to_muilt_bytes_vec_u32(&bytes);
+        self.hl = (5 + self.option_words().len()) as u8;
+        self.checksum_stale = true;
+    }
+
+    /**
+     * 经 remove_option / rewrite_mss 修改之后, 发出前应当调用 generate_checksum
+     */
+    pub fn checksum_stale(&self) -> bool {
+        self.checksum_stale
+    }
+
+    fn option_words(&self) -> &[u32] {
+        &self.options[..self.options.len().min(MAX_OPTION_WORDS)]
+    }
+
+    /**
+     * SACK 选项中的块, 选项解析失败或没有 SACK 时为空
+     */
+    pub fn sack_blocks(&self) -> Vec<(u32, u32)> {
+        self.parsed_options().unwrap_or_default().into_iter()
+            .filter_map(|option| match option {
+                TcpOption::Sack(blocks) => Some(blocks),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    #[deprecated(note = "use WireSerialize::serialize")]
+    pub fn serialized(&self) -> Vec<u8> {
+        self.serialize()
+    }
+
+    /**
+     * 按 RFC 793 计算并更新校验和, 覆盖伪首部、首部和数据
+     */
+    pub fn generate_checksum(&mut self, s_addr: u32, d_addr: u32) -> u16 {
+        self.checksum = 0;
+        self.checksum = checksum::generate_checksum(&self.checksum_input(s_addr, d_addr));
+        self.checksum_stale = false;
+        self.checksum
+    }
+
+    /**
+     * 校验和填 0, 由链路保证完整性时使用 (见 ChecksumPolicy::TrustLink)
+     */
+    pub fn clear_checksum(&mut self) {
+        self.checksum = 0;
+    }
+
+    pub fn check_checksum(&self, s_addr: u32, d_addr: u32) -> bool {
+        checksum::check(&self.checksum_input(s_addr, d_addr))
+    }
+
+    fn checksum_input(&self, s_addr: u32, d_addr: u32) -> Vec<u8> {
+        let segment = self.serialize();
+        let mut bytes = checksum::pseudo_header(s_addr, d_addr, 6, segment.len() as u16);
+        bytes.extend_from_slice(&segment);
+        bytes
+    }
+
+    pub fn update_ctrl(&mut self, flags: TcpFlags, valid: bool) {
+        self.ctrl.set(flags, valid);
     }
 
     // 生成对应的检查方法
@@ -96,9 +517,38 @@ impl TcpSegment {
     generate_check_ctrl!(ECE);
     generate_check_ctrl!(CWR);
     generate_check_ctrl!(NS);
-    
 
+}
+
+impl WireSerialize for TcpSegment {
+    fn wire_size(&self) -> usize {
+        20 + self.option_words().len() * 4 + self.data.len()
+    }
 
+    fn serialize_into(&self, buf: &mut [u8]) -> Result<usize, SerializeError> {
+        let size = self.wire_size();
+        wire::check_buffer(buf, size)?;
+        let (hdr, hdr_len) = self.header_bytes();
+        buf[..hdr_len].copy_from_slice(&hdr[..hdr_len]);
+        buf[hdr_len..size].copy_from_slice(&self.data);
+        Ok(size)
+    }
+}
+
+impl WireDeserialize for TcpSegment {
+    fn deserialize(bytes: &[u8]) -> Result<Self, ParseError> {
+        Ok(Self::try_deserialize(bytes)?)
+    }
+}
+
+/**
+ * 单行摘要, 例如 `12345 > 80 [SYN|ACK] seq=1001 ack=2002 win=4096 len=4`
+ */
+impl fmt::Display for TcpSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} > {} [{}] seq={} ack={} win={} len={}",
+            self.s_port, self.d_port, self.ctrl, self.seq, self.ack, self.win_size, self.data.len())
+    }
 }
 
 
@@ -126,7 +576,7 @@ mod tests {
         );
 
         // 生成该段的序列化字节
-        let serialized = segment.serialized();
+        let serialized = segment.serialize();
 
         // 验证源端口 (0x3039 => 12345)
         assert_eq!(serialized[0], 0x30);
@@ -155,9 +605,9 @@ mod tests {
         // 控制字段
         assert_eq!((((serialized[12] & 1 ) as u16)<< 8) + (serialized[13] as u16), 0x12);
         assert!(segment.SYN());
-        segment.update_ctrl(&TcpCtrlFlag::SYN, false);
+        segment.update_ctrl(TcpFlags::SYN, false);
         assert!(!segment.SYN());
-        segment.update_ctrl(&TcpCtrlFlag::SYN, true);
+        segment.update_ctrl(TcpFlags::SYN, true);
         // 验证窗口大小 (0x1000 => 4096)
         assert_eq!(serialized[14], 0x10);
         assert_eq!(serialized[15], 0x00);
@@ -171,7 +621,7 @@ mod tests {
         assert_eq!(serialized[20..], vec![1, 2, 3, 4]);
 
         // 反序列化字节数据
-        let deserialized = TcpSegment::deserialize(&serialized);
+        let deserialized = TcpSegment::try_deserialize(&serialized).unwrap();
 
         // 验证反序列化后的数据是否与原始数据相同
         assert_eq!(deserialized.s_port, segment.s_port);
@@ -188,6 +638,134 @@ mod tests {
         assert_eq!(deserialized.data, segment.data);
 
     }
+
+    #[test]
+    fn test_deserialize_invalid() {
+        assert_eq!(TcpSegment::try_deserialize(&[0; 8]).unwrap_err(), TcpParseError::TooShort { len: 8 });
+
+        let mut bytes: Vec<u8> = vec![0; 20];
+        bytes[12] = 0xf0; // hl = 15, 需要60字节
+        assert_eq!(
+            TcpSegment::try_deserialize(&bytes).unwrap_err(),
+            TcpParseError::BadHeaderLength { hl: 15, available: 20 }
+        );
+    }
+
+    #[test]
+    fn test_flag_names() {
+        let ctrl = TcpCtrlFlag::ACK as u16 | TcpCtrlFlag::SYN as u16;
+        assert_eq!(TcpCtrlFlag::names(ctrl), vec!["SYN", "ACK"]);
+        assert_eq!(TcpCtrlFlag::ctrl_from_names(&["syn", "ACK"]), Ok(ctrl));
+        assert_eq!(TcpCtrlFlag::ctrl_from_names(&["SYN", "XYZ"]), Err("XYZ"));
+        for flag in TcpCtrlFlag::ALL {
+            assert_eq!(format!("{:?}", flag), flag.name());
+        }
+    }
+
+    #[test]
+    fn test_flags_bit_ops() {
+        let mut flags = TcpFlags::SYN | TcpFlags::ACK;
+        assert_eq!(flags.bits(), 0x12);
+        assert!(flags.contains(TcpFlags::SYN));
+        assert!(!flags.contains(TcpFlags::SYN | TcpFlags::FIN));
+        assert!(flags.intersects(TcpFlags::SYN | TcpFlags::FIN));
+        flags.insert(TcpFlags::NS);
+        flags.remove(TcpFlags::SYN);
+        assert_eq!(flags, TcpFlags::ACK | TcpFlags::NS);
+        assert_eq!(flags.iter().collect::<Vec<_>>().len(), 2);
+        assert_eq!(flags & TcpFlags::ACK, TcpFlags::ACK);
+        assert_eq!(!TcpFlags::EMPTY, TcpFlags::from(0xffff));
+        assert_eq!(TcpFlags::from(0xffff).bits(), 0x1ff);
+        assert_eq!(u16::from(TcpFlags::from(TcpCtrlFlag::RST)), 0x04);
+
+        assert_eq!((TcpFlags::ACK | TcpFlags::SYN).to_string(), "SYN|ACK");
+        assert_eq!((TcpFlags::FIN | TcpFlags::PSH | TcpFlags::ACK).to_string(), "ACK|FIN|PSH");
+        assert_eq!(TcpFlags::EMPTY.to_string(), "");
+    }
+
+    #[test]
+    fn test_flags_serialize_like_raw_bits() {
+        for bits in [0u16, 0x02, 0x12, 0x11, 0x18, 0x1ff, 0x100] {
+            let from_raw = TcpSegment::new(1, 2, 3, 4, 5, 0, bits, 100, 0, vec![], vec![9]);
+            let from_flags = TcpSegment::new(1, 2, 3, 4, 5, 0, TcpFlags::from(bits), 100, 0, vec![], vec![9]);
+            let bytes = from_flags.serialize();
+            assert_eq!(bytes, from_raw.serialize());
+            assert_eq!((((bytes[12] & 1) as u16) << 8) | bytes[13] as u16, bits);
+            assert_eq!(TcpSegment::try_deserialize(&bytes).unwrap().ctrl, from_flags.ctrl);
+        }
+
+        // 线路上的标志位字节按 RFC 793 / RFC 3168 / RFC 3540 的位置, 与常量的定义无关
+        let wire = |flags: TcpFlags| {
+            let bytes = TcpSegment::new(1, 2, 3, 4, 5, 0, flags, 100, 0, vec![], vec![]).serialize();
+            (bytes[12] & 0x0f, bytes[13])
+        };
+        assert_eq!(wire(TcpFlags::FIN), (0, 0x01));
+        assert_eq!(wire(TcpFlags::SYN), (0, 0x02));
+        assert_eq!(wire(TcpFlags::RST), (0, 0x04));
+        assert_eq!(wire(TcpFlags::PSH), (0, 0x08));
+        assert_eq!(wire(TcpFlags::ACK), (0, 0x10));
+        assert_eq!(wire(TcpFlags::URG), (0, 0x20));
+        assert_eq!(wire(TcpFlags::ECE), (0, 0x40));
+        assert_eq!(wire(TcpFlags::CWR), (0, 0x80));
+        assert_eq!(wire(TcpFlags::NS), (0x01, 0x00));
+        assert_eq!(wire(TcpFlags::SYN | TcpFlags::ACK), (0, 0x12));
+        assert_eq!(wire(TcpFlags::FIN | TcpFlags::ACK), (0, 0x11));
+        assert_eq!(wire(TcpFlags::PSH | TcpFlags::ACK), (0, 0x18));
+        assert_eq!(wire(TcpFlags::RST | TcpFlags::ACK), (0, 0x14));
+        assert_eq!(wire(TcpFlags::EMPTY), (0, 0x00));
+    }
+
+    #[test]
+    fn test_header_bytes() {
+        let segment = TcpSegment::new(1, 2, 3, 4, 6, 0, TcpCtrlFlag::ACK as u16, 5, 0, vec![0x01020304], b"data".to_vec());
+        let (hdr, len) = segment.header_bytes();
+        assert_eq!(len, 24);
+        assert_eq!(&hdr[..len], &segment.serialize()[..24]);
+        assert!(hdr[len..].iter().all(|&b| b == 0));
+
+        // hl 表示不了的 options 不上线路
+        let segment = TcpSegment::new(1, 2, 3, 4, 15, 0, 0, 5, 0, vec![0xffff_ffff; 12], vec![]);
+        assert_eq!(segment.header_bytes().1, MAX_HDR_LEN);
+        assert_eq!(segment.wire_size(), MAX_HDR_LEN);
+        assert_eq!(segment.serialize().len(), MAX_HDR_LEN);
+    }
+
+    #[test]
+    fn test_remove_option_and_rewrite_mss() {
+        // MSS 1460, SACK permitted + Timestamps, Window scale 7
+        let options = vec![0x0204_05b4, 0x0402_080a, 1, 0, 0x0103_0307];
+        let mut segment = TcpSegment::new(1, 2, 3, 0, 10, 0, TcpCtrlFlag::SYN as u16, 5, 0, options, vec![]);
+        segment.generate_checksum(7, 8);
+        assert!(!segment.checksum_stale());
+
+        assert!(segment.rewrite_mss(536));
+        assert!(segment.checksum_stale());
+        assert_eq!(segment.hl, 10);
+
+        assert!(segment.remove_option(8));
+        assert!(!segment.remove_option(8));
+        assert_eq!(segment.hl, 8);
+        assert_eq!(
+            tcp_option::parse_options(&segment.options_bytes()).unwrap(),
+            vec![
+                TcpOption::Mss(536),
+                TcpOption::SackPermitted,
+                TcpOption::Nop,
+                TcpOption::WindowScale(7),
+                TcpOption::EndOfList,
+            ]
+        );
+
+        // 修改后按新的首部长度上线路, 重新计算的校验和能通过校验
+        segment.generate_checksum(7, 8);
+        assert!(!segment.checksum_stale());
+        let parsed = TcpSegment::try_deserialize(&segment.serialize()).unwrap();
+        assert_eq!(parsed.hl, 8);
+        assert!(parsed.check_checksum(7, 8));
+
+        assert!(segment.remove_option(2));
+        assert!(!segment.rewrite_mss(1460));
+    }
 }
 
 /*
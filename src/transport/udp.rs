@@ -0,0 +1,118 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::error::SerializeError;
+use crate::utils::checksum;
+use crate::utils::wire::{self, WireSerialize};
+
+pub const PROTOCOL_UDP: u8 = 17;
+pub const UDP_HDR_LEN: usize = 8;
+
+/**
+ * UDP 报文解析错误
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UdpParseError {
+    TooShort { len: usize },
+    BadLength { length: u16, available: usize },
+}
+
+impl fmt::Display for UdpParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UdpParseError::TooShort { len } => write!(f, "datagram too short: {} bytes, header needs 8", len),
+            UdpParseError::BadLength { length, available } => {
+                write!(f, "invalid length {} (offset 4) for {} available bytes", length, available)
+            }
+        }
+    }
+}
+
+impl Error for UdpParseError {}
+
+/**
+ * UDP 数据报 (RFC 768), checksum 为 0 表示发送方没有计算
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UdpDatagram {
+    pub s_port: u16,
+    pub d_port: u16,
+    pub checksum: u16,
+    pub data: Vec<u8>,
+}
+
+impl UdpDatagram {
+    /**
+     * 带伪首部校验和
+     */
+    pub fn new(s_port: u16, d_port: u16, data: Vec<u8>, s_addr: u32, d_addr: u32) -> Self {
+        let mut datagram = UdpDatagram { s_port, d_port, checksum: 0, data };
+        let sum = checksum::generate_checksum(&datagram.checksum_input(s_addr, d_addr));
+        datagram.checksum = if sum == 0 { 0xffff } else { sum }; // 算出 0 时发送全 1
+        datagram
+    }
+
+    /**
+     * 只解析首部里的端口和长度; 差错报文里带回的原报文只有前 8 字节也能解析
+     */
+    pub fn try_deserialize(bytes: &[u8]) -> Result<Self, UdpParseError> {
+        if bytes.len() < UDP_HDR_LEN {
+            return Err(UdpParseError::TooShort { len: bytes.len() });
+        }
+        let length = u16::from_be_bytes([bytes[4], bytes[5]]);
+        if (length as usize) < UDP_HDR_LEN || length as usize > bytes.len() {
+            return Err(UdpParseError::BadLength { length, available: bytes.len() });
+        }
+        Ok(UdpDatagram {
+            s_port: u16::from_be_bytes([bytes[0], bytes[1]]),
+            d_port: u16::from_be_bytes([bytes[2], bytes[3]]),
+            checksum: u16::from_be_bytes([bytes[6], bytes[7]]),
+            data: bytes[UDP_HDR_LEN..length as usize].to_vec(),
+        })
+    }
+
+    pub fn check_checksum(&self, s_addr: u32, d_addr: u32) -> bool {
+        self.checksum == 0 || checksum::check(&self.checksum_input(s_addr, d_addr))
+    }
+
+    fn checksum_input(&self, s_addr: u32, d_addr: u32) -> Vec<u8> {
+        let datagram = self.serialize();
+        let mut bytes = checksum::pseudo_header(s_addr, d_addr, PROTOCOL_UDP, datagram.len() as u16);
+        bytes.extend_from_slice(&datagram);
+        bytes
+    }
+}
+
+impl WireSerialize for UdpDatagram {
+    fn wire_size(&self) -> usize {
+        UDP_HDR_LEN + self.data.len()
+    }
+
+    fn serialize_into(&self, buf: &mut [u8]) -> Result<usize, SerializeError> {
+        let size = self.wire_size();
+        wire::check_buffer(buf, size)?;
+        buf[0..2].copy_from_slice(&self.s_port.to_be_bytes());
+        buf[2..4].copy_from_slice(&self.d_port.to_be_bytes());
+        buf[4..6].copy_from_slice(&(size as u16).to_be_bytes());
+        buf[6..8].copy_from_slice(&self.checksum.to_be_bytes());
+        buf[8..size].copy_from_slice(&self.data);
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_checksum() {
+        let datagram = UdpDatagram::new(33434, 53, b"probe".to_vec(), 0x0a000001, 0x0a000002);
+        let bytes = datagram.serialize();
+        let parsed = UdpDatagram::try_deserialize(&bytes).unwrap();
+        assert_eq!(parsed, datagram);
+        assert!(parsed.check_checksum(0x0a000001, 0x0a000002));
+        assert!(!parsed.check_checksum(0x0a000001, 0x0a000003));
+        assert_eq!(UdpDatagram::try_deserialize(&bytes[..7]), Err(UdpParseError::TooShort { len: 7 }));
+        assert_eq!(UdpDatagram::try_deserialize(&bytes[..10]), Err(UdpParseError::BadLength { length: 13, available: 10 }));
+    }
+}
@@ -0,0 +1,121 @@
+use crate::config::TcpConfig;
+
+/**
+ * 接收端防御窗口更新丢失的小定时器
+ * 通告过零窗口之后, 若窗口重新打开到值得通告的大小(1 个 MSS 或缓冲区的一半, 取小者),
+ * 且对端已经 interval 没有发来任何报文, 就补发窗口更新 ACK, 最多 retries 次
+ * 补发之间的间隔逐次加倍; 收到对端报文时计时重新开始, 带数据说明对端已经看到窗口
+ */
+#[derive(Debug, Clone)]
+pub struct WindowUpdateTimer {
+    interval_ms: u64,
+    retries: u32,
+    threshold: u32,
+    zero_advertised: bool,
+    last_peer_ms: u64,
+    next_at_ms: u64,
+    sent: u32,
+}
+
+impl WindowUpdateTimer {
+    pub fn new(config: &TcpConfig, now_ms: u64) -> Self {
+        WindowUpdateTimer {
+            interval_ms: config.window_update_interval_ms,
+            retries: config.window_update_retries,
+            threshold: (config.mss as u32).min((config.recv_buffer / 2) as u32).max(1),
+            zero_advertised: false,
+            last_peer_ms: now_ms,
+            next_at_ms: 0,
+            sent: 0,
+        }
+    }
+
+    /**
+     * 本端发出了通告 window 的报文
+     */
+    pub fn on_advertised(&mut self, window: u32) {
+        if window == 0 {
+            self.zero_advertised = true;
+            self.sent = 0;
+        }
+    }
+
+    /**
+     * 收到对端的任何报文
+     * 带数据时本端随后会回 ACK, 若窗口仍为零会通过 on_advertised 重新进入等待
+     */
+    pub fn on_peer_segment(&mut self, has_data: bool, now_ms: u64) {
+        self.last_peer_ms = now_ms;
+        self.sent = 0;
+        if has_data {
+            self.zero_advertised = false;
+        }
+    }
+
+    /**
+     * 由 tick 调用, 返回 true 时调用方应立即发送一个携带当前窗口的 ACK
+     */
+    pub fn poll(&mut self, window: u32, now_ms: u64) -> bool {
+        if !self.zero_advertised || window < self.threshold || self.sent >= self.retries {
+            return false;
+        }
+        if self.sent == 0 {
+            self.next_at_ms = self.last_peer_ms + self.interval_ms;
+        }
+        if now_ms < self.next_at_ms {
+            return false;
+        }
+        self.sent += 1;
+        self.next_at_ms = now_ms + (self.interval_ms << self.sent.min(16));
+        true
+    }
+
+    /**
+     * 当前窗口为 window 时下一次补发的时刻, 不会补发时为 None
+     */
+    pub fn next_deadline(&self, window: u32) -> Option<u64> {
+        if !self.zero_advertised || window < self.threshold || self.sent >= self.retries {
+            return None;
+        }
+        Some(if self.sent == 0 { self.last_peer_ms + self.interval_ms } else { self.next_at_ms })
+    }
+
+    /**
+     * 本轮已补发的次数
+     */
+    pub fn sent(&self) -> u32 {
+        self.sent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reannounce_with_backoff_and_limit() {
+        let config = TcpConfig { mss: 100, recv_buffer: 1000, window_update_interval_ms: 50, window_update_retries: 2, ..TcpConfig::default() };
+        let mut timer = WindowUpdateTimer::new(&config, 0);
+        timer.on_advertised(500);
+        assert!(!timer.poll(500, 1000)); // 从未通告零窗口
+
+        timer.on_advertised(0);
+        assert!(!timer.poll(99, 1000)); // 窗口还不值得通告
+        timer.on_peer_segment(false, 990);
+        assert!(!timer.poll(100, 1000)); // 对端 10ms 前刚发过报文
+        assert_eq!(timer.next_deadline(100), Some(1040));
+        assert!(timer.poll(100, 1040));
+        assert_eq!(timer.next_deadline(100), Some(1140));
+        assert!(!timer.poll(100, 1100));
+        assert!(timer.poll(100, 1140)); // 第二次间隔加倍
+        assert!(!timer.poll(100, 5000)); // 达到上限
+        assert_eq!(timer.next_deadline(100), None);
+        assert_eq!(timer.sent(), 2);
+
+        timer.on_peer_segment(false, 5000); // 对端的纯 ACK, 重新计时
+        assert!(!timer.poll(100, 5040));
+        assert!(timer.poll(100, 5050));
+        timer.on_peer_segment(true, 5060); // 对端恢复发送数据
+        assert!(!timer.poll(100, 9000));
+    }
+}
@@ -0,0 +1,109 @@
+use std::net::Ipv4Addr;
+
+/**
+ * 地址的文本形式, 用于日志、配置和转储
+ * MAC 为 `02:00:00:00:00:01`, IPv4 为点分十进制
+ */
+pub fn format_mac(mac: &[u8; 6]) -> String {
+    mac.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<String>>().join(":")
+}
+
+/**
+ * 接受 `:` 或 `-` 分隔、大小写不敏感的 MAC 地址
+ */
+pub fn parse_mac(text: &str) -> Option<[u8; 6]> {
+    let mut mac = [0u8; 6];
+    let mut parts = text.split([':', '-']);
+    for byte in mac.iter_mut() {
+        let part = parts.next()?;
+        if part.len() != 2 || !part.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return None; // from_str_radix 接受前导的 +, 这里只收两位十六进制数字
+        }
+        *byte = u8::from_str_radix(part, 16).ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(mac)
+}
+
+pub fn format_ipv4(addr: u32) -> String {
+    Ipv4Addr::from(addr).to_string()
+}
+
+pub fn parse_ipv4(text: &str) -> Option<u32> {
+    text.parse::<Ipv4Addr>().ok().map(u32::from)
+}
+
+/**
+ * `10.0.0.1/24` 形式的地址和前缀长度, 前缀不超过 32
+ */
+pub fn parse_cidr(text: &str) -> Option<(u32, u8)> {
+    let (addr, prefix_len) = text.split_once('/')?;
+    let prefix_len: u8 = prefix_len.parse().ok().filter(|&len| len <= 32)?;
+    Some((parse_ipv4(addr)?, prefix_len))
+}
+
+/**
+ * serde(with = ...) 用: MAC 地址序列化为 format_mac 的文本形式
+ */
+#[cfg(feature = "serde")]
+pub mod serde_mac {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(mac: &[u8; 6], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::format_mac(mac))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 6], D::Error> {
+        let text = String::deserialize(deserializer)?;
+        super::parse_mac(&text).ok_or_else(|| de::Error::custom(format!("invalid mac address {:?}", text)))
+    }
+}
+
+/**
+ * serde(with = ...) 用: IPv4 地址序列化为点分十进制
+ */
+#[cfg(feature = "serde")]
+pub mod serde_ipv4 {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(addr: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::format_ipv4(*addr))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        super::parse_ipv4(&text).ok_or_else(|| de::Error::custom(format!("invalid ipv4 address {:?}", text)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mac_text() {
+        let mac = [0x02, 0x00, 0x5e, 0xab, 0xcd, 0xef];
+        assert_eq!(format_mac(&mac), "02:00:5e:ab:cd:ef");
+        assert_eq!(parse_mac("02:00:5e:ab:cd:ef"), Some(mac));
+        assert_eq!(parse_mac("02-00-5E-AB-CD-EF"), Some(mac));
+        assert_eq!(parse_mac("02:00:5e:ab:cd"), None);
+        assert_eq!(parse_mac("02:00:5e:ab:cd:ef:00"), None);
+        assert_eq!(parse_mac("2:00:5e:ab:cd:ef"), None);
+        assert_eq!(parse_mac("zz:00:5e:ab:cd:ef"), None);
+        assert_eq!(parse_mac("+f:00:5e:ab:cd:ef"), None);
+        assert_eq!(parse_mac("02:00:5e:ab:cd:-f"), None);
+    }
+
+    #[test]
+    fn test_ipv4_text() {
+        assert_eq!(format_ipv4(0xc0a80001), "192.168.0.1");
+        assert_eq!(parse_ipv4("192.168.0.1"), Some(0xc0a80001));
+        assert_eq!(parse_ipv4("192.168.0"), None);
+        assert_eq!(parse_ipv4("256.0.0.1"), None);
+        assert_eq!(parse_cidr("10.211.0.1/24"), Some((0x0ad30001, 24)));
+        assert_eq!(parse_cidr("10.211.0.1/33"), None);
+        assert_eq!(parse_cidr("10.211.0.1"), None);
+    }
+}
@@ -1,16 +1,13 @@
-
 /**
  * 返回校验和(已按位取反)
+ * 奇数长度时末尾按补一个 0 字节处理(RFC 1071)
  */
-pub fn generate_checksum(bytes: &Vec<u8>) -> u16{
-    let mut checksum = 0;
-
-    if bytes.len() & 1 == 1 {
-        panic!("Ethernet header with odd length!");
-    }
+pub fn generate_checksum(bytes: &[u8]) -> u16{
+    let mut checksum: u32 = 0;
 
-    for i in (0..bytes.len()).step_by(2) {
-        checksum += ((bytes[i] as u32) << 8) + (bytes[i + 1] as u32);
+    for chunk in bytes.chunks(2) {
+        let low = if chunk.len() == 2 { chunk[1] } else { 0 };
+        checksum += ((chunk[0] as u32) << 8) + (low as u32);
         
         if checksum & 0xffff0000 != 0 { // 处理溢出
             checksum = (checksum & 0x0000ffff) + (checksum >> 16);
@@ -20,6 +17,92 @@ pub fn generate_checksum(bytes: &Vec<u8>) -> u16{
     !(checksum as u16)
 }
 
-pub fn check(bytes: &Vec<u8>) -> bool {
+pub fn check(bytes: &[u8]) -> bool {
     generate_checksum(bytes) == 0
-}
\ No newline at end of file
+}
+
+/**
+ * 接口上的校验和策略, 默认完整计算和校验
+ * TrustLink 只用于两端都在本进程内存中的链路, 相当于网卡的校验和卸载: 发送不计算(填 0), 接收不校验
+ * 代价是线路上的损坏不再被发现
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChecksumPolicy {
+    #[default]
+    GenerateAndVerify,
+    TrustLink,
+}
+
+impl ChecksumPolicy {
+    /**
+     * 链路两端都选择 TrustLink 时才能跳过校验和
+     */
+    pub fn for_link(a: ChecksumPolicy, b: ChecksumPolicy) -> ChecksumPolicy {
+        if a == ChecksumPolicy::TrustLink && b == ChecksumPolicy::TrustLink {
+            ChecksumPolicy::TrustLink
+        } else {
+            ChecksumPolicy::GenerateAndVerify
+        }
+    }
+
+    pub fn generate(self) -> bool {
+        self == ChecksumPolicy::GenerateAndVerify
+    }
+
+    pub fn verify(self) -> bool {
+        self == ChecksumPolicy::GenerateAndVerify
+    }
+}
+
+/**
+ * TCP/UDP 校验和使用的伪首部: 源地址、目的地址、0、协议号、上层报文长度
+ */
+pub fn pseudo_header(s_addr: u32, d_addr: u32, protocol: u8, len: u16) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(12);
+    bytes.extend_from_slice(&s_addr.to_be_bytes());
+    bytes.extend_from_slice(&d_addr.to_be_bytes());
+    bytes.extend_from_slice(&[0, protocol]);
+    bytes.extend_from_slice(&len.to_be_bytes());
+    bytes
+}
+
+/**
+ * 增量更新(RFC 1624 式 3): 报文中一个 16 位字由 old 改为 new 时的新校验和, HC' = ~(~HC + ~m + m')
+ */
+pub fn update(checksum: u16, old: u16, new: u16) -> u16 {
+    let mut sum = (!checksum as u32) + (!old as u32) + (new as u32);
+    sum = (sum & 0xffff) + (sum >> 16);
+    sum = (sum & 0xffff) + (sum >> 16);
+    !(sum as u16)
+}
+
+/**
+ * 32 位字段(如 IPv4 地址)改变时的增量更新
+ */
+pub fn update_u32(checksum: u16, old: u32, new: u32) -> u16 {
+    let checksum = update(checksum, (old >> 16) as u16, (new >> 16) as u16);
+    update(checksum, old as u16, new as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_odd_length() {
+        // 奇数长度等价于补一个 0 字节
+        assert_eq!(generate_checksum(&[0x12, 0x34, 0x56]), generate_checksum(&[0x12, 0x34, 0x56, 0x00]));
+        assert_eq!(generate_checksum(&[]), 0xffff);
+    }
+
+    #[test]
+    fn test_incremental_update() {
+        let mut bytes = vec![0x45, 0x00, 0x00, 0x54, 0x12, 0x34, 0x40, 0x00, 0x40, 0x01, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01];
+        let before = generate_checksum(&bytes);
+        bytes[12..16].copy_from_slice(&0xc0a8_0101u32.to_be_bytes());
+        assert_eq!(update_u32(before, 0x0a00_0001, 0xc0a8_0101), generate_checksum(&bytes));
+        let before = generate_checksum(&bytes);
+        bytes[4..6].copy_from_slice(&0xffffu16.to_be_bytes());
+        assert_eq!(update(before, 0x1234, 0xffff), generate_checksum(&bytes));
+    }
+}
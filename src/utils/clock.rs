@@ -0,0 +1,145 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/**
+ * 时钟抽象, 单位毫秒
+ * 协议栈内所有定时器(重传、TIME_WAIT、ARP 过期等)都只能从这里取时间,
+ * 不得直接调用 Instant / SystemTime
+ */
+pub trait Clock {
+    fn now_ms(&self) -> u64;
+
+    /**
+     * 微秒读数, 用于测量 RTT 和吞吐; 默认由毫秒换算
+     */
+    fn now_us(&self) -> u64 {
+        self.now_ms() * 1000
+    }
+}
+
+/**
+ * 生产环境使用的单调时钟, 以创建时刻为零点
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    start: Instant,
+}
+
+impl MonotonicClock {
+    pub fn new() -> Self {
+        MonotonicClock { start: Instant::now() }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now_ms(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    fn now_us(&self) -> u64 {
+        self.start.elapsed().as_micros() as u64
+    }
+}
+
+/**
+ * 墙上时钟, 以 Unix 纪元为零点, 只用来给抓到的帧打 pcap 时间戳
+ * 会随系统时间调整跳变, 不能驱动定时器
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WallClock;
+
+impl Clock for WallClock {
+    fn now_ms(&self) -> u64 {
+        self.now_us() / 1000
+    }
+
+    fn now_us(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_micros() as u64)
+    }
+}
+
+/**
+ * 测试用的手动时钟, 只有调用 advance/set 时间才会前进
+ * clone 出来的实例共享同一个时间
+ */
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new(start_ms: u64) -> Self {
+        ManualClock { now: Arc::new(AtomicU64::new(start_ms)) }
+    }
+
+    pub fn advance(&self, ms: u64) {
+        self.now.fetch_add(ms, Ordering::SeqCst);
+    }
+
+    /**
+     * 直接设置当前时间, 时间不允许倒退
+     */
+    pub fn set(&self, now_ms: u64) {
+        self.now.fetch_max(now_ms, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_ms(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock_advance() {
+        let clock = ManualClock::new(100);
+        assert_eq!(clock.now_ms(), 100);
+        clock.advance(50);
+        assert_eq!(clock.now_ms(), 150);
+        clock.set(1000);
+        assert_eq!(clock.now_ms(), 1000);
+        clock.set(10); // 不允许倒退
+        assert_eq!(clock.now_ms(), 1000);
+    }
+
+    #[test]
+    fn test_manual_clock_shared() {
+        let clock = ManualClock::default();
+        let shared = clock.clone();
+        clock.advance(30);
+        assert_eq!(shared.now_ms(), 30);
+
+        // 通过 trait object 使用
+        let dyn_clock: Box<dyn Clock> = Box::new(shared);
+        clock.advance(5);
+        assert_eq!(dyn_clock.now_ms(), 35);
+    }
+
+    #[test]
+    fn test_monotonic_clock_non_decreasing() {
+        let clock = MonotonicClock::new();
+        let t1 = clock.now_ms();
+        let t2 = clock.now_ms();
+        assert!(t2 >= t1);
+        assert!(clock.now_us() >= t2 * 1000);
+    }
+
+    #[test]
+    fn test_microseconds() {
+        assert_eq!(ManualClock::new(7).now_us(), 7000);
+        let wall = WallClock.now_us();
+        assert!(wall > 1_600_000_000_000_000); // 2020 年之后
+        assert!(WallClock.now_ms() >= wall / 1000);
+    }
+}
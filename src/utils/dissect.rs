@@ -0,0 +1,338 @@
+use std::fmt::Write;
+
+use crate::link::arp::{ArpPacket, OP_REPLY, OP_REQUEST};
+use crate::link::ethernet::EthernetFrame;
+use crate::net::icmp_v4::IcmpV4;
+use crate::net::ipv4::Ipv4Datagram;
+use crate::transport::tcp_option;
+use crate::transport::tcp_segment::{TcpCtrlFlag, TcpSegment};
+use crate::transport::udp::UdpDatagram;
+use crate::utils::addr;
+use crate::utils::checksum::{self, ChecksumPolicy};
+
+const MAX_PAYLOAD_HEX: usize = 16; // 载荷只显示前 16 字节
+
+/**
+ * 类似 Wireshark 的逐层、逐字段展开, 每个字段显示名字、解码值和原始字节
+ * 某一层格式错误时注明原因并停止向上解析
+ * 例:
+ * Ethernet II, 64 bytes
+ *     Destination: 02:00:00:00:00:01 [02 00 00 00 00 01]
+ * ...
+ */
+pub fn dissect(frame: &[u8]) -> String {
+    dissect_with(frame, ChecksumPolicy::GenerateAndVerify)
+}
+
+/**
+ * 按抓包接口的校验和策略展开; TrustLink 时 FCS 和 TCP 校验和注明 offloaded 而不是判定对错
+ */
+pub fn dissect_with(frame: &[u8], policy: ChecksumPolicy) -> String {
+    let mut out = Dissection { text: String::new(), policy };
+    out.ethernet(frame);
+    out.text
+}
+
+/**
+ * 单行摘要, 类似 tcpdump 的默认输出, 例如
+ * `192.168.0.2 > 192.168.0.1 TCP 51000 > 80 [SYN] seq=287454020 ack=0 win=64240 len=0`
+ */
+pub fn summary(frame: &[u8]) -> String {
+    let frame = match EthernetFrame::try_deserialize(frame) {
+        Ok(frame) => frame,
+        Err(e) => return format!("malformed ethernet: {}", e),
+    };
+    match frame.ether_type() {
+        0x0800 => {}
+        0x0806 => return arp_summary(&frame),
+        other => return format!("{} > {} ethertype {:#06x}, {} bytes",
+            addr::format_mac(&frame.s_mac()), addr::format_mac(&frame.d_mac()), other, frame.payload().len()),
+    }
+    let datagram = match Ipv4Datagram::try_deserialize(frame.payload()) {
+        Ok(datagram) => datagram,
+        Err(e) => return format!("malformed ipv4: {}", e),
+    };
+    let hosts = format!("{} > {}", addr::format_ipv4(datagram.s_addr()), addr::format_ipv4(datagram.d_addr()));
+    match datagram.protocol() {
+        6 => match TcpSegment::try_deserialize(datagram.payload()) {
+            Ok(segment) => format!("{} TCP {}", hosts, segment),
+            Err(e) => format!("{} malformed tcp: {}", hosts, e),
+        },
+        1 => match IcmpV4::try_deserialize(datagram.payload()) {
+            Ok(icmp) => format!("{} ICMP {}, code {}, len={}",
+                hosts, icmp_type_name(icmp.icmp_type()).to_lowercase(), icmp.code(), icmp.data().len()),
+            Err(e) => format!("{} malformed icmp: {}", hosts, e),
+        },
+        17 => format!("{} UDP, {} bytes", hosts, datagram.payload().len()),
+        other => format!("{} ip-proto-{}, {} bytes", hosts, other, datagram.payload().len()),
+    }
+}
+
+/**
+ * 以太网上 IPv4 的 ARP: who-has / is-at
+ */
+fn arp_summary(frame: &EthernetFrame) -> String {
+    let arp = frame.payload();
+    if arp.len() < 28 {
+        return format!("ARP, truncated ({} bytes)", arp.len());
+    }
+    let sender_ip = addr::format_ipv4(u32::from_be_bytes([arp[14], arp[15], arp[16], arp[17]]));
+    let target_ip = addr::format_ipv4(u32::from_be_bytes([arp[24], arp[25], arp[26], arp[27]]));
+    match u16::from_be_bytes([arp[6], arp[7]]) {
+        1 => format!("ARP who-has {} tell {}", target_ip, sender_ip),
+        2 => format!("ARP {} is-at {}", sender_ip, addr::format_mac(&frame.s_mac())),
+        op => format!("ARP op {}", op),
+    }
+}
+
+fn icmp_type_name(icmp_type: u8) -> &'static str {
+    match icmp_type {
+        0 => "Echo Reply",
+        3 => "Destination Unreachable",
+        8 => "Echo Request",
+        11 => "Time Exceeded",
+        _ => "Unknown",
+    }
+}
+
+struct Dissection {
+    text: String,
+    policy: ChecksumPolicy,
+}
+
+impl Dissection {
+    fn title(&mut self, title: &str) {
+        let _ = writeln!(self.text, "{}", title);
+    }
+
+    /**
+     * 缩进 depth 层的一行, 每层 4 个空格
+     */
+    fn line(&mut self, depth: usize, line: &str) {
+        let _ = writeln!(self.text, "{:indent$}{}", "", line, indent = depth * 4);
+    }
+
+    fn field(&mut self, depth: usize, name: &str, value: &str, raw: &[u8]) {
+        self.line(depth, &format!("{}: {} [{}]", name, value, hex(raw)));
+    }
+
+    /**
+     * 发送方按策略可能没有计算的校验和
+     */
+    fn offloadable_verdict(&self, ok: bool) -> &'static str {
+        if self.policy.verify() { verdict(ok) } else { "checksum offloaded" }
+    }
+
+    fn note(&mut self, depth: usize, note: &str) {
+        self.line(depth, &format!("[{}]", note));
+    }
+
+    fn ethernet(&mut self, bytes: &[u8]) {
+        self.title(&format!("Ethernet II, {} bytes", bytes.len()));
+        let frame = match EthernetFrame::try_deserialize(bytes) {
+            Ok(frame) => frame,
+            Err(e) => return self.note(1, &format!("Malformed: {}", e)),
+        };
+        let ether_type = frame.ether_type();
+        let type_name = match ether_type {
+            0x0800 => "IPv4",
+            0x0806 => "ARP",
+            0x86dd => "IPv6",
+            _ => "Unknown",
+        };
+        self.field(1, "Destination", &addr::format_mac(&frame.d_mac()), &bytes[0..6]);
+        self.field(1, "Source", &addr::format_mac(&frame.s_mac()), &bytes[6..12]);
+        self.field(1, "Type", &format!("{} ({:#06x})", type_name, ether_type), &bytes[12..14]);
+        let fcs = &bytes[bytes.len() - 4..];
+        let fcs_value = u32::from_be_bytes([fcs[0], fcs[1], fcs[2], fcs[3]]);
+        self.field(1, "FCS", &format!("{:#010x} ({})", fcs_value, self.offloadable_verdict(frame.check_fcs())), fcs);
+
+        match ether_type {
+            0x0800 => self.ipv4(frame.payload()),
+            0x0806 => self.arp(frame.payload()),
+            _ => self.undissected("Data", frame.payload()),
+        }
+    }
+
+    fn ipv4(&mut self, bytes: &[u8]) {
+        let datagram = match Ipv4Datagram::try_deserialize(bytes) {
+            Ok(datagram) => datagram,
+            Err(e) => {
+                self.title("Internet Protocol Version 4");
+                return self.note(1, &format!("Malformed: {}", e));
+            }
+        };
+        let src = addr::format_ipv4(datagram.s_addr());
+        let dst = addr::format_ipv4(datagram.d_addr());
+        let hdr_len = ((bytes[0] & 0x0f) as usize) * 4;
+        let total_len = u16::from_be_bytes([bytes[2], bytes[3]]) as usize;
+        let flags = bytes[6] >> 5;
+        let mut flag_names: Vec<&str> = vec![];
+        if flags & 0b100 != 0 { flag_names.push("Reserved"); }
+        if flags & 0b010 != 0 { flag_names.push("Don't Fragment"); }
+        if flags & 0b001 != 0 { flag_names.push("More Fragments"); }
+        let protocol = datagram.protocol();
+        let protocol_name = match protocol {
+            1 => "ICMP",
+            6 => "TCP",
+            17 => "UDP",
+            _ => "Unknown",
+        };
+
+        self.title(&format!("Internet Protocol Version 4, Src: {}, Dst: {}", src, dst));
+        self.field(1, "Version", &(bytes[0] >> 4).to_string(), &bytes[0..1]);
+        self.field(1, "Header Length", &format!("{} bytes ({})", hdr_len, bytes[0] & 0x0f), &bytes[0..1]);
+        self.field(1, "Type of Service", &format!("{:#04x}", bytes[1]), &bytes[1..2]);
+        self.field(1, "Total Length", &total_len.to_string(), &bytes[2..4]);
+        let id = u16::from_be_bytes([bytes[4], bytes[5]]);
+        self.field(1, "Identification", &format!("{:#06x} ({})", id, id), &bytes[4..6]);
+        self.field(1, "Flags", &format!("{:#x} ({})", flags, flag_names.join(", ")), &bytes[6..7]);
+        let frag_offset = u16::from_be_bytes([bytes[6] & 0x1f, bytes[7]]);
+        self.field(1, "Fragment Offset", &frag_offset.to_string(), &bytes[6..8]);
+        self.field(1, "Time to Live", &datagram.ttl().to_string(), &bytes[8..9]);
+        self.field(1, "Protocol", &format!("{} ({})", protocol_name, protocol), &bytes[9..10]);
+        let hdr_checksum = u16::from_be_bytes([bytes[10], bytes[11]]);
+        self.field(1, "Header Checksum", &format!("{:#06x} ({})", hdr_checksum, verdict(datagram.check_hdr_checksum())), &bytes[10..12]);
+        self.field(1, "Source Address", &src, &bytes[12..16]);
+        self.field(1, "Destination Address", &dst, &bytes[16..20]);
+        if hdr_len > 20 {
+            self.field(1, "Options", &format!("{} bytes", hdr_len - 20), &bytes[20..hdr_len]);
+        }
+        if bytes.len() > total_len {
+            self.note(1, &format!("Ethernet padding: {} bytes", bytes.len() - total_len));
+        }
+
+        match protocol {
+            1 => self.icmp(datagram.payload()),
+            6 => self.tcp(datagram.payload(), datagram.s_addr(), datagram.d_addr()),
+            17 => self.udp(datagram.payload(), datagram.s_addr(), datagram.d_addr()),
+            _ => self.undissected("Data", datagram.payload()),
+        }
+    }
+
+    fn tcp(&mut self, bytes: &[u8], s_addr: u32, d_addr: u32) {
+        let segment = match TcpSegment::try_deserialize(bytes) {
+            Ok(segment) => segment,
+            Err(e) => {
+                self.title("Transmission Control Protocol");
+                return self.note(1, &format!("Malformed: {}", e));
+            }
+        };
+        let hdr_len = (segment.hl as usize) * 4;
+        self.title(&format!("Transmission Control Protocol, Src Port: {}, Dst Port: {}, Seq: {}, Ack: {}, Len: {}",
+            segment.s_port, segment.d_port, segment.seq, segment.ack, segment.data.len()));
+        self.field(1, "Source Port", &segment.s_port.to_string(), &bytes[0..2]);
+        self.field(1, "Destination Port", &segment.d_port.to_string(), &bytes[2..4]);
+        self.field(1, "Sequence Number", &segment.seq.to_string(), &bytes[4..8]);
+        self.field(1, "Acknowledgment Number", &segment.ack.to_string(), &bytes[8..12]);
+        self.field(1, "Header Length", &format!("{} bytes ({})", hdr_len, segment.hl), &bytes[12..13]);
+        self.field(1, "Flags", &format!("{:#05x} ({})", segment.ctrl.bits(), TcpCtrlFlag::names(segment.ctrl.bits()).join(", ")), &bytes[12..14]);
+        self.field(1, "Window", &segment.win_size.to_string(), &bytes[14..16]);
+        let tcp_checksum = u16::from_be_bytes([bytes[16], bytes[17]]);
+        self.field(1, "Checksum", &format!("{:#06x} ({})", tcp_checksum, self.offloadable_verdict(segment.check_checksum(s_addr, d_addr))), &bytes[16..18]);
+        self.field(1, "Urgent Pointer", &segment.ur_ptr.to_string(), &bytes[18..20]);
+
+        if hdr_len > 20 {
+            let options = &bytes[20..hdr_len];
+            self.field(1, "Options", &format!("{} bytes", options.len()), options);
+            match tcp_option::parse_options_with_spans(options) {
+                Ok(parsed) => {
+                    for (option, span) in parsed {
+                        self.line(2, &format!("{} [{}]", option, hex(&options[span])));
+                    }
+                }
+                Err(e) => self.note(2, &format!("Malformed: {}", e)),
+            }
+        }
+        if !segment.data.is_empty() {
+            self.payload(1, &segment.data);
+        }
+    }
+
+    /**
+     * 只展开以太网/IPv4 的 ARP, 其余硬件和协议类型按格式错误处理; 最小帧的填充不算在报文内
+     */
+    fn arp(&mut self, bytes: &[u8]) {
+        let arp = match ArpPacket::try_deserialize(bytes) {
+            Ok(arp) => arp,
+            Err(e) => {
+                self.title("Address Resolution Protocol");
+                return self.note(1, &format!("Malformed: {}", e));
+            }
+        };
+        let op_name = match arp.op {
+            OP_REQUEST => "request",
+            OP_REPLY => "reply",
+            _ => "unknown",
+        };
+        self.title(&format!("Address Resolution Protocol ({})", op_name));
+        self.field(1, "Hardware Type", "Ethernet (1)", &bytes[0..2]);
+        self.field(1, "Protocol Type", "IPv4 (0x0800)", &bytes[2..4]);
+        self.field(1, "Hardware Size", &bytes[4].to_string(), &bytes[4..5]);
+        self.field(1, "Protocol Size", &bytes[5].to_string(), &bytes[5..6]);
+        self.field(1, "Opcode", &format!("{} ({})", op_name, arp.op), &bytes[6..8]);
+        self.field(1, "Sender MAC Address", &addr::format_mac(&arp.s_mac), &bytes[8..14]);
+        self.field(1, "Sender IP Address", &addr::format_ipv4(arp.s_ip), &bytes[14..18]);
+        self.field(1, "Target MAC Address", &addr::format_mac(&arp.t_mac), &bytes[18..24]);
+        self.field(1, "Target IP Address", &addr::format_ipv4(arp.t_ip), &bytes[24..28]);
+    }
+
+    /**
+     * 校验和为 0 表示发送方没有计算, 不判定对错
+     */
+    fn udp(&mut self, bytes: &[u8], s_addr: u32, d_addr: u32) {
+        let datagram = match UdpDatagram::try_deserialize(bytes) {
+            Ok(datagram) => datagram,
+            Err(e) => {
+                self.title("User Datagram Protocol");
+                return self.note(1, &format!("Malformed: {}", e));
+            }
+        };
+        self.title(&format!("User Datagram Protocol, Src Port: {}, Dst Port: {}", datagram.s_port, datagram.d_port));
+        self.field(1, "Source Port", &datagram.s_port.to_string(), &bytes[0..2]);
+        self.field(1, "Destination Port", &datagram.d_port.to_string(), &bytes[2..4]);
+        let length = u16::from_be_bytes([bytes[4], bytes[5]]);
+        self.field(1, "Length", &length.to_string(), &bytes[4..6]);
+        let udp_verdict = if datagram.checksum == 0 { "none" } else { self.offloadable_verdict(datagram.check_checksum(s_addr, d_addr)) };
+        self.field(1, "Checksum", &format!("{:#06x} ({})", datagram.checksum, udp_verdict), &bytes[6..8]);
+        if !datagram.data.is_empty() {
+            self.payload(1, &datagram.data);
+        }
+    }
+
+    fn icmp(&mut self, bytes: &[u8]) {
+        self.title("Internet Control Message Protocol");
+        if let Err(e) = IcmpV4::try_deserialize(bytes) {
+            return self.note(1, &format!("Malformed: {}", e));
+        }
+        self.field(1, "Type", &format!("{} ({})", bytes[0], icmp_type_name(bytes[0])), &bytes[0..1]);
+        self.field(1, "Code", &bytes[1].to_string(), &bytes[1..2]);
+        let icmp_checksum = u16::from_be_bytes([bytes[2], bytes[3]]);
+        self.field(1, "Checksum", &format!("{:#06x} ({})", icmp_checksum, verdict(checksum::check(bytes))), &bytes[2..4]);
+        if bytes.len() > 4 {
+            self.payload(1, &bytes[4..]);
+        }
+    }
+
+    fn payload(&mut self, depth: usize, data: &[u8]) {
+        let shown = &data[..data.len().min(MAX_PAYLOAD_HEX)];
+        let ellipsis = if data.len() > MAX_PAYLOAD_HEX { " ..." } else { "" };
+        self.line(depth, &format!("Payload: {} bytes [{}{}]", data.len(), hex(shown), ellipsis));
+    }
+
+    /**
+     * 没有实现解析的协议, 只给出长度
+     */
+    fn undissected(&mut self, title: &str, data: &[u8]) {
+        self.title(title);
+        self.note(1, &format!("Not dissected: {} bytes", data.len()));
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<String>>().join(" ")
+}
+
+fn verdict(ok: bool) -> &'static str {
+    if ok { "correct" } else { "incorrect" }
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::error::ParseError;
+
+/**
+ * 协议栈丢弃数据的所有原因
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum DropReason {
+    BadFcs,
+    RxTruncated,     // 帧内的 IP total_len 超过实际载荷
+    RxGiant,         // 载荷超过接口 MTU
+    BadIpChecksum,
+    BadTcpChecksum,
+    BadUdpChecksum,
+    BadMd5Signature, // 配置了 MD5 密钥的连接上签名缺失或不匹配
+    BadIcmpChecksum,
+    NotForUs,
+    NoRoute,
+    MartianSource,     // 回环、组播、E 类、0.0.0.0/8 等不该出现在线路上的源地址
+    LocalSource,       // 从非回环接口收到源地址是本机地址的数据报
+    ReversePath,       // 严格反向路径检查失败
+    TtlExpired,
+    NoListener,
+    NotSynchronized, // 连接尚未收到 SYN 时到达的报文段
+    OutOfWindow,
+    ReassemblyTimeout,
+    QueueFull,
+    NoMemory,        // 内存预算耗尽, 无法缓存
+    RateLimited,
+    Untranslatable,  // 要经 NAT 出去的数据报无法转换: 分片、不支持的协议或端口耗尽
+    ParseError,
+}
+
+impl DropReason {
+    /**
+     * 稳定的名字, 用于日志和指标
+     */
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DropReason::BadFcs => "bad_fcs",
+            DropReason::RxTruncated => "rx_truncated",
+            DropReason::RxGiant => "rx_giant",
+            DropReason::BadIpChecksum => "bad_ip_checksum",
+            DropReason::BadTcpChecksum => "bad_tcp_checksum",
+            DropReason::BadUdpChecksum => "bad_udp_checksum",
+            DropReason::BadMd5Signature => "bad_md5_signature",
+            DropReason::BadIcmpChecksum => "bad_icmp_checksum",
+            DropReason::NotForUs => "not_for_us",
+            DropReason::NoRoute => "no_route",
+            DropReason::MartianSource => "martian_source",
+            DropReason::LocalSource => "local_source",
+            DropReason::ReversePath => "reverse_path",
+            DropReason::TtlExpired => "ttl_expired",
+            DropReason::NoListener => "no_listener",
+            DropReason::NotSynchronized => "not_synchronized",
+            DropReason::OutOfWindow => "out_of_window",
+            DropReason::ReassemblyTimeout => "reassembly_timeout",
+            DropReason::QueueFull => "queue_full",
+            DropReason::NoMemory => "no_memory",
+            DropReason::RateLimited => "rate_limited",
+            DropReason::Untranslatable => "untranslatable",
+            DropReason::ParseError => "parse_error",
+        }
+    }
+}
+
+impl fmt::Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl From<&ParseError> for DropReason {
+    fn from(_: &ParseError) -> Self {
+        DropReason::ParseError
+    }
+}
+
+/**
+ * 按原因统计的丢弃计数
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DropCounters {
+    counts: BTreeMap<DropReason, u64>,
+}
+
+impl DropCounters {
+    pub fn new() -> Self {
+        DropCounters { counts: BTreeMap::new() }
+    }
+
+    /**
+     * 记录一次丢弃, 返回该原因累计的次数
+     */
+    pub fn record(&mut self, reason: DropReason) -> u64 {
+        let count = self.counts.entry(reason).or_insert(0);
+        *count += 1;
+        *count
+    }
+
+    pub fn get(&self, reason: DropReason) -> u64 {
+        self.counts.get(&reason).copied().unwrap_or(0)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /**
+     * 只包含计数非零的原因, 按原因排序
+     */
+    pub fn iter(&self) -> impl Iterator<Item = (DropReason, u64)> + '_ {
+        self.counts.iter().map(|(reason, count)| (*reason, *count))
+    }
+
+    /**
+     * 汇总其它组件的计数
+     */
+    pub fn merge(&mut self, other: &DropCounters) {
+        for (reason, count) in other.iter() {
+            *self.counts.entry(reason).or_insert(0) += count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_merge() {
+        let mut counters = DropCounters::new();
+        assert_eq!(counters.record(DropReason::BadFcs), 1);
+        assert_eq!(counters.record(DropReason::BadFcs), 2);
+        counters.record(DropReason::OutOfWindow);
+
+        let mut other = DropCounters::new();
+        other.record(DropReason::OutOfWindow);
+        other.record(DropReason::QueueFull);
+        counters.merge(&other);
+
+        assert_eq!(counters.get(DropReason::BadFcs), 2);
+        assert_eq!(counters.get(DropReason::OutOfWindow), 2);
+        assert_eq!(counters.get(DropReason::NoRoute), 0);
+        assert_eq!(counters.total(), 5);
+        let reasons: Vec<DropReason> = counters.iter().map(|(reason, _)| reason).collect();
+        assert_eq!(reasons, vec![DropReason::BadFcs, DropReason::OutOfWindow, DropReason::QueueFull]);
+    }
+}
@@ -0,0 +1,311 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::link::ethernet::EthernetFrame;
+use crate::net::ipv4::Ipv4Datagram;
+use crate::utils::addr;
+
+/**
+ * 按协议匹配
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Proto {
+    Arp,
+    Ip,
+    Icmp,
+    Tcp,
+    Udp,
+}
+
+/**
+ * 端口、地址匹配的方向, Either 表示源或目的任一匹配即可
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dir {
+    Src,
+    Dst,
+    Either,
+}
+
+/**
+ * tcpdump 风格过滤表达式的一个子集:
+ * 原语 `arp` `ip` `icmp` `tcp` `udp` `[src|dst] port N` `[src|dst] host A.B.C.D`,
+ * 用 `not`/`!`、`and`/`&&`、`or`/`||` 和括号组合, 优先级 not > and > or; 空表达式匹配一切
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    Any,
+    Proto(Proto),
+    Port { dir: Dir, port: u16 },
+    Host { dir: Dir, addr: u32 },
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+/**
+ * 过滤表达式解析错误, position 为出错的记号下标(从 0 开始)
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterParseError {
+    UnexpectedEnd,
+    UnexpectedToken { token: String, position: usize },
+    BadPort { token: String, position: usize },
+    BadHost { token: String, position: usize },
+}
+
+impl fmt::Display for FilterParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterParseError::UnexpectedEnd => write!(f, "unexpected end of filter expression"),
+            FilterParseError::UnexpectedToken { token, position } => {
+                write!(f, "unexpected {:?} at token {}", token, position)
+            }
+            FilterParseError::BadPort { token, position } => write!(f, "bad port {:?} at token {}", token, position),
+            FilterParseError::BadHost { token, position } => write!(f, "bad host {:?} at token {}", token, position),
+        }
+    }
+}
+
+impl Error for FilterParseError {}
+
+/**
+ * 匹配时用到的各层字段, 解析不出的层为 None
+ */
+struct Fields {
+    ether_type: u16,
+    ip: Option<(u8, u32, u32)>, // (protocol, s_addr, d_addr)
+    ports: Option<(u16, u16)>,
+}
+
+impl Fields {
+    fn extract(frame: &[u8]) -> Option<Fields> {
+        let frame = EthernetFrame::try_deserialize(frame).ok()?;
+        let mut fields = Fields { ether_type: frame.ether_type(), ip: None, ports: None };
+        if fields.ether_type != 0x0800 {
+            return Some(fields);
+        }
+        if let Ok(datagram) = Ipv4Datagram::try_deserialize(frame.payload()) {
+            fields.ip = Some((datagram.protocol(), datagram.s_addr(), datagram.d_addr()));
+            let l4 = datagram.payload();
+            if matches!(datagram.protocol(), 6 | 17) && l4.len() >= 4 {
+                fields.ports = Some((u16::from_be_bytes([l4[0], l4[1]]), u16::from_be_bytes([l4[2], l4[3]])));
+            }
+        }
+        Some(fields)
+    }
+}
+
+fn dir_matches<T: PartialEq>(dir: Dir, pair: (T, T), want: T) -> bool {
+    match dir {
+        Dir::Src => pair.0 == want,
+        Dir::Dst => pair.1 == want,
+        Dir::Either => pair.0 == want || pair.1 == want,
+    }
+}
+
+impl Filter {
+    pub fn parse(expr: &str) -> Result<Filter, FilterParseError> {
+        let tokens = tokenize(expr);
+        if tokens.is_empty() {
+            return Ok(Filter::Any);
+        }
+        let mut parser = Parser { tokens, pos: 0 };
+        let filter = parser.or()?;
+        match parser.peek() {
+            None => Ok(filter),
+            Some(token) => Err(FilterParseError::UnexpectedToken { token: token.to_string(), position: parser.pos }),
+        }
+    }
+
+    /**
+     * frame 为带 FCS 的完整以太网帧; 以太网层都解析不出时只有 Any 匹配
+     */
+    pub fn matches(&self, frame: &[u8]) -> bool {
+        match Fields::extract(frame) {
+            Some(fields) => self.eval(&fields),
+            None => *self == Filter::Any,
+        }
+    }
+
+    fn eval(&self, fields: &Fields) -> bool {
+        match self {
+            Filter::Any => true,
+            Filter::Proto(Proto::Arp) => fields.ether_type == 0x0806,
+            Filter::Proto(Proto::Ip) => fields.ip.is_some(),
+            Filter::Proto(Proto::Icmp) => matches!(fields.ip, Some((1, _, _))),
+            Filter::Proto(Proto::Tcp) => matches!(fields.ip, Some((6, _, _))),
+            Filter::Proto(Proto::Udp) => matches!(fields.ip, Some((17, _, _))),
+            Filter::Port { dir, port } => fields.ports.is_some_and(|ports| dir_matches(*dir, ports, *port)),
+            Filter::Host { dir, addr } => fields.ip.is_some_and(|(_, s, d)| dir_matches(*dir, (s, d), *addr)),
+            Filter::Not(inner) => !inner.eval(fields),
+            Filter::And(a, b) => a.eval(fields) && b.eval(fields),
+            Filter::Or(a, b) => a.eval(fields) || b.eval(fields),
+        }
+    }
+}
+
+fn tokenize(expr: &str) -> Vec<String> {
+    expr.replace('(', " ( ").replace(')', " ) ").replace('!', " ! ")
+        .split_whitespace()
+        .map(str::to_string)
+        .collect()
+}
+
+/**
+ * 递归下降: or := and (or and)*, and := unary (and unary)*, unary := not unary | primary
+ */
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<String, FilterParseError> {
+        let token = self.tokens.get(self.pos).cloned().ok_or(FilterParseError::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn or(&mut self) -> Result<Filter, FilterParseError> {
+        let mut left = self.and()?;
+        while matches!(self.peek(), Some("or") | Some("||")) {
+            self.pos += 1;
+            left = Filter::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Filter, FilterParseError> {
+        let mut left = self.unary()?;
+        while matches!(self.peek(), Some("and") | Some("&&")) {
+            self.pos += 1;
+            left = Filter::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Filter, FilterParseError> {
+        if matches!(self.peek(), Some("not") | Some("!")) {
+            self.pos += 1;
+            return Ok(Filter::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Filter, FilterParseError> {
+        let position = self.pos;
+        let token = self.next()?;
+        match token.as_str() {
+            "(" => {
+                let inner = self.or()?;
+                let position = self.pos;
+                match self.next()?.as_str() {
+                    ")" => Ok(inner),
+                    other => Err(FilterParseError::UnexpectedToken { token: other.to_string(), position }),
+                }
+            }
+            "arp" => Ok(Filter::Proto(Proto::Arp)),
+            "ip" => Ok(Filter::Proto(Proto::Ip)),
+            "icmp" => Ok(Filter::Proto(Proto::Icmp)),
+            "tcp" => Ok(Filter::Proto(Proto::Tcp)),
+            "udp" => Ok(Filter::Proto(Proto::Udp)),
+            "src" => self.qualified(Dir::Src),
+            "dst" => self.qualified(Dir::Dst),
+            "port" | "host" => {
+                self.pos -= 1;
+                self.qualified(Dir::Either)
+            }
+            _ => Err(FilterParseError::UnexpectedToken { token, position }),
+        }
+    }
+
+    fn qualified(&mut self, dir: Dir) -> Result<Filter, FilterParseError> {
+        let position = self.pos;
+        let kind = self.next()?;
+        let value_position = self.pos;
+        match kind.as_str() {
+            "port" => {
+                let token = self.next()?;
+                match token.parse::<u16>() {
+                    Ok(port) => Ok(Filter::Port { dir, port }),
+                    Err(_) => Err(FilterParseError::BadPort { token, position: value_position }),
+                }
+            }
+            "host" => {
+                let token = self.next()?;
+                match addr::parse_ipv4(&token) {
+                    Some(addr) => Ok(Filter::Host { dir, addr }),
+                    None => Err(FilterParseError::BadHost { token, position: value_position }),
+                }
+            }
+            _ => Err(FilterParseError::UnexpectedToken { token: kind, position }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::packet::PacketBuilder;
+
+    const A: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+    const B: [u8; 6] = [0x02, 0, 0, 0, 0, 2];
+
+    fn tcp(s_port: u16, d_port: u16) -> Vec<u8> {
+        PacketBuilder::ether(A, B).ipv4(0x0a000001, 0x0a000002).tcp(s_port, d_port).build()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Filter::parse("  ").unwrap(), Filter::Any);
+        assert_eq!(Filter::parse("src port 80").unwrap(), Filter::Port { dir: Dir::Src, port: 80 });
+        assert_eq!(
+            Filter::parse("tcp and not port 22 or icmp").unwrap(),
+            Filter::Or(
+                Box::new(Filter::And(
+                    Box::new(Filter::Proto(Proto::Tcp)),
+                    Box::new(Filter::Not(Box::new(Filter::Port { dir: Dir::Either, port: 22 }))),
+                )),
+                Box::new(Filter::Proto(Proto::Icmp)),
+            )
+        );
+        assert_eq!(Filter::parse("tcp and").unwrap_err(), FilterParseError::UnexpectedEnd);
+        assert_eq!(
+            Filter::parse("port http").unwrap_err(),
+            FilterParseError::BadPort { token: "http".to_string(), position: 1 }
+        );
+        assert_eq!(
+            Filter::parse("dst host 10.0.0").unwrap_err(),
+            FilterParseError::BadHost { token: "10.0.0".to_string(), position: 2 }
+        );
+        assert_eq!(
+            Filter::parse("(tcp udp)").unwrap_err(),
+            FilterParseError::UnexpectedToken { token: "udp".to_string(), position: 2 }
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        let web = tcp(40000, 80);
+        let ssh = tcp(40001, 22);
+        let ping = PacketBuilder::ether(A, B).ipv4(0x0a000003, 0x0a000002).icmp(8, 0).build();
+
+        let filter = Filter::parse("tcp && !(port 22)").unwrap();
+        assert!(filter.matches(&web));
+        assert!(!filter.matches(&ssh));
+        assert!(!filter.matches(&ping));
+
+        assert!(Filter::parse("dst port 80").unwrap().matches(&web));
+        assert!(!Filter::parse("src port 80").unwrap().matches(&web));
+        assert!(Filter::parse("src host 10.0.0.3 or port 22").unwrap().matches(&ping));
+        assert!(Filter::parse("ip and dst host 10.0.0.2").unwrap().matches(&ssh));
+        assert!(!Filter::parse("arp").unwrap().matches(&web));
+        assert!(!Filter::parse("tcp").unwrap().matches(&[0u8; 10]));
+        assert!(Filter::parse("").unwrap().matches(&[0u8; 10]));
+    }
+}
@@ -0,0 +1,91 @@
+/**
+ * 桶的个数: 第 0 个桶为 0ms, 第 i 个桶为 [2^(i-1), 2^i) ms, 最后一个桶收容所有更大的值
+ */
+pub const LATENCY_BUCKETS: usize = 16;
+
+/**
+ * 毫秒延迟的直方图, 桶按 2 的幂划分
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    buckets: [u64; LATENCY_BUCKETS],
+    count: u64,
+    sum_ms: u64,
+    max_ms: u64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, ms: u64) {
+        let index = (u64::BITS - ms.leading_zeros()) as usize;
+        self.buckets[index.min(LATENCY_BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.sum_ms = self.sum_ms.saturating_add(ms);
+        self.max_ms = self.max_ms.max(ms);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn max_ms(&self) -> u64 {
+        self.max_ms
+    }
+
+    pub fn mean_ms(&self) -> Option<u64> {
+        self.sum_ms.checked_div(self.count)
+    }
+
+    pub fn buckets(&self) -> &[u64; LATENCY_BUCKETS] {
+        &self.buckets
+    }
+
+    /**
+     * 第 index 个桶的上界(不含), 最后一个桶没有上界
+     */
+    pub fn bucket_upper_ms(index: usize) -> Option<u64> {
+        (index < LATENCY_BUCKETS - 1).then(|| 1u64 << index)
+    }
+
+    /**
+     * 分位数 q (0.0..=1.0) 落在的桶的上界, 最后一个桶用 max_ms 代替; 没有样本时为 None
+     */
+    pub fn quantile_upper_ms(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Some(Self::bucket_upper_ms(index).unwrap_or(self.max_ms));
+            }
+        }
+        Some(self.max_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets() {
+        let mut histogram = LatencyHistogram::new();
+        for ms in [0, 1, 2, 3, 4, 100, u64::MAX] {
+            histogram.record(ms);
+        }
+        let buckets = histogram.buckets();
+        assert_eq!(&buckets[..4], &[1, 1, 2, 1]);
+        assert_eq!(buckets[7], 1); // [64, 128)
+        assert_eq!(buckets[LATENCY_BUCKETS - 1], 1);
+        assert_eq!(histogram.count(), 7);
+        assert_eq!(histogram.max_ms(), u64::MAX);
+        assert_eq!(histogram.quantile_upper_ms(0.5), Some(4));
+        assert_eq!(LatencyHistogram::new().mean_ms(), None);
+    }
+}
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/**
+ * 向内存预算记账的组件
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MemoryComponent {
+    SendBuffer,     // TCP 发送缓冲区和在途数据
+    RecvBuffer,     // TCP 接收缓冲区: 未读数据和已通告的窗口(乱序数据落在窗口内)
+    Fragments,      // IP 分片重组
+    InterfaceQueue, // 接口上排队的数据报
+}
+
+impl MemoryComponent {
+    pub const ALL: [MemoryComponent; 4] = [
+        MemoryComponent::SendBuffer,
+        MemoryComponent::RecvBuffer,
+        MemoryComponent::Fragments,
+        MemoryComponent::InterfaceQueue,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MemoryComponent::SendBuffer => "send_buffer",
+            MemoryComponent::RecvBuffer => "recv_buffer",
+            MemoryComponent::Fragments => "fragments",
+            MemoryComponent::InterfaceQueue => "interface_queue",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    limit: usize,
+    total: AtomicUsize,
+    peak: AtomicUsize,
+    used: [AtomicUsize; 4], // 按 MemoryComponent::ALL 的顺序
+}
+
+/**
+ * 整个协议栈共享的内存预算, 各组件在缓存数据之前记账, 释放数据时退还
+ * 预算耗尽时组件应当降级(通告更小的窗口、丢弃数据、拒绝新的重组), 而不是继续分配
+ * clone 出来的实例共享同一个计数
+ */
+#[derive(Debug, Clone)]
+pub struct MemoryBudget {
+    shared: Arc<Shared>,
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize) -> Self {
+        MemoryBudget {
+            shared: Arc::new(Shared {
+                limit,
+                total: AtomicUsize::new(0),
+                peak: AtomicUsize::new(0),
+                used: Default::default(),
+            }),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(usize::MAX)
+    }
+
+    pub fn limit(&self) -> usize {
+        self.shared.limit
+    }
+
+    pub fn used(&self) -> usize {
+        self.shared.total.load(Ordering::SeqCst)
+    }
+
+    pub fn available(&self) -> usize {
+        self.shared.limit.saturating_sub(self.used())
+    }
+
+    /**
+     * 记账时出现过的最大用量
+     */
+    pub fn peak(&self) -> usize {
+        self.shared.peak.load(Ordering::SeqCst)
+    }
+
+    /**
+     * 尽量记账 n 字节, 返回实际记上的字节数(可能为 0)
+     */
+    pub fn charge_up_to(&self, component: MemoryComponent, n: usize) -> usize {
+        self.charge(component, n, false)
+    }
+
+    /**
+     * 全部记上才返回 true, 否则不记账
+     */
+    pub fn try_charge(&self, component: MemoryComponent, n: usize) -> bool {
+        self.charge(component, n, true) == n
+    }
+
+    pub fn release(&self, component: MemoryComponent, n: usize) {
+        if n == 0 {
+            return;
+        }
+        self.shared.total.fetch_sub(n, Ordering::SeqCst);
+        self.shared.used[component as usize].fetch_sub(n, Ordering::SeqCst);
+    }
+
+    fn charge(&self, component: MemoryComponent, n: usize, all_or_nothing: bool) -> usize {
+        if n == 0 {
+            return 0;
+        }
+        let limit = self.shared.limit;
+        let mut granted = 0;
+        let result = self.shared.total.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |total| {
+            granted = n.min(limit.saturating_sub(total));
+            if granted == 0 || (all_or_nothing && granted < n) {
+                return None;
+            }
+            Some(total + granted)
+        });
+        let Ok(before) = result else { return 0 };
+        self.shared.used[component as usize].fetch_add(granted, Ordering::SeqCst);
+        self.shared.peak.fetch_max(before + granted, Ordering::SeqCst);
+        granted
+    }
+
+    /**
+     * 按组件分列的当前用量
+     */
+    pub fn usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            by_component: MemoryComponent::ALL.map(|component| (component, self.shared.used[component as usize].load(Ordering::SeqCst))),
+            total: self.used(),
+            peak: self.peak(),
+            limit: self.limit(),
+        }
+    }
+}
+
+/**
+ * 内存用量快照
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub by_component: [(MemoryComponent, usize); 4],
+    pub total: usize,
+    pub peak: usize,
+    pub limit: usize,
+}
+
+impl MemoryUsage {
+    pub fn get(&self, component: MemoryComponent) -> usize {
+        self.by_component[component as usize].1
+    }
+}
+
+impl fmt::Display for MemoryUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (component, used) in &self.by_component {
+            write!(f, "{}={} ", component.as_str(), used)?;
+        }
+        write!(f, "total={} peak={}", self.total, self.peak)?;
+        if self.limit != usize::MAX {
+            write!(f, " limit={}", self.limit)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_charge_and_release() {
+        let budget = MemoryBudget::new(1000);
+        let shared = budget.clone();
+        assert!(budget.try_charge(MemoryComponent::SendBuffer, 600));
+        assert!(!shared.try_charge(MemoryComponent::Fragments, 500));
+        assert_eq!(shared.charge_up_to(MemoryComponent::RecvBuffer, 500), 400);
+        assert_eq!(budget.charge_up_to(MemoryComponent::RecvBuffer, 1), 0);
+        assert_eq!(budget.available(), 0);
+
+        budget.release(MemoryComponent::SendBuffer, 600);
+        let usage = shared.usage();
+        assert_eq!((usage.get(MemoryComponent::SendBuffer), usage.get(MemoryComponent::RecvBuffer)), (0, 400));
+        assert_eq!((usage.total, usage.peak), (400, 1000));
+        assert_eq!(usage.to_string(), "send_buffer=0 recv_buffer=400 fragments=0 interface_queue=0 total=400 peak=1000 limit=1000");
+        assert_eq!(MemoryBudget::unlimited().charge_up_to(MemoryComponent::Fragments, usize::MAX / 2), usize::MAX / 2);
+    }
+}
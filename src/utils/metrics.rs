@@ -0,0 +1,185 @@
+use std::fmt::Write;
+
+use crate::link::arp_cache::ArpCache;
+use crate::link::interface::EthernetInterface;
+use crate::transport::connection_table::ConnectionTable;
+use crate::utils::drops::{DropCounters, DropReason};
+use crate::utils::memory::MemoryBudget;
+
+/**
+ * 校验和类的丢弃原因与 checksum_errors 指标的 kind 标签
+ */
+const CHECKSUM_KINDS: [(DropReason, &str); 5] = [
+    (DropReason::BadFcs, "fcs"),
+    (DropReason::BadIpChecksum, "ip"),
+    (DropReason::BadTcpChecksum, "tcp"),
+    (DropReason::BadUdpChecksum, "udp"),
+    (DropReason::BadIcmpChecksum, "icmp"),
+];
+
+/**
+ * Prometheus 文本格式的输出, 每个指标先写 HELP 和 TYPE 再写样本
+ */
+#[derive(Debug, Default)]
+pub struct MetricsWriter {
+    out: String,
+}
+
+impl MetricsWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn counter(&mut self, name: &str, help: &str, samples: &[(Vec<(&str, &str)>, u64)]) {
+        self.family(name, help, "counter", samples.iter().map(|(labels, value)| (labels, value.to_string())));
+    }
+
+    pub fn gauge(&mut self, name: &str, help: &str, samples: &[(Vec<(&str, &str)>, u64)]) {
+        self.family(name, help, "gauge", samples.iter().map(|(labels, value)| (labels, value.to_string())));
+    }
+
+    /**
+     * 字节数的 gauge, usize::MAX 表示没有上限, 写成 +Inf
+     */
+    pub fn gauge_bytes(&mut self, name: &str, help: &str, value: usize) {
+        let value = if value == usize::MAX { "+Inf".to_string() } else { value.to_string() };
+        self.family(name, help, "gauge", std::iter::once((&vec![], value)));
+    }
+
+    fn family<'a>(&mut self, name: &str, help: &str, kind: &str, samples: impl Iterator<Item = (&'a Vec<(&'a str, &'a str)>, String)>) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+        for (labels, value) in samples {
+            if labels.is_empty() {
+                let _ = writeln!(self.out, "{} {}", name, value);
+                continue;
+            }
+            let labels: Vec<String> = labels.iter().map(|(key, value)| format!("{}=\"{}\"", key, escape(value))).collect();
+            let _ = writeln!(self.out, "{}{{{}}} {}", name, labels.join(","), value);
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+/**
+ * 标签值中的反斜杠、双引号和换行需要转义
+ */
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/**
+ * 协议栈各组件的指标汇总; 连接只按状态聚合, 不输出单个连接, 控制标签基数
+ * 指标名是稳定的接口, 改名需要同时更新 tests/metrics.rs
+ */
+#[derive(Default)]
+pub struct StackMetrics<'a> {
+    interfaces: Vec<(&'a str, &'a EthernetInterface)>,
+    connections: Option<&'a ConnectionTable>,
+    drops: Vec<&'a DropCounters>,
+    arp: Vec<&'a ArpCache>,
+    memory: Option<&'a MemoryBudget>,
+}
+
+impl<'a> StackMetrics<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * 接口的收发计数, 丢弃计数一并汇总到 drops
+     */
+    pub fn interface(mut self, name: &'a str, iface: &'a EthernetInterface) -> Self {
+        self.interfaces.push((name, iface));
+        self
+    }
+
+    pub fn connections(mut self, table: &'a ConnectionTable) -> Self {
+        self.connections = Some(table);
+        self
+    }
+
+    /**
+     * 其他组件(重组器、ICMP 等)的丢弃计数
+     */
+    pub fn drops(mut self, drops: &'a DropCounters) -> Self {
+        self.drops.push(drops);
+        self
+    }
+
+    /**
+     * 多个接口的 ARP 缓存各调用一次, 表项数相加
+     */
+    pub fn arp_cache(mut self, arp: &'a ArpCache) -> Self {
+        self.arp.push(arp);
+        self
+    }
+
+    pub fn memory(mut self, budget: &'a MemoryBudget) -> Self {
+        self.memory = Some(budget);
+        self
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = MetricsWriter::new();
+        if !self.interfaces.is_empty() {
+            let per_interface = |f: fn(&EthernetInterface) -> u64| -> Vec<(Vec<(&str, &str)>, u64)> {
+                self.interfaces.iter().map(|(name, iface)| (vec![("interface", *name)], f(iface))).collect()
+            };
+            out.counter("stip_interface_rx_frames_total", "Frames received from the device.", &per_interface(|iface| iface.stats().rx_frames));
+            out.counter("stip_interface_rx_bytes_total", "Bytes received from the device.", &per_interface(|iface| iface.stats().rx_bytes));
+            out.counter("stip_interface_tx_frames_total", "Frames handed to the device.", &per_interface(|iface| iface.stats().tx_frames));
+            out.counter("stip_interface_tx_bytes_total", "Bytes handed to the device.", &per_interface(|iface| iface.stats().tx_bytes));
+        }
+
+        let mut drops = DropCounters::new();
+        for (_, iface) in &self.interfaces {
+            drops.merge(iface.drop_counters());
+        }
+        for counters in &self.drops {
+            drops.merge(counters);
+        }
+        let by_reason: Vec<(Vec<(&str, &str)>, u64)> = drops.iter().map(|(reason, count)| (vec![("reason", reason.as_str())], count)).collect();
+        out.counter("stip_drops_total", "Packets dropped, by reason.", &by_reason);
+        let checksum: Vec<(Vec<(&str, &str)>, u64)> = CHECKSUM_KINDS.iter().map(|(reason, kind)| (vec![("kind", *kind)], drops.get(*reason))).collect();
+        out.counter("stip_checksum_errors_total", "Frames or packets that failed checksum verification.", &checksum);
+
+        if let Some(table) = self.connections {
+            let states: Vec<(Vec<(&str, &str)>, u64)> = table.state_counts().iter()
+                .map(|(state, count)| (vec![("state", state.as_str())], *count as u64))
+                .collect();
+            out.gauge("stip_tcp_connections", "TCP connections, by state.", &states);
+            out.counter("stip_tcp_retransmissions_total", "TCP segments retransmitted.", &[(vec![], table.retransmissions())]);
+            out.counter("stip_tcp_stalls_total", "Stalled TCP connections detected by the watchdog.", &[(vec![], table.stalls_detected())]);
+        }
+        if !self.arp.is_empty() {
+            let entries = self.arp.iter().map(|arp| arp.len() as u64).sum();
+            out.gauge("stip_arp_cache_entries", "Entries in the ARP cache.", &[(vec![], entries)]);
+        }
+        if let Some(budget) = self.memory {
+            let usage = budget.usage();
+            let by_component: Vec<(Vec<(&str, &str)>, u64)> = usage.by_component.iter()
+                .map(|(component, used)| (vec![("component", component.as_str())], *used as u64))
+                .collect();
+            out.gauge("stip_memory_used_bytes", "Memory charged to the budget, by component.", &by_component);
+            out.gauge_bytes("stip_memory_peak_bytes", "Highest total charge seen.", usage.peak);
+            out.gauge_bytes("stip_memory_limit_bytes", "Memory budget limit.", usage.limit);
+        }
+        out.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_escaping() {
+        let mut out = MetricsWriter::new();
+        out.counter("x_total", "Help.", &[(vec![("interface", "a\"b\\c")], 1), (vec![], 2)]);
+        assert_eq!(out.finish(), "# HELP x_total Help.\n# TYPE x_total counter\nx_total{interface=\"a\\\"b\\\\c\"} 1\nx_total 2\n");
+    }
+}
@@ -1,3 +1,19 @@
 pub mod checksum;
 pub mod trans_bytes;
-pub mod stream_reassemble;
\ No newline at end of file
+pub(crate) mod stream_reassemble;
+pub mod clock;
+pub mod timer;
+pub mod trace;
+pub mod wire;
+pub mod drops;
+pub mod pcap;
+pub mod addr;
+pub mod dissect;
+pub mod filter;
+pub mod md5;
+pub(crate) mod siphash;
+pub mod memory;
+pub mod latency;
+pub mod metrics;
+#[cfg(feature = "async")]
+pub mod waker;
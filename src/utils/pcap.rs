@@ -0,0 +1,512 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::utils::clock::Clock;
+use crate::link::interface::PacketMeta;
+use crate::utils::trace::TraceSink;
+
+const PCAP_MAGIC: u32 = 0xa1b2c3d4; // 微秒精度
+const PCAP_MAGIC_NANOS: u32 = 0xa1b23c4d; // 纳秒精度
+const PCAPNG_MAGIC: u32 = 0x0a0d0d0a; // pcapng 的 Section Header Block 类型, 两种字节序相同
+const VERSION_MAJOR: u16 = 2;
+const VERSION_MINOR: u16 = 4;
+pub const LINKTYPE_ETHERNET: u32 = 1;
+pub const LINKTYPE_RAW: u32 = 101; // 记录直接以 IP 首部开始, 没有链路层首部
+pub const DEFAULT_SNAPLEN: u32 = 65535;
+
+/**
+ * 经典 libpcap 格式的写入器, 可用 Wireshark / tcpdump 打开
+ * 所有字段统一按小端写出, 读取方通过 magic 的字节序识别
+ */
+pub struct PcapWriter<W: io::Write> {
+    writer: W,
+    snaplen: u32,
+    link_type: u32,
+}
+
+impl<W: io::Write> PcapWriter<W> {
+    /**
+     * 创建时立即写出全局首部
+     */
+    pub fn new(writer: W) -> io::Result<Self> {
+        Self::with_snaplen(writer, DEFAULT_SNAPLEN)
+    }
+
+    /**
+     * 超过 snaplen 的帧只记录前 snaplen 字节, 原始长度照常记录
+     */
+    pub fn with_snaplen(writer: W, snaplen: u32) -> io::Result<Self> {
+        Self::with_link_type(writer, snaplen, LINKTYPE_ETHERNET)
+    }
+
+    /**
+     * 记录的不是以太网帧时使用, 例如 LINKTYPE_RAW 的 IP 数据报
+     */
+    pub fn with_link_type(writer: W, snaplen: u32, link_type: u32) -> io::Result<Self> {
+        let mut pcap = PcapWriter { writer, snaplen, link_type };
+        pcap.write_global_header()?;
+        Ok(pcap)
+    }
+
+    pub fn link_type(&self) -> u32 {
+        self.link_type
+    }
+
+    fn write_global_header(&mut self) -> io::Result<()> {
+        let mut hdr = [0u8; 24];
+        hdr[0..4].copy_from_slice(&PCAP_MAGIC.to_le_bytes());
+        hdr[4..6].copy_from_slice(&VERSION_MAJOR.to_le_bytes());
+        hdr[6..8].copy_from_slice(&VERSION_MINOR.to_le_bytes());
+        // thiszone(4) 和 sigfigs(4) 均为 0
+        hdr[16..20].copy_from_slice(&self.snaplen.to_le_bytes());
+        hdr[20..24].copy_from_slice(&self.link_type.to_le_bytes());
+        self.writer.write_all(&hdr)
+    }
+
+    /**
+     * 写入一帧, timestamp_ms 一般取自协议栈注入的时钟
+     */
+    pub fn write_packet(&mut self, timestamp_ms: u64, frame: &[u8]) -> io::Result<()> {
+        let captured = frame.len().min(self.snaplen as usize);
+        let mut hdr = [0u8; 16];
+        hdr[0..4].copy_from_slice(&((timestamp_ms / 1000) as u32).to_le_bytes());
+        hdr[4..8].copy_from_slice(&(((timestamp_ms % 1000) * 1000) as u32).to_le_bytes());
+        hdr[8..12].copy_from_slice(&(captured as u32).to_le_bytes());
+        hdr[12..16].copy_from_slice(&(frame.len() as u32).to_le_bytes());
+        self.writer.write_all(&hdr)?;
+        self.writer.write_all(&frame[..captured])
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/**
+ * PcapTraceSink 何时刷新底层 writer
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    EveryPacket,       // 每帧都刷新, 适合边抓边看
+    EveryN(u32),       // 每 N 帧刷新一次
+    Manual,            // 只在调用 flush 或 drop writer 时刷新
+}
+
+/**
+ * 把接口收发的每一帧记录到 pcap 文件
+ * 写入失败不影响协议栈, 直接忽略
+ */
+pub struct PcapTraceSink<W: io::Write, C: Clock> {
+    pcap: PcapWriter<W>,
+    clock: C,
+    flush_policy: FlushPolicy,
+    unflushed: u32,
+}
+
+impl<W: io::Write, C: Clock> PcapTraceSink<W, C> {
+    pub fn new(pcap: PcapWriter<W>, clock: C, flush_policy: FlushPolicy) -> Self {
+        PcapTraceSink { pcap, clock, flush_policy, unflushed: 0 }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.unflushed = 0;
+        self.pcap.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.pcap.into_inner()
+    }
+
+    fn record(&mut self, frame: &[u8]) {
+        self.record_at(self.clock.now_ms(), frame);
+    }
+
+    fn record_at(&mut self, timestamp_ms: u64, frame: &[u8]) {
+        if self.pcap.write_packet(timestamp_ms, frame).is_err() {
+            return;
+        }
+        self.unflushed += 1;
+        let should_flush = match self.flush_policy {
+            FlushPolicy::EveryPacket => true,
+            FlushPolicy::EveryN(n) => self.unflushed >= n,
+            FlushPolicy::Manual => false,
+        };
+        if should_flush {
+            let _ = self.flush();
+        }
+    }
+}
+
+impl<W: io::Write, C: Clock> TraceSink for PcapTraceSink<W, C> {
+    fn on_frame_rx(&mut self, frame: &[u8]) {
+        self.record(frame);
+    }
+
+    fn on_frame_tx(&mut self, frame: &[u8]) {
+        self.record(frame);
+    }
+
+    /**
+     * 已经有时间戳时用它, 而不是写入时的时钟
+     */
+    fn on_frame_rx_meta(&mut self, frame: &[u8], meta: &PacketMeta) {
+        self.record_at(meta.rx_timestamp_ms, frame);
+    }
+
+    fn on_frame_tx_at(&mut self, frame: &[u8], tx_timestamp_ms: u64) {
+        self.record_at(tx_timestamp_ms, frame);
+    }
+}
+
+/**
+ * pcap 文件读取错误
+ */
+#[derive(Debug)]
+pub enum PcapError {
+    Io(io::Error),
+    Pcapng,
+    BadMagic(u32),
+    UnsupportedLinkType(u32),
+    Truncated { record: usize, needed: usize, available: usize },
+    BadRecordLength { record: usize, captured: u32, original: u32, snaplen: u32 },
+}
+
+impl fmt::Display for PcapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PcapError::Io(e) => write!(f, "io error: {}", e),
+            PcapError::Pcapng => write!(f, "pcapng files are not supported, convert with `editcap -F pcap`"),
+            PcapError::BadMagic(magic) => write!(f, "not a pcap file: magic {:#010x}", magic),
+            PcapError::UnsupportedLinkType(link_type) => write!(f, "unsupported link type {}, only ethernet (1) and raw IP (101) are supported", link_type),
+            PcapError::Truncated { record, needed, available } => {
+                write!(f, "record {} truncated: needs {} bytes, {} available", record, needed, available)
+            }
+            PcapError::BadRecordLength { record, captured, original, snaplen } => {
+                write!(f, "record {} has captured length {} (original {}, snaplen {})", record, captured, original, snaplen)
+            }
+        }
+    }
+}
+
+impl Error for PcapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PcapError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PcapError {
+    fn from(e: io::Error) -> Self {
+        PcapError::Io(e)
+    }
+}
+
+/**
+ * 经典 libpcap 格式的读取器, 支持两种字节序以及微秒/纳秒精度
+ * 作为迭代器逐条产出 (时间戳微秒, 帧), 出错后不再产出
+ */
+pub struct PcapReader<R: io::Read> {
+    reader: R,
+    big_endian: bool,
+    nanos: bool,
+    snaplen: u32,
+    link_type: u32,
+    records: usize,
+    done: bool,
+}
+
+impl<R: io::Read> PcapReader<R> {
+    /**
+     * 读取并校验全局首部
+     */
+    pub fn new(mut reader: R) -> Result<Self, PcapError> {
+        let mut hdr = [0u8; 24];
+        let n = read_full(&mut reader, &mut hdr)?;
+        if n >= 4 && u32::from_le_bytes([hdr[0], hdr[1], hdr[2], hdr[3]]) == PCAPNG_MAGIC {
+            return Err(PcapError::Pcapng);
+        }
+        if n < hdr.len() {
+            return Err(PcapError::Truncated { record: 0, needed: hdr.len(), available: n });
+        }
+
+        let (big_endian, nanos) = match u32::from_le_bytes([hdr[0], hdr[1], hdr[2], hdr[3]]) {
+            PCAP_MAGIC => (false, false),
+            PCAP_MAGIC_NANOS => (false, true),
+            magic if magic.swap_bytes() == PCAP_MAGIC => (true, false),
+            magic if magic.swap_bytes() == PCAP_MAGIC_NANOS => (true, true),
+            magic => return Err(PcapError::BadMagic(magic)),
+        };
+        let mut pcap = PcapReader { reader, big_endian, nanos, snaplen: 0, link_type: 0, records: 0, done: false };
+        pcap.snaplen = pcap.u32_at(&hdr, 16);
+        pcap.link_type = pcap.u32_at(&hdr, 20);
+        if pcap.link_type != LINKTYPE_ETHERNET && pcap.link_type != LINKTYPE_RAW {
+            return Err(PcapError::UnsupportedLinkType(pcap.link_type));
+        }
+        Ok(pcap)
+    }
+
+    pub fn snaplen(&self) -> u32 {
+        self.snaplen
+    }
+
+    /**
+     * LINKTYPE_ETHERNET 或 LINKTYPE_RAW, 决定记录该从哪一层解析
+     */
+    pub fn link_type(&self) -> u32 {
+        self.link_type
+    }
+
+    /**
+     * 读取下一条记录, 文件在记录边界处结束时返回 None
+     */
+    pub fn next_record(&mut self) -> Result<Option<(u64, Vec<u8>)>, PcapError> {
+        let record = self.records;
+        let mut hdr = [0u8; 16];
+        let n = read_full(&mut self.reader, &mut hdr)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        if n < hdr.len() {
+            return Err(PcapError::Truncated { record, needed: hdr.len(), available: n });
+        }
+
+        let ts_sec = self.u32_at(&hdr, 0) as u64;
+        let ts_frac = self.u32_at(&hdr, 4) as u64;
+        let captured = self.u32_at(&hdr, 8);
+        let original = self.u32_at(&hdr, 12);
+        let over_snaplen = self.snaplen != 0 && captured > self.snaplen;
+        if captured > original || over_snaplen {
+            return Err(PcapError::BadRecordLength { record, captured, original, snaplen: self.snaplen });
+        }
+
+        let mut frame = vec![0u8; captured as usize];
+        let n = read_full(&mut self.reader, &mut frame)?;
+        if n < frame.len() {
+            return Err(PcapError::Truncated { record, needed: frame.len(), available: n });
+        }
+        self.records += 1;
+
+        let ts_us = ts_sec * 1_000_000 + if self.nanos { ts_frac / 1000 } else { ts_frac };
+        Ok(Some((ts_us, frame)))
+    }
+
+    fn u32_at(&self, bytes: &[u8], at: usize) -> u32 {
+        let field = [bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]];
+        if self.big_endian { u32::from_be_bytes(field) } else { u32::from_le_bytes(field) }
+    }
+}
+
+impl<R: io::Read> Iterator for PcapReader<R> {
+    type Item = Result<(u64, Vec<u8>), PcapError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_record().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+/**
+ * 尽量读满 buf, 返回实际读到的字节数, 只有遇到 EOF 时才会小于 buf.len()
+ */
+fn read_full<R: io::Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock::ManualClock;
+
+    /**
+     * 记录 flush 次数的 writer
+     */
+    #[derive(Default)]
+    struct CountingWriter {
+        bytes: Vec<u8>,
+        flushes: u32,
+    }
+
+    impl io::Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.bytes.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_two_frames_match_expected_file() {
+        let clock = ManualClock::new(1_500); // 1.5s
+        let pcap = PcapWriter::new(Vec::new()).unwrap();
+        let mut sink = PcapTraceSink::new(pcap, clock.clone(), FlushPolicy::EveryPacket);
+
+        sink.on_frame_tx(&[0xaa, 0xbb, 0xcc]);
+        clock.advance(2_001);
+        sink.on_frame_rx(&[0x01, 0x02]);
+
+        let expected: Vec<u8> = vec![
+            // 全局首部
+            0xd4, 0xc3, 0xb2, 0xa1, // magic
+            0x02, 0x00, 0x04, 0x00, // version 2.4
+            0x00, 0x00, 0x00, 0x00, // thiszone
+            0x00, 0x00, 0x00, 0x00, // sigfigs
+            0xff, 0xff, 0x00, 0x00, // snaplen 65535
+            0x01, 0x00, 0x00, 0x00, // linktype ethernet
+            // 第一帧: 1s + 500000us
+            0x01, 0x00, 0x00, 0x00,
+            0x20, 0xa1, 0x07, 0x00,
+            0x03, 0x00, 0x00, 0x00,
+            0x03, 0x00, 0x00, 0x00,
+            0xaa, 0xbb, 0xcc,
+            // 第二帧: 3s + 501000us
+            0x03, 0x00, 0x00, 0x00,
+            0x08, 0xa5, 0x07, 0x00,
+            0x02, 0x00, 0x00, 0x00,
+            0x02, 0x00, 0x00, 0x00,
+            0x01, 0x02,
+        ];
+        assert_eq!(sink.into_inner(), expected);
+    }
+
+    #[test]
+    fn test_global_header_endianness() {
+        let bytes = PcapWriter::with_snaplen(Vec::new(), 96).unwrap().into_inner();
+        assert_eq!(bytes.len(), 24);
+        // 小端写出, 读取方按小端解析 magic 得到 0xa1b2c3d4, 按大端则得到 0xd4c3b2a1
+        assert_eq!(u32::from_le_bytes(bytes[0..4].try_into().unwrap()), 0xa1b2c3d4);
+        assert_eq!(u32::from_be_bytes(bytes[0..4].try_into().unwrap()), 0xd4c3b2a1);
+        assert_eq!(u16::from_le_bytes([bytes[4], bytes[5]]), 2);
+        assert_eq!(u16::from_le_bytes([bytes[6], bytes[7]]), 4);
+        assert_eq!(u32::from_le_bytes(bytes[16..20].try_into().unwrap()), 96);
+        assert_eq!(u32::from_le_bytes(bytes[20..24].try_into().unwrap()), 1);
+    }
+
+    #[test]
+    fn test_snaplen_truncates() {
+        let mut pcap = PcapWriter::with_snaplen(Vec::new(), 4).unwrap();
+        pcap.write_packet(0, &[1, 2, 3, 4, 5, 6]).unwrap();
+        let bytes = pcap.into_inner();
+        assert_eq!(&bytes[24 + 8..24 + 16], &[4, 0, 0, 0, 6, 0, 0, 0]);
+        assert_eq!(&bytes[24 + 16..], &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_flush_policy() {
+        let clock = ManualClock::default();
+        let mut sink = PcapTraceSink::new(PcapWriter::new(CountingWriter::default()).unwrap(), clock.clone(), FlushPolicy::EveryN(3));
+        for _ in 0..7 {
+            sink.on_frame_tx(&[0u8; 64]);
+        }
+        assert_eq!(sink.pcap.writer.flushes, 2);
+
+        let mut sink = PcapTraceSink::new(PcapWriter::new(CountingWriter::default()).unwrap(), clock.clone(), FlushPolicy::Manual);
+        sink.on_frame_tx(&[0u8; 64]);
+        sink.on_frame_rx(&[0u8; 64]);
+        assert_eq!(sink.pcap.writer.flushes, 0);
+        sink.flush().unwrap();
+        assert_eq!(sink.into_inner().flushes, 1);
+
+        let mut sink = PcapTraceSink::new(PcapWriter::new(CountingWriter::default()).unwrap(), clock, FlushPolicy::EveryPacket);
+        sink.on_frame_tx(&[0u8; 64]);
+        sink.on_frame_rx(&[0u8; 64]);
+        assert_eq!(sink.into_inner().flushes, 2);
+    }
+
+    #[test]
+    fn test_reader_round_trip() {
+        let mut pcap = PcapWriter::new(Vec::new()).unwrap();
+        pcap.write_packet(1_500, &[0xaa, 0xbb, 0xcc]).unwrap();
+        pcap.write_packet(3_501, &[0x01, 0x02]).unwrap();
+        let bytes = pcap.into_inner();
+
+        let reader = PcapReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.snaplen(), DEFAULT_SNAPLEN);
+        let records: Vec<(u64, Vec<u8>)> = reader.map(|r| r.unwrap()).collect();
+        assert_eq!(records, vec![(1_500_000, vec![0xaa, 0xbb, 0xcc]), (3_501_000, vec![0x01, 0x02])]);
+    }
+
+    #[test]
+    fn test_raw_link_type_round_trip() {
+        let mut pcap = PcapWriter::with_link_type(Vec::new(), DEFAULT_SNAPLEN, LINKTYPE_RAW).unwrap();
+        pcap.write_packet(10, &[0x45, 0x00]).unwrap();
+        let bytes = pcap.into_inner();
+        assert_eq!(u32::from_le_bytes(bytes[20..24].try_into().unwrap()), 101);
+
+        let mut reader = PcapReader::new(&bytes[..]).unwrap();
+        assert_eq!(reader.link_type(), LINKTYPE_RAW);
+        assert_eq!(reader.next().unwrap().unwrap(), (10_000, vec![0x45, 0x00]));
+
+        let mut bytes = bytes;
+        bytes[20] = 105; // 802.11
+        assert!(matches!(PcapReader::new(&bytes[..]), Err(PcapError::UnsupportedLinkType(105))));
+    }
+
+    #[test]
+    fn test_reader_big_endian_nanos() {
+        let mut bytes: Vec<u8> = vec![
+            0xa1, 0xb2, 0x3c, 0x4d, // 大端、纳秒精度
+            0x00, 0x02, 0x00, 0x04,
+            0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x40, // snaplen 64
+            0x00, 0x00, 0x00, 0x01,
+            0x00, 0x00, 0x00, 0x02, // 2s
+            0x00, 0x00, 0x03, 0xe8, // 1000ns
+            0x00, 0x00, 0x00, 0x01,
+            0x00, 0x00, 0x00, 0x01,
+            0x7f,
+        ];
+        let records: Vec<(u64, Vec<u8>)> = PcapReader::new(&bytes[..]).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(records, vec![(2_000_001, vec![0x7f])]);
+
+        // 截断的记录
+        bytes.pop();
+        let mut reader = PcapReader::new(&bytes[..]).unwrap();
+        assert!(matches!(reader.next(), Some(Err(PcapError::Truncated { record: 0, needed: 1, available: 0 }))));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_reader_rejects_bad_files() {
+        let pcapng = [0x0a, 0x0d, 0x0d, 0x0a, 0x1c, 0x00, 0x00, 0x00];
+        assert!(matches!(PcapReader::new(&pcapng[..]), Err(PcapError::Pcapng)));
+        assert!(matches!(PcapReader::new(&[0u8; 24][..]), Err(PcapError::BadMagic(0))));
+        assert!(matches!(PcapReader::new(&[0xd4, 0xc3][..]), Err(PcapError::Truncated { record: 0, needed: 24, available: 2 })));
+
+        // 记录长度超过 snaplen / 原始长度
+        let mut pcap = PcapWriter::with_snaplen(Vec::new(), 4).unwrap();
+        pcap.write_packet(0, &[1, 2, 3, 4]).unwrap();
+        let mut bytes = pcap.into_inner();
+        bytes[24 + 8] = 5;
+        assert!(matches!(
+            PcapReader::new(&bytes[..]).unwrap().next(),
+            Some(Err(PcapError::BadRecordLength { record: 0, captured: 5, original: 4, snaplen: 4 }))
+        ));
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 
 /**
  * 重组数据流器
@@ -7,46 +7,104 @@ use std::collections::BTreeMap;
  * 如果 ByteStream 已满，则必须暂停装配，将未装配数据暂时保存起来
  * |         assembled_window             |<next_to_be_assembled>             unassembled_window              |
  * |                              buffer_window                                                               |
- * 
+ * assembled_window 用环形缓冲区, 应用层每次只读少量字节时也不需要搬移剩余数据
+ * 没有失序数据时按序到达的数据直接追加 (快路径), 不查询 unassembled_buff
  */
 pub(crate) struct StreamReassembler {
     unassembled_buff: BTreeMap<usize, Vec<u8>>,
-    assembled_window: Vec<u8>,
+    assembled_window: VecDeque<u8>,
     next_to_be_assembled: usize,
     buffer_size: usize,
-    eof_idx: usize, // EOF
+    eof_idx: usize, // EOF 的位置, 即最后一个字节之后的偏移; 之前的数据都拼接好才算输入结束
+    fast_path: u64,
+    slow_path: u64,
 }
 
 impl StreamReassembler{
     pub fn new(buffer_size: usize) -> Self {
         StreamReassembler {
             unassembled_buff: BTreeMap::new(),
-            assembled_window: Vec::new(),
+            assembled_window: VecDeque::new(),
             next_to_be_assembled: 0,
             eof_idx: usize::MAX,
             buffer_size,
+            fast_path: 0,
+            slow_path: 0,
         }
     }
 
+    /**
+     * 带数据的 recv 调用中走快路径和慢路径的次数, 超出窗口被丢弃的不计
+     */
+    pub fn path_counts(&self) -> (u64, u64) {
+        (self.fast_path, self.slow_path)
+    }
+
     /**
      * 返回已经按序接收的数据的引用，但不取出
+     * 环形缓冲区绕回时先整理成连续的一段
      */
-    pub fn view_assembled(&self) -> &[u8] {
-        &self.assembled_window
+    #[cfg(test)]
+    pub fn view_assembled(&mut self) -> &[u8] {
+        self.assembled_window.make_contiguous()
     }
+
+    /**
+     * 不整理缓冲区, 以两段的形式返回已经按序接收的数据, 按顺序拼接即为完整数据
+     */
+    #[cfg(test)]
+    pub fn assembled_slices(&self) -> (&[u8], &[u8]) {
+        self.assembled_window.as_slices()
+    }
+
     /**
-     * 返回已经按序接收的数据，并取出
+     * 从头部取出至多 max 字节, 剩余数据不搬移
      */
-    pub fn get_and_remove_assembled(&mut self) -> Vec<u8> {
-        let mut result: Vec<u8> = Vec::new();
-        result.append(&mut self.assembled_window); // 清空assembled_window
-        result
+    pub fn pop_assembled(&mut self, max: usize) -> Vec<u8> {
+        let n = max.min(self.assembled_window.len());
+        self.assembled_window.drain(..n).collect()
     }
 
     pub fn assembled_cnt(&self) -> u64 {
         self.next_to_be_assembled as u64
     }
 
+    /**
+     * 已缓存但尚未拼接的区间 [start, end), 绝对偏移, 按起点排序
+     */
+    pub fn buffered_ranges(&self) -> Vec<(usize, usize)> {
+        self.unassembled_buff.iter().map(|(k, v)| (*k, k + v.len())).collect()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer_size
+    }
+
+    /**
+     * 收到了 EOF 且之前的数据都已拼接
+     */
+    pub fn input_ended(&self) -> bool {
+        self.eof_idx <= self.next_to_be_assembled
+    }
+
+    /**
+     * 已拼接但应用层尚未取走的字节数
+     */
+    pub fn unread(&self) -> usize {
+        self.assembled_window.len()
+    }
+
+    /**
+     * 调整缓冲区容量, 返回实际生效的容量
+     * 不丢弃任何已缓存的数据: 容量至少覆盖未读数据和最远的失序数据
+     */
+    pub fn resize_capacity(&mut self, new_size: usize) -> usize {
+        let furthest = self.unassembled_buff.iter().next_back().map_or(self.next_to_be_assembled, |(k, v)| k + v.len());
+        let in_use = self.assembled_window.len() + (furthest - self.next_to_be_assembled);
+        self.buffer_size = new_size.max(in_use);
+        self.buffer_size
+    }
+
     pub fn unassembled_window_size(&self) -> u32 {
         (self.buffer_size - self.assembled_window.len()) as u32
     }
@@ -54,23 +112,45 @@ impl StreamReassembler{
     /**
      * 接收数据, 暂存或者拼接或丢弃
      * 尽可能合并区间，确保缓存区域的区间不重叠
+     * eof 表示这段数据之后输入结束, 提前到达(前面还有空洞)时记下位置, 等空洞填上
+     * 超出窗口被整段丢弃时返回 false
      */
-    pub fn recv(&mut self, data: &[u8], offset: usize, eof: bool) {
+    pub fn recv(&mut self, data: &[u8], offset: usize, eof: bool) -> bool {
         let next_idx_from_data: usize = offset + data.len();
-        if self.beyond_window(next_idx_from_data - 1) { // 超出窗口，直接返回
-            return;
+        if !data.is_empty() && self.beyond_window(next_idx_from_data - 1) { // 超出窗口，直接返回
+            return false;
         }
 
-        if offset <= self.next_to_be_assembled { /* 可以并入结果集 */
-            self.merge_to_assembled(&data, offset);          
+        if data.is_empty() { // 只带 EOF 的空段, 没有数据要合并
+        }
+        else if offset <= self.next_to_be_assembled && self.unassembled_buff.is_empty() { /* 快路径: 按序到达且没有失序数据 */
+            self.fast_path += 1;
+            self.append_assembled(data, offset);
+        }
+        else if offset <= self.next_to_be_assembled { /* 可以并入结果集 */
+            self.slow_path += 1;
+            self.merge_to_assembled(data, offset);
         }
         else { /* 不能并入结果集, 将unassembled缓冲区合并 */
-            self.merge_from_unassemble(&data, offset);
+            self.slow_path += 1;
+            self.merge_from_unassemble(data, offset);
         }
 
         if eof {
-            self.eof_idx = self.next_to_be_assembled;
+            self.eof_idx = next_idx_from_data;
         }
+        true
+    }
+
+    /**
+     * 起点不晚于 next_to_be_assembled 的数据追加到 assembled window, 去掉已经拼接过的前缀
+     */
+    fn append_assembled(&mut self, data: &[u8], offset: usize) {
+        if offset + data.len() <= self.next_to_be_assembled { // 已经全部拼接过
+            return;
+        }
+        self.assembled_window.extend(&data[(self.next_to_be_assembled - offset)..]); // 新添加到assembled段的数据
+        self.next_to_be_assembled = data.len() + offset;
     }
 
     /**
@@ -78,27 +158,23 @@ impl StreamReassembler{
      * 将新一段数据加入assembled window后,对 unassembled 缓冲区的数据的处理
      */
     fn merge_to_assembled(&mut self, data: &[u8], offset: usize) {
-        self.assembled_window.extend_from_slice(&data[(self.next_to_be_assembled - offset)..]); // 新添加到assembled段的数据
-        self.next_to_be_assembled = data.len() + offset;
-
-        let mut to_remove: Vec<usize> = Vec::new(); // 记录将要从unassembled buff 删除的数据
+        self.append_assembled(data, offset);
 
         /*
             unassembled_window中，每个区间[l, r)
             能参与合并的，只有满足l在 (..,self.next_to_be_assembled], r在(self.next_to_be_assembled, ..)
-            被删除: 所有满足l在 (..,self.next_to_be_assembled]
+            被删除: 所有满足l在 (..,self.next_to_be_assembled], 它们是键最小的若干个区间
             被删除但不被合并：满足l在 (..,self.next_to_be_assembled）, r 在 (..,self.next_to_be_assembled]
         */
-        for (k, v) in self.unassembled_buff.range(..=self.next_to_be_assembled) {
+        while let Some(entry) = self.unassembled_buff.first_entry() {
+            if *entry.key() > self.next_to_be_assembled {
+                break;
+            }
+            let (k, v) = entry.remove_entry();
             if k + v.len() > self.next_to_be_assembled { // 只可能最多有一个
-                self.assembled_window.extend_from_slice(&v[(self.next_to_be_assembled - k)..]);
+                self.assembled_window.extend(&v[(self.next_to_be_assembled - k)..]);
                 self.next_to_be_assembled = k + v.len();
             }
-            to_remove.push(*k);
-        }
-        // 删除重叠的区间
-        for key in to_remove {
-            self.rm_from_unassembled_buff(key);
         }
     }
 
@@ -107,7 +183,6 @@ impl StreamReassembler{
      */
     fn merge_from_unassemble(&mut self, data: &[u8], offset: usize) {
         let next_idx_from_data = data.len() + offset;
-        let mut to_remove: Vec<usize> = Vec::new();
         // merged用于存储合并后的区间
         let mut merged: Vec<u8> = Vec::new();
         let mut merged_st = offset;
@@ -118,47 +193,32 @@ impl StreamReassembler{
                 合并: l在(.., offset), r在[offset,..)
             上面的合并后，生成合并段[m_l, m_r), m_l=l, m_r=max{r, next_idx_from_data}
 
-            l 在 [m_r, next_idx_from_data]:
-                合并, r在 (next_idx_from_data, ..)
+            l 在 [offset, next_idx_from_data]:
+                被合并段吸收, r 超出合并段右端的部分接在后面
         */
-        for (k, v) in self.unassembled_buff.range(..offset) {
-            if k + v.len() >= offset { // 至多有一个
-                merged.extend_from_slice(&v);
-                merged_st = *k;
-                if k + v.len() < next_idx_from_data { 
-                    merged.extend_from_slice(&data[(k + v.len() - offset)..]);
-                }
+        // 区间互不重叠, 只有起点最靠近 offset 的区间可能与 data 相接
+        if let Some((&k, v)) = self.unassembled_buff.range(..offset).next_back() {
+            if k + v.len() >= offset {
+                merged = self.unassembled_buff.remove(&k).unwrap();
+                merged_st = k;
             }
         }
-        // 若合并后的窗口右侧大于data的右侧, 则不可能存在右边可以与之合并的
-        if merged_st + merged.len() <= next_idx_from_data {
-            for (k, v) in self.unassembled_buff.range((merged_st + merged.len())..=next_idx_from_data) {
-                if k + v.len() > next_idx_from_data { // 至多有一个
-                    merged.extend_from_slice(&v[(next_idx_from_data - k)..]);
-                }
-                to_remove.push(*k);
-            }
-        }
-
-        for key in to_remove {
-            self.rm_from_unassembled_buff(key);
+        // 没有左侧区间时 merged 为空, 整个 data 都会被加入
+        let merged_end = merged_st + merged.len();
+        if merged_end < next_idx_from_data {
+            merged.extend_from_slice(&data[(merged_end - offset)..]);
         }
 
-        if merged.len() == 0 { // 以上两个合并均没有进行
-            merged = data.to_vec();
+        // 此时合并段至少覆盖到 next_idx_from_data, 起点在 [offset, next_idx_from_data] 的区间都被吸收
+        while let Some(&k) = self.unassembled_buff.range(offset..=next_idx_from_data).next().map(|(k, _)| k) {
+            let v = self.unassembled_buff.remove(&k).unwrap();
+            let merged_end = merged_st + merged.len();
+            if k + v.len() > merged_end { // 至多有一个
+                merged.extend_from_slice(&v[(merged_end - k)..]);
+            }
         }
 
-        self.add_to_unassembled_buff(merged_st, &merged);
-        
-    }
-
-
-    fn rm_from_unassembled_buff(&mut self, key: usize) {
-        self.unassembled_buff.remove(&key);
-    }
-
-    fn add_to_unassembled_buff(&mut self, key: usize, val: &[u8]) {
-        self.unassembled_buff.insert(key, val.to_vec());
+        self.unassembled_buff.insert(merged_st, merged);
     }
 
     fn beyond_window(&self, last_idx: usize) -> bool {
@@ -192,7 +252,7 @@ mod tests {
         // 接收数据，模拟数据超出窗口
         reassembler.recv(&[0, 1, 2, 3], 0, false);
         reassembler.recv(&[4, 5, 6], 4, false);
-        reassembler.recv(&[7, 8, 9, 10], 7, false); // 超过窗口
+        assert!(!reassembler.recv(&[7, 8, 9, 10], 7, false)); // 超过窗口
 
         // 验证是否被丢弃（缓冲区满了）
         assert_eq!(reassembler.view_assembled(), &[0, 1, 2, 3, 4, 5, 6]);
@@ -257,4 +317,82 @@ mod tests {
         // 验证拼接后的数据
         assert_eq!(reassembler.view_assembled(), &[0, 1, 2, 3, 4, 5, 6]);
     }
+
+    #[test]
+    fn test_pop_assembled_wraps() {
+        let mut reassembler = StreamReassembler::new(8);
+        reassembler.recv(&[0, 1, 2, 3, 4, 5], 0, false);
+        assert_eq!(reassembler.pop_assembled(4), vec![0, 1, 2, 3]);
+        assert_eq!(reassembler.unassembled_window_size(), 6);
+
+        // 先缓存失序数据, 再补上缺口, 合并后的数据在环形缓冲区中绕回
+        reassembler.recv(&[8, 9], 8, false);
+        reassembler.recv(&[6, 7], 6, false);
+        let (head, tail) = reassembler.assembled_slices();
+        assert_eq!([head, tail].concat(), vec![4, 5, 6, 7, 8, 9]);
+        assert_eq!(reassembler.view_assembled(), &[4, 5, 6, 7, 8, 9]);
+
+        assert_eq!(reassembler.pop_assembled(100), vec![4, 5, 6, 7, 8, 9]);
+        assert!(reassembler.pop_assembled(1).is_empty());
+        assert_eq!(reassembler.assembled_cnt(), 10);
+    }
+
+    #[test]
+    fn test_resize_keeps_buffered_data() {
+        let mut reassembler = StreamReassembler::new(10);
+        reassembler.recv(&[0, 1, 2, 3], 0, false);
+        reassembler.recv(&[8, 9], 8, false);
+        // 未读 4 字节加上到 10 的失序数据, 不能缩到 10 以下
+        assert_eq!(reassembler.resize_capacity(2), 10);
+        assert_eq!(reassembler.unassembled_window_size(), 6);
+        assert!(!reassembler.recv(&[10], 10, false));
+
+        assert_eq!(reassembler.resize_capacity(20), 20);
+        assert!(reassembler.recv(&[4, 5, 6, 7], 4, false));
+        assert!(reassembler.recv(&[10, 11], 10, false));
+        assert_eq!(reassembler.pop_assembled(100), (0..12).collect::<Vec<u8>>());
+        assert_eq!(reassembler.resize_capacity(5), 5);
+        assert_eq!(reassembler.unassembled_window_size(), 5);
+    }
+
+    #[test]
+    fn test_fast_path_only_without_buffered_data() {
+        let mut reassembler = StreamReassembler::new(100);
+        reassembler.recv(&[0, 1, 2, 3], 0, false);
+        reassembler.recv(&[2, 3, 4, 5], 2, false); // 重复的前缀被去掉
+        reassembler.recv(&[0, 1], 0, false);
+        assert_eq!(reassembler.path_counts(), (3, 0));
+
+        // 有失序数据时按序到达的数据也要走慢路径, 把缓存的区间接上
+        reassembler.recv(&[8, 9], 8, false);
+        reassembler.recv(&[6, 7], 6, false);
+        assert_eq!(reassembler.path_counts(), (3, 2));
+        reassembler.recv(&[10], 10, true);
+        assert!(!reassembler.recv(&[0; 100], 11, false));
+        assert_eq!(reassembler.path_counts(), (4, 2));
+        assert_eq!(reassembler.view_assembled(), &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        assert!(reassembler.input_ended());
+    }
+
+    /**
+     * 逐字节读取 100 KB 的数据流不能是平方复杂度
+     */
+    #[test]
+    fn test_single_byte_reads() {
+        const LEN: usize = 100_000;
+        let data: Vec<u8> = (0..LEN).map(|i| i as u8).collect();
+        let mut reassembler = StreamReassembler::new(LEN);
+        // 分段乱序到达
+        for chunk_start in (0..LEN).step_by(1000).rev() {
+            reassembler.recv(&data[chunk_start..chunk_start + 1000], chunk_start, false);
+        }
+        assert_eq!(reassembler.assembled_cnt(), LEN as u64);
+
+        let mut read: Vec<u8> = Vec::with_capacity(LEN);
+        for _ in 0..LEN {
+            read.extend(reassembler.pop_assembled(1));
+        }
+        assert_eq!(read, data);
+        assert_eq!(reassembler.unassembled_window_size(), LEN as u32);
+    }
 }
@@ -0,0 +1,254 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::hash::Hash;
+
+/**
+ * 堆中的一项, 只按 (deadline, seq) 排序, 与 key 的类型无关
+ * seq 是调度时的递增编号: 同一 deadline 的定时器按调度先后到期
+ */
+struct HeapEntry<K> {
+    deadline_ms: u64,
+    seq: u64,
+    key: K,
+}
+
+impl<K> PartialEq for HeapEntry<K> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.deadline_ms, self.seq) == (other.deadline_ms, other.seq)
+    }
+}
+
+impl<K> Eq for HeapEntry<K> {}
+
+impl<K> PartialOrd for HeapEntry<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K> Ord for HeapEntry<K> {
+    // BinaryHeap 是大顶堆, 反转比较得到最早到期的在堆顶
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.deadline_ms, other.seq).cmp(&(self.deadline_ms, self.seq))
+    }
+}
+
+/**
+ * 定时器队列(截止时间队列), 各子系统各持有一个
+ * 二叉堆 + 惰性删除: cancel/reschedule 只更新 live 表, 堆中的过期项在弹出时跳过
+ * schedule / cancel / expired 均为 O(log n)
+ */
+pub struct TimerQueue<K> {
+    heap: BinaryHeap<HeapEntry<K>>,
+    live: HashMap<K, (u64, u64)>, // key -> (deadline_ms, seq)
+    next_seq: u64,
+}
+
+impl<K: Eq + Hash + Clone> TimerQueue<K> {
+    pub fn new() -> Self {
+        TimerQueue {
+            heap: BinaryHeap::new(),
+            live: HashMap::new(),
+            next_seq: 0,
+        }
+    }
+
+    /**
+     * 为 key 设置截止时间, key 已存在时覆盖原来的截止时间
+     */
+    pub fn schedule(&mut self, key: K, deadline_ms: u64) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.live.insert(key.clone(), (deadline_ms, seq));
+        self.heap.push(HeapEntry { deadline_ms, seq, key });
+        self.compact_if_needed();
+    }
+
+    /**
+     * 修改已存在定时器的截止时间, key 不存在时返回 false 且不做任何事
+     */
+    pub fn reschedule(&mut self, key: K, deadline_ms: u64) -> bool {
+        if !self.live.contains_key(&key) {
+            return false;
+        }
+        self.schedule(key, deadline_ms);
+        true
+    }
+
+    /**
+     * 取消定时器, 返回该定时器是否存在
+     */
+    pub fn cancel(&mut self, key: &K) -> bool {
+        let existed = self.live.remove(key).is_some();
+        self.compact_if_needed();
+        existed
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.live.contains_key(key)
+    }
+
+    pub fn deadline_of(&self, key: &K) -> Option<u64> {
+        self.live.get(key).map(|&(deadline_ms, _)| deadline_ms)
+    }
+
+    /**
+     * 取出所有截止时间 <= now_ms 的定时器, 按 (deadline, 调度先后) 排序
+     * 取出后定时器即被移除
+     */
+    pub fn expired(&mut self, now_ms: u64) -> Vec<K> {
+        let mut result: Vec<K> = Vec::new();
+        while let Some(top) = self.heap.peek() {
+            if top.deadline_ms > now_ms {
+                break;
+            }
+            let entry = self.heap.pop().unwrap();
+            if self.is_live(&entry) {
+                self.live.remove(&entry.key);
+                result.push(entry.key);
+            }
+        }
+        result
+    }
+
+    /**
+     * 最早的截止时间, 没有定时器时返回 None
+     */
+    pub fn next_deadline(&mut self) -> Option<u64> {
+        while let Some(top) = self.heap.peek() {
+            if self.is_live(top) {
+                return Some(top.deadline_ms);
+            }
+            self.heap.pop();
+        }
+        None
+    }
+
+    /**
+     * 距离最早截止时间还有多久, 已经到期返回 0
+     */
+    pub fn time_until_next(&mut self, now_ms: u64) -> Option<u64> {
+        self.next_deadline().map(|deadline| deadline.saturating_sub(now_ms))
+    }
+
+    pub fn len(&self) -> usize {
+        self.live.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live.is_empty()
+    }
+
+    fn is_live(&self, entry: &HeapEntry<K>) -> bool {
+        matches!(self.live.get(&entry.key), Some(&(_, seq)) if seq == entry.seq)
+    }
+
+    /**
+     * 堆中过期项过多时重建堆, 避免频繁 reschedule 使堆无限增长
+     */
+    fn compact_if_needed(&mut self) {
+        if self.heap.len() <= 64 || self.heap.len() <= 2 * self.live.len() {
+            return;
+        }
+        let entries: Vec<HeapEntry<K>> = std::mem::take(&mut self.heap).into_vec();
+        self.heap = entries.into_iter().filter(|e| self.is_live(e)).collect();
+    }
+}
+
+impl<K: Eq + Hash + Clone> Default for TimerQueue<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/**
+ * 只列出有效的定时器, 堆里等待惰性删除的旧项不显示
+ */
+impl<K: fmt::Debug> fmt::Debug for TimerQueue<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.live.iter().map(|(key, (deadline_ms, _))| (key, deadline_ms))).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_and_expire() {
+        let mut queue: TimerQueue<u32> = TimerQueue::new();
+        queue.schedule(1, 300);
+        queue.schedule(2, 100);
+        queue.schedule(3, 200);
+
+        assert_eq!(queue.next_deadline(), Some(100));
+        assert_eq!(queue.time_until_next(40), Some(60));
+        assert!(queue.expired(99).is_empty());
+        assert_eq!(queue.expired(200), vec![2, 3]);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.expired(1000), vec![1]);
+        assert!(queue.is_empty());
+        assert_eq!(queue.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_cancel_and_reschedule() {
+        let mut queue: TimerQueue<&str> = TimerQueue::new();
+        queue.schedule("rto", 100);
+        queue.schedule("time_wait", 500);
+
+        assert!(queue.cancel(&"rto"));
+        assert!(!queue.cancel(&"rto"));
+        assert_eq!(queue.next_deadline(), Some(500));
+
+        // 不存在的 key 不能 reschedule
+        assert!(!queue.reschedule("rto", 50));
+        assert!(queue.reschedule("time_wait", 50));
+        assert_eq!(queue.deadline_of(&"time_wait"), Some(50));
+
+        // 旧的截止时间不再触发
+        assert_eq!(queue.expired(60), vec!["time_wait"]);
+        assert!(queue.expired(600).is_empty());
+
+        // schedule 覆盖已有定时器
+        queue.schedule("arp", 10);
+        queue.schedule("arp", 20);
+        assert!(queue.expired(15).is_empty());
+        assert_eq!(queue.expired(20), vec!["arp"]);
+    }
+
+    #[test]
+    fn test_same_deadline_keeps_schedule_order() {
+        let mut queue: TimerQueue<u32> = TimerQueue::new();
+        for key in 0..100 {
+            queue.schedule(key, 42);
+        }
+        queue.cancel(&50);
+        let expected: Vec<u32> = (0..100).filter(|&k| k != 50).collect();
+        assert_eq!(queue.expired(42), expected);
+    }
+
+    #[test]
+    fn test_scale_10k_timers() {
+        let mut queue: TimerQueue<u32> = TimerQueue::new();
+        for key in 0..10_000u32 {
+            queue.schedule(key, ((key * 7919) % 10_000) as u64);
+        }
+        // 反复 reschedule 一半的定时器, 堆不能无限增长
+        for round in 0..10u64 {
+            for key in (0..10_000u32).step_by(2) {
+                queue.reschedule(key, 20_000 + round);
+            }
+        }
+        assert_eq!(queue.len(), 10_000);
+        assert!(queue.heap.len() <= 3 * 10_000);
+
+        let first = queue.expired(9_999);
+        assert_eq!(first.len(), 5_000);
+        assert!(first.iter().all(|key| key % 2 == 1));
+        let second = queue.expired(20_009);
+        assert_eq!(second.len(), 5_000);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,230 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::rc::Rc;
+
+use crate::link::interface::PacketMeta;
+use crate::transport::tcp_connection::{ConnectionId, StallDiagnosis, TcpState};
+use crate::transport::tcp_segment::TcpSegment;
+use crate::utils::drops::DropReason;
+
+/**
+ * 收到的报文段最终被如何处理
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentVerdict {
+    Accepted,
+    Duplicate,
+    OutOfWindow,
+    Dropped,
+}
+
+/**
+ * 协议栈事件的追踪钩子, 所有方法默认什么都不做
+ * 实现者只需覆盖关心的事件
+ */
+pub trait TraceSink {
+    fn on_frame_rx(&mut self, _frame: &[u8]) {}
+
+    fn on_frame_tx(&mut self, _frame: &[u8]) {}
+
+    /**
+     * 带接收元数据的版本, 默认转给 on_frame_rx
+     */
+    fn on_frame_rx_meta(&mut self, frame: &[u8], _meta: &PacketMeta) {
+        self.on_frame_rx(frame);
+    }
+
+    /**
+     * 带交付时刻的版本, 默认转给 on_frame_tx
+     */
+    fn on_frame_tx_at(&mut self, frame: &[u8], _tx_timestamp_ms: u64) {
+        self.on_frame_tx(frame);
+    }
+
+    fn on_segment_rx(&mut self, _conn: &ConnectionId, _segment: &TcpSegment, _verdict: SegmentVerdict) {}
+
+    fn on_segment_tx(&mut self, _conn: &ConnectionId, _segment: &TcpSegment) {}
+
+    fn on_state_change(&mut self, _conn: &ConnectionId, _old: TcpState, _new: TcpState) {}
+
+    /**
+     * total 为该原因累计的丢弃次数
+     */
+    fn on_drop(&mut self, _reason: DropReason, _bytes: usize, _total: u64) {}
+
+    /**
+     * 看门狗发现连接卡死, 每次卡死只报告一次
+     */
+    fn on_stall(&mut self, _diagnosis: &StallDiagnosis) {}
+}
+
+/**
+ * 多个层共用的 sink: 接口、IP 层、连接表和连接各持有一份, 事件按发生的先后到达同一个 sink
+ */
+pub type SharedTraceSink = Rc<RefCell<dyn TraceSink>>;
+
+/**
+ * 各层持有的追踪钩子, 没有设置 sink 时什么都不做
+ * 相等比较不看 sink, 持有它的类型的相等性不受影响
+ */
+#[derive(Clone, Default)]
+pub struct Tracer(Option<SharedTraceSink>);
+
+impl Tracer {
+    pub fn new(sink: Option<SharedTraceSink>) -> Self {
+        Tracer(sink)
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn sink(&self) -> Option<SharedTraceSink> {
+        self.0.clone()
+    }
+
+    pub fn emit(&self, event: impl FnOnce(&mut dyn TraceSink)) {
+        if let Some(sink) = &self.0 {
+            event(&mut *sink.borrow_mut());
+        }
+    }
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer").field("installed", &self.is_set()).finish()
+    }
+}
+
+impl PartialEq for Tracer {
+    fn eq(&self, _other: &Tracer) -> bool {
+        true
+    }
+}
+
+impl Eq for Tracer {}
+
+/**
+ * 默认的空实现
+ */
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopTraceSink;
+
+impl TraceSink for NoopTraceSink {}
+
+/**
+ * 每个事件写一行摘要到任意 io::Write
+ * 写入失败不影响协议栈, 直接忽略
+ */
+pub struct WriterTraceSink<W: io::Write> {
+    writer: W,
+}
+
+impl<W: io::Write> WriterTraceSink<W> {
+    pub fn new(writer: W) -> Self {
+        WriterTraceSink { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: io::Write> TraceSink for WriterTraceSink<W> {
+    fn on_frame_rx(&mut self, frame: &[u8]) {
+        let _ = writeln!(self.writer, "frame rx len={}", frame.len());
+    }
+
+    fn on_frame_tx(&mut self, frame: &[u8]) {
+        let _ = writeln!(self.writer, "frame tx len={}", frame.len());
+    }
+
+    fn on_frame_rx_meta(&mut self, frame: &[u8], meta: &PacketMeta) {
+        let _ = writeln!(self.writer, "frame rx len={} if={} t={}", frame.len(), meta.interface_id, meta.rx_timestamp_ms);
+    }
+
+    fn on_frame_tx_at(&mut self, frame: &[u8], tx_timestamp_ms: u64) {
+        let _ = writeln!(self.writer, "frame tx len={} t={}", frame.len(), tx_timestamp_ms);
+    }
+
+    fn on_segment_rx(&mut self, conn: &ConnectionId, segment: &TcpSegment, verdict: SegmentVerdict) {
+        let _ = writeln!(self.writer, "tcp rx {} {} {:?}", conn, segment, verdict);
+    }
+
+    fn on_segment_tx(&mut self, conn: &ConnectionId, segment: &TcpSegment) {
+        let _ = writeln!(self.writer, "tcp tx {} {}", conn, segment);
+    }
+
+    fn on_state_change(&mut self, conn: &ConnectionId, old: TcpState, new: TcpState) {
+        let _ = writeln!(self.writer, "tcp state {} {:?} -> {:?}", conn, old, new);
+    }
+
+    fn on_drop(&mut self, reason: DropReason, bytes: usize, total: u64) {
+        let _ = writeln!(self.writer, "drop {} bytes={} total={}", reason, bytes, total);
+    }
+
+    fn on_stall(&mut self, diagnosis: &StallDiagnosis) {
+        let _ = writeln!(self.writer, "{}", diagnosis);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tcp_segment::TcpCtrlFlag;
+
+    fn conn_id() -> ConnectionId {
+        ConnectionId { s_ip: 0x0a000001, s_port: 12345, d_ip: 0x0a000002, d_port: 80 }
+    }
+
+    #[test]
+    fn test_writer_sink_lines() {
+        let mut sink = WriterTraceSink::new(Vec::new());
+        let syn = TcpSegment::new(12345, 80, 1000, 0, 5, 0, TcpCtrlFlag::SYN as u16, 4096, 0, vec![], vec![]);
+        let syn_ack = TcpSegment::new(80, 12345, 5000, 1001, 5, 0, TcpCtrlFlag::SYN as u16 | TcpCtrlFlag::ACK as u16, 4096, 0, vec![], vec![]);
+
+        sink.on_segment_tx(&conn_id(), &syn);
+        sink.on_state_change(&conn_id(), TcpState::Closed, TcpState::SynSent);
+        sink.on_segment_rx(&conn_id(), &syn_ack, SegmentVerdict::Accepted);
+        sink.on_state_change(&conn_id(), TcpState::SynSent, TcpState::Established);
+        sink.on_drop(DropReason::BadTcpChecksum, 60, 3);
+
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines, vec![
+            "tcp tx 10.0.0.1:12345 -> 10.0.0.2:80 12345 > 80 [SYN] seq=1000 ack=0 win=4096 len=0",
+            "tcp state 10.0.0.1:12345 -> 10.0.0.2:80 Closed -> SynSent",
+            "tcp rx 10.0.0.1:12345 -> 10.0.0.2:80 80 > 12345 [SYN|ACK] seq=5000 ack=1001 win=4096 len=0 Accepted",
+            "tcp state 10.0.0.1:12345 -> 10.0.0.2:80 SynSent -> Established",
+            "drop bad_tcp_checksum bytes=60 total=3",
+        ]);
+    }
+
+    /**
+     * 只覆盖部分方法的记录器, 其余事件走默认空实现
+     */
+    #[test]
+    fn test_recording_sink_defaults() {
+        struct Recorder {
+            states: Vec<(TcpState, TcpState)>,
+        }
+        impl TraceSink for Recorder {
+            fn on_state_change(&mut self, _conn: &ConnectionId, old: TcpState, new: TcpState) {
+                self.states.push((old, new));
+            }
+        }
+
+        let mut recorder = Recorder { states: vec![] };
+        {
+            let sink: &mut dyn TraceSink = &mut recorder;
+            sink.on_frame_rx(&[0u8; 64]);
+            sink.on_state_change(&conn_id(), TcpState::Listen, TcpState::SynReceived);
+            sink.on_drop(DropReason::QueueFull, 10, 1);
+        }
+        assert_eq!(recorder.states, vec![(TcpState::Listen, TcpState::SynReceived)]);
+
+        let mut noop = NoopTraceSink;
+        noop.on_frame_tx(&[0u8; 64]);
+    }
+}
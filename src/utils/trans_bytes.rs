@@ -1,11 +1,8 @@
 use std::{mem, vec};
-use std::any::TypeId;
 
 /**
  * 多字节数，多字节数组转为单字节数组
  */
-
-
 pub fn multi_bytes_to_bytes_vec<T>(num: T) -> Vec<u8>
 where
     T: Copy + Into<u64>,  // 限制 T 可以转换为 u64
@@ -23,7 +20,7 @@ where
 }
 
 
-pub fn multi_bytes_vec_to_bytes_vec<T>(nums: &Vec<T>) -> Vec<u8> 
+pub fn multi_bytes_vec_to_bytes_vec<T>(nums: &[T]) -> Vec<u8> 
 where 
     T: Copy + Into<u64>
 {
@@ -34,7 +31,7 @@ where
 }
 
 pub fn bytes_vec_to_muilt_bytes(bytes: &[u8]) -> u64{
-    bytes.iter().fold(0 as u64, |acc: u64, byte: &u8| {
+    bytes.iter().fold(0_u64, |acc: u64, byte: &u8| {
         (acc << 8) + (*byte as u64)
     })
 }
@@ -59,18 +56,37 @@ bytes_vec_to_muilt_bytes_vec!(u16, bytes_vec_to_muilt_bytes_vec_u16);
 bytes_vec_to_muilt_bytes_vec!(u32, bytes_vec_to_muilt_bytes_vec_u32);
 bytes_vec_to_muilt_bytes_vec!(u64, bytes_vec_to_muilt_bytes_vec_u64);
 
+/**
+ * 原地只保留 bytes[start..end], 不重新分配内存
+ * 用于把下层的 payload 直接交给上层, 去掉首部和尾部
+ */
+pub fn keep_range(bytes: &mut Vec<u8>, start: usize, end: usize) {
+    bytes.truncate(end);
+    bytes.drain(..start);
+}
+
+#[cfg(test)]
 mod tests {
     use crate::utils::trans_bytes;
 
     #[test]
     fn test_trans_to_muilt() {
-        assert_eq!(trans_bytes::multi_bytes_to_bytes_vec(1 as u64), vec![0, 0, 0, 0, 0 , 0, 0, 1]);
-        assert_eq!(trans_bytes::multi_bytes_vec_to_bytes_vec(&vec![1 as u64, 1 as u64]), vec![0, 0, 0, 0, 0 , 0, 0, 1, 0, 0, 0, 0, 0 , 0, 0, 1])
+        assert_eq!(trans_bytes::multi_bytes_to_bytes_vec(1_u64), vec![0, 0, 0, 0, 0 , 0, 0, 1]);
+        assert_eq!(trans_bytes::multi_bytes_vec_to_bytes_vec(&[1_u64, 1_u64]), vec![0, 0, 0, 0, 0 , 0, 0, 1, 0, 0, 0, 0, 0 , 0, 0, 1])
     }
 
     #[test]
     fn test_muilt_trans_to() {
-        assert_eq!(trans_bytes::bytes_vec_to_muilt_bytes(&[1 as u8, 0 as u8]) as u16, 0x0100);
+        assert_eq!(trans_bytes::bytes_vec_to_muilt_bytes(&[1_u8, 0_u8]) as u16, 0x0100);
         assert_eq!(trans_bytes::bytes_vec_to_muilt_bytes_vec_u32(&[1,0,1,0]), vec![0x01000100]);
     }
+
+    #[test]
+    fn test_keep_range() {
+        let mut bytes: Vec<u8> = (0..10).collect();
+        let ptr = bytes.as_ptr();
+        trans_bytes::keep_range(&mut bytes, 2, 7);
+        assert_eq!(bytes, vec![2, 3, 4, 5, 6]);
+        assert_eq!(bytes.as_ptr(), ptr);
+    }
 }
\ No newline at end of file
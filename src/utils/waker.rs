@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::task::Waker;
+use std::time::Duration;
+
+use crate::transport::tcp_connection::ConnectionId;
+
+use super::timer::TimerQueue;
+
+/**
+ * 等待的就绪事件
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Interest {
+    Readable,
+    Writable,
+    Acceptable, // 监听端口上有可 accept 的连接, 以本地地址端口、对端为 0 的 ConnectionId 作键
+}
+
+/**
+ * 异步适配层的 waker 登记表: poll 函数未就绪时登记, 协议栈在状态变化时唤醒
+ * 同一个任务重复登记只保留一份(Waker::will_wake)
+ */
+pub struct WakerRegistry {
+    wakers: HashMap<(ConnectionId, Interest), Vec<Waker>>,
+}
+
+impl WakerRegistry {
+    pub fn new() -> Self {
+        WakerRegistry { wakers: HashMap::new() }
+    }
+
+    pub fn register(&mut self, id: ConnectionId, interest: Interest, waker: &Waker) {
+        let list = self.wakers.entry((id, interest)).or_default();
+        if !list.iter().any(|w| w.will_wake(waker)) {
+            list.push(waker.clone());
+        }
+    }
+
+    /**
+     * 唤醒并移除等待该事件的所有任务, 返回唤醒的个数
+     */
+    pub fn wake(&mut self, id: ConnectionId, interest: Interest) -> usize {
+        match self.wakers.remove(&(id, interest)) {
+            Some(list) => {
+                let n = list.len();
+                list.into_iter().for_each(Waker::wake);
+                n
+            }
+            None => 0,
+        }
+    }
+
+    /**
+     * 连接关闭或复位时唤醒它上面的所有等待者, 让它们观察到错误
+     */
+    pub fn wake_all(&mut self, id: ConnectionId) -> usize {
+        [Interest::Readable, Interest::Writable, Interest::Acceptable]
+            .iter()
+            .map(|&interest| self.wake(id, interest))
+            .sum()
+    }
+
+    /**
+     * 登记在册的 waker 总数
+     */
+    pub fn len(&self) -> usize {
+        self.wakers.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.wakers.is_empty()
+    }
+}
+
+impl Default for WakerRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/**
+ * 距下一个定时器到期还要多久, 交给运行时的定时器驱动 tick; 没有定时器时返回 None
+ */
+pub fn poll_delay<K: Eq + Hash + Clone>(timers: &mut TimerQueue<K>, now_ms: u64) -> Option<Duration> {
+    timers.time_until_next(now_ms).map(Duration::from_millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll, Wake};
+    use std::thread;
+
+    use super::*;
+    use crate::testing::executor::block_on;
+
+    const ID: ConnectionId = ConnectionId { s_ip: 1, s_port: 2, d_ip: 3, d_port: 4 };
+
+    /**
+     * 模拟连接的接收缓冲区, 写入方负责唤醒
+     */
+    #[derive(Default)]
+    struct Shared {
+        buffer: VecDeque<u8>,
+        closed: bool,
+        registry: WakerRegistry,
+    }
+
+    struct Read(Arc<Mutex<Shared>>);
+
+    impl Future for Read {
+        type Output = Option<u8>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<u8>> {
+            let mut shared = self.0.lock().unwrap();
+            if let Some(byte) = shared.buffer.pop_front() {
+                return Poll::Ready(Some(byte));
+            }
+            if shared.closed {
+                return Poll::Ready(None);
+            }
+            shared.registry.register(ID, Interest::Readable, cx.waker());
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn test_reader_is_woken_by_writer() {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let writer = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                for byte in b"echo" {
+                    let mut shared = shared.lock().unwrap();
+                    shared.buffer.push_back(*byte);
+                    shared.registry.wake(ID, Interest::Readable);
+                }
+                let mut shared = shared.lock().unwrap();
+                shared.closed = true;
+                shared.registry.wake_all(ID);
+            })
+        };
+
+        let received = block_on(async {
+            let mut out = Vec::new();
+            while let Some(byte) = Read(Arc::clone(&shared)).await {
+                out.push(byte);
+            }
+            out
+        });
+        writer.join().unwrap();
+        assert_eq!(received, b"echo");
+        assert!(shared.lock().unwrap().registry.is_empty());
+    }
+
+    #[derive(Default)]
+    struct CountWaker(AtomicUsize);
+
+    impl Wake for CountWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_duplicate_registration_and_poll_delay() {
+        let count = Arc::new(CountWaker::default());
+        let waker: Waker = Arc::clone(&count).into();
+        let mut registry = WakerRegistry::new();
+        registry.register(ID, Interest::Writable, &waker);
+        registry.register(ID, Interest::Writable, &waker.clone());
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.wake(ID, Interest::Readable), 0);
+        assert_eq!(registry.wake(ID, Interest::Writable), 1);
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+
+        let mut timers: TimerQueue<u32> = TimerQueue::new();
+        assert_eq!(poll_delay(&mut timers, 0), None);
+        timers.schedule(7, 250);
+        assert_eq!(poll_delay(&mut timers, 100), Some(Duration::from_millis(150)));
+    }
+}
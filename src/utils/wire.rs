@@ -0,0 +1,151 @@
+use crate::error::{ParseError, SerializeError};
+
+/**
+ * 所有协议类型统一的序列化接口
+ */
+pub trait WireSerialize {
+    /**
+     * 序列化后的字节数
+     */
+    fn wire_size(&self) -> usize;
+
+    /**
+     * 写入 buf 的开头, 返回写入的字节数
+     * buf 不足 wire_size() 时返回错误且不写入任何内容
+     */
+    fn serialize_into(&self, buf: &mut [u8]) -> Result<usize, SerializeError>;
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf: Vec<u8> = vec![0; self.wire_size()];
+        let written = self.serialize_into(&mut buf).expect("buffer sized by wire_size()");
+        buf.truncate(written);
+        buf
+    }
+}
+
+/**
+ * 解析时对保留位和首部自洽性的要求
+ * Loose 时保留位原样保存、序列化时原样写回, 转发和回放不会悄悄改写报文; Strict 时这些都是解析错误
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseStrictness {
+    Strict,
+    #[default]
+    Loose,
+}
+
+impl ParseStrictness {
+    pub fn is_strict(&self) -> bool {
+        *self == ParseStrictness::Strict
+    }
+}
+
+/**
+ * 所有协议类型统一的反序列化接口
+ */
+pub trait WireDeserialize: Sized {
+    fn deserialize(bytes: &[u8]) -> Result<Self, ParseError>;
+}
+
+/**
+ * serialize_into 实现中的缓冲区长度检查
+ */
+pub fn check_buffer(buf: &[u8], needed: usize) -> Result<(), SerializeError> {
+    if buf.len() < needed {
+        return Err(SerializeError::BufferTooSmall { needed, available: buf.len() });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::ethernet::EthernetFrame;
+    use crate::net::icmp_v4::IcmpV4;
+    use crate::net::ipv4::Ipv4Datagram;
+    use crate::transport::tcp_segment::TcpSegment;
+
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn bytes(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| self.next() as u8).collect()
+        }
+    }
+
+    /**
+     * serialize -> deserialize -> serialize 结果完全一致
+     */
+    fn assert_round_trip<T: WireSerialize + WireDeserialize>(value: &T) {
+        let bytes = value.serialize();
+        assert_eq!(bytes.len(), value.wire_size());
+        let parsed = T::deserialize(&bytes).unwrap();
+        assert_eq!(parsed.serialize(), bytes);
+    }
+
+    #[test]
+    fn test_round_trip_all_types() {
+        let mut rng = Rng(0x1234_5678_9abc_def0);
+        for _ in 0..500 {
+            let r = rng.next();
+
+            let len = 46 + (r % 100) as usize;
+            let frame = EthernetFrame::new([1, 2, 3, 4, 5, 6], [6, 5, 4, 3, 2, 1], r as u16, rng.bytes(len));
+            assert_round_trip(&frame);
+
+            let payload = rng.bytes((r % 80) as usize);
+            let datagram = Ipv4Datagram::new(4, 5, r as u8, 20 + payload.len() as u16, (r >> 24) as u16, (r >> 40) as u8 & 0b111,
+                (r >> 43) as u16 & 0x1fff, 64, 6, r as u32, (r >> 32) as u32, payload);
+            assert_round_trip(&datagram);
+
+            let options: Vec<u32> = (0..(r % 11)).map(|_| rng.next() as u32).collect();
+            let segment = TcpSegment::new((r >> 8) as u16, (r >> 24) as u16, r as u32, (r >> 32) as u32,
+                5 + options.len() as u8, (r >> 4) as u8 & 0b111, (r >> 16) as u16 & 0x1ff, (r >> 40) as u16, 0,
+                options, rng.bytes((r % 64) as usize));
+            assert_round_trip(&segment);
+
+            let icmp = IcmpV4::new(r as u8, (r >> 8) as u8, rng.bytes((r % 64) as usize));
+            assert_round_trip(&icmp);
+        }
+    }
+
+    #[test]
+    fn test_serialize_into_small_buffer() {
+        let icmp = IcmpV4::new(8, 0, vec![1, 2, 3, 4]);
+        let mut buf = [0u8; 7];
+        assert_eq!(
+            icmp.serialize_into(&mut buf).unwrap_err(),
+            SerializeError::BufferTooSmall { needed: 8, available: 7 }
+        );
+        assert_eq!(buf, [0u8; 7]);
+
+        let mut buf = [0u8; 16];
+        assert_eq!(icmp.serialize_into(&mut buf), Ok(8));
+        assert_eq!(&buf[..8], &icmp.serialize()[..]);
+    }
+
+    #[test]
+    fn test_dyn_wire_serialize() {
+        let packets: Vec<Box<dyn WireSerialize>> = vec![
+            Box::new(IcmpV4::new(8, 0, vec![0; 4])),
+            Box::new(TcpSegment::new(1, 2, 3, 4, 5, 0, 0, 0, 0, vec![], vec![9; 3])),
+        ];
+        let sizes: Vec<usize> = packets.iter().map(|p| p.serialize().len()).collect();
+        assert_eq!(sizes, vec![8, 23]);
+    }
+
+    #[test]
+    fn test_deserialize_error_wrapping() {
+        assert!(matches!(EthernetFrame::deserialize(&[0; 10]), Err(ParseError::Ethernet(_))));
+        assert!(matches!(Ipv4Datagram::deserialize(&[0; 10]), Err(ParseError::Ipv4(_))));
+        assert!(matches!(TcpSegment::deserialize(&[0; 10]), Err(ParseError::Tcp(_))));
+        assert!(matches!(IcmpV4::deserialize(&[0; 2]), Err(ParseError::Icmp(_))));
+    }
+}
@@ -0,0 +1,76 @@
+/**
+ * ARP 负缓存: 连接一个不存在的主机, 3 次广播之后连接以 HostUnreachable 失败
+ * 退避期内第二次连接立即失败, 不再广播; 主机发出免费 ARP 之后恢复
+ * 测试内的粘合代码充当 IP 层: 发送前查询 ARP, 不可达时通知连接表
+ */
+use simple_tcp_ip::config::{ArpConfig, TcpConfig};
+use simple_tcp_ip::link::arp::ArpPacket;
+use simple_tcp_ip::link::arp_cache::{ArpCache, ArpState, Resolution};
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionError, ConnectionId, TcpState};
+
+const LOCAL: u32 = 0x0a000001;
+const DEAD_HOST: u32 = 0x0a000063;
+const HOST_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x63];
+
+/**
+ * 主动打开并把 SYN 交给"IP 层"; 返回 SYN 是否可以立即发出
+ */
+fn connect(table: &mut ConnectionTable, arp: &mut ArpCache, id: ConnectionId, now: u64) -> bool {
+    table.connect(id, now);
+    match arp.resolution(id.d_ip, now) {
+        Resolution::Resolved(_) => true,
+        Resolution::Pending => false, // SYN 排队等待解析
+        Resolution::Unreachable => {
+            table.host_unreachable(id.d_ip, now);
+            false
+        }
+    }
+}
+
+/**
+ * 推进时钟, 把解析失败的地址报告给连接表
+ */
+fn tick(table: &mut ConnectionTable, arp: &mut ArpCache, now: u64) {
+    arp.tick(now);
+    for ip in arp.take_unreachable() {
+        table.host_unreachable(ip, now);
+    }
+}
+
+#[test]
+fn test_dead_host_is_negatively_cached() {
+    let config = ArpConfig::default(); // 3 次请求, 间隔 1 秒, 负缓存 5 秒
+    let mut arp = ArpCache::new(&config);
+    let mut table = ConnectionTable::new(&TcpConfig::default());
+    let mut broadcasts = 0;
+
+    let first = ConnectionId { s_ip: LOCAL, s_port: 40001, d_ip: DEAD_HOST, d_port: 80 };
+    assert!(!connect(&mut table, &mut arp, first, 0));
+    let mut now = 0;
+    while table.state(first) == Some(TcpState::SynSent) {
+        now += 100;
+        tick(&mut table, &mut arp, now);
+        broadcasts += arp.take_requests().iter().filter(|request| request.is_broadcast()).count();
+    }
+    assert_eq!(broadcasts, 3);
+    assert_eq!(now, 3 * config.retry_interval_ms);
+    assert_eq!(table.error(first), Some(ConnectionError::HostUnreachable));
+    assert_eq!(table.write(first, b"x"), Err(ConnectionError::HostUnreachable));
+    assert_eq!(arp.state(DEAD_HOST), Some(ArpState::Unreachable));
+
+    // 退避期内的第二次连接立即失败, 没有任何 ARP 请求
+    let second = ConnectionId { s_port: 40002, ..first };
+    assert!(!connect(&mut table, &mut arp, second, now + 10));
+    assert_eq!(table.state(second), Some(TcpState::Closed));
+    assert_eq!(table.error(second), Some(ConnectionError::HostUnreachable));
+    assert!(arp.take_requests().is_empty());
+
+    // 主机上线后发出免费 ARP, 负缓存表项被更新
+    arp.on_arp_packet(&ArpPacket::request(HOST_MAC, DEAD_HOST, DEAD_HOST), now + 20);
+    assert_eq!(arp.state(DEAD_HOST), Some(ArpState::Reachable));
+    let third = ConnectionId { s_port: 40003, ..first };
+    assert!(connect(&mut table, &mut arp, third, now + 30));
+    assert_eq!(table.state(third), Some(TcpState::SynSent));
+    assert_eq!(table.error(third), None);
+}
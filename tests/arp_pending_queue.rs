@@ -0,0 +1,104 @@
+/**
+ * ARP 解析失败时排队数据报的去向: 本机发出的让连接以 HostUnreachable 失败, 线路上不出现 ICMP;
+ * 转发的向原始源地址回一个 ICMP 主机不可达
+ * 测试内的粘合代码充当 IP 层: 查询 ARP, 解析中的数据报带着来源标记排队, 失败的地址交给排队队列
+ */
+use simple_tcp_ip::config::{ArpConfig, Ipv4Config, TcpConfig};
+use simple_tcp_ip::link::arp_cache::{ArpCache, Resolution};
+use simple_tcp_ip::link::arp_queue::{ArpFailure, ArpPendingQueue, Origin};
+use simple_tcp_ip::net::icmp_v4::IcmpV4;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionError, ConnectionId, TcpState};
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const LOCAL: u32 = 0x0a000001;
+const DEAD_HOST: u32 = 0x0a000063;
+const REMOTE_SENDER: u32 = 0x0a010005; // 经由本机转发的另一个网段上的主机
+
+struct Glue {
+    arp: ArpCache,
+    queue: ArpPendingQueue,
+    table: ConnectionTable,
+    wire: Vec<Ipv4Datagram>, // 发到线路上的 IP 数据报, ARP 请求不算
+}
+
+impl Glue {
+    fn new() -> Self {
+        let arp = ArpConfig::default();
+        Glue {
+            arp: ArpCache::new(&arp),
+            queue: ArpPendingQueue::new(&arp, &Ipv4Config::default()),
+            table: ConnectionTable::new(&TcpConfig::default()),
+            wire: vec![],
+        }
+    }
+
+    fn send(&mut self, datagram: Ipv4Datagram, origin: Origin, now: u64) {
+        match self.arp.resolution(datagram.d_addr(), now) {
+            Resolution::Resolved(_) => self.wire.push(datagram),
+            Resolution::Pending => {
+                self.queue.push(datagram.d_addr(), datagram, origin);
+            }
+            Resolution::Unreachable => panic!("negative cache is covered by arp_negative_cache.rs"),
+        }
+    }
+
+    fn tick(&mut self, now: u64) {
+        self.arp.tick(now);
+        self.arp.take_requests();
+        for ip in self.arp.take_unreachable() {
+            for notice in self.queue.failed(ip, LOCAL, now) {
+                match notice {
+                    ArpFailure::Local { d_addr, .. } => {
+                        self.table.host_unreachable(d_addr, now);
+                    }
+                    ArpFailure::Icmp(icmp) => self.wire.push(icmp),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_local_connect_fails_without_icmp() {
+    let mut glue = Glue::new();
+    let id = ConnectionId { s_ip: LOCAL, s_port: 40000, d_ip: DEAD_HOST, d_port: 80 };
+    let syn = glue.table.connect(id, 0);
+    let datagram = glue.table.datagram(id, &syn, &Ipv4Config::default()).unwrap();
+    glue.send(datagram, Origin::Local { conn_hint: Some(id) }, 0);
+    assert_eq!(glue.queue.pending(DEAD_HOST), 1);
+
+    let mut now = 0;
+    while glue.table.state(id) == Some(TcpState::SynSent) {
+        now += 100;
+        glue.tick(now);
+    }
+    assert_eq!(now, 3 * ArpConfig::default().retry_interval_ms); // 3 次广播之后立即失败, 不等 TCP 超时
+    assert_eq!(glue.table.error(id), Some(ConnectionError::HostUnreachable));
+    assert!(glue.wire.is_empty());
+    assert_eq!(glue.queue.stats().icmp_sent, 0);
+}
+
+#[test]
+fn test_forwarded_packet_gets_one_host_unreachable() {
+    let mut glue = Glue::new();
+    let payload = vec![0x9c, 0x40, 0x00, 0x50, 0, 0, 0, 1]; // TCP 首部的前 8 字节就够了
+    let original = Ipv4Datagram::new(4, 5, 0, 28, 0x1234, 0, 0, 30, 6, REMOTE_SENDER, DEAD_HOST, payload);
+    let original_bytes = original.serialize();
+    let mut forwarded = Ipv4Datagram::try_deserialize(&original_bytes).unwrap();
+    assert!(forwarded.decrement_ttl());
+    glue.send(forwarded, Origin::Forwarded { original }, 0);
+
+    for now in (100..=5000).step_by(100) {
+        glue.tick(now);
+    }
+    assert_eq!(glue.wire.len(), 1);
+    let error = &glue.wire[0];
+    assert_eq!((error.s_addr(), error.d_addr(), error.protocol()), (LOCAL, REMOTE_SENDER, 1));
+    let icmp = IcmpV4::try_deserialize(error.payload()).unwrap();
+    assert_eq!((icmp.icmp_type(), icmp.code()), (3, 1));
+    // 带回的是收到时的首部 (TTL 还没有减) 和 8 字节载荷
+    let embedded = icmp.embedded_datagram().unwrap();
+    assert_eq!(embedded, &original_bytes[..]);
+}
@@ -0,0 +1,91 @@
+/*
+ * 异步适配层: 客户端与回显服务端各是一张连接表上的任务, 驱动循环只在两张表之间搬运报文段
+ * 任务只在被唤醒时才被 poll, 回显完成说明读、写、accept 的唤醒都没有遗漏
+ * cargo test --features async --test async_echo
+ */
+#![cfg(feature = "async")]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::testing::executor::LocalTasks;
+use simple_tcp_ip::transport::async_stream::AsyncStack;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+
+const CLIENT_IP: u32 = 0x0a000001;
+const SERVER_IP: u32 = 0x0a000002;
+const ID: ConnectionId = ConnectionId { s_ip: CLIENT_IP, s_port: 40000, d_ip: SERVER_IP, d_port: 7 };
+
+/**
+ * 把两端这一轮要发的段交给对端, 直到双方都没有要发的; 返回搬运的段数
+ */
+fn shuttle(client: &AsyncStack, server: &AsyncStack, now_ms: u64) -> usize {
+    let mut moved = 0;
+    let mut to_server: Vec<_> = client.poll(now_ms).into_iter().map(|(_, segment)| segment).collect();
+    let mut to_client: Vec<_> = server.poll(now_ms).into_iter().map(|(_, segment)| segment).collect();
+    while !to_server.is_empty() || !to_client.is_empty() {
+        moved += to_server.len() + to_client.len();
+        for segment in std::mem::take(&mut to_server) {
+            to_client.extend(server.segment_received(CLIENT_IP, SERVER_IP, &segment, now_ms));
+        }
+        for segment in std::mem::take(&mut to_client) {
+            to_server.extend(client.segment_received(SERVER_IP, CLIENT_IP, &segment, now_ms));
+        }
+        to_server.extend(client.poll(now_ms).into_iter().map(|(_, segment)| segment));
+        to_client.extend(server.poll(now_ms).into_iter().map(|(_, segment)| segment));
+    }
+    moved
+}
+
+#[test]
+fn test_echo_through_two_tables() {
+    // 发送缓冲区比消息小, 写满之后要等 Writable 唤醒
+    let config = TcpConfig { send_buffer: 4096, ..TcpConfig::default() };
+    let client = AsyncStack::new(ConnectionTable::new(&config));
+    let server = AsyncStack::new(ConnectionTable::new(&config));
+    let listener = server.listen(SERVER_IP, 7);
+    let message: Vec<u8> = (0..20_000).map(|i| (i * 7 % 251) as u8).collect();
+    let echoed = Rc::new(RefCell::new(None));
+
+    let mut tasks = LocalTasks::new();
+    tasks.spawn(async move {
+        let mut stream = listener.accept().await;
+        let mut buf = [0u8; 1500];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            stream.write_all(&buf[..n]).await.unwrap();
+        }
+        stream.close().await.unwrap();
+    });
+    let mut stream = client.connect(ID);
+    let (expected, result) = (message.clone(), Rc::clone(&echoed));
+    tasks.spawn(async move {
+        // 接收缓冲区放得下整条消息, 先写完再读不会互相等待
+        stream.write_all(&expected).await.unwrap();
+        let mut received = vec![0u8; expected.len()];
+        stream.read_exact(&mut received).await.unwrap();
+        stream.close().await.unwrap();
+        let mut rest = vec![];
+        stream.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+        *result.borrow_mut() = Some(received);
+    });
+
+    let mut now = 0;
+    while !tasks.is_empty() {
+        tasks.run_ready();
+        shuttle(&client, &server, now);
+        now += 1;
+        assert!(now < 1000, "echo stalled with {} tasks waiting", tasks.len());
+    }
+    assert_eq!(echoed.borrow().as_deref(), Some(&message[..]));
+    assert_eq!(client.waiting() + server.waiting(), 0);
+    assert!(client.with(|table| table.state(ID)) != Some(TcpState::Established));
+}
@@ -0,0 +1,26 @@
+/**
+ * 批量传输吞吐基准: 默认参数下传 4 MB, 打印报告并与基线比较
+ * 耗时较长, 默认忽略; 用 `cargo test --release -- --ignored bench_bulk --nocapture` 运行
+ * goodput 按虚拟时间计算, 只随协议行为变化; 行为有意改变时用报告中的 JSON 更新基线
+ */
+use simple_tcp_ip::testing::bench::{self, Baseline, BenchConfig, CountingAllocator};
+use simple_tcp_ip::utils::clock::MonotonicClock;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const MAX_DROP_PERCENT: f64 = 5.0;
+
+#[test]
+#[ignore]
+fn bench_bulk() {
+    let report = bench::bulk_transfer(&BenchConfig::default(), &MonotonicClock::new());
+    println!("{}", report);
+    println!("{}", report.to_json());
+    assert!(report.allocations > 0);
+
+    let baseline = Baseline::from_json(include_str!("fixtures/bench_baseline.json")).unwrap();
+    if let Err(regression) = bench::compare(&baseline, &report, MAX_DROP_PERCENT) {
+        panic!("{}", regression);
+    }
+}
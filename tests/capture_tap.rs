@@ -0,0 +1,81 @@
+/**
+ * IP 层旁路抓包: 在回环接口上 ping 本机, 请求和应答各经过一次发送和一次接收
+ * 测试内的粘合代码代替协议栈的 IP 输入, 把取回的回显请求交给 echo_reply_datagram
+ */
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use simple_tcp_ip::config::Ipv4Config;
+use simple_tcp_ip::net::capture::{pcap_capture, CaptureDirection};
+use simple_tcp_ip::net::icmp_v4::IcmpV4;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::net::loopback::LoopbackInterface;
+use simple_tcp_ip::net::pinger::{echo_reply_datagram, PingOutcome, Pinger, ECHO_REPLY, ECHO_REQUEST};
+use simple_tcp_ip::net::raw_socket::{IpProtocol, RawSockets};
+use simple_tcp_ip::utils::clock::ManualClock;
+use simple_tcp_ip::utils::pcap::{PcapReader, PcapWriter, DEFAULT_SNAPLEN, LINKTYPE_RAW};
+
+const LOCALHOST: u32 = 0x7f000001;
+
+/**
+ * 发出一个回显请求并处理到收到应答为止, 返回 Pinger 的结果
+ */
+fn ping(lo: &mut LoopbackInterface) -> Vec<PingOutcome> {
+    let mut raw = RawSockets::new(LOCALHOST, &Ipv4Config::default());
+    let icmp = raw.raw_socket(IpProtocol::Icmp);
+    let mut pinger = Pinger::new(7, 32);
+    let probe = pinger.send_probe(&mut raw, icmp, LOCALHOST, 0).unwrap();
+    assert!(lo.transmit(&probe));
+    while let Some(received) = lo.receive() {
+        let (datagram, _) = received.unwrap();
+        raw.deliver(&datagram);
+        if let Some(reply) = echo_reply_datagram(&datagram, LOCALHOST, 0) {
+            assert!(lo.transmit(&reply));
+        }
+    }
+    pinger.receive(&mut raw, icmp, 1)
+}
+
+#[test]
+fn test_counting_capture_sees_ping_exchange() {
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut lo = LoopbackInterface::new();
+    let log = seen.clone();
+    lo.set_capture(Some(Box::new(move |direction, datagram: &Ipv4Datagram| {
+        let icmp = IcmpV4::try_deserialize(datagram.payload()).unwrap();
+        log.borrow_mut().push((direction, icmp.icmp_type()));
+    })));
+
+    assert_eq!(ping(&mut lo), vec![PingOutcome::Reply { seq: 0, size: 32, rtt_ms: 1 }]);
+    assert_eq!(*seen.borrow(), vec![
+        (CaptureDirection::Tx, ECHO_REQUEST),
+        (CaptureDirection::Rx, ECHO_REQUEST),
+        (CaptureDirection::Tx, ECHO_REPLY),
+        (CaptureDirection::Rx, ECHO_REPLY),
+    ]);
+
+    // 卸下之后不再抓取
+    assert!(lo.set_capture(None).is_some());
+    ping(&mut lo);
+    assert_eq!(seen.borrow().len(), 4);
+}
+
+#[test]
+fn test_pcap_capture_writes_raw_ip() {
+    let pcap = Rc::new(RefCell::new(PcapWriter::with_link_type(Vec::new(), DEFAULT_SNAPLEN, LINKTYPE_RAW).unwrap()));
+    let mut lo = LoopbackInterface::new();
+    lo.set_capture(Some(pcap_capture(pcap.clone(), ManualClock::new(2_000))));
+    ping(&mut lo);
+    drop(lo);
+
+    let bytes = Rc::try_unwrap(pcap).ok().unwrap().into_inner().into_inner();
+    let reader = PcapReader::new(&bytes[..]).unwrap();
+    assert_eq!(reader.link_type(), LINKTYPE_RAW);
+    let records: Vec<(u64, Vec<u8>)> = reader.map(|record| record.unwrap()).collect();
+    assert_eq!(records.len(), 4);
+    for (timestamp_us, packet) in records {
+        assert_eq!(timestamp_us, 2_000_000);
+        let datagram = Ipv4Datagram::try_deserialize(&packet).unwrap();
+        assert_eq!((datagram.s_addr(), datagram.d_addr(), datagram.protocol()), (LOCALHOST, LOCALHOST, 1));
+    }
+}
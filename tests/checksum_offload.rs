@@ -0,0 +1,149 @@
+/**
+ * 校验和卸载: 内存链路两端都选择 TrustLink 时不计算也不校验校验和
+ * 两端策略一致才能互通; 被篡改的字节在 GenerateAndVerify 下被发现, 在 TrustLink 下原样交付(这是卸载的代价)
+ * 测试内的粘合代码把报文段封装成帧、经接口收发
+ */
+use simple_tcp_ip::config::{Ipv4Config, TcpConfig};
+use simple_tcp_ip::error::DeviceError;
+use simple_tcp_ip::link::interface::EthernetInterface;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+use simple_tcp_ip::utils::checksum::ChecksumPolicy;
+use simple_tcp_ip::utils::dissect;
+use simple_tcp_ip::utils::drops::DropReason;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const A_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+const B_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 2];
+
+struct Node {
+    iface: EthernetInterface,
+    table: ConnectionTable,
+}
+
+impl Node {
+    fn new(mac: [u8; 6], ip: u32, policy: ChecksumPolicy) -> Self {
+        let mut iface = EthernetInterface::new(mac, ip, 24);
+        iface.set_checksum_policy(policy).unwrap();
+        let mut table = ConnectionTable::new(&TcpConfig::default());
+        table.set_checksum_policy(policy);
+        Node { iface, table }
+    }
+
+    fn frame(&mut self, id: ConnectionId, segment: &TcpSegment, d_mac: [u8; 6]) -> Vec<u8> {
+        let datagram = self.table.datagram(id, segment, &Ipv4Config::default()).unwrap();
+        self.iface.frame(d_mac, 0x0800, datagram.serialize()).serialize()
+    }
+
+    /**
+     * 收一帧, 通过检查的报文段交给连接表, 返回应答的帧
+     */
+    fn receive(&mut self, bytes: &[u8], d_mac: [u8; 6]) -> Vec<Vec<u8>> {
+        let Some(frame) = self.iface.receive(bytes) else { return vec![] };
+        let datagram = Ipv4Datagram::try_deserialize(frame.payload()).unwrap();
+        if !self.iface.verify_datagram(&datagram) {
+            return vec![];
+        }
+        let segment = TcpSegment::try_deserialize(datagram.payload()).unwrap();
+        let replies = self.table.segment_received(datagram.s_addr(), datagram.d_addr(), &segment, 0);
+        let id = ConnectionId::for_incoming(datagram.s_addr(), datagram.d_addr(), &segment);
+        replies.iter().map(|reply| self.frame(id, reply, d_mac)).collect()
+    }
+}
+
+/**
+ * A 向 B 主动打开, 两边来回投递直到没有新的帧
+ */
+fn handshake(a: &mut Node, b: &mut Node) -> ConnectionId {
+    let id = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+    b.table.listen(B_IP, 80);
+    let syn = a.table.connect(id, 0);
+    let mut to_b = vec![a.frame(id, &syn, B_MAC)];
+    while !to_b.is_empty() {
+        let to_a: Vec<Vec<u8>> = to_b.drain(..).flat_map(|bytes| b.receive(&bytes, A_MAC)).collect();
+        to_b = to_a.iter().flat_map(|bytes| a.receive(bytes, B_MAC)).collect();
+    }
+    id
+}
+
+/**
+ * A 发出 "payload" 的数据帧, 把第 offset 个载荷字节翻转后交给 B, 返回 B 读到的数据
+ */
+fn send_corrupted(a: &mut Node, b: &mut Node, id: ConnectionId) -> Vec<u8> {
+    a.table.write(id, b"payload").unwrap();
+    let (_, segment) = a.table.poll_transmit(1).pop().unwrap();
+    let mut bytes = a.frame(id, &segment, B_MAC);
+    let last = bytes.len() - 4 - 1; // FCS 之前的最后一个载荷字节
+    bytes[last] ^= 0x20;
+    b.receive(&bytes, A_MAC);
+    b.table.read(id.reversed(), usize::MAX).unwrap()
+}
+
+#[test]
+fn test_matching_policies_interoperate() {
+    for policy in [ChecksumPolicy::GenerateAndVerify, ChecksumPolicy::TrustLink] {
+        let mut a = Node::new(A_MAC, A_IP, policy);
+        let mut b = Node::new(B_MAC, B_IP, policy);
+        let id = handshake(&mut a, &mut b);
+        assert_eq!(a.table.state(id), Some(TcpState::Established));
+        assert_eq!(b.table.state(id.reversed()), Some(TcpState::Established));
+    }
+
+    // 一端卸载而另一端校验: 对端丢掉 FCS 为 0 的帧, 连接建立不起来
+    let mut a = Node::new(A_MAC, A_IP, ChecksumPolicy::TrustLink);
+    let mut b = Node::new(B_MAC, B_IP, ChecksumPolicy::GenerateAndVerify);
+    let id = handshake(&mut a, &mut b);
+    assert_eq!(a.table.state(id), Some(TcpState::SynSent));
+    assert_eq!(b.table.state(id.reversed()), None);
+    assert_eq!(b.iface.drop_counters().get(DropReason::BadFcs), 1);
+    assert_eq!(ChecksumPolicy::for_link(ChecksumPolicy::TrustLink, ChecksumPolicy::GenerateAndVerify), ChecksumPolicy::GenerateAndVerify);
+}
+
+#[test]
+fn test_corruption_is_caught_only_when_verifying() {
+    let mut a = Node::new(A_MAC, A_IP, ChecksumPolicy::GenerateAndVerify);
+    let mut b = Node::new(B_MAC, B_IP, ChecksumPolicy::GenerateAndVerify);
+    let id = handshake(&mut a, &mut b);
+    assert!(send_corrupted(&mut a, &mut b, id).is_empty());
+    assert_eq!(b.iface.drop_counters().get(DropReason::BadFcs), 1);
+
+    // FCS 对但 TCP 校验和不对: 在 IP 之上的校验中被发现
+    let mut a = Node::new(A_MAC, A_IP, ChecksumPolicy::GenerateAndVerify);
+    let mut b = Node::new(B_MAC, B_IP, ChecksumPolicy::GenerateAndVerify);
+    let id = handshake(&mut a, &mut b);
+    a.table.write(id, b"payload").unwrap();
+    let (_, mut segment) = a.table.poll_transmit(1).pop().unwrap();
+    segment.data[0] ^= 0x20;
+    let bytes = a.frame(id, &segment, B_MAC);
+    b.receive(&bytes, A_MAC);
+    assert_eq!(b.iface.drop_counters().get(DropReason::BadTcpChecksum), 1);
+    assert!(b.table.read(id.reversed(), usize::MAX).unwrap().is_empty());
+
+    // 卸载时同样的损坏没有被发现, 应用读到了错误的数据
+    let mut a = Node::new(A_MAC, A_IP, ChecksumPolicy::TrustLink);
+    let mut b = Node::new(B_MAC, B_IP, ChecksumPolicy::TrustLink);
+    let id = handshake(&mut a, &mut b);
+    assert_eq!(send_corrupted(&mut a, &mut b, id), b"payloaD");
+    assert_eq!(b.iface.drop_counters().total(), 0);
+}
+
+#[test]
+fn test_tap_refuses_trust_link_and_dissector_notes_offload() {
+    let mut iface = EthernetInterface::new(A_MAC, A_IP, 24);
+    iface.set_tap(true);
+    assert!(matches!(iface.set_checksum_policy(ChecksumPolicy::TrustLink), Err(DeviceError::Unsupported { .. })));
+    assert_eq!(iface.checksum_policy(), ChecksumPolicy::GenerateAndVerify);
+
+    let mut a = Node::new(A_MAC, A_IP, ChecksumPolicy::TrustLink);
+    let id = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+    let syn = a.table.connect(id, 0);
+    let bytes = a.frame(id, &syn, B_MAC);
+    let text = dissect::dissect_with(&bytes, ChecksumPolicy::TrustLink);
+    assert!(text.contains("FCS: 0x00000000 (checksum offloaded)"));
+    assert!(text.contains("Checksum: 0x0000 (checksum offloaded)"));
+    assert!(dissect::dissect(&bytes).contains("(incorrect)"));
+}
@@ -0,0 +1,194 @@
+/**
+ * 目的地缓存: 连接结束时按对端地址留下 RTT、ssthresh 与路径 MTU, 之后到同一对端的新连接从这些值起步
+ * 条目在虚拟时钟上 destination_cache_ttl_ms 后过期, 条目数不超过 destination_cache_size
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::testing::pair::TablePair;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+
+const CLIENT: u32 = 0x0a000001;
+const SERVER: u32 = 0x0a000002;
+const LINK: TablePair = TablePair::new(CLIENT, SERVER).polling();
+const TTL_MS: u64 = 10 * 60_000;
+
+fn id(d_ip: u32, s_port: u16) -> ConnectionId {
+    ConnectionId { s_ip: CLIENT, s_port, d_ip, d_port: 80 }
+}
+
+/**
+ * 建立连接, 传一些数据, 然后双方关闭; 客户端进入 TimeWait
+ */
+fn session(client: &mut ConnectionTable, server: &mut ConnectionTable, id: ConnectionId, now: u64, delay_ms: u64) -> u64 {
+    let syn = client.connect(id, now);
+    let mut now = LINK.at(now).delay(delay_ms).exchange(client, server, vec![syn]).now_ms;
+    for _ in 0..5 {
+        client.tick(now); // 发出时刻取连接最近看到的时钟
+        client.write(id, &[7; 1000]).unwrap();
+        let segments = client.poll_transmit(usize::MAX).into_iter().map(|(_, segment)| segment).collect();
+        now = LINK.at(now).delay(delay_ms).exchange(client, server, segments).now_ms;
+    }
+    assert_eq!(server.read(id.reversed(), usize::MAX).unwrap().len(), 5000);
+    finish(client, server, id, now, delay_ms)
+}
+
+/**
+ * 双方关闭, 客户端进入 TimeWait 并把测量值留给目的地缓存
+ */
+fn finish(client: &mut ConnectionTable, server: &mut ConnectionTable, id: ConnectionId, mut now: u64, delay_ms: u64) -> u64 {
+    client.close(id, now).unwrap();
+    let fin = client.poll_transmit(usize::MAX).into_iter().map(|(_, segment)| segment).collect();
+    now = LINK.at(now).delay(delay_ms).exchange(client, server, fin).now_ms;
+    server.close(id.reversed(), now).unwrap();
+    let fin = server.poll_transmit(usize::MAX).into_iter().map(|(_, segment)| segment).collect::<Vec<_>>();
+    now += delay_ms;
+    for segment in fin {
+        for ack in client.segment_received(SERVER, CLIENT, &segment, now) {
+            server.segment_received(CLIENT, SERVER, &ack, now + delay_ms);
+        }
+    }
+    assert_eq!(client.state(id), Some(TcpState::TimeWait));
+    now
+}
+
+fn tables(config: &TcpConfig) -> (ConnectionTable, ConnectionTable) {
+    let client = ConnectionTable::new(config);
+    let mut server = ConnectionTable::new(config);
+    server.listen(SERVER, 80);
+    (client, server)
+}
+
+#[test]
+fn test_second_connection_starts_from_cached_rtt() {
+    let config = TcpConfig::default();
+    let (mut client, mut server) = tables(&config);
+    let first = id(SERVER, 40000);
+    assert_eq!(client.rto_ms(first), None);
+    let now = session(&mut client, &mut server, first, 0, 50);
+
+    // 每个数据段都在 100ms 后被确认
+    assert_eq!(client.srtt_ms(first), Some(100));
+    let cached_rto = client.rto_ms(first).unwrap();
+    assert!(cached_rto < config.rto_initial_ms);
+    assert_eq!(client.destination_cache().len(), 1);
+
+    let second = id(SERVER, 40001);
+    client.connect(second, now);
+    assert_eq!(client.rto_ms(second), Some(cached_rto));
+    assert_eq!(client.srtt_ms(second), Some(100));
+    assert_eq!(client.ssthresh(second), Some(u32::MAX));
+
+    // 其他对端仍从默认值起步
+    let other = id(0x0a000003, 40002);
+    client.connect(other, now);
+    assert_eq!(client.rto_ms(other), Some(config.rto_initial_ms));
+    assert_eq!(client.srtt_ms(other), None);
+}
+
+#[test]
+fn test_ssthresh_from_loss_is_cached() {
+    let (mut client, mut server) = tables(&TcpConfig::default());
+    let first = id(SERVER, 40000);
+    let syn = client.connect(first, 0);
+    let mut now = LINK.delay(10).exchange(&mut client, &mut server, vec![syn]).now_ms;
+    // 缓存只记录有 RTT 样本的连接, 第一段正常送达
+    client.write(first, &[3; 1000]).unwrap();
+    now = LINK.at(now).delay(10).polling().exchange(&mut client, &mut server, vec![]).now_ms;
+    client.write(first, &[3; 8000]).unwrap();
+    assert!(client.poll_transmit(usize::MAX).len() > 1); // 整窗丢失
+
+    // 超时重传: ssthresh 减为在途数据的一半, cwnd 回到一个 MSS
+    now += 1000;
+    while client.send_queued(first) != Some(0) {
+        let retransmitted = client.retransmission(first).unwrap();
+        assert_eq!(client.ssthresh(first), Some(4000));
+        now = LINK.at(now).delay(10).exchange(&mut client, &mut server, vec![retransmitted]).now_ms;
+        client.poll(now);
+    }
+    assert_eq!(server.read(first.reversed(), usize::MAX).unwrap().len(), 9000);
+    assert!(client.cwnd(first).unwrap() < 4000);
+    let now = finish(&mut client, &mut server, first, now, 10);
+
+    let second = id(SERVER, 40001);
+    client.connect(second, now);
+    assert_eq!(client.ssthresh(second), Some(4000));
+}
+
+#[test]
+fn test_cached_path_mtu_seeds_mss() {
+    let (mut client, mut server) = tables(&TcpConfig::default());
+    let first = id(SERVER, 40000);
+    let syn = client.connect(first, 0);
+    let now = LINK.delay(10).exchange(&mut client, &mut server, vec![syn]).now_ms;
+    client.set_path_mtu(first, 1000).unwrap();
+    client.write(first, &[1; 3000]).unwrap();
+    let segments: Vec<TcpSegment> = client.poll_transmit(usize::MAX).into_iter().map(|(_, segment)| segment).collect();
+    assert_eq!(segments[0].data.len(), 960);
+    let now = LINK.at(now).delay(10).exchange(&mut client, &mut server, segments).now_ms;
+    client.abort(first, now).unwrap();
+
+    // 新连接不用等到 PMTU 发现就按缓存的 MTU 分段
+    let second = id(SERVER, 40001);
+    let syn = client.connect(second, now);
+    let now = LINK.at(now).delay(10).exchange(&mut client, &mut server, vec![syn]).now_ms;
+    client.write(second, &[2; 2000]).unwrap();
+    let segments: Vec<TcpSegment> = client.poll_transmit(usize::MAX).into_iter().map(|(_, segment)| segment).collect();
+    assert_eq!(segments.iter().map(|segment| segment.data.len()).collect::<Vec<_>>(), vec![960, 960, 80]);
+    LINK.at(now).delay(10).exchange(&mut client, &mut server, segments);
+}
+
+#[test]
+fn test_entries_expire_on_virtual_clock() {
+    let config = TcpConfig::default();
+    let (mut client, mut server) = tables(&config);
+    let now = session(&mut client, &mut server, id(SERVER, 40000), 0, 50);
+
+    let before = id(SERVER, 40001);
+    client.connect(before, now + TTL_MS - 1);
+    assert_eq!(client.srtt_ms(before), Some(100));
+
+    // 缓存在第一个连接进入 TimeWait 时记录, 到期之后新连接回到默认值
+    let after = id(SERVER, 40002);
+    client.connect(after, now + TTL_MS);
+    assert_eq!(client.srtt_ms(after), None);
+    assert_eq!(client.rto_ms(after), Some(config.rto_initial_ms));
+    assert!(client.destination_cache().is_empty());
+}
+
+#[test]
+fn test_cache_never_exceeds_capacity() {
+    let config = TcpConfig { destination_cache_size: 2, ..TcpConfig::default() };
+    let mut client = ConnectionTable::new(&config);
+    let mut now = 0;
+    for (i, d_ip) in (0x0a000010..0x0a000016).enumerate() {
+        // 每个对端一个服务器, 各自只有一个地址
+        let mut server = ConnectionTable::new(&config);
+        server.listen(d_ip, 80);
+        let conn = id(d_ip, 40000 + i as u16);
+        let syn = client.connect(conn, now);
+        let synack = server.segment_received(CLIENT, d_ip, &syn, now + 10);
+        let mut acks = vec![];
+        for segment in synack {
+            acks.extend(client.segment_received(d_ip, CLIENT, &segment, now + 20));
+        }
+        client.write(conn, &[0; 100]).unwrap();
+        for (_, segment) in client.poll_transmit(usize::MAX) {
+            acks.push(segment);
+        }
+        let mut replies = vec![];
+        for segment in acks {
+            replies.extend(server.segment_received(CLIENT, d_ip, &segment, now + 30));
+        }
+        replies.extend(server.poll_transmit(usize::MAX).into_iter().map(|(_, segment)| segment));
+        for segment in replies {
+            client.segment_received(d_ip, CLIENT, &segment, now + 40);
+        }
+        client.poll_transmit(usize::MAX); // 一轮结束, 合并的 ACK 交给发送端
+        assert_eq!(client.srtt_ms(conn), Some(20));
+        client.abort(conn, now + 40).unwrap();
+        now += 100;
+        assert_eq!(client.destination_cache().len(), (i + 1).min(2));
+    }
+    assert_eq!(client.destination_cache().capacity(), 2);
+}
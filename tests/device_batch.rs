@@ -0,0 +1,119 @@
+/**
+ * 设备的批量收发: NetworkDevice 的默认批量方法逐帧调用单帧方法, ChannelPair 一次取空队列
+ * InterfaceSet::pump 只走批量路径, 设备没有收下的帧留在接口的发送队列里
+ */
+use std::collections::VecDeque;
+
+use simple_tcp_ip::error::DeviceError;
+use simple_tcp_ip::link::device::{ChannelPair, DeviceCalls, NetworkDevice};
+use simple_tcp_ip::net::interfaces::DeviceIoStats;
+use simple_tcp_ip::net::ipv4::{Ipv4Datagram, Ipv4DatagramBuilder};
+use simple_tcp_ip::net::raw_socket::IpProtocol;
+use simple_tcp_ip::testing::topology::host;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const A_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+const B_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 2];
+
+/**
+ * 只实现单帧方法的设备, 发送队列至多 room 帧
+ */
+struct SingleFrame {
+    rx: VecDeque<Vec<u8>>,
+    tx: Vec<Vec<u8>>,
+    room: usize,
+    calls: DeviceCalls,
+}
+
+impl NetworkDevice for SingleFrame {
+    fn receive(&mut self) -> Result<Option<Vec<u8>>, DeviceError> {
+        self.calls.receive += 1;
+        Ok(self.rx.pop_front())
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), DeviceError> {
+        self.calls.transmit += 1;
+        if self.tx.len() == self.room {
+            return Err(DeviceError::QueueFull);
+        }
+        self.tx.push(frame.to_vec());
+        Ok(())
+    }
+}
+
+fn frames(n: u8) -> Vec<Vec<u8>> {
+    (0..n).map(|i| vec![i; 64]).collect()
+}
+
+fn datagram(s_addr: u32, d_addr: u32, tag: u8) -> Ipv4Datagram {
+    Ipv4DatagramBuilder::new().source(s_addr).destination(d_addr).protocol(IpProtocol::Other(253)).payload(vec![tag; 100]).build().unwrap()
+}
+
+#[test]
+fn test_default_batch_methods_loop_over_single_frames() {
+    let mut device = SingleFrame { rx: frames(5).into(), tx: vec![], room: 2, calls: DeviceCalls::default() };
+    let mut out = vec![vec![0xff]];
+    assert_eq!(device.receive_batch(&mut out, 3), 3);
+    assert_eq!(out, [vec![vec![0xff]], frames(3)].concat());
+    assert_eq!(device.receive_batch(&mut out, 10), 2);
+    assert_eq!(device.receive_batch(&mut out, 10), 0);
+    assert_eq!(device.calls.receive, 3 + 3 + 1);
+
+    let batch = frames(4);
+    let slices: Vec<&[u8]> = batch.iter().map(Vec::as_slice).collect();
+    assert_eq!(device.transmit_batch(&slices), 2);
+    assert_eq!(device.tx, frames(2));
+    assert_eq!(device.calls.transmit, 3); // 第三帧失败之后不再尝试
+}
+
+#[test]
+fn test_channel_pair_batches_preserve_order_and_capacity() {
+    let ChannelPair { mut a, mut b } = ChannelPair::new(4);
+    a.transmit(&[9; 64]).unwrap();
+    let batch = frames(5);
+    let slices: Vec<&[u8]> = batch.iter().map(Vec::as_slice).collect();
+    // 队列里已经有一帧, 只放得下三帧
+    assert_eq!(a.transmit_batch(&slices), 3);
+    assert_eq!(a.transmit(&[9; 64]), Err(DeviceError::QueueFull));
+    assert_eq!(b.pending(), 4);
+
+    let mut out = vec![];
+    assert_eq!(b.receive_batch(&mut out, 2), 2);
+    assert_eq!(b.receive_batch(&mut out, usize::MAX), 2);
+    assert_eq!(out, [vec![vec![9; 64]], frames(3)].concat());
+    assert_eq!(b.receive().unwrap(), None);
+
+    // 另一个方向是独立的队列
+    assert_eq!(b.transmit_batch(&slices[3..]), 2);
+    assert_eq!(a.receive().unwrap(), Some(frames(5)[3].clone()));
+    assert_eq!(b.calls(), DeviceCalls { receive: 1, transmit: 0, receive_batch: 2, transmit_batch: 1 });
+}
+
+#[test]
+fn test_pump_uses_batch_paths() {
+    let (mut a, mut b) = (host(A_MAC, A_IP, None), host(B_MAC, B_IP, None));
+    let ChannelPair { a: mut wire_a, b: mut wire_b } = ChannelPair::new(2);
+    for tag in 0..4 {
+        a.send(datagram(A_IP, B_IP, tag), None, 0).unwrap();
+    }
+    // 等待 ARP 的数据报排队, 只发出一个请求; 应答在第三轮收到, 之后四个数据报排队发出, 链路一次只放得下两帧
+    let mut sent = vec![];
+    for _ in 0..6 {
+        sent.push(a.pump(0, &mut wire_a, 64).1);
+        a.poll(usize::MAX, 0);
+        b.pump(0, &mut wire_b, 64);
+        b.poll(usize::MAX, 0);
+    }
+    assert_eq!(sent, vec![1, 0, 0, 2, 2, 0]);
+    let delivered: Vec<u8> = b.take_delivered().iter().map(|(datagram, _)| datagram.payload()[0]).collect();
+    assert_eq!(delivered, vec![0, 1, 2, 3]);
+
+    assert_eq!(a.io_stats(0), Some(DeviceIoStats { rx_batches: 6, rx_frames: 1, tx_batches: 3, tx_frames: 5 }));
+    assert_eq!(b.io_stats(0).unwrap().rx_frames, 5);
+    assert_eq!(b.io_stats(0).unwrap().tx_frames, 1);
+    for calls in [wire_a.calls(), wire_b.calls()] {
+        assert_eq!((calls.receive, calls.transmit), (0, 0));
+    }
+    assert_eq!(a.io_stats(1), None);
+}
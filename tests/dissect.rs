@@ -0,0 +1,154 @@
+/**
+ * 对 tests/fixtures/handshake.pcap 中的 SYN 帧逐字段展开, 比对完整输出
+ */
+use std::fs::File;
+
+use simple_tcp_ip::link::arp::{ArpPacket, ETHER_TYPE_ARP};
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::net::ipv4::Ipv4DatagramBuilder;
+use simple_tcp_ip::net::raw_socket::IpProtocol;
+use simple_tcp_ip::transport::udp::UdpDatagram;
+use simple_tcp_ip::utils::dissect::{dissect, summary};
+use simple_tcp_ip::utils::wire::WireSerialize;
+use simple_tcp_ip::utils::pcap::PcapReader;
+
+fn syn_frame() -> Vec<u8> {
+    let mut reader = PcapReader::new(File::open("tests/fixtures/handshake.pcap").unwrap()).unwrap();
+    reader.next().unwrap().unwrap().1
+}
+
+#[test]
+fn test_dissect_syn() {
+    let expected = "\
+Ethernet II, 64 bytes
+    Destination: 02:00:00:00:00:01 [02 00 00 00 00 01]
+    Source: 02:00:00:00:00:02 [02 00 00 00 00 02]
+    Type: IPv4 (0x0800) [08 00]
+    FCS: 0x17a60d14 (correct) [17 a6 0d 14]
+Internet Protocol Version 4, Src: 192.168.0.2, Dst: 192.168.0.1
+    Version: 4 [45]
+    Header Length: 20 bytes (5) [45]
+    Type of Service: 0x00 [00]
+    Total Length: 44 [00 2c]
+    Identification: 0x1001 (4097) [10 01]
+    Flags: 0x2 (Don't Fragment) [40]
+    Fragment Offset: 0 [40 00]
+    Time to Live: 64 [40]
+    Protocol: TCP (6) [06]
+    Header Checksum: 0xa977 (correct) [a9 77]
+    Source Address: 192.168.0.2 [c0 a8 00 02]
+    Destination Address: 192.168.0.1 [c0 a8 00 01]
+    [Ethernet padding: 2 bytes]
+Transmission Control Protocol, Src Port: 51000, Dst Port: 80, Seq: 287454020, Ack: 0, Len: 0
+    Source Port: 51000 [c7 38]
+    Destination Port: 80 [00 50]
+    Sequence Number: 287454020 [11 22 33 44]
+    Acknowledgment Number: 0 [00 00 00 00]
+    Header Length: 24 bytes (6) [60]
+    Flags: 0x002 (SYN) [60 02]
+    Window: 64240 [fa f0]
+    Checksum: 0x0ff3 (correct) [0f f3]
+    Urgent Pointer: 0 [00 00]
+    Options: 4 bytes [02 04 05 b4]
+        MSS: 1460 [02 04 05 b4]
+";
+    assert_eq!(dissect(&syn_frame()), expected);
+}
+
+/**
+ * total_len 被改成 30, TCP 层只剩 10 字节; 校验和随之失效
+ */
+#[test]
+fn test_dissect_truncated_segment() {
+    let mut frame = syn_frame();
+    frame[17] = 30;
+    let output = dissect(&frame);
+    assert!(output.contains("    FCS: 0x17a60d14 (incorrect) [17 a6 0d 14]\n"));
+    assert!(output.contains("    Header Checksum: 0xa977 (incorrect) [a9 77]\n"));
+    assert!(output.contains("    [Ethernet padding: 16 bytes]\n"));
+    assert!(output.ends_with("\
+Transmission Control Protocol
+    [Malformed: segment too short: 10 bytes, header needs at least 20]
+"));
+}
+
+#[test]
+fn test_dissect_truncated_frame() {
+    assert_eq!(dissect(&syn_frame()[..40]), "\
+Ethernet II, 40 bytes
+    [Malformed: frame too short: 40 bytes, need at least 64]
+");
+}
+
+const HOST_MAC: [u8; 6] = [2, 0, 0, 0, 0, 2];
+const GW_MAC: [u8; 6] = [2, 0, 0, 0, 0, 1];
+const HOST: u32 = 0xc0a80002;
+const GW: u32 = 0xc0a80001;
+
+#[test]
+fn test_dissect_arp_request() {
+    let arp = ArpPacket::request(HOST_MAC, HOST, GW).serialize();
+    let frame = EthernetFrame::new([0xff; 6], HOST_MAC, ETHER_TYPE_ARP, arp).serialize();
+    let output = dissect(&frame);
+    assert!(output.contains("    Type: ARP (0x0806) [08 06]\n"));
+    assert!(output.ends_with("\
+Address Resolution Protocol (request)
+    Hardware Type: Ethernet (1) [00 01]
+    Protocol Type: IPv4 (0x0800) [08 00]
+    Hardware Size: 6 [06]
+    Protocol Size: 4 [04]
+    Opcode: request (1) [00 01]
+    Sender MAC Address: 02:00:00:00:00:02 [02 00 00 00 00 02]
+    Sender IP Address: 192.168.0.2 [c0 a8 00 02]
+    Target MAC Address: 00:00:00:00:00:00 [00 00 00 00 00 00]
+    Target IP Address: 192.168.0.1 [c0 a8 00 01]
+"), "{}", output);
+
+    // 硬件类型不是以太网
+    let mut arp = ArpPacket::request(HOST_MAC, HOST, GW).serialize();
+    arp[1] = 6;
+    let output = dissect(&EthernetFrame::new([0xff; 6], HOST_MAC, ETHER_TYPE_ARP, arp).serialize());
+    assert!(output.ends_with("\
+Address Resolution Protocol
+    [Malformed: unsupported hardware type 6 (offset 0)]
+"), "{}", output);
+}
+
+fn udp_frame(udp: &UdpDatagram) -> Vec<u8> {
+    let datagram = Ipv4DatagramBuilder::new().source(HOST).destination(GW).protocol(IpProtocol::Udp)
+        .payload(udp.serialize()).build().unwrap();
+    EthernetFrame::new(GW_MAC, HOST_MAC, 0x0800, datagram.serialize()).serialize()
+}
+
+#[test]
+fn test_dissect_udp() {
+    let udp = UdpDatagram::new(40000, 53, b"query".to_vec(), HOST, GW);
+    let output = dissect(&udp_frame(&udp));
+    assert!(output.contains("    Protocol: UDP (17) [11]\n"));
+    assert!(output.ends_with(&format!("\
+User Datagram Protocol, Src Port: 40000, Dst Port: 53
+    Source Port: 40000 [9c 40]
+    Destination Port: 53 [00 35]
+    Length: 13 [00 0d]
+    Checksum: {:#06x} (correct) [{:02x} {:02x}]
+    Payload: 5 bytes [71 75 65 72 79]
+", udp.checksum, udp.checksum >> 8, udp.checksum & 0xff)), "{}", output);
+
+    // 校验和为 0: 发送方没有计算
+    let unchecked = UdpDatagram { checksum: 0, ..udp.clone() };
+    assert!(dissect(&udp_frame(&unchecked)).contains("    Checksum: 0x0000 (none) [00 00]\n"));
+    let corrupted = UdpDatagram { data: b"qverq".to_vec(), ..udp };
+    assert!(dissect(&udp_frame(&corrupted)).contains("(incorrect)"));
+}
+
+#[test]
+fn test_summary_lines() {
+    let reader = PcapReader::new(File::open("tests/fixtures/handshake.pcap").unwrap()).unwrap();
+    let lines: Vec<String> = reader.map(|record| summary(&record.unwrap().1)).collect();
+    assert_eq!(lines, vec![
+        "192.168.0.2 > 192.168.0.1 TCP 51000 > 80 [SYN] seq=287454020 ack=0 win=64240 len=0",
+        "192.168.0.1 > 192.168.0.2 TCP 80 > 51000 [SYN|ACK] seq=2864434397 ack=287454021 win=64240 len=0",
+        "192.168.0.2 > 192.168.0.1 TCP 51000 > 80 [ACK] seq=287454021 ack=2864434398 win=64240 len=0",
+    ]);
+    assert_eq!(summary(&[0u8; 20]), "malformed ethernet: frame too short: 20 bytes, need at least 64");
+}
@@ -0,0 +1,81 @@
+/**
+ * 接收链路上每一处丢弃都能归到一个 DropReason
+ */
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::testing::packet::PacketBuilder;
+use simple_tcp_ip::transport::tcp_segment::{TcpCtrlFlag, TcpSegment};
+use simple_tcp_ip::utils::drops::{DropCounters, DropReason};
+use simple_tcp_ip::utils::wire::WireDeserialize;
+
+const LOCAL_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+const PEER_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+const LOCAL_IP: u32 = 0x0a000001;
+const PEER_IP: u32 = 0x0a000002;
+
+/**
+ * 依次做各层的检查, 返回第一处丢弃的原因
+ */
+fn receive(frame_bytes: &[u8]) -> Result<TcpSegment, DropReason> {
+    let frame = EthernetFrame::deserialize(frame_bytes).map_err(|e| DropReason::from(&e))?;
+    if !frame.check_fcs() {
+        return Err(DropReason::BadFcs);
+    }
+    if frame.d_mac() != LOCAL_MAC {
+        return Err(DropReason::NotForUs);
+    }
+    let datagram = Ipv4Datagram::deserialize(frame.payload()).map_err(|e| DropReason::from(&e))?;
+    if !datagram.check_hdr_checksum() {
+        return Err(DropReason::BadIpChecksum);
+    }
+    if datagram.ttl() == 0 {
+        return Err(DropReason::TtlExpired);
+    }
+    if datagram.d_addr() != LOCAL_IP {
+        return Err(DropReason::NotForUs);
+    }
+    let segment = TcpSegment::deserialize(datagram.payload()).map_err(|e| DropReason::from(&e))?;
+    if !segment.check_checksum(datagram.s_addr(), datagram.d_addr()) {
+        return Err(DropReason::BadTcpChecksum);
+    }
+    Ok(segment)
+}
+
+fn build_frame(d_mac: [u8; 6], ttl: u8, d_addr: u32, corrupt_tcp: bool) -> Vec<u8> {
+    let builder = PacketBuilder::ether(PEER_MAC, d_mac)
+        .ipv4(PEER_IP, d_addr).ttl(ttl).ip_id(7)
+        .tcp(40000, 80).flags(TcpCtrlFlag::SYN as u16).seq(1).window(1024);
+    if corrupt_tcp { builder.corrupt_l4_checksum().build() } else { builder.build() }
+}
+
+#[test]
+fn test_each_discard_site_counts_its_reason() {
+    let mut counters = DropCounters::new();
+    let mut inject = |bytes: &[u8]| {
+        if let Err(reason) = receive(bytes) {
+            counters.record(reason);
+        }
+    };
+
+    // 合法报文不计数
+    inject(&build_frame(LOCAL_MAC, 64, LOCAL_IP, false));
+
+    inject(&[0u8; 20]); // 帧太短
+    inject(&PacketBuilder::ether(PEER_MAC, LOCAL_MAC).ipv4(PEER_IP, LOCAL_IP).tcp(40000, 80).corrupt_fcs().build());
+    inject(&build_frame(PEER_MAC, 64, LOCAL_IP, false)); // MAC 不是本机
+    inject(&build_frame(LOCAL_MAC, 64, 0x0a000009, false)); // IP 不是本机
+
+    // IP 首部校验和错误, FCS 正确
+    inject(&PacketBuilder::ether(PEER_MAC, LOCAL_MAC).ipv4(PEER_IP, LOCAL_IP).corrupt_ip_checksum().tcp(40000, 80).build());
+
+    inject(&build_frame(LOCAL_MAC, 0, LOCAL_IP, false));
+    inject(&build_frame(LOCAL_MAC, 64, LOCAL_IP, true));
+
+    assert_eq!(counters.get(DropReason::ParseError), 1);
+    assert_eq!(counters.get(DropReason::BadFcs), 1);
+    assert_eq!(counters.get(DropReason::NotForUs), 2);
+    assert_eq!(counters.get(DropReason::BadIpChecksum), 1);
+    assert_eq!(counters.get(DropReason::TtlExpired), 1);
+    assert_eq!(counters.get(DropReason::BadTcpChecksum), 1);
+    assert_eq!(counters.total(), 7);
+}
@@ -0,0 +1,105 @@
+/**
+ * 握手 ACK 与第一个数据段被重排: 数据段先到达 SynReceived 的服务器
+ * 数据段的确认号正确时它本身就完成握手; 数据只交付一次, SYN|ACK 不必重传
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_segment::{TcpFlags, TcpSegment};
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const ID: ConnectionId = ConnectionId { s_ip: B_IP, s_port: 80, d_ip: A_IP, d_port: 40000 };
+const PEER_ISN: u32 = 1000;
+
+/**
+ * 测试扮演客户端; 返回停在 SynReceived 的服务器和它 SYN|ACK 的序号
+ */
+fn server(config: &TcpConfig) -> (ConnectionTable, u32) {
+    let mut table = ConnectionTable::new(config);
+    table.listen(B_IP, 80);
+    let syn = TcpSegment::new(40000, 80, PEER_ISN, 0, 5, 0, TcpFlags::SYN, 65535, 0, vec![], vec![]);
+    let syn_ack = table.segment_received(A_IP, B_IP, &syn, 0).pop().unwrap();
+    assert_eq!(table.state(ID), Some(TcpState::SynReceived));
+    (table, syn_ack.seq)
+}
+
+fn segment(seq: u32, ack: u32, flags: TcpFlags, data: Vec<u8>) -> TcpSegment {
+    TcpSegment::new(40000, 80, seq, ack, 5, 0, flags, 65535, 0, vec![], data)
+}
+
+/**
+ * 交给服务器, 返回它的全部回复(立即回复和 poll_transmit 发出的)
+ */
+fn deliver(table: &mut ConnectionTable, segment: &TcpSegment, now_ms: u64) -> Vec<TcpSegment> {
+    let mut replies = table.segment_received(A_IP, B_IP, segment, now_ms);
+    replies.extend(table.poll_transmit(usize::MAX).into_iter().map(|(_, seg)| seg));
+    replies
+}
+
+#[test]
+fn test_data_before_handshake_ack_is_delivered_once() {
+    let (mut table, iss) = server(&TcpConfig::default());
+    let start = PEER_ISN + 1;
+    let data = b"GET / HTTP/1.1\r\n\r\n".to_vec();
+
+    // 数据段先到: 它的 ACK 确认了 SYN|ACK, 握手就此完成
+    let replies = deliver(&mut table, &segment(start, iss + 1, TcpFlags::ACK | TcpFlags::PSH, data.clone()), 10);
+    assert_eq!(table.state(ID), Some(TcpState::Established));
+    assert_eq!(replies.last().unwrap().ack, start + data.len() as u32);
+
+    // 迟到的握手 ACK 是重复的 ACK, 不再交付任何东西
+    deliver(&mut table, &segment(start, iss + 1, TcpFlags::ACK, vec![]), 12);
+    assert_eq!(table.read(ID, usize::MAX).unwrap(), data);
+    assert_eq!(table.read(ID, usize::MAX).unwrap(), Vec::<u8>::new());
+
+    // 过了初始 RTO 也没有重传
+    assert!(table.tick(5000).is_empty());
+    assert_eq!(table.retransmissions(), 0);
+    assert_eq!(table.state(ID), Some(TcpState::Established));
+}
+
+#[test]
+fn test_data_without_ack_is_held_until_handshake_completes() {
+    let (mut table, iss) = server(&TcpConfig::default());
+    let start = PEER_ISN + 1;
+
+    // 不带 ACK 的数据段不能完成握手, 先缓存, 也不回 ACK
+    assert!(deliver(&mut table, &segment(start, 0, TcpFlags::PSH, b"hello ".to_vec()), 10).is_empty());
+    assert!(deliver(&mut table, &segment(start + 6, 0, TcpFlags::PSH, b"world".to_vec()), 11).is_empty());
+    assert_eq!(table.state(ID), Some(TcpState::SynReceived));
+
+    let replies = deliver(&mut table, &segment(start, iss + 1, TcpFlags::ACK, vec![]), 12);
+    assert_eq!(table.state(ID), Some(TcpState::Established));
+    assert_eq!(replies.last().unwrap().ack, start + 11);
+    assert_eq!(table.read(ID, usize::MAX).unwrap(), b"hello world");
+    assert_eq!(table.retransmissions(), 0);
+}
+
+#[test]
+fn test_early_data_bounded_by_window() {
+    let (mut table, iss) = server(&TcpConfig { recv_buffer: 100, ..TcpConfig::default() });
+    let start = PEER_ISN + 1;
+
+    deliver(&mut table, &segment(start, 0, TcpFlags::PSH, vec![1; 80]), 10);
+    deliver(&mut table, &segment(start + 80, 0, TcpFlags::PSH, vec![2; 40]), 11); // 合计超过一个窗口, 丢弃
+    let replies = deliver(&mut table, &segment(start, iss + 1, TcpFlags::ACK, vec![]), 12);
+    assert_eq!(replies.last().unwrap().ack, start + 80);
+    assert_eq!(table.read(ID, usize::MAX).unwrap(), vec![1; 80]);
+}
+
+#[test]
+fn test_unacceptable_ack_in_syn_received_gets_reset() {
+    let (mut table, iss) = server(&TcpConfig::default());
+    let start = PEER_ISN + 1;
+
+    // 确认号不在 (SND.UNA, SND.NXT] 内: 回 RST, 序号取自它的确认号, 连接留在 SynReceived
+    let replies = deliver(&mut table, &segment(start, iss + 1000, TcpFlags::ACK, b"stale".to_vec()), 10);
+    let rst = replies.iter().find(|seg| seg.RST()).unwrap();
+    assert_eq!(rst.seq, iss + 1000);
+    assert_eq!(table.state(ID), Some(TcpState::SynReceived));
+
+    deliver(&mut table, &segment(start, iss + 1, TcpFlags::ACK, vec![]), 20);
+    assert_eq!(table.state(ID), Some(TcpState::Established));
+    assert_eq!(table.read(ID, usize::MAX).unwrap(), Vec::<u8>::new());
+}
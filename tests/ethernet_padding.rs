@@ -0,0 +1,99 @@
+/**
+ * 以太网最小帧的补齐: 复用的缓冲区里补齐部分一定写 0; Strict 模式拒绝不足 46 字节的载荷
+ * 收到的帧分别给出上层数据的实际长度和含补齐的长度
+ */
+use simple_tcp_ip::config::{ArpConfig, Ipv4Config};
+use simple_tcp_ip::error::{DeviceError, StackError};
+use simple_tcp_ip::link::arp::{ArpPacket, ETHER_TYPE_ARP};
+use simple_tcp_ip::link::ethernet::{pad_payload, EthernetFrame, MIN_PAYLOAD_LEN};
+use simple_tcp_ip::link::interface::{EthernetInterface, Padding};
+use simple_tcp_ip::net::interfaces::InterfaceSet;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::testing::packet::PacketBuilder;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+const PEER_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 2];
+const IP: u32 = 0x0a000001;
+const PEER_IP: u32 = 0x0a000002;
+
+fn iface() -> EthernetInterface {
+    EthernetInterface::new(MAC, IP, 24)
+}
+
+#[test]
+fn test_poisoned_pool_buffer_is_zero_padded() {
+    let mut iface = iface();
+    // 缓冲池里的缓冲区上一次装的是一个大帧, 内容还在
+    let mut buf = vec![0xa5; 1518];
+    let big = iface.frame(PEER_MAC, 0x0800, vec![0x5a; 1000]);
+    assert_eq!(iface.transmit_into(&big, &mut buf), Ok(1018));
+
+    let short = iface.frame(PEER_MAC, 0x0800, vec![1, 2, 3]);
+    let len = iface.transmit_into(&short, &mut buf).unwrap();
+    assert_eq!(len, 64);
+    assert_eq!(&buf[14..17], &[1, 2, 3]);
+    assert!(buf[17..60].iter().all(|&b| b == 0));
+    assert_eq!(&buf[..len], &short.serialize()[..]);
+    assert!(EthernetFrame::try_deserialize(&buf[..len]).unwrap().check_fcs());
+    assert_eq!(iface.stats().tx_frames, 2);
+
+    // 缓冲区不够时不写入
+    let mut small = [0xa5; 60];
+    assert!(matches!(iface.transmit_into(&short, &mut small), Err(StackError::Serialize(_))));
+    assert!(small.iter().all(|&b| b == 0xa5));
+}
+
+#[test]
+fn test_strict_mode_rejects_short_payloads() {
+    let mut iface = iface();
+    iface.set_padding(Padding::Strict);
+    let short = iface.frame(PEER_MAC, ETHER_TYPE_ARP, vec![0; 28]);
+    assert_eq!(iface.transmit(&short), Err(DeviceError::ShortPayload { len: 28, min: MIN_PAYLOAD_LEN }));
+    let mut buf = vec![0; 64];
+    assert_eq!(iface.transmit_into(&short, &mut buf),
+               Err(StackError::Device(DeviceError::ShortPayload { len: 28, min: MIN_PAYLOAD_LEN })));
+    assert_eq!(iface.stats().tx_frames, 0);
+
+    // 上层主动补齐之后可以发送
+    let mut payload = vec![0; 28];
+    pad_payload(&mut payload);
+    assert_eq!(payload.len(), MIN_PAYLOAD_LEN);
+    let padded = iface.frame(PEER_MAC, ETHER_TYPE_ARP, payload);
+    assert_eq!(iface.transmit(&padded).unwrap().len(), 64);
+
+    // InterfaceSet 自己补齐, Strict 的接口上照样发出免费 ARP
+    let mut net = InterfaceSet::new(&Ipv4Config::default(), &ArpConfig::default());
+    net.add_interface(iface);
+    net.set_link(0, false, 0);
+    net.set_link(0, true, 10);
+    let frames = net.take_tx(0);
+    assert_eq!(frames.len(), 1);
+    let frame = EthernetFrame::try_deserialize(&frames[0]).unwrap();
+    assert!(ArpPacket::try_deserialize(frame.unpadded_payload()).unwrap().is_gratuitous());
+}
+
+#[test]
+fn test_payload_length_bookkeeping() {
+    // 40 字节的纯 ACK 补齐到 46 字节
+    let ack = PacketBuilder::ether(PEER_MAC, MAC).ipv4(PEER_IP, IP).tcp(80, 40000).build();
+    let frame = iface().receive(&ack).unwrap();
+    assert_eq!((frame.payload().len(), frame.payload_len(), frame.padded_len()), (46, 40, 46));
+    let datagram = Ipv4Datagram::try_deserialize(frame.unpadded_payload()).unwrap();
+    assert_eq!(datagram.serialize(), frame.unpadded_payload());
+
+    // ARP 固定 28 字节
+    let arp = EthernetFrame::new(MAC, PEER_MAC, ETHER_TYPE_ARP, ArpPacket::request(PEER_MAC, PEER_IP, IP).serialize());
+    let received = EthernetFrame::try_deserialize(&arp.serialize()).unwrap();
+    assert_eq!((received.payload_len(), received.padded_len()), (28, 46));
+
+    // 不是最小帧就没有补齐
+    let data = PacketBuilder::ether(PEER_MAC, MAC).ipv4(PEER_IP, IP).tcp(80, 40000).payload(&[7; 100]).build();
+    let frame = EthernetFrame::from_frame_vec(data).unwrap();
+    assert_eq!((frame.payload_len(), frame.padded_len()), (140, 140));
+    assert_eq!(frame.unpadded_payload(), frame.payload());
+
+    // 自己构造的帧记录的是给出的载荷长度
+    let built = iface().frame(PEER_MAC, 0x0800, vec![9; 10]);
+    assert_eq!((built.payload_len(), built.padded_len()), (10, 46));
+}
@@ -0,0 +1,117 @@
+/**
+ * TCP Fast Open (RFC 7413) 经过两张连接表: 第一次连接请求 cookie, 第二次带着 cookie 在 SYN 中捎带数据,
+ * 服务端在握手 ACK 到达之前就读到请求并回复; 服务端换了密钥时 SYN 中的数据退回握手之后发送
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::transport::connection_table::{listener_id, ConnectionTable};
+use simple_tcp_ip::transport::fast_open::{fast_open_cookie, TfoStats, TFO_COOKIE_LEN};
+use simple_tcp_ip::transport::socket_options::ListenOptions;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const REQUEST: &[u8] = b"GET / HTTP/1.0\r\n\r\n";
+const RESPONSE: &[u8] = b"HTTP/1.0 200 OK\r\n\r\n";
+
+fn id(port: u16) -> ConnectionId {
+    ConnectionId { s_ip: A_IP, s_port: port, d_ip: B_IP, d_port: 80 }
+}
+
+fn fast_open_server() -> ConnectionTable {
+    let mut server = ConnectionTable::new(&TcpConfig::default());
+    server.listen_with_options(B_IP, 80, ListenOptions { fast_open: true });
+    server
+}
+
+fn transmit(table: &mut ConnectionTable) -> Vec<TcpSegment> {
+    table.poll_transmit(usize::MAX).into_iter().map(|(_, segment)| segment).collect()
+}
+
+/**
+ * 来回投递, 直到两边都没有要发的段
+ */
+fn exchange(client: &mut ConnectionTable, server: &mut ConnectionTable, mut to_server: Vec<TcpSegment>, now_ms: u64) {
+    loop {
+        let mut to_client: Vec<TcpSegment> = to_server.iter().flat_map(|segment| server.segment_received(A_IP, B_IP, segment, now_ms)).collect();
+        to_client.extend(transmit(server));
+        to_server = to_client.iter().flat_map(|segment| client.segment_received(B_IP, A_IP, segment, now_ms)).collect();
+        to_server.extend(transmit(client));
+        if to_server.is_empty() {
+            return;
+        }
+    }
+}
+
+#[test]
+fn test_cookie_request_then_data_on_syn() {
+    let mut client = ConnectionTable::new(&TcpConfig::default());
+    let mut server = fast_open_server();
+    let listener = listener_id(B_IP, 80);
+
+    // 第一次连接: SYN 只请求 cookie, 请求在握手之后照常发出
+    let syn = client.connect_fast_open(id(40000), REQUEST, 0).unwrap();
+    assert_eq!(fast_open_cookie(&syn), Some(vec![]));
+    assert!(syn.data.is_empty());
+    let syn_ack = server.segment_received(A_IP, B_IP, &syn, 1).pop().unwrap();
+    let cookie = fast_open_cookie(&syn_ack).unwrap();
+    assert_eq!(cookie.len(), TFO_COOKIE_LEN);
+    let ack = client.segment_received(B_IP, A_IP, &syn_ack, 2);
+    exchange(&mut client, &mut server, ack, 3);
+    let first = server.accept(listener).unwrap();
+    assert_eq!(server.read(first, usize::MAX).unwrap(), REQUEST);
+
+    // 第二次连接: SYN 带着 cookie 捎带请求
+    let syn = client.connect_fast_open(id(40001), REQUEST, 10).unwrap();
+    assert_eq!(fast_open_cookie(&syn), Some(cookie));
+    assert_eq!(syn.data, REQUEST);
+    let syn_ack = server.segment_received(A_IP, B_IP, &syn, 11).pop().unwrap();
+    assert_eq!(syn_ack.ack, syn.seq.wrapping_add(1 + REQUEST.len() as u32));
+
+    // 握手 ACK 还没有到达, 服务端已经可以 accept、读到请求并回复
+    let conn = server.accept(listener).unwrap();
+    assert_eq!(conn, id(40001).reversed());
+    assert_eq!(server.state(conn), Some(TcpState::SynReceived));
+    assert_eq!(server.read(conn, usize::MAX).unwrap(), REQUEST);
+    server.write(conn, RESPONSE).unwrap();
+    let response = transmit(&mut server);
+    assert_eq!(response.len(), 1);
+    assert_eq!(response[0].data, RESPONSE);
+
+    let mut to_server = client.segment_received(B_IP, A_IP, &syn_ack, 12);
+    to_server.extend(client.segment_received(B_IP, A_IP, &response[0], 12));
+    exchange(&mut client, &mut server, to_server, 13);
+    assert_eq!(client.read(id(40001), usize::MAX).unwrap(), RESPONSE);
+    assert_eq!(client.send_queued(id(40001)), Some(0));
+    assert_eq!(server.state(conn), Some(TcpState::Established));
+    assert_eq!(server.tfo_stats(listener), Some(TfoStats { cookies_issued: 1, accepted: 1, rejected: 0 }));
+}
+
+#[test]
+fn test_rejected_cookie_sends_data_after_handshake() {
+    let mut client = ConnectionTable::new(&TcpConfig::default());
+    let mut old = fast_open_server();
+    let syn = client.connect_fast_open(id(40000), b"", 0).unwrap();
+    let syn_ack = old.segment_received(A_IP, B_IP, &syn, 1).pop().unwrap();
+    client.segment_received(B_IP, A_IP, &syn_ack, 2);
+
+    // 服务端重启换了密钥: cookie 无效, SYN|ACK 只确认 SYN 并签发新 cookie
+    let mut server = fast_open_server();
+    let syn = client.connect_fast_open(id(40001), REQUEST, 10).unwrap();
+    assert_eq!(syn.data, REQUEST);
+    let syn_ack = server.segment_received(A_IP, B_IP, &syn, 11).pop().unwrap();
+    assert_eq!(syn_ack.ack, syn.seq.wrapping_add(1));
+    assert_eq!(server.accept(listener_id(B_IP, 80)), None);
+
+    let ack = client.segment_received(B_IP, A_IP, &syn_ack, 12);
+    assert_eq!(client.state(id(40001)), Some(TcpState::Established));
+    exchange(&mut client, &mut server, ack, 13);
+    let conn = server.accept(listener_id(B_IP, 80)).unwrap();
+    assert_eq!(server.read(conn, usize::MAX).unwrap(), REQUEST);
+    assert_eq!(client.send_queued(id(40001)), Some(0));
+    assert_eq!(server.tfo_stats(listener_id(B_IP, 80)), Some(TfoStats { cookies_issued: 1, accepted: 0, rejected: 1 }));
+
+    // 新 cookie 替换了缓存中的旧 cookie
+    let syn = client.connect_fast_open(id(40002), REQUEST, 20).unwrap();
+    assert_eq!(fast_open_cookie(&syn), fast_open_cookie(&syn_ack));
+}
@@ -0,0 +1,60 @@
+/**
+ * 长度与 IP total_len 不一致的帧在接口上丢弃, 不会交给上层
+ */
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::link::interface::EthernetInterface;
+use simple_tcp_ip::testing::packet::PacketBuilder;
+use simple_tcp_ip::transport::tcp_segment::TcpFlags;
+use simple_tcp_ip::utils::drops::DropReason;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const LOCAL_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+const PEER_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+
+fn segment(payload_len: usize) -> Vec<u8> {
+    PacketBuilder::new().ipv4(0x0a000002, 0x0a000001).tcp(51000, 80).flags(TcpFlags::ACK).payload(&vec![7; payload_len]).build()
+}
+
+/**
+ * 以太网载荷原样封装, FCS 按实际载荷计算, 只有长度不一致
+ */
+fn frame(ip_bytes: Vec<u8>) -> Vec<u8> {
+    EthernetFrame::new(LOCAL_MAC, PEER_MAC, 0x0800, ip_bytes).serialize()
+}
+
+#[test]
+fn test_truncated_and_giant_frames_are_counted() {
+    let mut iface = EthernetInterface::new(LOCAL_MAC, 0x0a000001, 24);
+    let mut delivered = 0;
+    let receive = |iface: &mut EthernetInterface, bytes: Vec<u8>| iface.receive(&bytes).is_some() as usize;
+
+    delivered += receive(&mut iface, frame(segment(100)));
+
+    // 截断: 设备只交上来前 80 字节, total_len 仍是 140
+    let mut truncated = segment(100);
+    truncated.truncate(80);
+    delivered += receive(&mut iface, frame(truncated));
+
+    // giant: 1600 字节的载荷超过 1500 的 MTU
+    delivered += receive(&mut iface, frame(segment(1560)));
+
+    assert_eq!(delivered, 1);
+    assert_eq!(iface.drop_counters().get(DropReason::RxTruncated), 1);
+    assert_eq!(iface.drop_counters().get(DropReason::RxGiant), 1);
+    assert_eq!(iface.drop_counters().total(), 2);
+
+    // 打开 jumbo 后同一帧可以通过
+    iface.set_jumbo(true);
+    delivered += receive(&mut iface, frame(segment(1560)));
+    assert_eq!(delivered, 2);
+    assert_eq!(iface.drop_counters().get(DropReason::RxGiant), 1);
+}
+
+#[test]
+fn test_padding_is_not_truncation() {
+    let mut iface = EthernetInterface::new(LOCAL_MAC, 0x0a000001, 24);
+    // 40 字节的纯 ACK 补齐到 46 字节
+    let bytes = PacketBuilder::ether(PEER_MAC, LOCAL_MAC).ipv4(0x0a000002, 0x0a000001).tcp(51000, 80).flags(TcpFlags::ACK).build();
+    assert!(iface.receive(&bytes).is_some());
+    assert_eq!(iface.drop_counters().total(), 0);
+}
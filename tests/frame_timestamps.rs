@@ -0,0 +1,131 @@
+/**
+ * 帧时间戳: 收发两端共享一个 ManualClock, 在流水线的各个阶段之间手动推进时间
+ * 断言记录下来的栈内处理时间和链路层 RTT 恰好是推进的量
+ * 测试内的粘合代码把报文段封装成帧、经接口收发, 接口把收发的每一帧交给装在它上面的 TraceSink
+ */
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use simple_tcp_ip::config::{Ipv4Config, TcpConfig};
+use simple_tcp_ip::link::interface::EthernetInterface;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+use simple_tcp_ip::utils::clock::{Clock, ManualClock};
+use simple_tcp_ip::utils::trace::WriterTraceSink;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const A_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+const B_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 2];
+
+const WIRE_MS: u64 = 5;   // 帧在线路上的时间
+const PARSE_MS: u64 = 2;  // 从设备取出到连接处理完
+
+struct Node {
+    iface: EthernetInterface,
+    table: ConnectionTable,
+    trace: Rc<RefCell<WriterTraceSink<Vec<u8>>>>,
+}
+
+impl Node {
+    fn new(mac: [u8; 6], ip: u32, id: usize) -> Self {
+        let mut iface = EthernetInterface::new(mac, ip, 24);
+        iface.set_id(id);
+        let trace = Rc::new(RefCell::new(WriterTraceSink::new(Vec::new())));
+        iface.set_trace_sink(Some(trace.clone()));
+        Node { iface, table: ConnectionTable::new(&TcpConfig::default()), trace }
+    }
+
+    fn transmit(&mut self, id: ConnectionId, segment: &TcpSegment, d_mac: [u8; 6], clock: &ManualClock) -> Vec<u8> {
+        let datagram = self.table.datagram(id, segment, &Ipv4Config::default()).unwrap();
+        let frame = self.iface.frame(d_mac, 0x0800, datagram.serialize());
+        let (bytes, tx_ms) = self.iface.transmit_stamped(&frame, clock).unwrap();
+        self.table.frame_transmitted(id, segment, tx_ms);
+        bytes
+    }
+
+    /**
+     * 取出一帧, 解析耗时 PARSE_MS 后交给连接表, 返回立即应答的报文段
+     */
+    fn receive(&mut self, bytes: &[u8], clock: &ManualClock) -> (ConnectionId, Vec<TcpSegment>) {
+        let (frame, meta) = self.iface.receive_stamped(bytes, clock).unwrap();
+        clock.advance(PARSE_MS);
+        let datagram = Ipv4Datagram::try_deserialize(frame.payload()).unwrap();
+        let segment = TcpSegment::try_deserialize(datagram.payload()).unwrap();
+        let replies = self.table.segment_received_with_meta(datagram.s_addr(), datagram.d_addr(), &segment, &meta, clock.now_ms());
+        (ConnectionId::for_incoming(datagram.s_addr(), datagram.d_addr(), &segment), replies)
+    }
+
+    fn trace_lines(&self) -> Vec<String> {
+        let trace = std::mem::replace(&mut *self.trace.borrow_mut(), WriterTraceSink::new(Vec::new()));
+        String::from_utf8(trace.into_inner()).unwrap().lines().map(str::to_string).collect()
+    }
+}
+
+#[test]
+fn test_pipeline_deltas() {
+    let clock = ManualClock::new(100);
+    let mut a = Node::new(A_MAC, A_IP, 0);
+    let mut b = Node::new(B_MAC, B_IP, 3);
+    let id = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+    b.table.listen(B_IP, 80);
+
+    // t=100 发出 SYN, t=105 到达 B, t=107 B 回 SYN-ACK, t=112 到达 A, t=114 A 处理完
+    let syn = a.table.connect(id, clock.now_ms());
+    let bytes = a.transmit(id, &syn, B_MAC, &clock);
+    clock.advance(WIRE_MS);
+    let (peer, replies) = b.receive(&bytes, &clock);
+    let bytes = b.transmit(peer, &replies[0], A_MAC, &clock);
+    clock.advance(WIRE_MS);
+    let (_, replies) = a.receive(&bytes, &clock);
+    assert_eq!(a.table.state(id), Some(TcpState::Established));
+    let ack = a.transmit(id, &replies[0], B_MAC, &clock);
+    clock.advance(WIRE_MS);
+    b.receive(&ack, &clock);
+    assert_eq!(b.table.state(peer), Some(TcpState::Established));
+
+    let latency = a.table.latency(id).unwrap();
+    assert_eq!(latency.link_rtt().count(), 1);
+    assert_eq!(latency.link_rtt().max_ms(), 2 * WIRE_MS + PARSE_MS); // 112 - 100
+    assert_eq!(latency.processing().max_ms(), PARSE_MS);
+
+    // 数据段在 A 的发送路径上停留 3ms 才交给设备, 这段时间不算在链路 RTT 里
+    a.table.write(id, b"hello").unwrap();
+    let (_, segment) = a.table.poll_transmit(1).pop().unwrap();
+    clock.advance(3);
+    let sent_at = clock.now_ms();
+    let bytes = a.transmit(id, &segment, B_MAC, &clock);
+    clock.advance(WIRE_MS);
+    let (_, replies) = b.receive(&bytes, &clock);
+    let bytes = b.transmit(peer, &replies[0], A_MAC, &clock);
+    clock.advance(WIRE_MS);
+    let acked_at = clock.now_ms();
+    a.receive(&bytes, &clock);
+
+    let latency = a.table.latency(id).unwrap();
+    assert_eq!(latency.link_rtt().count(), 2);
+    assert_eq!(latency.link_rtt().max_ms(), acked_at - sent_at);
+    assert_eq!(latency.processing().count(), 2);
+    assert_eq!(latency.processing().mean_ms(), Some(PARSE_MS));
+    assert_eq!(b.table.latency(peer).unwrap().processing().count(), 3); // SYN、握手 ACK 和数据段
+
+    // 重传的段按 Karn 算法不取样
+    a.table.write(id, b"again").unwrap();
+    let (_, segment) = a.table.poll_transmit(1).pop().unwrap();
+    a.transmit(id, &segment, B_MAC, &clock);
+    clock.advance(50);
+    let bytes = a.transmit(id, &segment, B_MAC, &clock);
+    clock.advance(WIRE_MS);
+    let (_, replies) = b.receive(&bytes, &clock);
+    let bytes = b.transmit(peer, &replies[0], A_MAC, &clock);
+    clock.advance(WIRE_MS);
+    a.receive(&bytes, &clock);
+    assert_eq!(a.table.latency(id).unwrap().link_rtt().count(), 2);
+
+    let lines = b.trace_lines();
+    assert!(lines[0].starts_with("frame rx ") && lines[0].ends_with(" if=3 t=105"), "{}", lines[0]);
+    assert!(lines[1].starts_with("frame tx ") && lines[1].ends_with(" t=107"), "{}", lines[1]);
+}
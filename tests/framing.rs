@@ -0,0 +1,141 @@
+/**
+ * 长度前缀分帧跑在协议栈的 Stream 上: 消息跨越许多段和多轮发送缓冲区,
+ * 接收端按就绪事件累积字节, 恶意的超长长度前缀被拒绝而连接保持可用
+ */
+use simple_tcp_ip::app::framing::{FrameCodec, FramedStream, FramingError};
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::testing::pair::TablePair;
+use simple_tcp_ip::transport::connection_table::{ConnectionTable, Readiness};
+use simple_tcp_ip::transport::tcp_connection::ConnectionId;
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+
+const CLIENT: u32 = 0x0a000001;
+const SERVER: u32 = 0x0a000002;
+const LINK: TablePair = TablePair::new(CLIENT, SERVER);
+const ID: ConnectionId = ConnectionId { s_ip: CLIENT, s_port: 40000, d_ip: SERVER, d_port: 80 };
+
+fn connected() -> (ConnectionTable, ConnectionTable) {
+    let config = TcpConfig { mss: 536, send_buffer: 8 * 1024, ..TcpConfig::default() };
+    let mut client = ConnectionTable::new(&config);
+    let mut server = ConnectionTable::new(&config);
+    server.listen(SERVER, 80);
+    let syn = client.connect(ID, 0);
+    LINK.exchange(&mut client, &mut server, vec![syn]);
+    (client, server)
+}
+
+/**
+ * 双方都没有要发的段为止
+ */
+fn pump(client: &mut ConnectionTable, server: &mut ConnectionTable) {
+    loop {
+        let to_server: Vec<TcpSegment> = client.poll_transmit(usize::MAX).into_iter().map(|(_, segment)| segment).collect();
+        let to_client: Vec<TcpSegment> = server.poll_transmit(usize::MAX).into_iter().map(|(_, segment)| segment).collect();
+        if to_server.is_empty() && to_client.is_empty() {
+            return;
+        }
+        let replies: Vec<TcpSegment> = to_client.iter().flat_map(|segment| client.segment_received(SERVER, CLIENT, segment, 0)).collect();
+        LINK.exchange(client, server, [to_server, replies].concat());
+    }
+}
+
+/**
+ * 按就绪事件把服务端已到达的字节交给分帧器, 取出其中完整的消息
+ */
+fn drain_ready(server: &mut ConnectionTable, codec: &mut FrameCodec) -> Vec<Result<Vec<u8>, FramingError>> {
+    let mut messages = vec![];
+    while server.readiness_of(ID.reversed()).contains(Readiness::READABLE) && !codec.at_eof() {
+        codec.fill_from(&mut server.stream(ID.reversed())).unwrap();
+    }
+    loop {
+        match codec.decode() {
+            Ok(Some(msg)) => messages.push(Ok(msg)),
+            Ok(None) => return messages,
+            Err(e) => messages.push(Err(e)),
+        }
+    }
+}
+
+#[test]
+fn test_messages_spanning_many_segments() {
+    let (mut client, mut server) = connected();
+    let big: Vec<u8> = (0..40_000u32).map(|i| (i % 251) as u8).collect();
+
+    // 40 KB 的消息放不进 8 KB 的发送缓冲区: 分帧器在两次借用 Stream 之间保留没写出的部分
+    let mut sender = FrameCodec::default();
+    sender.encode(b"first").unwrap();
+    sender.encode(&[]).unwrap();
+    sender.encode(&big).unwrap();
+    sender.encode(b"last").unwrap();
+
+    let mut receiver = FrameCodec::default();
+    let mut received = vec![];
+    let mut rounds = 0;
+    loop {
+        let pending = sender.flush_to(&mut client.stream(ID)).unwrap();
+        pump(&mut client, &mut server);
+        received.extend(drain_ready(&mut server, &mut receiver));
+        rounds += 1;
+        if pending == 0 {
+            break;
+        }
+    }
+    assert!(rounds > 4);
+    assert_eq!(received, vec![Ok(b"first".to_vec()), Ok(vec![]), Ok(big), Ok(b"last".to_vec())]);
+
+    // 对端在消息边界上关闭: FramedStream 读到 None
+    client.close(ID, 0).unwrap();
+    pump(&mut client, &mut server);
+    let mut framed = FramedStream::from_parts(server.stream(ID.reversed()), receiver);
+    assert_eq!(framed.recv_msg(), Ok(None));
+}
+
+#[test]
+fn test_would_block_keeps_partial_message() {
+    let (mut client, mut server) = connected();
+    client.write(ID, &[0, 0, 0, 3, b'a']).unwrap();
+    pump(&mut client, &mut server);
+
+    let mut framed = FramedStream::new(server.stream(ID.reversed()));
+    assert_eq!(framed.recv_msg(), Err(FramingError::Io(std::io::ErrorKind::WouldBlock)));
+    let (_, codec) = framed.into_parts();
+    assert_eq!(codec.buffered(), 5);
+
+    client.write(ID, b"bc").unwrap();
+    pump(&mut client, &mut server);
+    let mut framed = FramedStream::from_parts(server.stream(ID.reversed()), codec);
+    assert_eq!(framed.recv_msg(), Ok(Some(b"abc".to_vec())));
+
+    // 消息中间 EOF 是错误
+    client.write(ID, &[0, 0, 0, 9, b'x']).unwrap();
+    client.close(ID, 0).unwrap();
+    pump(&mut client, &mut server);
+    let mut framed = FramedStream::new(server.stream(ID.reversed()));
+    assert_eq!(framed.recv_msg(), Err(FramingError::UnexpectedEof { expected: 13, received: 5 }));
+}
+
+#[test]
+fn test_malicious_length_prefix() {
+    let (mut client, mut server) = connected();
+    let mut receiver = FrameCodec::new(64 * 1024);
+
+    // 声称 2 GB 的长度前缀: 立即拒绝, 不为它分配缓冲区
+    client.write(ID, &[0x80, 0, 0, 0]).unwrap();
+    client.write(ID, &[0xee; 3000]).unwrap();
+    pump(&mut client, &mut server);
+    let received = drain_ready(&mut server, &mut receiver);
+    assert_eq!(received, vec![Err(FramingError::TooLarge { len: 1 << 31, max: 64 * 1024 })]);
+    assert_eq!(receiver.buffered(), 0);
+
+    // 之后到达的内容继续被丢弃, 连接两个方向都还能用
+    client.write(ID, &[0xee; 3000]).unwrap();
+    pump(&mut client, &mut server);
+    assert!(drain_ready(&mut server, &mut receiver).is_empty());
+    assert_eq!(receiver.buffered(), 0);
+
+    let mut reply = FramedStream::new(server.stream(ID.reversed()));
+    reply.send_msg(b"too large").unwrap();
+    pump(&mut client, &mut server);
+    let mut framed = FramedStream::new(client.stream(ID));
+    assert_eq!(framed.recv_msg(), Ok(Some(b"too large".to_vec())));
+}
@@ -0,0 +1,152 @@
+/**
+ * 线路格式的黄金样本: tests/fixtures 下 .hex 样本中的帧解析出确定的字段值, 再序列化回逐字节相同的原始帧
+ * 样本由 Linux 内核在两个网络命名空间之间收发、在网卡之前抓取, 不含 FCS, 用 fixtures::wire_frame 补上
+ */
+use simple_tcp_ip::config::Ipv4Config;
+use simple_tcp_ip::link::arp::{ArpPacket, ETHER_TYPE_ARP, OP_REPLY, OP_REQUEST};
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::link::vlan::{VlanTag, ETHER_TYPE_VLAN};
+use simple_tcp_ip::net::icmp_v4::IcmpV4;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::net::reassembly::Ipv4Reassembler;
+use simple_tcp_ip::testing::fixtures;
+use simple_tcp_ip::transport::tcp_option::TcpOption;
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+use simple_tcp_ip::transport::udp::UdpDatagram;
+use simple_tcp_ip::utils::wire::{ParseStrictness, WireSerialize};
+
+const A_MAC: [u8; 6] = [0x02, 0, 0, 0, 0xd4, 0x01];
+const B_MAC: [u8; 6] = [0x02, 0, 0, 0, 0xd4, 0x02];
+const A: u32 = 0x0ad40001; // 10.212.0.1, 发起一端
+const B: u32 = 0x0ad40002; // 10.212.0.2
+
+/**
+ * 补上 FCS 后解析以太网帧, 确认序列化后与线路字节完全相同, 并且开头就是抓到的字节
+ */
+fn frame(captured: &[u8]) -> EthernetFrame {
+    let wire = fixtures::wire_frame(captured);
+    let frame = EthernetFrame::try_deserialize(&wire).unwrap();
+    assert!(frame.check_fcs());
+    let bytes = frame.serialize();
+    assert_eq!(bytes, wire);
+    assert_eq!(bytes[..captured.len()], captured[..]);
+    frame
+}
+
+/**
+ * 以 Loose 模式解析 IPv4 数据报, 确认首部校验和正确并且序列化后与载荷中 total_len 范围内的字节相同
+ */
+fn datagram(payload: &[u8]) -> Ipv4Datagram {
+    let datagram = Ipv4Datagram::try_deserialize_with(payload, ParseStrictness::Loose).unwrap();
+    assert!(datagram.check_hdr_checksum());
+    let bytes = datagram.serialize();
+    assert_eq!(bytes, payload[..bytes.len()]);
+    datagram
+}
+
+#[test]
+fn test_arp_request() {
+    let frames = fixtures::load("arp_request").unwrap();
+    assert_eq!(frames.len(), 2);
+    let eth = frame(&frames[0]);
+    assert_eq!((eth.d_mac(), eth.s_mac(), eth.ether_type()), ([0xff; 6], A_MAC, ETHER_TYPE_ARP));
+    assert_eq!(eth.payload_len(), 28);
+
+    let arp = ArpPacket::try_deserialize(eth.payload()).unwrap();
+    assert_eq!(arp, ArpPacket { op: OP_REQUEST, s_mac: A_MAC, s_ip: A, t_mac: [0; 6], t_ip: B });
+    assert_eq!(arp.serialize(), eth.unpadded_payload());
+    assert_eq!(ArpPacket::request(A_MAC, A, B), arp);
+
+    // 对端内核的应答与我们构造的应答逐字节相同
+    let eth = frame(&frames[1]);
+    assert_eq!((eth.d_mac(), eth.s_mac()), (A_MAC, B_MAC));
+    let reply = ArpPacket::try_deserialize(eth.payload()).unwrap();
+    assert_eq!(reply, ArpPacket { op: OP_REPLY, s_mac: B_MAC, s_ip: B, t_mac: A_MAC, t_ip: A });
+    assert_eq!(ArpPacket::reply_to(&arp, B_MAC).serialize(), eth.unpadded_payload());
+}
+
+#[test]
+fn test_icmp_echo_pair() {
+    let frames = fixtures::load("icmp_echo").unwrap();
+    assert_eq!(frames.len(), 2);
+    let mut icmps = vec![];
+    // 原始套接字发出的请求带 DF, 内核的应答不带
+    for (bytes, (s_addr, d_addr, id, df)) in frames.iter().zip([(A, B, 0x8a28, true), (B, A, 0xf671, false)]) {
+        let ip = datagram(frame(bytes).payload());
+        assert_eq!((ip.s_addr(), ip.d_addr(), ip.ttl(), ip.id(), ip.protocol()), (s_addr, d_addr, 64, id, 1));
+        assert_eq!(ip.dont_fragment(), df);
+        assert!(!ip.is_fragment());
+        assert!(IcmpV4::check(ip.payload()));
+        let icmp = IcmpV4::try_deserialize(ip.payload()).unwrap();
+        assert_eq!(icmp.serialize(), ip.payload());
+        icmps.push(icmp);
+    }
+    assert_eq!((icmps[0].icmp_type(), icmps[1].icmp_type()), (8, 0));
+    assert_eq!(&icmps[0].data()[..4], &[0x1f, 0x2e, 0x00, 0x01]);
+    assert_eq!(&icmps[0].data()[4..], b"abcdefghijklmnopqrstuvwabcdefghi");
+    assert_eq!(icmps[0].data(), icmps[1].data());
+    // 重新构造得到相同的校验和
+    assert_eq!(IcmpV4::new(0, 0, icmps[0].data().to_vec()).serialize(), icmps[1].serialize());
+}
+
+#[test]
+fn test_tcp_syn_with_options() {
+    let frames = fixtures::load("tcp_syn").unwrap();
+    let ip = datagram(frame(&frames[0]).payload());
+    assert_eq!((ip.s_addr(), ip.d_addr(), ip.id(), ip.protocol()), (A, B, 0x7e38, 6));
+    assert!(ip.dont_fragment());
+
+    let syn = TcpSegment::try_deserialize_with(ip.payload(), ParseStrictness::Loose).unwrap();
+    assert!(syn.check_checksum(A, B));
+    assert_eq!((syn.s_port, syn.d_port, syn.seq, syn.ack, syn.hl, syn.win_size), (51724, 443, 0x9966d3f3, 0, 10, 64240));
+    assert!(syn.SYN() && !syn.ACK());
+    assert_eq!(syn.parsed_options().unwrap(), vec![
+        TcpOption::Mss(1460),
+        TcpOption::SackPermitted,
+        TcpOption::Timestamps { val: 0x3886e4ed, ecr: 0 },
+        TcpOption::Nop,
+        TcpOption::WindowScale(10),
+    ]);
+    assert_eq!(syn.serialize(), ip.payload());
+}
+
+#[test]
+fn test_fragmented_udp() {
+    let frames = fixtures::load("udp_fragments").unwrap();
+    let mut reassembler = Ipv4Reassembler::new(&Ipv4Config::default());
+    let mut whole = None;
+    for (bytes, (offset, more, len)) in frames.iter().zip([(0, true, 48), (48, false, 20)]) {
+        let ip = datagram(frame(bytes).payload());
+        assert_eq!((ip.id(), ip.fragment_offset(), ip.more_fragments(), ip.payload().len()), (0xf9cd, offset, more, len));
+        whole = reassembler.push(ip, 0);
+    }
+    let whole = whole.unwrap();
+    let udp = UdpDatagram::try_deserialize(whole.payload()).unwrap();
+    assert!(udp.check_checksum(A, B));
+    assert_eq!((udp.s_port, udp.d_port), (5353, 9999));
+    assert_eq!(udp.data, (0x41..0x41 + 60).collect::<Vec<u8>>());
+    assert_eq!(udp.serialize(), whole.payload());
+
+    // 按同样的 MTU 68 重新分片, 得到与内核发出的相同的两片
+    let refragmented = whole.fragment(68).unwrap();
+    assert_eq!(refragmented.len(), 2);
+    for (fragment, bytes) in refragmented.iter().zip(&frames) {
+        assert_eq!(fragment.serialize(), bytes[14..]);
+    }
+}
+
+#[test]
+fn test_vlan_tagged_frame() {
+    let frames = fixtures::load("vlan_udp").unwrap();
+    let eth = frame(&frames[0]);
+    assert_eq!((eth.d_mac(), eth.s_mac(), eth.ether_type()), (B_MAC, A_MAC, ETHER_TYPE_VLAN));
+    let tag = VlanTag::try_deserialize(eth.payload()).unwrap();
+    assert_eq!(tag, VlanTag { pcp: 5, dei: false, vid: 100, ether_type: 0x0800 });
+    assert_eq!(tag.serialize(), eth.payload()[..4]);
+
+    let ip = datagram(&eth.payload()[4..]);
+    let udp = UdpDatagram::try_deserialize(ip.payload()).unwrap();
+    assert!(udp.check_checksum(A, B));
+    assert_eq!((udp.s_port, udp.d_port, &udp.data[..]), (40000, 53, &b"vlan"[..]));
+    assert_eq!(UdpDatagram::new(40000, 53, b"vlan".to_vec(), A, B), udp);
+}
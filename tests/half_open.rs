@@ -0,0 +1,101 @@
+/**
+ * 半开连接: 一端重启后丢掉了所有连接状态, 另一端仍然认为连接处于 Established
+ * 对端继续发数据时得到 RST; 重启的一端在同一四元组上重新连接时, 对端的 challenge ACK 引出 RST 清掉旧连接
+ * 两种情况最后都能在同样的端口上建立新连接
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::testing::pair::TablePair;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionError, ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+
+const A: u32 = 0x0a000001;
+const B: u32 = 0x0a000002;
+
+fn transmit(table: &mut ConnectionTable) -> Vec<TcpSegment> {
+    table.poll_transmit(64).into_iter().map(|(_, segment)| segment).collect()
+}
+
+#[test]
+fn test_b_restarts_mid_transfer() {
+    let config = TcpConfig::default();
+    let mut a = ConnectionTable::new(&config);
+    let mut b = ConnectionTable::new(&config);
+    b.listen(B, 80);
+    let id = ConnectionId { s_ip: A, s_port: 40000, d_ip: B, d_port: 80 };
+    let syn = a.connect(id, 0);
+    TablePair::new(A, B).exchange(&mut a, &mut b, vec![syn]);
+    assert_eq!(a.write(id, &[1; 500]), Ok(500));
+    let data = transmit(&mut a);
+    TablePair::new(A, B).exchange(&mut a, &mut b, data);
+    assert_eq!(b.read(id.reversed(), usize::MAX).unwrap().len(), 500);
+
+    assert_eq!(b.reset_all(), 1);
+    b.listen(B, 80);
+    assert_eq!(b.state(id.reversed()), None);
+
+    // A 的下一个数据段就得到 RST, 不必等到重传次数用完
+    assert_eq!(a.write(id, &[2; 500]), Ok(500));
+    let data = transmit(&mut a);
+    assert_eq!(data.len(), 1);
+    let rst = b.segment_received(A, B, &data[0], 0);
+    assert_eq!(rst.len(), 1);
+    assert!(rst[0].RST() && !rst[0].ACK());
+    assert_eq!(rst[0].seq, data[0].ack);
+    assert!(a.segment_received(B, A, &rst[0], 0).is_empty());
+    assert_eq!(a.state(id), Some(TcpState::Closed));
+    assert_eq!(a.error(id), Some(ConnectionError::Reset));
+    assert!(b.segment_received(B, A, &rst[0], 0).is_empty()); // RST 不会引出 RST
+
+    // 同样的端口上重新连接
+    let syn = a.connect(id, 0);
+    TablePair::new(A, B).exchange(&mut a, &mut b, vec![syn]);
+    assert_eq!(a.state(id), Some(TcpState::Established));
+    assert_eq!(b.state(id.reversed()), Some(TcpState::Established));
+    assert_eq!(a.write(id, b"again"), Ok(5));
+    let data = transmit(&mut a);
+    TablePair::new(A, B).exchange(&mut a, &mut b, data);
+    assert_eq!(b.read(id.reversed(), usize::MAX).unwrap(), b"again");
+}
+
+#[test]
+fn test_restarted_client_reconnects_through_challenge_ack() {
+    let config = TcpConfig::default();
+    let mut a = ConnectionTable::new(&config);
+    let mut b = ConnectionTable::new(&config);
+    let listener = a.listen(A, 80);
+    let id = ConnectionId { s_ip: B, s_port: 40000, d_ip: A, d_port: 80 };
+    let syn = b.connect(id, 0);
+    TablePair::new(B, A).exchange(&mut b, &mut a, vec![syn]);
+    assert_eq!(a.accept(listener), Some(id.reversed()));
+    assert_eq!(b.write(id, &[1; 300]), Ok(300));
+    let data = transmit(&mut b);
+    TablePair::new(B, A).exchange(&mut b, &mut a, data);
+
+    b.reset_all();
+    let syn = b.connect(id, 0);
+
+    // A 认为连接还在, 对 SYN 回 challenge ACK 而不是 SYN|ACK
+    let challenge = a.segment_received(B, A, &syn, 0);
+    assert_eq!(challenge.len(), 1);
+    assert!(challenge[0].ACK() && !challenge[0].SYN());
+    assert_eq!(a.state(id.reversed()), Some(TcpState::Established));
+
+    // B 的 SYN 没有被确认, 以 RST 回应; A 收到后放弃旧连接
+    let rst = b.segment_received(A, B, &challenge[0], 0);
+    assert_eq!(rst.len(), 1);
+    assert!(rst[0].RST());
+    assert_eq!(rst[0].seq, challenge[0].ack);
+    assert_eq!(b.state(id), Some(TcpState::SynSent));
+    a.segment_received(B, A, &rst[0], 0);
+    assert_eq!(a.error(id.reversed()), Some(ConnectionError::Reset));
+
+    // B 重传 SYN, 这次建立新连接
+    let syn = b.retransmission(id).unwrap();
+    assert!(syn.SYN() && !syn.ACK());
+    TablePair::new(B, A).exchange(&mut b, &mut a, vec![syn]);
+    assert_eq!(b.state(id), Some(TcpState::Established));
+    assert_eq!(a.state(id.reversed()), Some(TcpState::Established));
+    assert_eq!(a.accept(listener), Some(id.reversed()));
+    assert_eq!(a.read(id.reversed(), usize::MAX).unwrap(), b"");
+}
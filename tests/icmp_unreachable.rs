@@ -0,0 +1,124 @@
+/**
+ * ICMP 目的不可达交给 TCP: 按带回的原首部找到连接 (RFC 1122 4.2.3.9)
+ * 握手中的主动打开遇到主机/端口不可达立即失败, 已建立的连接只记下通知, 继续重传
+ */
+use std::io::{ErrorKind, Write};
+
+use simple_tcp_ip::config::{Ipv4Config, TcpConfig};
+use simple_tcp_ip::net::icmp_v4::IcmpV4;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::testing::packet::PacketBuilder;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionError, ConnectionId, IcmpAdvice, TcpState};
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const CLIENT: u32 = 0x0a000001;
+const SERVER: u32 = 0x0a000002;
+const ROUTER: u32 = 0x0a0000fe;
+const ID: ConnectionId = ConnectionId { s_ip: CLIENT, s_port: 40000, d_ip: SERVER, d_port: 80 };
+const RTT_MS: u64 = 40;
+
+/**
+ * from 对 client 发出的 segment 回复的目的不可达
+ */
+fn unreachable(client: &mut ConnectionTable, segment: &TcpSegment, from: u32, code: u8) -> Ipv4Datagram {
+    let original = client.datagram(ID, segment, &Ipv4Config::default()).unwrap().serialize();
+    let icmp = IcmpV4::dest_unreachable(code, &original);
+    let bytes = PacketBuilder::new().ipv4(from, CLIENT).icmp(3, code).payload(icmp.data()).build();
+    Ipv4Datagram::try_deserialize(&bytes).unwrap()
+}
+
+#[test]
+fn test_port_unreachable_fails_connect_in_one_rtt() {
+    let mut client = ConnectionTable::new(&TcpConfig::default());
+    let syn = client.connect(ID, 0);
+    let rto = client.rto_ms(ID).unwrap();
+
+    // 网络不可达可能是暂时的, 握手中也只记下来
+    let net = unreachable(&mut client, &syn, ROUTER, 0);
+    assert_eq!(client.icmp_received(&net, RTT_MS / 2), Some(ID));
+    assert_eq!(client.state(ID), Some(TcpState::SynSent));
+    assert_eq!(client.icmp_advice(ID), Some(IcmpAdvice { code: 0, when_ms: RTT_MS / 2 }));
+
+    let port = unreachable(&mut client, &syn, SERVER, 3);
+    assert_eq!(client.icmp_received(&port, RTT_MS), Some(ID));
+    assert_eq!(client.state(ID), Some(TcpState::Closed));
+    assert_eq!(client.error(ID), Some(ConnectionError::Unreachable(3)));
+    assert!(RTT_MS < rto, "failed before the first retransmission");
+    assert_eq!(client.stream(ID).write(b"x").unwrap_err().kind(), ErrorKind::ConnectionRefused);
+
+    // 连接已经关闭, 重复的通知不再交给它
+    assert_eq!(client.icmp_received(&port, RTT_MS), None);
+}
+
+#[test]
+fn test_host_unreachable_and_forged_sequence() {
+    let mut client = ConnectionTable::new(&TcpConfig::default());
+    let syn = client.connect(ID, 0);
+
+    // 带回的序号不是我们发出的 SYN: 视为伪造
+    let mut forged = syn.clone();
+    forged.seq = syn.seq.wrapping_add(1000);
+    let icmp = unreachable(&mut client, &forged, ROUTER, 1);
+    assert_eq!(client.icmp_received(&icmp, 10), None);
+    assert_eq!(client.state(ID), Some(TcpState::SynSent));
+    assert_eq!(client.icmp_advice(ID), None);
+
+    // 需要分片同样核对序号; 真实的只调小路径 MTU, 不算不可达
+    let forged_frag = unreachable(&mut client, &forged, ROUTER, 4);
+    assert_eq!(client.icmp_received(&forged_frag, 10), None);
+    assert_eq!(client.path_mtu(SERVER), 1500);
+    let frag = unreachable(&mut client, &syn, ROUTER, 4);
+    assert_eq!(client.icmp_received(&frag, 10), Some(ID));
+    assert!(client.path_mtu(SERVER) < 1500);
+    assert_eq!((client.state(ID), client.icmp_advice(ID)), (Some(TcpState::SynSent), None));
+
+    let host = unreachable(&mut client, &syn, ROUTER, 1);
+    assert_eq!(client.icmp_received(&host, 20), Some(ID));
+    assert_eq!(client.error(ID), Some(ConnectionError::Unreachable(1)));
+    assert_eq!(client.stream(ID).write(b"x").unwrap_err().kind(), ErrorKind::HostUnreachable);
+}
+
+#[test]
+fn test_established_survives_transient_net_unreachable() {
+    let config = TcpConfig::default();
+    let mut client = ConnectionTable::new(&config);
+    let mut server = ConnectionTable::new(&config);
+    server.listen(SERVER, 80);
+    let syn = client.connect(ID, 0);
+    for reply in server.segment_received(CLIENT, SERVER, &syn, 0) {
+        for ack in client.segment_received(SERVER, CLIENT, &reply, 0) {
+            server.segment_received(CLIENT, SERVER, &ack, 0);
+        }
+    }
+    assert_eq!(client.state(ID), Some(TcpState::Established));
+
+    // 路由器暂时没有去往服务器的路由, 数据段被退回
+    client.write(ID, b"hello").unwrap();
+    let (_, lost) = client.poll_transmit(usize::MAX).remove(0);
+    let icmp = unreachable(&mut client, &lost, ROUTER, 0);
+    assert_eq!(client.icmp_received(&icmp, 100), Some(ID));
+    assert_eq!(client.state(ID), Some(TcpState::Established));
+    assert_eq!(client.error(ID), None);
+    let snapshot = client.snapshot(ID).unwrap();
+    assert_eq!(snapshot.icmp_advice, Some(IcmpAdvice { code: 0, when_ms: 100 }));
+    assert!(snapshot.to_string().contains("icmp unreachable code 0 at 100ms"));
+
+    // 已建立的连接遇到端口不可达也只记下来
+    let port = unreachable(&mut client, &lost, SERVER, 3);
+    assert_eq!(client.icmp_received(&port, 150), Some(ID));
+    assert_eq!(client.state(ID), Some(TcpState::Established));
+
+    // 路由恢复, 重传送达, 确认清除通知
+    let retransmitted = client.retransmission(ID).unwrap();
+    for ack in server.segment_received(CLIENT, SERVER, &retransmitted, 1_000) {
+        client.segment_received(SERVER, CLIENT, &ack, 1_000);
+    }
+    for (_, ack) in server.poll_transmit(usize::MAX) {
+        client.segment_received(SERVER, CLIENT, &ack, 1_000);
+    }
+    client.poll_transmit(usize::MAX);
+    assert_eq!(server.read(ID.reversed(), usize::MAX).unwrap(), b"hello");
+    assert_eq!(client.icmp_advice(ID), None);
+}
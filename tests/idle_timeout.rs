@@ -0,0 +1,106 @@
+/**
+ * 空闲超时: 两个方向都没有数据超过设定时间后关闭连接, 不发探测
+ * 虚拟时钟驱动, 测试内的粘合代码在两张连接表之间投递报文段
+ */
+use std::time::Duration;
+
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::testing::pair::TablePair;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionError, ConnectionId, IdleAction, TcpState};
+use simple_tcp_ip::transport::tcp_segment::{TcpFlags, TcpSegment};
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const LINK: TablePair = TablePair::new(A_IP, B_IP).polling();
+const ID: ConnectionId = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+const IDLE: Option<Duration> = Some(Duration::from_secs(5));
+
+fn established() -> (ConnectionTable, ConnectionTable) {
+    let mut client = ConnectionTable::new(&TcpConfig::default());
+    let mut server = ConnectionTable::new(&TcpConfig::default());
+    server.listen(B_IP, 80);
+    let syn = client.connect(ID, 0);
+    LINK.exchange(&mut client, &mut server, vec![syn]);
+    assert_eq!(client.state(ID), Some(TcpState::Established));
+    (client, server)
+}
+
+#[test]
+fn test_idle_connection_closes_gracefully() {
+    let (mut client, mut server) = established();
+    client.set_idle_timeout(ID, IDLE, IdleAction::Close).unwrap();
+
+    assert!(client.tick(4999).is_empty());
+    assert_eq!(client.state(ID), Some(TcpState::Established));
+    assert!(client.tick(5000).is_empty());
+    assert_eq!(client.state(ID), Some(TcpState::FinWait1));
+    assert_eq!(client.write(ID, b"late"), Err(ConnectionError::IdleTimeout));
+    assert_eq!(client.read(ID, 16), Err(ConnectionError::IdleTimeout));
+
+    // FIN 照常发出, 对端看到的是正常关闭
+    LINK.at(5000).exchange(&mut client, &mut server, vec![]);
+    assert_eq!(client.state(ID), Some(TcpState::FinWait2));
+    assert_eq!(server.state(ID.reversed()), Some(TcpState::CloseWait));
+    assert_eq!(server.error(ID.reversed()), None);
+}
+
+#[test]
+fn test_data_resets_timer_but_acks_and_keepalives_do_not() {
+    let (mut client, mut server) = established();
+    client.set_idle_timeout(ID, IDLE, IdleAction::Close).unwrap();
+
+    // 3 秒时客户端发出数据, 到期时刻推迟到 8 秒; 发出的时刻按最近一次 tick 计
+    client.tick(3000);
+    client.write(ID, b"ping").unwrap();
+    let ack = LINK.at(3000).exchange(&mut client, &mut server, vec![]).last_to_client.unwrap();
+    client.tick(6000);
+    assert_eq!(client.state(ID), Some(TcpState::Established));
+
+    // 6 秒时收到对端的数据, 推迟到 11 秒
+    server.write(ID.reversed(), b"pong").unwrap();
+    let data = server.poll_transmit(usize::MAX).pop().unwrap().1;
+    let replies = client.segment_received(B_IP, A_IP, &data, 6000);
+    LINK.at(6000).exchange(&mut client, &mut server, replies);
+    assert_eq!(client.read(ID, 16).unwrap(), b"pong");
+
+    // 纯 ACK 和保活探测都不推迟
+    let probe = TcpSegment::new(80, 40000, data.seq + data.data.len() as u32 - 1, ack.ack, 5, 0, TcpFlags::ACK, ack.win_size, 0,
+        vec![], vec![]);
+    let replies = client.segment_received(B_IP, A_IP, &probe, 9000);
+    assert_eq!(replies.len(), 1);
+    LINK.at(9000).exchange(&mut client, &mut server, replies);
+    client.tick(10_999);
+    assert_eq!(client.state(ID), Some(TcpState::Established));
+    client.tick(11_000);
+    assert_eq!(client.state(ID), Some(TcpState::FinWait1));
+}
+
+#[test]
+fn test_abort_on_idle_sends_reset() {
+    let (mut client, mut server) = established();
+    client.set_idle_timeout(ID, IDLE, IdleAction::Abort).unwrap();
+    client.write(ID, b"unsent").unwrap(); // 留在发送缓冲区里, 放弃时丢弃
+
+    let out = client.tick(5000);
+    assert_eq!(out.len(), 1);
+    assert!(out[0].RST());
+    assert_eq!(client.state(ID), Some(TcpState::Closed));
+    assert_eq!(client.write(ID, b"x"), Err(ConnectionError::IdleTimeout));
+
+    server.segment_received(A_IP, B_IP, &out[0], 5000);
+    assert_eq!(server.error(ID.reversed()), Some(ConnectionError::Reset));
+}
+
+#[test]
+fn test_graceful_close_is_not_interrupted() {
+    let (mut client, mut server) = established();
+    client.set_idle_timeout(ID, IDLE, IdleAction::Abort).unwrap();
+    client.close(ID, 1000).unwrap();
+    LINK.at(1000).exchange(&mut client, &mut server, vec![]);
+    assert_eq!(client.state(ID), Some(TcpState::FinWait2));
+
+    assert!(client.tick(60_000).is_empty());
+    assert_eq!(client.state(ID), Some(TcpState::FinWait2));
+    assert_eq!(client.error(ID), None);
+}
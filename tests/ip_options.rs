@@ -0,0 +1,60 @@
+/**
+ * Record Route / Timestamp 选项经过转发路径的填写
+ * 拓扑 A - R1 - R2 - B 是测试内的点对点链路: 路由器转发时 TTL 减一并在选项中记录自己的出口地址
+ */
+use simple_tcp_ip::net::ip_options::{SendOptions, TimestampFlag};
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::net::pinger::{echo_reply_datagram, PingOutcome, Pinger};
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const A: u32 = 0x0a000001;
+const R1: u32 = 0x0a000101;
+const R2: u32 = 0x0a000201;
+const B: u32 = 0x0a000302;
+
+fn forward(mut datagram: Ipv4Datagram, routers: &[u32], now_ms: u64) -> Ipv4Datagram {
+    for &router in routers {
+        assert!(datagram.decrement_ttl());
+        datagram.record_options(router, now_ms);
+        datagram = Ipv4Datagram::try_deserialize(&datagram.serialize()).unwrap();
+        assert!(datagram.check_hdr_checksum());
+    }
+    datagram
+}
+
+fn ping(options: SendOptions) -> (PingOutcome, simple_tcp_ip::net::ip_options::RecordedOptions) {
+    let mut pinger = Pinger::new(0x77, 32);
+    let icmp = pinger.next_probe(0).serialize();
+    let mut request = Ipv4Datagram::new(4, 5, 0, (20 + icmp.len()) as u16, 1, 0, 0, 64, 1, A, B, icmp);
+    request.set_options(options.encode());
+
+    let request = forward(request, &[R1, R2], 10);
+    let reply = echo_reply_datagram(&request, B, 20).unwrap();
+    let reply = forward(reply, &[R2, R1], 30);
+    pinger.on_reply_datagram(&reply, 40).unwrap()
+}
+
+#[test]
+fn test_record_route_round_trip() {
+    let (outcome, recorded) = ping(SendOptions { record_route: true, ip_timestamp: None });
+    assert_eq!(outcome, PingOutcome::Reply { seq: 0, size: 32, rtt_ms: 40 });
+    assert_eq!(recorded.route, vec![R1, R2, B, R2, R1]);
+    assert!(recorded.timestamps.is_empty());
+}
+
+#[test]
+fn test_timestamp_round_trip() {
+    let (_, recorded) = ping(SendOptions { record_route: false, ip_timestamp: Some(TimestampFlag::WithAddress) });
+    assert_eq!(recorded.timestamps, vec![(Some(R1), 10), (Some(R2), 10), (Some(B), 20), (Some(R2), 30)]);
+    assert_eq!(recorded.timestamp_overflow, 1); // 只有 4 个槽位, 最后一跳没有空间
+
+    let (_, recorded) = ping(SendOptions { record_route: true, ip_timestamp: Some(TimestampFlag::TimestampOnly) });
+    assert_eq!(recorded.route, vec![R1, R2, B, R2]);
+    assert_eq!(recorded.timestamps, vec![(None, 10), (None, 10), (None, 20), (None, 30)]);
+}
+
+#[test]
+fn test_no_options_requested() {
+    let (_, recorded) = ping(SendOptions::default());
+    assert!(recorded.is_empty());
+}
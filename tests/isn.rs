@@ -0,0 +1,78 @@
+/**
+ * RFC 6528 的 ISN: 同一四元组随虚拟时钟单调增长, 不同四元组之间看不出关系
+ * 分布检查只是健全性检查, 不是密码学证明
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::isn::IsnGenerator;
+use simple_tcp_ip::transport::tcp_connection::ConnectionId;
+use simple_tcp_ip::transport::tcp_segment::{TcpFlags, TcpSegment};
+
+const SECRET: [u64; 2] = [0x0123_4567_89ab_cdef, 0xfedc_ba98_7654_3210];
+const CLIENT: u32 = 0x0a000001;
+const SERVER: u32 = 0x0a000002;
+
+fn id(s_port: u16) -> ConnectionId {
+    ConnectionId { s_ip: CLIENT, s_port, d_ip: SERVER, d_port: 80 }
+}
+
+/**
+ * 每一位为 1 的比例都在 [0.45, 0.55] 内
+ */
+fn assert_balanced_bits(values: &[u32]) {
+    for bit in 0..32 {
+        let ones = values.iter().filter(|value| *value >> bit & 1 == 1).count();
+        let ratio = ones as f64 / values.len() as f64;
+        assert!((0.45..=0.55).contains(&ratio), "bit {} set in {:.3} of samples", bit, ratio);
+    }
+}
+
+#[test]
+fn test_same_tuple_increases_with_clock() {
+    let isn = IsnGenerator::with_secret(SECRET);
+    let mut now_us = 0;
+    let mut last = isn.generate(&id(40000), now_us);
+    for step in [4, 400, 40_000, 4_000_000, 1_000_000_000] {
+        now_us += step;
+        let next = isn.generate(&id(40000), now_us);
+        assert_eq!(next.wrapping_sub(last), (step / 4) as u32);
+        last = next;
+    }
+}
+
+#[test]
+fn test_different_tuples_are_uncorrelated() {
+    let isn = IsnGenerator::with_secret(SECRET);
+    let values: Vec<u32> = (1024..5120).map(|port| isn.generate(&id(port), 0)).collect();
+    assert_balanced_bits(&values);
+
+    // 相邻端口之间的差没有规律, 不能由一个观测值推出下一个
+    let deltas: Vec<u32> = values.windows(2).map(|pair| pair[1].wrapping_sub(pair[0])).collect();
+    assert_balanced_bits(&deltas);
+
+    // 观测者知道四元组和时钟也没用: 换一个密钥, 同样的输入得到完全不同的 ISN
+    let other = IsnGenerator::with_secret([SECRET[0] ^ 1, SECRET[1]]);
+    let flipped: Vec<u32> = (1024..5120).map(|port| isn.generate(&id(port), 0) ^ other.generate(&id(port), 0)).collect();
+    assert_balanced_bits(&flipped);
+}
+
+#[test]
+fn test_table_uses_generator_for_both_opens() {
+    let config = TcpConfig::default();
+    let mut client = ConnectionTable::new(&config);
+    client.set_isn_generator(IsnGenerator::with_secret(SECRET));
+    let isn = IsnGenerator::with_secret(SECRET);
+
+    let first = client.connect(id(40000), 0);
+    assert_eq!(first.seq, isn.generate(&id(40000), 0));
+    client.remove(id(40000));
+    let second = client.connect(id(40000), 4_000);
+    assert_eq!(second.seq.wrapping_sub(first.seq), 1_000_000);
+
+    let mut server = ConnectionTable::new(&config);
+    server.set_isn_generator(IsnGenerator::with_secret(SECRET));
+    server.listen(SERVER, 80);
+    let syn = TcpSegment::new(40001, 80, 7, 0, 5, 0, TcpFlags::SYN, 65535, 0, vec![], vec![]);
+    let syn_ack = server.segment_received(CLIENT, SERVER, &syn, 12).pop().unwrap();
+    assert_eq!(syn_ack.seq, isn.generate(&id(40001).reversed(), 12_000));
+}
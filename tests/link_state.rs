@@ -0,0 +1,186 @@
+/**
+ * 链路状态: 接口断开时经过它的路由不可用, 恢复时发免费 ARP
+ * 已有的连接默认一直重传等待链路恢复; 配置 link_down_abort_ms 时断开超过这个时间的连接以 NetworkDown 失败
+ */
+use std::io::{ErrorKind, Write};
+
+use simple_tcp_ip::config::{Ipv4Config, StackConfig};
+use simple_tcp_ip::link::arp::ArpPacket;
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::link::interface::EthernetInterface;
+use simple_tcp_ip::net::interfaces::LinkEvent;
+use simple_tcp_ip::stack::Stack;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionError, ConnectionId, TcpState};
+use simple_tcp_ip::utils::drops::DropReason;
+
+const A_IP: u32 = 0x0a000102;
+const B_IP: u32 = 0x0a000103;
+const A_IP1: u32 = 0x0a000302;
+const C_IP: u32 = 0x0a000303;
+const A_MAC: [u8; 6] = [0x02, 0, 0, 0, 1, 2];
+const B_MAC: [u8; 6] = [0x02, 0, 0, 0, 1, 3];
+const A_MAC1: [u8; 6] = [0x02, 0, 0, 0, 3, 2];
+const ID: ConnectionId = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+
+fn node(config: &Ipv4Config, interfaces: &[([u8; 6], u32)]) -> Stack {
+    let mut node = Stack::new(&StackConfig { ipv4: config.clone(), ..StackConfig::default() }).unwrap();
+    for (mac, ip) in interfaces {
+        node.add_interface(EthernetInterface::new(*mac, *ip, 24));
+    }
+    node
+}
+
+/**
+ * A 的接口 0 与 B 之间的一根线, 可以断开; 两端轮流 poll 并搬运帧, 直到双方都没有要发的
+ */
+fn run(a: &mut Stack, b: &mut Stack, now_ms: u64) {
+    loop {
+        a.poll(now_ms);
+        b.poll(now_ms);
+        let mut moved = false;
+        for bytes in a.take_tx(0) {
+            b.frame_received(0, bytes);
+            moved = true;
+        }
+        for bytes in b.take_tx(0) {
+            a.frame_received(0, bytes);
+            moved = true;
+        }
+        if !moved {
+            return;
+        }
+    }
+}
+
+fn connected(config: &Ipv4Config) -> (Stack, Stack) {
+    let mut a = node(config, &[(A_MAC, A_IP), (A_MAC1, A_IP1)]);
+    let mut b = node(&Ipv4Config::default(), &[(B_MAC, B_IP)]);
+    b.tcp_mut().listen(B_IP, 80);
+    a.connect(ID, 0).unwrap();
+    run(&mut a, &mut b, 0);
+    assert_eq!(a.tcp().state(ID), Some(TcpState::Established));
+    (a, b)
+}
+
+/**
+ * 拔插 A 与 B 之间的网线, 两端同时看到链路状态变化
+ */
+fn set_link(a: &mut Stack, b: &mut Stack, up: bool, now_ms: u64) {
+    a.interfaces_mut().set_link(0, up, now_ms);
+    b.interfaces_mut().set_link(0, up, now_ms);
+}
+
+#[test]
+fn test_down_link_routes_are_unusable() {
+    let mut a = node(&Ipv4Config::default(), &[(A_MAC, A_IP), (A_MAC1, A_IP1)]);
+    // 10.0.2.0/24 经接口 0 上的网关, 默认路由经接口 1 上的网关
+    a.interfaces_mut().routes_mut().add_on(0x0a000200, 24, Some(B_IP), 0);
+    a.interfaces_mut().routes_mut().add_on(0, 0, Some(C_IP), 1);
+    assert!(a.interfaces().is_up(0));
+    assert_eq!(a.interfaces().egress(0x0a000205).map(|hop| hop.interface), Some(0));
+
+    a.interfaces_mut().set_link(0, false, 10);
+    a.interfaces_mut().set_link(0, false, 20);
+    assert!(!a.interfaces().is_up(0));
+    assert_eq!(a.interfaces_mut().take_link_events(), vec![LinkEvent::Down(0)]);
+
+    // 更具体的路由不可用时退回默认路由; 去掉默认路由之后直连网段没有别的出口
+    assert_eq!(a.interfaces().egress(0x0a000205).map(|hop| (hop.interface, hop.next_hop)), Some((1, C_IP)));
+    assert!(a.interfaces_mut().routes_mut().remove(0, 0).is_some());
+    assert_eq!(a.interfaces().egress(B_IP), None);
+    assert!(a.interfaces().reaches_via(B_IP, 0));
+
+    // 新的连接发不出去, 连接表收到主机不可达; 收到的帧被丢弃
+    let syn = a.tcp_mut().connect(ID, 20);
+    assert_eq!(a.send(ID, &syn, 20), Err(DropReason::NoRoute));
+    assert_eq!(a.tcp_mut().host_unreachable(B_IP, 20), vec![ID]);
+    assert_eq!(a.tcp().error(ID), Some(ConnectionError::HostUnreachable));
+    a.interfaces_mut().frame_received(0, vec![0; 64]);
+    assert_eq!(a.interfaces_mut().poll(usize::MAX, 20), 0);
+
+    a.interfaces_mut().set_link(0, true, 30);
+    assert_eq!(a.interfaces_mut().take_link_events(), vec![LinkEvent::Up(0)]);
+    assert_eq!(a.interfaces().egress(B_IP).map(|hop| hop.interface), Some(0));
+}
+
+#[test]
+fn test_link_up_announces_with_gratuitous_arp() {
+    let (mut a, mut b) = connected(&Ipv4Config::default());
+    a.interfaces_mut().set_link(0, false, 100);
+    assert!(a.interfaces_mut().take_tx(0).is_empty());
+
+    a.interfaces_mut().set_link(0, true, 200);
+    let frames = a.interfaces_mut().take_tx(0);
+    assert_eq!(frames.len(), 1);
+    let frame = EthernetFrame::try_deserialize(&frames[0]).unwrap();
+    assert_eq!((frame.d_mac(), frame.s_mac()), ([0xff; 6], A_MAC));
+    let packet = ArpPacket::try_deserialize(frame.payload()).unwrap();
+    assert!(packet.is_gratuitous());
+    assert_eq!((packet.s_ip, packet.s_mac), (A_IP, A_MAC));
+
+    // 其他接口的链路没有变化, 不发
+    assert!(a.interfaces_mut().take_tx(1).is_empty());
+
+    // 邻居按免费 ARP 刷新已有的表项
+    b.interfaces_mut().frame_received(0, frames[0].clone());
+    b.interfaces_mut().poll(usize::MAX, 200);
+    assert!(b.interfaces_mut().take_tx(0).is_empty());
+    assert!(b.interfaces().arp_cache(0).unwrap().state(A_IP).is_some());
+}
+
+#[test]
+fn test_connections_keep_retrying_by_default() {
+    let (mut a, mut b) = connected(&Ipv4Config::default());
+    set_link(&mut a, &mut b, false, 1_000);
+
+    // 断开期间的写入和重传都发不出去, 连接一直等待
+    a.tcp_mut().write(ID, b"queued while down").unwrap();
+    for (id, segment) in a.tcp_mut().poll_transmit(usize::MAX) {
+        assert_eq!(a.send(id, &segment, 1_000), Err(DropReason::NoRoute));
+    }
+    for now_ms in (2_000..=120_000).step_by(1_000) {
+        a.interfaces_mut().tick(now_ms);
+        for segment in a.tcp_mut().tick(now_ms) {
+            assert_eq!(a.send(ID, &segment, now_ms), Err(DropReason::NoRoute));
+        }
+    }
+    assert!(a.interfaces_mut().take_link_events().iter().all(|event| *event == LinkEvent::Down(0)));
+    assert_eq!(a.tcp().state(ID), Some(TcpState::Established));
+    assert_eq!(a.tcp().error(ID), None);
+
+    // 链路恢复后重传送达
+    set_link(&mut a, &mut b, true, 121_000);
+    let segment = a.tcp_mut().retransmission(ID).unwrap();
+    a.send(ID, &segment, 121_000).unwrap();
+    run(&mut a, &mut b, 121_000);
+    assert_eq!(b.tcp_mut().read(ID.reversed(), usize::MAX).unwrap(), b"queued while down");
+}
+
+#[test]
+fn test_connections_abort_after_configured_outage() {
+    let (mut a, mut b) = connected(&Ipv4Config { link_down_abort_ms: Some(5_000), ..Ipv4Config::default() });
+    // 接口 1 上正在建立的连接不受接口 0 断开的影响
+    let other = ConnectionId { s_ip: A_IP1, s_port: 40001, d_ip: C_IP, d_port: 80 };
+    a.tcp_mut().connect(other, 0);
+
+    set_link(&mut a, &mut b, false, 10_000);
+    a.interfaces_mut().tick(14_999);
+    assert_eq!(a.interfaces_mut().take_link_events(), vec![LinkEvent::Down(0)]);
+    a.interfaces_mut().tick(15_000);
+    a.interfaces_mut().tick(16_000);
+    let events = a.interfaces_mut().take_link_events();
+    assert_eq!(events, vec![LinkEvent::DownTimeout(0)]);
+
+    assert_eq!(a.link_down(0, 15_000), vec![ID]);
+    assert_eq!(a.tcp().state(ID), Some(TcpState::Closed));
+    assert_eq!(a.tcp().error(ID), Some(ConnectionError::NetworkDown));
+    assert_eq!(a.tcp().state(other), Some(TcpState::SynSent));
+    assert_eq!(a.tcp_mut().stream(ID).write(b"late").unwrap_err().kind(), ErrorKind::NetworkDown);
+
+    // 下一次断开重新计时
+    set_link(&mut a, &mut b, true, 20_000);
+    set_link(&mut a, &mut b, false, 30_000);
+    a.interfaces_mut().tick(34_000);
+    a.interfaces_mut().tick(35_000);
+    assert_eq!(a.interfaces_mut().take_link_events(), vec![LinkEvent::Up(0), LinkEvent::Down(0), LinkEvent::DownTimeout(0)]);
+}
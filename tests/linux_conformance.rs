@@ -0,0 +1,213 @@
+/**
+ * 与 Linux 的线路行为对照: tests/fixtures/linux_*.hex 是经 TAP 从宿主机内核抓到的交互
+ * 协议栈扮演内核一方 (10.211.0.1), 依次收到同样的激励报文段, 发出的应答与内核的应答逐字段比较
+ * 内核一方的序号按各自的 ISN 对齐; 窗口取决于缓冲区配置, 只在 RST 和零窗口上比较; 校验和不比较
+ */
+use simple_tcp_ip::config::{Ipv4Config, TcpConfig};
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::testing::fixtures;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::ConnectionId;
+use simple_tcp_ip::transport::tcp_segment::{TcpFlags, TcpSegment};
+
+const HOST_IP: u32 = 0x0ad30001;  // 10.211.0.1, 内核一方
+const STACK_IP: u32 = 0x0ad30002; // 10.211.0.2, 激励的来源
+const ETHER_HDR_LEN: usize = 14;  // TAP 抓到的帧不含 FCS
+
+/**
+ * 样本中的报文段, 连同比较用到的 IP 首部字段
+ */
+struct Captured {
+    s_addr: u32,
+    ip: String, // TTL、TOS 和 DF; 标识字段不比较
+    segment: TcpSegment,
+}
+
+fn ip_fields(datagram: &Ipv4Datagram) -> String {
+    format!("ttl {} tos {:#04x} df {}", datagram.ttl(), datagram.tos(), datagram.dont_fragment())
+}
+
+fn capture(name: &str) -> Vec<Captured> {
+    fixtures::load(name).unwrap().iter().map(|frame| {
+        let datagram = Ipv4Datagram::try_deserialize(&frame[ETHER_HDR_LEN..]).unwrap();
+        let segment = TcpSegment::try_deserialize(datagram.payload()).unwrap();
+        assert!(segment.check_checksum(datagram.s_addr(), datagram.d_addr()), "{}: bad checksum in fixture", name);
+        Captured { s_addr: datagram.s_addr(), ip: ip_fields(&datagram), segment }
+    }).collect()
+}
+
+fn host() -> ConnectionTable {
+    host_with(TcpConfig::default())
+}
+
+fn host_with(config: TcpConfig) -> ConnectionTable {
+    let mut table = ConnectionTable::new(&config);
+    table.listen(HOST_IP, 7777);
+    table
+}
+
+/**
+ * 把激励交给协议栈, 收集它的应答并与内核的应答对照
+ * base 为 (内核, 协议栈) 在内核一方序号空间里的对应点, 由第一个应答确定
+ */
+struct Replay {
+    table: ConnectionTable,
+    replies: Vec<TcpSegment>,
+    base: Option<(u32, u32)>,
+    problems: Vec<String>,
+}
+
+impl Replay {
+    fn new(table: ConnectionTable) -> Self {
+        Replay { table, replies: vec![], base: None, problems: vec![] }
+    }
+
+    /**
+     * 内核序号空间里的值换算到协议栈; 确定对应点之前原样使用
+     */
+    fn ours(&self, linux: u32) -> u32 {
+        match self.base {
+            Some((theirs, ours)) => linux.wrapping_sub(theirs).wrapping_add(ours),
+            None => linux,
+        }
+    }
+
+    /**
+     * 激励报文段: 确认号属于内核一方的序号空间, 换算之后交给协议栈
+     */
+    fn stimulus(&mut self, segment: &TcpSegment) {
+        let mut segment = segment.clone();
+        if segment.ACK() {
+            segment.ack = self.ours(segment.ack);
+        }
+        segment.generate_checksum(STACK_IP, HOST_IP);
+        let replies = self.table.segment_received(STACK_IP, HOST_IP, &segment, 0);
+        self.replies.extend(replies);
+        self.replies.extend(self.table.poll_transmit(usize::MAX).into_iter().map(|(_, seg)| seg));
+    }
+
+    /**
+     * 内核的应答: 与协议栈的下一个应答比较
+     */
+    fn expect(&mut self, captured: &Captured, label: &str) {
+        let linux = &captured.segment;
+        if self.replies.is_empty() {
+            self.problems.push(format!("{}: no reply, Linux sent {}", label, linux.ctrl));
+            return;
+        }
+        let ours = self.replies.remove(0);
+        if self.base.is_none() && !linux.RST() {
+            self.base = Some((linux.seq, ours.seq));
+        }
+        let mut fields: Vec<(&str, String, String)> = vec![
+            ("ports", format!("{}->{}", linux.s_port, linux.d_port), format!("{}->{}", ours.s_port, ours.d_port)),
+            ("flags", linux.ctrl.to_string(), ours.ctrl.to_string()),
+            ("seq", self.ours(linux.seq).to_string(), ours.seq.to_string()),
+            ("ack", linux.ack.to_string(), ours.ack.to_string()),
+            ("header length", linux.hl.to_string(), ours.hl.to_string()),
+            ("reserved", linux.rcvd.to_string(), ours.rcvd.to_string()),
+            ("urgent pointer", linux.ur_ptr.to_string(), ours.ur_ptr.to_string()),
+            ("options", format!("{:?}", linux.parsed_options()), format!("{:?}", ours.parsed_options())),
+            ("data", format!("{:?}", linux.data), format!("{:?}", ours.data)),
+        ];
+        if linux.RST() || linux.win_size == 0 {
+            fields.push(("window", linux.win_size.to_string(), ours.win_size.to_string()));
+        }
+        // 没有连接时发出的 RST 由调用方封装, 只有连接上的段才比较 IP 首部
+        let id = ConnectionId { s_ip: HOST_IP, s_port: ours.s_port, d_ip: STACK_IP, d_port: ours.d_port };
+        if let Some(datagram) = self.table.datagram(id, &ours, &Ipv4Config::default()) {
+            fields.push(("ip header", captured.ip.clone(), ip_fields(&datagram)));
+        }
+        for (field, theirs, mine) in fields {
+            if theirs != mine {
+                self.problems.push(format!("{}: {} is {}, Linux sent {}", label, field, mine, theirs));
+            }
+        }
+    }
+
+    /**
+     * 按顺序重放样本; 内核一方发出的段作为期望的应答
+     */
+    fn run(mut self, name: &str) -> Vec<String> {
+        for (i, captured) in capture(name).iter().enumerate() {
+            if captured.s_addr == STACK_IP {
+                self.stimulus(&captured.segment);
+            } else {
+                self.expect(captured, &format!("{} #{}", name, i));
+            }
+        }
+        for extra in &self.replies {
+            self.problems.push(format!("{}: unexpected {} from the stack", name, extra.ctrl));
+        }
+        self.problems
+    }
+}
+
+fn assert_conforms(problems: Vec<String>) {
+    assert!(problems.is_empty(), "{} divergences from Linux:\n{}", problems.len(), problems.join("\n"));
+}
+
+#[test]
+fn test_rst_to_syn_on_closed_port() {
+    assert_conforms(Replay::new(host()).run("linux_rst_syn_closed"));
+}
+
+#[test]
+fn test_rst_to_ack_on_closed_port() {
+    assert_conforms(Replay::new(host()).run("linux_rst_ack_closed"));
+}
+
+#[test]
+fn test_rst_to_data_without_ack_on_closed_port() {
+    assert_conforms(Replay::new(host()).run("linux_rst_data_closed"));
+}
+
+#[test]
+fn test_challenge_ack_to_syn_in_established() {
+    assert_conforms(Replay::new(host()).run("linux_challenge_ack"));
+}
+
+#[test]
+fn test_keepalive_probe_gets_ack() {
+    assert_conforms(Replay::new(host()).run("linux_keepalive_reply"));
+}
+
+#[test]
+fn test_zero_window_probe_reply() {
+    // 样本从内核通告零窗口开始: 先用同样的序号把协议栈的接收缓冲区填满
+    let capture = capture("linux_zero_window_reply");
+    let zero = &capture[0].segment;
+    const PEER_ISN: u32 = 30000;
+    let buffer = zero.ack.wrapping_sub(PEER_ISN + 1) as usize;
+    let mut replay = Replay::new(host_with(TcpConfig { recv_buffer: buffer, ..TcpConfig::default() }));
+    let syn = TcpSegment::new(zero.d_port, 7777, PEER_ISN, 0, 5, 0, TcpFlags::SYN, 64240, 0, vec![], vec![]);
+    replay.stimulus(&syn);
+    let syn_ack = replay.replies.remove(0);
+    replay.base = Some((zero.seq, syn_ack.seq.wrapping_add(1)));
+    let mut seq = PEER_ISN + 1;
+    for chunk in vec![0x5a; buffer].chunks(1460) {
+        let data = TcpSegment::new(zero.d_port, 7777, seq, zero.seq, 5, 0, TcpFlags::ACK, 64240, 0, vec![], chunk.to_vec());
+        replay.stimulus(&data);
+        seq += chunk.len() as u32;
+    }
+    let last = replay.replies.pop().unwrap();
+    assert_eq!((last.ack, last.win_size), (zero.ack, 0));
+    replay.replies.clear();
+    replay.replies.push(last);
+    assert_conforms(replay.run("linux_zero_window_reply"));
+}
+
+#[test]
+fn test_rst_to_syn_ack_on_listening_port() {
+    assert_conforms(Replay::new(host()).run("linux_rst_synack_listen"));
+}
+
+#[test]
+fn test_challenge_ack_to_inexact_rst() {
+    assert_conforms(Replay::new(host()).run("linux_challenge_ack_rst"));
+}
+
+#[test]
+fn test_keepalive_probe_with_garbage_byte_gets_ack() {
+    assert_conforms(Replay::new(host()).run("linux_keepalive_garbage_reply"));
+}
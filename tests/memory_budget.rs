@@ -0,0 +1,112 @@
+/**
+ * 内存预算: 设备一侧的连接表共享 256 KB 预算, 同时进行 8 条大流量传输(4 条上传、4 条下载)
+ * 对端不受限制; 两张表之间是测试内的无损线路, 设备上的应用每轮每条连接只读 16 KB
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::ConnectionId;
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+use simple_tcp_ip::utils::memory::{MemoryBudget, MemoryComponent};
+
+const DEVICE: u32 = 0x0a000001;
+const PEER: u32 = 0x0a000002;
+const LIMIT: usize = 256 * 1024;
+const TRANSFER: usize = 200 * 1024;
+const READ_PER_ROUND: usize = 16 * 1024;
+
+fn pump(device: &mut ConnectionTable, peer: &mut ConnectionTable, mut to_device: Vec<TcpSegment>, mut to_peer: Vec<TcpSegment>, now: u64) {
+    while !to_device.is_empty() || !to_peer.is_empty() {
+        for segment in std::mem::take(&mut to_device) {
+            to_peer.extend(device.segment_received(PEER, DEVICE, &segment, now));
+        }
+        for segment in std::mem::take(&mut to_peer) {
+            to_device.extend(peer.segment_received(DEVICE, PEER, &segment, now));
+        }
+    }
+}
+
+fn payload(stream: usize) -> Vec<u8> {
+    (0..TRANSFER).map(|i| (i * 31 + stream * 7) as u8).collect()
+}
+
+/**
+ * 一条单向传输: 发送方所在的表、连接和进度
+ */
+struct Transfer {
+    from_device: bool,
+    sender: ConnectionId,
+    receiver: ConnectionId,
+    data: Vec<u8>,
+    written: usize,
+    received: Vec<u8>,
+}
+
+#[test]
+fn test_eight_bulk_transfers_under_budget() {
+    let config = TcpConfig::default();
+    let budget = MemoryBudget::new(LIMIT);
+    let mut device = ConnectionTable::new(&config);
+    device.set_memory_budget(budget.clone());
+    let mut peer = ConnectionTable::new(&config);
+    let device_listener = device.listen(DEVICE, 80);
+    let peer_listener = peer.listen(PEER, 90);
+
+    let mut transfers = vec![];
+    for stream in 0..8 {
+        let from_device = stream % 2 == 0;
+        let port = 40000 + stream as u16;
+        let (sender, receiver) = if from_device {
+            let id = ConnectionId { s_ip: DEVICE, s_port: port, d_ip: PEER, d_port: 90 };
+            let syn = device.connect(id, 0);
+            pump(&mut device, &mut peer, vec![], vec![syn], 0);
+            (id, peer.accept(peer_listener).unwrap())
+        } else {
+            let id = ConnectionId { s_ip: PEER, s_port: port, d_ip: DEVICE, d_port: 80 };
+            let syn = peer.connect(id, 0);
+            pump(&mut device, &mut peer, vec![syn], vec![], 0);
+            (id, device.accept(device_listener).unwrap())
+        };
+        transfers.push(Transfer { from_device, sender, receiver, data: payload(stream), written: 0, received: vec![] });
+    }
+
+    let mut now = 0;
+    while transfers.iter().any(|t| t.received.len() < TRANSFER) {
+        now += 100;
+        assert!(now < 1_000_000, "stalled: {}", budget.usage());
+        for t in &mut transfers {
+            let table = if t.from_device { &mut device } else { &mut peer };
+            t.written += table.write(t.sender, &t.data[t.written..]).unwrap();
+        }
+        loop {
+            let to_peer: Vec<TcpSegment> = device.poll_transmit(usize::MAX).into_iter().map(|(_, s)| s).collect();
+            let to_device: Vec<TcpSegment> = peer.poll_transmit(usize::MAX).into_iter().map(|(_, s)| s).collect();
+            if to_peer.is_empty() && to_device.is_empty() {
+                break;
+            }
+            pump(&mut device, &mut peer, to_device, to_peer, now);
+            assert!(budget.used() <= LIMIT);
+        }
+        for t in &mut transfers {
+            let data = if t.from_device { peer.read(t.receiver, usize::MAX) } else { device.read(t.receiver, READ_PER_ROUND) };
+            t.received.extend(data.unwrap());
+        }
+        let to_peer = device.tick(now);
+        let to_device = peer.tick(now);
+        pump(&mut device, &mut peer, to_device, to_peer, now);
+        assert!(budget.used() <= LIMIT);
+    }
+
+    for t in &transfers {
+        assert!(t.received == t.data);
+    }
+    let usage = device.memory_usage();
+    assert!(usage.peak <= LIMIT);
+    assert!(usage.peak > LIMIT * 3 / 4, "budget was never under pressure: {}", usage);
+    assert!(usage.get(MemoryComponent::SendBuffer) + usage.get(MemoryComponent::RecvBuffer) == usage.total);
+
+    // 连接关闭后预算全部退还
+    for t in &transfers {
+        device.remove(if t.from_device { t.sender } else { t.receiver });
+    }
+    assert_eq!(budget.used(), 0);
+}
@@ -0,0 +1,118 @@
+/**
+ * Prometheus 文本指标的金样测试: 构造一个已知状态的协议栈, 输出必须逐字节一致
+ * 指标名和标签是对外的稳定接口, 这里的期望值改变意味着抓取端需要同步修改
+ */
+use simple_tcp_ip::config::{ArpConfig, TcpConfig};
+use simple_tcp_ip::link::arp_cache::ArpCache;
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::link::interface::EthernetInterface;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::ConnectionId;
+use simple_tcp_ip::utils::drops::{DropCounters, DropReason};
+use simple_tcp_ip::utils::memory::MemoryBudget;
+use simple_tcp_ip::utils::metrics::StackMetrics;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const A_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+const B_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 2];
+
+const EXPECTED: &str = r#"# HELP stip_interface_rx_frames_total Frames received from the device.
+# TYPE stip_interface_rx_frames_total counter
+stip_interface_rx_frames_total{interface="eth0"} 2
+# HELP stip_interface_rx_bytes_total Bytes received from the device.
+# TYPE stip_interface_rx_bytes_total counter
+stip_interface_rx_bytes_total{interface="eth0"} 128
+# HELP stip_interface_tx_frames_total Frames handed to the device.
+# TYPE stip_interface_tx_frames_total counter
+stip_interface_tx_frames_total{interface="eth0"} 1
+# HELP stip_interface_tx_bytes_total Bytes handed to the device.
+# TYPE stip_interface_tx_bytes_total counter
+stip_interface_tx_bytes_total{interface="eth0"} 64
+# HELP stip_drops_total Packets dropped, by reason.
+# TYPE stip_drops_total counter
+stip_drops_total{reason="bad_fcs"} 1
+stip_drops_total{reason="reassembly_timeout"} 1
+# HELP stip_checksum_errors_total Frames or packets that failed checksum verification.
+# TYPE stip_checksum_errors_total counter
+stip_checksum_errors_total{kind="fcs"} 1
+stip_checksum_errors_total{kind="ip"} 0
+stip_checksum_errors_total{kind="tcp"} 0
+stip_checksum_errors_total{kind="udp"} 0
+stip_checksum_errors_total{kind="icmp"} 0
+# HELP stip_tcp_connections TCP connections, by state.
+# TYPE stip_tcp_connections gauge
+stip_tcp_connections{state="closed"} 0
+stip_tcp_connections{state="listen"} 0
+stip_tcp_connections{state="syn_sent"} 1
+stip_tcp_connections{state="syn_received"} 0
+stip_tcp_connections{state="established"} 1
+stip_tcp_connections{state="fin_wait_1"} 0
+stip_tcp_connections{state="fin_wait_2"} 0
+stip_tcp_connections{state="close_wait"} 0
+stip_tcp_connections{state="closing"} 0
+stip_tcp_connections{state="last_ack"} 0
+stip_tcp_connections{state="time_wait"} 0
+# HELP stip_tcp_retransmissions_total TCP segments retransmitted.
+# TYPE stip_tcp_retransmissions_total counter
+stip_tcp_retransmissions_total 1
+# HELP stip_tcp_stalls_total Stalled TCP connections detected by the watchdog.
+# TYPE stip_tcp_stalls_total counter
+stip_tcp_stalls_total 0
+# HELP stip_arp_cache_entries Entries in the ARP cache.
+# TYPE stip_arp_cache_entries gauge
+stip_arp_cache_entries 1
+# HELP stip_memory_used_bytes Memory charged to the budget, by component.
+# TYPE stip_memory_used_bytes gauge
+stip_memory_used_bytes{component="send_buffer"} 1000
+stip_memory_used_bytes{component="recv_buffer"} 65535
+stip_memory_used_bytes{component="fragments"} 0
+stip_memory_used_bytes{component="interface_queue"} 0
+# HELP stip_memory_peak_bytes Highest total charge seen.
+# TYPE stip_memory_peak_bytes gauge
+stip_memory_peak_bytes 66535
+# HELP stip_memory_limit_bytes Memory budget limit.
+# TYPE stip_memory_limit_bytes gauge
+stip_memory_limit_bytes 1048576
+"#;
+
+#[test]
+fn test_golden_metrics() {
+    let mut eth0 = EthernetInterface::new(A_MAC, A_IP, 24);
+    let good = EthernetFrame::new(A_MAC, B_MAC, 0x0806, vec![0; 46]).serialize();
+    let mut bad = good.clone();
+    bad[20] ^= 0xff;
+    assert!(eth0.receive(&good).is_some());
+    assert!(eth0.receive(&bad).is_none());
+    eth0.transmit(&eth0.frame(B_MAC, 0x0806, vec![0; 46])).unwrap();
+
+    let budget = MemoryBudget::new(1 << 20);
+    let mut table = ConnectionTable::new(&TcpConfig::default());
+    table.set_memory_budget(budget.clone());
+    let mut server = ConnectionTable::new(&TcpConfig::default());
+    server.listen(B_IP, 80);
+    let established = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+    let syn = table.connect(established, 0);
+    let syn_ack = server.segment_received(A_IP, B_IP, &syn, 0).pop().unwrap();
+    table.segment_received(B_IP, A_IP, &syn_ack, 0);
+    table.connect(ConnectionId { s_ip: A_IP, s_port: 40001, d_ip: B_IP, d_port: 80 }, 0);
+    table.write(established, &[7; 1000]).unwrap();
+    table.poll_transmit(1);
+    table.retransmission(established).unwrap();
+
+    let mut arp = ArpCache::new(&ArpConfig::default());
+    arp.on_arp_reply(B_IP, B_MAC, 0);
+
+    let mut reassembly = DropCounters::new();
+    reassembly.record(DropReason::ReassemblyTimeout);
+
+    let rendered = StackMetrics::new()
+        .interface("eth0", &eth0)
+        .connections(&table)
+        .drops(&reassembly)
+        .arp_cache(&arp)
+        .memory(&budget)
+        .render();
+    assert_eq!(rendered, EXPECTED);
+}
@@ -0,0 +1,152 @@
+/**
+ * 链路上的改包设备: MSS clamping 之后发送端按改小的 MSS 分段
+ * 只剥掉重传 SYN 中选项的设备不会让两端的协商结果出现分歧
+ */
+use simple_tcp_ip::config::{Ipv4Config, TcpConfig};
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::testing::middlebox::{mangle_tcp, MssClamp};
+use simple_tcp_ip::testing::netem::{NetemConfig, NetemLink};
+use simple_tcp_ip::testing::sim::{Event, Side, SimNode, SimOutcome, Simulation};
+use simple_tcp_ip::transport::connection_table::{listener_id, ConnectionTable};
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_option::{NegotiatedOptions, TcpOption};
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+use simple_tcp_ip::utils::clock::ManualClock;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const ID: ConnectionId = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+
+fn config() -> TcpConfig {
+    TcpConfig { offer_window_scale: true, offer_sack: true, offer_timestamps: true, window_scale: 7, ..TcpConfig::default() }
+}
+
+/**
+ * 一张连接表作为仿真的一端, 帧就是序列化后的 IP 数据报
+ */
+struct Host {
+    table: ConnectionTable,
+    id: ConnectionId,
+    received: Vec<TcpSegment>,
+}
+
+impl Host {
+    fn new(id: ConnectionId) -> Self {
+        Host { table: ConnectionTable::new(&config()), id, received: vec![] }
+    }
+
+    fn frames(&mut self, segments: Vec<TcpSegment>) -> Vec<Vec<u8>> {
+        let config = Ipv4Config::default();
+        let mut frames: Vec<Vec<u8>> = segments.iter().filter_map(|seg| self.table.datagram(self.id, seg, &config)).map(|d| d.serialize()).collect();
+        for (id, seg) in self.table.poll_transmit(usize::MAX) {
+            frames.extend(self.table.datagram(id, &seg, &config).map(|d| d.serialize()));
+        }
+        frames
+    }
+}
+
+impl SimNode for Host {
+    fn receive(&mut self, frame: &[u8], now_ms: u64) -> Vec<Vec<u8>> {
+        let datagram = Ipv4Datagram::try_deserialize(frame).unwrap();
+        self.received.push(TcpSegment::try_deserialize(datagram.payload()).unwrap());
+        let replies = self.table.datagram_received(&datagram, now_ms);
+        self.frames(replies)
+    }
+
+    fn poll(&mut self, now_ms: u64) -> Vec<Vec<u8>> {
+        let out = self.table.tick(now_ms);
+        self.frames(out)
+    }
+
+    fn next_wakeup_ms(&mut self) -> Option<u64> {
+        None
+    }
+}
+
+fn simulation() -> Simulation<Host> {
+    let mut server = Host::new(ID.reversed());
+    server.table.listen(B_IP, 80);
+    let link = NetemLink::new(NetemConfig { delay_ms: 10, ..NetemConfig::default() }, 1);
+    let mut sim = Simulation::new(Host::new(ID), server, link, ManualClock::new(0));
+    sim.at(0, Event::act(Side::A, |host: &mut Host, now| {
+        let syn = host.table.connect(ID, now);
+        host.frames(vec![syn])
+    }));
+    sim
+}
+
+#[test]
+fn test_sender_honors_clamped_mss() {
+    let mut sim = simulation();
+    sim.link().a_to_b.set_mangler(Some(Box::new(MssClamp(536))));
+    sim.at(100, Event::act(Side::B, |host: &mut Host, _| {
+        assert_eq!(host.table.accept(listener_id(B_IP, 80)), Some(ID.reversed()));
+        host.table.write(ID.reversed(), &[7; 5000]).unwrap();
+        host.frames(vec![])
+    }));
+    assert_eq!(sim.run_until(10_000), SimOutcome::Idle);
+
+    let server = sim.node(Side::B);
+    assert_eq!(server.table.negotiated_options(server.id).unwrap().peer_mss, 536);
+    assert_eq!(server.table.state(server.id), Some(TcpState::Established));
+
+    let client = sim.node_mut(Side::A);
+    let sizes: Vec<usize> = client.received.iter().map(|seg| seg.data.len()).filter(|&len| len > 0).collect();
+    assert!(sizes.iter().all(|&len| len <= 536), "{:?}", sizes);
+    assert_eq!(sizes.iter().sum::<usize>(), 5000);
+    assert_eq!(client.table.read(ID, 8192).unwrap(), vec![7; 5000]);
+}
+
+#[test]
+fn test_options_stripped_from_retransmitted_syn() {
+    let mut sim = simulation();
+    // 第一个 SYN 原样通过, 之后的 SYN 只剩 MSS
+    let mut syns = 0;
+    sim.link().a_to_b.set_mangler(Some(Box::new(move |frame: Vec<u8>| {
+        Some(mangle_tcp(frame, |segment| {
+            if segment.SYN() {
+                syns += 1;
+                if syns > 1 {
+                    for kind in [3, 4, 8] {
+                        segment.remove_option(kind);
+                    }
+                }
+            }
+        }))
+    })));
+    // 第一个 SYN|ACK 丢失
+    let mut syn_acks = 0;
+    sim.link().b_to_a.set_mangler(Some(Box::new(move |frame: Vec<u8>| {
+        let datagram = Ipv4Datagram::try_deserialize(&frame).unwrap();
+        if TcpSegment::try_deserialize(datagram.payload()).unwrap().SYN() {
+            syn_acks += 1;
+            if syn_acks == 1 {
+                return None;
+            }
+        }
+        Some(frame)
+    })));
+    sim.at(1000, Event::act(Side::A, |host: &mut Host, _| {
+        assert_eq!(host.table.state(ID), Some(TcpState::SynSent));
+        let syn = host.table.retransmission(ID).unwrap();
+        host.frames(vec![syn])
+    }));
+    assert_eq!(sim.run_until(10_000), SimOutcome::Idle);
+    assert_eq!(sim.link().b_to_a.stats().dropped, 1);
+
+    // 服务端看到的第二个 SYN 确实被剥掉了选项
+    let syns: Vec<&TcpSegment> = sim.node(Side::B).received.iter().filter(|seg| seg.SYN()).collect();
+    assert_eq!(syns.len(), 2);
+    assert!(syns[0].parsed_options().unwrap().contains(&TcpOption::SackPermitted));
+    let stripped = syns[1].parsed_options().unwrap();
+    assert_eq!(stripped[0], TcpOption::Mss(1460));
+    assert!(stripped[1..].iter().all(|option| matches!(option, TcpOption::Nop | TcpOption::EndOfList)), "{:?}", stripped);
+
+    let expected = NegotiatedOptions { peer_mss: 1460, window_scale: Some((7, 7)), sack_permitted: true, timestamps: true };
+    for side in [Side::A, Side::B] {
+        let host = sim.node(side);
+        assert_eq!(host.table.state(host.id), Some(TcpState::Established), "{:?}", side);
+        assert_eq!(host.table.negotiated_options(host.id), Some(expected), "{:?}", side);
+    }
+}
@@ -0,0 +1,129 @@
+/**
+ * 多接口: A -- R -- B 三个节点, R 有两个接口并在它们之间转发
+ * 出接口只看路由表, ARP 缓存按接口分开; 强主机模型拒绝从别的接口进来的、发给本接口地址的数据报
+ */
+use simple_tcp_ip::config::{ArpConfig, HostModel, Ipv4Config};
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::link::interface::EthernetInterface;
+use simple_tcp_ip::net::interfaces::InterfaceSet;
+use simple_tcp_ip::net::ipv4::{Ipv4Datagram, Ipv4DatagramBuilder};
+use simple_tcp_ip::net::raw_socket::IpProtocol;
+use simple_tcp_ip::testing::topology::{host, stack, Topology};
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::utils::drops::DropReason;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const A_IP: u32 = 0x0a000102;
+const R_IP0: u32 = 0x0a000101;
+const R_IP1: u32 = 0x0a000201;
+const B_IP: u32 = 0x0a000202;
+const A_MAC: [u8; 6] = [0x02, 0, 0, 0, 1, 2];
+const R_MAC0: [u8; 6] = [0x02, 0, 0, 0, 1, 1];
+const R_MAC1: [u8; 6] = [0x02, 0, 0, 0, 2, 1];
+const B_MAC: [u8; 6] = [0x02, 0, 0, 0, 2, 2];
+
+fn router(config: &Ipv4Config) -> InterfaceSet {
+    let mut router = InterfaceSet::new(config, &ArpConfig::default());
+    assert_eq!(router.add_interface(EthernetInterface::new(R_MAC0, R_IP0, 24)), 0);
+    assert_eq!(router.add_interface(EthernetInterface::new(R_MAC1, R_IP1, 24)), 1);
+    router
+}
+
+/**
+ * 用 RFC 3692 的实验协议号, 载荷不经过 TCP/UDP 校验
+ */
+fn datagram(s_addr: u32, d_addr: u32, payload: &[u8]) -> Ipv4Datagram {
+    Ipv4DatagramBuilder::new().source(s_addr).destination(d_addr).protocol(IpProtocol::Other(253)).payload(payload.to_vec()).build().unwrap()
+}
+
+#[test]
+fn test_router_forwards_between_interfaces() {
+    let forwarding = Ipv4Config { forwarding: true, ..Ipv4Config::default() };
+    let mut net = Topology::new(stack(A_MAC, A_IP, Some(R_IP0)), router(&forwarding), stack(B_MAC, B_IP, Some(R_IP1)));
+
+    // 出接口和源地址只由路由表决定
+    assert_eq!(net.r.egress(B_IP).map(|hop| (hop.interface, hop.next_hop, hop.source)), Some((1, B_IP, R_IP1)));
+    assert_eq!(net.r.egress(A_IP).map(|hop| (hop.interface, hop.source)), Some((0, R_IP0)));
+    assert_eq!(net.r.egress(0x08080808), None);
+    assert_eq!(net.a.interfaces().egress(B_IP).map(|hop| hop.next_hop), Some(R_IP0));
+
+    assert_eq!(net.a.interfaces_mut().send(datagram(A_IP, B_IP, b"hello"), None, 0), Ok(0));
+    net.run();
+    let delivered = net.b.take_delivered();
+    assert_eq!(delivered.len(), 1);
+    let (datagram, arrival) = &delivered[0];
+    assert_eq!((datagram.s_addr(), datagram.payload(), datagram.ttl(), arrival.interface), (A_IP, &b"hello"[..], 63, 0));
+    assert!(net.r.take_delivered().is_empty());
+
+    // 每个接口只学到自己链路上的邻居
+    assert!(net.r.arp_cache(0).unwrap().state(A_IP).is_some());
+    assert!(net.r.arp_cache(0).unwrap().state(B_IP).is_none());
+    assert!(net.r.arp_cache(1).unwrap().state(B_IP).is_some());
+    assert!(net.r.arp_cache(1).unwrap().state(A_IP).is_none());
+
+    // 经过 R 建立一条 TCP 连接
+    net.b.tcp_mut().listen(B_IP, 80);
+    let id = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+    assert_eq!(net.a.connect(id, 0), Ok(0));
+    net.run();
+    assert_eq!(net.a.tcp().state(id), Some(TcpState::Established));
+    assert_eq!(net.b.tcp().state(id.reversed()), Some(TcpState::Established));
+}
+
+#[test]
+fn test_forwarding_requires_opt_in_and_ttl() {
+    let mut net = Topology::new(host(A_MAC, A_IP, Some(R_IP0)), router(&Ipv4Config::default()), host(B_MAC, B_IP, Some(R_IP1)));
+    net.a.send(datagram(A_IP, B_IP, b"hello"), None, 0).unwrap();
+    net.run();
+    assert!(net.b.take_delivered().is_empty());
+    assert_eq!(net.r.drop_counters().get(DropReason::NotForUs), 1);
+
+    net.r = router(&Ipv4Config { forwarding: true, ..Ipv4Config::default() });
+    let mut dying = datagram(A_IP, B_IP, b"hello");
+    while dying.ttl() > 1 {
+        dying.decrement_ttl();
+    }
+    net.a.send(dying, None, 0).unwrap();
+    net.run();
+    assert!(net.b.take_delivered().is_empty());
+    assert_eq!(net.r.drop_counters().get(DropReason::TtlExpired), 1);
+}
+
+/**
+ * 从 R 的接口 1 送进一个发给接口 0 地址的数据报
+ */
+fn cross_delivery(model: HostModel) -> (usize, u64) {
+    let mut r = router(&Ipv4Config { host_model: model, ..Ipv4Config::default() });
+    let frame = EthernetFrame::new(R_MAC1, B_MAC, 0x0800, datagram(B_IP, R_IP0, b"hi").serialize());
+    r.frame_received(1, frame.serialize());
+    r.poll(usize::MAX, 0);
+    (r.take_delivered().len(), r.drop_counters().get(DropReason::NotForUs))
+}
+
+#[test]
+fn test_host_models() {
+    assert_eq!(cross_delivery(HostModel::Weak), (1, 0));
+    assert_eq!(cross_delivery(HostModel::Strong), (0, 1));
+
+    // 强主机模型下发给到达接口自己的地址照常交付
+    let mut r = router(&Ipv4Config { host_model: HostModel::Strong, ..Ipv4Config::default() });
+    let frame = EthernetFrame::new(R_MAC1, B_MAC, 0x0800, datagram(B_IP, R_IP1, b"hi").serialize());
+    r.frame_received(1, frame.serialize());
+    r.poll(usize::MAX, 0);
+    assert_eq!(r.take_delivered().len(), 1);
+}
+
+#[test]
+fn test_poll_is_fair_across_interfaces() {
+    let mut r = router(&Ipv4Config::default());
+    for _ in 0..5 {
+        r.frame_received(0, EthernetFrame::new(R_MAC0, A_MAC, 0x0800, datagram(A_IP, R_IP0, b"x").serialize()).serialize());
+    }
+    r.frame_received(1, EthernetFrame::new(R_MAC1, B_MAC, 0x0800, datagram(B_IP, R_IP1, b"y").serialize()).serialize());
+
+    // 接口 0 排着 5 帧, 接口 1 的那一帧也在前两帧之内被处理
+    assert_eq!(r.poll(2, 0), 2);
+    let arrivals: Vec<usize> = r.take_delivered().iter().map(|(_, arrival)| arrival.interface).collect();
+    assert_eq!(arrivals, vec![0, 1]);
+    assert_eq!(r.poll(usize::MAX, 0), 4);
+}
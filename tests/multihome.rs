@@ -0,0 +1,53 @@
+/**
+ * 一块接口上有 10.0.0.1/24 和 192.168.1.1/24 两个地址
+ */
+use simple_tcp_ip::link::arp::{ArpPacket, OP_REPLY};
+use simple_tcp_ip::link::interface::EthernetInterface;
+use simple_tcp_ip::net::route::RoutingTable;
+use simple_tcp_ip::testing::packet::PacketBuilder;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+const PEER_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 9];
+
+fn iface() -> EthernetInterface {
+    let mut iface = EthernetInterface::new(MAC, 0x0a000001, 24);
+    iface.add_address(0xc0a80101, 24);
+    iface
+}
+
+#[test]
+fn test_answers_arp_for_every_address() {
+    let iface = iface();
+    for (peer, target) in [(0x0a000002, 0x0a000001), (0xc0a80102, 0xc0a80101)] {
+        let bytes = ArpPacket::request(PEER_MAC, peer, target).serialize();
+        let request = ArpPacket::try_deserialize(&bytes).unwrap();
+        let reply = iface.answer_arp(&request).unwrap();
+        assert_eq!((reply.op, reply.s_mac, reply.s_ip, reply.t_ip), (OP_REPLY, MAC, target, peer));
+    }
+    assert_eq!(iface.answer_arp(&ArpPacket::request(PEER_MAC, 0x0a000002, 0x0a000003)), None);
+}
+
+#[test]
+fn test_ping_source_selection() {
+    let iface = iface();
+    let mut routes = RoutingTable::new();
+    routes.add(0x0a000000, 24, None);
+    routes.add(0xc0a80100, 24, None);
+    routes.add(0, 0, Some(0x0a0000fe));
+
+    let ping = |routes: &RoutingTable, dst: u32| {
+        let source = iface.select_source(dst, routes);
+        let bytes = PacketBuilder::new().ipv4(source, dst).icmp(8, 0).payload(b"ping").build();
+        u32::from_be_bytes([bytes[12], bytes[13], bytes[14], bytes[15]])
+    };
+    assert_eq!(ping(&routes, 0x0a000005), 0x0a000001);
+    assert_eq!(ping(&routes, 0xc0a80105), 0xc0a80101);
+    assert_eq!(ping(&routes, 0x08080808), 0x0a000001); // 不直连, 用主地址
+
+    // 路由上的首选源地址优先, 但必须是本地地址
+    routes.set_preferred_source((0, 0), Some(0xc0a80101));
+    assert_eq!(ping(&routes, 0x08080808), 0xc0a80101);
+    routes.set_preferred_source((0, 0), Some(0x01020304));
+    assert_eq!(ping(&routes, 0x08080808), 0x0a000001);
+}
@@ -0,0 +1,107 @@
+/**
+ * NAT 转发: A -- R -- B, R 在接口 1 上把 A 的地址转换成自己的公网地址
+ * B 只看到 R 的地址和分配的公网端口; 应答经映射回到 A, 没有映射的数据报交给 R 自己
+ */
+use simple_tcp_ip::config::{ArpConfig, ConfigError, Ipv4Config, NatConfig};
+use simple_tcp_ip::link::interface::EthernetInterface;
+use simple_tcp_ip::net::interfaces::InterfaceSet;
+use simple_tcp_ip::net::ipv4::{Ipv4Datagram, Ipv4DatagramBuilder};
+use simple_tcp_ip::net::nat::NatProto;
+use simple_tcp_ip::net::raw_socket::IpProtocol;
+use simple_tcp_ip::testing::topology::{host, stack, Topology};
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::udp::UdpDatagram;
+use simple_tcp_ip::utils::drops::DropReason;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const A_IP: u32 = 0xc0a80002; // 192.168.0.2
+const R_INSIDE: u32 = 0xc0a80001;
+const R_PUBLIC: u32 = 0xcb007101; // 203.0.113.1
+const B_IP: u32 = 0xcb007114; // 203.0.113.20
+const A_MAC: [u8; 6] = [0x02, 0, 0, 0, 1, 2];
+const R_MAC0: [u8; 6] = [0x02, 0, 0, 0, 1, 1];
+const R_MAC1: [u8; 6] = [0x02, 0, 0, 0, 2, 1];
+const B_MAC: [u8; 6] = [0x02, 0, 0, 0, 2, 2];
+
+fn nat_router() -> InterfaceSet {
+    let mut router = InterfaceSet::new(&Ipv4Config { forwarding: true, ..Ipv4Config::default() }, &ArpConfig::default());
+    router.add_interface(EthernetInterface::new(R_MAC0, R_INSIDE, 24));
+    router.add_interface(EthernetInterface::new(R_MAC1, R_PUBLIC, 24));
+    router.set_nat(1, NatConfig { public_ip: R_PUBLIC, port_min: 50000, port_max: 50099, ..NatConfig::default() }).unwrap();
+    router
+}
+
+fn udp(s_addr: u32, d_addr: u32, s_port: u16, d_port: u16, data: &[u8]) -> Ipv4Datagram {
+    let udp = UdpDatagram::new(s_port, d_port, data.to_vec(), s_addr, d_addr);
+    Ipv4DatagramBuilder::new().source(s_addr).destination(d_addr).protocol(IpProtocol::Udp).payload(udp.serialize()).build().unwrap()
+}
+
+#[test]
+fn test_tcp_through_nat() {
+    let mut net = Topology::new(stack(A_MAC, A_IP, Some(R_INSIDE)), nat_router(), stack(B_MAC, B_IP, Some(R_PUBLIC)));
+    let listener = net.b.tcp_mut().listen(B_IP, 80);
+
+    let id = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+    net.a.connect(id, 0).unwrap();
+    net.run();
+    assert_eq!(net.a.tcp().state(id), Some(TcpState::Established));
+
+    // B 看到的对端是 R 的公网地址和分配的端口
+    let peer = net.b.tcp_mut().accept(listener).unwrap();
+    assert_eq!(peer, ConnectionId { s_ip: B_IP, s_port: 80, d_ip: R_PUBLIC, d_port: 50000 });
+    let mapping = *net.r.nat().unwrap().mappings().next().unwrap();
+    assert_eq!((mapping.proto, mapping.inside_ip, mapping.inside_port, mapping.public_port), (NatProto::Tcp, A_IP, 40000, 50000));
+
+    net.a.tcp_mut().write(id, b"upload").unwrap();
+    net.b.tcp_mut().write(peer, b"download").unwrap();
+    net.run();
+    assert_eq!(net.b.tcp_mut().read(peer, usize::MAX).unwrap(), b"upload");
+    assert_eq!(net.a.tcp_mut().read(id, usize::MAX).unwrap(), b"download");
+    assert_eq!(net.r.nat().unwrap().len(), 1);
+    assert_eq!(net.r.drop_counters().total(), 0);
+    assert!(net.r.take_delivered().is_empty());
+}
+
+#[test]
+fn test_unmapped_and_untranslatable() {
+    let mut net = Topology::new(host(A_MAC, A_IP, Some(R_INSIDE)), nat_router(), host(B_MAC, B_IP, Some(R_PUBLIC)));
+
+    // UDP 建立映射, B 的应答回到 A
+    net.a.send(udp(A_IP, B_IP, 5353, 53, b"query"), None, 0).unwrap();
+    net.run();
+    let (query, _) = net.b.take_delivered().pop().unwrap();
+    assert_eq!(query.s_addr(), R_PUBLIC);
+    let request = UdpDatagram::try_deserialize(query.payload()).unwrap();
+    assert!(request.check_checksum(R_PUBLIC, B_IP));
+    net.b.send(udp(B_IP, R_PUBLIC, 53, request.s_port, b"answer"), None, 0).unwrap();
+    net.run();
+    let (answer, _) = net.a.take_delivered().pop().unwrap();
+    assert_eq!((answer.s_addr(), answer.d_addr()), (B_IP, A_IP));
+    let response = UdpDatagram::try_deserialize(answer.payload()).unwrap();
+    assert!(response.check_checksum(B_IP, A_IP));
+    assert_eq!((response.d_port, &response.data[..]), (5353, &b"answer"[..]));
+
+    // 发往公网地址但没有映射: 交给 R 自己, 不转发
+    net.b.send(udp(B_IP, R_PUBLIC, 53, 7, b"stray"), None, 0).unwrap();
+    net.run();
+    assert_eq!(net.r.take_delivered().len(), 1);
+    assert!(net.a.take_delivered().is_empty());
+
+    // 不能转换的协议不以内网地址发出去
+    let gre = Ipv4DatagramBuilder::new().source(A_IP).destination(B_IP).protocol(IpProtocol::Other(47)).payload(vec![0; 4]).build().unwrap();
+    net.a.send(gre, None, 0).unwrap();
+    net.run();
+    assert!(net.b.take_delivered().is_empty());
+    assert_eq!(net.r.drop_counters().get(DropReason::Untranslatable), 1);
+
+    // 空闲超时后映射回收
+    net.r.tick(NatConfig::default().udp_idle_ms + 1);
+    assert!(net.r.nat().unwrap().is_empty());
+}
+
+#[test]
+fn test_invalid_nat_config_rejected() {
+    let mut router = nat_router();
+    let inverted = NatConfig { public_ip: R_PUBLIC, port_min: 60000, port_max: 50000, ..NatConfig::default() };
+    assert_eq!(router.set_nat(1, inverted), Err(ConfigError::NatPortRangeInverted { port_min: 60000, port_max: 50000 }));
+}
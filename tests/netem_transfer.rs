@@ -0,0 +1,105 @@
+/**
+ * 在双向 5% 丢包、有延迟抖动的链路上用两张连接表传输 1 MB
+ * 两端是一对 ChannelPair 设备, 各由 Netem 包住损伤自己的发送方向
+ * 每端是一个 TableHost, 它交出的 IP 数据报装进以太网帧发出, 收到的帧校验 FCS 后去掉以太网头交给它
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::link::device::{ChannelEnd, NetworkDevice};
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::testing::netem::{Netem, NetemConfig};
+use simple_tcp_ip::testing::sim::SimNode;
+use simple_tcp_ip::testing::table_host::TableHost;
+use simple_tcp_ip::transport::tcp_connection::ConnectionId;
+use simple_tcp_ip::utils::clock::ManualClock;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const A_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+const B_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 2];
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const ID: ConnectionId = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+const TOTAL: usize = 1 << 20;
+const TIME_LIMIT_MS: u64 = 600_000;
+
+/**
+ * 一端: 连接表和它的设备, 以及对端的 MAC
+ */
+struct Endpoint {
+    host: TableHost,
+    device: Netem<ChannelEnd>,
+    mac: [u8; 6],
+    peer_mac: [u8; 6],
+}
+
+impl Endpoint {
+    fn send(&mut self, datagrams: Vec<Vec<u8>>) {
+        for datagram in datagrams {
+            let frame = EthernetFrame::new(self.peer_mac, self.mac, 0x0800, datagram).serialize();
+            self.device.transmit(&frame).unwrap();
+        }
+    }
+
+    /**
+     * 收完设备里到达的帧, 再让连接表做定时处理
+     */
+    fn step(&mut self, now_ms: u64) {
+        while let Some(bytes) = self.device.receive().unwrap() {
+            let frame = EthernetFrame::from_frame_vec(bytes).unwrap();
+            assert!(frame.check_fcs());
+            assert_eq!(frame.d_mac(), self.mac);
+            let replies = self.host.receive(frame.unpadded_payload(), now_ms);
+            self.send(replies);
+        }
+        let frames = self.host.poll(now_ms);
+        self.send(frames);
+    }
+}
+
+/**
+ * 返回 (收到的数据, 发送端的重传次数, 结束时刻)
+ */
+fn transfer(seed: u64) -> (Vec<u8>, u64, u64) {
+    let data: Vec<u8> = (0..TOTAL).map(|i| (i * 31 % 251) as u8).collect();
+    let netem = NetemConfig { delay_ms: 10, jitter_ms: 4, ..NetemConfig::default() }.with_loss(0.05);
+    let clock = ManualClock::new(0);
+    let (a_dev, b_dev) = Netem::pair(netem.clone(), netem, seed, clock.clone());
+    let tcp = TcpConfig { mss: 1000, ..TcpConfig::default() };
+    let mut a = Endpoint { host: TableHost::new(&tcp, ID, data.clone()), device: a_dev, mac: A_MAC, peer_mac: B_MAC };
+    let mut b = Endpoint { host: TableHost::listening(&tcp, ID.reversed(), vec![]), device: b_dev, mac: B_MAC, peer_mac: A_MAC };
+
+    let syn = a.host.table.connect(ID, 0);
+    let frames = a.host.frames(vec![syn], 0);
+    a.send(frames);
+
+    let mut now: u64 = 0;
+    loop {
+        a.step(now);
+        b.step(now);
+        if a.host.finished_at.is_some() && b.host.received.len() == TOTAL {
+            break;
+        }
+
+        // 时钟直接推进到下一个事件: 帧到达、重传或 pacing 放行
+        let next = [a.device.next_delivery_ms(), b.device.next_delivery_ms(), a.host.next_wakeup_ms(), b.host.next_wakeup_ms()]
+            .into_iter().flatten().min().expect("transfer stalled");
+        now = next.max(now + 1);
+        assert!(now < TIME_LIMIT_MS, "transfer did not finish");
+        clock.set(now);
+    }
+    assert!(b.host.received == data, "received bytes differ from the data sent");
+    assert_eq!(a.host.drops.total(), 0);
+    assert_eq!(b.host.drops.total(), 0);
+    (b.host.received, a.host.table.retransmissions(), now)
+}
+
+#[test]
+fn test_1mb_over_lossy_link() {
+    let (received, retransmissions, elapsed) = transfer(2024);
+    assert_eq!(received.len(), TOTAL);
+    assert!(retransmissions > 0);
+    assert!(elapsed > 0);
+
+    // 同一种子结果完全相同
+    let (_, again, elapsed_again) = transfer(2024);
+    assert_eq!((retransmissions, elapsed), (again, elapsed_again));
+}
@@ -0,0 +1,79 @@
+/*
+ * 与宿主机 Linux 内核协议栈的互通测试, 需要 `--features os-interop` 且以 root 运行:
+ * cargo test --features os-interop --test os_interop -- --ignored
+ * 协议栈还没有 accept/connect, 这里由测试手工应答 ARP 和 TCP, 校验内核发出的报文能被解析、构造的报文被内核接受
+ */
+#![cfg(all(target_os = "linux", feature = "os-interop"))]
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddrV4, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::testing::osnet::{self, HostNet};
+use simple_tcp_ip::testing::packet::PacketBuilder;
+use simple_tcp_ip::transport::tcp_option::TcpOption;
+use simple_tcp_ip::transport::tcp_segment::{TcpFlags, TcpSegment};
+
+const HOST_IP: u32 = 0x0ad30001; // 10.211.0.1
+const STACK_IP: u32 = 0x0ad30002; // 10.211.0.2
+const STACK_MAC: [u8; 6] = [0x02, 0, 0, 0, 0xd3, 0x02];
+#[test]
+#[ignore]
+fn host_syn_is_parsed_and_rst_is_accepted() {
+    let mut net = HostNet::setup("stcpip0", HOST_IP, 24).expect("needs root and /dev/net/tun");
+
+    let client = thread::spawn(|| {
+        let addr = SocketAddrV4::new(Ipv4Addr::from(STACK_IP), 7);
+        TcpStream::connect_timeout(&addr.into(), Duration::from_secs(5)).map(|_| ())
+    });
+
+    let syn = loop {
+        let bytes = net.tap.read_frame().unwrap();
+        if osnet::parse_arp(&bytes).is_some() {
+            if let Some(reply) = osnet::arp_reply_to(&bytes, STACK_MAC, STACK_IP) {
+                net.tap.write_frame(&reply).unwrap();
+            }
+            continue;
+        }
+        let frame = EthernetFrame::try_deserialize(&bytes).unwrap();
+        let Ok(datagram) = Ipv4Datagram::try_deserialize(frame.payload()) else { continue };
+        if datagram.protocol() != 6 || datagram.d_addr() != STACK_IP {
+            continue; // 内核可能发出 IPv6 邻居发现等无关报文
+        }
+        let segment = TcpSegment::try_deserialize(datagram.payload()).unwrap();
+        assert!(segment.check_checksum(datagram.s_addr(), datagram.d_addr()));
+        break (frame.s_mac(), segment);
+    };
+
+    let (host_mac, segment) = syn;
+    assert!(segment.ctrl.contains(TcpFlags::SYN));
+    let options = segment.parsed_options().unwrap();
+    assert!(options.iter().any(|o| matches!(o, TcpOption::Mss(mss) if *mss > 0)));
+    assert!(options.iter().any(|o| matches!(o, TcpOption::WindowScale(_))));
+
+    // 用构造器回 RST|ACK, 内核接受后 connect 立即以 ConnectionRefused 失败
+    let rst = PacketBuilder::ether(STACK_MAC, host_mac)
+        .ipv4(STACK_IP, HOST_IP)
+        .tcp(7, segment.s_port)
+        .flags(TcpFlags::RST | TcpFlags::ACK)
+        .seq(0)
+        .ack(segment.seq.wrapping_add(1))
+        .window(0)
+        .build();
+    net.tap.write_frame(&rst).unwrap();
+
+    let err = client.join().unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+}
+
+#[test]
+#[ignore]
+fn host_answers_our_arp_request() {
+    let mut net = HostNet::setup("stcpip1", 0x0ad40001, 24).expect("needs root and /dev/net/tun");
+    let host_mac = net.resolve_host(STACK_MAC, 0x0ad40002, Duration::from_secs(2)).unwrap();
+    assert_ne!(host_mac, [0; 6]);
+    assert_ne!(host_mac, STACK_MAC);
+}
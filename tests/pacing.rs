@@ -0,0 +1,56 @@
+/**
+ * 2 MB 经过 8 Mbit/s、单程 20ms、队列 8 帧的链路, 两端各是一个 TableHost
+ * 不开 pacing 时慢启动的 cwnd 按 ACK 时钟整批发出, 突发超过队列就溢出; 开启后连接表按 cwnd / SRTT 的速率发出,
+ * 溢出只剩下探到瓶颈时的少量丢包, 等待重传超时的时间也相应减少
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::testing::netem::{NetemConfig, NetemLink};
+use simple_tcp_ip::testing::sim::{Event, Side, SimOutcome, Simulation};
+use simple_tcp_ip::testing::table_host::TableHost;
+use simple_tcp_ip::transport::tcp_connection::ConnectionId;
+use simple_tcp_ip::utils::clock::ManualClock;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const ID: ConnectionId = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+const TOTAL: usize = 2_000_000;
+
+struct Outcome {
+    overflowed: u64,
+    finished_ms: u64,
+    retransmissions: u64,
+}
+
+fn transfer(pacing: bool, seed: u64) -> Outcome {
+    let config = TcpConfig { mss: 1000, pacing, ..TcpConfig::default() };
+    let link = NetemConfig { delay_ms: 20, bandwidth_bps: Some(8_000_000), queue_limit: Some(8), ..NetemConfig::default() };
+    let data: Vec<u8> = (0..TOTAL).map(|i| (i * 13 % 251) as u8).collect();
+    let server = TableHost::listening(&config, ID.reversed(), vec![]);
+    let mut sim = Simulation::new(TableHost::new(&config, ID, data), server, NetemLink::new(link, seed), ManualClock::new(0));
+    sim.at(0, Event::act(Side::A, |host: &mut TableHost, now| {
+        let syn = host.table.connect(ID, now);
+        host.frames(vec![syn], now)
+    }));
+    assert_eq!(sim.run_until(3_600_000), SimOutcome::Idle);
+
+    let overflowed = sim.link().a_to_b.stats().overflowed;
+    let (a, b) = (sim.node(Side::A), sim.node(Side::B));
+    assert_eq!(b.received, a.data);
+    assert_eq!(a.table.pacing_rate_bps(ID).is_some(), pacing);
+    Outcome {
+        overflowed,
+        finished_ms: a.finished_at.unwrap(),
+        retransmissions: a.table.retransmissions(),
+    }
+}
+
+#[test]
+fn test_pacing_reduces_queue_overflow() {
+    let bursty = transfer(false, 7);
+    let paced = transfer(true, 7);
+    assert!(paced.overflowed * 3 < bursty.overflowed, "{} vs {}", paced.overflowed, bursty.overflowed);
+    assert!(paced.retransmissions < bursty.retransmissions);
+    assert!(paced.finished_ms * 3 < bursty.finished_ms * 2, "{} vs {}", paced.finished_ms, bursty.finished_ms);
+}
+
+
@@ -0,0 +1,44 @@
+/**
+ * 以太网补齐字节不能混入上层载荷
+ * 1 字节载荷的 TCP 报文段 -> IPv4 -> 补齐到 64 字节的以太网帧 -> 逐层解析
+ */
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::testing::packet::PacketBuilder;
+use simple_tcp_ip::transport::tcp_segment::{TcpCtrlFlag, TcpSegment};
+use simple_tcp_ip::utils::wire::WireDeserialize;
+
+const S_ADDR: u32 = 0x0a000001;
+const D_ADDR: u32 = 0x0a000002;
+
+#[test]
+fn test_one_byte_payload_through_padded_frame() {
+    let wire = PacketBuilder::ether([0x02, 0, 0, 0, 0, 1], [0x02, 0, 0, 0, 0, 2])
+        .ipv4(S_ADDR, D_ADDR).ip_id(1)
+        .tcp(40000, 80).flags(TcpCtrlFlag::ACK as u16 | TcpCtrlFlag::PSH as u16).seq(1000).ack(2000).window(8192)
+        .payload(b"x")
+        .build();
+    assert_eq!(wire.len(), 64); // 41 字节的 IP 数据报被补齐到 46 字节
+
+    let frame = EthernetFrame::deserialize(&wire).unwrap();
+    assert!(frame.check_fcs());
+    assert_eq!(frame.payload().len(), 46);
+
+    let datagram = Ipv4Datagram::deserialize(frame.payload()).unwrap();
+    assert_eq!(datagram.payload().len(), 21);
+
+    let segment = TcpSegment::deserialize(datagram.payload()).unwrap();
+    assert_eq!(segment.data, b"x");
+    assert!(segment.check_checksum(datagram.s_addr(), datagram.d_addr()));
+}
+
+#[test]
+fn test_checksum_detects_corruption() {
+    let mut segment = TcpSegment::new(40000, 80, 1000, 2000, 5, 0, TcpCtrlFlag::ACK as u16, 8192, 0, vec![], b"y".to_vec());
+    segment.generate_checksum(S_ADDR, D_ADDR);
+    assert!(segment.check_checksum(S_ADDR, D_ADDR));
+    assert!(!segment.check_checksum(S_ADDR, D_ADDR + 1));
+
+    segment.data[0] = b'z';
+    assert!(!segment.check_checksum(S_ADDR, D_ADDR));
+}
@@ -0,0 +1,137 @@
+/**
+ * 确定性的解析链模糊测试
+ * 结构化随机 + 纯随机字节依次送入 Ethernet -> IPv4 -> TCP/ICMP, 任何一层都不能 panic
+ * 与 fuzz/fuzz_targets/parse_chain.rs 使用相同的解析链
+ */
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::net::icmp_v4::IcmpV4;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const ROUNDS: usize = 150_000;
+
+/**
+ * xorshift64*, 固定种子保证每次运行结果相同
+ */
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % (n as u64)) as usize
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) {
+        for byte in buf.iter_mut() {
+            *byte = self.next_u64() as u8;
+        }
+    }
+}
+
+fn parse_chain(bytes: &[u8]) {
+    let frame = match EthernetFrame::try_deserialize(bytes) {
+        Ok(frame) => frame,
+        Err(_) => return,
+    };
+    let _ = frame.check_fcs();
+    let _ = frame.serialize();
+
+    let datagram = match Ipv4Datagram::try_deserialize(frame.payload()) {
+        Ok(datagram) => datagram,
+        Err(_) => return,
+    };
+    let _ = datagram.serialize();
+
+    match datagram.protocol() {
+        6 => {
+            if let Ok(segment) = TcpSegment::try_deserialize(datagram.payload()) {
+                let _ = segment.serialize();
+                let _ = segment.to_string();
+            }
+        }
+        1 => {
+            if let Ok(icmp) = IcmpV4::try_deserialize(datagram.payload()) {
+                let _ = IcmpV4::check(&icmp.serialize());
+            }
+        }
+        _ => {}
+    }
+}
+
+/**
+ * 先构造合法的以太网/IPv4/TCP 头部, 再随机篡改若干字节并随机截断
+ */
+fn structured_input(rng: &mut Rng) -> Vec<u8> {
+    let mut bytes: Vec<u8> = vec![0; 14 + 60 + 60 + rng.below(64) + 4];
+    rng.fill(&mut bytes);
+    bytes[12] = 0x08;
+    bytes[13] = 0x00;
+    let ihl = 5 + rng.below(11);
+    bytes[14] = 0x40 | ihl as u8;
+    bytes[14 + 9] = if rng.below(2) == 0 { 6 } else { 1 };
+    let total_len = (bytes.len() - 14 - 4) as u16;
+    bytes[14 + 2..14 + 4].copy_from_slice(&total_len.to_be_bytes());
+    let tcp_start = 14 + ihl * 4;
+    bytes[tcp_start + 12] = ((5 + rng.below(11)) as u8) << 4 | (bytes[tcp_start + 12] & 0x0f);
+
+    for _ in 0..rng.below(4) {
+        let idx = rng.below(bytes.len());
+        bytes[idx] = rng.next_u64() as u8;
+    }
+    let keep = rng.below(bytes.len() + 1);
+    bytes.truncate(keep);
+    bytes
+}
+
+#[test]
+fn fuzz_structured_inputs() {
+    let mut rng = Rng(0x5eed_1234_abcd_0001);
+    for _ in 0..ROUNDS {
+        let bytes = structured_input(&mut rng);
+        parse_chain(&bytes);
+    }
+}
+
+/**
+ * 构造函数接受任意字段值, 序列化时不能溢出
+ */
+#[test]
+fn fuzz_serializers_with_arbitrary_fields() {
+    let mut rng = Rng(0x5eed_1234_abcd_0003);
+    for _ in 0..ROUNDS / 10 {
+        let r = rng.next_u64();
+        let mut payload: Vec<u8> = vec![0; rng.below(64)];
+        rng.fill(&mut payload);
+
+        let datagram = Ipv4Datagram::new(r as u8, (r >> 8) as u8, 0, (r >> 16) as u16, 0, (r >> 32) as u8,
+            (r >> 40) as u16, 64, 6, 0x0a000001, 0x0a000002, payload.clone());
+        let _ = datagram.serialize();
+
+        let segment = TcpSegment::new(1, 2, r as u32, (r >> 32) as u32, (r >> 8) as u8, (r >> 16) as u8,
+            (r >> 24) as u16, 0, 0, vec![], payload.clone());
+        let _ = segment.serialize();
+
+        let _ = IcmpV4::new(r as u8, (r >> 8) as u8, payload);
+    }
+}
+
+#[test]
+fn fuzz_random_bytes() {
+    let mut rng = Rng(0x5eed_1234_abcd_0002);
+    for _ in 0..ROUNDS {
+        let mut bytes: Vec<u8> = vec![0; rng.below(200)];
+        rng.fill(&mut bytes);
+        parse_chain(&bytes);
+        // 各层解析器也单独接受任意字节
+        let _ = Ipv4Datagram::try_deserialize(&bytes);
+        let _ = TcpSegment::try_deserialize(&bytes);
+        let _ = IcmpV4::try_deserialize(&bytes);
+    }
+}
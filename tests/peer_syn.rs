@@ -0,0 +1,51 @@
+/**
+ * 被动打开时保留对端 SYN 的内容: 选项、ISN、窗口, 以及承载它的数据报的 TTL 和 DSCP
+ * 测试内的粘合代码把客户端的报文段封装成数据报交给服务器
+ */
+use simple_tcp_ip::config::{Ipv4Config, TcpConfig};
+use simple_tcp_ip::net::dscp::Dscp;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::socket_options::SocketOptions;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, PeerSynInfo};
+use simple_tcp_ip::transport::tcp_option::TcpOption;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+
+#[test]
+fn test_accept_reports_peer_syn() {
+    let mut client = ConnectionTable::new(&TcpConfig { mss: 1400, window_scale: 7, offer_window_scale: true, ..TcpConfig::default() });
+    let mut server = ConnectionTable::new(&TcpConfig::default());
+    let listener = server.listen(B_IP, 80);
+    let id = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+
+    let mut options = SocketOptions { ttl: Some(33), ..SocketOptions::default() };
+    options.set_dscp(Dscp::EF);
+    let syn = client.connect_with_options(id, options, 0);
+    let datagram = client.datagram(id, &syn, &Ipv4Config::default()).unwrap();
+    let syn_ack = server.datagram_received(&datagram, 1).pop().unwrap();
+
+    // 握手完成之前就能查询, 还不能 accept
+    let expected = PeerSynInfo {
+        options: vec![TcpOption::Mss(1400), TcpOption::Nop, TcpOption::WindowScale(7)],
+        isn: syn.seq,
+        window: syn.win_size,
+        ttl: Some(33),
+        dscp: Some(Dscp::EF),
+    };
+    assert_eq!(server.peer_syn(id.reversed()), Some(&expected));
+    assert_eq!(server.accept_with_syn(listener), None);
+
+    let ack = client.segment_received(B_IP, A_IP, &syn_ack, 2).pop().unwrap();
+    server.segment_received(A_IP, B_IP, &ack, 3);
+    assert_eq!(server.accept_with_syn(listener), Some((id.reversed(), expected.clone())));
+    assert!(!expected.has_fast_open_cookie());
+
+    // 不经过 IP 层交上来的 SYN 没有 TTL 和 DSCP; 主动打开的一端没有 PeerSynInfo
+    let id = ConnectionId { s_port: 40001, ..id };
+    let syn = client.connect(id, 10);
+    server.segment_received(A_IP, B_IP, &syn, 11);
+    let info = server.peer_syn(id.reversed()).unwrap();
+    assert_eq!((info.ttl, info.dscp), (None, None));
+    assert_eq!(client.peer_syn(id), None);
+}
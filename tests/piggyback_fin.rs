@@ -0,0 +1,83 @@
+/**
+ * 写完最后的数据就关闭: 剩下的数据放得进一段时 FIN 捎带在这段上
+ * 对端一次确认数据和 FIN; 确认丢失后的重传与第一次逐字节相同
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::transport::connection_table::{listener_id, ConnectionTable};
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_segment::TcpFlags;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const ID: ConnectionId = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+
+/**
+ * 握手完成的一对连接表, 返回客户端的 ISN
+ */
+fn established() -> (ConnectionTable, ConnectionTable, u32) {
+    let mut client = ConnectionTable::new(&TcpConfig::default());
+    let mut server = ConnectionTable::new(&TcpConfig::default());
+    server.listen(B_IP, 80);
+    let syn = client.connect(ID, 0);
+    let syn_ack = server.segment_received(A_IP, B_IP, &syn, 0).pop().unwrap();
+    let ack = client.segment_received(B_IP, A_IP, &syn_ack, 0).pop().unwrap();
+    server.segment_received(A_IP, B_IP, &ack, 0);
+    assert_eq!(server.accept(listener_id(B_IP, 80)), Some(ID.reversed()));
+    (client, server, syn.seq)
+}
+
+#[test]
+fn test_final_write_carries_fin() {
+    let (mut client, mut server, isn) = established();
+    client.write(ID, &[7; 100]).unwrap();
+    client.close(ID, 10).unwrap();
+
+    let sent = client.poll_transmit(usize::MAX);
+    assert_eq!(sent.len(), 1);
+    let segment = &sent[0].1;
+    assert!(segment.ctrl.contains(TcpFlags::ACK | TcpFlags::FIN));
+    assert_eq!((segment.seq, segment.data.len()), (isn.wrapping_add(1), 100));
+
+    // 一个确认同时覆盖 100 字节和 FIN
+    let ack = server.segment_received(A_IP, B_IP, segment, 20).pop().unwrap();
+    assert_eq!(ack.ack, isn.wrapping_add(1 + 100 + 1));
+    assert_eq!(server.state(ID.reversed()), Some(TcpState::CloseWait));
+    assert_eq!(server.read(ID.reversed(), usize::MAX).unwrap(), vec![7; 100]);
+
+    client.segment_received(B_IP, A_IP, &ack, 30);
+    assert!(client.poll_transmit(usize::MAX).is_empty()); // 合并的 ACK 在这里交给发送端
+    assert_eq!(client.state(ID), Some(TcpState::FinWait2));
+}
+
+#[test]
+fn test_retransmission_keeps_fin() {
+    let (mut client, mut server, isn) = established();
+    client.write(ID, &[7; 100]).unwrap();
+    client.close(ID, 10).unwrap();
+    let (_, segment) = client.poll_transmit(usize::MAX).pop().unwrap();
+
+    // 对端收到了, 但确认丢失: 重传与第一次逐字节相同, 对端回重复确认
+    let lost_ack = server.segment_received(A_IP, B_IP, &segment, 20).pop().unwrap();
+    let again = client.retransmission(ID).unwrap();
+    assert_eq!(again.serialize(), segment.serialize());
+    let ack = server.segment_received(A_IP, B_IP, &again, 1020).pop().unwrap();
+    assert_eq!((ack.ack, lost_ack.ack), (isn.wrapping_add(102), isn.wrapping_add(102)));
+    assert_eq!(server.read(ID.reversed(), usize::MAX).unwrap().len(), 100);
+
+    client.segment_received(B_IP, A_IP, &ack, 1030);
+    assert!(client.poll_transmit(usize::MAX).is_empty());
+    assert_eq!(client.state(ID), Some(TcpState::FinWait2));
+    assert!(client.retransmission(ID).is_none());
+}
+
+#[test]
+fn test_data_larger_than_one_segment_sends_fin_on_last() {
+    let (mut client, _server, _) = established();
+    client.write(ID, &[1; 3000]).unwrap();
+    client.close(ID, 10).unwrap();
+    let sent: Vec<_> = client.poll_transmit(usize::MAX).into_iter().map(|(_, segment)| segment).collect();
+    assert_eq!(sent.len(), 3);
+    assert!(sent[..2].iter().all(|segment| !segment.FIN()));
+    assert!(sent[2].FIN() && !sent[2].data.is_empty());
+}
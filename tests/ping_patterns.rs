@@ -0,0 +1,66 @@
+/**
+ * ICMP 回显的载荷核对与大报文分片
+ * 链路是测试内的 1500 MTU 点对点线路: 超过 MTU 的数据报先分片, 对端重组后再处理
+ */
+use simple_tcp_ip::config::Ipv4Config;
+use simple_tcp_ip::net::icmp_v4::IcmpV4;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::net::pinger::{echo_reply, PayloadPattern, PingOutcome, Pinger};
+use simple_tcp_ip::net::reassembly::Ipv4Reassembler;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const HOST: u32 = 0x0a000001;
+const PEER: u32 = 0x0a000002;
+const MTU: u16 = 1500;
+
+/**
+ * 一个方向的线路: 发送端分片, 线路检查 MTU, 接收端重组
+ */
+fn transmit(icmp: &IcmpV4, s_addr: u32, d_addr: u32, id: u16, reassembler: &mut Ipv4Reassembler) -> IcmpV4 {
+    let payload = icmp.serialize();
+    let datagram = Ipv4Datagram::new(4, 5, 0, (20 + payload.len()) as u16, id, 0, 0, 64, 1, s_addr, d_addr, payload);
+    let mut whole = None;
+    for fragment in datagram.fragment(MTU).unwrap() {
+        let bytes = fragment.serialize();
+        assert!(bytes.len() <= MTU as usize);
+        whole = reassembler.push(Ipv4Datagram::try_deserialize(&bytes).unwrap(), 0);
+    }
+    IcmpV4::try_deserialize(whole.unwrap().payload()).unwrap()
+}
+
+#[test]
+fn test_corrupting_responder_is_detected() {
+    let mut pinger = Pinger::new(0x1234, 64).with_pattern(PayloadPattern::Fixed(0x55));
+    let request = pinger.next_probe(0);
+    let mut data = echo_reply(&request).unwrap().data().to_vec();
+    data[4 + 37] ^= 0x01; // 有缺陷的对端翻转了载荷中的一位
+    let reply = IcmpV4::new(0, 0, data);
+    assert_eq!(pinger.on_reply(&reply, 5), Some(PingOutcome::PayloadMismatch { seq: 0, first_diff_offset: 37 }));
+    assert!(pinger.expire(10_000).is_empty()); // 不会再当作丢失
+}
+
+#[test]
+fn test_large_probe_is_fragmented() {
+    let config = Ipv4Config::default();
+    let mut at_peer = Ipv4Reassembler::new(&config);
+    let mut at_host = Ipv4Reassembler::new(&config);
+    let mut pinger = Pinger::new(1, 2000);
+
+    let request = transmit(&pinger.next_probe(0), HOST, PEER, 100, &mut at_peer);
+    let reply = transmit(&echo_reply(&request).unwrap(), PEER, HOST, 200, &mut at_host);
+    assert_eq!(pinger.on_reply(&reply, 3), Some(PingOutcome::Reply { seq: 0, size: 2000, rtt_ms: 3 }));
+}
+
+#[test]
+fn test_size_sweep_across_mtu() {
+    let config = Ipv4Config::default();
+    let mut at_peer = Ipv4Reassembler::new(&config);
+    let mut at_host = Ipv4Reassembler::new(&config);
+    let mut pinger = Pinger::new(1, 0).with_sweep(1400, 50, 1600).with_pattern(PayloadPattern::Custom(b"abc".to_vec()));
+    for i in 0..5u16 {
+        let size = pinger.size();
+        let request = transmit(&pinger.next_probe(0), HOST, PEER, i, &mut at_peer);
+        let reply = transmit(&echo_reply(&request).unwrap(), PEER, HOST, i, &mut at_host);
+        assert_eq!(pinger.on_reply(&reply, 1), Some(PingOutcome::Reply { seq: i, size, rtt_ms: 1 }));
+    }
+}
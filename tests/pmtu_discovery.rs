@@ -0,0 +1,125 @@
+/**
+ * 路径上有一跳 MTU 为 1000: 带 DF 的大报文被丢弃并回 ICMP 需要分片,
+ * 发送端据此调小 MSS、按新大小重传, 直到整个传输完成
+ * 第一个测试用测试内的简化发送端(停等, 每次发一个段)只检验 PmtuCache; 第二个经 ConnectionTable::icmp_received
+ */
+use simple_tcp_ip::config::{Ipv4Config, TcpConfig};
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::net::pmtu::{PmtuCache, PMTU_EXPIRY_MS};
+use simple_tcp_ip::testing::packet::PacketBuilder;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::ConnectionId;
+use simple_tcp_ip::transport::tcp_segment::{TcpFlags, TcpSegment};
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const SENDER: u32 = 0x0a000001;
+const RECEIVER: u32 = 0x0a010001;
+const ROUTER: u32 = 0x0a000002;
+const HOP_MTU: u16 = 1000;
+const ISN: u32 = 5000;
+
+/**
+ * 中间路由器: 超过下一跳 MTU 且带 DF 的数据报换成发回源端的 ICMP type 3 code 4
+ */
+fn router(datagram: Vec<u8>) -> Result<Vec<u8>, Vec<u8>> {
+    if datagram.len() <= HOP_MTU as usize {
+        return Ok(datagram);
+    }
+    let parsed = Ipv4Datagram::try_deserialize(&datagram).unwrap();
+    assert_eq!(datagram[6] & 0x40, 0x40, "TCP datagrams must carry DF");
+    let mut data = vec![0, 0];
+    data.extend_from_slice(&HOP_MTU.to_be_bytes());
+    data.extend_from_slice(&datagram[..28]);
+    Err(PacketBuilder::new().ipv4(ROUTER, parsed.s_addr()).icmp(3, 4).payload(&data).build())
+}
+
+#[test]
+fn test_transfer_converges_to_hop_mtu() {
+    let data: Vec<u8> = (0..100_000u32).map(|i| (i * 7) as u8).collect();
+    let mut cache = PmtuCache::new(1500);
+    let mut received: Vec<u8> = Vec::with_capacity(data.len());
+    let mut sent = 0usize;
+    let mut icmp_errors = 0;
+    let mut now_ms = 0;
+
+    while sent < data.len() {
+        let mss = cache.mss_for(RECEIVER, now_ms) as usize;
+        let chunk = &data[sent..(sent + mss).min(data.len())];
+        let datagram = PacketBuilder::new().ipv4(SENDER, RECEIVER).tcp(40000, 80)
+            .flags(TcpFlags::ACK | TcpFlags::PSH).seq(ISN.wrapping_add(sent as u32)).payload(chunk).build();
+        now_ms += 1;
+        match router(datagram) {
+            Ok(delivered) => {
+                let ip = Ipv4Datagram::try_deserialize(&delivered).unwrap();
+                let segment = TcpSegment::try_deserialize(ip.payload()).unwrap();
+                assert_eq!(segment.seq.wrapping_sub(ISN) as usize, received.len());
+                received.extend_from_slice(&segment.data);
+                sent += chunk.len();
+            }
+            Err(icmp) => {
+                icmp_errors += 1;
+                let ip = Ipv4Datagram::try_deserialize(&icmp).unwrap();
+                assert_eq!(cache.on_icmp_error(ip.payload(), now_ms), Some((RECEIVER, HOP_MTU)));
+            }
+        }
+    }
+
+    assert_eq!(icmp_errors, 1);
+    assert_eq!(cache.mss_for(RECEIVER, now_ms), 960);
+    assert_eq!(received, data);
+}
+
+/**
+ * client 发出的段经 router: 放得下的交给 server, 返回 server 的应答; 放不下的换成 ICMP 交给 client
+ */
+fn send_through(client: &mut ConnectionTable, server: &mut ConnectionTable, id: ConnectionId, segment: &TcpSegment, now_ms: u64) -> Vec<TcpSegment> {
+    let datagram = client.datagram(id, segment, &Ipv4Config::default()).unwrap().serialize();
+    match router(datagram) {
+        Ok(_) => server.segment_received(SENDER, RECEIVER, segment, now_ms),
+        Err(icmp) => {
+            assert_eq!(client.icmp_received(&Ipv4Datagram::try_deserialize(&icmp).unwrap(), now_ms), Some(id));
+            vec![]
+        }
+    }
+}
+
+fn handshake(client: &mut ConnectionTable, server: &mut ConnectionTable, id: ConnectionId, now_ms: u64) {
+    let syn = client.connect(id, now_ms);
+    for reply in send_through(client, server, id, &syn, now_ms) {
+        for ack in client.segment_received(RECEIVER, SENDER, &reply, now_ms) {
+            send_through(client, server, id, &ack, now_ms);
+        }
+    }
+}
+
+fn sizes(client: &mut ConnectionTable, data: &[u8], id: ConnectionId) -> Vec<usize> {
+    client.write(id, data).unwrap();
+    client.poll_transmit(usize::MAX).into_iter().map(|(_, segment)| segment.data.len()).collect()
+}
+
+#[test]
+fn test_connection_table_shares_discovered_mtu() {
+    let mut client = ConnectionTable::new(&TcpConfig::default());
+    let mut server = ConnectionTable::new(&TcpConfig::default());
+    server.listen(RECEIVER, 80);
+    let first = ConnectionId { s_ip: SENDER, s_port: 40000, d_ip: RECEIVER, d_port: 80 };
+    handshake(&mut client, &mut server, first, 0);
+    assert_eq!(client.path_mtu(RECEIVER), 1500);
+
+    // 第一个满长的段被路由器退回, 之后的新数据按 HOP_MTU 分段
+    client.write(first, &[1; 1460]).unwrap();
+    let (_, full) = client.poll_transmit(usize::MAX).pop().unwrap();
+    assert!(send_through(&mut client, &mut server, first, &full, 10).is_empty());
+    assert_eq!(client.path_mtu(RECEIVER), HOP_MTU);
+    assert_eq!(sizes(&mut client, &[2; 2000], first), vec![960, 960, 80]);
+
+    // 同一目的地址的新连接一开始就按发现的 MTU 分段, 不必再丢一次
+    let second = ConnectionId { s_port: 40001, ..first };
+    handshake(&mut client, &mut server, second, 20);
+    assert_eq!(sizes(&mut client, &[3; 1460], second), vec![960, 500]);
+
+    // 过期之后重新按链路 MTU 试探
+    client.tick(10 + PMTU_EXPIRY_MS);
+    assert_eq!(client.path_mtu(RECEIVER), 1500);
+    assert_eq!(sizes(&mut client, &[4; 1460], second), vec![1460]);
+}
@@ -0,0 +1,63 @@
+/**
+ * 公开接口: 只用 prelude 在回环接口上跑一次回显, 用到的类型都必须能从 prelude 拿到
+ */
+use std::io::{Read, Write};
+
+use simple_tcp_ip::prelude::*;
+
+/**
+ * 连接表发出的段经回环接口送回同一张表, 直到没有报文在途
+ */
+fn pump(table: &mut ConnectionTable, lo: &mut LoopbackInterface, config: &StackConfig, now_ms: u64) {
+    loop {
+        for (id, segment) in table.poll(now_ms) {
+            assert!(lo.transmit(&table.datagram(id, &segment, &config.ipv4).unwrap()));
+        }
+        let Some(received) = lo.receive() else {
+            return;
+        };
+        let (datagram, _) = received.unwrap();
+        let segment = TcpSegment::try_deserialize(datagram.payload()).unwrap();
+        let id = ConnectionId::for_incoming(datagram.s_addr(), datagram.d_addr(), &segment);
+        for reply in table.datagram_received(&datagram, now_ms) {
+            assert!(lo.transmit(&table.datagram(id, &reply, &config.ipv4).unwrap()));
+        }
+    }
+}
+
+#[test]
+fn test_loopback_echo_through_prelude() {
+    let config = StackConfig::default();
+    config.validate().unwrap();
+    let localhost = parse_ipv4("127.0.0.1").unwrap();
+    let mut table = ConnectionTable::new(&config.tcp);
+    let mut lo = LoopbackInterface::new();
+
+    let listener = table.listen(localhost, 7);
+    let client = ConnectionId { s_ip: localhost, s_port: 40000, d_ip: localhost, d_port: 7 };
+    let syn = table.connect(client, 0);
+    assert!(lo.transmit(&table.datagram(client, &syn, &config.ipv4).unwrap()));
+    pump(&mut table, &mut lo, &config, 0);
+    let server = table.accept(listener).unwrap();
+    assert_eq!(server, client.reversed());
+    assert_eq!(table.state(client), Some(TcpState::Established));
+
+    table.stream(client).write_all(b"hello, loopback").unwrap();
+    pump(&mut table, &mut lo, &config, 10);
+    let mut buf = [0; 64];
+    let n = table.stream(server).read(&mut buf).unwrap();
+    table.stream(server).write_all(&buf[..n]).unwrap();
+    pump(&mut table, &mut lo, &config, 20);
+    let n = table.stream(client).read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"hello, loopback");
+
+    // 双方关闭, 读端看到 EOF; 错误类型同样经 prelude 可见
+    table.close(client, 30).unwrap();
+    pump(&mut table, &mut lo, &config, 30);
+    assert_eq!(table.stream(server).read(&mut buf).unwrap(), 0);
+    table.close(server, 40).unwrap();
+    pump(&mut table, &mut lo, &config, 40);
+    assert_eq!(table.state(client), Some(TcpState::TimeWait));
+    assert_eq!(table.write(server, b"late"), Err(ConnectionError::Closed));
+    assert_eq!(format_ipv4(client.d_ip), "127.0.0.1");
+}
@@ -0,0 +1,92 @@
+/**
+ * PSH 落在每次 write 的最后一个段上, 以及 FIN 之前的最后一个数据段上
+ * 打开 Nagle 时不满 MSS 的段在有未确认数据时被扣留, flush 让它立即发出
+ */
+use std::io::Write;
+
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::testing::pair::TablePair;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::socket_options::SocketOptions;
+use simple_tcp_ip::transport::tcp_connection::ConnectionId;
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+
+const CLIENT: u32 = 0x0a000001;
+const SERVER: u32 = 0x0a000002;
+const LINK: TablePair = TablePair::new(CLIENT, SERVER);
+const ID: ConnectionId = ConnectionId { s_ip: CLIENT, s_port: 40000, d_ip: SERVER, d_port: 80 };
+const MSS: u16 = 1000;
+
+fn connected(options: SocketOptions) -> (ConnectionTable, ConnectionTable) {
+    let config = TcpConfig { mss: MSS, ..TcpConfig::default() };
+    let mut client = ConnectionTable::new(&config);
+    let mut server = ConnectionTable::new(&config);
+    server.listen(SERVER, 80);
+    let syn = client.connect_with_options(ID, options, 0);
+    LINK.exchange(&mut client, &mut server, vec![syn]);
+    (client, server)
+}
+
+fn transmit(client: &mut ConnectionTable) -> Vec<TcpSegment> {
+    client.poll_transmit(usize::MAX).into_iter().map(|(_, segment)| segment).collect()
+}
+
+/**
+ * 每个段的 (长度, 是否带 PSH)
+ */
+fn shape(segments: &[TcpSegment]) -> Vec<(usize, bool)> {
+    segments.iter().map(|segment| (segment.data.len(), segment.PSH())).collect()
+}
+
+#[test]
+fn test_psh_marks_end_of_each_write() {
+    let (mut client, mut server) = connected(SocketOptions::default());
+    client.write(ID, &[1; 3000]).unwrap();
+    client.write(ID, &[2; 700]).unwrap();
+    client.write(ID, &[3; 1500]).unwrap();
+    let segments = transmit(&mut client);
+    // 第三次 write 从第四个段的中间开始, 第四个段只因第二次 write 结束而带 PSH
+    assert_eq!(shape(&segments), vec![(1000, false), (1000, false), (1000, true), (1000, true), (1000, false), (200, true)]);
+    LINK.exchange(&mut client, &mut server, segments);
+    assert_eq!(server.read(ID.reversed(), usize::MAX).unwrap().len(), 5200);
+
+    // 捎带 FIN 的最后一个数据段也带 PSH
+    client.write(ID, &[4; 1200]).unwrap();
+    client.close(ID, 0).unwrap();
+    let segments = transmit(&mut client);
+    assert_eq!(shape(&segments), vec![(1000, false), (200, true)]);
+    assert!(segments[1].FIN());
+}
+
+#[test]
+fn test_flush_defeats_nagle() {
+    let (mut client, mut server) = connected(SocketOptions { nagle: true, ..SocketOptions::default() });
+
+    // 没有在途数据时小段照常发出; 它未被确认之前, 下一个小段被扣留
+    client.write(ID, b"GET").unwrap();
+    let first = transmit(&mut client);
+    assert_eq!(shape(&first), vec![(3, true)]);
+    client.write(ID, b" /index").unwrap();
+    assert!(transmit(&mut client).is_empty());
+
+    // flush 之后立即发出
+    client.flush(ID).unwrap();
+    let flushed = transmit(&mut client);
+    assert_eq!(shape(&flushed), vec![(7, true)]);
+
+    // 之后写入的数据重新受 Nagle 约束: 凑满 MSS 的段照常发出, 不满的尾巴被扣留
+    client.write(ID, b".html").unwrap();
+    client.write(ID, &[0; 2000]).unwrap();
+    let full = transmit(&mut client);
+    assert_eq!(shape(&full), vec![(1000, true), (1000, false)]); // 第一个段包含 ".html" 的结尾
+
+    // std::io 的 flush 同样放行被扣留的尾巴
+    client.stream(ID).flush().unwrap();
+    let rest = transmit(&mut client);
+    assert_eq!(shape(&rest), vec![(5, true)]);
+
+    LINK.exchange(&mut client, &mut server, [first, flushed, full, rest].concat());
+    let received = server.read(ID.reversed(), usize::MAX).unwrap();
+    assert_eq!(&received[..15], b"GET /index.html");
+    assert_eq!(received.len(), 2015);
+}
@@ -0,0 +1,81 @@
+/**
+ * 乱序但不丢包的链路: 被选中的帧越过前面的帧先到, 接收端为此回重复 ACK
+ * DupAck 模式把乱序误判为丢包而快速重传; Rack 模式按发送时间判断, 同一种子下没有重传
+ * 报文段序列化后经 NetemLink 传输, 测试内的循环把时钟推进到下一个事件
+ */
+use simple_tcp_ip::config::{LossDetection, TcpConfig};
+use simple_tcp_ip::testing::netem::{NetemConfig, NetemLink};
+use simple_tcp_ip::transport::connection_table::{listener_id, ConnectionTable};
+use simple_tcp_ip::transport::tcp_connection::ConnectionId;
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const TOTAL: usize = 300_000;
+
+/**
+ * 返回 (收到的数据, 重传次数)
+ */
+fn transfer(mode: LossDetection, seed: u64) -> (Vec<u8>, u64) {
+    let config = TcpConfig { mss: 1000, initial_cwnd: 40, loss_detection: mode, ..TcpConfig::default() };
+    let mut client = ConnectionTable::new(&config);
+    let mut server = ConnectionTable::new(&TcpConfig::default());
+    let id = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+    server.listen(B_IP, 80);
+    let syn = client.connect(id, 0);
+    let syn_ack = server.segment_received(A_IP, B_IP, &syn, 0).pop().unwrap();
+    let ack = client.segment_received(B_IP, A_IP, &syn_ack, 0).pop().unwrap();
+    server.segment_received(A_IP, B_IP, &ack, 0);
+    let peer = server.accept(listener_id(B_IP, 80)).unwrap();
+
+    let forward = NetemConfig { delay_ms: 20, jitter_ms: 0, reorder: 0.25, ..NetemConfig::default() };
+    let backward = NetemConfig { delay_ms: 20, ..NetemConfig::default() };
+    let mut link = NetemLink::with_configs(forward, backward, seed);
+
+    let data: Vec<u8> = (0..TOTAL).map(|i| (i * 17 % 251) as u8).collect();
+    let mut written = 0;
+    let mut received = vec![];
+    let mut retransmissions = 0;
+    let mut now = 0;
+    while received.len() < TOTAL {
+        assert!(now < 60_000, "transfer stalled");
+        client.tick(now);
+        written += client.write(id, &data[written..]).unwrap();
+        for (_, segment) in client.poll_transmit(64) {
+            link.a_to_b.send(segment.serialize(), now);
+        }
+        if client.fast_retransmit_due(id) {
+            let segment = client.retransmission(id).unwrap();
+            link.a_to_b.send(segment.serialize(), now);
+            retransmissions += 1;
+        }
+
+        for bytes in link.a_to_b.poll(now) {
+            let segment = TcpSegment::try_deserialize(&bytes).unwrap();
+            for reply in server.segment_received(A_IP, B_IP, &segment, now) {
+                link.b_to_a.send(reply.serialize(), now);
+            }
+        }
+        received.extend(server.read(peer, usize::MAX).unwrap());
+        for bytes in link.b_to_a.poll(now) {
+            client.segment_received(B_IP, A_IP, &TcpSegment::try_deserialize(&bytes).unwrap(), now);
+        }
+        client.poll_transmit(0); // 把本轮合并的 ACK 交给发送端, 再决定下一个事件
+        let next = [link.next_delivery_ms(), client.loss_timer_ms(id)].into_iter().flatten().min();
+        now = next.unwrap_or(now + 1).max(now + 1);
+    }
+    assert_eq!(link.a_to_b.stats().dropped, 0);
+    assert!(link.a_to_b.stats().reordered > 0);
+    (received, retransmissions)
+}
+
+#[test]
+fn test_reordering_without_loss() {
+    let (data, dup_ack) = transfer(LossDetection::DupAck, 7);
+    assert_eq!(data.len(), TOTAL);
+    let (data, rack) = transfer(LossDetection::Rack, 7);
+    assert_eq!(data.len(), TOTAL);
+    assert!(dup_ack >= 3, "dup-ack retransmissions: {}", dup_ack);
+    assert_eq!(rack, 0);
+}
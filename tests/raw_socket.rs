@@ -0,0 +1,77 @@
+/**
+ * 原始套接字: 协议栈处理的协议(ICMP)只是多一个旁观者, 其他协议号由原始套接字独占
+ * 测试内的 host_input 代替协议栈的 IP 输入: 先复制给原始套接字, 再按协议号处理
+ */
+use simple_tcp_ip::config::Ipv4Config;
+use simple_tcp_ip::net::icmp_v4::IcmpV4;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::net::pinger::{echo_reply_datagram, PingOutcome, Pinger, ECHO_REPLY, ECHO_REQUEST};
+use simple_tcp_ip::net::raw_socket::{IpProtocol, RawSendOptions, RawSockets};
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const HOST: u32 = 0x0a000001;
+const PEER: u32 = 0x0a000002;
+const EXPERIMENTAL: u8 = 253; // RFC 3692 留作实验的协议号
+
+/**
+ * 返回协议栈自己的应答: 回显应答, 或没有任何消费者时的协议不可达
+ */
+fn host_input(raw: &mut RawSockets, datagram: &Ipv4Datagram) -> Option<Ipv4Datagram> {
+    let copies = raw.deliver(datagram);
+    match IpProtocol::from(datagram.protocol()) {
+        IpProtocol::Icmp => echo_reply_datagram(datagram, HOST, 0),
+        protocol if protocol.handled() => None,
+        _ if copies > 0 => None,
+        _ => {
+            let icmp = IcmpV4::dest_unreachable(2, &datagram.serialize()).serialize();
+            Some(Ipv4Datagram::new(4, 5, 0, (20 + icmp.len()) as u16, 0, 0, 0, 64, 1, HOST, datagram.s_addr(), icmp))
+        }
+    }
+}
+
+#[test]
+fn test_raw_icmp_socket_sees_requests_the_stack_answers() {
+    let config = Ipv4Config::default();
+    let mut host_raw = RawSockets::new(HOST, &config);
+    let monitor = host_raw.raw_socket(IpProtocol::Icmp);
+
+    // 对端的 ping 程序同样经原始套接字收发
+    let mut peer_raw = RawSockets::new(PEER, &config);
+    let icmp = peer_raw.raw_socket(IpProtocol::Icmp);
+    let mut pinger = Pinger::new(0x4242, 32);
+    let request = pinger.send_probe(&mut peer_raw, icmp, HOST, 0).unwrap();
+    assert_eq!((request.s_addr(), request.d_addr(), request.protocol(), request.ttl()), (PEER, HOST, 1, 64));
+    assert!(request.check_hdr_checksum());
+
+    let reply = host_input(&mut host_raw, &request).unwrap();
+    let (header, payload) = host_raw.recv(monitor).unwrap();
+    assert_eq!((header.s_addr, header.protocol), (PEER, 1));
+    assert_eq!(IcmpV4::try_deserialize(&payload).unwrap().icmp_type(), ECHO_REQUEST);
+    assert!(host_raw.recv(monitor).is_none());
+
+    assert_eq!(peer_raw.deliver(&reply), 1);
+    assert_eq!(pinger.receive(&mut peer_raw, icmp, 7), vec![PingOutcome::Reply { seq: 0, size: 32, rtt_ms: 7 }]);
+    assert_eq!(IcmpV4::try_deserialize(reply.payload()).unwrap().icmp_type(), ECHO_REPLY);
+}
+
+#[test]
+fn test_raw_socket_is_sole_consumer_of_unhandled_protocol() {
+    let config = Ipv4Config::default();
+    let mut host_raw = RawSockets::new(HOST, &config);
+    let mut peer_raw = RawSockets::new(PEER, &config);
+    let handle = host_raw.raw_socket(IpProtocol::Other(EXPERIMENTAL));
+    let sender = peer_raw.raw_socket(IpProtocol::Other(EXPERIMENTAL));
+    let options = RawSendOptions { ttl: Some(9), tos: 0xb8, dont_fragment: true };
+    let datagram = peer_raw.send(sender, HOST, b"new protocol".to_vec(), &options).unwrap();
+    assert_eq!((datagram.protocol(), datagram.ttl(), datagram.tos(), datagram.dont_fragment()), (EXPERIMENTAL, 9, 0xb8, true));
+
+    assert!(host_input(&mut host_raw, &datagram).is_none());
+    let (header, payload) = host_raw.recv(handle).unwrap();
+    assert_eq!((header.protocol, header.ttl, payload), (EXPERIMENTAL, 9, b"new protocol".to_vec()));
+
+    // 没有人绑定这个协议号时, 协议栈回协议不可达
+    host_raw.close(handle);
+    let unreachable = host_input(&mut host_raw, &datagram).unwrap();
+    let icmp = IcmpV4::try_deserialize(unreachable.payload()).unwrap();
+    assert_eq!((icmp.icmp_type(), icmp.code()), (3, 2));
+}
@@ -0,0 +1,17 @@
+/**
+ * 按序到达的数据走重组器的快路径: 不查询失序缓存, recv 内除了追加数据本身不做任何分配
+ * 用 `cargo test --release --test reassembly_bench -- --nocapture` 查看耗时
+ */
+use simple_tcp_ip::testing::bench::{self, CountingAllocator};
+use simple_tcp_ip::utils::clock::MonotonicClock;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn bench_in_order_reassembly() {
+    let report = bench::in_order_reassembly(1460, 20_000, &MonotonicClock::new());
+    println!("{}", report);
+    assert_eq!((report.fast_path, report.slow_path), (report.calls, 0));
+    assert_eq!(report.allocations, 0);
+}
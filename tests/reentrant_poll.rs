@@ -0,0 +1,163 @@
+/**
+ * 重复 poll 与重入: 同一时刻重复 poll 和只 poll 一次对外表现相同, 时钟回退按已经见过的时刻处理并计数
+ * 回调里再驱动共享的连接表得到 PollError::Reentrant
+ */
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::testing::pair::TablePair;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::isn::IsnGenerator;
+use simple_tcp_ip::transport::shared_table::{PollError, SharedTable};
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, IdleAction, TcpState};
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const CLIENT: u32 = 0x0a000001;
+const SERVER: u32 = 0x0a000002;
+const ID: ConnectionId = ConnectionId { s_ip: CLIENT, s_port: 40000, d_ip: SERVER, d_port: 80 };
+
+/**
+ * 每个时刻线路上出现的段
+ */
+type Trace = Vec<(u64, Vec<Vec<u8>>)>;
+
+fn table(config: &TcpConfig) -> ConnectionTable {
+    let mut table = ConnectionTable::new(config);
+    table.set_isn_generator(IsnGenerator::with_secret([1, 2]));
+    table
+}
+
+/**
+ * 客户端发出的段交给服务器, 应答交回客户端, 每次投递之后两边 poll, 直到没有要发的; 返回两个方向上的段
+ */
+fn deliver(client: &mut ConnectionTable, server: &mut ConnectionTable, to_server: Vec<TcpSegment>, now: u64) -> Vec<Vec<u8>> {
+    let exchanged = TablePair::new(CLIENT, SERVER).ticking().at(now).exchange(client, server, to_server);
+    exchanged.wire.iter().map(|segment| segment.serialize()).collect()
+}
+
+/**
+ * 建立连接, 传数据, 然后空闲超时放弃连接; 每个时刻客户端 poll `polls` 次, 返回线路上出现的所有段
+ */
+fn scenario(polls: usize) -> (Trace, ConnectionTable) {
+    let config = TcpConfig { send_buffer: 4000, ..TcpConfig::default() };
+    let (mut client, mut server) = (table(&config), table(&config));
+    server.listen(SERVER, 80);
+    let mut trace = vec![];
+    for now in (0..=6_000).step_by(100) {
+        match now {
+            0 => {
+                let syn = client.connect(ID, now);
+                client.set_idle_timeout(ID, Some(Duration::from_millis(2_000)), IdleAction::Abort).unwrap();
+                trace.push((now, deliver(&mut client, &mut server, vec![syn], now)));
+            }
+            500 | 700 => {
+                client.write(ID, &[7; 3000]).unwrap();
+            }
+            _ => {}
+        }
+        let mut out = vec![];
+        for _ in 0..polls {
+            out.extend(client.poll(now).into_iter().map(|(_, segment)| segment));
+        }
+        let wire = deliver(&mut client, &mut server, out, now);
+        if !wire.is_empty() {
+            trace.push((now, wire));
+        }
+    }
+    assert_eq!(server.read(ID.reversed(), usize::MAX).unwrap().len(), 6000);
+    (trace, client)
+}
+
+#[test]
+fn test_double_poll_matches_single_poll() {
+    let (once, client) = scenario(1);
+    let (twice, _) = scenario(2);
+    let (thrice, _) = scenario(3);
+    assert_eq!(once, twice);
+    assert_eq!(once, thrice);
+
+    // 空闲超时只放弃一次: 2700ms 的 RST 是最后一个段
+    let (last_ms, last) = once.last().unwrap();
+    assert_eq!((*last_ms, last.len()), (2_700, 1));
+    assert!(TcpSegment::try_deserialize(&last[0]).unwrap().RST());
+    assert_eq!(client.state(ID), Some(TcpState::Closed));
+    assert_eq!(client.clock_regressions(), 0);
+}
+
+#[test]
+fn test_backwards_clock_is_clamped() {
+    let config = TcpConfig::default();
+    let (mut client, mut server) = (table(&config), table(&config));
+    server.listen(SERVER, 80);
+    let syn = client.connect(ID, 0);
+    deliver(&mut client, &mut server, vec![syn], 0);
+    client.set_idle_timeout(ID, Some(Duration::from_millis(1_000)), IdleAction::Abort).unwrap();
+
+    assert!(client.poll(600).is_empty());
+    // 时钟跳回去: 计数, 不重新计时
+    assert!(client.poll(100).is_empty());
+    assert_eq!(client.clock_regressions(), 1);
+    assert!(client.poll(999).is_empty());
+
+    // 回退的时刻收到的段也按见过的时刻处理
+    client.write(ID, b"ping").unwrap();
+    let mut acks = vec![];
+    for (_, segment) in client.poll(999) {
+        acks.extend(server.segment_received(CLIENT, SERVER, &segment, 999));
+    }
+    acks.extend(server.poll(999).into_iter().map(|(_, segment)| segment));
+    assert_eq!(acks.len(), 1);
+    client.segment_received(SERVER, CLIENT, &acks[0], 50);
+    assert_eq!(client.clock_regressions(), 2);
+    assert_eq!(server.clock_regressions(), 0);
+
+    // 最后一次数据在 999ms, 超时在 1999ms 到期且只到期一次
+    assert!(client.poll(1_998).is_empty());
+    let rst = client.poll(1_999);
+    assert_eq!(rst.len(), 1);
+    assert!(rst[0].1.RST());
+    assert!(client.poll(1_999).is_empty());
+    assert!(client.poll(1_000).is_empty());
+    assert_eq!(client.clock_regressions(), 3);
+    assert_eq!(client.state(ID), Some(TcpState::Closed));
+}
+
+#[test]
+fn test_nested_poll_from_callback_is_rejected() {
+    let config = TcpConfig { send_buffer: 2000, ..TcpConfig::default() };
+    let (mut client, mut server) = (table(&config), table(&config));
+    server.listen(SERVER, 80);
+    let syn = client.connect(ID, 0);
+    deliver(&mut client, &mut server, vec![syn], 0);
+    assert_eq!(client.write(ID, &[1; 5000]), Ok(2000));
+
+    let shared = SharedTable::new(client);
+    let nested = Rc::new(RefCell::new(vec![]));
+    let (inner, seen) = (shared.clone(), nested.clone());
+    shared.with(|table| table.on_writable(ID, Box::new(move || {
+        seen.borrow_mut().push((inner.poll(10).err(), inner.with(|table| table.write(ID, b"more")).err()));
+    }))).unwrap().unwrap();
+
+    // 数据发出并被确认, 发送缓冲区腾出空间, 可写回调在 poll 中触发
+    let out = shared.poll(10).unwrap().into_iter().map(|(_, segment)| segment).collect::<Vec<_>>();
+    let mut acks = vec![];
+    for segment in &out {
+        acks.extend(server.segment_received(CLIENT, SERVER, segment, 10));
+    }
+    acks.extend(server.poll(10).into_iter().map(|(_, segment)| segment));
+    shared.with(|table| {
+        for ack in &acks {
+            table.segment_received(SERVER, CLIENT, ack, 10);
+        }
+    }).unwrap();
+    assert!(nested.borrow().is_empty());
+    shared.poll(10).unwrap();
+    assert_eq!(*nested.borrow(), vec![(Some(PollError::Reentrant), Some(PollError::Reentrant))]);
+
+    // poll 返回之后照常可用
+    assert_eq!(shared.with(|table| table.write(ID, b"more")).unwrap(), Ok(4));
+    assert_eq!(format!("{:?}", shared), "SharedTable { busy: false }");
+}
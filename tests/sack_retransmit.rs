@@ -0,0 +1,87 @@
+/**
+ * 发送 10 段, 第 3、6 段首次发送时丢失
+ * 接收端回复累计 ACK + SACK 块(经过真实的报文编码), 发送端只应重传这两段
+ */
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::testing::packet::PacketBuilder;
+use simple_tcp_ip::transport::retransmit_queue::RetransmitQueue;
+use simple_tcp_ip::transport::tcp_segment::{TcpFlags, TcpSegment};
+
+const ISN: u32 = 0xffff_fc00; // 传输过程中序号回绕
+const MSS: u32 = 100;
+
+/**
+ * 测试用的接收端: 记录收到的区间, 生成 ACK + SACK (最多 3 块, 最新的块在前)
+ */
+struct Receiver {
+    ack: u32,
+    out_of_order: Vec<(u32, u32)>,
+}
+
+impl Receiver {
+    fn on_segment(&mut self, seq: u32, len: u32) -> Vec<u8> {
+        let end = seq.wrapping_add(len);
+        if seq == self.ack {
+            self.ack = end;
+            while let Some(i) = self.out_of_order.iter().position(|&(left, _)| left == self.ack) {
+                self.ack = self.out_of_order.remove(i).1;
+            }
+        } else {
+            // 与相邻块合并, 新块放在最前
+            let mut block = (seq, end);
+            self.out_of_order.retain(|&(left, right)| {
+                if right == block.0 { block.0 = left; false }
+                else if left == block.1 { block.1 = right; false }
+                else { true }
+            });
+            self.out_of_order.insert(0, block);
+        }
+        let blocks: Vec<(u32, u32)> = self.out_of_order.iter().take(3).copied().collect();
+        let mut builder = PacketBuilder::new().ipv4(2, 1).tcp(80, 40000).flags(TcpFlags::ACK).ack(self.ack);
+        if !blocks.is_empty() {
+            builder = builder.sack(&blocks);
+        }
+        builder.build()
+    }
+}
+
+fn parse_ack(bytes: &[u8]) -> (u32, Vec<(u32, u32)>) {
+    let datagram = Ipv4Datagram::try_deserialize(bytes).unwrap();
+    let segment = TcpSegment::try_deserialize(datagram.payload()).unwrap();
+    assert!(segment.check_checksum(2, 1));
+    (segment.ack, segment.sack_blocks())
+}
+
+#[test]
+fn test_only_holes_are_retransmitted() {
+    let mut queue = RetransmitQueue::new();
+    let mut receiver = Receiver { ack: ISN, out_of_order: vec![] };
+    let mut retransmitted = vec![];
+
+    for i in 0..10u32 {
+        let seq = ISN.wrapping_add(i * MSS);
+        queue.push(seq, vec![i as u8; MSS as usize]);
+        if i == 3 || i == 6 {
+            continue; // 首次发送丢失
+        }
+        let (ack, blocks) = parse_ack(&receiver.on_segment(seq, MSS));
+        queue.ack_received(ack, &blocks);
+    }
+    assert_eq!(queue.snd_una(), Some(ISN.wrapping_add(3 * MSS)));
+    assert_eq!(queue.len(), 7); // 被 SACK 的段仍然保留, 直到累计确认
+
+    // 快速重传: 逐个重发空洞, 每次用新 ACK 更新记分板
+    loop {
+        let lost = queue.lost(1);
+        let Some(&(seq, end)) = lost.first() else { break };
+        let segment = queue.retransmit(seq).unwrap();
+        assert_eq!(segment.data.len() as u32, end.wrapping_sub(seq));
+        retransmitted.push(seq);
+        let (ack, blocks) = parse_ack(&receiver.on_segment(seq, MSS));
+        queue.ack_received(ack, &blocks);
+    }
+
+    assert_eq!(retransmitted, vec![ISN.wrapping_add(3 * MSS), ISN.wrapping_add(6 * MSS)]);
+    assert!(queue.is_empty());
+    assert_eq!(receiver.ack, ISN.wrapping_add(10 * MSS));
+}
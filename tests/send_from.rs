@@ -0,0 +1,124 @@
+/**
+ * send_from: 应用把数据源直接交给连接, 数据只在发送缓冲区里出现一次
+ * 数据确认之前要留着重传, 预读不超过拥塞窗口加 SEND_FROM_MARGIN_SEGMENTS 个段, 不会把整个数据源读进来
+ */
+use std::io::{self, Cursor, Read};
+
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::testing::pair::TablePair;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionError, ConnectionId, SEND_FROM_MARGIN_SEGMENTS};
+use simple_tcp_ip::utils::memory::MemoryComponent;
+
+const CLIENT: u32 = 0x0a000001;
+const SERVER: u32 = 0x0a000002;
+const LINK: TablePair = TablePair::new(CLIENT, SERVER).polling();
+const ID: ConnectionId = ConnectionId { s_ip: CLIENT, s_port: 40000, d_ip: SERVER, d_port: 80 };
+
+fn connected(config: &TcpConfig) -> (ConnectionTable, ConnectionTable) {
+    let mut client = ConnectionTable::new(config);
+    let mut server = ConnectionTable::new(config);
+    server.listen(SERVER, 80);
+    let syn = client.connect(ID, 0);
+    LINK.exchange(&mut client, &mut server, vec![syn]);
+    (client, server)
+}
+
+/**
+ * 每次最多读 chunk 字节的数据源, 模拟按块读文件
+ */
+struct Chunked<R> {
+    inner: R,
+    chunk: usize,
+}
+
+impl<R: Read> Read for Chunked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.chunk);
+        self.inner.read(&mut buf[..n])
+    }
+}
+
+/**
+ * 预读随 cwnd 增长, 但每一轮都不超过当时的 cwnd 加余量
+ * 服务端读完之后靠窗口更新定时器重新打开窗口, 每轮推进时钟并 tick 服务端
+ */
+#[test]
+fn test_stream_10mb_with_bounded_buffering() {
+    let config = TcpConfig { send_buffer: 64 * 1024, window_update_interval_ms: 1, ..TcpConfig::default() };
+    let (mut client, mut server) = connected(&config);
+    let data: Vec<u8> = (0..10 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+    let mut source = Chunked { inner: Cursor::new(&data), chunk: 4096 };
+    let margin = SEND_FROM_MARGIN_SEGMENTS * config.mss as usize;
+
+    let mut received = Vec::with_capacity(data.len());
+    let mut sent = 0;
+    let mut peak = 0;
+    let mut now = 0;
+    while received.len() < data.len() {
+        now += 2;
+        let bound = client.cwnd(ID).unwrap() as usize + margin;
+        sent += client.send_from(ID, &mut source, None).unwrap();
+        let queued = client.send_queued(ID).unwrap();
+        assert!(queued <= bound, "queued {} bound {}", queued, bound);
+        peak = peak.max(queued);
+        let segments = client.poll_transmit(usize::MAX).into_iter().map(|(_, segment)| segment).collect();
+        LINK.at(now).exchange(&mut client, &mut server, segments);
+        received.extend(server.read(ID.reversed(), usize::MAX).unwrap());
+        for update in server.tick(now + 1) {
+            client.segment_received(SERVER, CLIENT, &update, now + 1);
+        }
+    }
+    assert_eq!(sent, data.len() as u64);
+    assert!(received == data);
+    assert!(peak <= config.send_buffer, "peak {}", peak);
+    assert!(client.memory_usage().peak <= config.send_buffer + config.recv_buffer);
+    assert_eq!(client.memory_usage().get(MemoryComponent::SendBuffer), 0);
+
+    // 数据源读完之后再调用只返回 0
+    assert_eq!(client.send_from(ID, &mut source, None), Ok(0));
+}
+
+#[test]
+fn test_resume_with_limit_and_fill_callback() {
+    let (mut client, mut server) = connected(&TcpConfig::default());
+    let mut next = 0u8;
+    let mut fill = |buf: &mut [u8]| {
+        // 每次只给 100 字节, 连接自己接着要
+        let n = buf.len().min(100);
+        for byte in &mut buf[..n] {
+            *byte = next;
+            next = next.wrapping_add(1);
+        }
+        n
+    };
+    assert_eq!(client.send_from_fn(ID, &mut fill, Some(1500)), Ok(1500));
+    assert_eq!(client.send_queued(ID), Some(1500));
+    assert_eq!(client.send_from_fn(ID, &mut fill, Some(500)), Ok(500));
+    let segments = client.poll_transmit(usize::MAX).into_iter().map(|(_, segment)| segment).collect();
+    LINK.exchange(&mut client, &mut server, segments);
+    let expected: Vec<u8> = (0..2000u32).map(|i| i as u8).collect();
+    assert_eq!(server.read(ID.reversed(), usize::MAX).unwrap(), expected);
+}
+
+#[test]
+fn test_reader_errors() {
+    struct Failing(io::ErrorKind);
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(self.0.into())
+        }
+    }
+    let (mut client, _server) = connected(&TcpConfig::default());
+    // 暂时没有数据不是错误
+    assert_eq!(client.send_from(ID, &mut Failing(io::ErrorKind::WouldBlock), None), Ok(0));
+    assert_eq!(client.send_from(ID, &mut Failing(io::ErrorKind::PermissionDenied), None),
+               Err(ConnectionError::ReadFailed(io::ErrorKind::PermissionDenied)));
+    assert_eq!(client.send_queued(ID), Some(0));
+
+    // 连接本身仍然可用
+    assert_eq!(client.error(ID), None);
+    assert_eq!(client.send_from(ID, &mut &b"after"[..], None), Ok(5));
+    client.close(ID, 0).unwrap();
+    assert_eq!(client.send_from(ID, &mut &b"late"[..], None), Err(ConnectionError::Closed));
+}
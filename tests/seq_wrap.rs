@@ -0,0 +1,187 @@
+/**
+ * 序号回绕: 双方的 ISN 都取在 0xFFFF_FF00 附近, 传输开始后几百字节内序号就越过 2^32
+ * 每个种子随机选择 ISN 与链路的丢包、乱序, 双向各传一段数据
+ * 性质: 数据完整; 与 ISN 远离回绕点(最高位取反)的同一次传输逐帧相同, 重传次数与结束时刻都不变
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::link::device::{ChannelEnd, NetworkDevice};
+use simple_tcp_ip::testing::netem::{Netem, NetemConfig};
+use simple_tcp_ip::testing::rng::SimRng;
+use simple_tcp_ip::transport::connection_table::{listener_id, ConnectionTable};
+use simple_tcp_ip::transport::isn::IsnGenerator;
+use simple_tcp_ip::transport::tcp_connection::ConnectionId;
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+use simple_tcp_ip::utils::clock::ManualClock;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const TOTAL: usize = 200_000;
+const ID: ConnectionId = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+
+#[derive(Debug, PartialEq, Eq)]
+struct Outcome {
+    retransmissions: u64,
+    dropped: u64,
+    elapsed_ms: u64,
+}
+
+/**
+ * 每端一个设备: ChannelPair 的一端, 由 Netem 损伤它的发送方向
+ */
+type Device = Netem<ChannelEnd>;
+
+/**
+ * 一端把设备收到的帧交给连接表, 应答经同一个设备发回
+ */
+fn deliver(table: &mut ConnectionTable, device: &mut Device, s_ip: u32, d_ip: u32, now: u64) {
+    while let Some(bytes) = device.receive().unwrap() {
+        let segment = TcpSegment::try_deserialize(&bytes).unwrap();
+        for reply in table.segment_received(s_ip, d_ip, &segment, now) {
+            device.transmit(&reply.serialize()).unwrap();
+        }
+    }
+}
+
+/**
+ * 协议栈不自带重传定时器, 测试按 RTO 驱动: 发送队列在 RTO 内没有缩短(没有新的确认)就重传第一个空洞
+ */
+struct RtoTimer {
+    deadline_ms: u64,
+    queued: usize,
+}
+
+impl RtoTimer {
+    fn new() -> Self {
+        RtoTimer { deadline_ms: u64::MAX, queued: 0 }
+    }
+
+    /**
+     * 在写入之前调用, 发送队列比上一轮写入之后短说明有数据被确认
+     */
+    fn expire(&mut self, table: &mut ConnectionTable, id: ConnectionId, device: &mut Device, now: u64) {
+        let queued = table.send_queued(id).unwrap();
+        let rto = table.rto_ms(id).unwrap();
+        if queued < self.queued || queued == 0 {
+            self.deadline_ms = now + rto;
+        } else if now >= self.deadline_ms {
+            if let Some(segment) = table.retransmission(id) {
+                device.transmit(&segment.serialize()).unwrap();
+            }
+            self.deadline_ms = now + rto;
+        }
+    }
+
+    fn written(&mut self, table: &ConnectionTable, id: ConnectionId) {
+        self.queued = table.send_queued(id).unwrap();
+    }
+}
+
+/**
+ * 连接表这一轮要发的段: 定时处理、新数据与合并的 ACK、快速重传
+ */
+fn transmit(table: &mut ConnectionTable, id: ConnectionId, device: &mut Device, now: u64) {
+    for segment in table.tick(now) {
+        device.transmit(&segment.serialize()).unwrap();
+    }
+    for (_, segment) in table.poll_transmit(usize::MAX) {
+        device.transmit(&segment.serialize()).unwrap();
+    }
+    if table.fast_retransmit_due(id) {
+        device.transmit(&table.retransmission(id).unwrap().serialize()).unwrap();
+    }
+}
+
+fn transfer(client_isn: u32, server_isn: u32, link: NetemConfig, seed: u64) -> Outcome {
+    let clock = ManualClock::new(0);
+    let (mut client_dev, mut server_dev) = Netem::pair(link.clone(), link, seed, clock.clone());
+    let config = TcpConfig { mss: 1000, ..TcpConfig::default() };
+    let mut client = ConnectionTable::new(&config);
+    let mut server = ConnectionTable::new(&config);
+    client.set_isn_generator(IsnGenerator::fixed(client_isn));
+    server.set_isn_generator(IsnGenerator::fixed(server_isn));
+    server.listen(B_IP, 80);
+    let syn = client.connect(ID, 0);
+    assert_eq!(syn.seq, client_isn);
+    let syn_ack = server.segment_received(A_IP, B_IP, &syn, 0).pop().unwrap();
+    assert_eq!(syn_ack.seq, server_isn);
+    let ack = client.segment_received(B_IP, A_IP, &syn_ack, 0).pop().unwrap();
+    server.segment_received(A_IP, B_IP, &ack, 0);
+    let peer = server.accept(listener_id(B_IP, 80)).unwrap();
+
+    let upload: Vec<u8> = (0..TOTAL).map(|i| (i * 31 % 251) as u8).collect();
+    let download: Vec<u8> = (0..TOTAL).map(|i| (i * 17 % 241) as u8).collect();
+    let (mut uploaded, mut downloaded) = (0, 0);
+    let (mut at_server, mut at_client) = (vec![], vec![]);
+    let (mut client_rto, mut server_rto) = (RtoTimer::new(), RtoTimer::new());
+    let mut now = 0;
+    while at_server.len() < TOTAL || at_client.len() < TOTAL {
+        assert!(now < 300_000, "transfer stalled at {} / {} bytes", at_server.len(), at_client.len());
+        client_rto.expire(&mut client, ID, &mut client_dev, now);
+        server_rto.expire(&mut server, peer, &mut server_dev, now);
+        uploaded += client.write(ID, &upload[uploaded..]).unwrap();
+        downloaded += server.write(peer, &download[downloaded..]).unwrap();
+        client_rto.written(&client, ID);
+        server_rto.written(&server, peer);
+        transmit(&mut client, ID, &mut client_dev, now);
+        transmit(&mut server, peer, &mut server_dev, now);
+
+        client_dev.flush();
+        deliver(&mut server, &mut server_dev, A_IP, B_IP, now);
+        deliver(&mut client, &mut client_dev, B_IP, A_IP, now);
+        at_server.extend(server.read(peer, usize::MAX).unwrap());
+        at_client.extend(client.read(ID, usize::MAX).unwrap());
+
+        // 没有帧在途时按 10ms 推进, 让重传定时器有机会到期
+        let next = [client_dev.next_delivery_ms(), server_dev.next_delivery_ms()].into_iter().flatten().min();
+        now = next.unwrap_or(now + 10).clamp(now + 1, now + 10);
+        clock.set(now);
+    }
+    assert!(at_server == upload, "upload corrupted");
+    assert!(at_client == download, "download corrupted");
+    Outcome {
+        retransmissions: client.retransmissions() + server.retransmissions(),
+        dropped: client_dev.stats().dropped + server_dev.stats().dropped,
+        elapsed_ms: now,
+    }
+}
+
+fn impaired_link(rng: &mut SimRng) -> NetemConfig {
+    NetemConfig {
+        delay_ms: 5 + rng.below(20),
+        jitter_ms: rng.below(5),
+        reorder: rng.next_f64() * 0.1,
+        ..NetemConfig::default()
+    }.with_loss(rng.next_f64() * 0.03)
+}
+
+#[test]
+fn test_transfer_across_wrap_matches_unwrapped() {
+    let mut retransmissions = 0;
+    for seed in 1..=12 {
+        let mut rng = SimRng::new(seed);
+        let client_isn = 0xffff_ff00u32.wrapping_add(rng.below(0x200) as u32);
+        let server_isn = 0xffff_ff00u32.wrapping_add(rng.below(0x200) as u32);
+        let link_seed = rng.next_u64();
+        let link = impaired_link(&mut rng);
+        let wrapped = transfer(client_isn, server_isn, link.clone(), link_seed);
+        let plain = transfer(client_isn ^ 0x8000_0000, server_isn ^ 0x8000_0000, link, link_seed);
+        assert_eq!(wrapped, plain, "seed {} isn {:#x} / {:#x}", seed, client_isn, server_isn);
+
+        // 每个丢失的帧至多引起少量重传, 不会因为序号比较出错而反复重发
+        assert!(wrapped.retransmissions <= 3 * wrapped.dropped + 8, "seed {}: {:?}", seed, wrapped);
+        retransmissions += wrapped.retransmissions;
+    }
+    assert!(retransmissions > 0); // 确实走到了重传路径
+}
+
+#[test]
+fn test_isn_on_every_side_of_the_wrap() {
+    // SYN 本身、第一个数据字节、第一段数据的结尾分别落在 2^32 上, 0 作为对照
+    for isn in [u32::MAX, u32::MAX - 1, u32::MAX - 1000, 0] {
+        let link = NetemConfig { delay_ms: 10, ..NetemConfig::default() }.with_loss(0.02);
+        let outcome = transfer(isn, isn, link, 99);
+        assert!(outcome.retransmissions <= 3 * outcome.dropped + 8, "isn {:#x}: {:?}", isn, outcome);
+    }
+}
+
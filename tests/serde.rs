@@ -0,0 +1,130 @@
+/*
+ * serde 支持: 首部、配置与统计类型经 serde_json 往返不变, 地址和控制位使用文本形式
+ * cargo test --features serde --test serde
+ */
+#![cfg(feature = "serde")]
+
+use serde_json::json;
+
+use simple_tcp_ip::config::{HostModel, LossDetection, NatConfig, StackConfig};
+use simple_tcp_ip::link::arp::ArpPacket;
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::net::icmp_v4::IcmpV4;
+use simple_tcp_ip::net::ipv4::{Ipv4Datagram, Ipv4DatagramBuilder};
+use simple_tcp_ip::net::raw_socket::IpProtocol;
+use simple_tcp_ip::transport::syn_cookie::BacklogStats;
+use simple_tcp_ip::transport::tcp_option::TcpOption;
+use simple_tcp_ip::transport::tcp_segment::{TcpFlags, TcpSegment};
+use simple_tcp_ip::utils::drops::{DropCounters, DropReason};
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const HOST: u32 = 0xc0a80002;
+const GW: u32 = 0xc0a80001;
+const HOST_MAC: [u8; 6] = [0x02, 0x00, 0x5e, 0xab, 0xcd, 0xef];
+
+#[test]
+fn test_tcp_segment_flags_as_names() {
+    let segment = TcpSegment::new(40000, 80, 7, 9, 5, 0, TcpFlags::SYN | TcpFlags::ACK, 64240, 0, vec![], b"hi".to_vec());
+    let value = serde_json::to_value(&segment).unwrap();
+    assert_eq!(value["ctrl"], json!(["SYN", "ACK"]));
+    assert_eq!((value["s_port"].clone(), value["d_port"].clone()), (json!(40000), json!(80)));
+
+    let back: TcpSegment = serde_json::from_value(value).unwrap();
+    assert_eq!(back.serialize(), segment.serialize());
+    assert!(back.check_checksum(0, 0) == segment.check_checksum(0, 0));
+
+    let mut value = serde_json::to_value(&segment).unwrap();
+    value["ctrl"] = json!(["syn", "XMAS"]);
+    let err = serde_json::from_value::<TcpSegment>(value).unwrap_err();
+    assert!(err.to_string().contains("unknown tcp flag \"XMAS\""), "{}", err);
+}
+
+#[test]
+fn test_addresses_as_text() {
+    let arp = ArpPacket::request(HOST_MAC, HOST, GW);
+    let value = serde_json::to_value(&arp).unwrap();
+    assert_eq!(value, json!({
+        "op": 1,
+        "s_mac": "02:00:5e:ab:cd:ef",
+        "s_ip": "192.168.0.2",
+        "t_mac": "00:00:00:00:00:00",
+        "t_ip": "192.168.0.1",
+    }));
+    assert_eq!(serde_json::from_value::<ArpPacket>(value).unwrap(), arp);
+
+    let bad = json!({ "op": 1, "s_mac": "+f:00:5e:ab:cd:ef", "s_ip": "192.168.0.2", "t_mac": "00:00:00:00:00:00", "t_ip": "192.168.0.1" });
+    assert!(serde_json::from_value::<ArpPacket>(bad).is_err());
+    let bad = json!({ "op": 1, "s_mac": "02:00:5e:ab:cd:ef", "s_ip": "192.168.0.256", "t_mac": "00:00:00:00:00:00", "t_ip": "192.168.0.1" });
+    assert!(serde_json::from_value::<ArpPacket>(bad).is_err());
+}
+
+#[test]
+fn test_datagram_and_frame_round_trip() {
+    let datagram = Ipv4DatagramBuilder::new().source(HOST).destination(GW).protocol(IpProtocol::Udp)
+        .payload(b"payload".to_vec()).build().unwrap();
+    let value = serde_json::to_value(&datagram).unwrap();
+    assert_eq!((value["s_addr"].clone(), value["d_addr"].clone()), (json!("192.168.0.2"), json!("192.168.0.1")));
+    let back: Ipv4Datagram = serde_json::from_value(value).unwrap();
+    assert_eq!(back.serialize(), datagram.serialize());
+    assert!(back.check_hdr_checksum());
+    assert_eq!(serde_json::to_value(datagram.header()).unwrap()["s_addr"], json!("192.168.0.2"));
+
+    let frame = EthernetFrame::new([0xff; 6], HOST_MAC, 0x0800, datagram.serialize());
+    let value = serde_json::to_value(&frame).unwrap();
+    assert_eq!((value["d_mac"].clone(), value["s_mac"].clone()), (json!("ff:ff:ff:ff:ff:ff"), json!("02:00:5e:ab:cd:ef")));
+    let back: EthernetFrame = serde_json::from_value(value).unwrap();
+    assert_eq!(back.serialize(), frame.serialize());
+    assert!(back.check_fcs());
+
+    let icmp = IcmpV4::new(8, 0, vec![0, 1, 0, 1, 0xaa]);
+    let back: IcmpV4 = serde_json::from_str(&serde_json::to_string(&icmp).unwrap()).unwrap();
+    assert_eq!(back.serialize(), icmp.serialize());
+}
+
+#[test]
+fn test_options_round_trip() {
+    let options = vec![
+        TcpOption::Mss(1460),
+        TcpOption::WindowScale(7),
+        TcpOption::SackPermitted,
+        TcpOption::Sack(vec![(100, 200), (300, 400)]),
+        TcpOption::Timestamps { val: 1, ecr: 2 },
+        TcpOption::FastOpen(vec![]),
+        TcpOption::Unknown { kind: 253, data: vec![1, 2] },
+    ];
+    let text = serde_json::to_string(&options).unwrap();
+    assert!(text.starts_with(r#"[{"Mss":1460},{"WindowScale":7},"SackPermitted","#), "{}", text);
+    assert_eq!(serde_json::from_str::<Vec<TcpOption>>(&text).unwrap(), options);
+}
+
+#[test]
+fn test_config_round_trip_and_defaults() {
+    let mut config = StackConfig::default();
+    config.tcp.loss_detection = LossDetection::Rack;
+    config.ipv4.host_model = HostModel::Strong;
+    let text = serde_json::to_string(&config).unwrap();
+    assert_eq!(serde_json::from_str::<StackConfig>(&text).unwrap(), config);
+
+    // 配置文件只需写出与默认值不同的字段
+    let partial: StackConfig = serde_json::from_value(json!({ "tcp": { "mss": 1200 }, "ipv4": { "forwarding": true } })).unwrap();
+    assert_eq!((partial.tcp.mss, partial.tcp.recv_buffer, partial.ipv4.forwarding), (1200, 65535, true));
+    partial.validate().unwrap();
+
+    let nat: NatConfig = serde_json::from_value(json!({ "public_ip": "203.0.113.1", "port_min": 50000 })).unwrap();
+    assert_eq!((nat.public_ip, nat.port_min, nat.port_max), (0xcb007101, 50000, 65535));
+    assert_eq!(serde_json::to_value(&nat).unwrap()["public_ip"], json!("203.0.113.1"));
+}
+
+#[test]
+fn test_stats_round_trip() {
+    let stats = BacklogStats { cookies_sent: 3, accept_overflows: 1, ..BacklogStats::default() };
+    assert_eq!(serde_json::from_str::<BacklogStats>(&serde_json::to_string(&stats).unwrap()).unwrap(), stats);
+
+    let mut drops = DropCounters::new();
+    drops.record(DropReason::BadFcs);
+    drops.record(DropReason::BadMd5Signature);
+    drops.record(DropReason::BadFcs);
+    let value = serde_json::to_value(&drops).unwrap();
+    assert_eq!(value, json!({ "counts": { "bad_fcs": 2, "bad_md5_signature": 1 } }));
+    assert_eq!(serde_json::from_value::<DropCounters>(value).unwrap(), drops);
+}
@@ -0,0 +1,74 @@
+/**
+ * 64 字节以太网帧的序列化吞吐: 旧的 Vec 拼首部 vs 定长数组首部
+ * 计时部分只打印结果不做断言, 默认忽略; 用 `cargo test --release -- --ignored bench_small_frame_serialize --nocapture` 运行
+ */
+use std::hint::black_box;
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::transport::tcp_segment::{TcpCtrlFlag, TcpSegment};
+use simple_tcp_ip::utils::clock::{Clock, MonotonicClock};
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const ROUNDS: usize = 50_000;
+
+/**
+ * 改动前 serialize_into 的做法: 每层先把首部拼成 Vec 再复制
+ */
+fn old_serialize(segment: &TcpSegment, datagram: &Ipv4Datagram, frame: &EthernetFrame, buf: &mut [u8]) -> usize {
+    let ip_hdr = datagram.serialized_hdr();
+    let tcp_hdr = segment.serialized_hdr();
+    buf[..14].copy_from_slice(&frame.header_bytes());
+    let mut pos = 14;
+    buf[pos..pos + ip_hdr.len()].copy_from_slice(&ip_hdr);
+    pos += ip_hdr.len();
+    buf[pos..pos + tcp_hdr.len()].copy_from_slice(&tcp_hdr);
+    pos += tcp_hdr.len();
+    buf[pos..pos + segment.data.len()].copy_from_slice(&segment.data);
+    pos + segment.data.len()
+}
+
+fn new_serialize(segment: &TcpSegment, datagram: &Ipv4Datagram, frame: &EthernetFrame, buf: &mut [u8]) -> usize {
+    let (ip_hdr, ip_len) = datagram.header_bytes();
+    buf[..14].copy_from_slice(&frame.header_bytes());
+    buf[14..14 + ip_len].copy_from_slice(&ip_hdr[..ip_len]);
+    14 + ip_len + segment.serialize_into(&mut buf[14 + ip_len..]).unwrap()
+}
+
+fn packets_per_sec(f: impl Fn(&mut [u8]) -> usize) -> f64 {
+    let mut buf = [0u8; 64];
+    let clock = MonotonicClock::new();
+    for _ in 0..ROUNDS {
+        black_box(f(black_box(&mut buf)));
+    }
+    ROUNDS as f64 * 1e6 / clock.now_us().max(1) as f64
+}
+
+fn small_frame() -> (TcpSegment, Ipv4Datagram, EthernetFrame) {
+    let segment = TcpSegment::new(40000, 80, 1000, 2000, 5, 0, TcpCtrlFlag::ACK as u16, 8192, 0, vec![], vec![0x5a; 6]);
+    let datagram = Ipv4Datagram::new(4, 5, 0, 46, 1, 0b010, 0, 64, 6, 0x0a000001, 0x0a000002, segment.serialize());
+    let frame = EthernetFrame::new([0x02, 0, 0, 0, 0, 2], [0x02, 0, 0, 0, 0, 1], 0x0800, datagram.serialize());
+    (segment, datagram, frame)
+}
+
+/**
+ * 两种做法的输出必须一致
+ */
+#[test]
+fn test_small_frame_serialize_matches() {
+    let (segment, datagram, frame) = small_frame();
+    let mut old_buf = [0u8; 64];
+    let mut new_buf = [0u8; 64];
+    assert_eq!(old_serialize(&segment, &datagram, &frame, &mut old_buf), 60);
+    assert_eq!(new_serialize(&segment, &datagram, &frame, &mut new_buf), 60);
+    assert_eq!(old_buf[..60], new_buf[..60]);
+    assert_eq!(old_buf[..60], frame.serialize()[..60]);
+}
+
+#[test]
+#[ignore]
+fn bench_small_frame_serialize() {
+    let (segment, datagram, frame) = small_frame();
+    let old = packets_per_sec(|buf| old_serialize(&segment, &datagram, &frame, buf));
+    let new = packets_per_sec(|buf| new_serialize(&segment, &datagram, &frame, buf));
+    println!("64-byte frames: vec headers {:.0} pkt/s, array headers {:.0} pkt/s ({:.2}x)", old, new, new / old);
+}
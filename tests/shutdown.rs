@@ -0,0 +1,92 @@
+/**
+ * 有序关闭: 设备上有两条连接, 一个对端正常配合关闭, 另一个握手之后再也不回应
+ * 截止时间到时前者已经优雅关闭, 后者被 RST 放弃, 报告中带着它没有被确认的字节数
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::testing::pair::TablePair;
+use simple_tcp_ip::transport::connection_table::{ConnectionTable, ShutdownOutcome};
+use simple_tcp_ip::transport::tcp_connection::{ConnectionError, ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_segment::{TcpFlags, TcpSegment};
+
+const DEVICE: u32 = 0x0a000001;
+const GOOD_PEER: u32 = 0x0a000002;
+const DEAD_PEER: u32 = 0x0a000003;
+const DEADLINE: u64 = 5_000;
+
+/**
+ * 设备发出的报文: 发给配合的对端则投递并交换应答, 发给不回应的对端则丢弃
+ */
+fn deliver(device: &mut ConnectionTable, peer: &mut ConnectionTable, segments: Vec<(u32, TcpSegment)>, now: u64) {
+    let to_peer = segments.into_iter().filter(|(d_addr, _)| *d_addr == GOOD_PEER).map(|(_, segment)| segment).collect();
+    TablePair::new(DEVICE, GOOD_PEER).at(now).exchange(device, peer, to_peer);
+}
+
+#[test]
+fn test_shutdown_closes_cooperative_and_aborts_unresponsive() {
+    let config = TcpConfig::default();
+    let mut device = ConnectionTable::new(&config);
+    let mut peer = ConnectionTable::new(&config);
+    let listener = device.listen(DEVICE, 80);
+    peer.listen(GOOD_PEER, 80);
+
+    let good = ConnectionId { s_ip: DEVICE, s_port: 40001, d_ip: GOOD_PEER, d_port: 80 };
+    let syn = device.connect(good, 0);
+    deliver(&mut device, &mut peer, vec![(GOOD_PEER, syn)], 0);
+    assert_eq!(device.state(good), Some(TcpState::Established));
+
+    // 不回应的对端: 握手由测试手工完成, 之后它发来的只有这一个 ACK
+    let dead = ConnectionId { s_ip: DEVICE, s_port: 40002, d_ip: DEAD_PEER, d_port: 80 };
+    let syn = device.connect(dead, 0);
+    let syn_ack = TcpSegment::new(80, 40002, 9000, syn.seq.wrapping_add(1), 6, 0, TcpFlags::SYN | TcpFlags::ACK, 65535, 0, vec![], vec![]);
+    let ack = device.segment_received(DEAD_PEER, DEVICE, &syn_ack, 0);
+    assert_eq!(ack.len(), 1);
+    assert_eq!(device.state(dead), Some(TcpState::Established));
+
+    assert_eq!(device.write(good, &[1; 3000]), Ok(3000));
+    assert_eq!(device.write(dead, &[2; 3000]), Ok(3000));
+    device.shutdown(0, DEADLINE);
+    assert_eq!(device.write(good, b"late"), Err(ConnectionError::Closed));
+
+    // 关闭过程中的新连接不再被接受
+    let late = TcpSegment::new(50000, 80, 1, 0, 5, 0, TcpFlags::SYN, 65535, 0, vec![], vec![]);
+    assert!(device.segment_received(GOOD_PEER, DEVICE, &late, 0).is_empty());
+    assert_eq!(device.accept(listener), None);
+
+    let mut now = 0;
+    let report = loop {
+        let out = device.poll_transmit(64).into_iter().map(|(id, segment)| (id.d_ip, segment)).collect();
+        deliver(&mut device, &mut peer, out, now);
+        // 对端的应用读完数据, 看到 EOF 后也关闭
+        let peer_id = good.reversed();
+        peer.read(peer_id, usize::MAX).unwrap();
+        if peer.state(peer_id) == Some(TcpState::CloseWait) {
+            peer.close(peer_id, now).unwrap();
+        }
+        let replies = peer.poll_transmit(64);
+        for (_, segment) in replies {
+            let acks = device.segment_received(GOOD_PEER, DEVICE, &segment, now);
+            deliver(&mut device, &mut peer, acks.into_iter().map(|ack| (GOOD_PEER, ack)).collect(), now);
+        }
+        device.tick(now);
+        if let Some(report) = device.poll_shutdown(now) {
+            break report;
+        }
+        now += 100;
+    };
+
+    assert_eq!(now, DEADLINE);
+    assert_eq!(report.graceful(), vec![good]);
+    assert_eq!(report.aborted(), vec![dead]);
+    let dead_entry = report.entries.iter().find(|entry| entry.id == dead).unwrap();
+    assert_eq!((dead_entry.outcome, dead_entry.unsent), (ShutdownOutcome::Aborted, 3000));
+    assert_eq!(device.state(good), Some(TcpState::TimeWait));
+    assert_eq!(peer.state(good.reversed()), Some(TcpState::Closed));
+    assert_eq!(device.state(dead), Some(TcpState::Closed));
+    assert!(device.poll_shutdown(now + 100).is_none());
+
+    // 最后一次发送只有给不回应对端的 RST
+    let flushed = device.poll_transmit(64);
+    assert_eq!(flushed.len(), 1);
+    assert_eq!(flushed[0].0, dead);
+    assert!(flushed[0].1.RST());
+}
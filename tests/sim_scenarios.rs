@@ -0,0 +1,119 @@
+/**
+ * 用 Simulation 脚本驱动的场景测试: 两端各是一个 TableHost, 帧就是序列化后的 IP 数据报
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::testing::netem::{NetemConfig, NetemLink};
+use simple_tcp_ip::testing::packet::PacketBuilder;
+use simple_tcp_ip::testing::sim::{Event, FrameOutcome, Side, SimOutcome, Simulation};
+use simple_tcp_ip::testing::table_host::TableHost;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionError, ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_segment::{TcpCtrlFlag, TcpSegment};
+use simple_tcp_ip::utils::clock::ManualClock;
+use simple_tcp_ip::utils::drops::DropReason;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const ID: ConnectionId = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+
+fn payload(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 % 253) as u8).collect()
+}
+
+/**
+ * A 向 B 发送 len 字节; 握手在 0ms 发起
+ */
+fn simulation(len: usize, config: NetemConfig) -> Simulation<TableHost> {
+    let tcp = TcpConfig::default();
+    let server = TableHost::listening(&tcp, ID.reversed(), vec![]);
+    let mut sim = Simulation::new(TableHost::new(&tcp, ID, payload(len)), server, NetemLink::new(config, 42), ManualClock::new(0));
+    sim.at(0, Event::act(Side::A, |host: &mut TableHost, now| {
+        let syn = host.table.connect(ID, now);
+        host.frames(vec![syn], now)
+    }));
+    sim
+}
+
+fn delayed(delay_ms: u64) -> NetemConfig {
+    NetemConfig { delay_ms, ..NetemConfig::default() }
+}
+
+/**
+ * 传输中途接收端中止连接, 发送端收到 RST 后不再发出任何帧
+ */
+#[test]
+fn test_peer_rst_mid_transfer() {
+    const LEN: usize = 1_000_000;
+    let mut sim = simulation(LEN, delayed(5));
+    sim.at(40, Event::act(Side::B, |host: &mut TableHost, now| {
+        host.table.abort(host.id, now).unwrap();
+        host.frames(vec![], now)
+    }));
+    assert_eq!(sim.run_until(60_000), SimOutcome::Idle);
+
+    let (a, b) = (sim.node(Side::A), sim.node(Side::B));
+    assert_eq!(a.table.state(ID), Some(TcpState::Closed));
+    assert_eq!(a.table.error(ID), Some(ConnectionError::Reset));
+    assert_eq!(b.table.state(ID.reversed()), Some(TcpState::Closed));
+    assert!(a.finished_at.is_none());
+    assert!(!b.received.is_empty() && b.received.len() < LEN);
+    assert_eq!(b.received[..], a.data[..b.received.len()]);
+
+    // RST 在 45ms 到达 A, 之后 A 没有再发送
+    let rst_at = sim.records().iter()
+        .filter(|r| r.from == Side::B && r.outcome == FrameOutcome::Delivered)
+        .filter(|r| TcpSegment::try_deserialize(Ipv4Datagram::try_deserialize(&r.frame).unwrap().payload()).unwrap().RST())
+        .map(|r| r.at_ms)
+        .min()
+        .unwrap();
+    assert_eq!(rst_at, 45);
+    let last_sent_by_a = sim.records().iter()
+        .filter(|r| r.from == Side::A && r.outcome == FrameOutcome::Sent)
+        .map(|r| r.at_ms)
+        .max()
+        .unwrap();
+    assert!(last_sent_by_a <= rst_at);
+}
+
+/**
+ * 链路中断 10 秒, 恢复后靠重传完成传输
+ */
+#[test]
+fn test_link_down_for_ten_seconds() {
+    let mut sim = simulation(100_000, delayed(5));
+    sim.at(20, Event::LinkDown).at(10_020, Event::LinkUp);
+    assert_eq!(sim.run_until(60_000), SimOutcome::Idle);
+
+    let (a, b) = (sim.node(Side::A), sim.node(Side::B));
+    assert_eq!(b.received, a.data);
+    assert!(a.finished_at.unwrap() > 10_020);
+    assert!(a.table.retransmissions() > 0);
+    assert_eq!(a.table.state(ID), Some(TcpState::Established));
+    let lost = sim.records().iter().filter(|r| r.outcome == FrameOutcome::LinkDown).count();
+    assert!(lost > 0);
+    assert!(sim.records().iter().all(|r| r.outcome != FrameOutcome::LinkDown || (20..10_020).contains(&r.at_ms)));
+}
+
+/**
+ * 中途把链路换成 30% 丢包并乱序, 再注入一个校验和错误的报文段
+ */
+#[test]
+fn test_netem_change_and_corrupt_injection() {
+    let mut sim = simulation(100_000, delayed(5));
+    let lossy = NetemConfig { loss: 0.3, reorder: 0.2, delay_ms: 5, jitter_ms: 10, ..NetemConfig::default() };
+    let corrupt = PacketBuilder::new().ipv4(A_IP, B_IP)
+        .tcp(ID.s_port, ID.d_port).flags(TcpCtrlFlag::ACK).seq(0).payload(b"garbage")
+        .corrupt_l4_checksum()
+        .build();
+    sim.at(30, Event::SetNetem { a_to_b: lossy.clone(), b_to_a: lossy, seed: 7 })
+        .at(60, Event::InjectFrame { to: Side::B, frame: corrupt });
+    // 两个方向各 30% 丢包, 连续丢失的重传让 RTO 退避到上限, 仿真时间要放宽到一小时
+    assert_eq!(sim.run_until(3_600_000), SimOutcome::Idle);
+
+    let (a, b) = (sim.node(Side::A), sim.node(Side::B));
+    assert_eq!(b.received, a.data);
+    assert!(a.table.retransmissions() > 0);
+    assert_eq!(b.drops.get(DropReason::BadTcpChecksum), 1);
+    assert_eq!(a.table.state(ID), Some(TcpState::Established));
+    assert_eq!(b.table.state(ID.reversed()), Some(TcpState::Established));
+}
@@ -0,0 +1,120 @@
+/**
+ * 同时关闭: 两端的应用在同一轮里调用 close, 两个 FIN 在链路上交错
+ * 两端都经过 Closing 进入 TimeWait, FIN 之前的数据照常交付, 读到的是干净的 EOF
+ */
+use std::io::Read;
+
+use simple_tcp_ip::config::{Ipv4Config, TcpConfig};
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::testing::netem::{NetemConfig, NetemLink};
+use simple_tcp_ip::testing::sim::{Event, Side, SimNode, SimOutcome, Simulation};
+use simple_tcp_ip::transport::connection_table::{listener_id, ConnectionTable};
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+use simple_tcp_ip::utils::clock::ManualClock;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const ID: ConnectionId = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+
+/**
+ * 一张连接表作为仿真的一端, 帧就是序列化后的 IP 数据报
+ */
+struct Host {
+    table: ConnectionTable,
+    id: ConnectionId,
+    states: Vec<TcpState>, // 每次收发之后连接的状态, 相邻重复的只记一次
+}
+
+impl Host {
+    fn new(id: ConnectionId) -> Self {
+        Host { table: ConnectionTable::new(&TcpConfig::default()), id, states: vec![] }
+    }
+
+    fn frames(&mut self, segments: Vec<TcpSegment>) -> Vec<Vec<u8>> {
+        let config = Ipv4Config::default();
+        let mut frames: Vec<Vec<u8>> = segments.iter().filter_map(|seg| self.table.datagram(self.id, seg, &config)).map(|d| d.serialize()).collect();
+        for (id, seg) in self.table.poll_transmit(usize::MAX) {
+            frames.extend(self.table.datagram(id, &seg, &config).map(|d| d.serialize()));
+        }
+        if let Some(state) = self.table.state(self.id) {
+            if self.states.last() != Some(&state) {
+                self.states.push(state);
+            }
+        }
+        frames
+    }
+}
+
+impl SimNode for Host {
+    fn receive(&mut self, frame: &[u8], now_ms: u64) -> Vec<Vec<u8>> {
+        let datagram = Ipv4Datagram::try_deserialize(frame).unwrap();
+        let replies = self.table.datagram_received(&datagram, now_ms);
+        self.frames(replies)
+    }
+
+    fn poll(&mut self, now_ms: u64) -> Vec<Vec<u8>> {
+        let out = self.table.tick(now_ms);
+        self.frames(out)
+    }
+
+    fn next_wakeup_ms(&mut self) -> Option<u64> {
+        None
+    }
+}
+
+fn read_to_eof(host: &mut Host) -> Vec<u8> {
+    let mut data = vec![];
+    host.table.stream(host.id).read_to_end(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn test_both_sides_close_in_same_round() {
+    let mut server = Host::new(ID.reversed());
+    server.table.listen(B_IP, 80);
+    let link = NetemLink::new(NetemConfig { delay_ms: 10, ..NetemConfig::default() }, 1);
+    let mut sim = Simulation::new(Host::new(ID), server, link, ManualClock::new(0));
+    sim.at(0, Event::act(Side::A, |host: &mut Host, now| {
+        let syn = host.table.connect(ID, now);
+        host.frames(vec![syn])
+    }));
+    sim.at(100, Event::act(Side::B, |host: &mut Host, _| {
+        assert_eq!(host.table.accept(listener_id(B_IP, 80)), Some(ID.reversed()));
+        vec![]
+    }));
+
+    // 同一时刻两端各写一段数据然后关闭, 数据和 FIN 在同一段里
+    sim.at(200, Event::act(Side::A, |host: &mut Host, now| {
+        host.table.write(ID, b"request").unwrap();
+        host.table.close(ID, now).unwrap();
+        host.frames(vec![])
+    }));
+    sim.at(200, Event::act(Side::B, |host: &mut Host, now| {
+        host.table.write(ID.reversed(), b"response").unwrap();
+        host.table.close(ID.reversed(), now).unwrap();
+        host.frames(vec![])
+    }));
+    assert_eq!(sim.run_until(10_000), SimOutcome::Idle);
+
+    let expected = [TcpState::FinWait1, TcpState::Closing, TcpState::TimeWait];
+    for side in [Side::A, Side::B] {
+        let states = &sim.node(side).states;
+        assert_eq!(&states[states.len() - 3..], &expected, "{:?}: {:?}", side, states);
+    }
+    assert_eq!(read_to_eof(sim.node_mut(Side::A)), b"response");
+    assert_eq!(read_to_eof(sim.node_mut(Side::B)), b"request");
+    for side in [Side::A, Side::B] {
+        let host = sim.node(side);
+        assert_eq!(host.table.error(host.id), None);
+    }
+
+    // 2 * MSL 之后两端都回到 Closed
+    let end = sim.now_ms() + TcpConfig::default().time_wait_ms();
+    for side in [Side::A, Side::B] {
+        let host = sim.node_mut(side);
+        host.table.tick(end);
+        assert_eq!(host.table.state(host.id), Some(TcpState::Closed));
+    }
+}
@@ -0,0 +1,65 @@
+/**
+ * 套接字选项: TTL 5、DSCP EF 在 connect 之前设置, SYN、数据段和重传的 IP 首部都要带上
+ * 之后改的选项对接下来的报文立即生效
+ */
+use simple_tcp_ip::config::{Ipv4Config, TcpConfig};
+use simple_tcp_ip::net::dscp::Dscp;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::socket_options::{KeepaliveParams, SocketOptions};
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+
+const CLIENT: u32 = 0x0a000001;
+const SERVER: u32 = 0x0a000002;
+
+fn assert_marked(datagram: &Ipv4Datagram, ttl: u8, dscp: Dscp) {
+    assert_eq!(datagram.ttl(), ttl);
+    assert_eq!(datagram.dscp(), dscp);
+    assert_eq!((datagram.s_addr(), datagram.d_addr(), datagram.protocol()), (CLIENT, SERVER, 6));
+    assert!(datagram.check_hdr_checksum());
+}
+
+#[test]
+fn test_ttl_and_dscp_on_syn_data_and_retransmission() {
+    let ip_config = Ipv4Config::default();
+    let mut client = ConnectionTable::new(&TcpConfig::default());
+    let mut server = ConnectionTable::new(&TcpConfig::default());
+    server.listen(SERVER, 80);
+
+    let id = ConnectionId { s_ip: CLIENT, s_port: 40000, d_ip: SERVER, d_port: 80 };
+    let mut options = SocketOptions { ttl: Some(5), ..SocketOptions::default() };
+    options.set_dscp(Dscp::EF);
+    let syn = client.connect_with_options(id, options, 0);
+    let datagram = client.datagram(id, &syn, &ip_config).unwrap();
+    assert_marked(&datagram, 5, Dscp::EF);
+    assert!(TcpSegment::try_deserialize(datagram.payload()).unwrap().SYN());
+
+    let syn_ack = server.segment_received(CLIENT, SERVER, &syn, 0);
+    for segment in syn_ack {
+        for ack in client.segment_received(SERVER, CLIENT, &segment, 0) {
+            server.segment_received(CLIENT, SERVER, &ack, 0);
+        }
+    }
+    assert_eq!(client.state(id), Some(TcpState::Established));
+
+    client.write(id, b"hello").unwrap();
+    let (_, data) = client.poll_transmit(8).pop().unwrap();
+    assert_marked(&client.datagram(id, &data, &ip_config).unwrap(), 5, Dscp::EF);
+
+    // 数据段丢失, 重传的报文段与原来相同, IP 首部同样带着选项
+    let retransmission = client.retransmission(id).unwrap();
+    assert_eq!((retransmission.seq, &retransmission.data), (data.seq, &data.data));
+    assert_marked(&client.datagram(id, &retransmission, &ip_config).unwrap(), 5, Dscp::EF);
+
+    // connect 之后修改选项
+    let keepalive = KeepaliveParams { idle_ms: 10_000, interval_ms: 1_000, probes: 3 };
+    let mut changed = client.socket_options(id).unwrap();
+    changed.ttl = None;
+    changed.set_dscp(Dscp::af(4, 1));
+    changed.keepalive = Some(keepalive);
+    client.set_socket_options(id, changed).unwrap();
+    let retransmission = client.retransmission(id).unwrap();
+    assert_marked(&client.datagram(id, &retransmission, &ip_config).unwrap(), ip_config.default_ttl, Dscp::af(4, 1));
+    assert_eq!(client.socket_options(id).unwrap().keepalive, Some(keepalive));
+}
@@ -0,0 +1,66 @@
+/**
+ * 每类异常源地址注入一个数据报, 检查对应的丢弃计数, 正常流量不受影响
+ */
+use simple_tcp_ip::config::Ipv4Config;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::net::route::RoutingTable;
+use simple_tcp_ip::net::source_guard::{Arrival, SourceGuard};
+use simple_tcp_ip::testing::packet::PacketBuilder;
+use simple_tcp_ip::utils::drops::DropReason;
+
+const LOCAL: u32 = 0x0a000001;
+const ETH0: Arrival = Arrival { interface: 0, loopback: false };
+const ETH1: Arrival = Arrival { interface: 1, loopback: false };
+const LO: Arrival = Arrival { interface: 9, loopback: true };
+
+fn routes() -> RoutingTable {
+    let mut routes = RoutingTable::new();
+    routes.add_on(0x0a000000, 24, None, 0);
+    routes.add_on(0xc0a80000, 16, None, 1);
+    routes
+}
+
+fn inject(guard: &mut SourceGuard, s_addr: u32, d_addr: u32, arrival: Arrival) -> bool {
+    let bytes = PacketBuilder::new().ipv4(s_addr, d_addr).tcp(40000, 80).build();
+    let datagram = Ipv4Datagram::try_deserialize(&bytes).unwrap();
+    guard.accept(datagram.s_addr(), datagram.d_addr(), arrival, &routes())
+}
+
+#[test]
+fn test_martians_are_dropped_and_counted() {
+    let mut guard = SourceGuard::new(&Ipv4Config::default(), &[LOCAL]);
+    let martians = [0x7f000001, 0xe0000005, 0xf0000001, 0xffffffff, 0x00000001];
+    for s_addr in martians {
+        assert!(!inject(&mut guard, s_addr, LOCAL, ETH0), "{:08x}", s_addr);
+    }
+    assert!(!inject(&mut guard, 0, LOCAL, ETH0)); // 0.0.0.0 只能发往受限广播
+    assert!(!inject(&mut guard, LOCAL, LOCAL, ETH0));
+
+    assert!(inject(&mut guard, 0, 0xffffffff, ETH0)); // DHCP
+    assert!(inject(&mut guard, LOCAL, LOCAL, LO));
+    assert!(inject(&mut guard, 0x7f000001, 0x7f000001, LO));
+    assert!(inject(&mut guard, 0x0a000002, LOCAL, ETH0));
+    assert!(inject(&mut guard, 0x08080808, LOCAL, ETH1)); // 松散模式不检查回程
+
+    let drops = guard.drop_counters();
+    assert_eq!(drops.get(DropReason::MartianSource), 6);
+    assert_eq!(drops.get(DropReason::LocalSource), 1);
+    assert_eq!(drops.total(), 7);
+}
+
+#[test]
+fn test_strict_reverse_path() {
+    let config = Ipv4Config { strict_rpf: true, ..Ipv4Config::default() };
+    let mut guard = SourceGuard::new(&config, &[LOCAL]);
+    assert!(inject(&mut guard, 0x0a000002, LOCAL, ETH0));
+    assert!(inject(&mut guard, 0xc0a80102, LOCAL, ETH1));
+    assert!(!inject(&mut guard, 0xc0a80102, LOCAL, ETH0)); // 回程走 eth1
+    assert!(!inject(&mut guard, 0x08080808, LOCAL, ETH0)); // 没有回程路由
+    assert!(inject(&mut guard, 0, 0xffffffff, ETH1));
+    assert_eq!(guard.drop_counters().get(DropReason::ReversePath), 2);
+
+    let lax = Ipv4Config { martian_filter: false, ..Ipv4Config::default() };
+    let mut guard = SourceGuard::new(&lax, &[LOCAL]);
+    assert!(inject(&mut guard, 0x7f000001, LOCAL, ETH0));
+    assert!(!inject(&mut guard, LOCAL, LOCAL, ETH0)); // 本机地址总是检查
+}
@@ -0,0 +1,146 @@
+/**
+ * Stack: InterfaceSet 与 ConnectionTable 接在一起, A -- R -- B 中 A、B 是协议栈, R 只转发
+ * 连接的段由 poll 发出, 有序关闭、定时器的最早时刻、链路断开超时、分片重组和指标都经由 Stack
+ */
+use simple_tcp_ip::config::{ArpConfig, Ipv4Config, StackConfig, TcpConfig};
+use simple_tcp_ip::link::interface::EthernetInterface;
+use simple_tcp_ip::net::interfaces::{InterfaceSet, LinkEvent};
+use simple_tcp_ip::net::ipv4::Ipv4DatagramBuilder;
+use simple_tcp_ip::net::raw_socket::IpProtocol;
+use simple_tcp_ip::stack::Stack;
+use simple_tcp_ip::testing::topology::{stack, Topology};
+use simple_tcp_ip::transport::connection_table::{listener_id, ShutdownOutcome};
+use simple_tcp_ip::transport::tcp_connection::{ConnectionError, ConnectionId, TcpState};
+
+const A_IP: u32 = 0x0a000102;
+const R_IP0: u32 = 0x0a000101;
+const R_IP1: u32 = 0x0a000201;
+const B_IP: u32 = 0x0a000202;
+const A_MAC: [u8; 6] = [0x02, 0, 0, 0, 1, 2];
+const R_MAC0: [u8; 6] = [0x02, 0, 0, 0, 1, 1];
+const R_MAC1: [u8; 6] = [0x02, 0, 0, 0, 2, 1];
+const B_MAC: [u8; 6] = [0x02, 0, 0, 0, 2, 2];
+const GOOD: ConnectionId = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+const SLOW: ConnectionId = ConnectionId { s_ip: A_IP, s_port: 40001, d_ip: B_IP, d_port: 81 };
+const DEADLINE: u64 = 5_000;
+
+fn network() -> Topology<Stack> {
+    let mut router = InterfaceSet::new(&Ipv4Config { forwarding: true, ..Ipv4Config::default() }, &ArpConfig::default());
+    router.add_interface(EthernetInterface::new(R_MAC0, R_IP0, 24));
+    router.add_interface(EthernetInterface::new(R_MAC1, R_IP1, 24));
+    Topology::new(stack(A_MAC, A_IP, Some(R_IP0)), router, stack(B_MAC, B_IP, Some(R_IP1)))
+}
+
+#[test]
+fn test_shutdown_through_router() {
+    let mut net = network();
+    net.b.tcp_mut().listen(B_IP, 80);
+    net.b.tcp_mut().listen(B_IP, 81);
+    net.a.connect(GOOD, 0).unwrap();
+    net.a.connect(SLOW, 0).unwrap();
+    net.run();
+    assert_eq!(net.a.tcp().state(GOOD), Some(TcpState::Established));
+    assert_eq!(net.a.tcp().state(SLOW), Some(TcpState::Established));
+    let good = net.b.tcp_mut().accept(listener_id(B_IP, 80)).unwrap();
+    let slow = net.b.tcp_mut().accept(listener_id(B_IP, 81)).unwrap();
+
+    // 端口 80 的应用读完数据、看到 EOF 后关闭; 端口 81 的应用从不关闭
+    net.a.tcp_mut().write(GOOD, &[1; 1000]).unwrap();
+    net.a.tcp_mut().write(SLOW, &[2; 1000]).unwrap();
+    net.a.shutdown(0, DEADLINE);
+    let report = loop {
+        net.run();
+        net.b.tcp_mut().read(good, usize::MAX).unwrap();
+        if net.b.tcp().state(good) == Some(TcpState::CloseWait) {
+            net.b.tcp_mut().close(good, net.now_ms).unwrap();
+        }
+        if let Some(report) = net.a.poll_shutdown(net.now_ms) {
+            break report;
+        }
+        net.now_ms += 100;
+    };
+    assert_eq!(net.now_ms, DEADLINE);
+    assert_eq!(report.graceful(), vec![GOOD]);
+    assert_eq!(report.aborted(), vec![SLOW]);
+    let entry = report.entries.iter().find(|entry| entry.id == SLOW).unwrap();
+    assert_eq!((entry.outcome, entry.unsent), (ShutdownOutcome::Aborted, 0));
+
+    // 放弃的连接的 RST 已经交给 IP 层, 对端收到后复位
+    net.run();
+    assert_eq!(net.b.tcp().error(slow), Some(ConnectionError::Reset));
+    assert_eq!(net.a.tcp().state(GOOD), Some(TcpState::TimeWait));
+}
+
+#[test]
+fn test_next_event_covers_tcp_and_arp() {
+    let mut net = network();
+    assert_eq!(net.a.time_until_next_event(0), None);
+
+    // SYN 等待 ARP 解析: 最早的是 ARP 重试
+    net.a.connect(GOOD, 0).unwrap();
+    net.a.poll(0);
+    let retry = ArpConfig::default().retry_interval_ms;
+    assert_eq!(net.a.next_deadline_ms(), Some(retry));
+    assert_eq!(net.a.time_until_next_event(400), Some(retry - 400));
+
+    // ARP 解析之后 SYN 发出, 对端没有监听也不回应: 最早的是 SYN 的重传
+    net.run();
+    assert_eq!(net.a.tcp().state(GOOD), Some(TcpState::SynSent));
+    assert_eq!(net.a.next_deadline_ms(), Some(TcpConfig::default().rto_initial_ms));
+    assert_eq!(net.a.next_deadline_ms(), net.a.tcp_mut().next_timer_ms());
+}
+
+#[test]
+fn test_link_down_timeout_fails_connections() {
+    let config = StackConfig { ipv4: Ipv4Config { link_down_abort_ms: Some(5_000), ..Ipv4Config::default() }, ..StackConfig::default() };
+    let mut net = network();
+    net.a = Stack::new(&config).unwrap();
+    net.a.add_interface(EthernetInterface::new(A_MAC, A_IP, 24));
+    net.a.interfaces_mut().routes_mut().add(0, 0, Some(R_IP0));
+    net.b.tcp_mut().listen(B_IP, 80);
+    net.a.connect(GOOD, 0).unwrap();
+    net.run();
+    assert_eq!(net.a.tcp().state(GOOD), Some(TcpState::Established));
+
+    net.a.interfaces_mut().set_link(0, false, 1_000);
+    net.a.poll(1_000);
+    assert!(net.a.next_deadline_ms().unwrap() <= 6_000);
+    net.a.poll(5_999);
+    assert_eq!(net.a.tcp().state(GOOD), Some(TcpState::Established));
+    net.a.poll(6_000);
+    assert_eq!(net.a.tcp().error(GOOD), Some(ConnectionError::NetworkDown));
+    assert_eq!(net.a.take_link_events(), vec![LinkEvent::Down(0), LinkEvent::DownTimeout(0)]);
+}
+
+#[test]
+fn test_fragments_reassembled_before_delivery() {
+    let mut net = network();
+    let datagram = Ipv4DatagramBuilder::new().source(A_IP).destination(B_IP).protocol(IpProtocol::Other(253)).payload(vec![7; 1000]).build().unwrap();
+    for fragment in datagram.fragment(300).unwrap() {
+        net.a.interfaces_mut().send(fragment, None, 0).unwrap();
+    }
+    net.run();
+    let delivered = net.b.take_delivered();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].0.payload(), &[7; 1000][..]);
+}
+
+#[test]
+fn test_metrics_cover_every_interface() {
+    let mut net = network();
+    net.b.add_interface(EthernetInterface::new([0x02, 0, 0, 0, 3, 2], 0x0a000302, 24));
+    net.b.tcp_mut().listen(B_IP, 80);
+    net.a.connect(GOOD, 0).unwrap();
+    net.run();
+
+    let rendered = net.b.render_metrics();
+    for line in [
+        "stip_interface_rx_frames_total{interface=\"eth0\"} 3",
+        "stip_interface_rx_frames_total{interface=\"eth1\"} 0",
+        "stip_tcp_connections{state=\"established\"} 1",
+        "stip_tcp_connections{state=\"listen\"} 0",
+        "stip_arp_cache_entries 1",
+    ] {
+        assert!(rendered.lines().any(|rendered| rendered == line), "missing {}\n{}", line, rendered);
+    }
+}
@@ -0,0 +1,109 @@
+/**
+ * 卡死看门狗: 传输中途把服务器到客户端的方向变成黑洞, 客户端的数据再也得不到确认
+ * 测试内的驱动没有重传定时器, 客户端从此沉默; 看门狗应当只报告一次, 服务器读完数据后不算卡死
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::testing::netem::{NetemConfig, NetemLink};
+use simple_tcp_ip::transport::connection_table::{listener_id, ConnectionTable};
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, SegmentDirection, STALL_EVENTS};
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+use simple_tcp_ip::utils::trace::{TraceSink, WriterTraceSink};
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const TOTAL: usize = 200_000;
+const BLACKHOLE_AFTER: usize = 50_000;
+
+#[test]
+fn test_blackholed_acks_produce_one_diagnosis() {
+    let config = TcpConfig { stall_timeout_ms: Some(5000), ..TcpConfig::default() };
+    let mut client = ConnectionTable::new(&config);
+    let mut server = ConnectionTable::new(&config);
+    let id = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+    server.listen(B_IP, 80);
+    let syn = client.connect(id, 0);
+    let syn_ack = server.segment_received(A_IP, B_IP, &syn, 0).pop().unwrap();
+    let ack = client.segment_received(B_IP, A_IP, &syn_ack, 0).pop().unwrap();
+    server.segment_received(A_IP, B_IP, &ack, 0);
+    let peer = server.accept(listener_id(B_IP, 80)).unwrap();
+
+    let mut link = NetemLink::new(NetemConfig { delay_ms: 10, ..NetemConfig::default() }, 1);
+    let data = vec![0x5a; TOTAL];
+    let mut written = 0;
+    let mut received = 0;
+    let mut now = 0;
+    while now < 30_000 {
+        client.tick(now);
+        server.tick(now);
+        written += client.write(id, &data[written..]).unwrap();
+        for (_, segment) in client.poll_transmit(usize::MAX) {
+            link.a_to_b.send(segment.serialize(), now);
+        }
+        for bytes in link.a_to_b.poll(now) {
+            for reply in server.segment_received(A_IP, B_IP, &TcpSegment::try_deserialize(&bytes).unwrap(), now) {
+                if received < BLACKHOLE_AFTER {
+                    link.b_to_a.send(reply.serialize(), now);
+                }
+            }
+        }
+        received += server.read(peer, usize::MAX).unwrap().len();
+        for bytes in link.b_to_a.poll(now) {
+            client.segment_received(B_IP, A_IP, &TcpSegment::try_deserialize(&bytes).unwrap(), now);
+        }
+        now = link.next_delivery_ms().unwrap_or(now + 100).max(now + 1);
+    }
+    assert!((BLACKHOLE_AFTER..TOTAL).contains(&received));
+
+    // 只有客户端卡死, 而且只报告一次
+    assert_eq!(client.stalls_detected(), 1);
+    assert_eq!(server.stalls_detected(), 0);
+    let stalls = client.take_stalls();
+    assert_eq!(stalls.len(), 1);
+    let stall = &stalls[0];
+    assert_eq!(stall.id, id);
+    assert!((5000..5100).contains(&stall.silent_ms), "silent for {}ms", stall.silent_ms);
+    assert_eq!(stall.snd_nxt.wrapping_sub(stall.snd_una) as usize, stall.unacked);
+    assert!(stall.unacked > 0 && stall.unsent > 0);
+    assert_eq!(stall.unread, 0);
+    assert_eq!(stall.rcv_nxt, syn_ack.seq.wrapping_add(1));
+
+    // 最近的事件: 最后发出的是数据, 最后收到的确认停在 snd_una
+    assert_eq!(stall.recent.len(), STALL_EVENTS);
+    assert!(stall.recent.windows(2).all(|pair| pair[0].at_ms <= pair[1].at_ms));
+    let last_tx = stall.recent.iter().rev().find(|event| event.direction == SegmentDirection::Tx).unwrap();
+    assert!(last_tx.len > 0);
+    if let Some(last_rx) = stall.recent.iter().rev().find(|event| event.direction == SegmentDirection::Rx) {
+        assert_eq!(last_rx.ack, stall.snd_una);
+    }
+
+    // 已经取走, 不会再报告; 追踪钩子写出的文本
+    let mut sink = WriterTraceSink::new(Vec::new());
+    client.tick(now);
+    assert_eq!(client.report_stalls(&mut sink), 0);
+    sink.on_stall(stall);
+    let text = String::from_utf8(sink.into_inner()).unwrap();
+    assert!(text.starts_with(&format!("stall {} at ", id)));
+    assert!(text.contains(&format!("unacked {} unsent {} unread 0", stall.unacked, stall.unsent)));
+}
+
+#[test]
+fn test_idle_connection_is_not_a_stall() {
+    let config = TcpConfig { stall_timeout_ms: Some(1000), ..TcpConfig::default() };
+    let mut client = ConnectionTable::new(&config);
+    let mut server = ConnectionTable::new(&config);
+    let id = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+    server.listen(B_IP, 80);
+    let syn = client.connect(id, 0);
+    let syn_ack = server.segment_received(A_IP, B_IP, &syn, 0).pop().unwrap();
+    let ack = client.segment_received(B_IP, A_IP, &syn_ack, 0).pop().unwrap();
+    server.segment_received(A_IP, B_IP, &ack, 0);
+
+    let mut sink = WriterTraceSink::new(Vec::new());
+    for now in (0..60_000).step_by(500) {
+        client.tick(now);
+        server.tick(now);
+    }
+    assert_eq!(client.report_stalls(&mut sink) + server.report_stalls(&mut sink), 0);
+    assert!(sink.into_inner().is_empty());
+}
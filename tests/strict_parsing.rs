@@ -0,0 +1,162 @@
+/**
+ * 保留位与首部自洽性: Loose(默认)原样保留并逐字节写回, Strict 拒绝
+ * 选项: Strict 拒绝不能按 kind/length 解析的选项, Loose 把它们作为 Unknown 返回; EOL 之后的字节两种模式都原样保留
+ */
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::net::ipv4::{Ipv4Datagram, Ipv4ParseError};
+use simple_tcp_ip::testing::fixtures;
+use simple_tcp_ip::transport::tcp_option::TcpOption;
+use simple_tcp_ip::transport::tcp_segment::{TcpFlags, TcpParseError, TcpSegment};
+use simple_tcp_ip::utils::wire::{ParseStrictness, WireSerialize};
+
+const A: u32 = 0x0a000001;
+const B: u32 = 0x0a000002;
+
+/**
+ * 保留位为 0b101 的 SYN, 校验和按带保留位的首部计算
+ */
+fn reserved_syn() -> Vec<u8> {
+    let syn = TcpSegment::new(40000, 80, 1000, 0, 6, 0b101, TcpFlags::SYN, 65535, 0, vec![0x0204_05b4], vec![]);
+    let mut bytes = syn.serialize();
+    bytes[16] = 0;
+    bytes[17] = 0;
+    let mut segment = TcpSegment::try_deserialize(&bytes).unwrap();
+    segment.generate_checksum(A, B);
+    segment.serialize()
+}
+
+#[test]
+fn test_strict_rejects_reserved_syn_and_loose_preserves_it() {
+    let bytes = reserved_syn();
+    assert_eq!(
+        TcpSegment::try_deserialize_with(&bytes, ParseStrictness::Strict).unwrap_err(),
+        TcpParseError::ReservedBits { bits: 0b101 }
+    );
+    let syn = TcpSegment::try_deserialize_with(&bytes, ParseStrictness::Loose).unwrap();
+    assert_eq!(syn.reserved_bits(), 0b101);
+    assert!(syn.SYN() && syn.check_checksum(A, B));
+    assert_eq!(syn.serialize(), bytes);
+    assert_eq!(TcpSegment::from_payload_vec_with(bytes.clone(), ParseStrictness::Strict).unwrap_err(), TcpParseError::ReservedBits { bits: 0b101 });
+}
+
+#[test]
+fn test_strict_rejects_options_that_overrun_the_data_offset() {
+    // data offset 6: MSS 选项的长度字段写成 8, 越过了首部
+    let bytes = TcpSegment::new(40000, 80, 1000, 0, 6, 0, TcpFlags::SYN, 65535, 0, vec![0x0208_05b4], vec![]).serialize();
+    assert_eq!(TcpSegment::try_deserialize_with(&bytes, ParseStrictness::Strict).unwrap_err(), TcpParseError::MalformedOptions { offset: 20 });
+    assert!(TcpSegment::try_deserialize(&bytes).is_ok());
+}
+
+/**
+ * data offset 为 hl, 选项字段为 options(按需补 0 到 hl 对应的长度), 之后是 payload
+ */
+fn raw_segment(hl: u8, options: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut bytes = TcpSegment::new(40000, 80, 1000, 0, 5, 0, TcpFlags::ACK, 65535, 0, vec![], vec![]).serialize();
+    bytes[12] = hl << 4;
+    bytes.extend_from_slice(options);
+    bytes.resize(20.max(hl as usize * 4).max(20 + options.len()), 0);
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+#[test]
+fn test_malformed_options_strict_error_loose_blob() {
+    // MSS 之后的 Window Scale 长度为 4, 与 kind 3 不符
+    let bytes = raw_segment(7, &[2, 4, 0x05, 0xb4, 3, 4, 7, 0], b"xy");
+    assert_eq!(TcpSegment::try_deserialize_with(&bytes, ParseStrictness::Strict).unwrap_err(), TcpParseError::MalformedOptions { offset: 24 });
+
+    let segment = TcpSegment::try_deserialize_with(&bytes, ParseStrictness::Loose).unwrap();
+    assert_eq!(segment.data, b"xy");
+    assert!(segment.parsed_options().is_err());
+    assert_eq!(
+        segment.parsed_options_with(ParseStrictness::Loose).unwrap(),
+        vec![TcpOption::Mss(1460), TcpOption::Unknown { kind: 3, data: vec![3, 4, 7, 0] }]
+    );
+    assert_eq!(segment.parsed_options_with(ParseStrictness::Strict).unwrap_err(), TcpParseError::MalformedOptions { offset: 24 });
+    assert_eq!(segment.serialize(), bytes);
+}
+
+#[test]
+fn test_header_length_beyond_buffer_is_always_an_error() {
+    // data offset 15 要求 60 字节首部, 只有 24 字节
+    let mut bytes = raw_segment(6, &[1, 1, 1, 0], b"");
+    bytes[12] = 15 << 4;
+    for strictness in [ParseStrictness::Strict, ParseStrictness::Loose] {
+        assert_eq!(TcpSegment::try_deserialize_with(&bytes, strictness).unwrap_err(), TcpParseError::BadHeaderLength { hl: 15, available: 24 });
+        assert_eq!(TcpSegment::from_payload_vec_with(bytes.clone(), strictness).unwrap_err(), TcpParseError::BadHeaderLength { hl: 15, available: 24 });
+    }
+}
+
+#[test]
+fn test_bytes_after_eol_are_tolerated_and_preserved() {
+    let bytes = raw_segment(7, &[2, 4, 0x05, 0xb4, 0, 0xde, 0xad, 7], b"data");
+    for strictness in [ParseStrictness::Strict, ParseStrictness::Loose] {
+        let segment = TcpSegment::try_deserialize_with(&bytes, strictness).unwrap();
+        assert_eq!(segment.parsed_options_with(strictness).unwrap(), vec![TcpOption::Mss(1460), TcpOption::EndOfList]);
+        assert_eq!(segment.options_bytes(), [2, 4, 0x05, 0xb4, 0, 0xde, 0xad, 7]);
+        assert_eq!(segment.serialize(), bytes);
+    }
+}
+
+/**
+ * 随机选项字段中出现过的形状: (选项字节, Strict 下出错的位置, Loose 下的选项)
+ */
+#[test]
+fn test_option_regressions() {
+    let cases: Vec<(Vec<u8>, Option<usize>, Vec<TcpOption>)> = vec![
+        // 最后一个字节是 kind, 没有长度
+        (vec![1, 1, 1, 8], Some(23), vec![TcpOption::Nop, TcpOption::Nop, TcpOption::Nop, TcpOption::Unknown { kind: 8, data: vec![8] }]),
+        // 长度为 0 和 1
+        (vec![0x22, 0, 0, 0], Some(20), vec![TcpOption::Unknown { kind: 0x22, data: vec![0x22, 0, 0, 0] }]),
+        (vec![1, 0xfe, 1, 1], Some(21), vec![TcpOption::Nop, TcpOption::Unknown { kind: 0xfe, data: vec![0xfe, 1, 1] }]),
+        // 长度合法但与已知 kind 不符: 时间戳只有 6 字节
+        (vec![8, 6, 0, 0, 0, 1, 1, 1], Some(20), vec![TcpOption::Unknown { kind: 8, data: vec![8, 6, 0, 0, 0, 1, 1, 1] }]),
+        // 未知 kind 长度合法, 两种模式都接受
+        (vec![0xfd, 4, 0xab, 0xcd], None, vec![TcpOption::Unknown { kind: 0xfd, data: vec![0xab, 0xcd] }]),
+        // SACK 块不完整
+        (vec![5, 6, 0, 0, 0, 1, 1, 1], Some(20), vec![TcpOption::Unknown { kind: 5, data: vec![5, 6, 0, 0, 0, 1, 1, 1] }]),
+    ];
+    for (options, strict_error, loose) in cases {
+        let bytes = raw_segment(5 + options.len() as u8 / 4, &options, b"p");
+        match strict_error {
+            Some(offset) => assert_eq!(
+                TcpSegment::try_deserialize_with(&bytes, ParseStrictness::Strict).unwrap_err(),
+                TcpParseError::MalformedOptions { offset },
+                "{:02x?}", options
+            ),
+            None => assert!(TcpSegment::try_deserialize_with(&bytes, ParseStrictness::Strict).is_ok()),
+        }
+        let segment = TcpSegment::try_deserialize_with(&bytes, ParseStrictness::Loose).unwrap();
+        assert_eq!(segment.parsed_options_with(ParseStrictness::Loose).unwrap(), loose, "{:02x?}", options);
+        assert_eq!(segment.serialize(), bytes);
+        assert_eq!(segment.data, b"p");
+    }
+}
+
+#[test]
+fn test_ipv4_evil_bit_and_version() {
+    let frames = fixtures::load("tcp_syn").unwrap();
+    let original = EthernetFrame::try_deserialize(&fixtures::wire_frame(&frames[0])).unwrap().payload().to_vec();
+    let len = Ipv4Datagram::try_deserialize(&original).unwrap().serialize().len();
+    assert!(Ipv4Datagram::try_deserialize_with(&original, ParseStrictness::Strict).is_ok());
+
+    let mut evil = original[..len].to_vec();
+    evil[6] |= 0x80;
+    assert_eq!(Ipv4Datagram::try_deserialize_with(&evil, ParseStrictness::Strict).unwrap_err(), Ipv4ParseError::ReservedBits { bits: 1 });
+    let datagram = Ipv4Datagram::try_deserialize_with(&evil, ParseStrictness::Loose).unwrap();
+    assert_eq!(datagram.reserved_bits(), 1);
+    assert!(datagram.dont_fragment());
+    assert_eq!(datagram.serialize(), evil);
+
+    // 分片同样保留这一位
+    evil[6] &= !0x40;
+    let fragments = Ipv4Datagram::try_deserialize(&evil).unwrap().fragment(28).unwrap();
+    assert!(fragments.len() > 1);
+    assert!(fragments.iter().all(|fragment| fragment.reserved_bits() == 1));
+
+    let mut v6 = original[..len].to_vec();
+    v6[0] = (6 << 4) | (v6[0] & 0x0f);
+    assert_eq!(Ipv4Datagram::try_deserialize_with(&v6, ParseStrictness::Strict).unwrap_err(), Ipv4ParseError::BadVersion { version: 6 });
+    let datagram = Ipv4Datagram::try_deserialize(&v6).unwrap();
+    assert_eq!(datagram.serialize()[0], v6[0]);
+}
@@ -0,0 +1,120 @@
+/**
+ * SYN 洪泛: 伪造源地址的 SYN 只占用监听端口的半连接队列, 队列满之后改发 cookie, 不建立连接、不占用连接内存
+ * 正常客户端的握手在洪泛中经 cookie ACK 完成; accept 队列以 syn_backlog 为上限
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::testing::rng::SimRng;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_option::TcpOption;
+use simple_tcp_ip::transport::tcp_segment::{TcpFlags, TcpSegment};
+use simple_tcp_ip::utils::memory::MemoryBudget;
+
+const SERVER_IP: u32 = 0x0a000001;
+const CLIENT_IP: u32 = 0x0a000002;
+const BACKLOG: usize = 8;
+
+fn config() -> TcpConfig {
+    TcpConfig { syn_backlog: BACKLOG, syn_cookies: true, ..TcpConfig::default() }
+}
+
+fn client_id(port: u16) -> ConnectionId {
+    ConnectionId { s_ip: CLIENT_IP, s_port: port, d_ip: SERVER_IP, d_port: 80 }
+}
+
+/**
+ * 客户端连接表完成一次握手, 返回服务端收到握手 ACK 后的回复
+ */
+fn handshake(client: &mut ConnectionTable, server: &mut ConnectionTable, id: ConnectionId, now_ms: u64) -> Vec<TcpSegment> {
+    let syn = client.connect(id, now_ms);
+    let syn_ack = server.segment_received(CLIENT_IP, SERVER_IP, &syn, now_ms).pop().unwrap();
+    assert!(syn_ack.SYN() && syn_ack.ACK());
+    let ack = client.segment_received(SERVER_IP, CLIENT_IP, &syn_ack, now_ms).pop().unwrap();
+    assert_eq!(client.state(id), Some(TcpState::Established));
+    server.segment_received(CLIENT_IP, SERVER_IP, &ack, now_ms)
+}
+
+#[test]
+fn test_flood_then_cookie_handshake() {
+    let mut server = ConnectionTable::new(&config());
+    let budget = MemoryBudget::new(1 << 20);
+    server.set_memory_budget(budget.clone());
+    let listener = server.listen(SERVER_IP, 80);
+
+    let mut rng = SimRng::new(661);
+    for _ in 0..1000 {
+        let source = 0xc0a8_0000 | rng.below(0x1_0000) as u32;
+        let syn = TcpSegment::new(rng.below(0x1_0000) as u16, 80, rng.next_u64() as u32, 0, 5, 0, TcpFlags::SYN, 65535, 0,
+            vec![], vec![]);
+        let replies = server.segment_received(source, SERVER_IP, &syn, 10);
+        assert!(replies.len() == 1 && replies[0].SYN() && replies[0].ACK());
+    }
+    // 洪泛之后没有任何 TcpConnection, 也没有向内存预算记账
+    assert!(server.is_empty());
+    assert_eq!(budget.used(), 0);
+    assert_eq!(server.half_open(listener), BACKLOG);
+    assert_eq!(server.backlog_stats(listener).unwrap().cookies_sent, 1000 - BACKLOG as u64);
+
+    // 半连接队列已满, 正常客户端拿到 cookie; SYN|ACK 只带 cookie 编码的 MSS
+    let mut client = ConnectionTable::new(&config());
+    let id = client_id(40000);
+    let syn = client.connect(id, 20);
+    let syn_ack = server.segment_received(CLIENT_IP, SERVER_IP, &syn, 20).pop().unwrap();
+    assert_eq!(syn_ack.parsed_options().unwrap(), vec![TcpOption::Mss(1460)]);
+    assert_eq!(server.half_open(listener), BACKLOG);
+    assert!(server.is_empty());
+
+    // 伪造的握手 ACK 得到 RST, 不建立连接
+    let forged = TcpSegment::new(40000, 80, syn.seq.wrapping_add(1), syn_ack.seq.wrapping_add(7), 5, 0, TcpFlags::ACK, 65535, 0,
+        vec![], vec![]);
+    let replies = server.segment_received(CLIENT_IP, SERVER_IP, &forged, 25);
+    assert!(replies.len() == 1 && replies[0].RST());
+    assert!(server.is_empty());
+
+    let ack = client.segment_received(SERVER_IP, CLIENT_IP, &syn_ack, 30).pop().unwrap();
+    server.segment_received(CLIENT_IP, SERVER_IP, &ack, 30);
+    let stats = server.backlog_stats(listener).unwrap();
+    assert_eq!((stats.cookies_accepted, stats.cookies_rejected), (1, 1));
+    let peer = server.accept(listener).unwrap();
+    assert_eq!(peer, id.reversed());
+    assert_eq!(server.len(), 1);
+    assert_eq!(server.negotiated_options(peer).unwrap().peer_mss, 1460);
+
+    // 经 cookie 建立的连接照常收发
+    client.write(id, b"through the flood").unwrap();
+    for (_, segment) in client.poll_transmit(usize::MAX) {
+        server.segment_received(CLIENT_IP, SERVER_IP, &segment, 40);
+    }
+    assert_eq!(server.read(peer, usize::MAX).unwrap(), b"through the flood");
+
+    // 伪造的半连接超时后清空
+    server.tick(200_000);
+    assert_eq!(server.half_open(listener), 0);
+}
+
+#[test]
+fn test_accept_queue_bounded_by_backlog() {
+    let mut server = ConnectionTable::new(&config());
+    let mut client = ConnectionTable::new(&config());
+    let listener = server.listen(SERVER_IP, 80);
+    for port in 0..BACKLOG as u16 {
+        assert!(handshake(&mut client, &mut server, client_id(40000 + port), 0).is_empty());
+    }
+    assert_eq!(server.len(), BACKLOG);
+
+    // accept 队列已满: 新的 SYN 被丢弃, 不回复 SYN|ACK
+    let late = client_id(41000);
+    let syn = client.connect(late, 10);
+    assert!(server.segment_received(CLIENT_IP, SERVER_IP, &syn, 10).is_empty());
+    assert_eq!(server.half_open(listener), 0);
+    assert_eq!(server.backlog_stats(listener).unwrap().accept_overflows, 1);
+
+    // 应用取走一个连接之后, 客户端重传的 SYN 完成握手
+    assert!(server.accept(listener).is_some());
+    let syn = client.retransmission(late).unwrap();
+    let syn_ack = server.segment_received(CLIENT_IP, SERVER_IP, &syn, 1010).pop().unwrap();
+    let ack = client.segment_received(SERVER_IP, CLIENT_IP, &syn_ack, 1010).pop().unwrap();
+    server.segment_received(CLIENT_IP, SERVER_IP, &ack, 1010);
+    assert_eq!(server.state(late.reversed()), Some(TcpState::Established));
+    assert_eq!(server.len(), BACKLOG + 1);
+}
@@ -0,0 +1,121 @@
+/**
+ * 握手重传中的 TCP 选项: 重传的 SYN 与第一次逐字节相同
+ * 中间设备剥掉了选项的重传 SYN 或 SYN|ACK 不会改变已经协商好的结果
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_option::{NegotiatedOptions, TcpOption};
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+
+fn config() -> TcpConfig {
+    TcpConfig { offer_window_scale: true, offer_sack: true, offer_timestamps: true, window_scale: 7, ..TcpConfig::default() }
+}
+
+/**
+ * 只保留 MSS 选项的副本, 模拟剥掉其他选项的中间设备
+ */
+fn strip_options(segment: &TcpSegment) -> TcpSegment {
+    TcpSegment::new(segment.s_port, segment.d_port, segment.seq, segment.ack, 6, 0, segment.ctrl, segment.win_size, 0,
+        vec![segment.options[0]], segment.data.clone())
+}
+
+fn negotiated() -> NegotiatedOptions {
+    NegotiatedOptions { peer_mss: 1460, window_scale: Some((7, 7)), sack_permitted: true, timestamps: true }
+}
+
+#[test]
+fn test_stripped_syn_retransmission() {
+    let mut client = ConnectionTable::new(&config());
+    let mut server = ConnectionTable::new(&config());
+    let id = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+    server.listen(B_IP, 80);
+
+    let syn = client.connect(id, 10);
+    let options = syn.parsed_options().unwrap();
+    assert!(options.contains(&TcpOption::SackPermitted) && options.contains(&TcpOption::WindowScale(7)));
+    assert!(options.iter().any(|option| matches!(option, TcpOption::Timestamps { val: 10, ecr: 0 })));
+
+    // 客户端重传的 SYN 与第一次逐字节相同
+    assert_eq!(client.retransmission(id).unwrap().serialize(), syn.serialize());
+
+    let syn_ack = server.segment_received(A_IP, B_IP, &syn, 20).pop().unwrap();
+    let peer = ConnectionId::for_incoming(A_IP, B_IP, &syn);
+    assert_eq!(server.negotiated_options(peer), Some(negotiated()));
+    assert!(syn_ack.parsed_options().unwrap().contains(&TcpOption::Timestamps { val: 20, ecr: 10 }));
+
+    // SYN|ACK 丢失, 剥掉选项的重传 SYN 到达: 协商结果不变, 原样重发 SYN|ACK
+    let replies = server.segment_received(A_IP, B_IP, &strip_options(&syn), 1020);
+    assert_eq!(replies.len(), 1);
+    assert_eq!(replies[0].serialize(), syn_ack.serialize());
+    assert_eq!(server.negotiated_options(peer), Some(negotiated()));
+    assert_eq!(server.retransmission(peer).unwrap().serialize(), syn_ack.serialize());
+
+    let ack = client.segment_received(B_IP, A_IP, &syn_ack, 1030).pop().unwrap();
+    assert_eq!(client.state(id), Some(TcpState::Established));
+    assert_eq!(client.negotiated_options(id), Some(negotiated()));
+
+    // 重复的 SYN|ACK 被剥掉了选项, 客户端只回 challenge ACK, 协商结果不变
+    let replies = client.segment_received(B_IP, A_IP, &strip_options(&syn_ack), 1040);
+    assert_eq!(replies.len(), 1);
+    assert!(!replies[0].SYN());
+    assert_eq!(client.negotiated_options(id), Some(negotiated()));
+
+    server.segment_received(A_IP, B_IP, &ack, 1050);
+    assert_eq!(server.state(peer), Some(TcpState::Established));
+    assert_eq!(server.negotiated_options(peer), Some(negotiated()));
+}
+
+/**
+ * 对端只提出 MSS 时, SYN|ACK 也只带 MSS
+ */
+#[test]
+fn test_answer_only_what_peer_offered() {
+    let mut client = ConnectionTable::new(&TcpConfig { mss: 1200, ..TcpConfig::default() });
+    let mut server = ConnectionTable::new(&config());
+    let id = ConnectionId { s_ip: A_IP, s_port: 40001, d_ip: B_IP, d_port: 80 };
+    server.listen(B_IP, 80);
+
+    let syn = client.connect(id, 0);
+    assert_eq!(syn.parsed_options().unwrap(), vec![TcpOption::Mss(1200)]);
+    let syn_ack = server.segment_received(A_IP, B_IP, &syn, 0).pop().unwrap();
+    assert_eq!(syn_ack.parsed_options().unwrap(), vec![TcpOption::Mss(1460)]);
+    let peer = ConnectionId::for_incoming(A_IP, B_IP, &syn);
+    assert_eq!(server.negotiated_options(peer),
+        Some(NegotiatedOptions { peer_mss: 1200, window_scale: None, sack_permitted: false, timestamps: false }));
+}
+
+/**
+ * 握手之后协商的选项照常生效: 每个报文段都带 TSopt, 窗口字段按 Window Scale 右移, 对端按它左移还原
+ */
+#[test]
+fn test_scaled_window_and_timestamps_after_handshake() {
+    let mut client = ConnectionTable::new(&config());
+    let mut server = ConnectionTable::new(&TcpConfig { recv_buffer: 200_000, ..config() });
+    let id = ConnectionId { s_ip: A_IP, s_port: 40002, d_ip: B_IP, d_port: 80 };
+    server.listen(B_IP, 80);
+
+    let syn = client.connect(id, 10);
+    let syn_ack = server.segment_received(A_IP, B_IP, &syn, 20).pop().unwrap();
+    assert_eq!(syn_ack.win_size, u16::MAX); // SYN|ACK 中的窗口不缩放
+    let ack = client.segment_received(B_IP, A_IP, &syn_ack, 30).pop().unwrap();
+    assert!(ack.parsed_options().unwrap().contains(&TcpOption::Timestamps { val: 30, ecr: 20 }));
+    server.segment_received(A_IP, B_IP, &ack, 40);
+
+    client.write(id, b"scaled").unwrap();
+    let (_, data) = client.poll_transmit(usize::MAX).pop().unwrap();
+    assert!(data.parsed_options().unwrap().contains(&TcpOption::Timestamps { val: 30, ecr: 20 }));
+    let reply = server.segment_received(A_IP, B_IP, &data, 50).pop().unwrap();
+    assert!(reply.parsed_options().unwrap().contains(&TcpOption::Timestamps { val: 50, ecr: 30 }));
+    assert_eq!(reply.win_size as u32, (200_000 - 6) >> 7);
+
+    client.segment_received(B_IP, A_IP, &reply, 60);
+    client.poll(60); // 纯 ACK 合并到本轮 poll 结束时才交给发送端
+    let snd_wnd = client.snd_wnd(id).unwrap();
+    assert_eq!(snd_wnd, (reply.win_size as u32) << 7);
+    assert!(snd_wnd > u16::MAX as u32);
+}
@@ -0,0 +1,125 @@
+/**
+ * 分片的 TCP 段: 接收端先经 Ipv4Reassembler 重组, 连接表只接受完整的数据报
+ * 发送端按出口 MTU 缩小段长而不是分片, 只有 MSS 下限放不下时才分片
+ */
+use simple_tcp_ip::config::{Ipv4Config, TcpConfig};
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::net::reassembly::Ipv4Reassembler;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+
+fn handshake(client: &mut ConnectionTable, server: &mut ConnectionTable) -> (ConnectionId, ConnectionId) {
+    let id = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+    server.listen(B_IP, 80);
+    let syn = client.connect(id, 0);
+    let syn_ack = server.segment_received(A_IP, B_IP, &syn, 0).pop().unwrap();
+    let ack = client.segment_received(B_IP, A_IP, &syn_ack, 0).pop().unwrap();
+    server.segment_received(A_IP, B_IP, &ack, 0);
+    let peer = ConnectionId::for_incoming(A_IP, B_IP, &syn);
+    assert_eq!(server.state(peer), Some(TcpState::Established));
+    (id, peer)
+}
+
+#[test]
+fn test_out_of_order_fragments_reassemble_before_tcp() {
+    // 两端都通告 3000 的 MSS, 发送端才会发出超过 1500 MTU 的段
+    let mut client = ConnectionTable::new(&TcpConfig { mss: 3000, ..TcpConfig::default() });
+    let mut server = ConnectionTable::new(&TcpConfig { mss: 3000, ..TcpConfig::default() });
+    let (id, peer) = handshake(&mut client, &mut server);
+
+    let data: Vec<u8> = (0..3000u32).map(|i| (i * 31) as u8).collect();
+    client.write(id, &data).unwrap();
+    let (_, segment) = client.poll_transmit(1).pop().unwrap();
+    assert_eq!(segment.data.len(), 3000);
+    let fragments = client.datagrams(id, &segment, &Ipv4Config::default(), 1500).unwrap();
+    assert_eq!(fragments.len(), 3);
+    assert!(fragments.iter().all(|fragment| fragment.is_fragment() && fragment.wire_size() <= 1500));
+
+    // 连接表不接受单独的分片
+    assert!(server.datagram_received(&fragments[0], 0).is_empty());
+    assert!(server.read(peer, 4096).unwrap().is_empty());
+
+    let mut reassembler = Ipv4Reassembler::new(&Ipv4Config::default());
+    let bytes: Vec<Vec<u8>> = fragments.iter().map(|fragment| fragment.serialize()).collect();
+    let mut whole = None;
+    for index in [2, 0, 1] {
+        let fragment = Ipv4Datagram::try_deserialize(&bytes[index]).unwrap();
+        assert!(whole.is_none());
+        whole = reassembler.push(fragment, 0);
+    }
+    let whole = whole.unwrap();
+    assert!(!whole.is_fragment());
+    let reassembled = TcpSegment::try_deserialize(whole.payload()).unwrap();
+    assert!(reassembled.check_checksum(A_IP, B_IP));
+
+    let replies = server.datagram_received(&whole, 0);
+    assert_eq!(replies.len(), 1);
+    assert_eq!(server.read(peer, 4096).unwrap(), data);
+}
+
+/**
+ * 客户端发出的一个数据报
+ */
+struct Sent {
+    wire_size: usize,
+    fragment: bool,
+    dont_fragment: bool,
+}
+
+/**
+ * 把 total 字节从客户端传到服务端, 返回客户端发出的所有数据报
+ */
+fn bulk_transfer(path_mtu: u16, total: usize) -> Vec<Sent> {
+    let mut client = ConnectionTable::new(&TcpConfig::default());
+    let mut server = ConnectionTable::new(&TcpConfig::default());
+    let (id, peer) = handshake(&mut client, &mut server);
+    client.set_path_mtu(id, path_mtu).unwrap();
+
+    let data: Vec<u8> = (0..total as u32).map(|i| (i % 251) as u8).collect();
+    let mut written = 0;
+    let mut received = vec![];
+    let mut sent = vec![];
+    let mut reassembler = Ipv4Reassembler::new(&Ipv4Config::default());
+    for _ in 0..10_000 {
+        if received.len() == total {
+            break;
+        }
+        written += client.write(id, &data[written..]).unwrap();
+        for (_, segment) in client.poll_transmit(64) {
+            for datagram in client.datagrams(id, &segment, &Ipv4Config::default(), path_mtu).unwrap() {
+                sent.push(Sent { wire_size: datagram.wire_size(), fragment: datagram.is_fragment(), dont_fragment: datagram.dont_fragment() });
+                let Some(whole) = reassembler.push(datagram, 0) else { continue };
+                for ack in server.datagram_received(&whole, 0) {
+                    client.segment_received(B_IP, A_IP, &ack, 0);
+                }
+            }
+        }
+        received.extend(server.read(peer, usize::MAX).unwrap());
+    }
+    assert_eq!(received, data);
+    sent
+}
+
+#[test]
+fn test_bulk_transfer_never_fragments() {
+    for mtu in [1500, 1000] {
+        let sent = bulk_transfer(mtu, 200_000);
+        assert!(sent.iter().all(|datagram| !datagram.fragment && datagram.dont_fragment));
+        assert_eq!(sent.iter().map(|datagram| datagram.wire_size).max(), Some(mtu as usize));
+    }
+}
+
+/**
+ * 路径 MTU 小到 MSS 下限 536 也放不下时只能分片
+ */
+#[test]
+fn test_mss_floor_forces_fragmentation() {
+    let sent = bulk_transfer(400, 5_000);
+    assert!(sent.iter().any(|datagram| datagram.fragment));
+    assert!(sent.iter().all(|datagram| datagram.wire_size <= 400));
+}
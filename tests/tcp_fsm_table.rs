@@ -0,0 +1,274 @@
+/**
+ * 按 transport::tcp_fsm_table 逐行驱动一个连接: 先用一串合法的事件把它带到行的起始状态,
+ * 再注入行的事件, 比较之后的状态、发出的报文段和就绪事件、2*MSL 定时器
+ * 测试扮演对端, 报文段的序号和确认号按双方目前的序号空间填写
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::transport::connection_table::{listener_id, ConnectionTable, Readiness};
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_fsm_table::{FsmActions, FsmEvent, FsmRow, TCP_FSM_TABLE};
+use simple_tcp_ip::transport::tcp_segment::{TcpFlags, TcpSegment};
+
+const LOCAL_IP: u32 = 0x0a000002;
+const PEER_IP: u32 = 0x0a000001;
+const ID: ConnectionId = ConnectionId { s_ip: LOCAL_IP, s_port: 80, d_ip: PEER_IP, d_port: 40000 };
+const PEER_ISN: u32 = 7000;
+const MSL_MS: u64 = 1000;
+const TIME_WAIT_MS: u64 = 2 * MSL_MS;
+const EVENT_MS: u64 = 100; // 起始状态在 0 时刻建立, 行的事件在这之后注入
+
+/**
+ * 一个被测连接和测试所扮演的对端的序号状态
+ */
+struct Harness {
+    table: ConnectionTable,
+    listening: bool,
+    now_ms: u64,
+    local_nxt: u32,          // 本端发出过的最大序号之后
+    local_fin: Option<u32>,  // 本端 FIN 的序号
+    fin_acked: bool,         // 对端已经确认了本端的 FIN
+    peer_nxt: u32,
+    peer_fin: Option<u32>,   // 对端 FIN 的序号, 之后的 Fin 事件重传它
+    time_wait_since: Option<u64>,
+}
+
+impl Harness {
+    fn new(listening: bool) -> Self {
+        let mut table = ConnectionTable::new(&TcpConfig { msl_ms: MSL_MS, ..TcpConfig::default() });
+        if listening {
+            table.listen(LOCAL_IP, 80);
+        }
+        Harness {
+            table,
+            listening,
+            now_ms: 0,
+            local_nxt: 0,
+            local_fin: None,
+            fin_acked: false,
+            peer_nxt: PEER_ISN,
+            peer_fin: None,
+            time_wait_since: None,
+        }
+    }
+
+    /**
+     * 用合法的事件序列到达 state, 经过的每一步都要求迁移成功
+     */
+    fn reach(state: TcpState) -> Self {
+        use FsmEvent::*;
+        let (listening, path): (bool, &[FsmEvent]) = match state {
+            TcpState::Closed => (false, &[]),
+            TcpState::Listen => (true, &[]),
+            TcpState::SynSent => (false, &[Connect]),
+            // 被动打开的半连接在握手完成之前只在 SynBacklog 里, 没有可以 close 的连接; 用同时打开到达 SynReceived
+            TcpState::SynReceived => (false, &[Connect, Syn]),
+            TcpState::Established => (false, &[Connect, SynAck]),
+            TcpState::FinWait1 => (false, &[Connect, SynAck, Close]),
+            TcpState::FinWait2 => (false, &[Connect, SynAck, Close, Ack]),
+            TcpState::CloseWait => (false, &[Connect, SynAck, Fin]),
+            TcpState::Closing => (false, &[Connect, SynAck, Close, Fin]),
+            TcpState::LastAck => (false, &[Connect, SynAck, Fin, Close]),
+            TcpState::TimeWait => (false, &[Connect, SynAck, Close, Ack, Fin]),
+        };
+        let mut harness = Harness::new(listening);
+        for event in path {
+            harness.inject(*event);
+        }
+        assert_eq!(harness.state(), state, "setup path {:?}", path);
+        harness
+    }
+
+    /**
+     * 没有连接时按是否监听区分 Listen 和 Closed
+     */
+    fn state(&self) -> TcpState {
+        match self.table.state(ID) {
+            Some(state) => state,
+            None if self.listening => TcpState::Listen,
+            None => TcpState::Closed,
+        }
+    }
+
+    /**
+     * 应用能观察到就绪事件的地方: 被动打开的连接 accept 之前是监听端口
+     */
+    fn readiness(&self) -> Readiness {
+        let handle = if self.listening { listener_id(LOCAL_IP, 80) } else { ID };
+        self.table.readiness_of(handle)
+    }
+
+    fn segment(&self, seq: u32, ack: u32, flags: TcpFlags, data: Vec<u8>) -> TcpSegment {
+        TcpSegment::new(40000, 80, seq, ack, 5, 0, flags, 65535, 0, vec![], data)
+    }
+
+    /**
+     * 对端数据段和 FIN 携带的确认号: 不确认本端还没有被确认的 FIN
+     */
+    fn data_ack(&self) -> u32 {
+        match self.local_fin {
+            Some(fin) if !self.fin_acked => fin,
+            _ => self.local_nxt,
+        }
+    }
+
+    /**
+     * 注入一个事件, 返回之后发出的全部报文段(立即回复、poll_transmit 和定时器发出的)
+     */
+    fn inject(&mut self, event: FsmEvent) -> Vec<TcpSegment> {
+        let synchronized = !matches!(self.state(), TcpState::Closed | TcpState::Listen | TcpState::SynSent | TcpState::SynReceived);
+        let mut out = match event {
+            FsmEvent::Connect => vec![self.table.connect(ID, self.now_ms)],
+            FsmEvent::Close => {
+                let _ = self.table.close(ID, self.now_ms);
+                vec![]
+            }
+            FsmEvent::Abort => {
+                let _ = self.table.abort(ID, self.now_ms);
+                vec![]
+            }
+            FsmEvent::Timeout => {
+                self.now_ms += TIME_WAIT_MS;
+                self.table.tick(self.now_ms)
+            }
+            _ => {
+                let segment = self.peer_segment(event, synchronized);
+                self.table.segment_received(PEER_IP, LOCAL_IP, &segment, self.now_ms)
+            }
+        };
+        out.extend(self.table.poll_transmit(usize::MAX).into_iter().map(|(_, seg)| seg));
+        for seg in &out {
+            self.sent(seg);
+        }
+        if self.state() == TcpState::TimeWait && self.time_wait_since.is_none() {
+            self.time_wait_since = Some(self.now_ms);
+        }
+        out
+    }
+
+    /**
+     * 按事件构造对端的报文段, 并推进对端的序号
+     */
+    fn peer_segment(&mut self, event: FsmEvent, synchronized: bool) -> TcpSegment {
+        let (seq, ack) = (self.peer_nxt, self.local_nxt);
+        match event {
+            FsmEvent::Syn if synchronized => self.segment(PEER_ISN.wrapping_add(50_000), 0, TcpFlags::SYN, vec![]),
+            FsmEvent::Syn => {
+                self.peer_nxt = PEER_ISN.wrapping_add(1);
+                self.segment(PEER_ISN, 0, TcpFlags::SYN, vec![])
+            }
+            FsmEvent::SynAck => {
+                self.peer_nxt = PEER_ISN.wrapping_add(1);
+                self.segment(PEER_ISN, ack, TcpFlags::SYN | TcpFlags::ACK, vec![])
+            }
+            FsmEvent::Ack => {
+                self.fin_acked = self.local_fin.is_some();
+                self.segment(seq, ack, TcpFlags::ACK, vec![])
+            }
+            FsmEvent::Data => {
+                self.peer_nxt = seq.wrapping_add(1);
+                self.segment(seq, self.data_ack(), TcpFlags::ACK | TcpFlags::PSH, vec![0x5a])
+            }
+            FsmEvent::Fin => {
+                let fin = *self.peer_fin.get_or_insert(seq);
+                self.peer_nxt = fin.wrapping_add(1);
+                self.segment(fin, self.data_ack(), TcpFlags::ACK | TcpFlags::FIN, vec![])
+            }
+            FsmEvent::FinAck => {
+                self.peer_fin = Some(seq);
+                self.peer_nxt = seq.wrapping_add(1);
+                self.fin_acked = self.local_fin.is_some();
+                self.segment(seq, ack, TcpFlags::ACK | TcpFlags::FIN, vec![])
+            }
+            FsmEvent::Rst => self.segment(seq, ack, TcpFlags::RST | TcpFlags::ACK, vec![]),
+            FsmEvent::BareRst => self.segment(seq, 0, TcpFlags::RST, vec![]),
+            FsmEvent::AckUnsent => self.segment(seq, ack.wrapping_add(1000), TcpFlags::ACK, vec![]),
+            FsmEvent::Connect | FsmEvent::Close | FsmEvent::Abort | FsmEvent::Timeout => unreachable!(),
+        }
+    }
+
+    /**
+     * 记下本端发出的序号
+     */
+    fn sent(&mut self, segment: &TcpSegment) {
+        if segment.RST() {
+            return;
+        }
+        if segment.FIN() {
+            self.local_fin = Some(segment.seq.wrapping_add(segment.data.len() as u32));
+        }
+        let len = segment.data.len() as u32 + segment.SYN() as u32 + segment.FIN() as u32;
+        let end = segment.seq.wrapping_add(len);
+        if segment.SYN() || (end.wrapping_sub(self.local_nxt) as i32) > 0 {
+            self.local_nxt = end;
+        }
+    }
+}
+
+/**
+ * 新出现的就绪位, 消失的不算
+ */
+fn gained(before: Readiness, after: Readiness) -> bool {
+    [Readiness::READABLE, Readiness::WRITABLE, Readiness::ERROR, Readiness::ACCEPT, Readiness::READ_HIGH]
+        .iter()
+        .any(|bit| after.contains(*bit) && !before.contains(*bit))
+}
+
+/**
+ * 执行一行, 返回与表不符的地方
+ */
+fn run(row: &FsmRow) -> Vec<String> {
+    let mut harness = Harness::reach(row.state);
+    harness.now_ms = EVENT_MS;
+    let before = harness.readiness();
+    let out = harness.inject(row.event);
+    let mut problems = vec![];
+
+    if harness.state() != row.next {
+        problems.push(format!("next state {:?}, expected {:?}", harness.state(), row.next));
+    }
+    let mut sends = FsmActions::NONE;
+    for seg in &out {
+        sends = sends | FsmActions::for_segment(seg);
+    }
+    let expected_sends = row.actions.intersection(FsmActions::SENDS);
+    if sends != expected_sends || out.len() != expected_sends.count() {
+        let flags: Vec<String> = out.iter().map(|seg| seg.ctrl.to_string()).collect();
+        problems.push(format!("sent [{}], expected {}", flags.join(", "), expected_sends));
+    }
+    let signalled = gained(before, harness.readiness());
+    if signalled != row.actions.contains(FsmActions::SIGNAL_USER) {
+        problems.push(format!("user signalled: {}, readiness {} -> {}", signalled, before, harness.readiness()));
+    }
+
+    // 2*MSL 从哪一刻开始算: 行要求 (重新) 开始定时器时是事件的时刻, 否则是 setup 进入 TimeWait 的时刻
+    if row.next == TcpState::TimeWait {
+        let since = if row.actions.contains(FsmActions::START_TIMER) { Some(harness.now_ms) } else { harness.time_wait_since };
+        let since = since.unwrap();
+        harness.table.tick(since + TIME_WAIT_MS - 1);
+        let early = harness.state();
+        harness.table.tick(since + TIME_WAIT_MS);
+        if (early, harness.state()) != (TcpState::TimeWait, TcpState::Closed) {
+            problems.push(format!("2*MSL from {}: {:?} just before, {:?} at expiry", since, early, harness.state()));
+        }
+    } else if row.actions.contains(FsmActions::START_TIMER) {
+        problems.push("START_TIMER outside TimeWait".to_string());
+    }
+    problems
+}
+
+#[test]
+fn test_fsm_table_conformance() {
+    assert!(TCP_FSM_TABLE.len() >= 40);
+    let failures: Vec<String> = TCP_FSM_TABLE
+        .iter()
+        .flat_map(|row| run(row).into_iter().map(move |problem| format!("{:?} + {:?}: {}", row.state, row.event, problem)))
+        .collect();
+    assert!(failures.is_empty(), "{} mismatches:\n{}", failures.len(), failures.join("\n"));
+}
+
+#[test]
+fn test_setup_paths_reach_every_state() {
+    for state in TcpState::ALL {
+        assert_eq!(Harness::reach(state).state(), state);
+    }
+}
@@ -0,0 +1,82 @@
+/**
+ * TIME_WAIT 提前回收 (RFC 6191): 服务器主动关闭后停在 TimeWait, 客户端在 2 * MSL 之内用同一四元组重新连接
+ * 新 SYN 的 TSval 大于旧连接上见过的最后一个时接受, 否则按原来的行为回 challenge ACK
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::testing::pair::TablePair;
+use simple_tcp_ip::transport::connection_table::{listener_id, ConnectionTable};
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_option::SynOffer;
+use simple_tcp_ip::transport::tcp_segment::{TcpFlags, TcpSegment};
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const LINK: TablePair = TablePair::new(A_IP, B_IP).polling();
+const ID: ConnectionId = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+
+fn config(reuse: bool) -> TcpConfig {
+    TcpConfig { offer_timestamps: true, allow_time_wait_reuse: reuse, ..TcpConfig::default() }
+}
+
+/**
+ * 建立连接, 交换一次数据, 服务器先关闭: 结束时服务器在 TimeWait, 客户端在 Closed
+ */
+fn session(client: &mut ConnectionTable, server: &mut ConnectionTable, now_ms: u64) {
+    let syn = client.connect(ID, now_ms);
+    LINK.at(now_ms).exchange(client, server, vec![syn]);
+    assert_eq!(client.state(ID), Some(TcpState::Established));
+    assert_eq!(server.accept(listener_id(B_IP, 80)), Some(ID.reversed()));
+
+    client.write(ID, b"request").unwrap();
+    LINK.at(now_ms + 100).exchange(client, server, vec![]);
+    assert_eq!(server.read(ID.reversed(), usize::MAX).unwrap(), b"request");
+
+    server.close(ID.reversed(), now_ms + 200).unwrap();
+    LINK.at(now_ms + 200).exchange(client, server, vec![]);
+    client.close(ID, now_ms + 300).unwrap();
+    LINK.at(now_ms + 300).exchange(client, server, vec![]);
+    assert_eq!(client.state(ID), Some(TcpState::Closed));
+    assert_eq!(server.state(ID.reversed()), Some(TcpState::TimeWait));
+}
+
+#[test]
+fn test_back_to_back_connections_reuse_time_wait() {
+    let mut client = ConnectionTable::new(&config(true));
+    let mut server = ConnectionTable::new(&config(true));
+    server.listen(B_IP, 80);
+    assert_eq!(config(true).time_wait_ms(), 60_000);
+
+    session(&mut client, &mut server, 0);
+    server.tick(1000);
+    assert_eq!(server.state(ID.reversed()), Some(TcpState::TimeWait));
+
+    // 1 秒之后的 SYN 带着更大的 TSval, 旧连接让位, 两次连接在 2 秒内完成
+    session(&mut client, &mut server, 1000);
+    assert_eq!(server.len(), 1);
+}
+
+#[test]
+fn test_reuse_refused_without_timestamp_progress() {
+    let mut client = ConnectionTable::new(&config(true));
+    let mut server = ConnectionTable::new(&config(true));
+    server.listen(B_IP, 80);
+    session(&mut client, &mut server, 500);
+
+    // TSval 与旧连接的 SYN 相同, 序号在旧连接的序号空间之外: 仍然拒绝
+    let offer = SynOffer::from_config(&config(true));
+    let options = offer.encode(500, 0);
+    let syn = TcpSegment::new(40000, 80, 0x7000_0000, 0, 5 + options.len() as u8, 0, TcpFlags::SYN, 8192, 0, options, vec![]);
+    let replies = server.segment_received(A_IP, B_IP, &syn, 1500);
+    assert_eq!(server.state(ID.reversed()), Some(TcpState::TimeWait));
+    assert!(replies.iter().all(|reply| !reply.SYN()));
+
+    // 关闭开关时, 即使 TSval 前进也不回收
+    let mut client = ConnectionTable::new(&config(false));
+    let mut server = ConnectionTable::new(&config(false));
+    server.listen(B_IP, 80);
+    session(&mut client, &mut server, 0);
+    let syn = client.connect(ID, 1000);
+    let replies = server.segment_received(A_IP, B_IP, &syn, 1000);
+    assert_eq!(server.state(ID.reversed()), Some(TcpState::TimeWait));
+    assert!(replies.iter().all(|reply| !reply.SYN()));
+}
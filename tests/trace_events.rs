@@ -0,0 +1,172 @@
+/**
+ * 装在连接表和 InterfaceSet 上的 TraceSink: 握手在两张连接表之间进行, 事件按发生的先后记录
+ * 连接表报告收发的报文段和连接的状态变化, InterfaceSet 报告经过接口的帧和 IP 层的丢弃
+ */
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use simple_tcp_ip::config::{ArpConfig, Ipv4Config, TcpConfig};
+use simple_tcp_ip::link::interface::EthernetInterface;
+use simple_tcp_ip::net::interfaces::InterfaceSet;
+use simple_tcp_ip::net::ipv4::Ipv4DatagramBuilder;
+use simple_tcp_ip::net::raw_socket::IpProtocol;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+use simple_tcp_ip::utils::drops::DropReason;
+use simple_tcp_ip::utils::trace::{SegmentVerdict, SharedTraceSink, TraceSink};
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const ID: ConnectionId = ConnectionId { s_ip: A_IP, s_port: 40000, d_ip: B_IP, d_port: 80 };
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Event {
+    FrameRx(usize),
+    FrameTx(usize),
+    SegmentRx(&'static str, ConnectionId, String, SegmentVerdict),
+    SegmentTx(&'static str, ConnectionId, String),
+    State(&'static str, TcpState, TcpState),
+    Drop(DropReason, usize),
+}
+
+/**
+ * 两端共用一个 Recorder, 各自的 Probe 给事件标上是哪一端
+ */
+#[derive(Default)]
+struct Recorder {
+    events: Vec<Event>,
+}
+
+struct Probe {
+    side: &'static str,
+    recorder: Rc<RefCell<Recorder>>,
+}
+
+impl Probe {
+    fn install(side: &'static str, recorder: &Rc<RefCell<Recorder>>) -> SharedTraceSink {
+        Rc::new(RefCell::new(Probe { side, recorder: recorder.clone() }))
+    }
+
+    fn push(&self, event: Event) {
+        self.recorder.borrow_mut().events.push(event);
+    }
+}
+
+fn flags(segment: &TcpSegment) -> String {
+    let names = [(segment.SYN(), "SYN"), (segment.FIN(), "FIN"), (segment.RST(), "RST"), (segment.ACK(), "ACK")];
+    names.iter().filter(|(set, _)| *set).map(|(_, name)| *name).collect::<Vec<_>>().join("|")
+}
+
+impl TraceSink for Probe {
+    fn on_frame_rx(&mut self, frame: &[u8]) {
+        self.push(Event::FrameRx(frame.len()));
+    }
+
+    fn on_frame_tx(&mut self, frame: &[u8]) {
+        self.push(Event::FrameTx(frame.len()));
+    }
+
+    fn on_segment_rx(&mut self, conn: &ConnectionId, segment: &TcpSegment, verdict: SegmentVerdict) {
+        self.push(Event::SegmentRx(self.side, *conn, flags(segment), verdict));
+    }
+
+    fn on_segment_tx(&mut self, conn: &ConnectionId, segment: &TcpSegment) {
+        self.push(Event::SegmentTx(self.side, *conn, flags(segment)));
+    }
+
+    fn on_state_change(&mut self, _conn: &ConnectionId, old: TcpState, new: TcpState) {
+        self.push(Event::State(self.side, old, new));
+    }
+
+    fn on_drop(&mut self, reason: DropReason, bytes: usize, _total: u64) {
+        self.push(Event::Drop(reason, bytes));
+    }
+}
+
+fn tx(side: &'static str, conn: ConnectionId, flags: &str) -> Event {
+    Event::SegmentTx(side, conn, flags.to_string())
+}
+
+fn rx(side: &'static str, conn: ConnectionId, flags: &str, verdict: SegmentVerdict) -> Event {
+    Event::SegmentRx(side, conn, flags.to_string(), verdict)
+}
+
+#[test]
+fn test_handshake_event_order() {
+    let recorder = Rc::new(RefCell::new(Recorder::default()));
+    let mut client = ConnectionTable::new(&TcpConfig::default());
+    let mut server = ConnectionTable::new(&TcpConfig::default());
+    client.set_trace_sink(Some(Probe::install("client", &recorder)));
+    server.set_trace_sink(Some(Probe::install("server", &recorder)));
+    server.listen(B_IP, 80);
+
+    let syn = client.connect(ID, 0);
+    let syn_ack = server.segment_received(A_IP, B_IP, &syn, 1).pop().unwrap();
+    let ack = client.segment_received(B_IP, A_IP, &syn_ack, 2).pop().unwrap();
+    assert!(server.segment_received(A_IP, B_IP, &ack, 3).is_empty());
+
+    let peer = ID.reversed();
+    // 收到的段处理完才带着结果报告, 处理中的状态变化排在它前面
+    assert_eq!(recorder.borrow().events, vec![
+        Event::State("client", TcpState::Closed, TcpState::SynSent),
+        tx("client", ID, "SYN"),
+        Event::State("server", TcpState::Listen, TcpState::SynReceived),
+        rx("server", peer, "SYN", SegmentVerdict::Accepted),
+        tx("server", peer, "SYN|ACK"),
+        Event::State("client", TcpState::SynSent, TcpState::Established),
+        rx("client", ID, "SYN|ACK", SegmentVerdict::Accepted),
+        tx("client", ID, "ACK"),
+        Event::State("server", TcpState::SynReceived, TcpState::Established),
+        rx("server", peer, "ACK", SegmentVerdict::Accepted),
+    ]);
+
+    // 重传的 SYN 由半连接重发同一个 SYN|ACK; 没有监听的端口回 RST
+    recorder.borrow_mut().events.clear();
+    let other = ConnectionId { s_port: 40001, ..ID };
+    let syn = client.connect(other, 10);
+    server.segment_received(A_IP, B_IP, &syn, 11);
+    server.segment_received(A_IP, B_IP, &syn, 12);
+    let closed = ConnectionId { d_port: 81, ..ID };
+    let syn = client.connect(closed, 13);
+    server.segment_received(A_IP, B_IP, &syn, 14);
+    let events = recorder.borrow().events.clone();
+    assert!(events.contains(&rx("server", other.reversed(), "SYN", SegmentVerdict::Duplicate)));
+    assert_eq!(events[events.len() - 2..], [
+        rx("server", closed.reversed(), "SYN", SegmentVerdict::Dropped),
+        tx("server", closed.reversed(), "RST|ACK"),
+    ]);
+}
+
+#[test]
+fn test_interface_frames_and_drops() {
+    let recorder = Rc::new(RefCell::new(Recorder::default()));
+    let mut a = InterfaceSet::new(&Ipv4Config::default(), &ArpConfig::default());
+    a.set_trace_sink(Some(Probe::install("a", &recorder)));
+    a.add_interface(EthernetInterface::new([0x02, 0, 0, 0, 0, 1], A_IP, 24));
+    let mut b = InterfaceSet::new(&Ipv4Config::default(), &ArpConfig::default());
+    b.add_interface(EthernetInterface::new([0x02, 0, 0, 0, 0, 2], B_IP, 24));
+    b.set_trace_sink(Some(Probe::install("b", &recorder)));
+
+    // 发往同一网段: A 先广播 ARP 请求, B 收到后应答
+    let datagram = |d_addr| Ipv4DatagramBuilder::new().source(A_IP).destination(d_addr)
+        .protocol(IpProtocol::Other(253)).payload(b"ping".to_vec()).build().unwrap();
+    a.send(datagram(B_IP), None, 0).unwrap();
+    for bytes in a.take_tx(0) {
+        b.frame_received(0, bytes);
+    }
+    b.poll(usize::MAX, 0);
+    for bytes in b.take_tx(0) {
+        a.frame_received(0, bytes);
+    }
+    a.poll(usize::MAX, 0);
+    let frames: Vec<Event> = recorder.borrow().events.iter().take(4).cloned().collect();
+    assert!(matches!(frames[..], [Event::FrameTx(_), Event::FrameRx(_), Event::FrameTx(_), Event::FrameRx(_)]), "{frames:?}");
+
+    // 没有路由的目的地址在 IP 层丢弃
+    recorder.borrow_mut().events.clear();
+    let unroutable = datagram(0x08080808);
+    let size = unroutable.payload().len() + 20;
+    assert_eq!(a.send(unroutable, None, 0), Err(DropReason::NoRoute));
+    assert_eq!(recorder.borrow().events, vec![Event::Drop(DropReason::NoRoute, size)]);
+}
@@ -0,0 +1,68 @@
+/**
+ * 经过两台转发路由器的 traceroute
+ * 拓扑 A - R1 - R2 - B 是测试内的点对点链路: 路由器转发时 TTL 减一, 耗尽则回 Time Exceeded, B 对探测端口回端口不可达
+ */
+use simple_tcp_ip::app::traceroute::{ProbeMode, Traceroute};
+use simple_tcp_ip::net::icmp_v4::IcmpV4;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::net::pinger::echo_reply;
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+const A: u32 = 0x0a000001;
+const R1: u32 = 0x0a000101;
+const R2: u32 = 0x0a000201;
+const B: u32 = 0x0a000302;
+
+fn icmp_datagram(s_addr: u32, d_addr: u32, icmp: IcmpV4) -> Ipv4Datagram {
+    let payload = icmp.serialize();
+    Ipv4Datagram::new(4, 5, 0, (20 + payload.len()) as u16, 0, 0, 0, 64, 1, s_addr, d_addr, payload)
+}
+
+/**
+ * 把探测送过路径, 返回回到 A 的 ICMP 数据报; 回程不经过 TTL 检查
+ */
+fn forward(mut datagram: Ipv4Datagram) -> Ipv4Datagram {
+    for router in [R1, R2] {
+        if !datagram.decrement_ttl() {
+            return icmp_datagram(router, datagram.s_addr(), IcmpV4::time_exceeded(&datagram.serialize()));
+        }
+        // 线路上重新解析, 确认转发后的首部校验和正确
+        datagram = Ipv4Datagram::try_deserialize(&datagram.serialize()).unwrap();
+    }
+    match datagram.protocol() {
+        1 => {
+            let request = IcmpV4::try_deserialize(datagram.payload()).unwrap();
+            icmp_datagram(B, datagram.s_addr(), echo_reply(&request).unwrap())
+        }
+        _ => icmp_datagram(B, datagram.s_addr(), IcmpV4::dest_unreachable(3, &datagram.serialize())),
+    }
+}
+
+fn run(mut trace: Traceroute) -> Traceroute {
+    let mut now = 0;
+    while let Some(probe) = trace.poll(now) {
+        now += 2;
+        assert!(trace.on_icmp(&forward(probe), now));
+    }
+    trace
+}
+
+#[test]
+fn test_udp_trace_through_two_routers() {
+    let trace = run(Traceroute::new(A, B));
+    assert!(trace.is_done() && trace.reached());
+    let addrs: Vec<_> = trace.hops().iter().map(|hop| hop.addr()).collect();
+    assert_eq!(addrs, vec![Some(R1), Some(R2), Some(B)]);
+    for (i, hop) in trace.hops().iter().enumerate() {
+        assert_eq!(hop.ttl as usize, i + 1);
+        assert_eq!(hop.probes.len(), 3);
+        assert!(hop.probes.iter().all(|probe| probe.is_some_and(|(_, rtt)| rtt == 2)));
+    }
+}
+
+#[test]
+fn test_icmp_trace_through_two_routers() {
+    let trace = run(Traceroute::new(A, B).with_mode(ProbeMode::IcmpEcho { id: 0x4242 }).with_probes_per_hop(1));
+    let addrs: Vec<_> = trace.hops().iter().map(|hop| hop.addr()).collect();
+    assert_eq!(addrs, vec![Some(R1), Some(R2), Some(B)]);
+}
@@ -0,0 +1,86 @@
+/**
+ * 紧急数据: 发送端在包含紧急数据结尾之前字节的每个段上设置 URG 和紧急指针,
+ * 指针指向紧急数据之后的第一个字节 (RFC 6093), 可以跨过本段; 接收端用 read_urgent 读到紧急数据的结尾
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::testing::pair::TablePair;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::ConnectionId;
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+
+const CLIENT: u32 = 0x0a000001;
+const SERVER: u32 = 0x0a000002;
+const LINK: TablePair = TablePair::new(CLIENT, SERVER);
+const ID: ConnectionId = ConnectionId { s_ip: CLIENT, s_port: 40000, d_ip: SERVER, d_port: 80 };
+
+fn connected() -> (ConnectionTable, ConnectionTable) {
+    let config = TcpConfig { mss: 1000, ..TcpConfig::default() };
+    let mut client = ConnectionTable::new(&config);
+    let mut server = ConnectionTable::new(&config);
+    server.listen(SERVER, 80);
+    let syn = client.connect(ID, 0);
+    LINK.exchange(&mut client, &mut server, vec![syn]);
+    (client, server)
+}
+
+fn transmit(client: &mut ConnectionTable, budget: usize) -> Vec<TcpSegment> {
+    client.poll_transmit(budget).into_iter().map(|(_, segment)| segment).collect()
+}
+
+/**
+ * 每个段的 (长度, 紧急指针), 不带 URG 的段指针为 None
+ */
+fn urgent_pointers(segments: &[TcpSegment]) -> Vec<(usize, Option<u16>)> {
+    segments.iter().map(|segment| (segment.data.len(), segment.URG().then_some(segment.ur_ptr))).collect()
+}
+
+#[test]
+fn test_urgent_write_spanning_three_segments() {
+    let (mut client, mut server) = connected();
+    client.write(ID, &[0; 500]).unwrap();
+    client.write_urgent(ID, &[1; 2200]).unwrap();
+    let segments = transmit(&mut client, usize::MAX);
+    assert_eq!(urgent_pointers(&segments), vec![(1000, Some(2700)), (1000, Some(1700)), (700, Some(700))]);
+
+    // 紧急数据发完之后的段不再设置; 重传保留原来的 URG 和指针
+    client.write(ID, &[2; 300]).unwrap();
+    let after = transmit(&mut client, usize::MAX);
+    assert_eq!(urgent_pointers(&after), vec![(300, None)]);
+    assert_eq!(urgent_pointers(&[client.retransmission(ID).unwrap()]), vec![(1000, Some(2700))]);
+
+    LINK.exchange(&mut client, &mut server, [segments, after].concat());
+    assert_eq!(server.urgent_pending(ID.reversed()), Some(2700));
+    let urgent = server.read_urgent(ID.reversed(), usize::MAX).unwrap();
+    assert_eq!(urgent.len(), 2700);
+    assert_eq!(&urgent[499..501], &[0, 1]);
+    assert_eq!(server.urgent_pending(ID.reversed()), None);
+    assert_eq!(server.read_urgent(ID.reversed(), usize::MAX).unwrap(), Vec::<u8>::new());
+    assert_eq!(server.read(ID.reversed(), usize::MAX).unwrap(), vec![2; 300]);
+}
+
+#[test]
+fn test_urgent_mark_moves_forward_before_sent() {
+    let (mut client, mut server) = connected();
+    client.write_urgent(ID, &[1; 1500]).unwrap();
+    let first = transmit(&mut client, 1);
+    assert_eq!(urgent_pointers(&first), vec![(1000, Some(1500))]);
+
+    // 对端只收到第一段: 紧急数据的结尾还没有到达, read_urgent 只读已经到达的部分
+    LINK.exchange(&mut client, &mut server, first);
+    assert_eq!(server.urgent_pending(ID.reversed()), Some(1500));
+    assert_eq!(server.read_urgent(ID.reversed(), 400).unwrap(), vec![1; 400]);
+    assert_eq!(server.urgent_pending(ID.reversed()), Some(1100));
+
+    // 上一次的紧急数据还没有发完就写入新的紧急数据, 标记移到新数据的结尾
+    client.write_urgent(ID, &[2; 800]).unwrap();
+    let rest = transmit(&mut client, usize::MAX);
+    assert_eq!(urgent_pointers(&rest), vec![(1000, Some(1300)), (300, Some(300))]);
+    LINK.exchange(&mut client, &mut server, rest);
+    assert_eq!(server.urgent_pending(ID.reversed()), Some(1900));
+
+    // 普通的 read 读过标记之后, 紧急数据也就读完了
+    let mut received = vec![1; 400];
+    received.extend(server.read(ID.reversed(), usize::MAX).unwrap());
+    assert_eq!(server.urgent_pending(ID.reversed()), None);
+    assert_eq!(received, [vec![1; 1500], vec![2; 800]].concat());
+}
@@ -0,0 +1,78 @@
+/**
+ * 发送与接收队列的水位: 慢消费者场景下 WRITABLE 和 READ_HIGH 只在越过水位时变化, 两个水位之间不反复切换
+ * 可写回调只在由不可写变为可写时调用一次
+ */
+use std::cell::Cell;
+use std::rc::Rc;
+
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::testing::pair::TablePair;
+use simple_tcp_ip::transport::connection_table::{ConnectionTable, Readiness};
+use simple_tcp_ip::transport::socket_options::{SocketOptions, Watermarks};
+use simple_tcp_ip::transport::tcp_connection::ConnectionId;
+use simple_tcp_ip::transport::tcp_segment::TcpSegment;
+
+const CLIENT: u32 = 0x0a000001;
+const SERVER: u32 = 0x0a000002;
+
+const LINK: TablePair = TablePair::new(CLIENT, SERVER);
+
+fn transmit(client: &mut ConnectionTable) -> Vec<TcpSegment> {
+    client.poll_transmit(64).into_iter().map(|(_, segment)| segment).collect()
+}
+
+#[test]
+fn test_slow_consumer_watermark_transitions() {
+    let config = TcpConfig::default();
+    let mut client = ConnectionTable::new(&config);
+    let mut server = ConnectionTable::new(&config);
+    let listener = server.listen(SERVER, 80);
+    let id = ConnectionId { s_ip: CLIENT, s_port: 40000, d_ip: SERVER, d_port: 80 };
+    let options = SocketOptions { send_watermarks: Some(Watermarks::new(2000, 6000)), ..SocketOptions::default() };
+    let syn = client.connect_with_options(id, options, 0);
+    LINK.exchange(&mut client, &mut server, vec![syn]);
+    let peer = server.accept(listener).unwrap();
+    let options = SocketOptions { recv_watermarks: Some(Watermarks::new(1000, 4000)), ..SocketOptions::default() };
+    server.set_socket_options(peer, options).unwrap();
+    assert_eq!(client.readiness_changes(), vec![(id, Readiness::WRITABLE)]);
+    server.readiness_changes();
+
+    let wakeups = Rc::new(Cell::new(0));
+    let counter = wakeups.clone();
+    client.on_writable(id, Box::new(move || counter.set(counter.get() + 1))).unwrap();
+
+    // 低于高水位时仍然可写
+    assert_eq!(client.write(id, &[1; 3000]), Ok(3000));
+    assert!(client.readiness_changes().is_empty());
+    assert_eq!(client.write(id, &[2; 3000]), Ok(3000));
+    assert_eq!(client.readiness_changes(), vec![(id, Readiness::EMPTY)]);
+
+    // 一部分被确认, 队列深度落在两个水位之间, 仍然不可写
+    let segments = transmit(&mut client);
+    assert_eq!(segments.iter().map(|segment| segment.data.len()).sum::<usize>(), 6000);
+    LINK.exchange(&mut client, &mut server, segments[..2].to_vec());
+    assert!(transmit(&mut client).is_empty());
+    assert!(client.readiness_changes().is_empty());
+    assert_eq!(wakeups.get(), 0);
+    let open = Readiness::READABLE | Readiness::WRITABLE;
+    assert_eq!(server.readiness_changes(), vec![(peer, open)]);
+
+    // 全部确认: 发送端回到可写, 接收端未读数据进入高水位
+    LINK.exchange(&mut client, &mut server, segments[2..].to_vec());
+    assert_eq!(server.readiness_changes(), vec![(peer, open | Readiness::READ_HIGH)]);
+    transmit(&mut client);
+    assert_eq!(wakeups.get(), 1);
+    assert_eq!(client.readiness_changes(), vec![(id, Readiness::WRITABLE)]);
+
+    // 慢慢读: 两个水位之间不变, 回落到低水位才离开高水位
+    assert_eq!(server.read(peer, 2500).unwrap().len(), 2500);
+    assert!(server.readiness_changes().is_empty());
+    assert_eq!(server.read(peer, 2500).unwrap().len(), 2500);
+    assert_eq!(server.readiness_changes(), vec![(peer, open)]);
+
+    // 没有新的边沿就没有新的回调
+    transmit(&mut client);
+    transmit(&mut client);
+    assert_eq!(wakeups.get(), 1);
+    assert!(client.readiness_changes().is_empty());
+}
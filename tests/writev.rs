@@ -0,0 +1,59 @@
+/**
+ * writev: 三段消息(首部、正文、尾部)一次写入, 发送缓冲区在正文中间写满
+ * 返回值按所有缓冲区合计, 剩下的部分之后经 std::io 接口续写, 线路上的字节顺序与拼接后一致
+ */
+use std::io::{self, IoSlice, Read, Write};
+
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::testing::pair::TablePair;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::ConnectionId;
+
+const CLIENT: u32 = 0x0a000001;
+const SERVER: u32 = 0x0a000002;
+const LINK: TablePair = TablePair::new(CLIENT, SERVER);
+
+#[test]
+fn test_write_vectored_fills_mid_slice() {
+    let config = TcpConfig { send_buffer: 100, ..TcpConfig::default() };
+    let mut client = ConnectionTable::new(&config);
+    let mut server = ConnectionTable::new(&TcpConfig::default());
+    server.listen(SERVER, 80);
+    let id = ConnectionId { s_ip: CLIENT, s_port: 40000, d_ip: SERVER, d_port: 80 };
+    let syn = client.connect(id, 0);
+    LINK.exchange(&mut client, &mut server, vec![syn]);
+
+    let header = [b'H'; 40];
+    let body: Vec<u8> = (0..100).collect();
+    let trailer = [b'T'; 10];
+    let message = [&header[..], &body[..], &trailer[..]].concat();
+
+    // 首部 40 字节加上正文的前 60 字节
+    assert_eq!(client.write_vectored(id, &[&header, &body, &trailer]), Ok(100));
+    let mut stream = client.stream(id);
+    let err = stream.write_vectored(&[IoSlice::new(&body[60..]), IoSlice::new(&trailer)]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+    let mut written = 100;
+    let mut received = vec![];
+    while received.len() < message.len() {
+        let segments = client.poll_transmit(16).into_iter().map(|(_, segment)| segment).collect();
+        LINK.exchange(&mut client, &mut server, segments);
+        received.extend(server.read(id.reversed(), usize::MAX).unwrap());
+        if written < message.len() {
+            let (in_body, in_trailer) = (written.clamp(40, 140) - 40, written.max(140) - 140);
+            let rest = [IoSlice::new(&body[in_body..]), IoSlice::new(&trailer[in_trailer..])];
+            // 对端的 ACK 要到下一次 poll_transmit 才交给发送端, 在那之前缓冲区仍然是满的
+            match client.stream(id).write_vectored(&rest) {
+                Ok(n) => written += n,
+                Err(e) => assert_eq!(e.kind(), io::ErrorKind::WouldBlock),
+            }
+        }
+    }
+    assert_eq!(written, message.len());
+    assert_eq!(received, message);
+
+    // 对端没有数据时读到 WouldBlock
+    let mut buf = [0; 16];
+    assert_eq!(server.stream(id.reversed()).read(&mut buf).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+}
@@ -0,0 +1,98 @@
+/**
+ * 接收路径上一次分配从设备一直传到 TCP 报文段的 data
+ * 用计数的全局分配器统计解析过程中的分配次数
+ */
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use simple_tcp_ip::link::ethernet::EthernetFrame;
+use simple_tcp_ip::net::ipv4::Ipv4Datagram;
+use simple_tcp_ip::testing::packet::PacketBuilder;
+use simple_tcp_ip::transport::tcp_segment::{TcpCtrlFlag, TcpSegment};
+use simple_tcp_ip::utils::wire::WireSerialize;
+
+struct CountingAlloc;
+
+thread_local! {
+    // 只统计当前线程, 测试框架其他线程的分配不计入
+    static ALLOCS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCS.try_with(|n| n.set(n.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn allocs() -> usize {
+    ALLOCS.with(|n| n.get())
+}
+
+const S_ADDR: u32 = 0x0a000001;
+const D_ADDR: u32 = 0x0a000002;
+
+fn build_frame(data: &[u8]) -> Vec<u8> {
+    PacketBuilder::ether([0x02, 0, 0, 0, 0, 1], [0x02, 0, 0, 0, 0, 2])
+        .ipv4(S_ADDR, D_ADDR).ip_id(1)
+        .tcp(40000, 80).flags(TcpCtrlFlag::ACK as u16 | TcpCtrlFlag::PSH as u16).seq(1000).ack(2000).window(8192)
+        .payload(data)
+        .build()
+}
+
+#[test]
+fn test_one_allocation_per_received_packet() {
+    for data in [&b"x"[..], &[0x5a; 1000][..]] {
+        let wire = build_frame(data);
+
+        let before = allocs();
+        let device_buf = wire.to_vec(); // 设备交上来的缓冲区, 整条路径唯一的一次分配
+        let buf_ptr = device_buf.as_ptr();
+
+        let frame = EthernetFrame::from_frame_vec(device_buf).unwrap();
+        let datagram = Ipv4Datagram::from_payload_vec(frame.into_payload()).unwrap();
+        let (s_addr, d_addr) = (datagram.s_addr(), datagram.d_addr());
+        let segment = TcpSegment::from_payload_vec(datagram.into_payload()).unwrap();
+        let after = allocs();
+
+        assert_eq!(after - before, 1);
+        assert_eq!(segment.data, data);
+        assert_eq!(segment.data.as_ptr(), buf_ptr);
+        assert!(segment.check_checksum(s_addr, d_addr));
+    }
+}
+
+#[test]
+fn test_owned_parse_matches_borrowed_parse() {
+    let wire = build_frame(b"hello");
+    let borrowed = EthernetFrame::try_deserialize(&wire).unwrap();
+    let owned = EthernetFrame::from_frame_vec(wire.clone()).unwrap();
+    assert_eq!(owned.payload(), borrowed.payload());
+    assert!(owned.check_fcs());
+
+    let borrowed = Ipv4Datagram::try_deserialize(borrowed.payload()).unwrap();
+    let owned = Ipv4Datagram::from_payload_vec(owned.into_payload()).unwrap();
+    assert_eq!(owned.payload(), borrowed.payload()); // 链路层补齐已被去掉
+    assert!(owned.check_hdr_checksum());
+
+    let borrowed = TcpSegment::try_deserialize(borrowed.payload()).unwrap();
+    let owned = TcpSegment::from_payload_vec(owned.into_payload()).unwrap();
+    assert_eq!(owned.serialize(), borrowed.serialize());
+
+    // 错误与借用版本一致
+    assert!(EthernetFrame::from_frame_vec(wire[..63].to_vec()).is_err());
+    assert!(Ipv4Datagram::from_payload_vec(vec![0x45; 19]).is_err());
+    assert!(TcpSegment::from_payload_vec(vec![0; 19]).is_err());
+}
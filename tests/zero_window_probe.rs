@@ -0,0 +1,98 @@
+/**
+ * 零窗口探测: 本端是接收缓冲区已满的一方, 对端每次发来 1 字节的探测
+ * 没有空间时探测字节丢弃, 回复的 ACK 仍是原来的 rcv_nxt 和零窗口; 应用读走数据之后探测字节才被接受
+ */
+use simple_tcp_ip::config::TcpConfig;
+use simple_tcp_ip::transport::connection_table::ConnectionTable;
+use simple_tcp_ip::transport::tcp_connection::{ConnectionId, TcpState};
+use simple_tcp_ip::transport::tcp_segment::{TcpFlags, TcpSegment};
+
+const A_IP: u32 = 0x0a000001;
+const B_IP: u32 = 0x0a000002;
+const ID: ConnectionId = ConnectionId { s_ip: B_IP, s_port: 80, d_ip: A_IP, d_port: 40000 };
+const BUFFER: usize = 1000;
+const PEER_ISN: u32 = 0xffff_fff0; // 数据跨过序号回绕
+
+/**
+ * 测试扮演对端; 返回 (接收端, 第一个数据字节的序号, 对端报文段的确认号)
+ */
+fn receiver() -> (ConnectionTable, u32, u32) {
+    let mut table = ConnectionTable::new(&TcpConfig { recv_buffer: BUFFER, ..TcpConfig::default() });
+    table.listen(B_IP, 80);
+    let syn = TcpSegment::new(40000, 80, PEER_ISN, 0, 5, 0, TcpFlags::SYN, 65535, 0, vec![], vec![]);
+    let syn_ack = table.segment_received(A_IP, B_IP, &syn, 0).pop().unwrap();
+    let ack = segment(PEER_ISN.wrapping_add(1), syn_ack.seq.wrapping_add(1), TcpFlags::ACK, vec![]);
+    table.segment_received(A_IP, B_IP, &ack, 0);
+    table.poll_transmit(usize::MAX);
+    assert_eq!(table.state(ID), Some(TcpState::Established));
+    (table, PEER_ISN.wrapping_add(1), syn_ack.seq.wrapping_add(1))
+}
+
+fn segment(seq: u32, ack: u32, flags: TcpFlags, data: Vec<u8>) -> TcpSegment {
+    TcpSegment::new(40000, 80, seq, ack, 5, 0, flags, 65535, 0, vec![], data)
+}
+
+/**
+ * 交给接收端, 返回它的全部回复(立即回复和 poll_transmit 发出的)
+ */
+fn deliver(table: &mut ConnectionTable, segment: &TcpSegment, now_ms: u64) -> Vec<TcpSegment> {
+    let mut replies = table.segment_received(A_IP, B_IP, segment, now_ms);
+    replies.extend(table.poll_transmit(usize::MAX).into_iter().map(|(_, seg)| seg));
+    replies
+}
+
+fn stream(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn test_probe_rejected_until_read_frees_space() {
+    let (mut table, start, ack) = receiver();
+    let data = stream(BUFFER + 500);
+    let full = deliver(&mut table, &segment(start, ack, TcpFlags::ACK | TcpFlags::PSH, data[..BUFFER].to_vec()), 10);
+    let rcv_nxt = start.wrapping_add(BUFFER as u32);
+    assert_eq!((full.last().unwrap().ack, full.last().unwrap().win_size), (rcv_nxt, 0));
+
+    // 三次探测都没有空间: 字节丢弃, ACK 重申 rcv_nxt 和零窗口
+    for i in 0..3 {
+        let probe = segment(rcv_nxt, ack, TcpFlags::ACK, data[BUFFER..BUFFER + 1].to_vec());
+        let replies = deliver(&mut table, &probe, 100 + i * 100);
+        assert_eq!(replies.len(), 1, "probe {}", i);
+        assert_eq!((replies[0].ack, replies[0].win_size), (rcv_nxt, 0), "probe {}", i);
+    }
+
+    // 读走一部分之后探测字节被接受, 之后的数据按序接上
+    assert_eq!(table.read(ID, 300).unwrap(), data[..300]);
+    let probe = segment(rcv_nxt, ack, TcpFlags::ACK, data[BUFFER..BUFFER + 1].to_vec());
+    let replies = deliver(&mut table, &probe, 500);
+    assert_eq!(replies.last().unwrap().ack, rcv_nxt.wrapping_add(1));
+    deliver(&mut table, &segment(rcv_nxt.wrapping_add(1), ack, TcpFlags::ACK, data[BUFFER + 1..BUFFER + 299].to_vec()), 510);
+
+    let mut received = data[..300].to_vec();
+    received.extend(table.read(ID, usize::MAX).unwrap());
+    assert_eq!(received.len(), BUFFER + 299);
+    assert_eq!(received, data[..BUFFER + 299]);
+}
+
+#[test]
+fn test_probe_carrying_last_byte_and_fin() {
+    let (mut table, start, ack) = receiver();
+    let data = stream(BUFFER + 1);
+    deliver(&mut table, &segment(start, ack, TcpFlags::ACK, data[..BUFFER].to_vec()), 10);
+    let rcv_nxt = start.wrapping_add(BUFFER as u32);
+
+    // 最后一个字节和 FIN 在同一个探测里: 字节放不下时 FIN 也不算收到
+    let probe = segment(rcv_nxt, ack, TcpFlags::ACK | TcpFlags::FIN, data[BUFFER..].to_vec());
+    for i in 0..3 {
+        let replies = deliver(&mut table, &probe, 100 + i * 100);
+        assert_eq!((replies[0].ack, replies[0].win_size), (rcv_nxt, 0), "probe {}", i);
+        assert_eq!(table.state(ID), Some(TcpState::Established));
+    }
+
+    let mut received = table.read(ID, 1).unwrap();
+    let replies = deliver(&mut table, &probe, 500);
+    assert_eq!(replies.last().unwrap().ack, rcv_nxt.wrapping_add(2)); // 字节和 FIN 都确认
+    assert_eq!(table.state(ID), Some(TcpState::CloseWait));
+    received.extend(table.read(ID, usize::MAX).unwrap());
+    assert_eq!(received, data);
+}